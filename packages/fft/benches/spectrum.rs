@@ -0,0 +1,131 @@
+//! 频谱分析与重采样热路径的前后对比基准
+//!
+//! 运行 `cargo bench -p fft` 查看结果，"旧实现" 为改用 `realfft` 与单声道重采样之前的做法
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use fft::{FFTPlayer, SpectrumAnalyzer};
+use rubato::Resampler;
+use spectrum_analyzer::{FrequencyLimit, samples_fft_to_spectrum, scaling, windows};
+
+const FFT_SIZE: usize = 2048;
+const SAMPLE_RATE: u32 = 44100;
+const FREQ_RANGE: (f32, f32) = (80.0, 2000.0);
+/// 每次送入的帧数，与常见的解码包大小相近
+const CHUNK_FRAMES: usize = 1024;
+
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    // 预热，同时估算出能跑满约 1 秒的迭代次数
+    let warmup = Instant::now();
+    let mut warmup_iters = 0u32;
+    while warmup.elapsed() < Duration::from_millis(200) {
+        f();
+        warmup_iters += 1;
+    }
+    let iters = warmup_iters * 5;
+
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let per_iter = start.elapsed() / iters;
+    println!("{name:<40} {per_iter:>12.2?} / iter ({iters} iters)");
+    per_iter
+}
+
+fn report(old: Duration, new: Duration) {
+    println!(
+        "{:<40} {:>11.2}x\n",
+        "speedup",
+        old.as_secs_f64() / new.as_secs_f64()
+    );
+}
+
+fn test_signal(len: usize, channels: usize) -> Vec<f32> {
+    (0..len * channels)
+        .map(|i| {
+            let t = (i / channels) as f32 / 48000.0;
+            (t * 440.0 * std::f32::consts::TAU).sin() * 0.5
+                + (t * 1234.5 * std::f32::consts::TAU).sin() * 0.25
+        })
+        .collect()
+}
+
+fn bench_spectrum() {
+    let samples = test_signal(FFT_SIZE, 1);
+
+    let old = bench("spectrum: 旧实现 (spectrum-analyzer)", || {
+        let windowed = windows::hamming_window(black_box(&samples));
+        let spec = samples_fft_to_spectrum(
+            &windowed,
+            SAMPLE_RATE,
+            FrequencyLimit::Range(FREQ_RANGE.0, FREQ_RANGE.1),
+            Some(&scaling::divide_by_N_sqrt),
+        )
+        .unwrap();
+        let freq_min = spec.min_fr().val();
+        let freq_range = spec.max_fr().val() - freq_min;
+        let mut result = [0.0f32; FFT_SIZE];
+        for (i, v) in result.iter_mut().enumerate() {
+            let freq = i as f32 / FFT_SIZE as f32 * freq_range + freq_min;
+            *v = spec.freq_val_exact(freq).val();
+        }
+        black_box(result);
+    });
+
+    let mut analyzer = SpectrumAnalyzer::new(FFT_SIZE, SAMPLE_RATE);
+    let new = bench("spectrum: 新实现 (realfft)", || {
+        let mut result = [0.0f32; FFT_SIZE];
+        analyzer.analyze(black_box(&samples).iter().copied(), FREQ_RANGE, &mut result);
+        black_box(result);
+    });
+    report(old, new);
+}
+
+fn bench_resample() {
+    let input = test_signal(CHUNK_FRAMES, 2);
+
+    let mut stereo = rubato::FastFixedOut::<f32>::new(
+        SAMPLE_RATE as f64 / 48000.0,
+        2.0,
+        rubato::PolynomialDegree::Nearest,
+        CHUNK_FRAMES,
+        2,
+    )
+    .unwrap();
+    let mut stereo_in: Vec<Vec<f32>> = (0..2)
+        .map(|_| Vec::with_capacity(CHUNK_FRAMES * 2))
+        .collect();
+    let mut stereo_out = stereo.output_buffer_allocate(true);
+    let old = bench("resample 48k->44.1k: 旧实现 (双声道)", || {
+        for frame in black_box(&input).chunks_exact(2) {
+            stereo_in[0].push(frame[0]);
+            stereo_in[1].push(frame[1]);
+        }
+        while stereo_in[0].len() >= stereo.input_frames_next() {
+            let (consumed, _) = stereo
+                .process_into_buffer(&stereo_in, &mut stereo_out, None)
+                .unwrap();
+            stereo_in.iter_mut().for_each(|ch| {
+                ch.drain(..consumed);
+            });
+        }
+        black_box(&stereo_out);
+    });
+
+    let mut player = FFTPlayer::new();
+    let new = bench(
+        "resample 48k->44.1k: 新实现 (先混为单声道)",
+        || {
+            player.push_data(48000, 2, black_box(&input));
+            player.clear();
+        },
+    );
+    report(old, new);
+}
+
+fn main() {
+    bench_spectrum();
+    bench_resample();
+}
@@ -1,12 +1,15 @@
 use instant::Instant;
 use std::{cell::Cell, collections::VecDeque};
 
-use spectrum_analyzer::*;
 use symphonia_core::conv::{FromSample, IntoSample};
 use symphonia_core::sample::Sample;
 use wasm_bindgen::prelude::*;
 
 use super::resampler::FastFixedOutResampler;
+use super::spectrum::SpectrumAnalyzer;
+
+const FFT_SIZE: usize = 2048;
+const FFT_SAMPLE_RATE: usize = 44100;
 
 #[wasm_bindgen]
 extern "C" {
@@ -28,9 +31,12 @@ pub struct FFTPlayer {
     rate: usize,
     channels: usize,
     result_buf: [f32; 2048],
+    /// 上一次的频段数据，用于平滑，频段数变化时重新开始
+    bands_buf: Vec<f32>,
     pcm_queue: VecDeque<f32>,
     fft_duration: usize,
     resampler: Option<FastFixedOutResampler<f32>>,
+    analyzer: SpectrumAnalyzer,
     freq_range: Cell<(f32, f32)>,
 }
 
@@ -81,9 +87,11 @@ impl FFTPlayer {
         Self {
             last_fft_time: Instant::now(),
             result_buf: [0.0; 2048],
+            bands_buf: Vec::new(),
             pcm_queue: VecDeque::with_capacity(4096),
             fft_duration: 0,
             resampler: None,
+            analyzer: SpectrumAnalyzer::new(FFT_SIZE, FFT_SAMPLE_RATE as u32),
             freq_range: (80.0, 2000.0).into(),
             rate: 0,
             channels: 0,
@@ -96,6 +104,7 @@ impl FFTPlayer {
 
     pub fn clear(&mut self) {
         self.pcm_queue.clear();
+        self.bands_buf.clear();
     }
 
     pub fn set_freq_range(&self, start_freq: f32, end_freq: f32) {
@@ -103,59 +112,68 @@ impl FFTPlayer {
     }
 
     pub fn read(&mut self, buf: &mut [f32]) -> bool {
-        if self.pcm_queue.len() < 2048 {
+        if self.pcm_queue.len() < FFT_SIZE {
             self.last_fft_time = Instant::now();
             return false;
         }
 
-        let (start_freq, end_freq) = self.freq_range.get();
-
-        let mut fft_buf = [0.0; 2048];
-        self.pcm_queue
-            .iter()
-            .take(2048)
-            .enumerate()
-            .for_each(|(i, v)| {
-                fft_buf[i] = *v;
-            });
+        let mut spectrum = [0.0; 2048];
+        if !self.analyzer.analyze(
+            self.pcm_queue.iter().copied(),
+            self.freq_range.get(),
+            &mut spectrum,
+        ) {
+            eprintln!("FFT error: 无效的采样数据或频率范围");
+            return false;
+        }
+        self.result_buf
+            .iter_mut()
+            .zip(spectrum)
+            .for_each(|(v, s)| *v = (*v + s) / 2.0);
+        vec_interp(&self.result_buf, buf);
+        self.advance_queue();
+        true
+    }
 
-        let fft_buf = windows::hamming_window(&fft_buf);
+    /// 读取 `buf.len()` 个按对数间隔划分、经过 A 计权的频段数据，
+    /// 低频段在前，可以直接用于绘制可视化效果
+    pub fn read_bands(&mut self, buf: &mut [f32]) -> bool {
+        if self.pcm_queue.len() < FFT_SIZE {
+            self.last_fft_time = Instant::now();
+            return false;
+        }
 
-        match samples_fft_to_spectrum(
-            &fft_buf,
-            44100,
-            FrequencyLimit::Range(start_freq, end_freq),
-            Some(&scaling::divide_by_N_sqrt),
-        ) {
-            Ok(spec) => {
-                let result_buf_len = self.result_buf.len() as f32;
-                let freq_min = spec.min_fr().val();
-                let freq_max = spec.max_fr().val();
-                let freq_range = freq_max - freq_min;
-                self.result_buf.iter_mut().enumerate().for_each(|(i, v)| {
-                    let freq = i as f32 / result_buf_len * freq_range + freq_min;
-                    let freq = freq.clamp(freq_min, freq_max);
-                    *v += spec.freq_val_exact(freq).val();
-                    *v /= 2.0;
+        if !self
+            .analyzer
+            .analyze_bands(self.pcm_queue.iter().copied(), self.freq_range.get(), buf)
+        {
+            eprintln!("FFT error: 无效的采样数据或频率范围");
+            return false;
+        }
+        if self.bands_buf.len() == buf.len() {
+            self.bands_buf
+                .iter_mut()
+                .zip(buf.iter_mut())
+                .for_each(|(v, s)| {
+                    *v = (*v + *s) / 2.0;
+                    *s = *v;
                 });
-                vec_interp(&self.result_buf, buf);
-
-                let elapsed = self.last_fft_time.elapsed();
-                let elapsed_sec = elapsed.as_secs_f64();
-                self.last_fft_time = Instant::now();
-
-                let cut_len = (elapsed_sec * 44100.0) as usize;
-                for _ in 0..cut_len {
-                    self.pcm_queue.pop_front();
-                }
-                self.pcm_queue.truncate(2048 * 4);
-                true
-            }
-            Err(e) => {
-                eprintln!("FFT error: {:?}", e);
-                false
-            }
+        } else {
+            self.bands_buf = buf.to_vec();
         }
+        self.advance_queue();
+        true
+    }
+
+    /// 按照距离上次分析经过的时间丢弃已经播放过的采样
+    fn advance_queue(&mut self) {
+        let elapsed = self.last_fft_time.elapsed();
+        let elapsed_sec = elapsed.as_secs_f64();
+        self.last_fft_time = Instant::now();
+
+        let cut_len = (elapsed_sec * FFT_SAMPLE_RATE as f64) as usize;
+        self.pcm_queue.drain(..cut_len.min(self.pcm_queue.len()));
+        self.pcm_queue.truncate(FFT_SIZE * 4);
     }
 
     /// 将解码后的音频数据压入播放器
@@ -180,10 +198,11 @@ impl FFTPlayer {
             self.channels = channels;
             self.rate = rate;
 
+            // 先混合为单声道再重采样，多声道时可以省去大部分重采样的开销
             let resampler = FastFixedOutResampler::new_fast_fixed(
-                channels,
+                1,
                 rate,
-                44100,
+                FFT_SAMPLE_RATE,
                 1,
                 self.fft_duration as _,
             );
@@ -195,12 +214,10 @@ impl FFTPlayer {
 
         let rsp = self.resampler.as_mut().unwrap();
 
-        rsp.resample(channels, decoded);
+        rsp.resample_mono(channels, decoded);
 
         while let Some(buf) = rsp.flush() {
-            for v in buf.iter() {
-                self.pcm_queue.push_back(*v);
-            }
+            self.pcm_queue.extend(buf);
         }
     }
 }
@@ -270,4 +287,10 @@ impl FFTPlayer {
     pub fn read_js(&mut self, buf: &mut [f32]) -> bool {
         self.read(buf)
     }
+
+    /// 读取按对数间隔划分、经过 A 计权的频段数据，频段数为 `buf` 的长度
+    #[wasm_bindgen(js_name = "readBands")]
+    pub fn read_bands_js(&mut self, buf: &mut [f32]) -> bool {
+        self.read_bands(buf)
+    }
 }
@@ -1,5 +1,9 @@
 mod fft_player;
 mod resampler;
+mod spectrum;
+
+pub use fft_player::FFTPlayer;
+pub use spectrum::SpectrumAnalyzer;
 
 use wasm_bindgen::prelude::*;
 
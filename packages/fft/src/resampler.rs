@@ -113,17 +113,20 @@ where
         }
     }
 
-    /// Resamples a planar/non-interleaved input.
-    ///
-    /// Returns the resampled samples in an interleaved format.
-    pub fn resample<RS: Sample + IntoSample<f32> + Send>(&mut self, channels: usize, input: &[RS]) {
-        for i in input.chunks_exact(channels) {
-            for (ch, s) in i.iter().copied().enumerate() {
-                if let Some(channel) = self.input.get_mut(ch) {
-                    channel.push(s.into_sample());
-                }
-            }
-        }
+    /// 将交错的多声道输入混合为单声道后放入缓冲区，只应用于单声道的重采样器
+    pub fn resample_mono<RS: Sample + IntoSample<f32> + Send>(
+        &mut self,
+        channels: usize,
+        input: &[RS],
+    ) {
+        let scale = 1.0 / channels as f32;
+        self.input[0].extend(input.chunks_exact(channels).map(|frame| {
+            frame
+                .iter()
+                .map(|s| IntoSample::<f32>::into_sample(*s))
+                .sum::<f32>()
+                * scale
+        }));
     }
 
     /// Resample any remaining samples in the resample buffer.
@@ -0,0 +1,238 @@
+//! 基于 `realfft` 的频谱分析
+//!
+//! `realfft` 由 `rustfft` 实现，会在运行时检测并使用 AVX/SSE（x86_64）或 NEON（aarch64）指令。
+//! 窗函数、FFT 计划和各个缓冲区都只在创建时分配一次，之后每次分析都不会再分配内存。
+//!
+//! WASM 中的 [`crate::FFTPlayer`] 和 `amll-player-core` 的频谱广播共用这一个实现
+
+use std::{f32::consts::PI, sync::Arc};
+
+use realfft::{RealFftPlanner, RealToComplex, num_complex::Complex};
+
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn RealToComplex<f32>>,
+    sample_rate: f32,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    /// 创建一个每次分析 `size` 个采样的频谱分析器，`size` 应为 2 的幂
+    pub fn new(size: usize, sample_rate: u32) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(size);
+        let window = (0..size)
+            .map(|i| 0.54 - 0.46 * (2.0 * PI * i as f32 / (size - 1) as f32).cos())
+            .collect();
+        let input = fft.make_input_vec();
+        let output = fft.make_output_vec();
+        let scratch = fft.make_scratch_vec();
+        let magnitudes = vec![0.0; output.len()];
+        Self {
+            fft,
+            sample_rate: sample_rate as f32,
+            window,
+            input,
+            output,
+            scratch,
+            magnitudes,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.window.len()
+    }
+
+    /// 上一次分析得到的完整幅值谱，从 0 Hz 到奈奎斯特频率
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    /// 对 `samples` 加 Hamming 窗后做 FFT，并将 `freq_range` 范围内的幅值线性插值到 `dst` 中
+    ///
+    /// 采样不足时剩余部分视为 0，超出的部分会被忽略。
+    /// 幅值会除以 `sqrt(size)`，与 `spectrum_analyzer` 的 `divide_by_N_sqrt` 一致。
+    /// 采样中含有 NaN 或无穷大，或者频率范围内没有任何频点时返回 `false`
+    pub fn analyze(
+        &mut self,
+        samples: impl IntoIterator<Item = f32>,
+        freq_range: (f32, f32),
+        dst: &mut [f32],
+    ) -> bool {
+        if !self.compute_magnitudes(samples) {
+            return false;
+        }
+
+        let resolution = self.sample_rate / self.size() as f32;
+        let last_bin = self.magnitudes.len() - 1;
+        let (start_freq, end_freq) = freq_range;
+        let low = ((start_freq / resolution).ceil().max(0.0) as usize).min(last_bin);
+        let high = ((end_freq / resolution).floor().max(0.0) as usize).min(last_bin);
+        if low > high {
+            return false;
+        }
+
+        let bins = &self.magnitudes[low..=high];
+        let step = (high - low) as f32 / dst.len() as f32;
+        for (i, value) in dst.iter_mut().enumerate() {
+            let position = i as f32 * step;
+            let index = position as usize;
+            let frac = position - index as f32;
+            let next = bins.get(index + 1).unwrap_or(&bins[index]);
+            *value = bins[index] * (1.0 - frac) + next * frac;
+        }
+        true
+    }
+
+    /// 将 `freq_range` 按对数间隔分为 `dst.len()` 个频段，每个频段取其中各频点幅值的均方根，
+    /// 再按频段中心频率的 A 计权调整后写入 `dst`
+    ///
+    /// 低频处比频点间隔还窄的频段直接在中心频率处插值。
+    /// 采样无效或频率范围为空时返回 `false`
+    pub fn analyze_bands(
+        &mut self,
+        samples: impl IntoIterator<Item = f32>,
+        freq_range: (f32, f32),
+        dst: &mut [f32],
+    ) -> bool {
+        if dst.is_empty() || !self.compute_magnitudes(samples) {
+            return false;
+        }
+
+        let resolution = self.sample_rate / self.size() as f32;
+        let last_bin = self.magnitudes.len() - 1;
+        let start_freq = freq_range.0.max(resolution);
+        let end_freq = freq_range.1.min(last_bin as f32 * resolution);
+        if start_freq >= end_freq {
+            return false;
+        }
+
+        let ratio = (end_freq / start_freq).powf(1.0 / dst.len() as f32);
+        let mut low_freq = start_freq;
+        for value in dst.iter_mut() {
+            let high_freq = low_freq * ratio;
+            let center_freq = (low_freq * high_freq).sqrt();
+            let low = (low_freq / resolution).ceil() as usize;
+            let high = ((high_freq / resolution).floor() as usize).min(last_bin);
+            let magnitude = if low <= high {
+                let bins = &self.magnitudes[low..=high];
+                (bins.iter().map(|x| x * x).sum::<f32>() / bins.len() as f32).sqrt()
+            } else {
+                let position = center_freq / resolution;
+                let index = (position as usize).min(last_bin);
+                let frac = position - index as f32;
+                let next = self
+                    .magnitudes
+                    .get(index + 1)
+                    .unwrap_or(&self.magnitudes[index]);
+                self.magnitudes[index] * (1.0 - frac) + next * frac
+            };
+            *value = magnitude * a_weighting(center_freq);
+            low_freq = high_freq;
+        }
+        true
+    }
+
+    /// 加窗并做 FFT，结果保存在 `magnitudes` 中，采样中含有 NaN 或无穷大时返回 `false`
+    fn compute_magnitudes(&mut self, samples: impl IntoIterator<Item = f32>) -> bool {
+        self.input.fill(0.0);
+        for ((input, sample), window) in self.input.iter_mut().zip(samples).zip(&self.window) {
+            *input = sample * window;
+        }
+        if self.input.iter().any(|x| !x.is_finite()) {
+            return false;
+        }
+
+        if self
+            .fft
+            .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+            .is_err()
+        {
+            return false;
+        }
+
+        let scale = 1.0 / (self.size() as f32).sqrt();
+        for (magnitude, value) in self.magnitudes.iter_mut().zip(&self.output) {
+            *magnitude = value.norm() * scale;
+        }
+        true
+    }
+}
+
+/// IEC 61672 的 A 计权曲线，返回线性增益，1 kHz 处为 1
+fn a_weighting(freq: f32) -> f32 {
+    let f2 = (freq as f64).powi(2);
+    let ra = 12194f64.powi(2) * f2 * f2
+        / ((f2 + 20.6f64.powi(2))
+            * ((f2 + 107.7f64.powi(2)) * (f2 + 737.9f64.powi(2))).sqrt()
+            * (f2 + 12194f64.powi(2)));
+    // +2.0 dB 使 1 kHz 处的增益归一
+    (ra * 10f64.powf(2.0 / 20.0)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 2048;
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn sine(freq: f32) -> impl Iterator<Item = f32> {
+        (0..SIZE).map(move |i| (2.0 * PI * freq * i as f32 / SAMPLE_RATE as f32).sin())
+    }
+
+    fn loudest_band(freq: f32, freq_range: (f32, f32), bands: usize) -> usize {
+        let mut analyzer = SpectrumAnalyzer::new(SIZE, SAMPLE_RATE);
+        let mut dst = vec![0.0; bands];
+        assert!(analyzer.analyze_bands(sine(freq), freq_range, &mut dst));
+        dst.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0
+    }
+
+    fn to_db(gain: f32) -> f32 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn a_weighting_matches_reference_values() {
+        assert!(to_db(a_weighting(1000.0)).abs() < 0.01);
+        // IEC 61672 表中的标称值
+        assert!((to_db(a_weighting(100.0)) + 19.1).abs() < 0.1);
+        assert!((to_db(a_weighting(10000.0)) + 2.5).abs() < 0.1);
+        assert!((to_db(a_weighting(20.0)) + 50.5).abs() < 0.2);
+    }
+
+    #[test]
+    fn bands_are_split_logarithmically() {
+        // 100 Hz 到 10 kHz 分为 4 段，边界为 100、316、1000、3162 和 10000 Hz
+        let range = (100.0, 10000.0);
+        assert_eq!(loudest_band(200.0, range, 4), 0);
+        assert_eq!(loudest_band(450.0, range, 4), 1);
+        assert_eq!(loudest_band(850.0, range, 4), 1);
+        assert_eq!(loudest_band(1200.0, range, 4), 2);
+        assert_eq!(loudest_band(6000.0, range, 4), 3);
+    }
+
+    #[test]
+    fn bands_narrower_than_a_bin_are_interpolated() {
+        let mut analyzer = SpectrumAnalyzer::new(SIZE, SAMPLE_RATE);
+        let mut dst = [0.0; 64];
+        assert!(analyzer.analyze_bands(sine(100.0), (20.0, 200.0), &mut dst));
+        assert!(dst.iter().all(|x| x.is_finite() && *x >= 0.0));
+        assert!(dst.iter().any(|x| *x > 0.0));
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        let mut analyzer = SpectrumAnalyzer::new(SIZE, SAMPLE_RATE);
+        let mut dst = [0.0; 8];
+        assert!(!analyzer.analyze_bands(sine(1000.0), (5000.0, 1000.0), &mut dst));
+        assert!(!analyzer.analyze_bands(sine(1000.0), (100.0, 1000.0), &mut []));
+        assert!(!analyzer.analyze_bands(std::iter::once(f32::NAN), (100.0, 1000.0), &mut dst));
+    }
+}
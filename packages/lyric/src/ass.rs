@@ -6,10 +6,15 @@
 //! 如果是背景歌词则会在名称后面加上后缀 `-bg`
 //! 如果是译文则会在名称后面加上后缀 `-trans`
 //! 如果是音译则会在名称后面加上后缀 `-roman`
+//!
+//! 另外提供了导出 Aegisub 工程的 [`stringify_aegisub_project`]，
+//! 会附带完整的样式定义，字体和颜色等可以通过 [`AegisubTemplate`] 配置
 use crate::*;
 use std::fmt::Write;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "serde")]
+use serde::*;
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
 use wasm_bindgen::prelude::*;
 
 fn write_timestamp(result: &mut String, time: u64) {
@@ -21,6 +26,58 @@ fn write_timestamp(result: &mut String, time: u64) {
     write!(result, "{}:{:02}:{:02}.{:02}", hour, min, sec % 60, ms / 10).unwrap()
 }
 
+/// 计算歌词行的时间范围，忽略没有时长的单词，没有任何有效单词时返回 `None`
+fn line_time_range(line: &LyricLine) -> Option<(u64, u64)> {
+    // 防止开始时间为 0 的空格影响行开始时间的计算
+    let timed_words = line
+        .words
+        .iter()
+        .filter(|word| word.end_time > word.start_time);
+    let start_time = timed_words.clone().map(|x| x.start_time).min()?;
+    let end_time = timed_words.map(|x| x.end_time).max()?;
+    Some((start_time, end_time))
+}
+
+/// 写入歌词文本，换行需要写为 `\N`，`{` 和 `}` 会被当作标签，替换为全角括号
+fn write_escaped_text(result: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\n' => result.push_str("\\N"),
+            '{' => result.push('（'),
+            '}' => result.push('）'),
+            c => result.push(c),
+        }
+    }
+}
+
+/// 写入带有 `\k` 卡拉 OK 标签的歌词行文本
+fn write_karaoke_text(result: &mut String, line: &LyricLine, start_time: u64) {
+    let mut previous_word_end_time = start_time;
+
+    for word in &line.words {
+        if word.start_time >= word.end_time {
+            write_escaped_text(result, &word.word);
+            continue;
+        }
+
+        if word.start_time > previous_word_end_time {
+            let gap_duration_cs = (word.start_time.saturating_sub(previous_word_end_time) + 5) / 10;
+            if gap_duration_cs > 0 {
+                write!(result, "{{\\k{}}}", gap_duration_cs).unwrap();
+            }
+        }
+
+        let word_duration_cs = (word.end_time.saturating_sub(word.start_time) + 5) / 10;
+        if word_duration_cs > 0 {
+            write!(result, "{{\\k{}}}", word_duration_cs).unwrap();
+        }
+
+        write_escaped_text(result, &word.word);
+
+        previous_word_end_time = word.end_time;
+    }
+}
+
 pub fn stringify_ass(lines: &[LyricLine]) -> String {
     let mut result = String::with_capacity(
         lines
@@ -36,22 +93,10 @@ pub fn stringify_ass(lines: &[LyricLine]) -> String {
     );
 
     for line in lines {
-        result.push_str("Dialogue: 0,");
-
-        // 防止开始时间为 0 的空格影响行开始时间的计算
-        let timed_words: Vec<_> = line
-            .words
-            .iter()
-            .filter(|word| word.end_time > word.start_time)
-            .collect();
-        let start_time = timed_words.iter().map(|x| x.start_time).min();
-        let end_time = timed_words.iter().map(|x| x.end_time).max();
-
-        if start_time.is_none() || end_time.is_none() {
+        let Some((start_time, end_time)) = line_time_range(line) else {
             continue;
-        }
-        let start_time = start_time.unwrap();
-        let end_time = end_time.unwrap();
+        };
+        result.push_str("Dialogue: 0,");
         write_timestamp(&mut result, start_time);
         result.push_str(", ");
         write_timestamp(&mut result, end_time);
@@ -65,31 +110,7 @@ pub fn stringify_ass(lines: &[LyricLine]) -> String {
             result.push_str("-bg");
         }
         result.push_str(",0,0,0,,");
-        let mut previous_word_end_time = start_time;
-
-        for word in &line.words {
-            if word.start_time >= word.end_time {
-                result.push_str(&word.word);
-                continue;
-            }
-
-            if word.start_time > previous_word_end_time {
-                let gap_duration_cs =
-                    (word.start_time.saturating_sub(previous_word_end_time) + 5) / 10;
-                if gap_duration_cs > 0 {
-                    write!(&mut result, "{{\\k{}}}", gap_duration_cs).unwrap();
-                }
-            }
-
-            let word_duration_cs = (word.end_time.saturating_sub(word.start_time) + 5) / 10;
-            if word_duration_cs > 0 {
-                write!(&mut result, "{{\\k{}}}", word_duration_cs).unwrap();
-            }
-
-            result.push_str(&word.word);
-
-            previous_word_end_time = word.end_time;
-        }
+        write_karaoke_text(&mut result, line, start_time);
         result.push('\n');
 
         if !line.translated_lyric.is_empty() {
@@ -135,9 +156,371 @@ pub fn stringify_ass(lines: &[LyricLine]) -> String {
     result
 }
 
-#[cfg(target_arch = "wasm32")]
+/// Aegisub 工程中单个样式的配置
+///
+/// 颜色使用 `#RRGGBB` 或 `#RRGGBBAA` 格式，也可以直接填写 ASS 的 `&HAABBGGRR` 格式
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase", default))]
+pub struct AssStyle {
+    pub font_name: String,
+    pub font_size: u32,
+    /// 已唱部分的颜色
+    pub primary_colour: String,
+    /// 未唱部分的颜色
+    pub secondary_colour: String,
+    pub outline_colour: String,
+    pub back_colour: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub outline: f32,
+    pub shadow: f32,
+    /// 小键盘方位的对齐方式，例如 `2` 为底部居中
+    pub alignment: u8,
+    pub margin_l: u32,
+    pub margin_r: u32,
+    pub margin_v: u32,
+}
+
+impl Default for AssStyle {
+    fn default() -> Self {
+        Self {
+            font_name: "Arial".into(),
+            font_size: 72,
+            primary_colour: "#FFFFFF".into(),
+            secondary_colour: "#FFFFFF80".into(),
+            outline_colour: "#000000".into(),
+            back_colour: "#00000080".into(),
+            bold: false,
+            italic: false,
+            outline: 2.0,
+            shadow: 0.0,
+            alignment: 2,
+            margin_l: 60,
+            margin_r: 60,
+            margin_v: 160,
+        }
+    }
+}
+
+/// 导出 Aegisub 工程时使用的模板配置
+///
+/// 主唱、对唱、背景、译文和音译各自对应一个样式，分别命名为
+/// `Lyric`、`Duet`、`Background`、`Translation` 和 `Roman`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase", default))]
+pub struct AegisubTemplate {
+    pub title: String,
+    pub play_res_x: u32,
+    pub play_res_y: u32,
+    pub main_style: AssStyle,
+    pub duet_style: AssStyle,
+    pub background_style: AssStyle,
+    pub translation_style: AssStyle,
+    pub roman_style: AssStyle,
+    /// 是否用 `\k` 标签写入逐字时间，为 `false` 时只写入逐行的歌词文本
+    pub karaoke: bool,
+}
+
+impl Default for AegisubTemplate {
+    fn default() -> Self {
+        let main_style = AssStyle::default();
+        Self {
+            title: String::new(),
+            play_res_x: 1920,
+            play_res_y: 1080,
+            duet_style: AssStyle {
+                alignment: 3,
+                ..main_style.clone()
+            },
+            background_style: AssStyle {
+                font_size: 54,
+                margin_v: 250,
+                ..main_style.clone()
+            },
+            translation_style: AssStyle {
+                font_size: 48,
+                secondary_colour: "#FFFFFF".into(),
+                margin_v: 90,
+                ..main_style.clone()
+            },
+            roman_style: AssStyle {
+                font_size: 40,
+                secondary_colour: "#FFFFFF".into(),
+                margin_v: 40,
+                ..main_style.clone()
+            },
+            main_style,
+            karaoke: true,
+        }
+    }
+}
+
+/// 将 `#RRGGBB` 或 `#RRGGBBAA` 格式的颜色转换为 ASS 的 `&HAABBGGRR` 格式
+///
+/// ASS 中的透明度是反过来的，`00` 为不透明，无法识别的颜色会原样写入
+fn write_ass_colour(result: &mut String, colour: &str) {
+    let hex = colour.strip_prefix('#').unwrap_or(colour);
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    let rgba = match hex.len() {
+        6 => channel(0)
+            .zip(channel(2))
+            .zip(channel(4))
+            .map(|((r, g), b)| (r, g, b, 0xFF)),
+        8 => channel(0)
+            .zip(channel(2))
+            .zip(channel(4))
+            .zip(channel(6))
+            .map(|(((r, g), b), a)| (r, g, b, a)),
+        _ => None,
+    };
+    match rgba {
+        Some((r, g, b, a)) if colour.starts_with('#') => {
+            write!(result, "&H{:02X}{:02X}{:02X}{:02X}", 0xFF - a, b, g, r).unwrap()
+        }
+        _ => result.push_str(colour),
+    }
+}
+
+fn write_style(result: &mut String, name: &str, style: &AssStyle) {
+    write!(
+        result,
+        "Style: {},{},{},",
+        name, style.font_name, style.font_size
+    )
+    .unwrap();
+    for colour in [
+        &style.primary_colour,
+        &style.secondary_colour,
+        &style.outline_colour,
+        &style.back_colour,
+    ] {
+        write_ass_colour(result, colour);
+        result.push(',');
+    }
+    writeln!(
+        result,
+        "{},{},0,0,100,100,0,0,1,{},{},{},{},{},{},1",
+        if style.bold { -1 } else { 0 },
+        if style.italic { -1 } else { 0 },
+        style.outline,
+        style.shadow,
+        style.alignment,
+        style.margin_l,
+        style.margin_r,
+        style.margin_v,
+    )
+    .unwrap();
+}
+
+fn write_dialogue(
+    result: &mut String,
+    start_time: u64,
+    end_time: u64,
+    style: &str,
+    name: &str,
+    text: impl FnOnce(&mut String),
+) {
+    result.push_str("Dialogue: 0,");
+    write_timestamp(result, start_time);
+    result.push(',');
+    write_timestamp(result, end_time);
+    write!(result, ",{},{},0,0,0,,", style, name).unwrap();
+    text(result);
+    result.push('\n');
+}
+
+/// 将歌词导出为可以直接在 Aegisub 中打开编辑的卡拉 OK 字幕工程
+///
+/// 逐字时间会写为 `\k` 标签，译文和音译会作为单独的字幕行使用各自的样式，
+/// 说话人名称与 [`stringify_ass`] 的规则相同
+pub fn stringify_aegisub_project(lines: &[LyricLine], template: &AegisubTemplate) -> String {
+    let mut result = String::with_capacity(
+        lines
+            .iter()
+            .map(|x| x.words.iter().map(|x| x.word.len() + 20).sum::<usize>())
+            .sum::<usize>()
+            + 1024,
+    );
+
+    result.push_str("[Script Info]\n");
+    if !template.title.is_empty() {
+        writeln!(result, "Title: {}", template.title).unwrap();
+    }
+    result.push_str("ScriptType: v4.00+\n");
+    result.push_str("WrapStyle: 0\n");
+    result.push_str("ScaledBorderAndShadow: yes\n");
+    writeln!(result, "PlayResX: {}", template.play_res_x).unwrap();
+    writeln!(result, "PlayResY: {}", template.play_res_y).unwrap();
+    result.push('\n');
+
+    result.push_str("[V4+ Styles]\n");
+    result.push_str("Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n");
+    write_style(&mut result, "Lyric", &template.main_style);
+    write_style(&mut result, "Duet", &template.duet_style);
+    write_style(&mut result, "Background", &template.background_style);
+    write_style(&mut result, "Translation", &template.translation_style);
+    write_style(&mut result, "Roman", &template.roman_style);
+    result.push('\n');
+
+    result.push_str("[Events]\n");
+    result.push_str(
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+
+    for line in lines {
+        let Some((start_time, end_time)) = line_time_range(line) else {
+            continue;
+        };
+        let style = if line.is_bg {
+            "Background"
+        } else if line.is_duet {
+            "Duet"
+        } else {
+            "Lyric"
+        };
+        let name = match (line.is_duet, line.is_bg) {
+            (false, false) => "v1",
+            (true, false) => "v2",
+            (false, true) => "v1-bg",
+            (true, true) => "v2-bg",
+        };
+
+        write_dialogue(&mut result, start_time, end_time, style, name, |result| {
+            if template.karaoke {
+                write_karaoke_text(result, line, start_time)
+            } else {
+                for word in &line.words {
+                    write_escaped_text(result, &word.word);
+                }
+            }
+        });
+        if !line.translated_lyric.is_empty() {
+            write_dialogue(
+                &mut result,
+                start_time,
+                end_time,
+                "Translation",
+                &format!("{name}-trans"),
+                |result| write_escaped_text(result, &line.translated_lyric),
+            );
+        }
+        if !line.roman_lyric.is_empty() {
+            write_dialogue(
+                &mut result,
+                start_time,
+                end_time,
+                "Roman",
+                &format!("{name}-roman"),
+                |result| write_escaped_text(result, &line.roman_lyric),
+            );
+        }
+    }
+
+    result
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "stringifyAss", skip_typescript)]
 pub fn stringify_ass_js(lrc: JsValue) -> String {
     let lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lrc).unwrap();
     stringify_ass(&lines)
 }
+
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
+#[wasm_bindgen(js_name = "stringifyAegisubProject", skip_typescript)]
+pub fn stringify_aegisub_project_js(lrc: JsValue, template: JsValue) -> String {
+    let lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lrc).unwrap();
+    let template: Option<AegisubTemplate> = serde_wasm_bindgen::from_value(template).unwrap();
+    stringify_aegisub_project(&lines, &template.unwrap_or_default())
+}
+
+#[test]
+fn test_stringify_aegisub_project() {
+    let lines = vec![
+        LyricLine {
+            words: vec![
+                LyricWord {
+                    start_time: 1000,
+                    end_time: 1500,
+                    word: "Hello".into(),
+                    ..Default::default()
+                },
+                LyricWord {
+                    start_time: 1700,
+                    end_time: 2000,
+                    word: "world".into(),
+                    ..Default::default()
+                },
+            ],
+            translated_lyric: "你好世界".into(),
+            ..Default::default()
+        },
+        LyricLine {
+            words: vec![LyricWord {
+                start_time: 2000,
+                end_time: 2500,
+                word: "ooh".into(),
+                ..Default::default()
+            }],
+            is_bg: true,
+            is_duet: true,
+            ..Default::default()
+        },
+    ];
+    let template = AegisubTemplate {
+        main_style: AssStyle {
+            font_name: "Source Han Sans".into(),
+            primary_colour: "#FF8000".into(),
+            secondary_colour: "#11223380".into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let result = stringify_aegisub_project(&lines, &template);
+
+    assert!(result.contains("ScriptType: v4.00+\n"));
+    assert!(result.contains(
+        "Style: Lyric,Source Han Sans,72,&H000080FF,&H7F332211,&H00000000,&H7F000000,0,0,"
+    ));
+    assert!(result.contains(
+        "Dialogue: 0,0:00:01.00,0:00:02.00,Lyric,v1,0,0,0,,{\\k50}Hello{\\k20}{\\k30}world\n"
+    ));
+    assert!(
+        result.contains("Dialogue: 0,0:00:01.00,0:00:02.00,Translation,v1-trans,0,0,0,,你好世界\n")
+    );
+    assert!(
+        result.contains("Dialogue: 0,0:00:02.00,0:00:02.50,Background,v2-bg,0,0,0,,{\\k50}ooh\n")
+    );
+}
+
+#[test]
+fn test_stringify_aegisub_project_without_karaoke() {
+    let lines = vec![LyricLine {
+        words: vec![
+            LyricWord {
+                start_time: 1000,
+                end_time: 1500,
+                word: "Hello ".into(),
+                ..Default::default()
+            },
+            LyricWord {
+                start_time: 1500,
+                end_time: 2000,
+                word: "{world}".into(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }];
+    let template = AegisubTemplate {
+        karaoke: false,
+        ..Default::default()
+    };
+
+    let result = stringify_aegisub_project(&lines, &template);
+
+    assert!(result.contains("Dialogue: 0,0:00:01.00,0:00:02.00,Lyric,v1,0,0,0,,Hello （world）\n"));
+}
@@ -12,7 +12,7 @@ const DEC_KEY: &[u8; 24] = b"!@#)(*$%123ZXC!@!@#)(NHL";
 static CIPHER: LazyLock<qdec::TripleQDES> = LazyLock::new(|| qdec::TripleQDES::new(DEC_KEY, true));
 
 fn decode_hex(s: &str) -> Vec<u8> {
-    if s.len() % 2 == 0 {
+    if s.len().is_multiple_of(2) {
         (0..s.len())
             .step_by(2)
             .filter_map(|i| s.get(i..i + 2).map(|sub| u8::from_str_radix(sub, 16).ok()))
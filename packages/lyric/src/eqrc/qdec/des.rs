@@ -110,7 +110,7 @@ fn generate_sp_tables() -> [[u32; 64]; 8] {
     ];
 
     for s_box_idx in 0..8 {
-        for s_box_input in 0..64 {
+        for (s_box_input, sp_entry) in sp_tables[s_box_idx].iter_mut().enumerate() {
             let s_box_index = sboxbit(s_box_input as u8);
             let four_bit_output = sboxes[s_box_idx][s_box_index];
 
@@ -127,7 +127,7 @@ fn generate_sp_tables() -> [[u32; 64]; 8] {
                 }
             }
 
-            sp_tables[s_box_idx][s_box_input] = post_p_box_val;
+            *sp_entry = post_p_box_val;
         }
     }
     sp_tables
@@ -76,7 +76,9 @@ pub fn stringify_eslrc(lines: &[LyricLine]) -> String {
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "parseEslrc", skip_typescript)]
 pub fn parse_eslrc_js(src: &str) -> wasm_bindgen::JsValue {
-    serde_wasm_bindgen::to_value(&parse_eslrc(src)).unwrap()
+    let mut lines = parse_eslrc(src);
+    crate::attach_js_line_breaks(&mut lines);
+    serde_wasm_bindgen::to_value(&lines).unwrap()
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
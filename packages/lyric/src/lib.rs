@@ -4,6 +4,8 @@ pub mod ass;
 pub mod eqrc;
 #[cfg(feature = "eslrc")]
 pub mod eslrc;
+#[cfg(feature = "line-break")]
+pub mod line_break;
 #[cfg(feature = "lrc")]
 pub mod lrc;
 #[cfg(feature = "lys")]
@@ -26,6 +28,15 @@ use std::borrow::Cow;
 #[cfg(feature = "serde")]
 use serde::*;
 
+/// WASM 导出的解析函数在返回前调用，启用 `line-break` 功能时为歌词行附上候选换行点
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
+pub(crate) fn attach_js_line_breaks(lines: &mut [LyricLine]) {
+    #[cfg(feature = "line-break")]
+    line_break::attach_line_breaks(lines);
+    #[cfg(not(feature = "line-break"))]
+    let _ = lines;
+}
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(all(target_arch = "wasm32", feature = "wee_alloc"))]
@@ -73,6 +84,15 @@ impl LyricWord<'_> {
         }
     }
 
+    pub fn into_static(self) -> LyricWord<'static> {
+        LyricWord {
+            start_time: self.start_time,
+            end_time: self.end_time,
+            word: Cow::Owned(self.word.into_owned()),
+            roman_word: Cow::Owned(self.roman_word.into_owned()),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.word.trim().is_empty()
     }
@@ -93,6 +113,59 @@ impl LyricWordOwned {
     }
 }
 
+/// 带有语言信息的一条歌词翻译
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct LyricTranslation<'a> {
+    /// 翻译的语言代码，例如 `zh-Hans`，来源没有标注语言时为空字符串
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lang: Cow<'a, str>,
+    pub text: Cow<'a, str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct LyricTranslationOwned {
+    pub lang: String,
+    pub text: String,
+}
+
+impl<'a> From<LyricTranslation<'a>> for LyricTranslationOwned {
+    fn from(value: LyricTranslation<'a>) -> Self {
+        Self {
+            lang: value.lang.into_owned(),
+            text: value.text.into_owned(),
+        }
+    }
+}
+
+impl LyricTranslation<'_> {
+    pub fn to_owned(&self) -> LyricTranslationOwned {
+        LyricTranslationOwned {
+            lang: self.lang.clone().into_owned(),
+            text: self.text.clone().into_owned(),
+        }
+    }
+
+    pub fn into_static(self) -> LyricTranslation<'static> {
+        LyricTranslation {
+            lang: Cow::Owned(self.lang.into_owned()),
+            text: Cow::Owned(self.text.into_owned()),
+        }
+    }
+}
+
+impl LyricTranslationOwned {
+    pub fn to_ref<'a>(&'a self) -> LyricTranslation<'a> {
+        LyricTranslation {
+            lang: self.lang.as_str().into(),
+            text: self.text.as_str().into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
@@ -100,6 +173,12 @@ pub struct LyricLine<'a> {
     pub words: Vec<LyricWord<'a>>,
     #[cfg_attr(feature = "serde", serde(default))]
     pub translated_lyric: Cow<'a, str>,
+    /// 该行的所有翻译，按语言去重并保持来源中的顺序
+    ///
+    /// `translated_lyric` 总是其中优先级最高的一条。只有 TTML 能保存多条翻译，
+    /// 其它格式的解析器不会填写这个字段，生成 ASS 等其它格式时也只会写出 `translated_lyric`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub translations: Vec<LyricTranslation<'a>>,
     #[cfg_attr(feature = "serde", serde(default))]
     pub roman_lyric: Cow<'a, str>,
     #[cfg_attr(feature = "serde", serde(default, rename = "isBG"))]
@@ -110,6 +189,27 @@ pub struct LyricLine<'a> {
     pub start_time: u64,
     #[cfg_attr(feature = "serde", serde(default))]
     pub end_time: u64,
+    /// 该行的候选换行点，按位置排列
+    ///
+    /// 解析器不会填写这个字段，启用 `line-break` 功能后由 `line_break::attach_line_breaks` 计算
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub line_breaks: Vec<LineBreakCandidate>,
+}
+
+/// 一个候选换行点，表示可以在该位置之前换行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct LineBreakCandidate {
+    /// 换行点所在单词的下标
+    pub word_index: usize,
+    /// 换行点在该单词中的偏移，以 UTF-16 码元计，与 JS 字符串的下标一致
+    ///
+    /// 为 0 时表示在该单词之前换行
+    pub offset: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -118,11 +218,18 @@ pub struct LyricLine<'a> {
 pub struct LyricLineOwned {
     pub words: Vec<LyricWordOwned>,
     pub translated_lyric: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub translations: Vec<LyricTranslationOwned>,
     pub roman_lyric: String,
     pub is_bg: bool,
     pub is_duet: bool,
     pub start_time: u64,
     pub end_time: u64,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub line_breaks: Vec<LineBreakCandidate>,
 }
 
 impl<'a> From<LyricLine<'a>> for LyricLineOwned {
@@ -130,11 +237,13 @@ impl<'a> From<LyricLine<'a>> for LyricLineOwned {
         Self {
             words: value.words.iter().map(|w| w.to_owned()).collect(),
             translated_lyric: value.translated_lyric.into_owned(),
+            translations: value.translations.into_iter().map(Into::into).collect(),
             roman_lyric: value.roman_lyric.into_owned(),
             is_bg: value.is_bg,
             is_duet: value.is_duet,
             start_time: value.start_time,
             end_time: value.end_time,
+            line_breaks: value.line_breaks,
         }
     }
 }
@@ -144,11 +253,31 @@ impl LyricLine<'_> {
         LyricLineOwned {
             words: self.words.iter().map(|w| w.to_owned()).collect(),
             translated_lyric: self.translated_lyric.clone().into_owned(),
+            translations: self.translations.iter().map(|t| t.to_owned()).collect(),
             roman_lyric: self.roman_lyric.clone().into_owned(),
             is_bg: self.is_bg,
             is_duet: self.is_duet,
             start_time: self.start_time,
             end_time: self.end_time,
+            line_breaks: self.line_breaks.clone(),
+        }
+    }
+
+    pub fn into_static(self) -> LyricLine<'static> {
+        LyricLine {
+            words: self.words.into_iter().map(|w| w.into_static()).collect(),
+            translated_lyric: Cow::Owned(self.translated_lyric.into_owned()),
+            translations: self
+                .translations
+                .into_iter()
+                .map(|t| t.into_static())
+                .collect(),
+            roman_lyric: Cow::Owned(self.roman_lyric.into_owned()),
+            is_bg: self.is_bg,
+            is_duet: self.is_duet,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            line_breaks: self.line_breaks,
         }
     }
 
@@ -170,11 +299,13 @@ impl LyricLineOwned {
         LyricLine {
             words: self.words.iter().map(|w| w.to_ref()).collect(),
             translated_lyric: self.translated_lyric.as_str().into(),
+            translations: self.translations.iter().map(|t| t.to_ref()).collect(),
             roman_lyric: self.roman_lyric.as_str().into(),
             is_bg: self.is_bg,
             is_duet: self.is_duet,
             start_time: self.start_time,
             end_time: self.end_time,
+            line_breaks: self.line_breaks.clone(),
         }
     }
 
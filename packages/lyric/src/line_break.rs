@@ -0,0 +1,84 @@
+//! 为歌词行预先计算适合换行的位置
+//!
+//! 候选换行点需要同时满足两个条件：是 Unicode 换行规则（UAX #14）允许的换行机会，
+//! 并且落在分词结果的词语边界上。中文和日文会使用内置的词典分词，
+//! 因此不会把一个词从中间拆开，也不会在标点之前换行
+//!
+//! 内置的分词词典会让编译产物增大数 MB，因此需要手动启用 `line-break` 功能。
+//! 启用后 WASM 导出的各个解析函数会为解析结果附上候选换行点
+
+use icu_segmenter::{LineSegmenter, WordSegmenter, options::LineBreakOptions};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::{LineBreakCandidate, LyricLine};
+
+/// 计算每一行歌词的候选换行点，写入各行的 `line_breaks`
+///
+/// 行首和行尾不会作为候选换行点，已有的候选换行点会被覆盖
+pub fn attach_line_breaks(lines: &mut [LyricLine]) {
+    let word_segmenter = WordSegmenter::new_dictionary(Default::default());
+    let line_segmenter = LineSegmenter::new_for_non_complex_scripts(LineBreakOptions::default());
+
+    for line in lines {
+        let text: String = line.words.iter().map(|w| w.word.as_ref()).collect();
+        let word_boundaries: Vec<usize> = word_segmenter.segment_str(&text).collect();
+        let mut breaks = line_segmenter
+            .segment_str(&text)
+            .filter(|&pos| pos > 0 && pos < text.len())
+            .filter(|pos| word_boundaries.binary_search(pos).is_ok())
+            .peekable();
+
+        let mut candidates = Vec::new();
+        let mut word_start = 0;
+        for (word_index, word) in line.words.iter().enumerate() {
+            let word_end = word_start + word.word.len();
+            while let Some(pos) = breaks.next_if(|&pos| pos < word_end) {
+                candidates.push(LineBreakCandidate {
+                    word_index,
+                    offset: word.word[..pos - word_start].encode_utf16().count(),
+                });
+            }
+            word_start = word_end;
+        }
+        line.line_breaks = candidates;
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
+#[wasm_bindgen(js_name = "computeLineBreaks", skip_typescript)]
+pub fn compute_line_breaks_js(lines: JsValue) -> JsValue {
+    let mut lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lines).unwrap();
+    attach_line_breaks(&mut lines);
+    serde_wasm_bindgen::to_value(&lines).unwrap()
+}
+
+#[test]
+fn test_attach_line_breaks() {
+    use crate::LyricWord;
+
+    let line = |words: &[&'static str]| LyricLine {
+        words: words
+            .iter()
+            .map(|&word| LyricWord {
+                word: word.into(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let mut lines = vec![
+        line(&["我爱北京天安门"]),
+        line(&["Hello", " ", "world"]),
+        line(&["你好，", "世界"]),
+        line(&[]),
+    ];
+
+    attach_line_breaks(&mut lines);
+    let at = |word_index, offset| LineBreakCandidate { word_index, offset };
+
+    assert_eq!(lines[0].line_breaks, vec![at(0, 1), at(0, 2), at(0, 4)]);
+    assert_eq!(lines[1].line_breaks, vec![at(2, 0)]);
+    assert_eq!(lines[2].line_breaks, vec![at(1, 0)]);
+    assert!(lines[3].line_breaks.is_empty());
+}
@@ -262,7 +262,9 @@ fn stringify_lrc_test() {
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "parseLrc", skip_typescript)]
 pub fn parse_lrc_js(src: &str) -> JsValue {
-    serde_wasm_bindgen::to_value(&parse_lrc(src)).unwrap()
+    let mut lines = parse_lrc(src);
+    crate::attach_js_line_breaks(&mut lines);
+    serde_wasm_bindgen::to_value(&lines).unwrap()
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
@@ -171,7 +171,7 @@ pub fn stringify_lys(lines: &[LyricLine]) -> String {
             write!(result, "{prop}").unwrap();
             for word in line.words.iter() {
                 let start_time = word.start_time;
-                let duration = word.end_time - word.start_time;
+                let duration = word.end_time.saturating_sub(word.start_time);
                 result.push_str(&word.word);
                 write!(result, "({start_time},{duration})").unwrap();
             }
@@ -185,7 +185,9 @@ pub fn stringify_lys(lines: &[LyricLine]) -> String {
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "parseLys", skip_typescript)]
 pub fn parse_lys_js(src: &str) -> JsValue {
-    serde_wasm_bindgen::to_value(&parse_lys(src)).unwrap()
+    let mut lines = parse_lys(src);
+    crate::attach_js_line_breaks(&mut lines);
+    serde_wasm_bindgen::to_value(&lines).unwrap()
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
@@ -214,3 +216,17 @@ fn test_props() {
         stringify_lys(&parse_lys("[8]Test(1234,567)"))
     );
 }
+
+#[test]
+fn stringify_lys_reversed_word_test() {
+    let lines = [LyricLine {
+        words: vec![LyricWord {
+            start_time: 1000,
+            end_time: 900,
+            word: "Test".into(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }];
+    assert_eq!(stringify_lys(&lines), "[0]Test(1000,0)\n");
+}
@@ -143,11 +143,15 @@ pub fn stringify_qrc(lines: &[LyricLine]) -> String {
     for line in lines {
         if !line.words.is_empty() {
             let start_time = line.words[0].start_time;
-            let duration: u64 = line.words.iter().map(|x| x.end_time - x.start_time).sum();
+            let duration: u64 = line
+                .words
+                .iter()
+                .map(|x| x.end_time.saturating_sub(x.start_time))
+                .sum();
             write!(result, "[{start_time},{duration}]").unwrap();
             for word in line.words.iter() {
                 let start_time = word.start_time;
-                let duration = word.end_time - word.start_time;
+                let duration = word.end_time.saturating_sub(word.start_time);
                 result.push_str(&word.word);
                 write!(result, "({start_time},{duration})").unwrap();
             }
@@ -161,7 +165,9 @@ pub fn stringify_qrc(lines: &[LyricLine]) -> String {
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "parseQrc", skip_typescript)]
 pub fn parse_qrc_js(src: &str) -> JsValue {
-    serde_wasm_bindgen::to_value(&parse_qrc(src)).unwrap()
+    let mut lines = parse_qrc(src);
+    crate::attach_js_line_breaks(&mut lines);
+    serde_wasm_bindgen::to_value(&lines).unwrap()
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
@@ -170,3 +176,17 @@ pub fn stringify_qrc_js(lrc: JsValue) -> String {
     let lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lrc).unwrap();
     stringify_qrc(&lines)
 }
+
+#[test]
+fn stringify_qrc_reversed_word_test() {
+    let lines = [LyricLine {
+        words: vec![LyricWord {
+            start_time: 1000,
+            end_time: 900,
+            word: "Test".into(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }];
+    assert_eq!(stringify_qrc(&lines), "[1000,0]Test(1000,0)\n");
+}
@@ -11,12 +11,48 @@ pub use write::*;
 #[cfg(feature = "serde")]
 use serde::*;
 
+/// 解析警告的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum ParseWarningCode {
+    /// 无法识别的实体引用，对应的文本会被丢弃
+    UnknownEntity,
+    /// 歌词行的开始时间晚于结束时间
+    InvalidLineTiming,
+    /// 单词的开始时间晚于结束时间
+    InvalidWordTiming,
+    /// 歌词行缺少 `begin` 或 `end` 属性，时间是按前后行和文本长度推断出来的
+    EstimatedLineTiming,
+    /// 单词缺少 `begin` 属性，开始时间按 0 处理
+    UntimedWord,
+    /// 元素的 `xml:id` 与之前的元素重复
+    DuplicateId,
+}
+
+/// 解析过程中发现的非致命问题
+///
+/// 附带出错元素在源文件中的位置，方便编辑器跳转到对应的位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ParseWarning {
+    pub code: ParseWarningCode,
+    pub message: String,
+    /// 出错元素在源文件中的字节偏移
+    pub byte_offset: usize,
+    /// 出错元素所在的行号，从 1 开始
+    pub line: usize,
+}
+
 #[derive(Debug, Default, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct TTMLLyric<'a> {
     pub lines: Vec<LyricLine<'a>>,
     pub metadata: Vec<(Cow<'a, str>, Vec<Cow<'a, str>>)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub warnings: Vec<ParseWarning>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -25,6 +61,8 @@ pub struct TTMLLyric<'a> {
 pub struct TTMLLyricOwned {
     pub lines: Vec<LyricLineOwned>,
     pub metadata: Vec<(String, Vec<String>)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub warnings: Vec<ParseWarning>,
 }
 
 impl<'a> From<TTMLLyric<'a>> for TTMLLyricOwned {
@@ -41,6 +79,26 @@ impl<'a> From<TTMLLyric<'a>> for TTMLLyricOwned {
                     )
                 })
                 .collect(),
+            warnings: ttml.warnings,
+        }
+    }
+}
+
+impl TTMLLyric<'_> {
+    pub fn into_static(self) -> TTMLLyric<'static> {
+        TTMLLyric {
+            lines: self.lines.into_iter().map(|x| x.into_static()).collect(),
+            metadata: self
+                .metadata
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        Cow::Owned(k.into_owned()),
+                        v.into_iter().map(|x| Cow::Owned(x.into_owned())).collect(),
+                    )
+                })
+                .collect(),
+            warnings: self.warnings,
         }
     }
 }
@@ -59,6 +117,7 @@ impl TTMLLyricOwned {
                     )
                 })
                 .collect(),
+            warnings: self.warnings.clone(),
         }
     }
 }
@@ -2,16 +2,23 @@
 use wasm_bindgen::prelude::*;
 
 use quick_xml::{
+    encoding::EncodingError,
     events::{BytesStart, Event, attributes::AttrError},
     *,
 };
-use std::{borrow::Cow, collections::HashMap, io::BufRead};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::BufRead,
+    sync::Arc,
+};
 use thiserror::Error;
 
-use crate::{LyricLine, LyricWord};
+use crate::{LyricLine, LyricTranslation, LyricWord};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 enum CurrentStatus {
+    #[default]
     None,
     InDiv,
     InP,
@@ -62,6 +69,8 @@ pub enum TTMLError {
     XmlAttrError(usize, AttrError),
     #[error("xml error on parsing attr timestamp at {0}")]
     XmlTimeStampError(usize),
+    #[error("wallclock time expression is not supported at {0}")]
+    UnsupportedWallclockTime(usize),
     #[error("xml error at {0}: {1}")]
     XmlError(usize, quick_xml::Error),
 }
@@ -80,103 +89,580 @@ impl TTMLError {
             TTMLError::UnexpectedSpanElement(pos) => pos,
             TTMLError::XmlAttrError(pos, _) => pos,
             TTMLError::XmlTimeStampError(pos) => pos,
+            TTMLError::UnsupportedWallclockTime(pos) => pos,
             TTMLError::XmlError(pos, _) => pos,
         }
     }
 }
 
+/// 解析 `begin`/`end`/`dur` 属性中的时间表达式
+///
+/// 墙上时钟形式的 `wallclock(...)` 与媒体时间无关，无法换算为歌词时间，会返回单独的错误
+fn parse_time_attr(value: &[u8], read_len: usize) -> std::result::Result<u64, TTMLError> {
+    if value.trim_ascii_start().starts_with(b"wallclock(") {
+        return Err(TTMLError::UnsupportedWallclockTime(read_len));
+    }
+    parse_timestamp(value)
+        .map(|(_, time)| time)
+        .map_err(|_| TTMLError::XmlTimeStampError(read_len))
+}
+
+/// 歌词行元素上的时间属性是否完整
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineTiming {
+    Complete,
+    /// 只有 `begin`，缺少 `end` 和 `dur`
+    BeginOnly,
+    Missing,
+}
+
+/// 按元素的属性配置歌词行
+///
+/// 缺少时间的行不在这里检查，而是在解析结束后按上下文推断时间
 fn configure_lyric_line(
     e: &BytesStart<'_>,
     read_len: usize,
-    main_agent: &[u8],
+    agents: &mut Agents,
     line: &mut LyricLine<'_>,
-) -> std::result::Result<(), TTMLError> {
+    warnings: &mut Vec<ParseWarning>,
+) -> std::result::Result<LineTiming, TTMLError> {
+    let mut begin = None;
+    let mut end = None;
+    let mut dur = None;
     for attr in e.attributes() {
         match attr {
             Ok(a) => match a.key.as_ref() {
                 b"ttm:agent" => {
-                    line.is_duet |= a.value.as_ref() != main_agent;
+                    let agent = agents.intern(&a.value);
+                    line.is_duet |= !agents.is_main(agent);
                 }
                 b"begin" => {
-                    if let Ok((_, time)) = parse_timestamp(a.value.as_bytes()) {
-                        line.start_time = time as _;
-                    } else {
-                        return Err(TTMLError::XmlTimeStampError(read_len));
-                    }
+                    begin = Some(parse_time_attr(&a.value, read_len)?);
                 }
                 b"end" => {
-                    if let Ok((_, time)) = parse_timestamp(a.value.as_bytes()) {
-                        line.end_time = time as _;
-                    } else {
-                        return Err(TTMLError::XmlTimeStampError(read_len));
-                    }
+                    end = Some(parse_time_attr(&a.value, read_len)?);
+                }
+                b"dur" => {
+                    dur = Some(parse_time_attr(&a.value, read_len)?);
                 }
                 _ => {}
             },
             Err(err) => return Err(TTMLError::XmlAttrError(read_len, err)),
         }
     }
-    Ok(())
+    let Some(start_time) = begin else {
+        return Ok(LineTiming::Missing);
+    };
+    line.start_time = start_time;
+    let Some(end_time) = resolve_end_time(start_time, end, dur) else {
+        return Ok(LineTiming::BeginOnly);
+    };
+    line.end_time = end_time;
+    if line.start_time > line.end_time {
+        warnings.push(new_warning(
+            ParseWarningCode::InvalidLineTiming,
+            format!(
+                "歌词行的开始时间 {}ms 晚于结束时间 {}ms",
+                line.start_time, line.end_time
+            ),
+            read_len,
+        ));
+    }
+    Ok(LineTiming::Complete)
 }
 
+/// 读取单词的时间属性
+///
+/// 所在的行缺少时间时，单词的时间会随行一起推断，
+/// 此时 `line_is_timed` 为假，不会对缺少 `begin` 的单词产生警告
 fn configure_lyric_word(
     e: &BytesStart<'_>,
     read_len: usize,
     word: &mut LyricWord<'_>,
+    line_is_timed: bool,
+    warnings: &mut Vec<ParseWarning>,
 ) -> std::result::Result<(), TTMLError> {
+    let mut has_begin = false;
+    let mut end = None;
+    let mut dur = None;
     for attr in e.attributes() {
         match attr {
             Ok(a) => match a.key.as_ref() {
                 b"begin" => {
-                    if let Ok((_, time)) = parse_timestamp(a.value.as_bytes()) {
-                        word.start_time = time as _;
-                    } else {
-                        return Err(TTMLError::XmlTimeStampError(read_len));
-                    }
+                    word.start_time = parse_time_attr(&a.value, read_len)?;
+                    has_begin = true;
                 }
                 b"end" => {
-                    if let Ok((_, time)) = parse_timestamp(a.value.as_bytes()) {
-                        word.end_time = time as _;
-                    } else {
-                        return Err(TTMLError::XmlTimeStampError(read_len));
-                    }
+                    end = Some(parse_time_attr(&a.value, read_len)?);
+                }
+                b"dur" => {
+                    dur = Some(parse_time_attr(&a.value, read_len)?);
                 }
                 _ => {}
             },
             Err(err) => return Err(TTMLError::XmlAttrError(read_len, err)),
         }
     }
+    if !has_begin && line_is_timed {
+        warnings.push(new_warning(
+            ParseWarningCode::UntimedWord,
+            "单词缺少开始时间".to_string(),
+            read_len,
+        ));
+    }
+    if let Some(end_time) = resolve_end_time(word.start_time, end, dur) {
+        word.end_time = end_time;
+    }
+    if word.start_time > word.end_time {
+        warnings.push(new_warning(
+            ParseWarningCode::InvalidWordTiming,
+            format!(
+                "单词的开始时间 {}ms 晚于结束时间 {}ms",
+                word.start_time, word.end_time
+            ),
+            read_len,
+        ));
+    }
     Ok(())
 }
 
-pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>, TTMLError> {
-    let mut reader = Reader::from_reader(data);
-    let mut buf: Vec<u8> = Vec::with_capacity(256);
-    let mut str_buf = String::with_capacity(256);
-    let mut status = CurrentStatus::None;
-    let mut result = TTMLLyric::default();
-    let mut read_len = 0;
-    let mut main_agent = Vec::new();
-
-    // 用于存储 Apple Music 格式的翻译
-    let mut itunes_translations: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+/// 计算元素的结束时间，`end` 优先，缺少 `end` 时使用 `begin + dur`
+fn resolve_end_time(start_time: u64, end: Option<u64>, dur: Option<u64>) -> Option<u64> {
+    end.or_else(|| dur.map(|dur| start_time.saturating_add(dur)))
+}
+
+/// 推断缺少时间的行时每个字符的时长，用于之后没有带时间的行、无法确定可用区间的情况
+const ESTIMATED_MS_PER_CHAR: u64 = 250;
+/// 按字符数估算时长时，一行最短的时长
+const MIN_ESTIMATED_LINE_MS: u64 = 1000;
+
+/// 为缺少 `begin` 或 `end` 的行推断时间，并为每一行添加一条
+/// [`ParseWarningCode::EstimatedLineTiming`] 警告
+///
+/// 行内的单词带有时间时直接取单词的时间范围。否则从上一行的结束（或该行自己的 `begin`）
+/// 到下一个已知开始时间的主行之间，按文本长度分配给这一段中缺少时间的各行，
+/// 之后没有已知开始时间的行时按文本长度估算时长。
+/// 背景人声行沿用所属主行的时间，行内的单词都没有时间时同样按文本长度分配行的时间
+fn estimate_line_times(
+    lines: &mut [LyricLine<'_>],
+    untimed_lines: &[(usize, usize, LineTiming)],
+    warnings: &mut Vec<ParseWarning>,
+) {
+    if untimed_lines.is_empty() {
+        return;
+    }
+
+    let mut needs_context = vec![false; lines.len()];
+    let mut begin_only = vec![false; lines.len()];
+    for &(idx, _, timing) in untimed_lines {
+        match word_time_range(&lines[idx]) {
+            Some((start_time, end_time)) => {
+                lines[idx].start_time = start_time;
+                lines[idx].end_time = end_time;
+            }
+            None => {
+                needs_context[idx] = true;
+                begin_only[idx] = timing == LineTiming::BeginOnly;
+            }
+        }
+    }
+
+    let main_lines: Vec<usize> = (0..lines.len()).filter(|&i| !lines[i].is_bg).collect();
+    let mut run_start = 0;
+    while run_start < main_lines.len() {
+        if !needs_context[main_lines[run_start]] {
+            run_start += 1;
+            continue;
+        }
+        // 只有 begin 的行开始时间已知，可以作为下一段的终点
+        let run_end = main_lines[run_start + 1..]
+            .iter()
+            .position(|&i| !needs_context[i] || begin_only[i])
+            .map_or(main_lines.len(), |pos| run_start + 1 + pos);
+        let run = &main_lines[run_start..run_end];
+
+        let prev_end = if begin_only[run[0]] {
+            lines[run[0]].start_time
+        } else {
+            run_start
+                .checked_sub(1)
+                .map_or(0, |pos| lines[main_lines[pos]].end_time)
+        };
+        let next_start = main_lines.get(run_end).map(|&i| lines[i].start_time);
+        let weights: Vec<u64> = run.iter().map(|&i| text_weight(&lines[i].words)).collect();
+        let durations = match next_start.filter(|&next_start| next_start > prev_end) {
+            Some(next_start) => split_by_weight(next_start - prev_end, &weights),
+            None => weights
+                .iter()
+                .map(|weight| (weight * ESTIMATED_MS_PER_CHAR).max(MIN_ESTIMATED_LINE_MS))
+                .collect(),
+        };
+        let mut cursor = prev_end;
+        for (&idx, duration) in run.iter().zip(durations) {
+            lines[idx].start_time = cursor;
+            cursor += duration;
+            lines[idx].end_time = cursor;
+        }
+        run_start = run_end;
+    }
+
+    for idx in 0..lines.len() {
+        if needs_context[idx]
+            && lines[idx].is_bg
+            && let Some(parent) = (0..idx).rev().find(|&i| !lines[i].is_bg)
+        {
+            lines[idx].start_time = lines[parent].start_time;
+            lines[idx].end_time = lines[parent].end_time;
+        }
+    }
+
+    for &(idx, byte_offset, _) in untimed_lines {
+        let line = &mut lines[idx];
+        if needs_context[idx] {
+            let weights: Vec<u64> = line.words.iter().map(word_weight).collect();
+            let durations =
+                split_by_weight(line.end_time.saturating_sub(line.start_time), &weights);
+            let mut cursor = line.start_time;
+            for (word, duration) in line.words.iter_mut().zip(durations) {
+                word.start_time = cursor;
+                cursor += duration;
+                word.end_time = cursor;
+            }
+        }
+        warnings.push(new_warning(
+            ParseWarningCode::EstimatedLineTiming,
+            format!(
+                "歌词行缺少时间，已推断为 {}ms 至 {}ms",
+                line.start_time, line.end_time
+            ),
+            byte_offset,
+        ));
+    }
+}
+
+/// 行内带有时间的单词覆盖的时间范围，没有任何单词带有时间时返回空
+fn word_time_range(line: &LyricLine<'_>) -> Option<(u64, u64)> {
+    let timed = line.words.iter().filter(|word| word.end_time > 0);
+    let start_time = timed.clone().map(|word| word.start_time).min()?;
+    let end_time = timed.map(|word| word.end_time).max()?;
+    Some((start_time, end_time))
+}
+
+/// 单词中不含空白的字符数
+fn word_weight(word: &LyricWord<'_>) -> u64 {
+    word.word.chars().filter(|c| !c.is_whitespace()).count() as u64
+}
+
+/// 一行文本的权重，至少为 1，避免空行分不到任何时间
+fn text_weight(words: &[LyricWord<'_>]) -> u64 {
+    words.iter().map(word_weight).sum::<u64>().max(1)
+}
+
+/// 按权重把 `total` 毫秒分为若干段，各段之和总是等于 `total`，权重全为 0 时平均分配
+fn split_by_weight(total: u64, weights: &[u64]) -> Vec<u64> {
+    let even = weights.iter().all(|&weight| weight == 0);
+    let sum = if even {
+        weights.len() as u64
+    } else {
+        weights.iter().sum()
+    };
+    let mut acc = 0;
+    let mut prev = 0;
+    weights
+        .iter()
+        .map(|&weight| {
+            acc += if even { 1 } else { weight };
+            let boundary = total * acc / sum;
+            let duration = boundary - prev;
+            prev = boundary;
+            duration
+        })
+        .collect()
+}
+
+/// 创建一个解析警告，行号在解析结束后由 [`locate_warnings`] 统一填写
+fn new_warning(code: ParseWarningCode, message: String, byte_offset: usize) -> ParseWarning {
+    ParseWarning {
+        code,
+        message,
+        byte_offset,
+        line: 0,
+    }
+}
+
+/// 为每个与之前的元素重复的 `xml:id` 产生一条警告
+fn report_duplicate_ids(element_ids: &[(Vec<u8>, usize)], warnings: &mut Vec<ParseWarning>) {
+    let mut seen = HashSet::new();
+    for (id, offset) in element_ids {
+        if !seen.insert(id.as_slice()) {
+            warnings.push(new_warning(
+                ParseWarningCode::DuplicateId,
+                format!("重复的 xml:id '{}'", String::from_utf8_lossy(id)),
+                *offset,
+            ));
+        }
+    }
+}
+
+/// 按字节偏移排序警告，并计算每条警告所在的行号
+fn locate_warnings(src: &str, warnings: &mut [ParseWarning]) {
+    warnings.sort_by_key(|warning| warning.byte_offset);
+    let mut line = 1;
+    let mut pos = 0;
+    for warning in warnings {
+        let end = warning.byte_offset.min(src.len());
+        line += src.as_bytes()[pos..end]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        pos = end;
+        warning.line = line;
+    }
+}
+
+/// 解码文本事件的内容，CDATA 段中的内容与普通文本一样处理
+fn decode_text<'a>(event: &Event<'a>) -> std::result::Result<Cow<'a, str>, EncodingError> {
+    match event {
+        Event::CData(e) => e.decode(),
+        Event::Text(e) => e.decode(),
+        _ => Ok(Cow::Borrowed("")),
+    }
+}
+
+/// 读取元素上的 `xml:lang` 属性，没有时返回空字符串
+fn read_lang(e: &BytesStart) -> String {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == b"xml:lang")
+        .and_then(|a| String::from_utf8(a.value.into_owned()).ok())
+        .unwrap_or_default()
+}
+
+/// 按语言去重地追加一条翻译，语言代码不区分大小写
+///
+/// 已经存在相同语言的翻译时，`replace` 为真则替换原有的文本，否则保留原有的翻译
+fn add_translation<'a>(
+    translations: &mut Vec<LyricTranslation<'a>>,
+    translation: LyricTranslation<'a>,
+    replace: bool,
+) {
+    match translations
+        .iter_mut()
+        .find(|t| t.lang.eq_ignore_ascii_case(&translation.lang))
+    {
+        Some(existing) if replace => existing.text = translation.text,
+        Some(_) => {}
+        None => translations.push(translation),
+    }
+}
+
+/// 将文本追加到缓冲区中，缓冲区为空时直接借用输入而不分配
+fn push_text<'a>(buf: &mut Cow<'a, str>, txt: Cow<'a, str>) {
+    if buf.is_empty() {
+        *buf = txt;
+    } else {
+        buf.to_mut().push_str(&txt);
+    }
+}
+
+/// 从任意 [`BufRead`] 中解析 TTML 歌词
+///
+/// 由于数据源无法被借用，解析结果中的所有文本都会被复制一份，
+/// 如果已经持有完整的字符串，请使用 [`parse_ttml_str`] 以避免额外的分配
+pub fn parse_ttml<'a>(mut data: impl BufRead) -> std::result::Result<TTMLLyric<'a>, TTMLError> {
+    let mut src = String::new();
+    data.read_to_string(&mut src)
+        .map_err(|err| TTMLError::XmlError(0, quick_xml::Error::Io(err.into())))?;
+    parse_ttml_str(&src).map(TTMLLyric::into_static)
+}
+
+/// 从字符串中解析 TTML 歌词，解析结果中的文本会尽可能借用输入
+///
+/// 只要解析结果还存活，整个输入字符串就无法被释放，
+/// 需要长期保存解析结果时可以调用 [`TTMLLyric::into_static`] 复制一份
+pub fn parse_ttml_str<'a>(src: &'a str) -> std::result::Result<TTMLLyric<'a>, TTMLError> {
+    let mut state = ParserState::default();
+    feed_ttml(&mut state, src, 0, true)?;
+    Ok(state.finish(src))
+}
+
+/// 并行解析 TTML 歌词，适用于体积很大的歌词文件
+///
+/// 先按顺序解析头部信息，再将 `<div>` 下的每个 `<p>` 段落分发到 rayon 线程池中分别解析，
+/// 最后按原顺序合并。解析结果与 [`parse_ttml_str`] 一致，但出错时返回的不一定是文档中的第一个错误
+#[cfg(feature = "rayon")]
+pub fn parse_ttml_str_parallel<'a>(src: &'a str) -> std::result::Result<TTMLLyric<'a>, TTMLError> {
+    use rayon::prelude::*;
+
+    let paragraphs = find_paragraphs(src)?;
+    let Some(first) = paragraphs.first() else {
+        return parse_ttml_str(src);
+    };
+
+    let mut state = ParserState::default();
+    feed_ttml(&mut state, &src[..first.start], 0, true)?;
+
+    let agents = &state.agents;
+    let chunks = paragraphs
+        .par_iter()
+        .map(|range| {
+            let mut chunk = ParserState {
+                status: CurrentStatus::InDiv,
+                agents: agents.clone(),
+                ..Default::default()
+            };
+            feed_ttml(&mut chunk, &src[range.clone()], range.start, true)?;
+            Ok(chunk)
+        })
+        .collect::<std::result::Result<Vec<_>, TTMLError>>()?;
+
+    // 段落之间只剩下 div 的开闭等内容，按顺序解析即可
+    let mut gap_start = first.start;
+    for (range, chunk) in paragraphs.iter().zip(chunks) {
+        feed_ttml(&mut state, &src[gap_start..range.start], gap_start, false)?;
+        if state.status != CurrentStatus::InDiv {
+            return Err(TTMLError::UnexpectedPElement(range.start));
+        }
+        state.merge_paragraph(chunk);
+        gap_start = range.end;
+    }
+    feed_ttml(&mut state, &src[gap_start..], gap_start, false)?;
+    Ok(state.finish(src))
+}
+
+/// 找出所有直接位于 `<div>` 下的 `<p>` 段落在文档中的字节范围
+#[cfg(feature = "rayon")]
+fn find_paragraphs(src: &str) -> std::result::Result<Vec<std::ops::Range<usize>>, TTMLError> {
+    let mut reader = Reader::from_str(src);
+    // 记录每一层尚未闭合的元素是否为 div
+    let mut is_div_stack = Vec::new();
+    let mut paragraphs = Vec::new();
+    loop {
+        let read_len = reader.buffer_position() as usize;
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => {
+                if e.name().as_ref() == b"p" && is_div_stack.last() == Some(&true) {
+                    reader
+                        .read_to_end(e.name())
+                        .map_err(|err| TTMLError::XmlError(read_len, err))?;
+                    paragraphs.push(read_len..reader.buffer_position() as usize);
+                } else {
+                    is_div_stack.push(e.name().as_ref() == b"div");
+                }
+            }
+            Ok(Event::End(_)) => {
+                is_div_stack.pop();
+            }
+            Err(err) => return Err(TTMLError::XmlError(read_len, err)),
+            _ => {}
+        }
+    }
+    Ok(paragraphs)
+}
+
+/// 文档中出现过的演唱者 ID，每个 ID 只分配一次
+///
+/// 并行解析时各段落各自复制一份头部中声明的 ID，复制时只增加引用计数
+#[derive(Default, Clone)]
+struct Agents {
+    ids: Vec<Arc<[u8]>>,
+    // 第一个类型为 person 的演唱者在 ids 中的下标
+    main: Option<usize>,
+}
+
+impl Agents {
+    /// 返回 ID 在表中的下标，第一次出现时才复制一份
+    fn intern(&mut self, id: &[u8]) -> usize {
+        match self.ids.iter().position(|known| **known == *id) {
+            Some(index) => index,
+            None => {
+                self.ids.push(Arc::from(id));
+                self.ids.len() - 1
+            }
+        }
+    }
+
+    fn is_main(&self, index: usize) -> bool {
+        self.main == Some(index)
+    }
+}
+
+/// 解析过程中的状态，可以分多段连续地喂入同一份文档
+#[derive(Default)]
+struct ParserState<'a> {
+    status: CurrentStatus,
+    str_buf: Cow<'a, str>,
+    result: TTMLLyric<'a>,
+    agents: Agents,
+    // 用于存储 Apple Music 格式的翻译，同一行可能有多种语言的翻译
+    itunes_translations: HashMap<String, Vec<LyricTranslation<'a>>>,
+    // 当前 <translation> 元素的语言
+    current_itunes_lang: String,
+    // 当前内嵌翻译 <span> 的语言
+    current_translation_lang: String,
     // 用于存储行级音译（拼接后的整行）
-    let mut itunes_transliterations: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
-    // 用于存储逐词音译片段（按 <span> 分片，字节串列表）
-    let mut itunes_transliteration_pieces: HashMap<Vec<u8>, Vec<Vec<u8>>> = HashMap::new();
+    itunes_transliterations: HashMap<String, String>,
+    // 用于存储逐词音译片段（按 <span> 分片）
+    itunes_transliteration_pieces: HashMap<String, Vec<String>>,
     // 用于存储 for="L_ID"
-    let mut current_itunes_key: Option<Vec<u8>> = None;
+    current_itunes_key: Option<String>,
     // 用于拼接 <text> 下的所有文本（行级）
-    let mut current_itunes_text_buffer = String::with_capacity(128);
+    current_itunes_text_buffer: String,
     // 用于收集 <text> 下每个 <span> 的逐词音译片段（仅用于 transliterations）
-    let mut current_itunes_trans_pieces: Vec<String> = Vec::new();
-    // 记录每一行对应的 itunes:key，以便结束后把 pieces 分配到 word
-    let mut line_key_map: Vec<(usize, Vec<u8>)> = Vec::new();
+    current_itunes_trans_pieces: Vec<String>,
+    // 记录每一行对应的 itunes:key，以便结束后把翻译和音译分配到行和词上
+    line_key_map: Vec<(usize, String)>,
+    // 记录缺少时间的行、其元素的字节偏移和已有的时间属性，解析结束后按上下文推断时间
+    untimed_lines: Vec<(usize, usize, LineTiming)>,
+    // 按出现顺序记录所有元素的 xml:id 和字节偏移，解析结束后检查是否有重复
+    element_ids: Vec<(Vec<u8>, usize)>,
+}
+
+/// 解析文档中的一段内容，`offset` 为该段在整个文档中的字节偏移，用于错误定位
+///
+/// 解析不完整的片段（例如只包含 `</div><div>` 的片段）时需要关闭 `check_end_names`，
+/// 此时也允许出现没有对应开始标签的结束标签
+fn feed_ttml<'a>(
+    state: &mut ParserState<'a>,
+    src: &'a str,
+    offset: usize,
+    check_end_names: bool,
+) -> std::result::Result<(), TTMLError> {
+    let mut reader = Reader::from_str(src);
+    let config = reader.config_mut();
+    config.check_end_names = check_end_names;
+    config.allow_unmatched_ends = !check_end_names;
+    let ParserState {
+        status,
+        str_buf,
+        result,
+        agents,
+        itunes_translations,
+        current_itunes_lang,
+        current_translation_lang,
+        itunes_transliterations,
+        itunes_transliteration_pieces,
+        current_itunes_key,
+        current_itunes_text_buffer,
+        current_itunes_trans_pieces,
+        line_key_map,
+        untimed_lines,
+        element_ids,
+    } = state;
 
     loop {
-        match reader.read_event_into(&mut buf) {
+        let read_len = offset + reader.buffer_position() as usize;
+        match reader.read_event() {
             Ok(Event::Eof) => break,
             Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                if let Some(id) = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.as_ref() == b"xml:id")
+                {
+                    element_ids.push((id.value.into_owned(), read_len));
+                }
                 let attr_name = e.name();
                 // println!(
                 //     "start {} {:?}",
@@ -186,14 +672,14 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                 match attr_name.as_ref() {
                     b"iTunesMetadata" => {
                         if let CurrentStatus::InMetadata = status {
-                            status = CurrentStatus::InITunesMetadata;
+                            *status = CurrentStatus::InITunesMetadata;
                         }
                     }
                     b"translations" => match status {
                         CurrentStatus::InITunesMetadata
                         | CurrentStatus::InITunesTransliterations
                         | CurrentStatus::InITunesTranslation => {
-                            status = CurrentStatus::InITunesTranslations;
+                            *status = CurrentStatus::InITunesTranslations;
                         }
                         _ => {}
                     },
@@ -201,57 +687,66 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                         CurrentStatus::InITunesMetadata
                         | CurrentStatus::InITunesTranslations
                         | CurrentStatus::InITunesTranslation => {
-                            status = CurrentStatus::InITunesTransliterations;
+                            *status = CurrentStatus::InITunesTransliterations;
                         }
                         _ => {}
                     },
                     b"translation" => {
                         if let CurrentStatus::InITunesMetadata = status {
-                            status = CurrentStatus::InITunesTranslation;
+                            *status = CurrentStatus::InITunesTranslation;
+                            *current_itunes_lang = read_lang(&e);
                         } else if let CurrentStatus::InITunesTranslations = status {
                             // 等待 <text>
+                            *current_itunes_lang = read_lang(&e);
                         }
                     }
                     b"text" => {
                         if let CurrentStatus::InITunesTranslation = status {
-                            let mut key: Option<Vec<u8>> = None;
+                            let mut key: Option<String> = None;
                             for attr in e.attributes() {
                                 match attr {
                                     Ok(a) if a.key.as_ref() == b"for" => {
-                                        key = Some(a.value.into_owned());
+                                        key = String::from_utf8(a.value.into_owned()).ok();
                                     }
                                     _ => {}
                                 }
                             }
                             if let Some(k) = key
-                                && let Ok(Event::Text(text_event)) =
-                                    reader.read_event_into(&mut Vec::new())
-                                && let Ok(unescaped_text) = text_event.decode()
+                                && let Ok(event @ (Event::Text(_) | Event::CData(_))) =
+                                    reader.read_event()
+                                && let Ok(unescaped_text) = decode_text(&event)
                             {
-                                itunes_translations
-                                    .insert(k, unescaped_text.into_owned().into_bytes());
+                                add_translation(
+                                    itunes_translations.entry(k).or_default(),
+                                    LyricTranslation {
+                                        lang: current_itunes_lang.clone().into(),
+                                        text: unescaped_text,
+                                    },
+                                    false,
+                                );
                             }
                         } else if matches!(
                             status,
                             CurrentStatus::InITunesTranslations
                                 | CurrentStatus::InITunesTransliterations
                         ) {
-                            current_itunes_key = None;
+                            *current_itunes_key = None;
                             for attr in e.attributes() {
                                 match attr {
                                     Ok(a) if a.key.as_ref() == b"for" => {
-                                        current_itunes_key = Some(a.value.into_owned());
+                                        *current_itunes_key =
+                                            String::from_utf8(a.value.into_owned()).ok();
                                         break;
                                     }
                                     _ => {}
                                 }
                             }
                             if current_itunes_key.is_some() {
-                                if status == CurrentStatus::InITunesTranslations {
-                                    status = CurrentStatus::InITunesTranslationText;
+                                if *status == CurrentStatus::InITunesTranslations {
+                                    *status = CurrentStatus::InITunesTranslationText;
                                     current_itunes_text_buffer.clear();
                                 } else {
-                                    status = CurrentStatus::InITunesTransliterationText;
+                                    *status = CurrentStatus::InITunesTransliterationText;
                                     current_itunes_text_buffer.clear();
                                     current_itunes_trans_pieces.clear();
                                 }
@@ -260,56 +755,55 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                     }
                     b"tt" => {
                         if let CurrentStatus::None = status {
-                            status = CurrentStatus::InTtml;
+                            *status = CurrentStatus::InTtml;
                         } else {
                             return Err(TTMLError::UnexpectedTTElement(read_len));
                         }
                     }
                     b"head" => {
                         if let CurrentStatus::InTtml = status {
-                            status = CurrentStatus::InHead;
+                            *status = CurrentStatus::InHead;
                         } else {
                             return Err(TTMLError::UnexpectedHeadElement(read_len));
                         }
                     }
                     b"metadata" => {
                         if let CurrentStatus::InHead = status {
-                            status = CurrentStatus::InMetadata;
+                            *status = CurrentStatus::InMetadata;
                         } else {
                             return Err(TTMLError::UnexpectedMetadataElement(read_len));
                         }
                     }
                     b"ttm:agent" => {
-                        if main_agent.is_empty() {
-                            if let CurrentStatus::InMetadata = status {
-                                let mut agent_type = Cow::Borrowed(&[] as &[u8]);
-                                let mut agent_id = Cow::Borrowed(&[] as &[u8]);
-                                for attr in e.attributes() {
-                                    match attr {
-                                        Ok(a) => match a.key.as_ref() {
-                                            b"type" => {
-                                                agent_type = a.value.clone();
-                                            }
-                                            b"xml:id" => {
-                                                agent_id = a.value.clone();
-                                            }
-                                            _ => {}
-                                        },
-                                        Err(err) => {
-                                            return Err(TTMLError::XmlAttrError(read_len, err));
+                        if let CurrentStatus::InMetadata = status {
+                            let mut agent_type = Cow::Borrowed(&[] as &[u8]);
+                            let mut agent_id = Cow::Borrowed(&[] as &[u8]);
+                            for attr in e.attributes() {
+                                match attr {
+                                    Ok(a) => match a.key.as_ref() {
+                                        b"type" => {
+                                            agent_type = a.value.clone();
+                                        }
+                                        b"xml:id" => {
+                                            agent_id = a.value.clone();
                                         }
+                                        _ => {}
+                                    },
+                                    Err(err) => {
+                                        return Err(TTMLError::XmlAttrError(read_len, err));
                                     }
                                 }
-                                if agent_type == &b"person"[..] {
-                                    main_agent = agent_id.into_owned();
-                                    // println!(
-                                    //     "main agent: {}",
-                                    //     std::str::from_utf8(&main_agent).unwrap()
-                                    // );
-                                }
-                            } else {
-                                return Err(TTMLError::UnexpectedTtmlAgentElement(read_len));
                             }
+                            let agent = agents.intern(&agent_id);
+                            if agent_type == &b"person"[..] && agents.main.is_none() {
+                                agents.main = Some(agent);
+                                // println!(
+                                //     "main agent: {}",
+                                //     std::str::from_utf8(&main_agent).unwrap()
+                                // );
+                            }
+                        } else {
+                            return Err(TTMLError::UnexpectedTtmlAgentElement(read_len));
                         }
                     }
                     b"amll:meta" => {
@@ -352,69 +846,62 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                     }
                     b"body" => {
                         if let CurrentStatus::InTtml = status {
-                            status = CurrentStatus::InBody;
+                            *status = CurrentStatus::InBody;
                         } else {
                             return Err(TTMLError::UnexpectedBodyElement(read_len));
                         }
                     }
                     b"div" => {
                         if let CurrentStatus::InBody = status {
-                            status = CurrentStatus::InDiv;
+                            *status = CurrentStatus::InDiv;
                         } else {
                             return Err(TTMLError::UnexpectedDivElement(read_len));
                         }
                     }
                     b"p" => {
                         if let CurrentStatus::InDiv = status {
-                            status = CurrentStatus::InP;
+                            *status = CurrentStatus::InP;
                             let mut new_line = LyricLine::default();
 
-                            // 在配置行信息时，检查是否有 itunes:key 并查找翻译
-                            let mut itunes_key: Option<Vec<u8>> = None;
-                            for a in e.attributes().flatten() {
-                                if a.key.as_ref() == b"itunes:key" {
-                                    itunes_key = Some(a.value.into_owned());
-                                    break; // 找到 key 就退出
-                                }
+                            let timing = configure_lyric_line(
+                                &e,
+                                read_len,
+                                agents,
+                                &mut new_line,
+                                &mut result.warnings,
+                            )?;
+                            if timing != LineTiming::Complete {
+                                untimed_lines.push((result.lines.len(), read_len, timing));
                             }
 
-                            configure_lyric_line(&e, read_len, &main_agent, &mut new_line)?;
+                            // 在配置行信息时，检查是否有 itunes:key 并查找翻译
+                            let itunes_key = e
+                                .attributes()
+                                .flatten()
+                                .find(|a| a.key.as_ref() == b"itunes:key");
 
-                            if let Some(key) = &itunes_key {
-                                if let Some(translation_text) = itunes_translations.get(key)
-                                    && let Ok(s) = std::str::from_utf8(translation_text)
-                                {
-                                    new_line.translated_lyric = Cow::Owned(s.to_string());
-                                }
-                                if let Some(transliteration_text) = itunes_transliterations.get(key)
-                                    && let Ok(s) = std::str::from_utf8(transliteration_text)
-                                {
-                                    new_line.roman_lyric = Cow::Owned(s.to_string());
-                                }
+                            if let Some(key) = &itunes_key
+                                && let Ok(key) = std::str::from_utf8(&key.value)
+                            {
+                                // 记录行与 key 的映射，待解析结束后再分配翻译和音译
+                                line_key_map.push((result.lines.len(), key.to_owned()));
                             }
 
-                            // 先推入行，获取索引
                             result.lines.push(new_line);
-                            let line_idx = result.lines.len() - 1;
-
-                            // 记录行与 key 的映射，供逐词音译后处理
-                            if let Some(key) = &itunes_key {
-                                line_key_map.push((line_idx, key.clone()));
-                            }
                         } else {
                             return Err(TTMLError::UnexpectedPElement(read_len));
                         }
                     }
                     b"span" => match status {
                         CurrentStatus::InP => {
-                            status = CurrentStatus::InSpan;
+                            *status = CurrentStatus::InSpan;
                             for attr in e.attributes() {
                                 match attr {
                                     Ok(a) => {
                                         if a.key.as_ref() == b"ttm:role" {
                                             match a.value.as_ref() {
                                                 b"x-bg" => {
-                                                    status = CurrentStatus::InBackgroundSpan;
+                                                    *status = CurrentStatus::InBackgroundSpan;
                                                     let mut new_bg_line = LyricLine {
                                                         is_bg: true,
                                                         is_duet: result
@@ -424,21 +911,30 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                                                             .is_duet,
                                                         ..Default::default()
                                                     };
-                                                    configure_lyric_line(
+                                                    let timing = configure_lyric_line(
                                                         &e,
                                                         read_len,
-                                                        &main_agent,
+                                                        agents,
                                                         &mut new_bg_line,
+                                                        &mut result.warnings,
                                                     )?;
+                                                    if timing != LineTiming::Complete {
+                                                        untimed_lines.push((
+                                                            result.lines.len(),
+                                                            read_len,
+                                                            timing,
+                                                        ));
+                                                    }
                                                     result.lines.push(new_bg_line);
                                                     break;
                                                 }
                                                 b"x-translation" => {
-                                                    status = CurrentStatus::InTranslationSpan;
+                                                    *status = CurrentStatus::InTranslationSpan;
+                                                    *current_translation_lang = read_lang(&e);
                                                     break;
                                                 }
                                                 b"x-roman" => {
-                                                    status = CurrentStatus::InRomanSpan;
+                                                    *status = CurrentStatus::InRomanSpan;
                                                     break;
                                                 }
                                                 _ => {}
@@ -450,23 +946,33 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                             }
                             if let CurrentStatus::InSpan = status {
                                 let mut new_word = LyricWord::default();
-                                configure_lyric_word(&e, read_len, &mut new_word)?;
+                                let line_is_timed = untimed_lines
+                                    .last()
+                                    .is_none_or(|&(idx, _, _)| idx + 1 != result.lines.len());
+                                configure_lyric_word(
+                                    &e,
+                                    read_len,
+                                    &mut new_word,
+                                    line_is_timed,
+                                    &mut result.warnings,
+                                )?;
                                 result.lines.last_mut().unwrap().words.push(new_word);
                             }
                         }
                         CurrentStatus::InBackgroundSpan => {
-                            status = CurrentStatus::InSpanInBackgroundSpan;
+                            *status = CurrentStatus::InSpanInBackgroundSpan;
                             for attr in e.attributes() {
                                 match attr {
                                     Ok(a) => {
                                         if a.key.as_ref() == b"ttm:role" {
                                             match a.value.as_ref() {
                                                 b"x-translation" => {
-                                                    status = CurrentStatus::InTranslationSpanInBackgroundSpan;
+                                                    *status = CurrentStatus::InTranslationSpanInBackgroundSpan;
+                                                    *current_translation_lang = read_lang(&e);
                                                     break;
                                                 }
                                                 b"x-roman" => {
-                                                    status =
+                                                    *status =
                                                         CurrentStatus::InRomanSpanInBackgroundSpan;
                                                     break;
                                                 }
@@ -479,7 +985,16 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                             }
                             if let CurrentStatus::InSpanInBackgroundSpan = status {
                                 let mut new_word = LyricWord::default();
-                                configure_lyric_word(&e, read_len, &mut new_word)?;
+                                let line_is_timed = untimed_lines
+                                    .last()
+                                    .is_none_or(|&(idx, _, _)| idx + 1 != result.lines.len());
+                                configure_lyric_word(
+                                    &e,
+                                    read_len,
+                                    &mut new_word,
+                                    line_is_timed,
+                                    &mut result.warnings,
+                                )?;
                                 result.lines.last_mut().unwrap().words.push(new_word);
                             }
                         }
@@ -513,91 +1028,96 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                         | CurrentStatus::InITunesTranslation
                         | CurrentStatus::InITunesTranslationText
                         | CurrentStatus::InITunesTransliterationText => {
-                            status = CurrentStatus::InMetadata;
+                            *status = CurrentStatus::InMetadata;
                         }
                         _ => {}
                     },
                     b"text" => {
                         if let Some(key) = current_itunes_key.take() {
-                            if status == CurrentStatus::InITunesTranslationText {
-                                itunes_translations
-                                    .insert(key, current_itunes_text_buffer.clone().into_bytes());
-                                status = CurrentStatus::InITunesTranslations;
-                            } else if status == CurrentStatus::InITunesTransliterationText {
-                                let key_clone = key.clone();
-                                itunes_transliterations
-                                    .insert(key, current_itunes_text_buffer.clone().into_bytes());
-                                // 保存逐词片段（转为字节）
-                                let pieces_bytes: Vec<Vec<u8>> = current_itunes_trans_pieces
-                                    .iter()
-                                    .map(|s| s.as_bytes().to_vec())
-                                    .collect();
-                                itunes_transliteration_pieces.insert(key_clone, pieces_bytes);
-                                current_itunes_trans_pieces.clear();
-                                status = CurrentStatus::InITunesTransliterations;
+                            if *status == CurrentStatus::InITunesTranslationText {
+                                add_translation(
+                                    itunes_translations.entry(key).or_default(),
+                                    LyricTranslation {
+                                        lang: current_itunes_lang.clone().into(),
+                                        text: Cow::Owned(std::mem::take(
+                                            current_itunes_text_buffer,
+                                        )),
+                                    },
+                                    false,
+                                );
+                                *status = CurrentStatus::InITunesTranslations;
+                            } else if *status == CurrentStatus::InITunesTransliterationText {
+                                itunes_transliterations.insert(
+                                    key.clone(),
+                                    std::mem::take(current_itunes_text_buffer),
+                                );
+                                // 保存逐词片段
+                                itunes_transliteration_pieces
+                                    .insert(key, std::mem::take(current_itunes_trans_pieces));
+                                *status = CurrentStatus::InITunesTransliterations;
                             }
                         }
                     }
                     b"translation" => {
                         if let CurrentStatus::InITunesTranslation = status {
-                            status = CurrentStatus::InITunesMetadata;
+                            *status = CurrentStatus::InITunesMetadata;
                         }
                     }
                     b"translations" => {
                         if let CurrentStatus::InITunesTranslations = status {
-                            status = CurrentStatus::InITunesMetadata;
+                            *status = CurrentStatus::InITunesMetadata;
                         }
                     }
                     b"transliterations" => {
                         if let CurrentStatus::InITunesTransliterations = status {
-                            status = CurrentStatus::InITunesMetadata;
+                            *status = CurrentStatus::InITunesMetadata;
                         }
                     }
                     b"tt" => {
                         if let CurrentStatus::InTtml = status {
-                            status = CurrentStatus::None;
+                            *status = CurrentStatus::None;
                         } else {
                             return Err(TTMLError::UnexpectedTTElement(read_len));
                         }
                     }
                     b"head" => {
                         if let CurrentStatus::InHead = status {
-                            status = CurrentStatus::InTtml;
+                            *status = CurrentStatus::InTtml;
                         } else {
                             return Err(TTMLError::UnexpectedHeadElement(read_len));
                         }
                     }
                     b"metadata" => {
                         if let CurrentStatus::InMetadata = status {
-                            status = CurrentStatus::InHead;
+                            *status = CurrentStatus::InHead;
                         } else {
                             return Err(TTMLError::UnexpectedMetadataElement(read_len));
                         }
                     }
                     b"body" => {
                         if let CurrentStatus::InBody = status {
-                            status = CurrentStatus::InTtml;
+                            *status = CurrentStatus::InTtml;
                         } else {
                             return Err(TTMLError::UnexpectedBodyElement(read_len));
                         }
                     }
                     b"div" => {
                         if let CurrentStatus::InDiv = status {
-                            status = CurrentStatus::InBody;
+                            *status = CurrentStatus::InBody;
                         } else {
                             return Err(TTMLError::UnexpectedDivElement(read_len));
                         }
                     }
                     b"p" => {
                         if let CurrentStatus::InP = status {
-                            status = CurrentStatus::InDiv;
+                            *status = CurrentStatus::InDiv;
                         } else {
                             return Err(TTMLError::UnexpectedPElement(read_len));
                         }
                     }
                     b"span" => match status {
                         CurrentStatus::InSpan => {
-                            status = CurrentStatus::InP;
+                            *status = CurrentStatus::InP;
                             result
                                 .lines
                                 .last_mut()
@@ -605,16 +1125,14 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                                 .words
                                 .last_mut()
                                 .unwrap()
-                                .word = str_buf.clone().into();
-                            str_buf.clear();
+                                .word = std::mem::take(str_buf);
                         }
                         CurrentStatus::InBackgroundSpan => {
-                            status = CurrentStatus::InP;
-                            str_buf.clear();
+                            *status = CurrentStatus::InP;
+                            *str_buf = Cow::Borrowed("");
                         }
                         CurrentStatus::InSpanInBackgroundSpan => {
-                            status = CurrentStatus::InBackgroundSpan;
-                            // TODO: 尽可能借用而不克隆
+                            *status = CurrentStatus::InBackgroundSpan;
                             result
                                 .lines
                                 .iter_mut()
@@ -624,56 +1142,53 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                                 .words
                                 .last_mut()
                                 .unwrap()
-                                .word = str_buf.clone().into();
-                            str_buf.clear();
+                                .word = std::mem::take(str_buf);
                         }
                         CurrentStatus::InTranslationSpan => {
-                            status = CurrentStatus::InP;
-                            // TODO: 尽可能借用而不克隆
-                            // 只有在没有 Apple Music 样式翻译时才使用内嵌翻译
+                            *status = CurrentStatus::InP;
                             let current_line =
                                 result.lines.iter_mut().rev().find(|x| !x.is_bg).unwrap();
-
-                            if current_line.translated_lyric.is_empty() {
-                                current_line.translated_lyric = str_buf.clone().into();
-                            }
-                            str_buf.clear();
+                            add_translation(
+                                &mut current_line.translations,
+                                LyricTranslation {
+                                    lang: std::mem::take(current_translation_lang).into(),
+                                    text: std::mem::take(str_buf),
+                                },
+                                false,
+                            );
                         }
                         CurrentStatus::InRomanSpan => {
-                            status = CurrentStatus::InP;
-                            // TODO: 尽可能借用而不克隆
+                            *status = CurrentStatus::InP;
                             result
                                 .lines
                                 .iter_mut()
                                 .rev()
                                 .find(|x| !x.is_bg)
                                 .unwrap()
-                                .roman_lyric = str_buf.clone().into();
-                            str_buf.clear();
+                                .roman_lyric = std::mem::take(str_buf);
                         }
                         CurrentStatus::InTranslationSpanInBackgroundSpan => {
-                            status = CurrentStatus::InBackgroundSpan;
-                            // TODO: 尽可能借用而不克隆
-                            result
-                                .lines
-                                .iter_mut()
-                                .rev()
-                                .find(|x| x.is_bg)
-                                .unwrap()
-                                .translated_lyric = str_buf.clone().into();
-                            str_buf.clear();
+                            *status = CurrentStatus::InBackgroundSpan;
+                            let current_line =
+                                result.lines.iter_mut().rev().find(|x| x.is_bg).unwrap();
+                            add_translation(
+                                &mut current_line.translations,
+                                LyricTranslation {
+                                    lang: std::mem::take(current_translation_lang).into(),
+                                    text: std::mem::take(str_buf),
+                                },
+                                false,
+                            );
                         }
                         CurrentStatus::InRomanSpanInBackgroundSpan => {
-                            status = CurrentStatus::InBackgroundSpan;
-                            // TODO: 尽可能借用而不克隆
+                            *status = CurrentStatus::InBackgroundSpan;
                             result
                                 .lines
                                 .iter_mut()
                                 .rev()
                                 .find(|x| x.is_bg)
                                 .unwrap()
-                                .roman_lyric = str_buf.clone().into();
-                            str_buf.clear();
+                                .roman_lyric = std::mem::take(str_buf);
                         }
                         CurrentStatus::InITunesTranslationText
                         | CurrentStatus::InITunesTransliterationText => {}
@@ -695,8 +1210,14 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                         "gt" => '>',
                         "quot" => '"',
                         "apos" => '\'',
-                        // 应该在此处记录一个警告
-                        _ => '\0',
+                        _ => {
+                            result.warnings.push(new_warning(
+                                ParseWarningCode::UnknownEntity,
+                                format!("无法识别的实体引用 &{entity_name};"),
+                                read_len,
+                            ));
+                            '\0'
+                        }
                     };
 
                     if decoded_char != '\0' {
@@ -708,7 +1229,7 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                             | CurrentStatus::InSpanInBackgroundSpan
                             | CurrentStatus::InTranslationSpanInBackgroundSpan
                             | CurrentStatus::InRomanSpanInBackgroundSpan => {
-                                str_buf.push(decoded_char);
+                                str_buf.to_mut().push(decoded_char);
                             }
                             CurrentStatus::InITunesTranslationText => {
                                 current_itunes_text_buffer.push(decoded_char);
@@ -724,7 +1245,7 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                     }
                 }
             }
-            Ok(Event::Text(e)) => match e.decode() {
+            Ok(event @ (Event::Text(_) | Event::CData(_))) => match decode_text(&event) {
                 Ok(txt) => {
                     // println!("  text: {:?}", txt);
                     match status {
@@ -737,7 +1258,7 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                                 .unwrap()
                                 .words
                                 .push(LyricWord {
-                                    word: txt.into_owned().into(),
+                                    word: txt,
                                     ..Default::default()
                                 });
                         }
@@ -750,7 +1271,7 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                                 .unwrap()
                                 .words
                                 .push(LyricWord {
-                                    word: txt.into_owned().into(),
+                                    word: txt,
                                     ..Default::default()
                                 });
                         }
@@ -760,7 +1281,7 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
                         | CurrentStatus::InSpanInBackgroundSpan
                         | CurrentStatus::InTranslationSpanInBackgroundSpan
                         | CurrentStatus::InRomanSpanInBackgroundSpan => {
-                            str_buf.push_str(&txt);
+                            push_text(str_buf, txt);
                         }
                         CurrentStatus::InITunesTranslationText => {
                             current_itunes_text_buffer.push_str(&txt);
@@ -789,93 +1310,135 @@ pub fn parse_ttml<'a>(data: impl BufRead) -> std::result::Result<TTMLLyric<'a>,
             Err(err) => return Err(TTMLError::XmlError(read_len, err)),
             _ => (),
         }
-        read_len += buf.len();
-        buf.clear();
     }
-    for line in result.lines.iter_mut() {
-        if line.is_bg {
-            if let Some(first_word) = line.words.first_mut() {
-                match &mut first_word.word {
-                    Cow::Borrowed(word) => {
-                        *word = word.strip_suffix('(').unwrap_or(word);
-                    }
-                    Cow::Owned(word) => {
-                        if let Some(new_word) = word.strip_prefix('(') {
-                            *word = new_word.to_owned()
+    Ok(())
+}
+
+impl<'a> ParserState<'a> {
+    /// 将单独解析的段落合并到当前状态中
+    #[cfg(feature = "rayon")]
+    fn merge_paragraph(&mut self, chunk: ParserState<'a>) {
+        let base = self.result.lines.len();
+        self.line_key_map.extend(
+            chunk
+                .line_key_map
+                .into_iter()
+                .map(|(idx, key)| (idx + base, key)),
+        );
+        self.untimed_lines.extend(
+            chunk
+                .untimed_lines
+                .into_iter()
+                .map(|(idx, offset, timing)| (idx + base, offset, timing)),
+        );
+        self.element_ids.extend(chunk.element_ids);
+        self.result.lines.extend(chunk.result.lines);
+        self.result.warnings.extend(chunk.result.warnings);
+    }
+
+    fn finish(self, src: &str) -> TTMLLyric<'a> {
+        let ParserState {
+            mut result,
+            itunes_translations,
+            itunes_transliterations,
+            itunes_transliteration_pieces,
+            line_key_map,
+            untimed_lines,
+            element_ids,
+            ..
+        } = self;
+
+        estimate_line_times(&mut result.lines, &untimed_lines, &mut result.warnings);
+        report_duplicate_ids(&element_ids, &mut result.warnings);
+        locate_warnings(src, &mut result.warnings);
+
+        for line in result.lines.iter_mut() {
+            if line.is_bg {
+                if let Some(first_word) = line.words.first_mut() {
+                    match &mut first_word.word {
+                        Cow::Borrowed(word) => {
+                            *word = word.strip_prefix('(').unwrap_or(word);
+                        }
+                        Cow::Owned(word) => {
+                            if let Some(new_word) = word.strip_prefix('(') {
+                                *word = new_word.to_owned()
+                            }
                         }
                     }
                 }
-            }
-            if let Some(last_word) = line.words.last_mut() {
-                match &mut last_word.word {
-                    Cow::Borrowed(word) => {
-                        *word = word.strip_suffix(')').unwrap_or(word);
-                    }
-                    Cow::Owned(word) => {
-                        if let Some(new_word) = word.strip_suffix(')') {
-                            *word = new_word.to_owned()
+                if let Some(last_word) = line.words.last_mut() {
+                    match &mut last_word.word {
+                        Cow::Borrowed(word) => {
+                            *word = word.strip_suffix(')').unwrap_or(word);
+                        }
+                        Cow::Owned(word) => {
+                            if let Some(new_word) = word.strip_suffix(')') {
+                                *word = new_word.to_owned()
+                            }
                         }
                     }
                 }
             }
         }
-    }
-    // 结束后：将 iTunes 逐词音译片段映射到对应行的每个词
-    for (idx, key) in line_key_map.into_iter() {
-        if let Some(pieces) = itunes_transliteration_pieces.get(&key) {
+        // 结束后：将 iTunes 翻译和音译分配到对应行，逐词音译片段映射到对应行的每个词
+        for (idx, key) in line_key_map.into_iter() {
             let line = result.lines.get_mut(idx).unwrap();
-            // 仅对前景行进行分配
-            if !line.is_bg {
-                // 过滤出有效词索引
-                let mut word_indices: Vec<usize> = Vec::new();
-                for (wi, w) in line.words.iter().enumerate() {
-                    if !w.is_empty() {
-                        word_indices.push(wi);
-                    }
+            // Apple Music 样式翻译优先于内嵌翻译，而内嵌音译优先于 Apple Music 样式音译
+            if let Some(itunes_line_translations) = itunes_translations.get(&key) {
+                let inline_translations = std::mem::take(&mut line.translations);
+                line.translations = itunes_line_translations.clone();
+                for translation in inline_translations {
+                    add_translation(&mut line.translations, translation, false);
                 }
-
-                use std::borrow::Cow as ByteCow;
-                let mut pieces_norm: Vec<ByteCow<[u8]>> = pieces
-                    .iter()
-                    .map(|p| ByteCow::Borrowed(p.as_slice()))
-                    .collect();
-                // 对齐片段数量和词数
-                if !word_indices.is_empty() && !pieces_norm.is_empty() {
-                    if pieces_norm.len() > word_indices.len() {
-                        // 多余的片段合并到最后一个片段
-                        let last_keep = if word_indices.is_empty() {
-                            0
-                        } else {
-                            word_indices.len() - 1
-                        };
-                        let mut merged_tail: Vec<u8> = Vec::new();
-                        for part in pieces.iter().skip(last_keep) {
-                            merged_tail.extend_from_slice(part);
+            }
+            if line.roman_lyric.is_empty()
+                && let Some(transliteration_text) = itunes_transliterations.get(&key)
+            {
+                line.roman_lyric = Cow::Owned(transliteration_text.clone());
+            }
+            if let Some(pieces) = itunes_transliteration_pieces.get(&key) {
+                // 仅对前景行进行分配
+                if !line.is_bg {
+                    // 过滤出有效词索引
+                    let mut word_indices: Vec<usize> = Vec::new();
+                    for (wi, w) in line.words.iter().enumerate() {
+                        if !w.is_empty() {
+                            word_indices.push(wi);
                         }
-                        pieces_norm.truncate(last_keep);
-                        pieces_norm.push(ByteCow::Owned(merged_tail));
                     }
 
-                    for (i, wi) in word_indices.iter().enumerate() {
-                        if i < pieces_norm.len() {
-                            let piece = &pieces_norm[i];
-                            let trimmed = String::from_utf8_lossy(piece.as_ref())
-                                .trim_end()
-                                .to_string();
-                            line.words[*wi].roman_word = trimmed.into();
+                    // 对齐片段数量和词数，多余的片段合并到最后一个词上
+                    if !word_indices.is_empty() && !pieces.is_empty() {
+                        let last_keep = word_indices.len() - 1;
+                        for (i, wi) in word_indices.iter().enumerate() {
+                            let piece = if i == last_keep && pieces.len() > word_indices.len() {
+                                Cow::Owned(pieces[last_keep..].concat())
+                            } else if let Some(piece) = pieces.get(i) {
+                                Cow::Borrowed(piece.as_str())
+                            } else {
+                                break;
+                            };
+                            line.words[*wi].roman_word = piece.trim_end().to_owned().into();
                         }
                     }
                 }
             }
         }
+        for line in result.lines.iter_mut() {
+            if let Some(translation) = line.translations.first() {
+                line.translated_lyric = translation.text.clone();
+            }
+        }
+        result
     }
-    Ok(result)
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "parseTTML", skip_typescript)]
 pub fn parse_ttml_js(src: &str) -> JsValue {
-    serde_wasm_bindgen::to_value(&parse_ttml(src.as_bytes()).unwrap()).unwrap()
+    let mut ttml = parse_ttml_str(src).unwrap();
+    crate::attach_js_line_breaks(&mut ttml.lines);
+    serde_wasm_bindgen::to_value(&ttml).unwrap()
 }
 
 #[test]
@@ -905,10 +1468,10 @@ fn test_ttml() {
     println!("ttml: {t:?}");
 }
 
-use nom::{bytes::complete::*, combinator::*, *};
+use nom::{branch::alt, bytes::complete::*, combinator::*, sequence::preceded, *};
 use std::str::FromStr;
 
-use super::TTMLLyric;
+use super::{ParseWarning, ParseWarningCode, TTMLLyric};
 
 pub fn parse_hour(input: &[u8]) -> IResult<&[u8], u64> {
     let (input, result) = take_while_m_n(2, 3, |x: u8| x.is_dec_digit())(input)?;
@@ -936,6 +1499,43 @@ pub fn parse_fraction(input: &[u8]) -> IResult<&[u8], u64> {
     Ok((input, result))
 }
 
+// 形如 90.5s、1.5m、2h、500ms 的偏移时间，没有单位时视为秒
+pub fn parse_offset_time(input: &[u8]) -> IResult<&[u8], u64> {
+    let (input, (integer, fraction, metric, _)) = (
+        take_while1(|x: u8| x.is_dec_digit()),
+        opt(preceded(
+            tag(b".".as_slice()),
+            take_while1(|x: u8| x.is_dec_digit()),
+        )),
+        opt(alt((
+            tag(b"ms".as_slice()),
+            tag(b"h".as_slice()),
+            tag(b"m".as_slice()),
+            tag(b"s".as_slice()),
+        ))),
+        eof,
+    )
+        .parse(input)?;
+    let unit: u64 = match metric {
+        Some(b"h") => 60 * 60 * 1000,
+        Some(b"m") => 60 * 1000,
+        Some(b"ms") => 1,
+        _ => 1000,
+    };
+    let overflow = || nom::Err::Error(error::Error::new(input, error::ErrorKind::TooLarge));
+    let integer = u64::from_str(std::str::from_utf8(integer).unwrap()).map_err(|_| overflow())?;
+    let mut time = integer.checked_mul(unit).ok_or_else(overflow)?;
+    if let Some(fraction) = fraction {
+        // 只保留到毫秒精度，多余的位数直接截断
+        let fraction = &fraction[..fraction.len().min(9)];
+        let numerator = u64::from_str(std::str::from_utf8(fraction).unwrap()).unwrap();
+        time = time
+            .checked_add(numerator * unit / 10u64.pow(fraction.len() as u32))
+            .ok_or_else(overflow)?;
+    }
+    Ok((input, time))
+}
+
 // HH:MM:SS.MS
 // or MM:SS.MS
 pub fn parse_timestamp(input: &[u8]) -> IResult<&[u8], u64> {
@@ -976,26 +1576,7 @@ pub fn parse_timestamp(input: &[u8]) -> IResult<&[u8], u64> {
                     Ok((input, time))
                 }
             }
-            Err(_) => {
-                match (
-                    parse_minutes_or_seconds,
-                    opt(parse_fraction),
-                    opt(tag("s")),
-                    eof,
-                )
-                    .parse(input)
-                {
-                    Ok((input, result)) => {
-                        let time = result.0 * 1000;
-                        if let Some(frac) = result.1 {
-                            Ok((input, time + frac))
-                        } else {
-                            Ok((input, time))
-                        }
-                    }
-                    Err(err) => Err(err),
-                }
-            }
+            Err(_) => parse_offset_time(input),
         },
     }
 }
@@ -1022,6 +1603,40 @@ fn test_timestamp() {
         parse_timestamp("10.24".as_bytes()),
         Ok(("".as_bytes(), 10240))
     );
+    assert_eq!(
+        parse_timestamp("90.5s".as_bytes()),
+        Ok(("".as_bytes(), 90500))
+    );
+    assert_eq!(
+        parse_timestamp("1.5m".as_bytes()),
+        Ok(("".as_bytes(), 90000))
+    );
+    assert_eq!(
+        parse_timestamp("2h".as_bytes()),
+        Ok(("".as_bytes(), 7200000))
+    );
+    assert_eq!(
+        parse_timestamp("250ms".as_bytes()),
+        Ok(("".as_bytes(), 250))
+    );
+    assert_eq!(
+        parse_timestamp("125s".as_bytes()),
+        Ok(("".as_bytes(), 125000))
+    );
+    assert!(parse_timestamp("1.5f".as_bytes()).is_err());
+    // 整数部分刚好不溢出，加上小数部分后溢出
+    assert_eq!(
+        parse_timestamp("5124095576030h".as_bytes()),
+        Ok(("".as_bytes(), 18446744073708000000))
+    );
+    assert!(parse_timestamp("5124095576030.9h".as_bytes()).is_err());
+    assert!(parse_timestamp("5124095576030431.9h".as_bytes()).is_err());
+
+    const WALLCLOCK_TTML: &str = r#"<tt><body><div><p begin="wallclock(2024-01-01T10:00:00)" end="1s"><span begin="0s" end="1s">a</span></p></div></body></tt>"#;
+    assert!(matches!(
+        parse_ttml_str(WALLCLOCK_TTML),
+        Err(TTMLError::UnsupportedWallclockTime(pos)) if pos == WALLCLOCK_TTML.find("<p").unwrap()
+    ));
 }
 
 #[test]
@@ -1139,3 +1754,241 @@ fn test_parse_apple_music_word_by_word_lyrics() {
     assert!(line3.translated_lyric.is_empty(), "第三行不应有翻译");
     assert!(line3.roman_lyric.is_empty(), "第三行不应有音译");
 }
+
+#[test]
+fn test_parse_ttml_str_borrows_input() {
+    const TTML: &str = r#"<tt><body><div><p begin="0" end="5"><span begin="0" end="2">Hello</span> <span begin="2" end="5">&lt;3</span><span ttm:role="x-bg" begin="3" end="5"><span begin="3" end="5">(ooh)</span></span></p></div></body></tt>"#;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let line = &ttml_lyric.lines[0];
+
+    assert!(matches!(line.words[0].word, Cow::Borrowed("Hello")));
+    assert!(matches!(line.words[1].word, Cow::Borrowed(" ")));
+    assert_eq!(line.words[2].word, "<3");
+
+    let bg_line = &ttml_lyric.lines[1];
+    assert!(matches!(bg_line.words[0].word, Cow::Borrowed("ooh")));
+
+    let owned = parse_ttml(TTML.as_bytes()).unwrap();
+    assert!(matches!(owned.lines[0].words[0].word, Cow::Owned(_)));
+    assert_eq!(owned.lines, ttml_lyric.lines);
+}
+
+#[test]
+fn test_parse_ttml_agents() {
+    const TTML: &str = r#"<tt><head><metadata><ttm:agent type="other" xml:id="v0"/><ttm:agent type="person" xml:id="v1"/><ttm:agent type="person" xml:id="v2"/></metadata></head><body><div><p begin="0" end="1" ttm:agent="v1"><span begin="0" end="1">a</span></p><p begin="1" end="2" ttm:agent="v2"><span begin="1" end="2">b</span></p><p begin="2" end="3" ttm:agent="v3"><span begin="2" end="3">c</span></p><p begin="3" end="4" ttm:agent="v1"><span begin="3" end="4">d</span></p></div></body></tt>"#;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let is_duet: Vec<_> = ttml_lyric.lines.iter().map(|line| line.is_duet).collect();
+    assert_eq!(is_duet, [false, true, true, false]);
+
+    let mut agents = Agents::default();
+    let v1 = agents.intern(b"v1");
+    assert_eq!(agents.intern(b"v2"), 1);
+    assert_eq!(agents.intern(b"v1"), v1);
+    assert_eq!(agents.ids.len(), 2);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parse_ttml_str_parallel() {
+    const TEST_TTML: &str = include_str!("../../test/test.ttml");
+    const TTML_EXAMPLE: &str = r##"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:itunes="http://music.apple.com/lyric-ttml-internal"><head><metadata><iTunesMetadata xmlns="http://music.apple.com/lyric-ttml-internal"><translations><translation type="replacement" xml:lang="en"><text for="L2">second</text></translation></translations></iTunesMetadata></metadata></head><body><div><p begin="0s" end="1s" itunes:key="L1"><span begin="0s" end="1s">one</span></p></div>
+<div><p begin="1s" end="2s" itunes:key="L2"><span begin="1s" end="2s">two</span><span ttm:role="x-bg" begin="1s" end="2s"><span begin="1s" end="2s">(bg)</span></span></p></div></body></tt>"##;
+
+    for src in [TEST_TTML, TTML_EXAMPLE] {
+        let expected = parse_ttml_str(src).unwrap();
+        let parallel = parse_ttml_str_parallel(src).unwrap();
+        assert_eq!(parallel.lines, expected.lines);
+        assert_eq!(parallel.metadata, expected.metadata);
+        assert_eq!(parallel.warnings, expected.warnings);
+    }
+
+    const INVALID_TTML: &str =
+        r#"<tt><body><div><p begin="0" end="1"><span begin="x">a</span></p></div></body></tt>"#;
+    assert!(matches!(
+        parse_ttml_str_parallel(INVALID_TTML),
+        Err(TTMLError::XmlTimeStampError(pos)) if pos == INVALID_TTML.find("<span").unwrap()
+    ));
+}
+
+#[test]
+fn test_parse_ttml_warnings() {
+    const TTML: &str = "<tt><body><div>\n<p begin=\"5\" end=\"1\"><span begin=\"0\" end=\"1\">a&nbsp;</span></p>\n<p begin=\"1\" end=\"2\"><span begin=\"2\" end=\"1\">b</span></p>\n</div></body></tt>";
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let codes: Vec<_> = ttml_lyric
+        .warnings
+        .iter()
+        .map(|w| (w.code, w.line, w.byte_offset))
+        .collect();
+
+    assert_eq!(
+        codes,
+        vec![
+            (
+                ParseWarningCode::InvalidLineTiming,
+                2,
+                TTML.find("<p").unwrap()
+            ),
+            (
+                ParseWarningCode::UnknownEntity,
+                2,
+                TTML.find("&nbsp;").unwrap()
+            ),
+            (
+                ParseWarningCode::InvalidWordTiming,
+                3,
+                TTML.find("<span begin=\"2\"").unwrap()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_ttml_untimed_word_and_duplicate_id_warnings() {
+    const TTML: &str = "<tt><head><metadata><ttm:agent type=\"person\" xml:id=\"v1\"/></metadata></head><body><div>\n<p begin=\"0\" end=\"1\" itunes:key=\"L1\"><span end=\"1\">a</span></p>\n<p itunes:key=\"L2\"><span>b</span></p>\n<p begin=\"2\" end=\"3\" xml:id=\"v1\"><span begin=\"2\" end=\"3\">c</span></p>\n</div></body></tt>";
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let codes: Vec<_> = ttml_lyric
+        .warnings
+        .iter()
+        .map(|w| (w.code, w.line, w.byte_offset))
+        .collect();
+
+    // 缺少时间的行中的单词会随行一起推断时间，不单独报告
+    assert_eq!(
+        codes,
+        vec![
+            (
+                ParseWarningCode::UntimedWord,
+                2,
+                TTML.find("<span end").unwrap()
+            ),
+            (
+                ParseWarningCode::EstimatedLineTiming,
+                3,
+                TTML.find("<p itunes:key=\"L2\"").unwrap()
+            ),
+            (ParseWarningCode::DuplicateId, 4, TTML.rfind("<p").unwrap()),
+        ]
+    );
+
+    #[cfg(feature = "rayon")]
+    assert_eq!(
+        parse_ttml_str_parallel(TTML).unwrap().warnings,
+        ttml_lyric.warnings
+    );
+}
+
+#[test]
+fn test_parse_ttml_multiple_translations() {
+    const TTML: &str = r##"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:itunes="http://music.apple.com/lyric-ttml-internal"><head><metadata><iTunesMetadata xmlns="http://music.apple.com/lyric-ttml-internal"><translations><translation type="replacement" xml:lang="zh-Hans"><text for="L1">苹果翻译</text></translation><translation type="replacement" xml:lang="ja"><text for="L1">アップル</text></translation></translations></iTunesMetadata></metadata></head><body><div><p begin="0s" end="1s" itunes:key="L1"><span begin="0s" end="1s">one</span><span ttm:role="x-translation" xml:lang="en">first</span><span ttm:role="x-translation" xml:lang="ZH-hans">内嵌翻译</span><span ttm:role="x-translation" xml:lang="en">duplicated</span></p><p begin="1s" end="2s"><span begin="1s" end="2s">two</span><span ttm:role="x-translation" xml:lang="en">second</span><span ttm:role="x-translation" xml:lang="de">zweite</span></p></div></body></tt>"##;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let translations = |line: &LyricLine| {
+        line.translations
+            .iter()
+            .map(|t| (t.lang.to_string(), t.text.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let first = &ttml_lyric.lines[0];
+    assert_eq!(
+        translations(first),
+        vec![
+            ("zh-Hans".to_owned(), "苹果翻译".to_owned()),
+            ("ja".to_owned(), "アップル".to_owned()),
+            ("en".to_owned(), "first".to_owned()),
+        ]
+    );
+    assert_eq!(first.translated_lyric, "苹果翻译");
+
+    let second = &ttml_lyric.lines[1];
+    assert_eq!(
+        translations(second),
+        vec![
+            ("en".to_owned(), "second".to_owned()),
+            ("de".to_owned(), "zweite".to_owned()),
+        ]
+    );
+    assert_eq!(second.translated_lyric, "second");
+}
+
+#[test]
+fn test_multiple_translations_roundtrip() {
+    const TTML: &str = r##"<tt xmlns="http://www.w3.org/ns/ttml"><body><div><p begin="0s" end="1s"><span begin="0s" end="1s">one</span><span ttm:role="x-translation" xml:lang="en">first</span><span ttm:role="x-translation" xml:lang="de">erste</span></p></div></body></tt>"##;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let written = super::stringify_ttml(&ttml_lyric).unwrap();
+    let reparsed = parse_ttml_str(&written).unwrap();
+    assert_eq!(
+        reparsed.lines[0].translations,
+        ttml_lyric.lines[0].translations
+    );
+    assert_eq!(reparsed.lines[0].translated_lyric, "first");
+}
+
+#[test]
+fn test_parse_ttml_cdata() {
+    const TTML: &str = r##"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:itunes="http://music.apple.com/lyric-ttml-internal"><head><metadata><iTunesMetadata xmlns="http://music.apple.com/lyric-ttml-internal"><translations><translation xml:lang="zh-Hans"><text for="L1"><![CDATA[苹果 & 翻译]]></text></translation></translations></iTunesMetadata></metadata></head><body><div><p begin="0s" end="2s" itunes:key="L1"><span begin="0s" end="1s"><![CDATA[<Hello>]]></span> <span begin="1s" end="2s">wor<![CDATA[ld]]></span><span ttm:role="x-roman"><![CDATA[roman & text]]></span></p></div></body></tt>"##;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let line = &ttml_lyric.lines[0];
+
+    assert_eq!(line.words[0].word, "<Hello>");
+    assert_eq!(line.words[2].word, "world");
+    assert_eq!(line.roman_lyric, "roman & text");
+    assert_eq!(line.translated_lyric, "苹果 & 翻译");
+}
+
+#[test]
+fn test_parse_ttml_dur() {
+    const TTML: &str = r##"<tt xmlns="http://www.w3.org/ns/ttml"><body><div><p begin="1s" dur="2s"><span begin="1s" dur="500ms">Hello</span> <span begin="1.5s" end="3s" dur="1s">world</span></p></div></body></tt>"##;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let line = &ttml_lyric.lines[0];
+
+    assert_eq!(line.start_time, 1000);
+    assert_eq!(line.end_time, 3000);
+    assert_eq!(line.words[0].end_time, 1500);
+    // 同时存在 end 和 dur 时以 end 为准
+    assert_eq!(line.words[2].end_time, 3000);
+    assert!(ttml_lyric.warnings.is_empty());
+}
+
+#[test]
+fn test_parse_ttml_estimated_line_timing() {
+    const TTML: &str = r##"<tt xmlns="http://www.w3.org/ns/ttml"><body><div><p begin="1s" end="2s"><span begin="1s" end="2s">a</span></p><p><span>bb</span></p><p><span>b</span></p><p begin="5s" end="6s"><span begin="5s" end="6s">c</span></p><p><span begin="7s" end="8s">d</span></p><p begin="9s"><span>ee</span></p></div></body></tt>"##;
+
+    let ttml_lyric = parse_ttml_str(TTML).unwrap();
+    let times: Vec<_> = ttml_lyric
+        .lines
+        .iter()
+        .map(|line| (line.start_time, line.end_time))
+        .collect();
+
+    assert_eq!(
+        times,
+        vec![
+            (1000, 2000),
+            // 两行按文本长度 2:1 分配前后行之间的 3 秒空隙
+            (2000, 4000),
+            (4000, 5000),
+            (5000, 6000),
+            // 单词带有时间时直接使用单词的时间
+            (7000, 8000),
+            // 只有 begin 且之后没有带时间的行，按字符数估算时长
+            (9000, 10000),
+        ]
+    );
+    assert_eq!(ttml_lyric.lines[1].words[0].start_time, 2000);
+    assert_eq!(ttml_lyric.lines[1].words[0].end_time, 4000);
+    assert_eq!(ttml_lyric.warnings.len(), 4);
+    assert!(
+        ttml_lyric
+            .warnings
+            .iter()
+            .all(|w| w.code == ParseWarningCode::EstimatedLineTiming)
+    );
+}
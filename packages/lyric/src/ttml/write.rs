@@ -6,6 +6,43 @@ use std::io::Cursor;
 use quick_xml::{Writer, events::*};
 
 use super::TTMLLyric;
+use crate::LyricLine;
+
+/// 没有标注语言的翻译写出时使用的语言
+const DEFAULT_TRANSLATION_LANG: &str = "zh-CN";
+
+/// 写出一行的所有翻译，没有多语言翻译时写出 `translated_lyric`
+fn write_translations(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    line: &LyricLine,
+) -> Result<(), quick_xml::Error> {
+    let mut write = |lang: &str, text: &str| -> Result<(), quick_xml::Error> {
+        let lang = if lang.is_empty() {
+            DEFAULT_TRANSLATION_LANG
+        } else {
+            lang
+        };
+        writer.write_event(Event::Start(
+            BytesStart::new("span")
+                .with_attributes([("ttm:role", "x-translation"), ("xml:lang", lang)]),
+        ))?;
+        writer.write_event(Event::Text(BytesText::new(text)))?;
+        writer.write_event(Event::End(BytesEnd::new("span")))?;
+        Ok(())
+    };
+    if line.translations.is_empty() {
+        if !line.translated_lyric.is_empty() {
+            write("", &line.translated_lyric)?;
+        }
+        return Ok(());
+    }
+    for translation in &line.translations {
+        if !translation.text.is_empty() {
+            write(&translation.lang, &translation.text)?;
+        }
+    }
+    Ok(())
+}
 
 pub fn stringify_ttml(lyric: &TTMLLyric) -> Result<String, quick_xml::Error> {
     // let mut writer =
@@ -104,87 +141,63 @@ pub fn stringify_ttml(lyric: &TTMLLyric) -> Result<String, quick_xml::Error> {
                         }
                     }
 
-                    if let Some(next_line) = line_it.peek() {
-                        if next_line.is_bg {
-                            let begin_ts = ms_to_timestamp(next_line.start_time);
-                            let end_ts = ms_to_timestamp(next_line.end_time);
-                            writer.write_event(Event::Start(
-                                BytesStart::new("span").with_attributes([
-                                    ("ttm:role", "x-bg"),
-                                    ("begin", begin_ts.as_str()),
-                                    ("end", end_ts.as_str()),
-                                ]),
-                            ))?;
-
-                            for word in &next_line.words {
-                                let begin_ts = ms_to_timestamp(word.start_time);
-                                let end_ts = ms_to_timestamp(word.end_time);
-                                if word.word.trim().is_empty() {
-                                    writer.write_event(Event::Text(BytesText::new(
-                                        word.word.as_ref(),
-                                    )))?;
-                                } else {
-                                    writer.write_event(Event::Start(
-                                        BytesStart::new("span").with_attributes([
-                                            ("begin", begin_ts.as_str()),
-                                            ("end", end_ts.as_str()),
-                                        ]),
-                                    ))?;
-                                    writer.write_event(Event::Text(BytesText::new(
-                                        word.word.as_ref(),
-                                    )))?;
-                                    writer.write_event(Event::End(BytesEnd::new("span")))?;
-                                }
-                            }
+                    if let Some(next_line) = line_it.peek()
+                        && next_line.is_bg
+                    {
+                        let begin_ts = ms_to_timestamp(next_line.start_time);
+                        let end_ts = ms_to_timestamp(next_line.end_time);
+                        writer.write_event(Event::Start(
+                            BytesStart::new("span").with_attributes([
+                                ("ttm:role", "x-bg"),
+                                ("begin", begin_ts.as_str()),
+                                ("end", end_ts.as_str()),
+                            ]),
+                        ))?;
 
-                            if !next_line.translated_lyric.is_empty() {
+                        for word in &next_line.words {
+                            let begin_ts = ms_to_timestamp(word.start_time);
+                            let end_ts = ms_to_timestamp(word.end_time);
+                            if word.word.trim().is_empty() {
+                                writer
+                                    .write_event(Event::Text(BytesText::new(word.word.as_ref())))?;
+                            } else {
                                 writer.write_event(Event::Start(
                                     BytesStart::new("span").with_attributes([
-                                        ("ttm:role", "x-translation"),
-                                        ("xml:lang", "zh-CN"),
+                                        ("begin", begin_ts.as_str()),
+                                        ("end", end_ts.as_str()),
                                     ]),
                                 ))?;
-                                writer.write_event(Event::Text(BytesText::new(
-                                    &next_line.translated_lyric,
-                                )))?;
-                                writer.write_event(Event::End(BytesEnd::new("span")))?;
-                            }
-
-                            if !next_line.roman_lyric.is_empty() {
-                                writer.write_event(Event::Start(
-                                    BytesStart::new("span")
-                                        .with_attributes([("ttm:role", "x-roman")]),
-                                ))?;
-                                writer.write_event(Event::Text(BytesText::new(
-                                    &next_line.roman_lyric,
-                                )))?;
+                                writer
+                                    .write_event(Event::Text(BytesText::new(word.word.as_ref())))?;
                                 writer.write_event(Event::End(BytesEnd::new("span")))?;
                             }
-
-                            writer.write_event(Event::End(BytesEnd::new("span")))?;
-
-                            line_it.next();
                         }
 
-                        if !line.translated_lyric.is_empty() {
-                            writer.write_event(Event::Start(
-                                BytesStart::new("span").with_attributes([
-                                    ("ttm:role", "x-translation"),
-                                    ("xml:lang", "zh-CN"),
-                                ]),
-                            ))?;
-                            writer
-                                .write_event(Event::Text(BytesText::new(&line.translated_lyric)))?;
-                            writer.write_event(Event::End(BytesEnd::new("span")))?;
-                        }
+                        write_translations(&mut writer, next_line)?;
 
-                        if !line.roman_lyric.is_empty() {
+                        if !next_line.roman_lyric.is_empty() {
                             writer.write_event(Event::Start(
                                 BytesStart::new("span").with_attributes([("ttm:role", "x-roman")]),
                             ))?;
-                            writer.write_event(Event::Text(BytesText::new(&line.roman_lyric)))?;
+                            writer
+                                .write_event(Event::Text(BytesText::new(&next_line.roman_lyric)))?;
                             writer.write_event(Event::End(BytesEnd::new("span")))?;
                         }
+
+                        writer.write_event(Event::End(BytesEnd::new("span")))?;
+
+                        line_it.next();
+                    }
+
+                    // 无论后面是否还有其它行，都要写出本行的翻译和音译
+                    write_translations(&mut writer, line)?;
+
+                    if !line.roman_lyric.is_empty() {
+                        writer.write_event(Event::Start(
+                            BytesStart::new("span").with_attributes([("ttm:role", "x-roman")]),
+                        ))?;
+                        writer.write_event(Event::Text(BytesText::new(&line.roman_lyric)))?;
+                        writer.write_event(Event::End(BytesEnd::new("span")))?;
                     }
                     writer.write_event(Event::End(BytesEnd::new("p")))?;
                 }
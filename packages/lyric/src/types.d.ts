@@ -81,6 +81,63 @@ export function stringifyEslrc(lines: LyricLine[]): string;
  */
 export function stringifyAss(lines: LyricLine[]): string;
 
+/**
+ * Aegisub 工程中单个样式的配置
+ *
+ * 颜色使用 `#RRGGBB` 或 `#RRGGBBAA` 格式，也可以直接填写 ASS 的 `&HAABBGGRR` 格式
+ */
+export interface AssStyle {
+	fontName?: string;
+	fontSize?: number;
+	/** 已唱部分的颜色 */
+	primaryColour?: string;
+	/** 未唱部分的颜色 */
+	secondaryColour?: string;
+	outlineColour?: string;
+	backColour?: string;
+	bold?: boolean;
+	italic?: boolean;
+	outline?: number;
+	shadow?: number;
+	/** 小键盘方位的对齐方式，例如 `2` 为底部居中 */
+	alignment?: number;
+	marginL?: number;
+	marginR?: number;
+	marginV?: number;
+}
+
+/**
+ * 导出 Aegisub 工程时使用的模板配置，未填写的字段会使用默认值
+ *
+ * 主唱、对唱、背景、译文和音译各自对应一个样式，分别命名为
+ * `Lyric`、`Duet`、`Background`、`Translation` 和 `Roman`
+ */
+export interface AegisubTemplate {
+	title?: string;
+	playResX?: number;
+	playResY?: number;
+	mainStyle?: AssStyle;
+	duetStyle?: AssStyle;
+	backgroundStyle?: AssStyle;
+	translationStyle?: AssStyle;
+	romanStyle?: AssStyle;
+	/** 是否用 `\k` 标签写入逐字时间，为 `false` 时只写入逐行的歌词文本，默认为 `true` */
+	karaoke?: boolean;
+}
+
+/**
+ * 将歌词数组导出为可以直接在 Aegisub 中打开编辑的卡拉 OK 字幕工程
+ *
+ * 逐字时间会写为 `\k` 标签，译文和音译会作为单独的字幕行使用各自的样式
+ * @param lines 歌词数组
+ * @param template 样式模板，不传入时使用默认样式
+ * @returns ASS 字幕格式的字符串
+ */
+export function stringifyAegisubProject(
+	lines: LyricLine[],
+	template?: AegisubTemplate,
+): string;
+
 /**
  * 一个歌词单词
  */
@@ -95,6 +152,16 @@ export interface LyricWord {
 	romanWord: string;
 }
 
+/**
+ * 带有语言信息的一条歌词翻译
+ */
+export interface LyricTranslation {
+	/** 翻译的语言代码，例如 `zh-Hans`，来源没有标注语言时为空字符串 */
+	lang: string;
+	/** 翻译文本 */
+	text: string;
+}
+
 /**
  * 一行歌词，存储多个单词
  * 如果是 LyRiC 等只能表达一行歌词的格式，则会将整行当做一个单词存储起来
@@ -109,6 +176,12 @@ export interface LyricLine {
 	 * 该行的翻译
 	 */
 	translatedLyric: string;
+	/**
+	 * 该行的所有翻译，按语言去重并保持来源中的顺序
+	 *
+	 * `translatedLyric` 总是其中优先级最高的一条
+	 */
+	translations?: LyricTranslation[];
 	/**
 	 * 该行的音译
 	 */
@@ -135,8 +208,52 @@ export interface LyricLine {
 	 * **并不总是等于最后一个单词的开始时间**
 	 */
 	endTime: number;
+	/**
+	 * 该行的候选换行点，按位置排列
+	 *
+	 * 只有启用 `line-break` 功能构建时，解析函数和 `computeLineBreaks` 才会填写这个字段
+	 */
+	lineBreaks?: LineBreakCandidate[];
+}
+
+/**
+ * 规整逐字歌词的音节边界，消除逐字动画中因微小间隙或重叠产生的闪烁
+ *
+ * 同一行中相邻两个有时长的单词，如果前者的结束时间与后者的开始时间相差不超过阈值，
+ * 则将前者的结束时间对齐到后者的开始时间
+ * @param lines 歌词数组
+ * @param threshold 阈值，单位为毫秒，默认为 10
+ * @returns 规整后的歌词数组
+ */
+export function normalizeTiming(
+	lines: LyricLine[],
+	threshold?: number,
+): LyricLine[];
+
+/**
+ * 一个候选换行点，表示可以在该位置之前换行
+ */
+export interface LineBreakCandidate {
+	/** 换行点所在单词的下标 */
+	wordIndex: number;
+	/**
+	 * 换行点在该单词中的偏移，与 JS 字符串的下标一致
+	 *
+	 * 为 0 时表示在该单词之前换行
+	 */
+	offset: number;
 }
 
+/**
+ * 计算每一行歌词的候选换行点并写入 `lineBreaks`，供渲染层在长行换行时使用
+ *
+ * 中文和日文会按词典分词，不会把一个词从中间拆开，也不会在标点之前换行。
+ * 行首和行尾不会作为候选换行点。只有启用 `line-break` 功能构建时才会导出
+ * @param lines 歌词数组
+ * @returns 附上候选换行点的歌词数组
+ */
+export function computeLineBreaks(lines: LyricLine[]): LyricLine[];
+
 /**
  * 解密十六进制字符串格式的 Qrc 歌词数据
  * 解密后可去头尾 XML 数据后通过调用 `parseQrc` 解析歌词行
@@ -145,6 +262,39 @@ export interface LyricLine {
  */
 export function decryptQrcHex(hexData: string): string;
 
+/**
+ * 解析警告的类别
+ * - `unknownEntity`: 无法识别的实体引用，对应的文本会被丢弃
+ * - `invalidLineTiming`: 歌词行的开始时间晚于结束时间
+ * - `invalidWordTiming`: 单词的开始时间晚于结束时间
+ * - `estimatedLineTiming`: 歌词行缺少 `begin` 或 `end` 属性，时间是按前后行和文本长度推断出来的
+ * - `untimedWord`: 单词缺少 `begin` 属性，开始时间按 0 处理
+ * - `duplicateId`: 元素的 `xml:id` 与之前的元素重复
+ */
+export type ParseWarningCode =
+	| "unknownEntity"
+	| "invalidLineTiming"
+	| "invalidWordTiming"
+	| "estimatedLineTiming"
+	| "untimedWord"
+	| "duplicateId";
+
+/**
+ * 解析过程中发现的非致命问题，附带出错元素在源文件中的位置
+ */
+export interface ParseWarning {
+	code: ParseWarningCode;
+	message: string;
+	/**
+	 * 出错元素在源文件中的字节偏移
+	 */
+	byteOffset: number;
+	/**
+	 * 出错元素所在的行号，从 1 开始
+	 */
+	line: number;
+}
+
 /**
  * 一个 TTML 歌词行对象，存储了歌词行信息和 AMLL 元数据信息
  */
@@ -157,6 +307,10 @@ export interface TTMLLyric {
 	 * 一个元数据表，以 `[键, 值数组]` 的形式存储
 	 */
 	metadata: [string, string[]][];
+	/**
+	 * 解析过程中产生的警告，生成 TTML 时会被忽略
+	 */
+	warnings?: ParseWarning[];
 }
 
 /**
@@ -42,3 +42,75 @@ pub fn process_lyrics(lines: &mut [LyricLine]) {
         }
     }
 }
+
+/// [`normalize_timing`] 默认使用的阈值，单位为毫秒
+pub const DEFAULT_TIMING_SNAP_THRESHOLD: u64 = 10;
+
+/// 规整逐字歌词的音节边界，消除逐字动画中因微小间隙或重叠产生的闪烁
+///
+/// 同一行中相邻两个有时长的单词，如果前者的结束时间与后者的开始时间相差不超过 `threshold` 毫秒，
+/// 则将前者的结束时间对齐到后者的开始时间，没有时长的单词（例如空格）不参与规整
+pub fn normalize_timing(lines: &mut [LyricLine], threshold: u64) {
+    for line in lines.iter_mut() {
+        let mut timed_words = line
+            .words
+            .iter_mut()
+            .filter(|word| word.end_time > word.start_time)
+            .peekable();
+        while let Some(word) = timed_words.next() {
+            let Some(next_word) = timed_words.peek() else {
+                break;
+            };
+            if next_word.start_time > word.start_time
+                && word.end_time.abs_diff(next_word.start_time) <= threshold
+            {
+                word.end_time = next_word.start_time;
+            }
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
+#[wasm_bindgen(js_name = "normalizeTiming", skip_typescript)]
+pub fn normalize_timing_js(lines: JsValue, threshold: Option<u32>) -> JsValue {
+    let mut lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lines).unwrap();
+    normalize_timing(
+        &mut lines,
+        threshold.map_or(DEFAULT_TIMING_SNAP_THRESHOLD, u64::from),
+    );
+    serde_wasm_bindgen::to_value(&lines).unwrap()
+}
+
+#[test]
+fn test_normalize_timing() {
+    use crate::LyricWord;
+
+    let word = |start_time, end_time, word: &'static str| LyricWord {
+        start_time,
+        end_time,
+        word: word.into(),
+        ..Default::default()
+    };
+    let mut lines = vec![LyricLine {
+        words: vec![
+            word(0, 495, "a"),
+            word(0, 0, " "),
+            word(500, 1008, "b"),
+            word(1000, 1500, "c"),
+            word(1600, 2000, "d"),
+        ],
+        ..Default::default()
+    }];
+
+    normalize_timing(&mut lines, DEFAULT_TIMING_SNAP_THRESHOLD);
+
+    let times: Vec<_> = lines[0]
+        .words
+        .iter()
+        .map(|w| (w.start_time, w.end_time))
+        .collect();
+    assert_eq!(
+        times,
+        vec![(0, 500), (0, 0), (500, 1000), (1000, 1500), (1600, 2000)]
+    );
+}
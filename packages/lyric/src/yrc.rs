@@ -155,7 +155,9 @@ pub fn stringify_yrc(lines: &[LyricLine]) -> String {
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
 #[wasm_bindgen(js_name = "parseYrc", skip_typescript)]
 pub fn parse_yrc_js(src: &str) -> JsValue {
-    serde_wasm_bindgen::to_value(&parse_yrc(src)).unwrap()
+    let mut lines = parse_yrc(src);
+    crate::attach_js_line_breaks(&mut lines);
+    serde_wasm_bindgen::to_value(&lines).unwrap()
 }
 
 #[cfg(all(target_arch = "wasm32", feature = "serde"))]
@@ -1,6 +1,8 @@
-use ffmpeg_next as ffmpeg;
+use ffmpeg_next::{self as ffmpeg, ChannelLayout};
 use serde::*;
 
+use crate::dsd::{dsd_sample_rate, is_dsd_codec};
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AudioQuality {
@@ -10,10 +12,66 @@ pub struct AudioQuality {
     pub channels: Option<u32>,
     pub sample_format: String,
     pub codec: String,
+    /// 容器格式的名称，例如 `flac`、`mov,mp4,m4a,3gp,3g2,mj2`
+    pub container: String,
+    /// 码率，单位为 bit/s，编码器和容器都没有提供时为空
+    pub bit_rate: Option<u64>,
+    /// 原始音频的位深，有损编码没有位深时为空
+    pub bit_depth: Option<u32>,
+    /// 声道布局的名称，例如 `stereo`、`5.1`，无法识别时为声道数加 `ch`
+    pub channel_layout: String,
+    pub lossless: bool,
+    /// 无损且位深高于 16 位或采样率高于 48kHz
+    pub hi_res: bool,
+}
+
+/// 常见声道布局的名称，与 FFmpeg 的命名一致
+const CHANNEL_LAYOUT_NAMES: [(ChannelLayout, &str); 12] = [
+    (ChannelLayout::MONO, "mono"),
+    (ChannelLayout::STEREO, "stereo"),
+    (ChannelLayout::_2POINT1, "2.1"),
+    (ChannelLayout::SURROUND, "3.0"),
+    (ChannelLayout::_4POINT0, "4.0"),
+    (ChannelLayout::QUAD, "quad"),
+    (ChannelLayout::_5POINT0, "5.0(side)"),
+    (ChannelLayout::_5POINT0_BACK, "5.0"),
+    (ChannelLayout::_5POINT1, "5.1(side)"),
+    (ChannelLayout::_5POINT1_BACK, "5.1"),
+    (ChannelLayout::_7POINT1, "7.1"),
+    (ChannelLayout::_7POINT1POINT4_BACK, "7.1.4"),
+];
+
+fn is_lossless_codec(id: ffmpeg::codec::Id) -> bool {
+    use ffmpeg::codec::Id;
+    matches!(
+        id,
+        Id::FLAC
+            | Id::ALAC
+            | Id::WAVPACK
+            | Id::APE
+            | Id::TTA
+            | Id::TAK
+            | Id::MLP
+            | Id::TRUEHD
+            | Id::SHORTEN
+            | Id::WMALOSSLESS
+    ) || id.name().starts_with("pcm_")
+        || is_dsd_codec(id)
+}
+
+fn channel_layout_name(layout: &ChannelLayout, channels: u16) -> String {
+    CHANNEL_LAYOUT_NAMES
+        .iter()
+        .find(|(known, _)| known == layout)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("{channels}ch"))
 }
 
 impl AudioQuality {
-    pub fn from_ffmpeg_decoder(decoder: &ffmpeg::decoder::Audio) -> Self {
+    pub fn from_ffmpeg(
+        input_ctx: &ffmpeg::format::context::Input,
+        decoder: &ffmpeg::decoder::Audio,
+    ) -> Self {
         let sample_format_str = match decoder.format() {
             ffmpeg::format::Sample::U8(_) => "u8",
             ffmpeg::format::Sample::I16(_) => "i16",
@@ -34,9 +92,36 @@ impl AudioQuality {
             _ => None,
         };
 
+        // DSD 音源显示原本的 1 位采样率，而不是解码后的 PCM 采样率
+        let is_dsd = is_dsd_codec(decoder.id());
+        let sample_rate = if is_dsd {
+            dsd_sample_rate(decoder.rate())
+        } else {
+            decoder.rate()
+        };
+
+        let lossless = is_lossless_codec(decoder.id());
+        // SAFETY: 解码器上下文在整个调用期间有效，这里只读取一个整数字段
+        let raw_bits = unsafe { (*decoder.as_ptr()).bits_per_raw_sample };
+        let bit_depth = if is_dsd {
+            Some(1)
+        } else if !lossless {
+            None
+        } else if raw_bits > 0 {
+            Some(raw_bits as u32)
+        } else {
+            bits_per_sample
+        };
+        let hi_res = lossless && (bit_depth.is_some_and(|bits| bits > 16) || sample_rate > 48000);
+
+        let bit_rate = [decoder.bit_rate() as i64, input_ctx.bit_rate()]
+            .into_iter()
+            .find(|&rate| rate > 0)
+            .map(|rate| rate as u64);
+
         Self {
-            sample_rate: Some(decoder.rate()),
-            bits_per_coded_sample: None,
+            sample_rate: Some(sample_rate),
+            bits_per_coded_sample: is_dsd.then_some(1),
             bits_per_sample,
             channels: Some(decoder.channels() as u32),
             codec: decoder
@@ -44,6 +129,12 @@ impl AudioQuality {
                 .map(|c| c.name().to_string())
                 .unwrap_or_else(|| "unknown".to_string()),
             sample_format: sample_format_str.to_string(),
+            container: input_ctx.format().name().to_string(),
+            bit_rate,
+            bit_depth,
+            channel_layout: channel_layout_name(&decoder.channel_layout(), decoder.channels()),
+            lossless,
+            hi_res,
         }
     }
 }
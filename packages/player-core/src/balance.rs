@@ -0,0 +1,66 @@
+//! 左右声道平衡与强制单声道
+//!
+//! 只有一侧耳朵能听清时可以把两个声道合成单声道，左右声道混音有缺陷的歌曲也可以借此修正。
+//! 只处理输出的前两个声道（左前、右前），其余声道保持不变
+
+use serde::{Deserialize, Serialize};
+
+/// 声道平衡设置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BalanceOptions {
+    /// -1 为只有左声道，1 为只有右声道，0 为不调整。偏向一侧时只衰减另一侧，不会提升音量
+    pub balance: f64,
+    /// 把左右声道混合为单声道后再输出到两侧
+    pub mono: bool,
+}
+
+/// 作用在解码输出上的声道平衡
+pub struct ChannelBalance {
+    channels: usize,
+    left_gain: f32,
+    right_gain: f32,
+    mono: bool,
+}
+
+impl ChannelBalance {
+    /// 创建一个不做任何调整的声道平衡
+    pub fn new(channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            left_gain: 1.0,
+            right_gain: 1.0,
+            mono: false,
+        }
+    }
+
+    pub fn update(&mut self, options: &BalanceOptions) {
+        let balance = if options.balance.is_finite() {
+            options.balance.clamp(-1.0, 1.0) as f32
+        } else {
+            0.0
+        };
+        self.left_gain = (1.0 - balance).min(1.0);
+        self.right_gain = (1.0 + balance).min(1.0);
+        self.mono = options.mono;
+    }
+
+    fn is_identity(&self) -> bool {
+        !self.mono && self.left_gain == 1.0 && self.right_gain == 1.0
+    }
+
+    pub fn process(&self, samples: &mut [f32]) {
+        if self.channels < 2 || self.is_identity() {
+            return;
+        }
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let (mut left, mut right) = (frame[0], frame[1]);
+            if self.mono {
+                let mid = (left + right) * 0.5;
+                (left, right) = (mid, mid);
+            }
+            frame[0] = left * self.left_gain;
+            frame[1] = right * self.right_gain;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! 读取音频文件中内嵌的封面
+//!
+//! ID3 的 APIC 帧、FLAC 的 PICTURE 块和 MP4 的 `covr` 都会被 FFmpeg 在打开文件时读入
+//! 带有 `ATTACHED_PIC` 标记的视频流的 `attached_pic` 中，直接从那里取出即可，不需要扫描数据包
+
+use ffmpeg_next as ffmpeg;
+
+/// FFmpeg 用图片类型作为封面流的 `comment` 元数据，正面封面的类型名
+const FRONT_COVER_COMMENT: &str = "Cover (front)";
+
+/// 内嵌的封面图片
+pub(crate) struct EmbeddedCover {
+    pub data: Vec<u8>,
+    pub media_type: String,
+}
+
+/// 读取内嵌的封面，有多张图片时优先选择正面封面
+pub(crate) fn read_embedded_cover(
+    input_ctx: &ffmpeg::format::context::Input,
+) -> Option<EmbeddedCover> {
+    input_ctx
+        .streams()
+        .filter(|stream| {
+            stream
+                .disposition()
+                .contains(ffmpeg::format::stream::Disposition::ATTACHED_PIC)
+        })
+        .filter_map(|stream| {
+            let data = attached_picture(&stream)?;
+            let is_front = stream.metadata().get("comment") == Some(FRONT_COVER_COMMENT);
+            Some((is_front, data, stream.parameters().id()))
+        })
+        .min_by_key(|(is_front, ..)| !*is_front)
+        .map(|(_, data, codec_id)| EmbeddedCover {
+            media_type: sniff_media_type(&data).map_or_else(
+                || format!("image/{}", codec_id.name().to_lowercase()),
+                str::to_string,
+            ),
+            data,
+        })
+}
+
+fn attached_picture(stream: &ffmpeg::format::stream::Stream) -> Option<Vec<u8>> {
+    // SAFETY: 流指针来自仍然存活的输入上下文，attached_pic 由 FFmpeg 在打开文件时填充，这里只复制其数据
+    unsafe {
+        let packet = &(*stream.as_ptr()).attached_pic;
+        if packet.data.is_null() || packet.size <= 0 {
+            return None;
+        }
+        Some(std::slice::from_raw_parts(packet.data, packet.size as usize).to_vec())
+    }
+}
+
+/// 根据文件头判断图片格式，FFmpeg 的解码器名称（例如 `mjpeg`）不能直接作为 MIME 类型
+fn sniff_media_type(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => Some("image/webp"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        _ => None,
+    }
+}
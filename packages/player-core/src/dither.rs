@@ -0,0 +1,152 @@
+//! 输出设备使用 16 位及以下的整数采样格式时，在 f32 处理链的末端加入 TPDF 抖动，
+//! 把量化误差变为与信号无关的白噪声，可选一阶噪声整形把噪声推向人耳不敏感的高频
+//!
+//! 抖动是输出前的最后一步，淡入淡出、音量和静音渐变都在它之前作用在采样上，
+//! 量化后的采样不会再被缩放，正好落在设备的量化格点上
+
+use cpal::SampleFormat;
+use serde::{Deserialize, Serialize};
+
+use crate::settings_slot::SettingsSlot;
+
+/// 超过该位深时量化噪声已经远低于可闻阈值，不需要抖动
+const MAX_DITHER_BITS: u32 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DitherMode {
+    #[default]
+    Off,
+    /// 三角概率密度分布的抖动
+    Tpdf,
+    /// TPDF 抖动加一阶误差反馈噪声整形
+    NoiseShaped,
+}
+
+/// 整数采样格式的位深，浮点格式为空
+pub fn integer_sample_bits(format: SampleFormat) -> Option<u32> {
+    match format {
+        SampleFormat::I8 | SampleFormat::U8 => Some(8),
+        SampleFormat::I16 | SampleFormat::U16 => Some(16),
+        SampleFormat::I24 => Some(24),
+        SampleFormat::I32 | SampleFormat::U32 => Some(32),
+        SampleFormat::I64 | SampleFormat::U64 => Some(64),
+        _ => None,
+    }
+}
+
+/// 播放器线程与音频输出线程之间传递抖动设置，内容为抖动方式和设备的整数位深
+pub(crate) type DitherControl = SettingsSlot<(DitherMode, Option<u32>)>;
+
+/// 在输出线程上逐个采样做抖动和量化
+pub(crate) struct Ditherer {
+    mode: DitherMode,
+    channels: usize,
+    /// 量化步长，为零时不做处理
+    step: f32,
+    rng_state: u32,
+    /// 各声道上一个采样的量化误差，用于噪声整形
+    errors: Vec<f32>,
+    channel_index: usize,
+}
+
+impl Ditherer {
+    pub fn new(channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            mode: DitherMode::Off,
+            channels,
+            step: 0.0,
+            rng_state: 0x9E37_79B9,
+            errors: vec![0.0; channels],
+            channel_index: 0,
+        }
+    }
+
+    fn configure(&mut self, mode: DitherMode, output_bits: Option<u32>) {
+        self.mode = mode;
+        self.step = match output_bits {
+            Some(bits) if mode != DitherMode::Off && bits <= MAX_DITHER_BITS => {
+                1.0 / (1u32 << (bits - 1)) as f32
+            }
+            _ => 0.0,
+        };
+        self.errors.fill(0.0);
+    }
+
+    /// 对一个采样做抖动和量化，只在帧边界上接收新的设置
+    pub fn apply(&mut self, sample: f32, control: &DitherControl) -> f32 {
+        if self.channel_index == 0
+            && let Some((mode, output_bits)) = control.take()
+        {
+            self.configure(mode, output_bits);
+        }
+
+        let output = if self.step == 0.0 {
+            sample
+        } else {
+            let error = &mut self.errors[self.channel_index];
+            let shaped = match self.mode {
+                DitherMode::NoiseShaped => sample - *error,
+                _ => sample,
+            };
+            let noise =
+                (next_uniform(&mut self.rng_state) - next_uniform(&mut self.rng_state)) * self.step;
+            let quantized = ((shaped + noise) / self.step).round() * self.step;
+            *error = quantized - shaped;
+            quantized
+        };
+        self.channel_index = (self.channel_index + 1) % self.channels;
+        output
+    }
+}
+
+/// xorshift32 生成的 [0, 1) 均匀分布随机数，两个相减即为 TPDF 噪声
+fn next_uniform(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    (*state >> 8) as f32 / (1u32 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_or_float_output_passes_through() {
+        let control = DitherControl::default();
+        let mut ditherer = Ditherer::new(2);
+        control.publish((DitherMode::Tpdf, None));
+        assert_eq!(ditherer.apply(0.123, &control), 0.123);
+        control.publish((DitherMode::Off, Some(16)));
+        assert_eq!(ditherer.apply(0.123, &control), 0.123);
+    }
+
+    #[test]
+    fn output_lands_on_device_grid() {
+        let control = DitherControl::default();
+        let mut ditherer = Ditherer::new(2);
+        control.publish((DitherMode::NoiseShaped, Some(16)));
+        let step = 1.0 / 32768.0;
+        for i in 0..1000 {
+            let sample = (i as f32 * 0.01).sin() * 0.5;
+            let output = ditherer.apply(sample, &control);
+            let steps = output / step;
+            assert!((steps - steps.round()).abs() < 1e-3);
+            assert!((output - sample).abs() <= 3.0 * step);
+        }
+    }
+
+    #[test]
+    fn settings_change_only_on_frame_boundary() {
+        let control = DitherControl::default();
+        let mut ditherer = Ditherer::new(2);
+        ditherer.apply(0.0, &control);
+        control.publish((DitherMode::Tpdf, Some(8)));
+        // 同一帧的第二个声道仍然使用旧的设置
+        assert_eq!(ditherer.apply(0.123, &control), 0.123);
+        let output = ditherer.apply(0.123, &control);
+        assert_ne!(output, 0.123);
+    }
+}
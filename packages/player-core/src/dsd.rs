@@ -0,0 +1,28 @@
+//! DSD（DSF/DFF）音源的支持
+//!
+//! FFmpeg 的 DSD 解码器会把 1 位的 DSD 流低通滤波并抽取为 1/8 采样率的浮点 PCM
+//! （例如 DSD64 得到 352.8kHz），之后与普通音源一样经过重采样输出，不需要事先转换格式
+
+use ffmpeg_next as ffmpeg;
+
+/// 按源格式打开输出设备时 DSD 音源使用的最高 PCM 采样率，抽取后的 352.8kHz 及以上很少有设备支持
+pub const DSD_MAX_PCM_OUTPUT_RATE: u32 = 176_400;
+/// DSD 流每 8 位合成一个 PCM 采样
+const DSD_BITS_PER_PCM_SAMPLE: u32 = 8;
+
+/// 是否为 DSD 编码，DST 是 DFF 中使用的无损压缩 DSD
+pub fn is_dsd_codec(id: ffmpeg::codec::Id) -> bool {
+    matches!(
+        id,
+        ffmpeg::codec::Id::DSD_LSBF
+            | ffmpeg::codec::Id::DSD_MSBF
+            | ffmpeg::codec::Id::DSD_LSBF_PLANAR
+            | ffmpeg::codec::Id::DSD_MSBF_PLANAR
+            | ffmpeg::codec::Id::DST
+    )
+}
+
+/// 由解码输出的 PCM 采样率换算出 DSD 流原本的采样率，例如 352800 对应 DSD64 的 2822400
+pub fn dsd_sample_rate(pcm_rate: u32) -> u32 {
+    pcm_rate * DSD_BITS_PER_PCM_SAMPLE
+}
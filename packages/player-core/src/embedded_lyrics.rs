@@ -0,0 +1,389 @@
+//! 读取音频文件中内嵌的歌词
+//!
+//! FFmpeg 会把 ID3 的 USLT 帧、Vorbis 注释中的 LYRICS / UNSYNCEDLYRICS 和 MP4 的 `©lyr`
+//! 放进元数据，这里从中挑出最合适的一份。FFmpeg 不解析 ID3 的 SYLT 同步歌词帧，
+//! 所以需要直接读取文件开头的 ID3v2 标签，并把其中的同步歌词转换为 LRC 文本
+
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use ffmpeg_next as ffmpeg;
+
+use crate::AudioInfo;
+
+/// ID3v2 标签头的长度
+const ID3_HEADER_LEN: usize = 10;
+/// SYLT 帧的时间戳格式：以毫秒为单位的绝对时间
+const SYLT_TIMESTAMP_MS: u8 = 2;
+
+fn is_lyrics_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key == "lyrics"
+        || key.starts_with("lyrics-")
+        || key == "unsyncedlyrics"
+        || key == "unsynced lyrics"
+}
+
+/// 是否含有 LRC 格式的时间标签
+fn has_lrc_timestamps(lyric: &str) -> bool {
+    lyric.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with('[')
+            && line[1..].split_once(']').is_some_and(|(tag, _)| {
+                tag.contains(':') && tag.starts_with(|c: char| c.is_ascii_digit())
+            })
+    })
+}
+
+/// 从容器和音频流的元数据中读取歌词，带有时间标签的歌词优先，其次为最先读到的一份
+pub(crate) fn read_tag_lyrics(input_ctx: &ffmpeg::format::context::Input) -> String {
+    let stream = input_ctx.streams().best(ffmpeg::media::Type::Audio);
+    let mut candidates = Vec::new();
+    for metadata in
+        std::iter::once(input_ctx.metadata()).chain(stream.as_ref().map(|stream| stream.metadata()))
+    {
+        for (key, value) in metadata.iter() {
+            if is_lyrics_key(key) && !value.trim().is_empty() {
+                candidates.push(value.to_string());
+            }
+        }
+    }
+    let synced = candidates
+        .iter()
+        .position(|lyric| has_lrc_timestamps(lyric));
+    match synced {
+        Some(index) => candidates.swap_remove(index),
+        None => candidates.into_iter().next().unwrap_or_default(),
+    }
+}
+
+/// 标签中的歌词没有时间信息时，改用文件中 ID3 SYLT 帧的同步歌词（如果有）
+///
+/// FFmpeg 读不到 SYLT 帧，所以需要单独读取文件，只适用于本地文件
+pub fn attach_synced_lyrics(info: &mut AudioInfo, path: &Path) {
+    if has_lrc_timestamps(&info.lyric) {
+        return;
+    }
+    if let Some(lyric) = read_synced_lyrics(path) {
+        info.lyric = lyric;
+    }
+}
+
+/// 读取文件开头 ID3v2 标签中的 SYLT 同步歌词，并转换为 LRC 文本
+///
+/// 只支持以毫秒为时间单位的 SYLT 帧，以 MPEG 帧数为单位的帧会被忽略
+fn read_synced_lyrics(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let file_len = file.metadata().ok()?.len();
+    read_id3_sylt(BufReader::new(file), file_len)
+}
+
+/// 从 `reader` 开头的 ID3v2 标签中读取 SYLT 同步歌词，`file_len` 为数据的总长度
+///
+/// 标签头中的大小超过文件剩余的长度时视为损坏，不会按标签头分配缓冲区
+fn read_id3_sylt(mut reader: impl Read, file_len: u64) -> Option<String> {
+    let mut header = [0u8; ID3_HEADER_LEN];
+    reader.read_exact(&mut header).ok()?;
+    if &header[..3] != b"ID3" {
+        return None;
+    }
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]);
+    if u64::from(size) > file_len.saturating_sub(ID3_HEADER_LEN as u64) {
+        return None;
+    }
+    let mut tag = vec![0u8; size as usize];
+    reader.read_exact(&mut tag).ok()?;
+
+    // ID3v2.3 及更早的版本对整个标签做不同步处理，v2.4 则标记在各个帧上
+    if flags & 0x80 != 0 && version < 4 {
+        tag = remove_unsync(&tag);
+    }
+    let mut offset = 0;
+    if flags & 0x40 != 0 && version >= 3 {
+        let ext = tag.get(..4)?;
+        offset = if version == 4 {
+            syncsafe(ext) as usize
+        } else {
+            u32::from_be_bytes(ext.try_into().ok()?) as usize + 4
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    while offset + header_len <= tag.len() {
+        let frame = &tag[offset..];
+        if frame[0] == 0 {
+            break;
+        }
+        let id = &frame[..id_len];
+        let frame_size = match version {
+            2 => u32::from_be_bytes([0, frame[3], frame[4], frame[5]]) as usize,
+            3 => u32::from_be_bytes(frame[4..8].try_into().ok()?) as usize,
+            _ => syncsafe(&frame[4..8]) as usize,
+        };
+        let body = frame.get(header_len..header_len + frame_size)?;
+        offset += header_len + frame_size;
+
+        if id != b"SYLT" && id != b"SLT" {
+            continue;
+        }
+        let format_flags = if version == 4 { frame[9] } else { 0 };
+        // 压缩或加密的帧无法直接读取
+        if format_flags & 0x0c != 0 {
+            continue;
+        }
+        let mut body = body.to_vec();
+        if format_flags & 0x02 != 0 {
+            body = remove_unsync(&body);
+        }
+        // v2.4 帧可以带有 4 字节的数据长度指示
+        if format_flags & 0x01 != 0 {
+            body.drain(..4.min(body.len()));
+        }
+        if let Some(lyric) = parse_sylt(&body) {
+            return Some(lyric);
+        }
+    }
+    None
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .fold(0, |acc, &byte| (acc << 7) | (byte & 0x7f) as u32)
+}
+
+/// 去掉不同步处理插入的字节：`FF 00` 还原为 `FF`
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut prev = 0u8;
+    for &byte in data {
+        if !(prev == 0xff && byte == 0) {
+            output.push(byte);
+        }
+        prev = byte;
+    }
+    output
+}
+
+/// 解析 SYLT 帧的内容：编码、语言、时间戳格式、内容类型、描述，之后是若干个「文本 + 时间戳」
+fn parse_sylt(body: &[u8]) -> Option<String> {
+    let encoding = *body.first()?;
+    if *body.get(4)? != SYLT_TIMESTAMP_MS {
+        return None;
+    }
+    let mut rest = body.get(6..)?;
+    // 跳过内容描述
+    (_, rest) = split_text(rest, encoding)?;
+
+    let mut lines = Vec::new();
+    while !rest.is_empty() {
+        let (text, after) = split_text(rest, encoding)?;
+        let timestamp = u32::from_be_bytes(after.get(..4)?.try_into().ok()?);
+        rest = &after[4..];
+        let text = text.trim_start_matches('\n').trim_end();
+        lines.push(format!(
+            "[{:02}:{:02}.{:02}]{text}",
+            timestamp / 60_000,
+            timestamp / 1000 % 60,
+            timestamp % 1000 / 10,
+        ));
+    }
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// 按照 ID3 的文本编码读出一段以空字符结尾的文本，返回文本和其后的数据
+fn split_text(data: &[u8], encoding: u8) -> Option<(String, &[u8])> {
+    if encoding == 1 || encoding == 2 {
+        let end = data
+            .chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .map(|index| index * 2)?;
+        let text = decode_utf16(&data[..end], encoding == 2);
+        Some((text, &data[end + 2..]))
+    } else {
+        let end = data.iter().position(|&byte| byte == 0)?;
+        let bytes = &data[..end];
+        let text = if encoding == 3 {
+            String::from_utf8_lossy(bytes).into_owned()
+        } else {
+            bytes.iter().map(|&byte| byte as char).collect()
+        };
+        Some((text, &data[end + 1..]))
+    }
+}
+
+/// 解码 UTF-16 文本，有字节序标记时以标记为准，否则按 `big_endian` 解码
+fn decode_utf16(bytes: &[u8], mut big_endian: bool) -> String {
+    let mut bytes = bytes;
+    match bytes {
+        [0xfe, 0xff, rest @ ..] => {
+            big_endian = true;
+            bytes = rest;
+        }
+        [0xff, 0xfe, rest @ ..] => {
+            big_endian = false;
+            bytes = rest;
+        }
+        _ => {}
+    }
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// 以毫秒为单位、内容描述为空的 SYLT 帧内容，`lines` 为已按编码写好的文本及其时间戳
+    fn sylt_body(encoding: u8, terminator: &[u8], lines: &[(&[u8], u32)]) -> Vec<u8> {
+        let mut body = vec![encoding, b'z', b'h', b'o', SYLT_TIMESTAMP_MS, 1];
+        body.extend_from_slice(terminator);
+        for (text, timestamp) in lines {
+            body.extend_from_slice(text);
+            body.extend_from_slice(terminator);
+            body.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        body
+    }
+
+    fn syncsafe_bytes(value: usize) -> [u8; 4] {
+        let value = value as u32;
+        [
+            (value >> 21 & 0x7f) as u8,
+            (value >> 14 & 0x7f) as u8,
+            (value >> 7 & 0x7f) as u8,
+            (value & 0x7f) as u8,
+        ]
+    }
+
+    /// 只含一个 SYLT 帧的 ID3v2 标签
+    fn id3_tag(version: u8, tag_flags: u8, frame_flags: u8, body: &[u8]) -> Vec<u8> {
+        let mut frame = b"SYLT".to_vec();
+        if version == 4 {
+            frame.extend_from_slice(&syncsafe_bytes(body.len()));
+        } else {
+            frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        }
+        frame.extend_from_slice(&[0, frame_flags]);
+        frame.extend_from_slice(body);
+
+        let mut tag = b"ID3".to_vec();
+        tag.extend_from_slice(&[version, 0, tag_flags]);
+        tag.extend_from_slice(&syncsafe_bytes(frame.len()));
+        tag.extend_from_slice(&frame);
+        tag
+    }
+
+    fn read(tag: &[u8]) -> Option<String> {
+        read_id3_sylt(Cursor::new(tag), tag.len() as u64)
+    }
+
+    #[test]
+    fn reads_each_text_encoding() {
+        let latin1 = sylt_body(0, &[0], &[(b"caf\xe9", 1_500), (b"\nsecond", 61_020)]);
+        assert_eq!(
+            read(&id3_tag(3, 0, 0, &latin1)).as_deref(),
+            Some("[00:01.50]café\n[01:01.02]second")
+        );
+
+        let utf8 = sylt_body(3, &[0], &[("你好".as_bytes(), 2_000)]);
+        assert_eq!(
+            read(&id3_tag(3, 0, 0, &utf8)).as_deref(),
+            Some("[00:02.00]你好")
+        );
+
+        let utf16_bom: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("歌".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let utf16 = sylt_body(1, &[0, 0], &[(&utf16_bom, 3_000)]);
+        assert_eq!(
+            read(&id3_tag(3, 0, 0, &utf16)).as_deref(),
+            Some("[00:03.00]歌")
+        );
+
+        let utf16_be: Vec<u8> = "词".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let utf16_be = sylt_body(2, &[0, 0], &[(&utf16_be, 4_000)]);
+        assert_eq!(
+            read(&id3_tag(4, 0, 0, &utf16_be)).as_deref(),
+            Some("[00:04.00]词")
+        );
+    }
+
+    #[test]
+    fn removes_unsynchronisation() {
+        // 时间戳 0xff00 = 65280 毫秒，不同步处理后写作 FF 00 00
+        let body = sylt_body(0, &[0], &[(b"a", 0xff00)]);
+        let unsync = |data: &[u8]| {
+            let mut output = Vec::new();
+            for &byte in data {
+                output.push(byte);
+                if byte == 0xff {
+                    output.push(0);
+                }
+            }
+            output
+        };
+        let expected = Some("[01:05.28]a");
+
+        // v2.3 标记在整个标签上，帧的大小为还原后的大小
+        let tag = id3_tag(3, 0x80, 0, &body);
+        let mut unsynced = tag[..ID3_HEADER_LEN].to_vec();
+        unsynced.extend(unsync(&tag[ID3_HEADER_LEN..]));
+        let size = syncsafe_bytes(unsynced.len() - ID3_HEADER_LEN);
+        unsynced[6..10].copy_from_slice(&size);
+        assert_eq!(read(&unsynced).as_deref(), expected);
+
+        // v2.4 标记在帧上，帧的大小为不同步处理后的大小
+        assert_eq!(
+            read(&id3_tag(4, 0, 0x02, &unsync(&body))).as_deref(),
+            expected
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let body = sylt_body(0, &[0], &[(b"a", 1_000)]);
+        let tag = id3_tag(3, 0, 0, &body);
+
+        // 帧的大小超出标签
+        let mut oversized = tag.clone();
+        oversized[17] = 0x7f;
+        assert_eq!(read(&oversized), None);
+
+        // 时间戳被截断
+        let mut cut = body.clone();
+        cut.truncate(body.len() - 2);
+        assert_eq!(read(&id3_tag(3, 0, 0, &cut)), None);
+
+        // 标签头声明的大小超过文件长度时不读取
+        let mut huge = tag.clone();
+        huge[6..10].copy_from_slice(&[0x7f; 4]);
+        assert_eq!(read(&huge), None);
+        assert_eq!(read_id3_sylt(Cursor::new(&tag), tag.len() as u64 - 1), None);
+    }
+
+    #[test]
+    fn ignores_non_millisecond_timestamps() {
+        let mut body = sylt_body(0, &[0], &[(b"a", 100)]);
+        body[4] = 1;
+        assert_eq!(read(&id3_tag(3, 0, 0, &body)), None);
+    }
+}
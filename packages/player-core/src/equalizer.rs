@@ -0,0 +1,244 @@
+//! 十段图形均衡器
+//!
+//! 每个频段是一个 RBJ 峰值滤波器（Q 约为一个倍频程），作用在重采样之后的交错 f32 采样上，
+//! 因此与音源格式无关，修改参数时滤波器状态会保留，避免产生爆音
+
+use serde::{Deserialize, Serialize};
+
+pub const EQ_BAND_COUNT: usize = 10;
+/// 各频段的中心频率，单位为 Hz
+pub const EQ_BAND_FREQUENCIES: [f64; EQ_BAND_COUNT] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+pub const MIN_EQ_GAIN_DB: f64 = -12.0;
+pub const MAX_EQ_GAIN_DB: f64 = 12.0;
+
+/// 相邻频段相差一个倍频程时对应的 Q 值
+const BAND_Q: f64 = std::f64::consts::SQRT_2;
+/// 中心频率超过采样率的该比例时跳过该频段，避免滤波器在奈奎斯特频率附近失稳
+const MAX_BAND_FREQUENCY_RATIO: f64 = 0.45;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum EqualizerPreset {
+    Flat,
+    Pop,
+    Rock,
+    Jazz,
+    Classical,
+    Electronic,
+    Acoustic,
+    Vocal,
+    BassBoost,
+    TrebleBoost,
+}
+
+impl EqualizerPreset {
+    pub const ALL: [Self; 10] = [
+        Self::Flat,
+        Self::Pop,
+        Self::Rock,
+        Self::Jazz,
+        Self::Classical,
+        Self::Electronic,
+        Self::Acoustic,
+        Self::Vocal,
+        Self::BassBoost,
+        Self::TrebleBoost,
+    ];
+
+    /// 预设中各频段的增益，单位为 dB
+    pub fn band_gains_db(self) -> [f64; EQ_BAND_COUNT] {
+        match self {
+            Self::Flat => [0.0; EQ_BAND_COUNT],
+            Self::Pop => [-1.0, 1.0, 3.0, 4.0, 3.0, 0.0, -1.0, -1.0, 1.0, 2.0],
+            Self::Rock => [5.0, 4.0, 2.0, -1.0, -2.0, -1.0, 2.0, 3.0, 4.0, 4.0],
+            Self::Jazz => [3.0, 2.0, 1.0, 2.0, -1.0, -1.0, 0.0, 1.0, 2.0, 3.0],
+            Self::Classical => [4.0, 3.0, 2.0, 1.0, -1.0, -1.0, 0.0, 2.0, 3.0, 4.0],
+            Self::Electronic => [5.0, 4.0, 1.0, 0.0, -2.0, 1.0, 0.0, 1.0, 4.0, 5.0],
+            Self::Acoustic => [4.0, 3.0, 2.0, 1.0, 1.0, 1.0, 2.0, 2.0, 3.0, 2.0],
+            Self::Vocal => [-2.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0],
+            Self::BassBoost => [6.0, 5.0, 4.0, 2.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            Self::TrebleBoost => [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 5.0, 6.0],
+        }
+    }
+}
+
+/// 均衡器设置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EqualizerSettings {
+    pub enabled: bool,
+    /// 前级增益，用于在提升频段时预留余量，单位为 dB
+    pub preamp_db: f64,
+    /// 各频段的增益，单位为 dB，顺序与 [`EQ_BAND_FREQUENCIES`] 一致
+    pub band_gains_db: [f64; EQ_BAND_COUNT],
+    /// 当前使用的预设，手动调整过任一频段后为空
+    pub preset: Option<EqualizerPreset>,
+}
+
+impl Default for EqualizerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preamp_db: 0.0,
+            band_gains_db: [0.0; EQ_BAND_COUNT],
+            preset: Some(EqualizerPreset::Flat),
+        }
+    }
+}
+
+impl EqualizerSettings {
+    pub fn apply_preset(&mut self, preset: EqualizerPreset) {
+        self.band_gains_db = preset.band_gains_db();
+        self.preset = Some(preset);
+    }
+
+    /// 设置单个频段的增益，频段序号超出范围时返回 `false`
+    pub fn set_band_gain(&mut self, band: usize, gain_db: f64) -> bool {
+        let Some(slot) = self.band_gains_db.get_mut(band) else {
+            return false;
+        };
+        *slot = clamp_gain(gain_db);
+        self.preset = None;
+        true
+    }
+
+    pub fn set_preamp(&mut self, preamp_db: f64) {
+        self.preamp_db = clamp_gain(preamp_db);
+    }
+
+    /// 把外部传入的数值限制在允许的范围内
+    pub fn sanitized(mut self) -> Self {
+        self.preamp_db = clamp_gain(self.preamp_db);
+        for gain in &mut self.band_gains_db {
+            *gain = clamp_gain(*gain);
+        }
+        self
+    }
+
+    fn is_flat(&self) -> bool {
+        self.preamp_db == 0.0 && self.band_gains_db.iter().all(|&gain| gain == 0.0)
+    }
+}
+
+fn clamp_gain(gain_db: f64) -> f64 {
+    if gain_db.is_finite() {
+        gain_db.clamp(MIN_EQ_GAIN_DB, MAX_EQ_GAIN_DB)
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BiquadCoefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl BiquadCoefficients {
+    /// RBJ Audio EQ Cookbook 中的峰值滤波器
+    fn peaking(frequency: f64, gain_db: f64, sample_rate: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let omega = std::f64::consts::TAU * frequency / sample_rate;
+        let alpha = omega.sin() / (2.0 * BAND_Q);
+        let cos_omega = omega.cos();
+
+        let a0 = 1.0 + alpha / a;
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * cos_omega / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * cos_omega / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+}
+
+/// 直接 II 型转置结构的滤波器状态
+#[derive(Debug, Clone, Copy, Default)]
+struct BiquadState {
+    z1: f64,
+    z2: f64,
+}
+
+impl BiquadState {
+    fn process(&mut self, coefficients: &BiquadCoefficients, input: f64) -> f64 {
+        let output = coefficients.b0 * input + self.z1;
+        self.z1 = coefficients.b1 * input - coefficients.a1 * output + self.z2;
+        self.z2 = coefficients.b2 * input - coefficients.a2 * output;
+        output
+    }
+}
+
+/// 应用到解码输出上的均衡器
+pub struct Equalizer {
+    sample_rate: f64,
+    channels: usize,
+    preamp: f64,
+    bands: Vec<BiquadCoefficients>,
+    /// 按 `声道 * 频段数 + 频段` 排列的滤波器状态
+    states: Vec<BiquadState>,
+}
+
+impl Equalizer {
+    /// 创建一个不做任何处理的均衡器
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate: sample_rate as f64,
+            channels: channels.max(1) as usize,
+            preamp: 1.0,
+            bands: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, settings: &EqualizerSettings) {
+        if !settings.enabled || settings.is_flat() {
+            self.preamp = 1.0;
+            self.bands.clear();
+            self.states.clear();
+            return;
+        }
+
+        let settings = settings.sanitized();
+        self.preamp = 10f64.powf(settings.preamp_db / 20.0);
+        let bands: Vec<_> = EQ_BAND_FREQUENCIES
+            .iter()
+            .zip(settings.band_gains_db)
+            .filter(|&(&frequency, gain_db)| {
+                gain_db != 0.0 && frequency < self.sample_rate * MAX_BAND_FREQUENCY_RATIO
+            })
+            .map(|(&frequency, gain_db)| {
+                BiquadCoefficients::peaking(frequency, gain_db, self.sample_rate)
+            })
+            .collect();
+
+        // 启用的频段数量变化时状态无法一一对应，只能重置
+        if bands.len() != self.bands.len() {
+            self.states = vec![BiquadState::default(); bands.len() * self.channels];
+        }
+        self.bands = bands;
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.bands.is_empty() && self.preamp == 1.0 {
+            return;
+        }
+
+        let band_count = self.bands.len();
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let states = &mut self.states[channel * band_count..(channel + 1) * band_count];
+                let mut value = *sample as f64 * self.preamp;
+                for (state, coefficients) in states.iter_mut().zip(&self.bands) {
+                    value = state.process(coefficients, value);
+                }
+                *sample = value as f32;
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! 独占输出设备，使解码出的采样不经过系统混音器的重采样和混音直接送到设备
+//!
+//! macOS 上通过 CoreAudio 的 hog mode 独占默认输出设备，输出本身仍由 cpal 完成。
+//! cpal 在 Windows 上只支持 WASAPI 共享模式，因此 Windows 上由 [`WasapiExclusiveStream`]
+//! 以独占模式直接打开默认输出设备，在自己的渲染线程中把混音器的输出写入设备缓冲。
+//! 配合按源格式输出，在音量为 100% 且关闭各项音效处理时可以做到比特精确
+
+#[cfg(target_os = "windows")]
+pub(crate) use wasapi_exclusive::WasapiExclusiveStream;
+
+/// 持有期间独占默认输出设备，drop 时归还
+#[cfg(target_os = "macos")]
+pub(crate) struct ExclusiveOutput {
+    device_id: u32,
+}
+
+#[cfg(target_os = "macos")]
+impl ExclusiveOutput {
+    pub fn acquire() -> anyhow::Result<Self> {
+        let device_id = coreaudio::default_output_device()?;
+        coreaudio::set_hog_mode(device_id, std::process::id() as i32)?;
+        let owner = coreaudio::hog_mode_owner(device_id)?;
+        if owner != std::process::id() as i32 {
+            anyhow::bail!("输出设备已被进程 {owner} 独占");
+        }
+        Ok(Self { device_id })
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for ExclusiveOutput {
+    fn drop(&mut self) {
+        if let Err(err) = coreaudio::set_hog_mode(self.device_id, -1) {
+            tracing::warn!("归还输出设备的独占失败：{err:?}");
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod coreaudio {
+    use std::{ffi::c_void, ptr};
+
+    use anyhow::bail;
+
+    const AUDIO_OBJECT_SYSTEM_OBJECT: u32 = 1;
+    const AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL: u32 = u32::from_be_bytes(*b"glob");
+    const AUDIO_OBJECT_PROPERTY_ELEMENT_MAIN: u32 = 0;
+    const AUDIO_HARDWARE_PROPERTY_DEFAULT_OUTPUT_DEVICE: u32 = u32::from_be_bytes(*b"dOut");
+    /// 值为独占设备的进程 ID，没有进程独占时为 -1
+    const AUDIO_DEVICE_PROPERTY_HOG_MODE: u32 = u32::from_be_bytes(*b"oink");
+
+    #[repr(C)]
+    struct AudioObjectPropertyAddress {
+        selector: u32,
+        scope: u32,
+        element: u32,
+    }
+
+    impl AudioObjectPropertyAddress {
+        fn global(selector: u32) -> Self {
+            Self {
+                selector,
+                scope: AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+                element: AUDIO_OBJECT_PROPERTY_ELEMENT_MAIN,
+            }
+        }
+    }
+
+    #[link(name = "CoreAudio", kind = "framework")]
+    unsafe extern "C" {
+        fn AudioObjectGetPropertyData(
+            object_id: u32,
+            address: *const AudioObjectPropertyAddress,
+            qualifier_data_size: u32,
+            qualifier_data: *const c_void,
+            data_size: *mut u32,
+            data: *mut c_void,
+        ) -> i32;
+
+        fn AudioObjectSetPropertyData(
+            object_id: u32,
+            address: *const AudioObjectPropertyAddress,
+            qualifier_data_size: u32,
+            qualifier_data: *const c_void,
+            data_size: u32,
+            data: *const c_void,
+        ) -> i32;
+    }
+
+    fn get_property<T: Copy>(object_id: u32, selector: u32, mut value: T) -> anyhow::Result<T> {
+        let address = AudioObjectPropertyAddress::global(selector);
+        let mut size = size_of::<T>() as u32;
+        // SAFETY: 地址和输出缓冲都指向栈上有效的值，缓冲大小与 T 一致
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                object_id,
+                &address,
+                0,
+                ptr::null(),
+                &mut size,
+                (&mut value as *mut T).cast(),
+            )
+        };
+        if status != 0 {
+            bail!("读取 CoreAudio 属性失败，错误码 {status}");
+        }
+        Ok(value)
+    }
+
+    pub fn default_output_device() -> anyhow::Result<u32> {
+        let device_id = get_property(
+            AUDIO_OBJECT_SYSTEM_OBJECT,
+            AUDIO_HARDWARE_PROPERTY_DEFAULT_OUTPUT_DEVICE,
+            0u32,
+        )?;
+        if device_id == 0 {
+            bail!("找不到默认输出设备");
+        }
+        Ok(device_id)
+    }
+
+    pub fn hog_mode_owner(device_id: u32) -> anyhow::Result<i32> {
+        get_property(device_id, AUDIO_DEVICE_PROPERTY_HOG_MODE, -1i32)
+    }
+
+    /// `pid` 为 -1 时释放独占
+    pub fn set_hog_mode(device_id: u32, pid: i32) -> anyhow::Result<()> {
+        let address = AudioObjectPropertyAddress::global(AUDIO_DEVICE_PROPERTY_HOG_MODE);
+        // SAFETY: 地址和输入数据都指向栈上有效的值，数据大小与 pid_t 一致
+        let status = unsafe {
+            AudioObjectSetPropertyData(
+                device_id,
+                &address,
+                0,
+                ptr::null(),
+                size_of::<i32>() as u32,
+                (&pid as *const i32).cast(),
+            )
+        };
+        if status != 0 {
+            bail!("设置输出设备独占失败，错误码 {status}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod wasapi_exclusive {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+            mpsc,
+        },
+        thread::{self, JoinHandle},
+    };
+
+    use anyhow::{Context, anyhow};
+    use cpal::SampleFormat;
+    use rodio::mixer::{Mixer, MixerSource};
+    use wasapi::{
+        AudioClient, AudioRenderClient, Direction, Handle, SampleType, ShareMode, WaveFormat,
+    };
+
+    /// 等待设备请求数据的最长时间，超时说明设备已经失效
+    const EVENT_TIMEOUT_MS: u32 = 1000;
+
+    fn wasapi_error(err: impl std::fmt::Display) -> anyhow::Error {
+        anyhow!("{err}")
+    }
+
+    /// 以 WASAPI 独占模式打开的默认输出设备，drop 时停止输出并归还设备
+    pub(crate) struct WasapiExclusiveStream {
+        mixer: Mixer,
+        sample_rate: u32,
+        channels: u16,
+        sample_format: SampleFormat,
+        stopping: Arc<AtomicBool>,
+        render_thread: Option<JoinHandle<()>>,
+    }
+
+    impl WasapiExclusiveStream {
+        /// 以给定的格式独占默认输出设备，设备不支持该格式或已被其他程序独占时返回错误
+        pub fn open(
+            sample_rate: u32,
+            channels: u16,
+            sample_format: SampleFormat,
+        ) -> anyhow::Result<Self> {
+            let (mixer, source) = rodio::mixer::mixer(channels, sample_rate);
+            let stopping = Arc::new(AtomicBool::new(false));
+            let (init_tx, init_rx) = mpsc::sync_channel(1);
+            // WASAPI 的对象不能跨线程使用，打开设备和写入数据都在渲染线程中进行
+            let render_thread = {
+                let stopping = stopping.clone();
+                thread::Builder::new()
+                    .name("wasapi-exclusive".to_string())
+                    .spawn(move || {
+                        let device =
+                            match ExclusiveDevice::open(sample_rate, channels, sample_format) {
+                                Ok(device) => {
+                                    let _ = init_tx.send(Ok(()));
+                                    device
+                                }
+                                Err(err) => {
+                                    let _ = init_tx.send(Err(err));
+                                    return;
+                                }
+                            };
+                        if let Err(err) = device.render(source, &stopping) {
+                            tracing::error!("独占输出中断：{err:?}");
+                        }
+                    })?
+            };
+            let init = init_rx.recv().context("独占输出的渲染线程意外退出");
+            if let Err(err) = init.and_then(|result| result) {
+                let _ = render_thread.join();
+                return Err(err);
+            }
+            Ok(Self {
+                mixer,
+                sample_rate,
+                channels,
+                sample_format,
+                stopping,
+                render_thread: Some(render_thread),
+            })
+        }
+
+        pub fn mixer(&self) -> &Mixer {
+            &self.mixer
+        }
+
+        pub fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        pub fn channels(&self) -> u16 {
+            self.channels
+        }
+
+        pub fn sample_format(&self) -> SampleFormat {
+            self.sample_format
+        }
+    }
+
+    impl Drop for WasapiExclusiveStream {
+        fn drop(&mut self) {
+            self.stopping.store(true, Ordering::Release);
+            if let Some(thread) = self.render_thread.take()
+                && thread.join().is_err()
+            {
+                tracing::error!("独占输出的渲染线程 panic");
+            }
+        }
+    }
+
+    struct ExclusiveDevice {
+        client: AudioClient,
+        render_client: AudioRenderClient,
+        event: Handle,
+        channels: usize,
+        block_align: usize,
+        sample_format: SampleFormat,
+    }
+
+    impl ExclusiveDevice {
+        fn open(
+            sample_rate: u32,
+            channels: u16,
+            sample_format: SampleFormat,
+        ) -> anyhow::Result<Self> {
+            // 当前线程已经以其他方式初始化过 COM 时会返回错误，不影响后续调用
+            let _ = wasapi::initialize_mta();
+            let device = wasapi::get_default_device(&Direction::Render).map_err(wasapi_error)?;
+            let mut client = device.get_iaudioclient().map_err(wasapi_error)?;
+            let (bits, sample_type) = match sample_format {
+                SampleFormat::I16 => (16, SampleType::Int),
+                SampleFormat::I32 => (32, SampleType::Int),
+                _ => (32, SampleType::Float),
+            };
+            let format = WaveFormat::new(
+                bits,
+                bits,
+                &sample_type,
+                sample_rate as usize,
+                channels as usize,
+                None,
+            );
+            let (_, min_period) = client.get_periods().map_err(wasapi_error)?;
+            client
+                .initialize_client(
+                    &format,
+                    min_period,
+                    &Direction::Render,
+                    &ShareMode::Exclusive,
+                    false,
+                )
+                .map_err(wasapi_error)
+                .with_context(|| {
+                    format!(
+                        "输出设备不支持以 {sample_rate}Hz、{channels} 声道、{sample_format:?} 独占输出，或已被其他程序独占"
+                    )
+                })?;
+            let event = client.set_get_eventhandle().map_err(wasapi_error)?;
+            let render_client = client.get_audiorenderclient().map_err(wasapi_error)?;
+            Ok(Self {
+                client,
+                render_client,
+                event,
+                channels: channels as usize,
+                block_align: format.get_blockalign() as usize,
+                sample_format,
+            })
+        }
+
+        /// 设备每次请求数据时从混音器取出一整块采样写入，直到 `stopping` 被置为真
+        fn render(self, mut source: MixerSource, stopping: &AtomicBool) -> anyhow::Result<()> {
+            let mut buffer = Vec::new();
+            // 独占模式下开始输出前需要先填满一块缓冲
+            self.write_buffer(&mut source, &mut buffer)?;
+            self.client.start_stream().map_err(wasapi_error)?;
+            while !stopping.load(Ordering::Acquire) {
+                self.event
+                    .wait_for_event(EVENT_TIMEOUT_MS)
+                    .map_err(wasapi_error)
+                    .context("等待输出设备请求数据超时")?;
+                self.write_buffer(&mut source, &mut buffer)?;
+            }
+            self.client.stop_stream().map_err(wasapi_error)
+        }
+
+        fn write_buffer(
+            &self,
+            source: &mut MixerSource,
+            buffer: &mut Vec<u8>,
+        ) -> anyhow::Result<()> {
+            let frames = self
+                .client
+                .get_available_space_in_frames()
+                .map_err(wasapi_error)? as usize;
+            buffer.clear();
+            for _ in 0..frames * self.channels {
+                // 混音器中没有正在播放的声音时输出静音
+                let sample = source.next().unwrap_or(0.0);
+                encode_sample(sample, self.sample_format, buffer);
+            }
+            self.render_client
+                .write_to_device(frames, self.block_align, buffer, None)
+                .map_err(wasapi_error)
+        }
+    }
+
+    /// 按设备的采样格式写入一个采样，整数格式的换算与 FFmpeg 解码为浮点时互逆，保证比特精确
+    fn encode_sample(sample: f32, format: SampleFormat, buffer: &mut Vec<u8>) {
+        match format {
+            SampleFormat::I16 => {
+                let value = (sample as f64 * 32768.0)
+                    .round()
+                    .clamp(i16::MIN as f64, i16::MAX as f64);
+                buffer.extend_from_slice(&(value as i16).to_le_bytes());
+            }
+            SampleFormat::I32 => {
+                let value = (sample as f64 * 2147483648.0)
+                    .round()
+                    .clamp(i32::MIN as f64, i32::MAX as f64);
+                buffer.extend_from_slice(&(value as i32).to_le_bytes());
+            }
+            _ => buffer.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! 将本地歌曲解码后的输出导出为 WAV/FLAC 文件
+//!
+//! 导出使用独立的解码器离线运行，不会占用播放的输出设备，也不会影响正在进行的播放。
+//! 导出的采样率和声道数与播放输出一致，并经过与播放时相同的均衡器、响度均衡、
+//! 声道平衡、限幅器和音量处理。WAV 边解码边写入，不会把整首歌曲放在内存中；
+//! FLAC 编码器会先在内存中生成完整的文件再一次性写入
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use flacenc::{
+    component::BitRepr,
+    error::{SourceError, Verify},
+    source::{Fill, Source as FlacSource},
+};
+use parking_lot::RwLock;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    balance::BalanceOptions,
+    equalizer::EqualizerSettings,
+    ffmpeg_decoder::{DownmixOptions, FFmpegDecoder},
+    fft_player::FFTPlayer,
+    limiter::LimiterOptions,
+    loudness::{LoudnessOptions, LoudnessTags},
+};
+
+/// FLAC 导出使用的位深
+const FLAC_BITS_PER_SAMPLE: usize = 24;
+/// 每处理这么多秒的音频汇报一次进度
+const PROGRESS_INTERVAL_SECS: u64 = 1;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum AudioExportFormat {
+    /// 32 位浮点 PCM 的 WAV 文件
+    Wav,
+    /// 24 位的 FLAC 文件
+    Flac,
+}
+
+pub(crate) struct ExportOptions {
+    pub file_path: String,
+    pub output_path: String,
+    pub format: AudioExportFormat,
+    pub start_position: Duration,
+    /// 为空时导出到歌曲结尾
+    pub duration: Option<Duration>,
+    pub volume: f32,
+    pub target_channels: u16,
+    pub target_sample_rate: u32,
+    pub downmix: DownmixOptions,
+    pub loudness: LoudnessOptions,
+    pub pre_amp_db: f64,
+    /// 扫描得到的响度记录，用于补上文件标签中缺少的增益
+    pub scanned_loudness: Option<LoudnessTags>,
+    pub equalizer: EqualizerSettings,
+    pub balance: BalanceOptions,
+    pub limiter: LimiterOptions,
+}
+
+/// 执行一次导出，会阻塞当前线程直到导出完成
+///
+/// `on_progress` 会以 0 到 1 之间的进度被定期调用，`cancelled` 被置为真时导出会尽快停止并返回错误
+pub(crate) fn export_audio(
+    options: ExportOptions,
+    cancelled: Arc<AtomicBool>,
+    mut on_progress: impl FnMut(f64),
+) -> anyhow::Result<()> {
+    // 导出的频谱数据不需要展示，交给一个单独的 FFTPlayer 以免干扰主播放的频谱
+    let fft_player = Arc::new(RwLock::new(FFTPlayer::new()));
+    let (decoder, handle) = FFmpegDecoder::new(
+        options.file_path,
+        fft_player,
+        options.target_channels,
+        options.target_sample_rate,
+        options.downmix,
+        Some(options.start_position),
+    )?;
+    let decoder = decoder.into_offline();
+    // 设置在解码器取出第一块采样时生效，导出从一开始就带有全部音效
+    handle.set_volume(options.volume, Duration::ZERO);
+    handle.set_loudness(
+        &options.loudness,
+        options.pre_amp_db,
+        options.scanned_loudness,
+    );
+    handle.set_equalizer(&options.equalizer);
+    handle.set_balance(&options.balance);
+    handle.set_limiter(&options.limiter);
+
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+    let total_duration = options.duration.or_else(|| {
+        decoder
+            .total_duration()
+            .map(|d| d.saturating_sub(options.start_position))
+    });
+    let total_samples = total_duration
+        .map(|d| (d.as_secs_f64() * sample_rate as f64) as u64 * channels as u64)
+        .filter(|&n| n > 0);
+    let progress_interval = (PROGRESS_INTERVAL_SECS * sample_rate as u64 * channels as u64).max(1);
+
+    let source: Box<dyn Iterator<Item = f32>> = match options.duration {
+        Some(duration) => Box::new(decoder.take_duration(duration)),
+        None => Box::new(decoder),
+    };
+    let mut written = 0u64;
+    let samples = source.map(|sample| sample.clamp(-1.0, 1.0));
+    let samples = samples.inspect(|_| {
+        written += 1;
+        if written % progress_interval == 0 {
+            if let Some(total) = total_samples {
+                on_progress((written as f64 / total as f64).min(1.0));
+            }
+        }
+    });
+    let samples = samples.take_while(|_| !cancelled.load(Ordering::Relaxed));
+
+    let output_path = Path::new(&options.output_path);
+    match options.format {
+        AudioExportFormat::Wav => write_wav(output_path, samples, channels, sample_rate)?,
+        AudioExportFormat::Flac => write_flac(output_path, samples, channels, sample_rate)?,
+    }
+
+    if cancelled.load(Ordering::Relaxed) {
+        let _ = std::fs::remove_file(output_path);
+        return Err(anyhow!("导出已取消"));
+    }
+    on_progress(1.0);
+    Ok(())
+}
+
+fn write_wav(
+    path: &Path,
+    samples: impl Iterator<Item = f32>,
+    channels: u16,
+    sample_rate: u32,
+) -> anyhow::Result<()> {
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+    const BYTES_PER_SAMPLE: u16 = 4;
+
+    let file =
+        File::create(path).with_context(|| format!("无法创建导出文件: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let block_align = channels * BYTES_PER_SAMPLE;
+
+    // 数据长度在写完所有采样后回填
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&0u32.to_le_bytes())?;
+
+    let mut data_len = 0u32;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+        data_len = data_len
+            .checked_add(BYTES_PER_SAMPLE as u32)
+            .context("导出的音频过长，超出了 WAV 文件的大小限制")?;
+    }
+
+    writer.seek(SeekFrom::Start(4))?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.seek(SeekFrom::Start(40))?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    writer.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// 从采样迭代器中按块取出并量化为整数，交给 FLAC 编码器
+struct IterSource<I> {
+    samples: I,
+    channels: usize,
+    sample_rate: usize,
+    max_value: f32,
+    block: Vec<i32>,
+}
+
+impl<I: Iterator<Item = f32>> FlacSource for IterSource<I> {
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn bits_per_sample(&self) -> usize {
+        FLAC_BITS_PER_SAMPLE
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn read_samples<F: Fill>(
+        &mut self,
+        block_size: usize,
+        dest: &mut F,
+    ) -> Result<usize, SourceError> {
+        let max_value = self.max_value;
+        self.block.clear();
+        self.block.extend(
+            self.samples
+                .by_ref()
+                .take(block_size * self.channels)
+                .map(|sample| (sample * max_value).round() as i32),
+        );
+        // 结尾不足一帧的采样会被丢弃，避免写出声道错位的数据
+        let frames = self.block.len() / self.channels;
+        self.block.truncate(frames * self.channels);
+        dest.fill_interleaved(&self.block)?;
+        Ok(frames)
+    }
+}
+
+fn write_flac(
+    path: &Path,
+    samples: impl Iterator<Item = f32>,
+    channels: u16,
+    sample_rate: u32,
+) -> anyhow::Result<()> {
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, err)| anyhow!("FLAC 编码配置无效: {err:?}"))?;
+    let source = IterSource {
+        samples,
+        channels: channels as usize,
+        sample_rate: sample_rate as usize,
+        max_value: ((1i32 << (FLAC_BITS_PER_SAMPLE - 1)) - 1) as f32,
+        block: Vec::with_capacity(config.block_size * channels as usize),
+    };
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|err| anyhow!("FLAC 编码失败: {err:?}"))?;
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|err| anyhow!("FLAC 编码失败: {err:?}"))?;
+    std::fs::write(path, sink.as_slice())
+        .with_context(|| format!("无法写入导出文件: {}", path.display()))?;
+    Ok(())
+}
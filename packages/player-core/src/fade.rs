@@ -0,0 +1,104 @@
+//! 暂停、继续、跳转和切歌时的短淡入淡出，避免波形被突然截断产生爆音
+//!
+//! 包络按采样逐帧作用在解码输出上，由播放器通过解码器句柄请求淡入或淡出
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings_slot::SettingsSlot;
+
+pub const MIN_FADE_MS: u32 = 50;
+pub const MAX_FADE_MS: u32 = 200;
+
+/// 淡入淡出设置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FadeOptions {
+    pub enabled: bool,
+    /// 淡入淡出的时长，单位为毫秒，会被限制在 50 到 200 毫秒之间
+    pub duration_ms: u32,
+}
+
+impl Default for FadeOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duration_ms: 100,
+        }
+    }
+}
+
+impl FadeOptions {
+    /// 实际使用的淡入淡出时长，未开启时为零
+    pub fn duration(&self) -> Duration {
+        if self.enabled {
+            Duration::from_millis(self.duration_ms.clamp(MIN_FADE_MS, MAX_FADE_MS) as u64)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// 播放器线程与音频输出线程之间传递淡入淡出请求，内容为目标增益和渐变时长
+pub(crate) type FadeControl = SettingsSlot<(f32, Duration)>;
+
+/// 在输出线程上逐帧计算的增益包络
+pub(crate) struct FadeEnvelope {
+    sample_rate: u32,
+    channels: usize,
+    gain: f32,
+    target: f32,
+    step: f32,
+    /// 当前采样在一帧中的位置，同一帧的各声道使用相同的增益
+    channel_index: usize,
+}
+
+impl FadeEnvelope {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels: channels.max(1) as usize,
+            gain: 1.0,
+            target: 1.0,
+            step: 0.0,
+            channel_index: 0,
+        }
+    }
+
+    /// 对一个采样应用包络，只在帧边界上接收新的请求
+    pub fn apply(&mut self, sample: f32, control: &FadeControl) -> f32 {
+        if self.channel_index == 0
+            && let Some((target, duration)) = control.take()
+        {
+            self.start(target, duration);
+        }
+
+        let output = sample * self.gain;
+        self.channel_index += 1;
+        if self.channel_index == self.channels {
+            self.channel_index = 0;
+            self.advance();
+        }
+        output
+    }
+
+    fn start(&mut self, target: f32, duration: Duration) {
+        let frames = (duration.as_secs_f64() * self.sample_rate as f64) as u64;
+        self.target = target.clamp(0.0, 1.0);
+        if frames == 0 {
+            self.gain = self.target;
+            self.step = 0.0;
+        } else {
+            self.step = (self.target - self.gain).abs() / frames as f32;
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.gain < self.target {
+            self.gain = (self.gain + self.step).min(self.target);
+        } else if self.gain > self.target {
+            self.gain = (self.gain - self.step).max(self.target);
+        }
+    }
+}
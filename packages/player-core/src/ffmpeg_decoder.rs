@@ -1,46 +1,222 @@
 use std::collections::VecDeque;
 use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+    atomic::{AtomicBool, AtomicU64, Ordering, fence},
     mpsc::{self, Receiver, Sender, SyncSender},
 };
-use std::thread::{self, JoinHandle};
+use std::thread::{self, JoinHandle, Thread};
 use std::time::Duration;
 
 use crate::{
-    audio_quality::AudioQuality, fft_player::FFTPlayer, player::AudioInfo, utils::read_audio_info,
+    audio_quality::AudioQuality,
+    balance::{BalanceOptions, ChannelBalance},
+    dither::{DitherControl, DitherMode, Ditherer},
+    equalizer::{Equalizer, EqualizerSettings},
+    fade::{FadeControl, FadeEnvelope},
+    fft_player::FFTPlayer,
+    gapless::{GaplessInfo, read_gapless_info},
+    http_source::{MediaInput, is_remote_url, open_input_cancellable},
+    limiter::{Limiter, LimiterOptions},
+    loudness::{LoudnessGain, LoudnessOptions, LoudnessTags},
+    player::AudioInfo,
+    settings_slot::SettingsSlot,
+    silence::{PendingChunk, SilenceSkipOptions, SilenceSkipper},
+    tempo::{MAX_PITCH_SEMITONES, MAX_TEMPO, MIN_PITCH_SEMITONES, MIN_TEMPO, TempoStage},
+    utils::{ensure_ffmpeg_initialized, read_audio_info},
 };
 use anyhow::Context;
 use ffmpeg_next as ffmpeg;
-use ffmpeg_next::{ChannelLayout, format};
-use parking_lot::{Condvar, Mutex, RwLock};
+use ffmpeg_next::ChannelLayout;
+use parking_lot::{Mutex, RwLock};
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
 use rodio::Source;
 use rodio::source::SeekError;
+use serde::{Deserialize, Serialize};
 use tracing::{error, warn};
 
 const FRAME_BUFFER_CAPACITY: usize = 64;
+/// 环形缓冲已满时解码线程单次休眠的最长时间，正常情况下由播放线程取走数据时唤醒，这里只是兜底
+const BUFFER_FULL_PARK: Duration = Duration::from_millis(100);
+/// 离线模式下环形缓冲为空时读取线程单次休眠的最长时间，正常情况下由解码线程写入数据时唤醒
+const BUFFER_EMPTY_PARK: Duration = Duration::from_millis(100);
 const FFT_TARGET_RATE: u32 = 44100;
+/// 连续解码失败达到该次数后清空解码器状态，从下一个数据包重新同步
+const DECODE_ERROR_RESYNC_THRESHOLD: u32 = 8;
+/// 连续解码失败达到该次数后放弃这首歌曲
+const MAX_CONSECUTIVE_DECODE_ERRORS: u32 = 64;
+/// 跳转索引中相邻两项之间的最小间隔，单位为秒
+const SEEK_INDEX_INTERVAL_SECS: f64 = 0.5;
+/// 容器自身通常没有可靠索引、需要扫描建立跳转索引的格式
+const SEEK_INDEX_FORMATS: &[&str] = &["mp3", "ogg"];
 
 struct AudioChunk {
     player_samples: Vec<f32>,
     fft_samples: Vec<f32>,
+    /// 这一块在音频中的起始位置，单位为秒
+    start_secs: f64,
+    /// 在这一块之前被跳过的静音时长，单位为秒
+    skipped_secs: f64,
+    /// 写入时缓冲的代数，与当前代数不同的采样块已经作废
+    generation: u64,
 }
 
 struct Shared {
-    buffer: Mutex<VecDeque<AudioChunk>>,
+    /// 跳转等需要丢弃已缓冲的采样时加一，播放线程据此跳过旧的采样块
+    generation: AtomicU64,
     is_eof: AtomicBool,
-    is_stopping: AtomicBool,
-    condvar: Condvar,
+    /// 同时作为远程输入的取消标记，停止时正在进行的网络请求会尽快中断
+    is_stopping: Arc<AtomicBool>,
+    /// 解码线程，环形缓冲已满时会休眠，由播放线程取走数据或停止时唤醒
+    producer: OnceLock<Thread>,
+    /// 解码线程是否正在等待空位，播放线程据此决定是否需要唤醒
+    producer_waiting: AtomicBool,
+    /// 离线模式下正在等待数据的读取线程，解码线程写入数据或到达结尾时唤醒它
+    consumer: Mutex<Option<Thread>>,
+    gain: SettingsSlot<LoudnessGain>,
+    equalizer: SettingsSlot<EqualizerSettings>,
+    balance: SettingsSlot<BalanceOptions>,
+    limiter: SettingsSlot<LimiterOptions>,
+    dither: DitherControl,
+    fade: FadeControl,
+    /// 输出音量，包括静音时的渐变
+    volume: FadeControl,
+    /// 已经播放到、但播放器还没有计入进度的静音跳过时长，单位为微秒
+    skipped_us: AtomicU64,
+    /// 尚未通知播放器的解码错误
+    decode_errors: Mutex<Vec<DecodeErrorReport>>,
+}
+
+impl Shared {
+    /// 播放线程取走数据后调用，解码线程正在等待空位时唤醒它
+    fn wake_producer(&self) {
+        fence(Ordering::SeqCst);
+        if self.producer_waiting.load(Ordering::SeqCst)
+            && let Some(producer) = self.producer.get()
+        {
+            producer.unpark();
+        }
+    }
+
+    /// 解码线程写入数据或到达结尾后调用，离线读取线程正在等待时唤醒它
+    fn wake_consumer(&self) {
+        fence(Ordering::SeqCst);
+        if let Some(consumer) = &*self.consumer.lock() {
+            consumer.unpark();
+        }
+    }
+
+    fn stop(&self) {
+        self.is_stopping.store(true, Ordering::Release);
+        if let Some(producer) = self.producer.get() {
+            producer.unpark();
+        }
+    }
+}
+
+/// 解码过程中遇到的错误
+#[derive(Debug, Clone)]
+pub struct DecodeErrorReport {
+    pub message: String,
+    /// 是否已经跳过错误继续解码，为 `false` 时表示连续失败次数过多，已经停止解码
+    pub recovered: bool,
+}
+
+/// 统计连续的解码错误，决定跳过、重新同步还是放弃
+#[derive(Default)]
+struct DecodeErrorTracker {
+    consecutive: u32,
+}
+
+impl DecodeErrorTracker {
+    /// 记录一次错误，返回是否应当继续解码。每一轮连续错误只会向播放器报告第一次和最终放弃
+    fn record(&mut self, data: &mut DecoderInitData, shared: &Shared, message: String) -> bool {
+        self.consecutive += 1;
+        warn!("{message}，连续失败 {} 次", self.consecutive);
+
+        let recovered = self.consecutive < MAX_CONSECUTIVE_DECODE_ERRORS;
+        if self.consecutive == 1 || !recovered {
+            shared
+                .decode_errors
+                .lock()
+                .push(DecodeErrorReport { message, recovered });
+        }
+        if recovered && self.consecutive % DECODE_ERROR_RESYNC_THRESHOLD == 0 {
+            data.decoder.flush();
+        }
+        recovered
+    }
+
+    fn reset(&mut self) {
+        self.consecutive = 0;
+    }
+}
+
+/// 多声道音源缩混到较少的声道时，中置、环绕和低音炮声道的混入电平（线性幅度）
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DownmixOptions {
+    pub center_mix_level: f64,
+    pub surround_mix_level: f64,
+    pub lfe_mix_level: f64,
+    /// 按缩混矩阵的总增益归一化，避免多个声道叠加后削波
+    pub normalize: bool,
+}
+
+impl Default for DownmixOptions {
+    fn default() -> Self {
+        Self {
+            center_mix_level: std::f64::consts::FRAC_1_SQRT_2,
+            surround_mix_level: std::f64::consts::FRAC_1_SQRT_2,
+            lfe_mix_level: 0.0,
+            normalize: true,
+        }
+    }
+}
+
+impl DownmixOptions {
+    fn resampler_options(&self) -> ffmpeg::Dictionary<'static> {
+        let mut options = ffmpeg::Dictionary::new();
+        options.set("center_mix_level", &self.center_mix_level.to_string());
+        options.set("surround_mix_level", &self.surround_mix_level.to_string());
+        options.set("lfe_mix_level", &self.lfe_mix_level.to_string());
+        if self.normalize {
+            options.set("rematrix_maxval", "1.0");
+        }
+        options
+    }
+}
+
+/// 解码输出的目标格式
+struct OutputTarget {
+    channels: u16,
+    sample_rate: u32,
+    downmix: DownmixOptions,
+}
+
+/// 跳转索引中的一项，时间戳以音频流的时间基为单位
+pub struct SeekIndexEntry {
+    pos: i64,
+    timestamp: i64,
+    size: i32,
 }
 
 pub enum ControlMessage {
     Seek(Duration),
+    SeekIndex(Vec<SeekIndexEntry>),
+    SetTempo(f64),
+    SetPitch(f64),
+    SetSilenceSkip(SilenceSkipOptions),
+    /// 设置或清除 A-B 循环区间
+    SetLoop(Option<(Duration, Duration)>),
 }
 
 struct DecoderMetadata {
     total_duration: Option<Duration>,
     audio_info: AudioInfo,
     audio_quality: AudioQuality,
+    gapless_info: Option<GaplessInfo>,
+    loudness_tags: LoudnessTags,
 }
 
 pub struct FFmpegDecoder {
@@ -52,12 +228,24 @@ pub struct FFmpegDecoder {
     total_duration: Option<Duration>,
     audio_info: AudioInfo,
     audio_quality: AudioQuality,
+    gapless_info: Option<GaplessInfo>,
+    /// 播放线程一侧的环形缓冲读取端，取出采样块时不需要加锁
+    chunks: HeapCons<AudioChunk>,
     local_buffer: VecDeque<f32>,
+    gain: LoudnessGain,
+    equalizer: Equalizer,
+    balance: ChannelBalance,
+    limiter: Limiter,
+    fade: FadeEnvelope,
+    volume: FadeEnvelope,
+    dither: Ditherer,
     fft_player: Arc<RwLock<FFTPlayer>>,
+    /// 离线模式下缓冲为空时等待解码线程，而不是输出静音
+    offline: bool,
 }
 
 struct DecoderInitData {
-    input_ctx: ffmpeg::format::context::Input,
+    input_ctx: MediaInput,
     decoder: ffmpeg::decoder::Audio,
     audio_stream_index: usize,
     resampler: Option<ffmpeg::software::resampling::context::Context>,
@@ -65,44 +253,209 @@ struct DecoderInitData {
     total_duration: Option<Duration>,
     audio_info: AudioInfo,
     audio_quality: AudioQuality,
+    gapless_info: Option<GaplessInfo>,
+    loudness_tags: LoudnessTags,
+    /// 输出的有效采样帧数上限，用于裁掉尾部填充
+    output_frame_limit: Option<u64>,
+    output_rate: u32,
+    output_channels: u16,
+    tempo: f64,
+    /// 变调的半音数，正数升调，负数降调
+    pitch_semitones: f64,
+    tempo_stage: Option<TempoStage>,
+    /// A-B 循环区间的起止位置，单位为秒，输出到达终点时会精确跳回起点继续解码
+    loop_region: Option<(f64, f64)>,
+    silence_skipper: Option<SilenceSkipper>,
+    time_base: ffmpeg::Rational,
+    /// 音频流第一个采样的时间戳，单位为秒
+    start_time: f64,
+    /// 跳转后需要精确对齐的位置，早于该位置解码出的采样会被丢弃
+    seek_target: Option<f64>,
 }
 
 #[derive(Clone)]
 pub struct FFmpegDecoderHandle {
     control_tx: Sender<ControlMessage>,
+    shared: Arc<Shared>,
+    loudness_tags: LoudnessTags,
+    path: Arc<str>,
 }
 
 impl FFmpegDecoderHandle {
     pub fn seek(&self, pos: Duration) -> Result<(), mpsc::SendError<ControlMessage>> {
         self.control_tx.send(ControlMessage::Seek(pos))
     }
+
+    /// 让解码器立即结束输出，已经加入 Sink 但尚未播放的解码器会因此被直接跳过
+    pub fn stop(&self) {
+        self.shared.stop();
+    }
+
+    /// 设置播放速度，音高保持不变，范围为 0.5 到 2 倍
+    pub fn set_tempo(&self, tempo: f64) -> Result<(), mpsc::SendError<ControlMessage>> {
+        self.control_tx
+            .send(ControlMessage::SetTempo(tempo.clamp(MIN_TEMPO, MAX_TEMPO)))
+    }
+
+    /// 按半音升降调，播放速度保持不变，范围为 -12 到 12 个半音
+    pub fn set_pitch(&self, semitones: f64) -> Result<(), mpsc::SendError<ControlMessage>> {
+        self.control_tx.send(ControlMessage::SetPitch(
+            semitones.clamp(MIN_PITCH_SEMITONES, MAX_PITCH_SEMITONES),
+        ))
+    }
+
+    /// 设置静音跳过，会从缓冲中最早的位置重新处理，使开头的静音也能被跳过
+    pub fn set_silence_skip(
+        &self,
+        options: SilenceSkipOptions,
+    ) -> Result<(), mpsc::SendError<ControlMessage>> {
+        self.control_tx
+            .send(ControlMessage::SetSilenceSkip(options))
+    }
+
+    /// 取出自上次调用以来实际播放到的静音跳过时长，单位为秒
+    pub fn take_skipped_secs(&self) -> f64 {
+        self.shared.skipped_us.swap(0, Ordering::Relaxed) as f64 / 1_000_000.0
+    }
+
+    /// 取出自上次调用以来的解码错误
+    pub fn take_decode_errors(&self) -> Vec<DecodeErrorReport> {
+        std::mem::take(&mut *self.shared.decode_errors.lock())
+    }
+
+    /// 设置 A-B 循环区间，为空时取消循环
+    pub fn set_loop(
+        &self,
+        region: Option<(Duration, Duration)>,
+    ) -> Result<(), mpsc::SendError<ControlMessage>> {
+        self.control_tx.send(ControlMessage::SetLoop(region))
+    }
+
+    /// 在后台扫描整个文件的数据包，为缺少可靠索引的 VBR 文件建立跳转索引
+    ///
+    /// 扫描完成后索引会交给解码器线程登记到 FFmpeg 中，之后的跳转会直接按索引定位，
+    /// 其他格式或解码器已经停止时什么都不做
+    pub fn build_seek_index(&self) {
+        let handle = self.clone();
+        thread::spawn(
+            move || match scan_seek_index(&handle.path, &handle.shared) {
+                Ok(Some(entries)) => {
+                    let _ = handle.control_tx.send(ControlMessage::SeekIndex(entries));
+                }
+                Ok(None) => {}
+                Err(e) => warn!("建立跳转索引失败: {e:?}"),
+            },
+        );
+    }
+
+    /// 按照文件中的增益标签、给定的设置和前级增益更新响度均衡增益，从下一块解码输出开始生效
+    ///
+    /// `scanned` 为扫描得到的响度记录，用于补上文件标签中缺少的增益和峰值
+    pub fn set_loudness(
+        &self,
+        options: &LoudnessOptions,
+        pre_amp_db: f64,
+        scanned: Option<LoudnessTags>,
+    ) {
+        let tags = match scanned {
+            Some(scanned) => self.loudness_tags.or(scanned),
+            None => self.loudness_tags,
+        };
+        self.shared
+            .gain
+            .publish(LoudnessGain::new(&tags, options, pre_amp_db));
+    }
+
+    /// 解码器打开的文件路径
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 更新均衡器设置，从下一块解码输出开始生效
+    pub fn set_equalizer(&self, settings: &EqualizerSettings) {
+        self.shared.equalizer.publish(*settings);
+    }
+
+    /// 更新声道平衡设置，从下一块解码输出开始生效
+    pub fn set_balance(&self, options: &BalanceOptions) {
+        self.shared.balance.publish(*options);
+    }
+
+    /// 更新限幅器设置，从下一块解码输出开始生效
+    pub fn set_limiter(&self, options: &LimiterOptions) {
+        self.shared.limiter.publish(*options);
+    }
+
+    /// 更新抖动设置，`output_bits` 为输出设备的整数位深，浮点输出时为空
+    pub fn set_dither(&self, mode: DitherMode, output_bits: Option<u32>) {
+        self.shared.dither.publish((mode, output_bits));
+    }
+
+    /// 在给定时长内把输出音量平滑地变到 `volume`（0 到 1），时长为零时立即生效
+    ///
+    /// 音量在抖动之前作用在采样上，抖动之后的采样不会再被缩放
+    pub fn set_volume(&self, volume: f32, ramp: Duration) {
+        self.shared.volume.publish((volume, ramp));
+    }
+
+    /// 在给定时长内把输出增益平滑地变到 `target`（0 到 1），时长为零时立即生效
+    pub fn fade_to(&self, target: f32, duration: Duration) {
+        self.shared.fade.publish((target, duration));
+    }
 }
 
 impl FFmpegDecoder {
+    /// 创建解码器，`start_position` 不为空时会在开始解码前先跳转到该位置
+    ///
+    /// 音源的声道数多于 `target_channels` 时按 `downmix` 缩混，设备声道足够时则原样输出各声道
     pub fn new(
         path: String,
         fft_player: Arc<RwLock<FFTPlayer>>,
         target_channels: u16,
         target_sample_rate: u32,
+        downmix: DownmixOptions,
+        start_position: Option<Duration>,
     ) -> anyhow::Result<(Self, FFmpegDecoderHandle)> {
+        ensure_ffmpeg_initialized()?;
+
         let shared = Arc::new(Shared {
-            buffer: Mutex::new(VecDeque::with_capacity(FRAME_BUFFER_CAPACITY)),
+            generation: AtomicU64::new(0),
             is_eof: AtomicBool::new(false),
-            is_stopping: AtomicBool::new(false),
-            condvar: Condvar::new(),
+            is_stopping: Arc::default(),
+            producer: OnceLock::new(),
+            producer_waiting: AtomicBool::new(false),
+            consumer: Mutex::new(None),
+            gain: SettingsSlot::default(),
+            equalizer: SettingsSlot::default(),
+            balance: SettingsSlot::default(),
+            limiter: SettingsSlot::default(),
+            dither: DitherControl::default(),
+            fade: FadeControl::default(),
+            volume: FadeControl::default(),
+            skipped_us: AtomicU64::new(0),
+            decode_errors: Mutex::new(Vec::new()),
         });
 
         let (control_tx, control_rx) = mpsc::channel();
         let (init_tx, init_rx) = mpsc::sync_channel(1);
+        let (chunk_producer, chunk_consumer) =
+            HeapRb::<AudioChunk>::new(FRAME_BUFFER_CAPACITY).split();
 
+        let handle_path: Arc<str> = Arc::from(path.as_str());
         let decoder_thread = {
             let shared = shared.clone();
+            let target = OutputTarget {
+                channels: target_channels,
+                sample_rate: target_sample_rate,
+                downmix,
+            };
             thread::spawn(move || {
                 decoder_thread_entry(
                     path,
-                    target_channels,
-                    target_sample_rate,
+                    target,
+                    start_position,
                     shared,
+                    chunk_producer,
                     control_rx,
                     init_tx,
                 );
@@ -113,6 +466,9 @@ impl FFmpegDecoder {
 
         let handle = FFmpegDecoderHandle {
             control_tx: control_tx.clone(),
+            shared: shared.clone(),
+            loudness_tags: metadata.loudness_tags,
+            path: handle_path,
         };
 
         let decoder = Self {
@@ -124,13 +480,78 @@ impl FFmpegDecoder {
             total_duration: metadata.total_duration,
             audio_info: metadata.audio_info,
             audio_quality: metadata.audio_quality,
+            gapless_info: metadata.gapless_info,
+            chunks: chunk_consumer,
             local_buffer: VecDeque::new(),
+            gain: LoudnessGain::UNITY,
+            equalizer: Equalizer::new(target_sample_rate, target_channels),
+            balance: ChannelBalance::new(target_channels),
+            limiter: Limiter::new(target_sample_rate, target_channels),
+            fade: FadeEnvelope::new(target_sample_rate, target_channels),
+            volume: FadeEnvelope::new(target_sample_rate, target_channels),
+            dither: Ditherer::new(target_channels),
             fft_player,
+            offline: false,
         };
 
         Ok((decoder, handle))
     }
 
+    /// 切换到离线模式，用于导出等尽快读取全部采样的场合
+    ///
+    /// 播放时缓冲为空会输出静音以免阻塞音频回调，离线模式下则会等待解码线程写入数据，
+    /// 输出的采样与解码结果一一对应，不会混入静音
+    pub(crate) fn into_offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    /// 离线模式下等待解码线程写入数据或到达结尾，返回 `false` 表示解码线程已经退出
+    fn wait_for_chunk(&self) -> bool {
+        if self
+            .decoder_thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+        {
+            return false;
+        }
+        // 先登记等待再复查一次，解码线程在两者之间写入数据时也能看到登记并唤醒
+        *self.shared.consumer.lock() = Some(thread::current());
+        fence(Ordering::SeqCst);
+        if self.chunks.is_empty() && !self.shared.is_eof.load(Ordering::Acquire) {
+            thread::park_timeout(BUFFER_EMPTY_PARK);
+        }
+        *self.shared.consumer.lock() = None;
+        true
+    }
+
+    /// 取出播放器线程发布的新设置，再依次做均衡器、响度均衡、声道平衡和限幅
+    fn process_chunk(&mut self, samples: &mut [f32]) {
+        if let Some(settings) = self.shared.equalizer.take() {
+            self.equalizer.update(&settings);
+        }
+        if let Some(gain) = self.shared.gain.take() {
+            self.gain = gain;
+        }
+        if let Some(options) = self.shared.balance.take() {
+            self.balance.update(&options);
+        }
+        if let Some(options) = self.shared.limiter.take() {
+            self.limiter.update(&options);
+        }
+        self.equalizer.process(samples);
+        self.gain.apply(samples);
+        self.balance.process(samples);
+        self.limiter.process(samples);
+    }
+
+    /// 输出前逐个采样的最后几步：淡入淡出、音量，最后是抖动
+    fn finish_sample(&mut self, sample: f32) -> f32 {
+        let sample = self.fade.apply(sample, &self.shared.fade);
+        let sample = self.volume.apply(sample, &self.shared.volume);
+        self.dither.apply(sample, &self.shared.dither)
+    }
+
     pub fn audio_info(&self) -> AudioInfo {
         self.audio_info.clone()
     }
@@ -138,17 +559,24 @@ impl FFmpegDecoder {
     pub fn audio_quality(&self) -> AudioQuality {
         self.audio_quality.clone()
     }
+
+    /// 编码器延迟与尾部填充信息，文件中没有相关信息时为空
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.gapless_info
+    }
 }
 
 fn decoder_thread_entry(
     path: String,
-    target_channels: u16,
-    target_sample_rate: u32,
+    target: OutputTarget,
+    start_position: Option<Duration>,
     shared: Arc<Shared>,
+    chunk_producer: HeapProd<AudioChunk>,
     control_rx: Receiver<ControlMessage>,
     init_tx: SyncSender<anyhow::Result<DecoderMetadata>>,
 ) {
-    let init_result = setup_decoder_resources(&path, target_channels, target_sample_rate);
+    let _ = shared.producer.set(thread::current());
+    let init_result = setup_decoder_resources(&path, &target, shared.is_stopping.clone());
 
     let mut init_data = match init_result {
         Ok(data) => {
@@ -156,6 +584,8 @@ fn decoder_thread_entry(
                 total_duration: data.total_duration,
                 audio_info: data.audio_info.clone(),
                 audio_quality: data.audio_quality.clone(),
+                gapless_info: data.gapless_info,
+                loudness_tags: data.loudness_tags,
             };
             if init_tx.send(Ok(metadata)).is_err() {
                 return;
@@ -168,24 +598,121 @@ fn decoder_thread_entry(
         }
     };
 
-    run_decoding_loop(&mut init_data, shared, &control_rx);
+    let mut start_frame = 0;
+    if let Some(pos) = start_position {
+        if seek_input(&mut init_data, pos) {
+            start_frame = (pos.as_secs_f64() * init_data.output_rate as f64) as u64;
+        } else {
+            error!("跳转到起始位置失败");
+        }
+    }
+
+    let mut chunks = ChunkSender::new(chunk_producer, shared);
+    run_decoding_loop(&mut init_data, &mut chunks, &control_rx, start_frame);
+}
+
+/// 解码线程一侧的环形缓冲写入端
+struct ChunkSender {
+    producer: HeapProd<AudioChunk>,
+    shared: Arc<Shared>,
+    /// 已写入且未作废的采样块的起始位置，顺序与环形缓冲一致，其中已被取走的部分会在使用前裁掉
+    pending_starts: VecDeque<f64>,
+}
+
+impl ChunkSender {
+    fn new(producer: HeapProd<AudioChunk>, shared: Arc<Shared>) -> Self {
+        Self {
+            producer,
+            shared,
+            pending_starts: VecDeque::with_capacity(FRAME_BUFFER_CAPACITY),
+        }
+    }
+
+    /// 等待环形缓冲中出现空位，返回 `false` 表示解码器正在停止
+    fn wait_for_space(&mut self) -> bool {
+        while self.producer.is_full() && !self.shared.is_stopping.load(Ordering::Acquire) {
+            // 先登记等待再复查一次，播放线程在两者之间取走数据时也能看到登记并唤醒
+            self.shared.producer_waiting.store(true, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            if self.producer.is_full() && !self.shared.is_stopping.load(Ordering::Acquire) {
+                thread::park_timeout(BUFFER_FULL_PARK);
+            }
+            self.shared.producer_waiting.store(false, Ordering::Relaxed);
+        }
+        !self.shared.is_stopping.load(Ordering::Acquire)
+    }
+
+    /// 写入一个采样块，缓冲已满时等待播放线程取走数据，解码器停止时直接丢弃
+    fn send(&mut self, chunk: PendingChunk, skipped_secs: f64) {
+        if !self.wait_for_space() {
+            return;
+        }
+        let start_secs = chunk.start_secs;
+        let pushed = self.producer.try_push(AudioChunk {
+            player_samples: chunk.player_samples,
+            fft_samples: chunk.fft_samples,
+            start_secs,
+            skipped_secs,
+            generation: self.shared.generation.load(Ordering::Acquire),
+        });
+        if pushed.is_ok() {
+            self.pending_starts.push_back(start_secs);
+            self.shared.wake_consumer();
+        }
+    }
+
+    /// 还没有被播放线程取走的最早一块的起始位置
+    fn oldest_pending_start(&mut self) -> Option<f64> {
+        let unread = self.producer.occupied_len();
+        while self.pending_starts.len() > unread {
+            self.pending_starts.pop_front();
+        }
+        self.pending_starts.front().copied()
+    }
+
+    /// 作废已写入的所有采样块，播放线程会直接跳过它们
+    fn discard(&mut self) {
+        self.shared.generation.fetch_add(1, Ordering::AcqRel);
+        self.pending_starts.clear();
+    }
+}
+
+fn seek_input(data: &mut DecoderInitData, pos: Duration) -> bool {
+    let seek_ts = (pos.as_secs_f64() * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
+    if data.input_ctx.seek(seek_ts, ..).is_ok() {
+        data.decoder.flush();
+        data.seek_target = Some(pos.as_secs_f64());
+        true
+    } else {
+        false
+    }
 }
 
 fn setup_decoder_resources(
     path: &str,
-    target_channels: u16,
-    target_sample_rate: u32,
+    target: &OutputTarget,
+    cancelled: Arc<AtomicBool>,
 ) -> anyhow::Result<DecoderInitData> {
-    let mut input_ctx = format::input(&path).with_context(|| format!("打开 {path} 文件失败"))?;
-    let mut audio_info = read_audio_info(&mut input_ctx);
+    let mut input_ctx = open_input_cancellable(path, cancelled)?;
+    // 时长未知的远程输入视为直播流
+    let is_live = is_remote_url(path) && input_ctx.duration() <= 0;
+    let mut audio_info = read_audio_info(&input_ctx);
+    audio_info.is_live = is_live;
 
     let stream = input_ctx
         .streams()
         .best(ffmpeg::media::Type::Audio)
         .context("找不到音频流")?;
     let audio_stream_index = stream.index();
+    let gapless_info = read_gapless_info(&input_ctx, &stream, path);
+    let loudness_tags = LoudnessTags::read(&input_ctx, &stream);
 
     let time_base = stream.time_base();
+    let start_time = if stream.start_time() == ffmpeg::ffi::AV_NOPTS_VALUE {
+        0.0
+    } else {
+        stream.start_time() as f64 * time_base.0 as f64 / time_base.1 as f64
+    };
     let duration = stream.duration();
     if duration > 0 {
         audio_info.duration = duration as f64 * time_base.0 as f64 / time_base.1 as f64;
@@ -193,14 +720,14 @@ fn setup_decoder_resources(
 
     let decoder_ctx = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
     let decoder = decoder_ctx.decoder().audio()?;
-    let audio_quality = AudioQuality::from_ffmpeg_decoder(&decoder);
+    let audio_quality = AudioQuality::from_ffmpeg(&input_ctx, &decoder);
 
     let source_format = decoder.format();
     let source_channel_layout = decoder.channel_layout();
     let source_rate = decoder.rate();
 
     let target_format = ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar);
-    let target_channel_layout = ChannelLayout::default(target_channels as i32);
+    let target_channel_layout = ChannelLayout::default(target.channels as i32);
 
     let resampler = create_resampler(
         source_format,
@@ -208,9 +735,14 @@ fn setup_decoder_resources(
         source_rate,
         target_format,
         target_channel_layout,
-        target_sample_rate,
+        target.sample_rate,
+        target.downmix.resampler_options(),
     )?;
 
+    // 频谱只需要大致的波形，使用更短的滤波器换取更低的 CPU 占用
+    let mut fft_options = ffmpeg::Dictionary::new();
+    fft_options.set("filter_size", "8");
+    fft_options.set("phase_shift", "6");
     let fft_resampler = create_resampler(
         source_format,
         source_channel_layout,
@@ -218,8 +750,21 @@ fn setup_decoder_resources(
         ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar),
         ChannelLayout::MONO,
         FFT_TARGET_RATE,
+        fft_options,
     )?;
 
+    // FFmpeg 会依据 LAME 扩展头和 iTunSMPB 自行跳过开头的编码器延迟，
+    // 但 MP4 等容器的尾部填充不会被裁掉，所以这里按有效采样数截断输出
+    let (output_rate, output_channels) = if resampler.is_some() {
+        (target.sample_rate, target.channels)
+    } else {
+        (source_rate, decoder.channels() as u16)
+    };
+    let output_frame_limit = gapless_info
+        .and_then(|info| info.valid_frames)
+        .filter(|_| source_rate > 0)
+        .map(|frames| frames * output_rate as u64 / source_rate as u64);
+
     let total_duration = if input_ctx.duration() > 0 {
         Some(Duration::from_micros(input_ctx.duration() as u64))
     } else {
@@ -235,102 +780,405 @@ fn setup_decoder_resources(
         total_duration,
         audio_info,
         audio_quality,
+        gapless_info,
+        loudness_tags,
+        output_frame_limit,
+        output_rate,
+        output_channels,
+        tempo: 1.0,
+        pitch_semitones: 0.0,
+        tempo_stage: None,
+        loop_region: None,
+        silence_skipper: None,
+        time_base,
+        start_time,
+        seek_target: None,
     })
 }
 
 fn run_decoding_loop(
     data: &mut DecoderInitData,
-    shared: Arc<Shared>,
+    chunks: &mut ChunkSender,
     control_rx: &Receiver<ControlMessage>,
+    start_frame: u64,
 ) {
+    let shared = chunks.shared.clone();
     let mut player_scratch_buf = Vec::new();
     let mut fft_scratch_buf = Vec::new();
+    let mut emitted_frames = start_frame;
+    let mut errors = DecodeErrorTracker::default();
 
     'main_loop: loop {
         if let Ok(msg) = control_rx.try_recv() {
             match msg {
                 ControlMessage::Seek(pos) => {
-                    let seek_ts = (pos.as_secs_f64() * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
-                    if data.input_ctx.seek(seek_ts, ..).is_ok() {
-                        data.decoder.flush();
-                        let mut buffer = shared.buffer.lock();
-                        buffer.clear();
+                    if seek_input(data, pos) {
+                        emitted_frames = (pos.as_secs_f64() * data.output_rate as f64) as u64;
+                        rebuild_tempo_stage(data);
+                        reset_silence_skipper(data);
+                        chunks.discard();
                         shared.is_eof.store(false, Ordering::SeqCst);
-                        shared.condvar.notify_all();
                     } else {
                         error!("跳转失败");
                     }
                     continue 'main_loop;
                 }
+                ControlMessage::SetTempo(tempo) => {
+                    if (tempo - data.tempo).abs() < f64::EPSILON {
+                        continue 'main_loop;
+                    }
+                    data.tempo = tempo;
+                    redecode_buffered(data, chunks, &mut emitted_frames);
+                    continue 'main_loop;
+                }
+                ControlMessage::SetPitch(semitones) => {
+                    if (semitones - data.pitch_semitones).abs() < f64::EPSILON {
+                        continue 'main_loop;
+                    }
+                    data.pitch_semitones = semitones;
+                    redecode_buffered(data, chunks, &mut emitted_frames);
+                    continue 'main_loop;
+                }
+                ControlMessage::SetSilenceSkip(options) => {
+                    data.silence_skipper =
+                        SilenceSkipper::new(&options, data.output_rate, data.output_channels);
+                    redecode_buffered(data, chunks, &mut emitted_frames);
+                    continue 'main_loop;
+                }
+                ControlMessage::SetLoop(region) => {
+                    data.loop_region =
+                        region.map(|(start, end)| (start.as_secs_f64(), end.as_secs_f64()));
+                    // 缓冲中可能已经有越过新终点的采样
+                    if data.loop_region.is_some() {
+                        redecode_buffered(data, chunks, &mut emitted_frames);
+                    }
+                    continue 'main_loop;
+                }
+                ControlMessage::SeekIndex(entries) => {
+                    add_seek_index(data, &entries);
+                }
             }
         }
 
-        {
-            let mut buffer = shared.buffer.lock();
-            while buffer.len() >= FRAME_BUFFER_CAPACITY
-                && !shared.is_stopping.load(Ordering::Acquire)
-            {
-                shared.condvar.wait(&mut buffer);
-            }
-
-            if shared.is_stopping.load(Ordering::Acquire) {
-                break 'main_loop;
-            }
+        if !chunks.wait_for_space() {
+            break 'main_loop;
         }
 
         let mut decoded = ffmpeg::frame::Audio::empty();
         match data.decoder.receive_frame(&mut decoded) {
-            Ok(_) => {}
+            Ok(_) => errors.reset(),
             Err(ffmpeg::Error::Eof) => {
-                shared.is_eof.store(true, Ordering::Release);
-                shared.condvar.notify_all();
+                flush_silence_skipper(data, chunks);
+                if loop_back(data, &mut emitted_frames) {
+                    continue 'main_loop;
+                }
+                flush_tempo_stage(data, chunks);
                 break 'main_loop;
             }
             Err(ffmpeg::Error::Other {
                 errno: ffmpeg::ffi::EAGAIN,
             }) => {
-                match data.input_ctx.packets().next() {
-                    Some((stream, packet)) if stream.index() == data.audio_stream_index => {
-                        if data.decoder.send_packet(&packet).is_err() {
-                            error!("向解码器发送数据包失败");
+                let mut packet = ffmpeg::Packet::empty();
+                match packet.read(&mut data.input_ctx) {
+                    Ok(()) if packet.stream() == data.audio_stream_index => {
+                        // 损坏的数据包直接跳过，解码器会从之后的数据包继续
+                        if let Err(e) = data.decoder.send_packet(&packet)
+                            && !errors.record(data, &shared, format!("数据包解码失败: {e}"))
+                        {
                             break 'main_loop;
                         }
                     }
-                    None => {
+                    Ok(()) => {}
+                    Err(ffmpeg::Error::Eof) => {
                         if data.decoder.send_eof().is_err() {
                             error!("向解码器发送 EOF 失败");
                         }
                     }
-                    _ => {}
+                    Err(e) => {
+                        if !errors.record(data, &shared, format!("读取数据包失败: {e}")) {
+                            break 'main_loop;
+                        }
+                    }
                 }
                 continue 'main_loop;
             }
             Err(e) => {
-                error!("receive_frame 错误: {e}");
-                break 'main_loop;
+                if !errors.record(data, &shared, format!("解码失败: {e}")) {
+                    break 'main_loop;
+                }
+                continue 'main_loop;
             }
         }
         player_scratch_buf.clear();
         fft_scratch_buf.clear();
 
-        resample_frame(
+        let mut skip_secs = 0.0;
+        if let Some(target) = data.seek_target {
+            match frame_start_secs(data, &decoded) {
+                Some(start) => {
+                    let frame_secs = decoded.samples() as f64 / decoded.rate().max(1) as f64;
+                    if start + frame_secs <= target {
+                        continue 'main_loop;
+                    }
+                    skip_secs = (target - start).max(0.0);
+                    data.seek_target = None;
+                }
+                // 没有时间戳时无法对齐，只能从跳转落到的位置开始输出
+                None => data.seek_target = None,
+            }
+        }
+
+        let mut frames_written = resample_frame(
             data,
             &decoded,
             &mut player_scratch_buf,
             &mut fft_scratch_buf,
         );
 
-        let chunk = AudioChunk {
+        if skip_secs > 0.0 && frames_written > 0 {
+            let stride = player_scratch_buf.len() / frames_written;
+            let skip_frames = ((skip_secs * data.output_rate as f64) as usize).min(frames_written);
+            player_scratch_buf.drain(..skip_frames * stride);
+            frames_written -= skip_frames;
+
+            let fft_skip =
+                ((skip_secs * FFT_TARGET_RATE as f64) as usize).min(fft_scratch_buf.len());
+            fft_scratch_buf.drain(..fft_skip);
+        }
+
+        let mut reached_end = false;
+        if let Some(limit) = data.output_frame_limit
+            && frames_written > 0
+        {
+            let remaining = limit.saturating_sub(emitted_frames);
+            if (frames_written as u64) >= remaining {
+                let stride = player_scratch_buf.len() / frames_written;
+                player_scratch_buf.truncate(remaining as usize * stride);
+                reached_end = true;
+            }
+        }
+
+        let mut reached_loop_end = false;
+        if let Some((_, loop_end)) = data.loop_region
+            && frames_written > 0
+        {
+            let end_frame = (loop_end * data.output_rate as f64) as u64;
+            let remaining = end_frame.saturating_sub(emitted_frames);
+            if (frames_written as u64) >= remaining {
+                let stride = player_scratch_buf.len() / frames_written;
+                player_scratch_buf.truncate(remaining as usize * stride);
+                let fft_len = (remaining as f64 / data.output_rate.max(1) as f64
+                    * FFT_TARGET_RATE as f64) as usize;
+                fft_scratch_buf.truncate(fft_len);
+                frames_written = remaining as usize;
+                reached_loop_end = true;
+            }
+        }
+        let start_secs = emitted_frames as f64 / data.output_rate.max(1) as f64;
+        emitted_frames += frames_written as u64;
+
+        let chunk = PendingChunk {
             player_samples: std::mem::take(&mut player_scratch_buf),
             fft_samples: std::mem::take(&mut fft_scratch_buf),
+            start_secs,
         };
+        match &mut data.silence_skipper {
+            Some(skipper) => {
+                let (ready, mut skipped_secs) = skipper.push(chunk);
+                for chunk in ready {
+                    emit_chunk(data, chunks, chunk, std::mem::take(&mut skipped_secs));
+                }
+            }
+            None => emit_chunk(data, chunks, chunk, 0.0),
+        }
 
-        let mut buffer = shared.buffer.lock();
-        buffer.push_back(chunk);
-        shared.condvar.notify_one();
+        if reached_loop_end || reached_end {
+            flush_silence_skipper(data, chunks);
+        }
+        if (reached_loop_end || reached_end) && loop_back(data, &mut emitted_frames) {
+            continue 'main_loop;
+        }
+        if reached_end {
+            flush_tempo_stage(data, chunks);
+            break 'main_loop;
+        }
     }
     shared.is_eof.store(true, Ordering::Release);
-    shared.condvar.notify_all();
+    shared.wake_consumer();
+}
+
+/// 经过变速变调处理后把采样块放入缓冲
+fn emit_chunk(
+    data: &mut DecoderInitData,
+    chunks: &mut ChunkSender,
+    mut chunk: PendingChunk,
+    skipped_secs: f64,
+) {
+    if let Some(stage) = &mut data.tempo_stage
+        && let Err(e) = stage.process(&mut chunk.player_samples)
+    {
+        error!("变速变调处理失败: {e:?}");
+    }
+    chunks.send(chunk, skipped_secs);
+}
+
+/// 输出静音跳过中还在等待判断的短静音
+fn flush_silence_skipper(data: &mut DecoderInitData, chunks: &mut ChunkSender) {
+    let Some(skipper) = &mut data.silence_skipper else {
+        return;
+    };
+    for chunk in skipper.finish() {
+        emit_chunk(data, chunks, chunk, 0.0);
+    }
+}
+
+fn reset_silence_skipper(data: &mut DecoderInitData) {
+    if let Some(skipper) = &mut data.silence_skipper {
+        skipper.reset();
+    }
+}
+
+/// 设置了 A-B 循环时跳回循环起点，变速变调滤镜的状态会保留，使衔接处没有间隙
+fn loop_back(data: &mut DecoderInitData, emitted_frames: &mut u64) -> bool {
+    let Some((loop_start, _)) = data.loop_region else {
+        return false;
+    };
+    if seek_input(data, Duration::from_secs_f64(loop_start)) {
+        *emitted_frames = (loop_start * data.output_rate as f64) as u64;
+        true
+    } else {
+        error!("跳回循环起点失败，取消循环");
+        data.loop_region = None;
+        false
+    }
+}
+
+/// 丢弃按旧参数处理好的缓冲，从其中最早的位置重新解码，让新的设置尽快生效
+fn redecode_buffered(
+    data: &mut DecoderInitData,
+    chunks: &mut ChunkSender,
+    emitted_frames: &mut u64,
+) {
+    if let Some(start) = chunks.oldest_pending_start()
+        && seek_input(data, Duration::from_secs_f64(start))
+    {
+        *emitted_frames = (start * data.output_rate as f64) as u64;
+        chunks.discard();
+    }
+    rebuild_tempo_stage(data);
+    reset_silence_skipper(data);
+}
+
+fn rebuild_tempo_stage(data: &mut DecoderInitData) {
+    data.tempo_stage = if (data.tempo - 1.0).abs() < f64::EPSILON && data.pitch_semitones == 0.0 {
+        None
+    } else {
+        match TempoStage::new(
+            data.tempo,
+            data.pitch_semitones,
+            data.output_rate,
+            data.output_channels,
+        ) {
+            Ok(stage) => Some(stage),
+            Err(e) => {
+                error!("创建变速变调滤镜失败: {e:?}");
+                None
+            }
+        }
+    };
+}
+
+/// 音频结束时取出变速滤镜中剩余的采样
+fn flush_tempo_stage(data: &mut DecoderInitData, chunks: &mut ChunkSender) {
+    let Some(stage) = &mut data.tempo_stage else {
+        return;
+    };
+    let mut player_samples = Vec::new();
+    if let Err(e) = stage.flush(&mut player_samples) {
+        error!("变速变调处理失败: {e:?}");
+    }
+    if !player_samples.is_empty() {
+        chunks.send(
+            PendingChunk {
+                player_samples,
+                fft_samples: Vec::new(),
+                start_secs: 0.0,
+            },
+            0.0,
+        );
+    }
+}
+
+fn scan_seek_index(path: &str, shared: &Shared) -> anyhow::Result<Option<Vec<SeekIndexEntry>>> {
+    let mut input_ctx = open_input_cancellable(path, shared.is_stopping.clone())?;
+    let format_name = input_ctx.format().name().to_string();
+    if !SEEK_INDEX_FORMATS
+        .iter()
+        .any(|name| format_name.split(',').any(|part| part == *name))
+    {
+        return Ok(None);
+    }
+
+    let stream = input_ctx
+        .streams()
+        .best(ffmpeg::media::Type::Audio)
+        .context("找不到音频流")?;
+    let audio_stream_index = stream.index();
+    let time_base = stream.time_base();
+    let interval =
+        (SEEK_INDEX_INTERVAL_SECS * time_base.1 as f64 / time_base.0.max(1) as f64).max(1.0) as i64;
+
+    let mut entries = Vec::new();
+    let mut last_timestamp = None;
+    for (stream, packet) in input_ctx.packets() {
+        if shared.is_stopping.load(Ordering::Acquire) {
+            return Ok(None);
+        }
+        if stream.index() != audio_stream_index || packet.position() < 0 {
+            continue;
+        }
+        let Some(timestamp) = packet.pts().or_else(|| packet.dts()) else {
+            continue;
+        };
+        if last_timestamp.is_some_and(|last| timestamp - last < interval) {
+            continue;
+        }
+        last_timestamp = Some(timestamp);
+        entries.push(SeekIndexEntry {
+            pos: packet.position() as i64,
+            timestamp,
+            size: packet.size() as i32,
+        });
+    }
+    Ok(Some(entries))
+}
+
+/// 把扫描得到的跳转索引登记到音频流上，FFmpeg 的跳转会优先使用这些索引项
+fn add_seek_index(data: &mut DecoderInitData, entries: &[SeekIndexEntry]) {
+    let Some(mut stream) = data.input_ctx.stream_mut(data.audio_stream_index) else {
+        return;
+    };
+    // SAFETY: 流指针在输入上下文存活期间一直有效，索引项由 FFmpeg 复制保存
+    unsafe {
+        let stream_ptr = stream.as_mut_ptr();
+        for entry in entries {
+            ffmpeg::ffi::av_add_index_entry(
+                stream_ptr,
+                entry.pos,
+                entry.timestamp,
+                entry.size,
+                0,
+                ffmpeg::ffi::AVINDEX_KEYFRAME as i32,
+            );
+        }
+    }
+}
+
+/// 解码出的一帧相对于音频开头的起始时间，单位为秒
+fn frame_start_secs(data: &DecoderInitData, frame: &ffmpeg::frame::Audio) -> Option<f64> {
+    let timestamp = frame.timestamp().or_else(|| frame.pts())?;
+    let seconds = timestamp as f64 * data.time_base.0 as f64 / data.time_base.1 as f64;
+    Some(seconds - data.start_time)
 }
 
 fn resample_frame(
@@ -338,7 +1186,8 @@ fn resample_frame(
     decoded: &ffmpeg::frame::Audio,
     player_buf: &mut Vec<f32>,
     fft_buf: &mut Vec<f32>,
-) {
+) -> usize {
+    let frames_written;
     {
         if let Some(resampler_ctx) = &mut data.resampler {
             let target_rate = resampler_ctx.output().rate;
@@ -354,12 +1203,15 @@ fn resample_frame(
 
             if resampler_ctx.run(decoded, &mut resampled_frame).is_ok() {
                 let samples_written = resampled_frame.samples();
-                interleave_planar_frame(player_buf, &resampled_frame, samples_written);
+                interleave_frame(player_buf, &resampled_frame, samples_written);
+                frames_written = samples_written;
             } else {
                 error!("resampler.run() 失败");
+                frames_written = 0;
             }
         } else {
-            interleave_planar_frame(player_buf, decoded, decoded.samples());
+            interleave_frame(player_buf, decoded, decoded.samples());
+            frames_written = decoded.samples();
         }
     }
 
@@ -384,12 +1236,12 @@ fn resample_frame(
                 error!("fft_resampler.run() 失败");
             }
         } else {
-            let samples_written = decoded.samples();
-            if samples_written > 0 {
-                fft_buf.extend_from_slice(&decoded.plane::<f32>(0)[..samples_written]);
-            }
+            // 只有源本身就是 44.1kHz 单声道 f32 时才会走到这里
+            interleave_frame(fft_buf, decoded, decoded.samples());
         }
     }
+
+    frames_written
 }
 
 fn create_resampler(
@@ -399,18 +1251,24 @@ fn create_resampler(
     target_format: ffmpeg::format::Sample,
     target_channel_layout: ChannelLayout,
     target_rate: u32,
+    options: ffmpeg::Dictionary,
 ) -> anyhow::Result<Option<ffmpeg::software::resampling::context::Context>> {
-    if source_format != target_format
-        || source_channel_layout != target_channel_layout
-        || source_rate != target_rate
-    {
-        let resampler = ffmpeg::software::resampling::context::Context::get(
+    if !can_bypass_resampler(
+        source_format,
+        source_channel_layout,
+        source_rate,
+        target_format,
+        target_channel_layout,
+        target_rate,
+    ) {
+        let resampler = ffmpeg::software::resampling::context::Context::get_with(
             source_format,
             source_channel_layout,
             source_rate,
             target_format,
             target_channel_layout,
             target_rate,
+            options,
         )?;
         Ok(Some(resampler))
     } else {
@@ -418,7 +1276,31 @@ fn create_resampler(
     }
 }
 
-fn interleave_planar_frame(
+/// 源已经是目标采样率和声道数的 f32 采样时不需要经过 swresample，
+/// 交错格式会在复制时直接使用，声道顺序未指定的源按声道数比较
+fn can_bypass_resampler(
+    source_format: ffmpeg::format::Sample,
+    source_channel_layout: ChannelLayout,
+    source_rate: u32,
+    target_format: ffmpeg::format::Sample,
+    target_channel_layout: ChannelLayout,
+    target_rate: u32,
+) -> bool {
+    let same_format = matches!(
+        (source_format, target_format),
+        (
+            ffmpeg::format::Sample::F32(_),
+            ffmpeg::format::Sample::F32(_)
+        )
+    );
+    let same_layout = source_channel_layout == target_channel_layout
+        || (source_channel_layout.is_empty()
+            && source_channel_layout.channels() == target_channel_layout.channels());
+    same_format && same_layout && source_rate == target_rate
+}
+
+/// 把一帧 f32 采样以交错格式追加到缓冲区，平面格式逐帧交错，交错格式直接复制
+fn interleave_frame(
     sample_buffer: &mut Vec<f32>,
     frame: &ffmpeg::frame::Audio,
     samples_written: usize,
@@ -426,17 +1308,26 @@ fn interleave_planar_frame(
     if samples_written == 0 {
         return;
     }
-    let left_plane = &frame.plane::<f32>(0)[..samples_written];
+    if frame.is_packed() {
+        let len = samples_written * frame.channels() as usize * size_of::<f32>();
+        sample_buffer.extend(
+            frame.data(0)[..len]
+                .chunks_exact(size_of::<f32>())
+                .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        );
+        return;
+    }
+    let planes: Vec<&[f32]> = (0..frame.channels() as usize)
+        .map(|channel| &frame.plane::<f32>(channel)[..samples_written])
+        .collect();
 
-    if frame.channels() >= 2 {
-        let right_plane = &frame.plane::<f32>(1)[..samples_written];
-        let interleaved_samples = left_plane
-            .iter()
-            .zip(right_plane.iter())
-            .flat_map(|(&l, &r)| [l, r]);
-        sample_buffer.extend(interleaved_samples);
+    if let [mono] = planes.as_slice() {
+        sample_buffer.extend_from_slice(mono);
     } else {
-        sample_buffer.extend(left_plane.iter().cloned());
+        sample_buffer.reserve(samples_written * planes.len());
+        for index in 0..samples_written {
+            sample_buffer.extend(planes.iter().map(|plane| plane[index]));
+        }
     }
 }
 
@@ -444,35 +1335,61 @@ impl Iterator for FFmpegDecoder {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(sample) = self.local_buffer.pop_front() {
-            return Some(sample);
+        if self.shared.is_stopping.load(Ordering::Acquire) {
+            return None;
         }
 
-        let mut shared_buffer_lock = self.shared.buffer.lock();
+        if let Some(sample) = self.local_buffer.pop_front() {
+            return Some(self.finish_sample(sample));
+        }
 
-        while shared_buffer_lock.is_empty() {
-            if self.shared.is_eof.load(Ordering::Acquire)
-                || self.shared.is_stopping.load(Ordering::Acquire)
-            {
-                return None;
+        let mut chunk = loop {
+            // 结束标记在最后一块写入之后才设置，看到标记后还要再确认一次缓冲是否为空
+            let is_eof = self.shared.is_eof.load(Ordering::Acquire);
+            match self.chunks.try_pop() {
+                Some(chunk)
+                    if chunk.generation == self.shared.generation.load(Ordering::Acquire) =>
+                {
+                    break chunk;
+                }
+                Some(_) => {}
+                None if is_eof => return None,
+                None if self.offline => {
+                    // 解码线程异常退出时不会设置结束标记，取完剩余的数据后直接结束
+                    if !self.wait_for_chunk() && self.chunks.is_empty() {
+                        return None;
+                    }
+                }
+                None => {
+                    // 解码跟不上时输出一帧静音，不在音频回调中阻塞等待
+                    self.local_buffer
+                        .extend(std::iter::repeat_n(0.0, self.channels as usize));
+                    return self
+                        .local_buffer
+                        .pop_front()
+                        .map(|sample| self.finish_sample(sample));
+                }
             }
-            self.shared.condvar.wait(&mut shared_buffer_lock);
+        };
+        self.shared.wake_producer();
+        if chunk.skipped_secs > 0.0 {
+            self.shared
+                .skipped_us
+                .fetch_add((chunk.skipped_secs * 1_000_000.0) as u64, Ordering::Relaxed);
         }
 
-        let chunk = shared_buffer_lock.pop_front().unwrap();
-
-        self.shared.condvar.notify_one();
-        drop(shared_buffer_lock);
-
         if !chunk.fft_samples.is_empty() {
             if let Some(mut player) = self.fft_player.try_write() {
                 player.push_samples(&chunk.fft_samples);
             }
         }
 
+        self.process_chunk(&mut chunk.player_samples);
         self.local_buffer.extend(chunk.player_samples);
 
-        self.local_buffer.pop_front()
+        self.local_buffer
+            .pop_front()
+            .map(|sample| self.finish_sample(sample))
     }
 }
 
@@ -494,6 +1411,11 @@ impl Source for FFmpegDecoder {
     }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if self.audio_info.is_live {
+            return Err(SeekError::NotSupported {
+                underlying_source: "FFmpegDecoder",
+            });
+        }
         if self.control_tx.send(ControlMessage::Seek(pos)).is_err() {
             warn!("无法发送跳转命令，解码器线程可能已 panic");
             return Err(SeekError::NotSupported {
@@ -507,8 +1429,7 @@ impl Source for FFmpegDecoder {
 
 impl Drop for FFmpegDecoder {
     fn drop(&mut self) {
-        self.shared.is_stopping.store(true, Ordering::Release);
-        self.shared.condvar.notify_all();
+        self.shared.stop();
         if let Some(handle) = self.decoder_thread.take() {
             if let Err(e) = handle.join() {
                 error!("解码器线程 panic: {e:?}");
@@ -1,13 +1,29 @@
-use spectrum_analyzer::*;
 use std::{collections::VecDeque, time::Instant};
 
+use fft::SpectrumAnalyzer;
+
+use crate::onset::OnsetDetector;
+
+const FFT_SIZE: usize = 2048;
+const FFT_SAMPLE_RATE: u32 = 44100;
+const MAX_FFT_BANDS: usize = 256;
+
 /// 一个接收音频 PCM 数据并转换成频谱的伪播放结构
 /// 该结构会将传入的音频数据转换为单通道音频数据，然后进行频谱分析
 pub struct FFTPlayer {
     last_fft_time: Instant,
     result_buf: [f32; 2048],
+    /// 上一次的频段数据，用于平滑，频段数变化时重新开始
+    bands_buf: Vec<f32>,
     pcm_queue: VecDeque<f32>,
+    analyzer: SpectrumAnalyzer,
     freq_range: (f32, f32),
+    /// 不为 0 时频谱广播改为发送这个数量的对数频段
+    band_count: usize,
+    /// 不为空时对每一帧频谱做起音检测
+    onset_detector: Option<OnsetDetector>,
+    /// 上一次读取之后检测到的最强起音
+    pending_onset: Option<f32>,
 }
 
 // numpy.interp()
@@ -52,8 +68,13 @@ impl FFTPlayer {
         Self {
             last_fft_time: Instant::now(),
             result_buf: [0.0; 2048],
+            bands_buf: Vec::new(),
             pcm_queue: VecDeque::with_capacity(4096),
+            analyzer: SpectrumAnalyzer::new(FFT_SIZE, FFT_SAMPLE_RATE),
             freq_range: (80.0, 2000.0),
+            band_count: 0,
+            onset_detector: None,
+            pending_onset: None,
         }
     }
 
@@ -63,69 +84,113 @@ impl FFTPlayer {
 
     pub fn clear(&mut self) {
         self.pcm_queue.clear();
+        self.bands_buf.clear();
+        self.pending_onset = None;
+        if let Some(detector) = &mut self.onset_detector {
+            detector.reset();
+        }
     }
 
     pub fn set_freq_range(&mut self, start_freq: f32, end_freq: f32) {
         self.freq_range = (start_freq, end_freq);
     }
 
+    pub fn band_count(&self) -> usize {
+        self.band_count
+    }
+
+    pub fn set_band_count(&mut self, band_count: usize) {
+        self.band_count = band_count.min(MAX_FFT_BANDS);
+    }
+
+    pub fn set_onset_detection(&mut self, enabled: bool) {
+        if enabled != self.onset_detector.is_some() {
+            self.onset_detector = enabled.then(OnsetDetector::default);
+            self.pending_onset = None;
+        }
+    }
+
+    /// 取出自上次调用以来检测到的起音强度
+    pub fn take_onset(&mut self) -> Option<f32> {
+        self.pending_onset.take()
+    }
+
     pub fn push_samples(&mut self, samples: &[f32]) {
         self.pcm_queue.extend(samples);
     }
 
     pub fn read(&mut self, buf: &mut [f32]) -> bool {
-        if self.pcm_queue.len() < 2048 {
+        if self.pcm_queue.len() < FFT_SIZE {
             self.last_fft_time = Instant::now();
             return false;
         }
 
-        let (start_freq, end_freq) = self.freq_range;
-
-        let mut fft_buf = [0.0; 2048];
-        self.pcm_queue
-            .iter()
-            .take(2048)
-            .enumerate()
-            .for_each(|(i, v)| {
-                fft_buf[i] = *v;
-            });
+        let mut spectrum = [0.0; 2048];
+        if !self.analyzer.analyze(
+            self.pcm_queue.iter().copied(),
+            self.freq_range,
+            &mut spectrum,
+        ) {
+            eprintln!("FFT error: 无效的采样数据或频率范围");
+            return false;
+        }
+        self.result_buf
+            .iter_mut()
+            .zip(spectrum)
+            .for_each(|(v, s)| *v = (*v + s) / 2.0);
+        vec_interp(&self.result_buf, buf);
+        self.detect_onset();
+        self.advance_queue();
+        true
+    }
 
-        let fft_buf = windows::hamming_window(&fft_buf);
+    /// 读取 `buf.len()` 个按对数间隔划分、经过 A 计权的频段数据，
+    /// 低频段在前，可以直接用于绘制可视化效果
+    pub fn read_bands(&mut self, buf: &mut [f32]) -> bool {
+        if self.pcm_queue.len() < FFT_SIZE {
+            self.last_fft_time = Instant::now();
+            return false;
+        }
 
-        match samples_fft_to_spectrum(
-            &fft_buf,
-            44100,
-            FrequencyLimit::Range(start_freq, end_freq),
-            Some(&scaling::divide_by_N_sqrt),
-        ) {
-            Ok(spec) => {
-                let result_buf_len = self.result_buf.len() as f32;
-                let freq_min = spec.min_fr().val();
-                let freq_max = spec.max_fr().val();
-                let freq_range = freq_max - freq_min;
-                self.result_buf.iter_mut().enumerate().for_each(|(i, v)| {
-                    let freq = i as f32 / result_buf_len * freq_range + freq_min;
-                    let freq = freq.clamp(freq_min, freq_max);
-                    *v += spec.freq_val_exact(freq).val();
-                    *v /= 2.0;
+        if !self
+            .analyzer
+            .analyze_bands(self.pcm_queue.iter().copied(), self.freq_range, buf)
+        {
+            eprintln!("FFT error: 无效的采样数据或频率范围");
+            return false;
+        }
+        if self.bands_buf.len() == buf.len() {
+            self.bands_buf
+                .iter_mut()
+                .zip(buf.iter_mut())
+                .for_each(|(v, s)| {
+                    *v = (*v + *s) / 2.0;
+                    *s = *v;
                 });
-                vec_interp(&self.result_buf, buf);
-
-                let elapsed = self.last_fft_time.elapsed();
-                let elapsed_sec = elapsed.as_secs_f64();
-                self.last_fft_time = Instant::now();
-
-                let cut_len = (elapsed_sec * 44100.0) as usize;
-                for _ in 0..cut_len {
-                    self.pcm_queue.pop_front();
-                }
-                self.pcm_queue.truncate(2048 * 4);
-                true
-            }
-            Err(e) => {
-                eprintln!("FFT error: {:?}", e);
-                false
-            }
+        } else {
+            self.bands_buf = buf.to_vec();
+        }
+        self.detect_onset();
+        self.advance_queue();
+        true
+    }
+
+    fn detect_onset(&mut self) {
+        if let Some(detector) = &mut self.onset_detector
+            && let Some(strength) = detector.process(self.analyzer.magnitudes())
+        {
+            self.pending_onset = Some(self.pending_onset.map_or(strength, |s| s.max(strength)));
         }
     }
+
+    /// 按照距离上次分析经过的时间丢弃已经播放过的采样
+    fn advance_queue(&mut self) {
+        let elapsed = self.last_fft_time.elapsed();
+        let elapsed_sec = elapsed.as_secs_f64();
+        self.last_fft_time = Instant::now();
+
+        let cut_len = (elapsed_sec * FFT_SAMPLE_RATE as f64) as usize;
+        self.pcm_queue.drain(..cut_len.min(self.pcm_queue.len()));
+        self.pcm_queue.truncate(FFT_SIZE * 4);
+    }
 }
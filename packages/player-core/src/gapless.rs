@@ -0,0 +1,270 @@
+//! 无缝播放所需的编码器延迟与尾部填充信息
+//!
+//! MP3 的信息来自 Xing/Info 帧中的 LAME 扩展头，AAC 等格式的信息来自 iTunes 写入的 `iTunSMPB` 标签
+
+use std::{fs::File, io::Read};
+
+use ffmpeg_next as ffmpeg;
+
+/// 读取 LAME 扩展头时最多读取的文件头部字节数
+const HEADER_PROBE_LEN: u64 = 256 * 1024;
+
+/// 编码器在音频前后插入的静音，单位均为源采样率下的采样帧
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GaplessInfo {
+    /// 开头的编码器延迟（priming）
+    pub encoder_delay: u32,
+    /// 结尾的填充
+    pub padding: u32,
+    /// 去掉延迟与填充后的有效采样帧数，未知时为空
+    pub valid_frames: Option<u64>,
+}
+
+impl GaplessInfo {
+    /// 解析 `iTunSMPB` 标签，格式为若干个空格分隔的十六进制数：
+    /// 第二项是编码器延迟，第三项是尾部填充，第四项是有效采样数
+    pub fn from_itunsmpb(value: &str) -> Option<Self> {
+        let mut fields = value.split_whitespace().skip(1);
+        let encoder_delay = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let padding = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let valid_frames = fields
+            .next()
+            .and_then(|field| u64::from_str_radix(field, 16).ok())
+            .filter(|&frames| frames > 0);
+        Some(Self {
+            encoder_delay,
+            padding,
+            valid_frames,
+        })
+    }
+
+    /// 从文件开头的 Xing/Info 帧中读取 LAME 扩展头
+    pub fn from_lame_header(path: &str) -> Option<Self> {
+        let mut buf = Vec::new();
+        File::open(path)
+            .ok()?
+            .take(HEADER_PROBE_LEN)
+            .read_to_end(&mut buf)
+            .ok()?;
+        parse_lame_header(&buf)
+    }
+}
+
+/// 依次尝试从标签、LAME 扩展头和编解码参数中读取无缝播放信息
+pub fn read_gapless_info(
+    input_ctx: &ffmpeg::format::context::Input,
+    stream: &ffmpeg::format::stream::Stream,
+    path: &str,
+) -> Option<GaplessInfo> {
+    let itunsmpb = [stream.metadata(), input_ctx.metadata()]
+        .into_iter()
+        .find_map(|metadata| {
+            metadata
+                .iter()
+                .find(|(key, _)| key.to_ascii_lowercase().ends_with("itunsmpb"))
+                .map(|(_, value)| value.to_string())
+        });
+    if let Some(info) = itunsmpb.as_deref().and_then(GaplessInfo::from_itunsmpb) {
+        return Some(info);
+    }
+
+    if stream.parameters().id() == ffmpeg::codec::Id::MP3
+        && let Some(info) = GaplessInfo::from_lame_header(path)
+    {
+        return Some(info);
+    }
+
+    let parameters = stream.parameters();
+    // SAFETY: 参数指针来自仍然存活的流，这里只读取两个整数字段
+    let (initial_padding, trailing_padding) = unsafe {
+        let ptr = parameters.as_ptr();
+        ((*ptr).initial_padding, (*ptr).trailing_padding)
+    };
+    if initial_padding > 0 || trailing_padding > 0 {
+        return Some(GaplessInfo {
+            encoder_delay: initial_padding.max(0) as u32,
+            padding: trailing_padding.max(0) as u32,
+            valid_frames: None,
+        });
+    }
+
+    None
+}
+
+fn parse_lame_header(buf: &[u8]) -> Option<GaplessInfo> {
+    let mut offset = 0;
+    if buf.starts_with(b"ID3") && buf.len() >= 10 {
+        let size = buf[6..10]
+            .iter()
+            .fold(0usize, |acc, &byte| (acc << 7) | (byte & 0x7f) as usize);
+        let has_footer = buf[5] & 0x10 != 0;
+        offset = 10 + size + if has_footer { 10 } else { 0 };
+    }
+
+    let frame = buf.get(offset..)?;
+    let frame_start = frame
+        .windows(2)
+        .position(|pair| pair[0] == 0xff && pair[1] & 0xe0 == 0xe0)?;
+    let header = frame.get(frame_start..frame_start + 4)?;
+
+    let is_mpeg1 = (header[1] >> 3) & 0x03 == 0x03;
+    let is_mono = (header[3] >> 6) & 0x03 == 0x03;
+    let side_info_len = match (is_mpeg1, is_mono) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    };
+    let samples_per_frame: u64 = if is_mpeg1 { 1152 } else { 576 };
+
+    let xing = frame.get(frame_start + 4 + side_info_len..)?;
+    if !xing.starts_with(b"Xing") && !xing.starts_with(b"Info") {
+        return None;
+    }
+    let flags = u32::from_be_bytes(xing.get(4..8)?.try_into().ok()?);
+
+    let mut pos = 8;
+    let mut frame_count = None;
+    if flags & 0x01 != 0 {
+        frame_count = Some(u32::from_be_bytes(xing.get(pos..pos + 4)?.try_into().ok()?));
+        pos += 4;
+    }
+    if flags & 0x02 != 0 {
+        pos += 4;
+    }
+    if flags & 0x04 != 0 {
+        pos += 100;
+    }
+    if flags & 0x08 != 0 {
+        pos += 4;
+    }
+
+    // LAME 扩展头：9 字节编码器版本，之后第 21 字节起的 3 字节分别存放 12 位的延迟与填充
+    let lame = xing.get(pos..pos + 24)?;
+    if !lame[..9]
+        .iter()
+        .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+    {
+        return None;
+    }
+    let packed = lame[21..24]
+        .iter()
+        .fold(0u32, |acc, &byte| (acc << 8) | byte as u32);
+    let encoder_delay = packed >> 12;
+    let padding = packed & 0x0fff;
+
+    let valid_frames = frame_count.and_then(|count| {
+        (count as u64 * samples_per_frame).checked_sub((encoder_delay + padding) as u64)
+    });
+
+    Some(GaplessInfo {
+        encoder_delay,
+        padding,
+        valid_frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一个带有 Xing/Info 帧和 LAME 扩展头的 MP3 帧
+    fn lame_frame(header: [u8; 4], side_info_len: usize, flags: u32, frames: u32) -> Vec<u8> {
+        let mut frame = header.to_vec();
+        frame.resize(4 + side_info_len, 0);
+        frame.extend_from_slice(b"Info");
+        frame.extend_from_slice(&flags.to_be_bytes());
+        if flags & 0x01 != 0 {
+            frame.extend_from_slice(&frames.to_be_bytes());
+        }
+        if flags & 0x02 != 0 {
+            frame.extend_from_slice(&123_456u32.to_be_bytes());
+        }
+        if flags & 0x04 != 0 {
+            frame.extend_from_slice(&[0; 100]);
+        }
+        if flags & 0x08 != 0 {
+            frame.extend_from_slice(&50u32.to_be_bytes());
+        }
+        let mut lame = b"LAME3.100".to_vec();
+        lame.resize(21, 0);
+        // 延迟 576、填充 1200，各占 12 位
+        lame.extend_from_slice(&[0x24, 0x04, 0xb0]);
+        frame.extend_from_slice(&lame);
+        frame.extend_from_slice(&[0; 16]);
+        frame
+    }
+
+    #[test]
+    fn parses_itunsmpb() {
+        let info = GaplessInfo::from_itunsmpb(
+            " 00000000 00000840 000001CA 00000000003F31F6 00000000 00000000",
+        )
+        .unwrap();
+        assert_eq!(
+            info,
+            GaplessInfo {
+                encoder_delay: 2112,
+                padding: 458,
+                valid_frames: Some(0x3f31f6),
+            }
+        );
+
+        let unknown_length = GaplessInfo::from_itunsmpb(" 00000000 00000840 00000000 0").unwrap();
+        assert_eq!(unknown_length.valid_frames, None);
+        let no_length = GaplessInfo::from_itunsmpb("00000000 00000840 000001CA").unwrap();
+        assert_eq!(no_length.valid_frames, None);
+
+        assert_eq!(GaplessInfo::from_itunsmpb("00000000 00000840"), None);
+        assert_eq!(GaplessInfo::from_itunsmpb("00000000 zz 000001CA"), None);
+    }
+
+    #[test]
+    fn parses_mpeg1_stereo_lame_header() {
+        let frame = lame_frame([0xff, 0xfb, 0x90, 0x00], 32, 0x0f, 100);
+        assert_eq!(
+            parse_lame_header(&frame),
+            Some(GaplessInfo {
+                encoder_delay: 576,
+                padding: 1200,
+                valid_frames: Some(100 * 1152 - 576 - 1200),
+            })
+        );
+    }
+
+    #[test]
+    fn skips_id3_tag_and_leading_bytes() {
+        // MPEG-2 单声道，side info 为 9 字节，每帧 576 个采样
+        let frame = lame_frame([0xff, 0xf3, 0x90, 0xc0], 9, 0x01, 10);
+        let mut file = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        file.extend_from_slice(&[0xaa; 0x14]);
+        file.extend_from_slice(&[0, 0, 0]);
+        file.extend_from_slice(&frame);
+        assert_eq!(
+            parse_lame_header(&file),
+            Some(GaplessInfo {
+                encoder_delay: 576,
+                padding: 1200,
+                valid_frames: Some(10 * 576 - 576 - 1200),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_missing_or_truncated_headers() {
+        let frame = lame_frame([0xff, 0xfb, 0x90, 0x00], 32, 0x0f, 100);
+
+        let mut no_lame = frame.clone();
+        let lame_start = 4 + 32 + 8 + 4 + 4 + 100 + 4;
+        no_lame[lame_start] = 0;
+        assert_eq!(parse_lame_header(&no_lame), None);
+
+        let mut xing = frame.clone();
+        xing[36..40].copy_from_slice(b"Abcd");
+        assert_eq!(parse_lame_header(&xing), None);
+
+        assert_eq!(parse_lame_header(&frame[..frame.len() - 30]), None);
+        assert_eq!(parse_lame_header(b"ID3\x04\x00\x00\x00\x00\x7f\x7f"), None);
+        assert_eq!(parse_lame_header(&[]), None);
+    }
+}
@@ -0,0 +1,425 @@
+//! 通过 HTTP/HTTPS 播放远程音频文件
+//!
+//! 远程文件按块发起 Range 请求并缓存在内存中，读取失败或卡住时会自动重试，
+//! 再通过 FFmpeg 的自定义 IO 交给解复用器，使远程文件可以像本地文件一样跳转。
+//! 取消标记被置为真时，重试会立即停止，FFmpeg 也会通过中断回调尽快返回
+
+use std::{
+    ffi::{CString, c_int, c_void},
+    io::{self, Read, Seek, SeekFrom},
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use ffmpeg_next as ffmpeg;
+use tracing::warn;
+
+use crate::utils::ensure_ffmpeg_network_initialized;
+
+/// 每次 Range 请求预读的字节数
+const READ_AHEAD_BYTES: u64 = 1024 * 1024;
+/// 交给 FFmpeg 的 IO 缓冲区大小
+const AVIO_BUFFER_SIZE: usize = 64 * 1024;
+/// 单次请求最多重试的次数
+const MAX_RETRIES: u32 = 5;
+/// 第一次重试前等待的时间，之后每次重试等待的时间递增
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 超过该时间没有收到数据即视为卡住，中断本次请求并重试
+const STALL_TIMEOUT: Duration = Duration::from_secs(15);
+
+const SEEK_SET: c_int = 0;
+const SEEK_CUR: c_int = 1;
+const SEEK_END: c_int = 2;
+const EIO: c_int = 5;
+
+pub(crate) fn is_remote_url(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// HLS（`.m3u8`）和 DASH（`.mpd`）播放列表需要由 FFmpeg 自行请求各个分片
+fn is_adaptive_stream_url(path: &str) -> bool {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    let lower = path.to_ascii_lowercase();
+    lower.ends_with(".m3u8") || lower.ends_with(".mpd")
+}
+
+/// 一个带预读缓冲的远程文件，服务器支持 Range 请求时可以任意跳转
+pub(crate) struct HttpSource {
+    agent: ureq::Agent,
+    url: String,
+    len: Option<u64>,
+    pos: u64,
+    buffer: Vec<u8>,
+    buffer_start: u64,
+    /// 服务器不支持 Range 请求时只能从头顺序读取
+    stream: Option<Box<dyn Read + Send>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl HttpSource {
+    pub fn open(url: &str, cancelled: Arc<AtomicBool>) -> anyhow::Result<Self> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(STALL_TIMEOUT)
+            .build();
+
+        let response = with_retries(&cancelled, || {
+            agent
+                .get(url)
+                .set("Range", &format!("bytes=0-{}", READ_AHEAD_BYTES - 1))
+                .call()
+                .map_err(|err| io::Error::other(err.to_string()))
+        })
+        .with_context(|| format!("请求 {url} 失败"))?;
+
+        let mut source = Self {
+            agent,
+            url: url.to_string(),
+            len: None,
+            pos: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            stream: None,
+            cancelled,
+        };
+
+        if response.status() == 206 {
+            source.len = response
+                .header("Content-Range")
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|total| total.parse().ok());
+            response
+                .into_reader()
+                .take(READ_AHEAD_BYTES)
+                .read_to_end(&mut source.buffer)?;
+        } else {
+            warn!("{url} 不支持 Range 请求，将无法跳转");
+            source.len = response
+                .header("Content-Length")
+                .and_then(|len| len.parse().ok());
+            source.stream = Some(Box::new(response.into_reader()));
+        }
+
+        Ok(source)
+    }
+
+    fn fetch(&mut self, start: u64) -> io::Result<()> {
+        let mut end = start + READ_AHEAD_BYTES - 1;
+        if let Some(len) = self.len {
+            end = end.min(len.saturating_sub(1));
+        }
+
+        let data = with_retries(&self.cancelled, || {
+            let response = self
+                .agent
+                .get(&self.url)
+                .set("Range", &format!("bytes={start}-{end}"))
+                .call()
+                .map_err(|err| io::Error::other(err.to_string()))?;
+            if response.status() != 206 {
+                return Err(io::Error::other(format!(
+                    "Range 请求返回了状态码 {}",
+                    response.status()
+                )));
+            }
+            let mut data = Vec::new();
+            response
+                .into_reader()
+                .take(end - start + 1)
+                .read_to_end(&mut data)?;
+            if data.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "没有读到数据"));
+            }
+            Ok(data)
+        })?;
+
+        self.buffer = data;
+        self.buffer_start = start;
+        Ok(())
+    }
+}
+
+impl Read for HttpSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(stream) = &mut self.stream {
+            // 不支持 Range 时，先读完第一次请求留下的缓冲，再继续读取响应流
+            let buffered = self.buffer.len() as u64;
+            let read = if self.pos < buffered {
+                let start = self.pos as usize;
+                let count = buf.len().min(self.buffer.len() - start);
+                buf[..count].copy_from_slice(&self.buffer[start..start + count]);
+                count
+            } else {
+                stream.read(buf)?
+            };
+            self.pos += read as u64;
+            return Ok(read);
+        }
+
+        if self.len.is_some_and(|len| self.pos >= len) {
+            return Ok(0);
+        }
+
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.pos < self.buffer_start || self.pos >= buffer_end {
+            match self.fetch(self.pos) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && self.len.is_none() => {
+                    return Ok(0);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let offset = (self.pos - self.buffer_start) as usize;
+        let count = buf.len().min(self.buffer.len() - offset);
+        buf[..count].copy_from_slice(&self.buffer[offset..offset + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for HttpSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => self
+                .len
+                .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "远程文件长度未知"))?
+                .checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "跳转位置无效"))?;
+
+        // 不支持 Range 时只能在第一次请求读到的缓冲范围内跳转，
+        // 一旦开始读取后面的响应流，就再也回不到缓冲中的位置了
+        let buffered = self.buffer.len() as u64;
+        if self.stream.is_some() && target != self.pos && (target > buffered || self.pos > buffered)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "服务器不支持 Range 请求，无法跳转",
+            ));
+        }
+
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "已取消")
+}
+
+fn with_retries<T>(
+    cancelled: &AtomicBool,
+    mut request: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        if cancelled.load(Ordering::Acquire) {
+            return Err(cancelled_error());
+        }
+        match request() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < MAX_RETRIES && !cancelled.load(Ordering::Acquire) => {
+                attempt += 1;
+                warn!("网络请求失败，第 {attempt} 次重试: {err}");
+                thread::sleep(RETRY_DELAY * attempt);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// 打开的输入，远程文件会额外持有自定义 IO 的资源
+pub(crate) struct MediaInput {
+    // 字段按声明顺序析构，输入上下文必须先于自定义 IO 释放
+    input: ffmpeg::format::context::Input,
+    _io: Option<CustomIo>,
+}
+
+impl Deref for MediaInput {
+    type Target = ffmpeg::format::context::Input;
+
+    fn deref(&self) -> &Self::Target {
+        &self.input
+    }
+}
+
+impl DerefMut for MediaInput {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.input
+    }
+}
+
+/// 打开本地文件、HTTP/HTTPS 地址或 HLS/DASH 播放列表
+pub(crate) fn open_input(path: &str) -> anyhow::Result<MediaInput> {
+    open_input_cancellable(path, Arc::default())
+}
+
+/// 与 [`open_input`] 相同，`cancelled` 被置为真时远程输入的请求和读取会尽快中断
+pub(crate) fn open_input_cancellable(
+    path: &str,
+    cancelled: Arc<AtomicBool>,
+) -> anyhow::Result<MediaInput> {
+    if is_remote_url(path) && is_adaptive_stream_url(path) {
+        ensure_ffmpeg_network_initialized();
+        let input =
+            ffmpeg::format::input_with_interrupt(&path, move || cancelled.load(Ordering::Acquire))
+                .with_context(|| format!("打开 {path} 失败"))?;
+        return Ok(MediaInput { input, _io: None });
+    }
+
+    if !is_remote_url(path) {
+        let input =
+            ffmpeg::format::input(&path).with_context(|| format!("打开 {path} 文件失败"))?;
+        return Ok(MediaInput { input, _io: None });
+    }
+
+    let source = HttpSource::open(path, cancelled.clone())?;
+    let io = CustomIo::new(source, cancelled)?;
+    let url = CString::new(path)?;
+
+    // SAFETY: 上下文在打开失败时由 FFmpeg 释放，成功后交给 Input 管理；
+    // 自定义 IO 由 MediaInput 持有，并且在 Input 析构之后才会释放
+    let input = unsafe {
+        let mut ctx = ffmpeg::ffi::avformat_alloc_context();
+        if ctx.is_null() {
+            return Err(anyhow!("创建输入上下文失败"));
+        }
+        (*ctx).pb = io.avio;
+        (*ctx).flags |= ffmpeg::ffi::AVFMT_FLAG_CUSTOM_IO as c_int;
+        (*ctx).interrupt_callback = ffmpeg::ffi::AVIOInterruptCB {
+            callback: Some(interrupt),
+            opaque: Arc::as_ptr(&io.cancelled) as *mut c_void,
+        };
+
+        let ret =
+            ffmpeg::ffi::avformat_open_input(&mut ctx, url.as_ptr(), ptr::null(), ptr::null_mut());
+        if ret < 0 {
+            return Err(anyhow!("打开 {path} 失败: {}", ffmpeg::Error::from(ret)));
+        }
+        let ret = ffmpeg::ffi::avformat_find_stream_info(ctx, ptr::null_mut());
+        if ret < 0 {
+            ffmpeg::ffi::avformat_close_input(&mut ctx);
+            return Err(anyhow!(
+                "读取 {path} 的流信息失败: {}",
+                ffmpeg::Error::from(ret)
+            ));
+        }
+        ffmpeg::format::context::Input::wrap(ctx)
+    };
+
+    Ok(MediaInput {
+        input,
+        _io: Some(io),
+    })
+}
+
+/// FFmpeg 自定义 IO 上下文及其读取的远程文件
+struct CustomIo {
+    avio: *mut ffmpeg::ffi::AVIOContext,
+    source: *mut HttpSource,
+    /// 输入上下文的中断回调引用了这个标记，需要与自定义 IO 一起保持存活
+    cancelled: Arc<AtomicBool>,
+}
+
+// SAFETY: CustomIo 只在创建它的解码器线程中随输入上下文一起使用
+unsafe impl Send for CustomIo {}
+
+impl CustomIo {
+    fn new(source: HttpSource, cancelled: Arc<AtomicBool>) -> anyhow::Result<Self> {
+        let source = Box::into_raw(Box::new(source));
+        // SAFETY: 缓冲区由 av_malloc 分配，之后归 AVIOContext 所有；创建失败时在这里释放
+        unsafe {
+            let buffer = ffmpeg::ffi::av_malloc(AVIO_BUFFER_SIZE) as *mut u8;
+            if buffer.is_null() {
+                drop(Box::from_raw(source));
+                return Err(anyhow!("分配 IO 缓冲区失败"));
+            }
+            let avio = ffmpeg::ffi::avio_alloc_context(
+                buffer,
+                AVIO_BUFFER_SIZE as c_int,
+                0,
+                source as *mut c_void,
+                Some(read_packet),
+                None,
+                Some(seek),
+            );
+            if avio.is_null() {
+                ffmpeg::ffi::av_free(buffer as *mut c_void);
+                drop(Box::from_raw(source));
+                return Err(anyhow!("创建自定义 IO 失败"));
+            }
+            Ok(Self {
+                avio,
+                source,
+                cancelled,
+            })
+        }
+    }
+}
+
+impl Drop for CustomIo {
+    fn drop(&mut self) {
+        // SAFETY: 输入上下文已经释放，这里独占 AVIOContext 及其缓冲区
+        unsafe {
+            ffmpeg::ffi::av_freep(&mut (*self.avio).buffer as *mut *mut u8 as *mut c_void);
+            ffmpeg::ffi::avio_context_free(&mut self.avio);
+            drop(Box::from_raw(self.source));
+        }
+    }
+}
+
+unsafe extern "C" fn read_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    // SAFETY: opaque 是 CustomIo 持有的 HttpSource，buf 由 FFmpeg 保证至少有 buf_size 字节
+    let (source, buf) = unsafe {
+        (
+            &mut *(opaque as *mut HttpSource),
+            std::slice::from_raw_parts_mut(buf, buf_size.max(0) as usize),
+        )
+    };
+    if source.cancelled.load(Ordering::Acquire) {
+        return ffmpeg::ffi::AVERROR_EXIT;
+    }
+    match source.read(buf) {
+        Ok(0) => ffmpeg::ffi::AVERROR_EOF,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => ffmpeg::ffi::AVERROR_EXIT,
+        Ok(read) => read as c_int,
+        Err(err) => {
+            warn!("读取远程文件失败: {err}");
+            ffmpeg::ffi::AVERROR(EIO)
+        }
+    }
+}
+
+unsafe extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    // SAFETY: opaque 是 CustomIo 持有的 HttpSource
+    let source = unsafe { &mut *(opaque as *mut HttpSource) };
+    if whence & ffmpeg::ffi::AVSEEK_SIZE as c_int != 0 {
+        return source.len.map_or(-1, |len| len as i64);
+    }
+    let pos = match whence & !(ffmpeg::ffi::AVSEEK_FORCE as c_int) {
+        SEEK_SET => SeekFrom::Start(offset.max(0) as u64),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return -1,
+    };
+    source.seek(pos).map_or(-1, |pos| pos as i64)
+}
+
+unsafe extern "C" fn interrupt(opaque: *mut c_void) -> c_int {
+    // SAFETY: opaque 是 CustomIo 持有的取消标记，输入上下文释放之前一直有效
+    let cancelled = unsafe { &*(opaque as *const AtomicBool) };
+    cancelled.load(Ordering::Acquire) as c_int
+}
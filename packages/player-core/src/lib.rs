@@ -1,23 +1,67 @@
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug};
 
 use concat_string::concat_string;
 
-use self::audio_quality::AudioQuality;
 use serde::*;
 
 mod audio_quality;
+mod balance;
+mod cover;
+mod dither;
+mod dsd;
+mod embedded_lyrics;
+mod equalizer;
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+mod exclusive;
+mod export;
+mod fade;
 mod ffmpeg_decoder;
 mod fft_player;
+mod gapless;
+mod http_source;
+mod limiter;
+mod loudness;
 mod media_state;
+mod offline_decode;
+mod onset;
 mod player;
+mod queue;
+mod replaygain;
+mod settings_slot;
+mod silence;
+mod tag_writer;
+mod tags;
+mod tempo;
 pub mod utils;
+mod volume;
+mod waveform;
+pub use audio_quality::AudioQuality;
+pub use balance::BalanceOptions;
+pub use dither::DitherMode;
+pub use embedded_lyrics::attach_synced_lyrics;
+pub use equalizer::{
+    EQ_BAND_COUNT, EQ_BAND_FREQUENCIES, EqualizerPreset, EqualizerSettings, MAX_EQ_GAIN_DB,
+    MIN_EQ_GAIN_DB,
+};
+pub use export::AudioExportFormat;
+pub use fade::FadeOptions;
+pub use ffmpeg_decoder::DownmixOptions;
+pub use limiter::LimiterOptions;
+pub use loudness::{LoudnessOptions, LoudnessTags, ReplayGainMode};
 pub use player::*;
+pub use replaygain::{TrackLoudness, album_loudness, loudness_to_gain_db, scan_track_loudness};
+pub use silence::SilenceSkipOptions;
+pub use tag_writer::{TagUpdate, write_tags};
+pub use tags::{AudioTags, read_tags};
+pub use volume::{MAX_PRE_AMP_DB, volume_to_gain};
+pub use waveform::{WaveformExportFormat, WaveformPeaks, WaveformSyllable, read_waveform_peaks};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 #[non_exhaustive]
 pub enum SongData {
+    /// 本地歌曲，`file_path` 也可以是 HTTP/HTTPS 地址，此时会以流式方式读取远程文件
     #[serde(rename_all = "camelCase")]
     Local {
         file_path: String,
@@ -41,6 +85,33 @@ impl SongData {
     }
 }
 
+/// 音频文件中的章节标记，时间单位为秒
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A-B 循环区间，单位为毫秒
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopRegion {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl LoopRegion {
+    pub fn start_secs(&self) -> f64 {
+        self.start_ms as f64 / 1000.0
+    }
+
+    pub fn end_secs(&self) -> f64 {
+        self.end_ms as f64 / 1000.0
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -55,6 +126,16 @@ pub enum AudioThreadMessage {
     SeekAudio {
         position: f64,
     },
+    /// 跳转到当前歌曲的第 `index` 个章节，`offset` 为相对章节开头的秒数
+    #[serde(rename_all = "camelCase")]
+    SeekToChapter {
+        index: usize,
+        #[serde(default)]
+        offset: f64,
+    },
+    /// 获取当前歌曲的章节列表，结果通过 `Chapters` 事件返回
+    #[serde(rename_all = "camelCase")]
+    GetChapters,
     #[serde(rename_all = "camelCase")]
     JumpToSong {
         song_index: usize,
@@ -65,10 +146,37 @@ pub enum AudioThreadMessage {
     NextSong,
     #[serde(rename_all = "camelCase")]
     NextSongGapless,
+    /// 在后台提前打开一首歌曲并开始缓冲，之后切换到这首歌曲时可以立即开始播放
+    ///
+    /// 同一时间只保留一首预加载的歌曲，再次发送会替换掉之前的预加载
+    #[serde(rename_all = "camelCase")]
+    Preload {
+        song: SongData,
+    },
     #[serde(rename_all = "camelCase")]
     SetPlaylist {
         songs: Vec<SongData>,
     },
+    /// 将歌曲插入到当前歌曲之后，作为下一首播放
+    #[serde(rename_all = "camelCase")]
+    PlayNext {
+        songs: Vec<SongData>,
+    },
+    /// 将歌曲添加到播放队列末尾
+    #[serde(rename_all = "camelCase")]
+    AddToQueue {
+        songs: Vec<SongData>,
+    },
+    /// 将播放队列中的一首歌曲移动到新的位置，用于拖拽重排
+    #[serde(rename_all = "camelCase")]
+    MoveSong {
+        from_index: usize,
+        to_index: usize,
+    },
+    /// 撤销上一次插播、添加到队列或重排操作
+    #[serde(rename_all = "camelCase")]
+    UndoQueueChange,
+    /// 设置音量滑块的位置（0 到 1），按分贝曲线换算为实际增益
     #[serde(rename_all = "camelCase")]
     SetVolume {
         volume: f64,
@@ -77,6 +185,114 @@ pub enum AudioThreadMessage {
     SetVolumeRelative {
         volume: f64,
     },
+    /// 在 `fade_ms` 毫秒内把音量渐变到零，最长 2000 毫秒，为 0 时立即静音
+    ///
+    /// 静音状态在切歌后保持，期间调整音量只会记录下来，取消静音后生效
+    #[serde(rename_all = "camelCase")]
+    Mute {
+        fade_ms: u32,
+    },
+    /// 在 `fade_ms` 毫秒内把音量从零渐变回静音前的音量
+    #[serde(rename_all = "camelCase")]
+    Unmute {
+        fade_ms: u32,
+    },
+    /// 设置基于 ReplayGain / R128 标签的响度均衡，会立即应用到正在播放的歌曲
+    #[serde(rename_all = "camelCase")]
+    SetLoudnessNormalization {
+        options: LoudnessOptions,
+    },
+    /// 替换全部响度扫描记录，键为播放时使用的文件路径，会立即应用到正在播放的歌曲
+    ///
+    /// 文件本身缺少的增益和峰值标签会用这里的记录补上
+    #[serde(rename_all = "camelCase")]
+    SetScannedLoudness {
+        records: HashMap<String, LoudnessTags>,
+    },
+    /// 添加或更新一部分响度扫描记录
+    #[serde(rename_all = "camelCase")]
+    RecordScannedLoudness {
+        records: HashMap<String, LoudnessTags>,
+    },
+    /// 设置叠加到每首歌曲上的前级增益，单位为 dB，范围为 ±12 dB，会立即应用到正在播放的歌曲
+    ///
+    /// 与响度均衡的增益相加，开启防削波时同样受峰值限制
+    #[serde(rename_all = "camelCase")]
+    SetPreAmp {
+        gain_db: f64,
+    },
+    /// 按照歌曲的采样率、声道数和位深打开输出设备，避免不必要的重采样和缩混，从下一首歌曲开始生效
+    ///
+    /// 设备不支持时会继续使用当前的输出配置
+    #[serde(rename_all = "camelCase")]
+    SetSourceFormatOutput {
+        enabled: bool,
+    },
+    /// 独占输出设备，系统混音器不再对输出重采样，同时按歌曲的源格式输出，从下一首歌曲开始生效
+    ///
+    /// 只在 macOS（CoreAudio hog mode）和 Windows（WASAPI 独占模式）上提供
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[serde(rename_all = "camelCase")]
+    SetExclusiveOutput {
+        enabled: bool,
+    },
+    /// 设置十段均衡器，会立即应用到正在播放的歌曲
+    #[serde(rename_all = "camelCase")]
+    SetEqualizer {
+        settings: EqualizerSettings,
+    },
+    /// 设置输出设备为 16 位及以下整数格式时使用的抖动方式
+    #[serde(rename_all = "camelCase")]
+    SetDither {
+        mode: DitherMode,
+    },
+    /// 设置左右声道平衡与强制单声道，会立即应用到正在播放的歌曲
+    #[serde(rename_all = "camelCase")]
+    SetBalance {
+        options: BalanceOptions,
+    },
+    /// 设置输出前的限幅器，防止增益和均衡器的提升导致削波，会立即应用到正在播放的歌曲
+    #[serde(rename_all = "camelCase")]
+    SetLimiter {
+        options: LimiterOptions,
+    },
+    /// 设置暂停、继续、跳转和切歌时的淡入淡出
+    #[serde(rename_all = "camelCase")]
+    SetFadeOptions {
+        options: FadeOptions,
+    },
+    /// 设置是否自动跳过开头、结尾和曲目中间的长段静音，会立即应用到正在播放的歌曲
+    #[serde(rename_all = "camelCase")]
+    SetSilenceSkip {
+        options: SilenceSkipOptions,
+    },
+    /// 设置当前歌曲的 A-B 循环区间，`region` 为空时取消循环
+    ///
+    /// 播放到终点时会无缝跳回起点，切换歌曲或跳转到区间之外时自动取消
+    #[serde(rename_all = "camelCase")]
+    SetLoopRegion {
+        region: Option<LoopRegion>,
+    },
+    /// 设置播放速度，范围为 0.5 到 2 倍，音高保持不变，播放进度会按速度换算
+    #[serde(rename_all = "camelCase")]
+    SetPlaybackRate {
+        rate: f64,
+    },
+    /// 按半音升降调，范围为 -12 到 12 个半音，播放速度保持不变，可与变速同时使用
+    #[serde(rename_all = "camelCase")]
+    SetPitchShift {
+        semitones: f64,
+    },
+    /// 是否在后台为缺少可靠索引的 VBR 文件（MP3、Ogg）建立跳转索引，从下一首歌曲开始生效
+    #[serde(rename_all = "camelCase")]
+    SetSeekIndex {
+        enabled: bool,
+    },
+    /// 设置多声道音源缩混时各声道的混入电平，从下一首歌曲开始生效
+    #[serde(rename_all = "camelCase")]
+    SetDownmix {
+        options: DownmixOptions,
+    },
     #[serde(rename_all = "camelCase")]
     SetAudioOutput {
         name: String,
@@ -90,8 +306,60 @@ pub enum AudioThreadMessage {
         from_freq: f32,
         to_freq: f32,
     },
+    /// 让频谱广播改为发送 `count` 个按对数间隔划分、经过 A 计权的频段（`FFTBands` 事件），
+    /// 频段覆盖 `SetFFTRange` 设置的频率范围，最多 256 个，为 0 时恢复发送原始的 `FFTData`
+    #[serde(rename_all = "camelCase")]
+    SetFFTBands {
+        count: usize,
+    },
+    /// 是否对频谱做起音检测，开启后检测到节拍时发送 `Beat` 事件
+    #[serde(rename_all = "camelCase")]
+    SetBeatDetection {
+        enabled: bool,
+    },
     #[serde(rename_all = "camelCase")]
     SyncStatus,
+    /// 从指定位置开始试听一首歌曲，播放指定时长后自动停止
+    ///
+    /// 试听使用独立的输出，不会影响当前的播放会话，再次发送会替换掉正在进行的试听
+    #[serde(rename_all = "camelCase")]
+    StartPreview {
+        song: SongData,
+        start_position: f64,
+        duration: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    StopPreview,
+    /// 将一首本地歌曲解码后的输出导出为音频文件，会应用当前的音量增益
+    ///
+    /// `song` 为空时导出当前播放的歌曲，`duration` 为空时导出到歌曲结尾。
+    /// 导出在后台离线进行，同一时间只能进行一个导出任务
+    #[serde(rename_all = "camelCase")]
+    ExportAudio {
+        song: Option<SongData>,
+        output_path: String,
+        format: AudioExportFormat,
+        #[serde(default)]
+        start_position: f64,
+        duration: Option<f64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    CancelExport,
+    /// 导出一段音频的波形，并标出给定音节的开始和结束位置
+    #[serde(rename_all = "camelCase")]
+    ExportWaveform {
+        song: Option<SongData>,
+        output_path: String,
+        format: WaveformExportFormat,
+        #[serde(default)]
+        start_position: f64,
+        duration: Option<f64>,
+        /// 图片的宽度和高度，单位为像素，最大为 16384 × 4096
+        width: Option<u32>,
+        height: Option<u32>,
+        #[serde(default)]
+        syllables: Vec<WaveformSyllable>,
+    },
     #[serde(rename_all = "camelCase")]
     Close,
     SetMediaControlsEnabled {
@@ -128,6 +396,12 @@ pub enum AudioThreadEvent {
         music_id: String,
         current_play_index: usize,
     },
+    /// 开始播放一首歌曲，`quality` 为音源的编码、容器、码率、位深等信息，可用于显示无损或高解析度标识
+    #[serde(rename_all = "camelCase")]
+    TrackOpened {
+        music_id: String,
+        quality: AudioQuality,
+    },
     #[serde(rename_all = "camelCase")]
     AudioPlayFinished { music_id: String },
     #[serde(rename_all = "camelCase")]
@@ -149,17 +423,77 @@ pub enum AudioThreadEvent {
         playlist: Vec<SongData>,
         current_play_index: usize,
     },
+    /// 播放队列的撤销历史发生变化
+    #[serde(rename_all = "camelCase")]
+    QueueHistoryChanged { can_undo: bool },
     #[serde(rename_all = "camelCase")]
     PlayStatus { is_playing: bool },
     #[serde(rename_all = "camelCase")]
+    Chapters {
+        music_id: String,
+        chapters: Vec<Chapter>,
+    },
+    /// 播放进度进入了另一个章节，不在任何章节内时为空
+    #[serde(rename_all = "camelCase")]
+    ChapterChanged {
+        index: Option<usize>,
+        chapter: Option<Chapter>,
+    },
+    /// A-B 循环区间发生变化，为空时表示已取消循环
+    #[serde(rename_all = "camelCase")]
+    LoopRegionChanged { region: Option<LoopRegion> },
+    /// 独占输出的状态发生变化，`error` 为无法独占的原因，只在 macOS 和 Windows 上出现
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[serde(rename_all = "camelCase")]
+    ExclusiveOutputChanged { active: bool, error: Option<String> },
+    #[serde(rename_all = "camelCase")]
     LoadError { error: String },
     #[serde(rename_all = "camelCase")]
     PlayError { error: String },
+    /// 解码时遇到损坏的数据，`recovered` 为 `true` 时已经跳过错误继续播放，
+    /// 为 `false` 时表示连续失败次数过多，这首歌曲已经停止解码
+    #[serde(rename_all = "camelCase")]
+    DecodeError {
+        music_id: String,
+        error: String,
+        recovered: bool,
+    },
     #[serde(rename_all = "camelCase")]
     VolumeChanged { volume: f64 },
+    #[serde(rename_all = "camelCase")]
+    MuteChanged { muted: bool },
+    #[serde(rename_all = "camelCase")]
+    PreviewStatus {
+        music_id: String,
+        is_previewing: bool,
+    },
+    /// 试听无法开始，不影响主播放会话
+    #[serde(rename_all = "camelCase")]
+    PreviewError { music_id: String, error: String },
+    #[serde(rename_all = "camelCase")]
+    ExportProgress { output_path: String, progress: f64 },
+    /// 导出结束，`error` 为空时表示导出成功
+    #[serde(rename_all = "camelCase")]
+    ExportFinished {
+        output_path: String,
+        error: Option<String>,
+    },
+    /// 波形导出结束，`error` 为空时表示导出成功
+    #[serde(rename_all = "camelCase")]
+    WaveformExported {
+        output_path: String,
+        error: Option<String>,
+    },
     #[serde(rename = "fftData")]
     #[serde(rename_all = "camelCase")]
     FFTData { data: Vec<f32> },
+    /// 对数频段的频谱数据，低频段在前，只在通过 `SetFFTBands` 设置了频段数时发送
+    #[serde(rename = "fftBands")]
+    #[serde(rename_all = "camelCase")]
+    FFTBands { data: Vec<f32> },
+    /// 检测到一次起音，`position` 为当时的播放进度（秒），`strength` 为 0 到 1 之间的强度
+    #[serde(rename_all = "camelCase")]
+    Beat { position: f64, strength: f32 },
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
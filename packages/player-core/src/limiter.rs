@@ -0,0 +1,156 @@
+//! 前瞻限幅器，作为最后一级 DSP 处理，防止响度均衡和均衡器的提升使输出超过满幅度而削波
+//!
+//! 输出会延迟前瞻时长，限幅器在峰值到达之前就开始平滑地压低增益，
+//! 峰值过去后再按释放时间慢慢恢复
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+const MAX_LOOKAHEAD_MS: u32 = 20;
+const MIN_RELEASE_MS: u32 = 10;
+const MAX_RELEASE_MS: u32 = 1000;
+
+/// 限幅器设置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LimiterOptions {
+    pub enabled: bool,
+    /// 输出峰值的上限，单位为 dBFS，不会高于 0
+    pub ceiling_db: f64,
+    /// 前瞻时长，单位为毫秒，输出会因此延迟同样的时长
+    pub lookahead_ms: u32,
+    /// 峰值过去后增益恢复的时长，单位为毫秒
+    pub release_ms: u32,
+}
+
+impl Default for LimiterOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ceiling_db: -1.0,
+            lookahead_ms: 5,
+            release_ms: 100,
+        }
+    }
+}
+
+/// 作用在解码输出上的限幅器，按帧计算增益，同一帧的各声道使用相同的增益以保持声像
+pub struct Limiter {
+    sample_rate: f32,
+    channels: usize,
+    enabled: bool,
+    ceiling: f32,
+    lookahead_frames: usize,
+    attack_coeff: f32,
+    release_coeff: f32,
+    envelope: f32,
+    /// 延迟线，保存尚未输出的交错采样
+    delay: VecDeque<f32>,
+    /// 前瞻窗口内各帧所需增益的单调队列，队首为窗口内的最小值
+    required_gains: VecDeque<(u64, f32)>,
+    frame_index: u64,
+}
+
+impl Limiter {
+    /// 创建一个未开启的限幅器
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let mut limiter = Self {
+            sample_rate: sample_rate as f32,
+            channels: channels.max(1) as usize,
+            enabled: false,
+            ceiling: 1.0,
+            lookahead_frames: 0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: 1.0,
+            delay: VecDeque::new(),
+            required_gains: VecDeque::new(),
+            frame_index: 0,
+        };
+        limiter.update(&LimiterOptions::default());
+        limiter
+    }
+
+    pub fn update(&mut self, options: &LimiterOptions) {
+        let lookahead_frames = (options.lookahead_ms.clamp(1, MAX_LOOKAHEAD_MS) as f32
+            * self.sample_rate
+            / 1000.0) as usize;
+        let lookahead_frames = lookahead_frames.max(1);
+        let release_frames = options.release_ms.clamp(MIN_RELEASE_MS, MAX_RELEASE_MS) as f32
+            * self.sample_rate
+            / 1000.0;
+
+        if !options.enabled || lookahead_frames != self.lookahead_frames {
+            self.reset();
+        }
+        self.enabled = options.enabled;
+        self.ceiling = 10f64.powf(options.ceiling_db.min(0.0) / 20.0) as f32;
+        self.lookahead_frames = lookahead_frames;
+        // 攻击在前瞻时长的三分之一内基本完成，剩下的余量交给最后的硬限幅兜底
+        self.attack_coeff = (-3.0 / self.lookahead_frames as f32).exp();
+        self.release_coeff = (-1.0 / release_frames.max(1.0)).exp();
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 1.0;
+        self.delay.clear();
+        self.required_gains.clear();
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if !self.enabled {
+            return;
+        }
+
+        let delay_len = self.lookahead_frames * self.channels;
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let peak = frame
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let required = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+
+            while self
+                .required_gains
+                .back()
+                .is_some_and(|&(_, gain)| gain >= required)
+            {
+                self.required_gains.pop_back();
+            }
+            self.required_gains.push_back((self.frame_index, required));
+            let window_start = self
+                .frame_index
+                .saturating_sub(self.lookahead_frames as u64);
+            while self
+                .required_gains
+                .front()
+                .is_some_and(|&(index, _)| index < window_start)
+            {
+                self.required_gains.pop_front();
+            }
+            self.frame_index += 1;
+
+            let target = self.required_gains.front().map_or(1.0, |&(_, gain)| gain);
+            let coeff = if target < self.envelope {
+                self.attack_coeff
+            } else {
+                self.release_coeff
+            };
+            self.envelope = target + (self.envelope - target) * coeff;
+
+            self.delay.extend(frame.iter().copied());
+            for sample in frame.iter_mut() {
+                *sample = if self.delay.len() > delay_len {
+                    let delayed = self.delay.pop_front().unwrap_or_default() * self.envelope;
+                    delayed.clamp(-self.ceiling, self.ceiling)
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! 基于 ReplayGain / R128 标签的响度均衡
+//!
+//! 标签中的增益以 ReplayGain 的参考响度（-18 LUFS）为基准，R128 标签以 -23 LUFS 为基准，
+//! 这里统一换算到 ReplayGain 基准后再按目标响度整体偏移
+
+use ffmpeg_next as ffmpeg;
+use serde::{Deserialize, Serialize};
+
+use crate::volume::db_to_gain;
+
+/// ReplayGain 2.0 的参考响度
+pub(crate) const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+/// R128 标签相对于 ReplayGain 基准的偏移，R128 以 -23 LUFS 为基准
+const R128_TO_REPLAYGAIN_DB: f64 = 5.0;
+/// 开启防削波时，超过该幅度的采样会被柔和地压缩
+const LIMITER_THRESHOLD: f32 = 0.9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ReplayGainMode {
+    /// 使用单曲增益，没有单曲增益时退回专辑增益
+    #[default]
+    Track,
+    /// 使用专辑增益，保留专辑内歌曲之间的响度差异，没有专辑增益时退回单曲增益
+    Album,
+}
+
+/// 响度均衡设置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LoudnessOptions {
+    pub enabled: bool,
+    pub mode: ReplayGainMode,
+    /// 目标响度，单位为 LUFS
+    pub target_lufs: f64,
+    /// 没有增益标签的歌曲使用的增益，单位为 dB
+    pub fallback_gain_db: f64,
+    /// 防止增益后削波：已知峰值时限制增益，并对仍然过载的采样做柔和压缩
+    pub prevent_clipping: bool,
+}
+
+impl Default for LoudnessOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: ReplayGainMode::Track,
+            target_lufs: REPLAYGAIN_REFERENCE_LUFS,
+            fallback_gain_db: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+/// 从文件标签中读取到的增益与峰值，增益单位为 dB（以 ReplayGain 参考响度为基准），峰值为线性幅度
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LoudnessTags {
+    pub track_gain_db: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub album_gain_db: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+impl LoudnessTags {
+    /// 从容器和音频流的元数据中读取 ReplayGain 与 R128 标签，音频流上的标签优先
+    pub fn read(
+        input_ctx: &ffmpeg::format::context::Input,
+        stream: &ffmpeg::format::stream::Stream,
+    ) -> Self {
+        let mut tags = Self::default();
+        for metadata in [input_ctx.metadata(), stream.metadata()] {
+            for (key, value) in metadata.iter() {
+                tags.apply_tag(key, value);
+            }
+        }
+        tags
+    }
+
+    /// 用另一份数据补上缺少的增益和峰值，已有的值保持不变
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            track_gain_db: self.track_gain_db.or(other.track_gain_db),
+            track_peak: self.track_peak.or(other.track_peak),
+            album_gain_db: self.album_gain_db.or(other.album_gain_db),
+            album_peak: self.album_peak.or(other.album_peak),
+        }
+    }
+
+    fn apply_tag(&mut self, key: &str, value: &str) {
+        match key.to_ascii_uppercase().as_str() {
+            "REPLAYGAIN_TRACK_GAIN" => self.track_gain_db = parse_gain(value),
+            "REPLAYGAIN_TRACK_PEAK" => self.track_peak = parse_number(value),
+            "REPLAYGAIN_ALBUM_GAIN" => self.album_gain_db = parse_gain(value),
+            "REPLAYGAIN_ALBUM_PEAK" => self.album_peak = parse_number(value),
+            "R128_TRACK_GAIN" => self.track_gain_db = parse_r128(value),
+            "R128_ALBUM_GAIN" => self.album_gain_db = parse_r128(value),
+            _ => {}
+        }
+    }
+
+    /// 按照设置选出要使用的增益和对应的峰值
+    fn select(&self, mode: ReplayGainMode) -> (Option<f64>, Option<f64>) {
+        let track = (self.track_gain_db, self.track_peak);
+        let album = (self.album_gain_db, self.album_peak);
+        let (preferred, fallback) = match mode {
+            ReplayGainMode::Track => (track, album),
+            ReplayGainMode::Album => (album, track),
+        };
+        if preferred.0.is_some() {
+            preferred
+        } else if fallback.0.is_some() {
+            fallback
+        } else {
+            (None, preferred.1.or(fallback.1))
+        }
+    }
+}
+
+/// 应用到解码输出上的增益
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessGain {
+    factor: f32,
+    limit: bool,
+}
+
+impl Default for LoudnessGain {
+    fn default() -> Self {
+        Self::UNITY
+    }
+}
+
+impl LoudnessGain {
+    pub const UNITY: Self = Self {
+        factor: 1.0,
+        limit: false,
+    };
+
+    /// `pre_amp_db` 为前级增益，无论是否开启响度均衡都会叠加到每首歌曲上
+    pub fn new(tags: &LoudnessTags, options: &LoudnessOptions, pre_amp_db: f64) -> Self {
+        if !options.enabled && pre_amp_db == 0.0 {
+            return Self::UNITY;
+        }
+
+        let (gain_db, peak) = if options.enabled {
+            let (gain_db, peak) = tags.select(options.mode);
+            let gain_db = gain_db.unwrap_or(options.fallback_gain_db)
+                + (options.target_lufs - REPLAYGAIN_REFERENCE_LUFS);
+            (gain_db, peak)
+        } else {
+            (0.0, None)
+        };
+        let mut factor = db_to_gain(gain_db + pre_amp_db);
+        if options.prevent_clipping
+            && let Some(peak) = peak.filter(|&peak| peak > 0.0)
+        {
+            factor = factor.min(1.0 / peak);
+        }
+
+        Self {
+            factor: factor as f32,
+            limit: options.prevent_clipping && factor > 1.0,
+        }
+    }
+
+    pub fn apply(&self, samples: &mut [f32]) {
+        if *self == Self::UNITY {
+            return;
+        }
+        for sample in samples {
+            *sample *= self.factor;
+            if self.limit {
+                *sample = soft_limit(*sample);
+            }
+        }
+    }
+}
+
+/// 阈值以下保持不变，阈值以上用 tanh 曲线压缩，使输出幅度不超过 1
+fn soft_limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= LIMITER_THRESHOLD {
+        return sample;
+    }
+    let headroom = 1.0 - LIMITER_THRESHOLD;
+    let compressed =
+        LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh();
+    compressed.copysign(sample)
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite())
+}
+
+/// 解析形如 `-6.54 dB` 的增益值
+fn parse_gain(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    parse_number(number)
+}
+
+/// R128 标签是以 1/256 dB 为单位的整数
+fn parse_r128(value: &str) -> Option<f64> {
+    let raw: i32 = value.trim().parse().ok()?;
+    Some(raw as f64 / 256.0 + R128_TO_REPLAYGAIN_DB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_replaygain_values() {
+        assert_eq!(parse_gain("-6.5 dB"), Some(-6.5));
+        assert_eq!(parse_gain(" +3.20 dB "), Some(3.2));
+        assert_eq!(parse_gain("-1.25db"), Some(-1.25));
+        assert_eq!(parse_gain("2"), Some(2.0));
+        assert_eq!(parse_gain("loud dB"), None);
+        assert_eq!(parse_gain("NaN dB"), None);
+
+        assert_eq!(parse_number("0.988553"), Some(0.988553));
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn converts_r128_to_replaygain_reference() {
+        // -1536 / 256 = -6 dB（以 -23 LUFS 为基准），换算到 -18 LUFS 基准为 -1 dB
+        assert_eq!(parse_r128("-1536"), Some(-1.0));
+        assert_eq!(parse_r128(" 256 "), Some(6.0));
+        assert_eq!(parse_r128("-6.0"), None);
+    }
+
+    #[test]
+    fn applies_tags_case_insensitively() {
+        let mut tags = LoudnessTags::default();
+        tags.apply_tag("replaygain_track_gain", "-6.5 dB");
+        tags.apply_tag("REPLAYGAIN_TRACK_PEAK", "0.9");
+        tags.apply_tag("R128_ALBUM_GAIN", "-512");
+        tags.apply_tag("replaygain_album_peak", "1.2");
+        tags.apply_tag("TITLE", "-1 dB");
+        assert_eq!(
+            tags,
+            LoudnessTags {
+                track_gain_db: Some(-6.5),
+                track_peak: Some(0.9),
+                album_gain_db: Some(3.0),
+                album_peak: Some(1.2),
+            }
+        );
+    }
+
+    #[test]
+    fn selects_gain_with_fallback() {
+        let tags = LoudnessTags {
+            track_gain_db: Some(-6.0),
+            track_peak: Some(0.9),
+            album_gain_db: Some(-4.0),
+            album_peak: Some(1.1),
+        };
+        assert_eq!(tags.select(ReplayGainMode::Track), (Some(-6.0), Some(0.9)));
+        assert_eq!(tags.select(ReplayGainMode::Album), (Some(-4.0), Some(1.1)));
+
+        let track_only = LoudnessTags {
+            album_gain_db: None,
+            album_peak: None,
+            ..tags
+        };
+        assert_eq!(
+            track_only.select(ReplayGainMode::Album),
+            (Some(-6.0), Some(0.9))
+        );
+
+        let peak_only = LoudnessTags {
+            album_peak: Some(0.7),
+            ..LoudnessTags::default()
+        };
+        assert_eq!(peak_only.select(ReplayGainMode::Track), (None, Some(0.7)));
+    }
+
+    #[test]
+    fn peak_limits_positive_gain() {
+        let tags = LoudnessTags {
+            track_gain_db: Some(12.0),
+            track_peak: Some(0.5),
+            ..LoudnessTags::default()
+        };
+        let options = LoudnessOptions {
+            enabled: true,
+            ..LoudnessOptions::default()
+        };
+        let gain = LoudnessGain::new(&tags, &options, 0.0);
+        assert_eq!(gain.factor, 2.0);
+        assert!(gain.limit);
+
+        // 阈值以下的采样只乘以增益，超过阈值的被压缩到 1 以内
+        let mut samples = [0.25, -0.5, 0.6];
+        gain.apply(&mut samples);
+        assert_eq!(samples[0], 0.5);
+        assert!(samples[1] < -LIMITER_THRESHOLD && samples[1] > -1.0);
+        assert!(samples[2] > LIMITER_THRESHOLD && samples[2] < 1.0);
+
+        let disabled = LoudnessOptions::default();
+        assert_eq!(
+            LoudnessGain::new(&tags, &disabled, 0.0),
+            LoudnessGain::UNITY
+        );
+    }
+}
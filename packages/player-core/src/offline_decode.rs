@@ -0,0 +1,121 @@
+//! 不经过播放用的解码线程，直接把文件中的音频流完整解码为 f32 采样
+//!
+//! 响度扫描和波形峰值等离线分析共用，单个损坏的数据包会被跳过
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, bail};
+use ffmpeg_next::{self as ffmpeg, ChannelLayout};
+use tracing::warn;
+
+use crate::http_source::MediaInput;
+
+const OUTPUT_FORMAT: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar);
+/// 结束时从重采样器中取出剩余采样，每次最多取出的采样数
+const FLUSH_CHUNK_SAMPLES: usize = 4096;
+
+/// 解码输出的声道布局和采样率，为空时与音频流保持一致
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DecodeTarget {
+    pub channel_layout: Option<ChannelLayout>,
+    pub rate: Option<u32>,
+}
+
+/// 完整解码 `input_ctx` 中的音频流，每一帧转换为平面排列的 f32 采样后交给 `on_frame`
+///
+/// `cancelled` 被置为真时会尽快停止并返回错误。
+/// 到达结尾时会取出解码器和重采样器中剩余的采样，不会丢掉最后一小段音频
+pub(crate) fn decode_to_f32(
+    input_ctx: &mut MediaInput,
+    path: &str,
+    target: DecodeTarget,
+    cancelled: &AtomicBool,
+    mut on_frame: impl FnMut(&mut ffmpeg::frame::Audio) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let stream = input_ctx
+        .streams()
+        .best(ffmpeg::media::Type::Audio)
+        .context("找不到音频流")?;
+    let stream_index = stream.index();
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .audio()?;
+
+    // 重采样器按第一帧的格式创建，部分格式在解码出第一帧之前拿不到准确的声道布局
+    let mut resampler: Option<ffmpeg::software::resampling::context::Context> = None;
+    let mut convert = |decoded: &ffmpeg::frame::Audio| -> anyhow::Result<()> {
+        let resampler = match &mut resampler {
+            Some(resampler) => resampler,
+            None => resampler.insert(create_resampler(decoded, target)?),
+        };
+        let output = resampler.output();
+        let output_samples = (decoded.samples() as u64 * output.rate as u64)
+            .div_ceil(decoded.rate().max(1) as u64) as usize;
+        let mut resampled =
+            ffmpeg::frame::Audio::new(OUTPUT_FORMAT, output_samples, output.channel_layout);
+        if let Err(e) = resampler.run(decoded, &mut resampled) {
+            warn!("解码 {path} 时重采样失败: {e}");
+            return Ok(());
+        }
+        if resampled.samples() > 0 {
+            on_frame(&mut resampled)?;
+        }
+        Ok(())
+    };
+
+    let mut decoded = ffmpeg::frame::Audio::empty();
+    for (stream, packet) in input_ctx.packets() {
+        if cancelled.load(Ordering::Relaxed) {
+            bail!("已取消");
+        }
+        if stream.index() != stream_index {
+            continue;
+        }
+        if let Err(e) = decoder.send_packet(&packet) {
+            warn!("解码 {path} 时跳过损坏的数据包: {e}");
+            continue;
+        }
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            convert(&decoded)?;
+        }
+    }
+    decoder.send_eof()?;
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        convert(&decoded)?;
+    }
+
+    let Some(resampler) = &mut resampler else {
+        return Ok(());
+    };
+    loop {
+        let mut resampled = ffmpeg::frame::Audio::new(
+            OUTPUT_FORMAT,
+            FLUSH_CHUNK_SAMPLES,
+            resampler.output().channel_layout,
+        );
+        match resampler.flush(&mut resampled) {
+            Ok(_) if resampled.samples() > 0 => on_frame(&mut resampled)?,
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn create_resampler(
+    frame: &ffmpeg::frame::Audio,
+    target: DecodeTarget,
+) -> anyhow::Result<ffmpeg::software::resampling::context::Context> {
+    let source_layout = if frame.channel_layout().is_empty() {
+        ChannelLayout::default(frame.channels() as i32)
+    } else {
+        frame.channel_layout()
+    };
+    Ok(ffmpeg::software::resampling::context::Context::get(
+        frame.format(),
+        source_layout,
+        frame.rate(),
+        OUTPUT_FORMAT,
+        target.channel_layout.unwrap_or(source_layout),
+        target.rate.unwrap_or(frame.rate()),
+    )?)
+}
@@ -0,0 +1,127 @@
+//! 基于频谱通量（spectral flux）的起音检测，用于驱动随节拍变化的背景动画
+//!
+//! 每一帧幅值谱先做对数压缩，再与上一帧比较，只累加增大的部分得到通量。
+//! 通量明显高于最近一段时间的平均值时视为一次起音
+
+use std::collections::VecDeque;
+
+/// 用于计算自适应阈值的历史帧数，频谱每 50 毫秒分析一次，约为一秒
+const HISTORY_FRAMES: usize = 20;
+/// 历史不足该帧数时不做判断，避免刚开始播放或跳转后误报
+const MIN_HISTORY_FRAMES: usize = 4;
+/// 通量超过历史平均值的该倍数才视为起音
+const THRESHOLD_RATIO: f32 = 1.5;
+/// 通量的下限，低于该值时即使相对变化很大也不报告，避免在静音和渐弱段落误报
+const MIN_FLUX: f32 = 0.5;
+/// 两次起音之间至少间隔的帧数，约 200 毫秒
+const MIN_ONSET_GAP_FRAMES: usize = 4;
+
+#[derive(Default)]
+pub(crate) struct OnsetDetector {
+    prev: Vec<f32>,
+    history: VecDeque<f32>,
+    frames_since_onset: usize,
+}
+
+impl OnsetDetector {
+    /// 输入一帧幅值谱，检测到起音时返回 0 到 1 之间的强度
+    pub fn process(&mut self, magnitudes: &[f32]) -> Option<f32> {
+        if self.prev.len() != magnitudes.len() {
+            self.prev = magnitudes.iter().map(|m| m.ln_1p()).collect();
+            self.history.clear();
+            return None;
+        }
+
+        let mut flux = 0.0;
+        for (prev, magnitude) in self.prev.iter_mut().zip(magnitudes) {
+            let magnitude = magnitude.ln_1p();
+            flux += (magnitude - *prev).max(0.0);
+            *prev = magnitude;
+        }
+
+        self.frames_since_onset = self.frames_since_onset.saturating_add(1);
+        let mean = self.history.iter().sum::<f32>() / self.history.len().max(1) as f32;
+        let is_onset = self.history.len() >= MIN_HISTORY_FRAMES
+            && self.frames_since_onset >= MIN_ONSET_GAP_FRAMES
+            && flux >= MIN_FLUX
+            && flux > mean * THRESHOLD_RATIO;
+
+        if self.history.len() == HISTORY_FRAMES {
+            self.history.pop_front();
+        }
+        self.history.push_back(flux);
+
+        if !is_onset {
+            return None;
+        }
+        self.frames_since_onset = 0;
+        // 刚好达到阈值时约为 0.33，远高于平均值时趋近于 1
+        Some((1.0 - mean / flux).clamp(0.0, 1.0))
+    }
+
+    /// 跳转或切歌后重新开始，之前的帧不再参与比较
+    pub fn reset(&mut self) {
+        self.prev.clear();
+        self.history.clear();
+        self.frames_since_onset = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINS: usize = 64;
+
+    fn feed(detector: &mut OnsetDetector, frames: &[f32]) -> Vec<Option<f32>> {
+        frames
+            .iter()
+            .map(|&level| detector.process(&[level; BINS]))
+            .collect()
+    }
+
+    #[test]
+    fn silence_has_no_onsets() {
+        let mut detector = OnsetDetector::default();
+        assert!(feed(&mut detector, &[0.0; 40]).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn impulse_after_steady_signal_is_an_onset() {
+        let mut detector = OnsetDetector::default();
+        let mut frames = vec![0.1; 10];
+        frames.push(10.0);
+        let results = feed(&mut detector, &frames);
+        assert!(results[..10].iter().all(Option::is_none));
+        let strength = results[10].expect("impulse should be detected");
+        assert!(strength > 0.9 && strength <= 1.0);
+    }
+
+    #[test]
+    fn onsets_closer_than_min_gap_are_suppressed() {
+        let mut detector = OnsetDetector::default();
+        feed(&mut detector, &[0.1; 10]);
+        assert!(detector.process(&[10.0; BINS]).is_some());
+        // 先回到低电平，再在最小间隔之内出现第二次突增
+        assert!(detector.process(&[0.1; BINS]).is_none());
+        assert!(detector.process(&[100.0; BINS]).is_none());
+        feed(&mut detector, &[0.1; MIN_ONSET_GAP_FRAMES]);
+        assert!(detector.process(&[1000.0; BINS]).is_some());
+    }
+
+    #[test]
+    fn reset_forgets_history_and_gap() {
+        let mut detector = OnsetDetector::default();
+        feed(&mut detector, &[0.1; 10]);
+        assert!(detector.process(&[10.0; BINS]).is_some());
+        detector.reset();
+        assert_eq!(detector.frames_since_onset, 0);
+        // 重置后需要重新积累历史，不会立刻报告
+        assert!(detector.process(&[10.0; BINS]).is_none());
+        assert!(
+            feed(&mut detector, &[10.0; MIN_HISTORY_FRAMES])
+                .iter()
+                .all(Option::is_none)
+        );
+    }
+}
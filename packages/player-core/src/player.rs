@@ -1,22 +1,45 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
     fs::File,
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::{Duration, Instant},
 };
 
 use super::fft_player::FFTPlayer;
+#[cfg(target_os = "macos")]
+use crate::exclusive::ExclusiveOutput;
+#[cfg(target_os = "windows")]
+use crate::exclusive::WasapiExclusiveStream;
 use crate::{
     AudioPlayerEventReceiver, AudioPlayerEventSender, AudioPlayerMessageReceiver,
     AudioPlayerMessageSender, AudioThreadEvent, AudioThreadEventMessage, AudioThreadMessage,
-    SongData,
+    Chapter, LoopRegion, SongData,
     audio_quality::AudioQuality,
-    ffmpeg_decoder::{FFmpegDecoder, FFmpegDecoderHandle},
+    balance::BalanceOptions,
+    dither::{DitherMode, integer_sample_bits},
+    equalizer::EqualizerSettings,
+    export::{AudioExportFormat, ExportOptions, export_audio},
+    fade::FadeOptions,
+    ffmpeg_decoder::{DownmixOptions, FFmpegDecoder, FFmpegDecoderHandle},
+    limiter::LimiterOptions,
+    loudness::{LoudnessOptions, LoudnessTags},
     media_state::{MediaStateManager, MediaStateManagerBackend, MediaStateMessage},
+    queue::{QueueHistory, insert_play_next, move_song},
+    silence::SilenceSkipOptions,
+    tempo::{MAX_PITCH_SEMITONES, MAX_TEMPO, MIN_PITCH_SEMITONES, MIN_TEMPO},
+    utils::probe_audio_format,
+    volume::{MAX_PRE_AMP_DB, volume_to_gain},
+    waveform::{WaveformOptions, export_waveform},
 };
 use anyhow::{Context, anyhow};
+use cpal::SampleFormat;
+use ffmpeg_next as ffmpeg;
 use parking_lot::RwLock as ParkingLotRwLock;
-use rodio::{OutputStream, Sink, Source};
+use rodio::{OutputStream, OutputStreamBuilder, Sink, Source, mixer::Mixer};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock as TokioRwLock;
 use tokio::{
@@ -25,6 +48,17 @@ use tokio::{
 };
 use tracing::{info, warn};
 
+/// 当前歌曲剩余时间少于该秒数时，提前打开下一首歌曲的解码器以实现无缝播放
+const GAPLESS_PRELOAD_SECS: f64 = 5.0;
+/// A-B 循环区间的最短长度，单位为毫秒
+const MIN_LOOP_MS: u64 = 100;
+/// 静音渐变的最长时长
+const MAX_MUTE_FADE_MS: u32 = 2000;
+/// 试听期间主播放的增益，约为 -14dB
+const PREVIEW_DUCK_GAIN: f32 = 0.2;
+/// 试听开始和结束时主播放音量渐变的时长
+const PREVIEW_DUCK_RAMP: Duration = Duration::from_millis(300);
+
 pub struct AudioPlayer {
     evt_sender: AudioPlayerEventSender,
     evt_receiver: AudioPlayerEventReceiver,
@@ -32,8 +66,42 @@ pub struct AudioPlayer {
     msg_receiver: AudioPlayerMessageReceiver,
     sink: Arc<Sink>,
     current_decoder_handle: Option<FFmpegDecoderHandle>,
-    stream_handle: OutputStream,
+    queued_next: Option<QueuedTrack>,
+    next_preload_attempted: bool,
+    /// 通过 `Preload` 提前打开的歌曲，切到这首歌曲时直接使用
+    preloaded: Option<PreloadedTrack>,
+    stream_handle: OutputHandle,
+    /// 输出设备当前按照哪种源格式打开，为空表示使用设备的默认配置
+    output_format: Option<SourceOutputFormat>,
+    follow_source_format: bool,
+    /// 不为空时正在独占输出设备
+    #[cfg(target_os = "macos")]
+    exclusive_output: Option<ExclusiveOutput>,
+    /// 是否以 WASAPI 独占模式打开输出设备
+    #[cfg(target_os = "windows")]
+    exclusive_output: bool,
+    /// 音量滑块的位置，实际增益由 [`volume_to_gain`] 换算
     volume: f64,
+    muted: bool,
+    /// 叠加到每首歌曲上的前级增益，单位为 dB
+    pre_amp_db: f64,
+    loudness: LoudnessOptions,
+    /// 宿主程序扫描得到的响度记录，以文件路径为键，文件本身缺少增益标签时使用
+    scanned_loudness: HashMap<String, LoudnessTags>,
+    equalizer: EqualizerSettings,
+    balance: BalanceOptions,
+    limiter: LimiterOptions,
+    dither: DitherMode,
+    fade: FadeOptions,
+    downmix: DownmixOptions,
+    build_seek_index: bool,
+    loop_region: Option<LoopRegion>,
+    /// 上一次通知前端时所在的章节
+    current_chapter: Option<usize>,
+    silence_skip: SilenceSkipOptions,
+    playback_rate: f64,
+    playback_rate_tx: tokio::sync::watch::Sender<f64>,
+    pitch_semitones: f64,
     playlist: Vec<SongData>,
     playlist_inited: bool,
     current_play_index: usize,
@@ -51,6 +119,114 @@ pub struct AudioPlayer {
     fft_broadcast_task: Option<JoinHandle<()>>,
     target_channels: u16,
     target_sample_rate: u32,
+    preview: Option<PreviewSession>,
+    export: Option<ExportTask>,
+    queue_history: QueueHistory,
+}
+
+/// 正在进行的试听，使用独立的 Sink 输出，与主播放会话互不干扰
+struct PreviewSession {
+    music_id: String,
+    sink: Sink,
+}
+
+/// 播放使用的音频输出，所有 Sink 都连接到它的混音器上
+enum OutputHandle {
+    /// 由 cpal 打开的输出，经过系统混音器
+    Shared(OutputStream),
+    /// 以 WASAPI 独占模式直接打开的输出
+    #[cfg(target_os = "windows")]
+    Exclusive(WasapiExclusiveStream),
+}
+
+impl OutputHandle {
+    fn mixer(&self) -> &Mixer {
+        match self {
+            Self::Shared(stream) => stream.mixer(),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(stream) => stream.mixer(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match self {
+            Self::Shared(stream) => stream.config().channel_count(),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(stream) => stream.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Self::Shared(stream) => stream.config().sample_rate(),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(stream) => stream.sample_rate(),
+        }
+    }
+
+    fn sample_format(&self) -> SampleFormat {
+        match self {
+            Self::Shared(stream) => stream.config().sample_format(),
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(stream) => stream.sample_format(),
+        }
+    }
+
+    fn is_wasapi_exclusive(&self) -> bool {
+        match self {
+            Self::Shared(_) => false,
+            #[cfg(target_os = "windows")]
+            Self::Exclusive(_) => true,
+        }
+    }
+}
+
+/// 按照歌曲打开输出设备时使用的采样率、声道数和采样格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceOutputFormat {
+    sample_rate: u32,
+    channels: u16,
+    sample_format: SampleFormat,
+}
+
+impl SourceOutputFormat {
+    fn new(sample_rate: u32, channels: u16, source_format: ffmpeg::format::Sample) -> Self {
+        let sample_format = match source_format {
+            ffmpeg::format::Sample::U8(_) | ffmpeg::format::Sample::I16(_) => SampleFormat::I16,
+            ffmpeg::format::Sample::I32(_) | ffmpeg::format::Sample::I64(_) => SampleFormat::I32,
+            _ => SampleFormat::F32,
+        };
+        Self {
+            sample_rate,
+            channels,
+            sample_format,
+        }
+    }
+}
+
+/// 为无缝播放提前打开并加入 Sink 的下一首歌曲
+struct QueuedTrack {
+    play_index: usize,
+    song: SongData,
+    handle: FFmpegDecoderHandle,
+    audio_info: AudioInfo,
+    audio_quality: AudioQuality,
+}
+
+/// 在后台打开的歌曲解码器，解码线程会先把缓冲区填满
+struct PreloadedTrack {
+    music_id: String,
+    /// 打开解码器时的输出声道数、采样率和缩混设置，与播放时不一致就不能使用
+    target_channels: u16,
+    target_sample_rate: u32,
+    downmix: DownmixOptions,
+    task: JoinHandle<anyhow::Result<(FFmpegDecoder, FFmpegDecoderHandle)>>,
+}
+
+/// 正在后台进行的音频导出
+struct ExportTask {
+    cancelled: Arc<AtomicBool>,
+    task: JoinHandle<()>,
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -65,6 +241,12 @@ pub struct AudioInfo {
     pub comment: String,
     pub duration: f64,
     pub position: f64,
+    /// 是否为直播流，直播流没有确定的时长，也无法跳转
+    #[serde(default)]
+    pub is_live: bool,
+    /// 文件中的章节标记，常见于长混音和有声书
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
 }
 
 impl Debug for AudioInfo {
@@ -79,6 +261,8 @@ impl Debug for AudioInfo {
             .field("comment", &self.comment)
             .field("duration", &self.duration)
             .field("position", &self.position)
+            .field("is_live", &self.is_live)
+            .field("chapters", &self.chapters.len())
             .finish()
     }
 }
@@ -124,6 +308,7 @@ impl AudioPlayer {
         let audio_info_reader = current_audio_info.clone();
         let emitter_pos = AudioPlayerEventEmitter::new(evt_sender.clone());
         let (play_pos_sx, mut play_pos_rx) = tokio::sync::mpsc::unbounded_channel::<(bool, f64)>();
+        let (playback_rate_tx, mut playback_rate_rx) = tokio::sync::watch::channel(1.0);
         let media_state_manager_clone = media_state_manager.clone();
 
         tasks.push(tokio::task::spawn(async move {
@@ -133,8 +318,18 @@ impl AudioPlayer {
             let mut is_playing = false;
             let mut base_time = 0.0;
             let mut inst = Instant::now();
+            let mut playback_rate = 1.0;
 
             loop {
+                // 速度变化时先按旧速度结算已经播放的进度，再换用新速度计时
+                if playback_rate_rx.has_changed().unwrap_or(false) {
+                    if is_playing {
+                        base_time += inst.elapsed().as_secs_f64() * playback_rate;
+                    }
+                    inst = Instant::now();
+                    playback_rate = *playback_rate_rx.borrow_and_update();
+                }
+
                 if let Ok((new_is_playing, new_base_time)) = play_pos_rx.try_recv() {
                     is_playing = new_is_playing;
                     base_time = new_base_time;
@@ -160,7 +355,7 @@ impl AudioPlayer {
                         if is_playing {
                             let duration = audio_info_reader.read().await.duration;
                             if duration > 0.0 {
-                                let current_pos = (base_time + inst.elapsed().as_secs_f64()).min(duration);
+                                let current_pos = (base_time + inst.elapsed().as_secs_f64() * playback_rate).min(duration);
                                 *position_writer.write().await = current_pos;
 
                                 let _ = emitter_pos
@@ -186,27 +381,48 @@ impl AudioPlayer {
 
         let fft_player_clone = fft_player.clone();
         let emitter_clone = AudioPlayerEventEmitter::new(evt_sender.clone());
+        let position_reader = current_position.clone();
         let fft_broadcast_task = Some(tokio::task::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_millis(50));
             let mut fft_buffer = vec![0.0; 128];
+            let mut bands_buffer = Vec::new();
 
             loop {
                 interval.tick().await;
 
-                let data_to_send: Option<Vec<f32>> = {
+                let (event_to_send, onset): (Option<AudioThreadEvent>, Option<f32>) = {
                     if let Some(mut player) = fft_player_clone.try_write() {
-                        if player.has_data() && player.read(&mut fft_buffer) {
-                            Some(fft_buffer.clone())
+                        let band_count = player.band_count();
+                        let event = if !player.has_data() {
+                            None
+                        } else if band_count > 0 {
+                            bands_buffer.resize(band_count, 0.0);
+                            player.read_bands(&mut bands_buffer).then(|| {
+                                AudioThreadEvent::FFTBands {
+                                    data: bands_buffer.clone(),
+                                }
+                            })
+                        } else if player.read(&mut fft_buffer) {
+                            Some(AudioThreadEvent::FFTData {
+                                data: fft_buffer.clone(),
+                            })
                         } else {
                             None
-                        }
+                        };
+                        (event, player.take_onset())
                     } else {
-                        None
+                        (None, None)
                     }
                 };
 
-                if let Some(data) = data_to_send {
-                    let _ = emitter_clone.emit(AudioThreadEvent::FFTData { data }).await;
+                if let Some(event) = event_to_send {
+                    let _ = emitter_clone.emit(event).await;
+                }
+                if let Some(strength) = onset {
+                    let position = *position_reader.read().await;
+                    let _ = emitter_clone
+                        .emit(AudioThreadEvent::Beat { position, strength })
+                        .await;
                 }
             }
         }));
@@ -216,10 +432,36 @@ impl AudioPlayer {
             evt_receiver,
             msg_sender,
             msg_receiver,
-            stream_handle: handle,
+            stream_handle: OutputHandle::Shared(handle),
+            output_format: None,
+            follow_source_format: false,
+            #[cfg(target_os = "macos")]
+            exclusive_output: None,
+            #[cfg(target_os = "windows")]
+            exclusive_output: false,
             sink,
             current_decoder_handle: None,
+            queued_next: None,
+            next_preload_attempted: false,
+            preloaded: None,
             volume: 1.0,
+            muted: false,
+            pre_amp_db: 0.0,
+            scanned_loudness: HashMap::new(),
+            loudness: LoudnessOptions::default(),
+            equalizer: EqualizerSettings::default(),
+            balance: BalanceOptions::default(),
+            limiter: LimiterOptions::default(),
+            dither: DitherMode::default(),
+            fade: FadeOptions::default(),
+            downmix: DownmixOptions::default(),
+            build_seek_index: false,
+            loop_region: None,
+            current_chapter: None,
+            silence_skip: SilenceSkipOptions::default(),
+            playback_rate: 1.0,
+            playback_rate_tx,
+            pitch_semitones: 0.0,
             playlist: Vec::new(),
             playlist_inited: false,
             current_play_index: 0,
@@ -235,6 +477,9 @@ impl AudioPlayer {
             fft_broadcast_task,
             target_channels,
             target_sample_rate,
+            preview: None,
+            export: None,
+            queue_history: QueueHistory::default(),
         }
     }
 
@@ -332,6 +577,20 @@ impl AudioPlayer {
                     else { break; }
                 }
                 _ = check_end_interval.tick() => {
+                    if self.preview.as_ref().is_some_and(|preview| preview.sink.empty()) {
+                        self.stop_preview().await;
+                    }
+                    self.update_loop_position().await;
+                    self.update_skipped_silence().await;
+                    if let Err(e) = self.report_decode_errors().await {
+                        warn!("发送解码错误事件失败：{e:?}");
+                    }
+                    if let Err(e) = self.update_current_chapter().await {
+                        warn!("发送章节变化事件失败：{e:?}");
+                    }
+                    if let Err(e) = self.update_gapless_queue().await {
+                        warn!("预加载下一首歌曲失败：{e:?}");
+                    }
                     if self.sink.empty() && !self.sink.is_paused() && self.current_song.is_some() {
                         let _ = self.play_pos_sx.send((false, 0.0));
                         if let Err(e) = self.msg_sender.send(AudioThreadEventMessage::new(
@@ -385,13 +644,13 @@ impl AudioPlayer {
         if let Some(ref data) = msg.data {
             match data {
                 AudioThreadMessage::ResumeAudio => {
-                    self.sink.play();
+                    self.resume_with_fade();
                     let current_pos = *self.current_position.read().await;
                     let _ = self.play_pos_sx.send((true, current_pos));
                     self.update_media_manager_playback_state(true).await?;
                 }
                 AudioThreadMessage::PauseAudio => {
-                    self.sink.pause();
+                    self.pause_with_fade().await;
                     let current_pos = *self.current_position.read().await;
                     let _ = self.play_pos_sx.send((false, current_pos));
                     self.update_media_manager_playback_state(false).await?;
@@ -399,37 +658,259 @@ impl AudioPlayer {
                 AudioThreadMessage::ResumeOrPauseAudio => {
                     let is_paused = self.sink.is_paused();
                     if is_paused {
-                        self.sink.play();
+                        self.resume_with_fade();
                     } else {
-                        self.sink.pause();
+                        self.pause_with_fade().await;
                     }
                     let current_pos = *self.current_position.read().await;
                     let _ = self.play_pos_sx.send((is_paused, current_pos));
                     self.update_media_manager_playback_state(is_paused).await?;
                 }
                 AudioThreadMessage::SeekAudio { position } => {
-                    if let Some(handle) = &self.current_decoder_handle {
-                        let seek_pos = Duration::from_secs_f64(*position);
-
-                        if handle.seek(seek_pos).is_err() {
-                            warn!("发送跳转命令失败, 解码器可能已关闭");
-                        } else {
-                            let fft_player_clone = self.fft_player.clone();
-                            tokio::task::spawn_blocking(move || {
-                                fft_player_clone.write().clear();
-                            })
-                            .await?;
-                            let is_playing = !self.sink.is_paused();
-                            let _ = self.play_pos_sx.send((is_playing, *position));
-                            self.update_media_manager_playback_state(is_playing).await?;
+                    self.seek_to(*position).await?;
+                }
+                AudioThreadMessage::SeekToChapter { index, offset } => {
+                    let chapter = self
+                        .current_audio_info
+                        .read()
+                        .await
+                        .chapters
+                        .get(*index)
+                        .cloned();
+                    match chapter {
+                        Some(chapter) => {
+                            let position = (chapter.start + offset.max(0.0)).min(chapter.end);
+                            self.seek_to(position).await?;
                         }
-                    } else {
-                        warn!("找不到解码器句柄, 无法执行跳转");
+                        None => warn!("找不到第 {index} 个章节"),
                     }
                 }
+                AudioThreadMessage::GetChapters => {
+                    let chapters = self.current_audio_info.read().await.chapters.clone();
+                    emitter
+                        .emit(AudioThreadEvent::Chapters {
+                            music_id: self
+                                .current_song
+                                .as_ref()
+                                .map(|s| s.get_id())
+                                .unwrap_or_default(),
+                            chapters,
+                        })
+                        .await?;
+                }
                 AudioThreadMessage::SetVolume { volume } => {
                     self.volume = volume.clamp(0.0, 1.0);
-                    self.sink.set_volume(self.volume as f32);
+                    self.apply_output_volume(Duration::ZERO);
+                }
+                AudioThreadMessage::Mute { fade_ms } => {
+                    self.set_muted(true, *fade_ms).await?;
+                }
+                AudioThreadMessage::Unmute { fade_ms } => {
+                    self.set_muted(false, *fade_ms).await?;
+                }
+                AudioThreadMessage::SetDither { mode } => {
+                    self.dither = *mode;
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        self.apply_dither(handle);
+                    }
+                }
+                AudioThreadMessage::SetBalance { options } => {
+                    self.balance = *options;
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        handle.set_balance(&self.balance);
+                    }
+                }
+                AudioThreadMessage::SetLimiter { options } => {
+                    self.limiter = *options;
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        handle.set_limiter(&self.limiter);
+                    }
+                }
+                AudioThreadMessage::SetFadeOptions { options } => {
+                    self.fade = *options;
+                }
+                AudioThreadMessage::SetSilenceSkip { options } => {
+                    self.silence_skip = *options;
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        if handle.set_silence_skip(self.silence_skip).is_err() {
+                            warn!("发送静音跳过设置失败, 解码器可能已关闭");
+                        }
+                    }
+                }
+                AudioThreadMessage::SetLoopRegion { region } => {
+                    self.set_loop_region(*region).await?;
+                }
+                AudioThreadMessage::SetPlaybackRate { rate } => {
+                    self.playback_rate = rate.clamp(MIN_TEMPO, MAX_TEMPO);
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        if handle.set_tempo(self.playback_rate).is_err() {
+                            warn!("发送变速命令失败, 解码器可能已关闭");
+                        }
+                    }
+                    let _ = self.playback_rate_tx.send(self.playback_rate);
+                }
+                AudioThreadMessage::SetPitchShift { semitones } => {
+                    self.pitch_semitones =
+                        semitones.clamp(MIN_PITCH_SEMITONES, MAX_PITCH_SEMITONES);
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        if handle.set_pitch(self.pitch_semitones).is_err() {
+                            warn!("发送变调命令失败, 解码器可能已关闭");
+                        }
+                    }
+                }
+                AudioThreadMessage::SetSeekIndex { enabled } => {
+                    self.build_seek_index = *enabled;
+                }
+                AudioThreadMessage::SetDownmix { options } => {
+                    self.downmix = *options;
+                }
+                AudioThreadMessage::SetSourceFormatOutput { enabled } => {
+                    self.follow_source_format = *enabled;
+                }
+                #[cfg(any(target_os = "macos", target_os = "windows"))]
+                AudioThreadMessage::SetExclusiveOutput { enabled } => {
+                    self.set_exclusive_output(*enabled).await?;
+                }
+                AudioThreadMessage::SetPreAmp { gain_db } => {
+                    self.pre_amp_db = gain_db.clamp(-MAX_PRE_AMP_DB, MAX_PRE_AMP_DB);
+                    self.apply_loudness_to_all();
+                }
+                AudioThreadMessage::SetLoudnessNormalization { options } => {
+                    self.loudness = *options;
+                    self.apply_loudness_to_all();
+                }
+                AudioThreadMessage::SetScannedLoudness { records } => {
+                    self.scanned_loudness = records.clone();
+                    self.apply_loudness_to_all();
+                }
+                AudioThreadMessage::RecordScannedLoudness { records } => {
+                    self.scanned_loudness
+                        .extend(records.iter().map(|(path, tags)| (path.clone(), *tags)));
+                    self.apply_loudness_to_all();
+                }
+                AudioThreadMessage::SetEqualizer { settings } => {
+                    self.equalizer = settings.sanitized();
+                    let handles = self
+                        .current_decoder_handle
+                        .iter()
+                        .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+                    for handle in handles {
+                        handle.set_equalizer(&self.equalizer);
+                    }
+                }
+                AudioThreadMessage::StartPreview {
+                    song,
+                    start_position,
+                    duration,
+                } => {
+                    if let Err(err) = self
+                        .start_preview(song.clone(), *start_position, *duration)
+                        .await
+                    {
+                        warn!("开始试听失败：{err:?}");
+                        emitter
+                            .emit(AudioThreadEvent::PreviewError {
+                                music_id: song.get_id(),
+                                error: format!("{err:?}"),
+                            })
+                            .await?;
+                    }
+                }
+                AudioThreadMessage::StopPreview => {
+                    self.stop_preview().await;
+                }
+                AudioThreadMessage::ExportAudio {
+                    song,
+                    output_path,
+                    format,
+                    start_position,
+                    duration,
+                } => {
+                    if let Err(err) = self.start_export(
+                        song.clone(),
+                        output_path.clone(),
+                        *format,
+                        *start_position,
+                        *duration,
+                    ) {
+                        warn!("开始导出失败：{err:?}");
+                        emitter
+                            .emit(AudioThreadEvent::ExportFinished {
+                                output_path: output_path.clone(),
+                                error: Some(format!("{err:?}")),
+                            })
+                            .await?;
+                    }
+                }
+                AudioThreadMessage::CancelExport => {
+                    if let Some(export) = &self.export {
+                        export.cancelled.store(true, Ordering::Relaxed);
+                    }
+                }
+                AudioThreadMessage::ExportWaveform {
+                    song,
+                    output_path,
+                    format,
+                    start_position,
+                    duration,
+                    width,
+                    height,
+                    syllables,
+                } => {
+                    let options =
+                        self.local_file_path(song.clone())
+                            .map(|file_path| WaveformOptions {
+                                file_path,
+                                output_path: output_path.clone(),
+                                format: *format,
+                                start_position: Duration::from_secs_f64(start_position.max(0.0)),
+                                duration: duration.map(|d| Duration::from_secs_f64(d.max(0.0))),
+                                width: *width,
+                                height: *height,
+                                syllables: syllables.clone(),
+                            });
+                    match options {
+                        Ok(options) => self.start_waveform_export(options),
+                        Err(err) => {
+                            warn!("开始导出波形失败：{err:?}");
+                            emitter
+                                .emit(AudioThreadEvent::WaveformExported {
+                                    output_path: output_path.clone(),
+                                    error: Some(format!("{err:?}")),
+                                })
+                                .await?;
+                        }
+                    }
+                }
+                AudioThreadMessage::Preload { song } => {
+                    // 预加载只是提前准备，失败时切歌会照常重新打开，不影响这条消息的回复
+                    if let Err(err) = self.preload(song.clone()) {
+                        warn!("预加载歌曲失败：{err:?}");
+                    }
                 }
                 AudioThreadMessage::NextSong => {
                     if self.playlist.is_empty() {
@@ -468,6 +949,55 @@ impl AudioPlayer {
                 AudioThreadMessage::SetPlaylist { songs } => {
                     self.playlist = songs.clone();
                     self.playlist_inited = true;
+                    // 整个队列被替换后，之前的编辑历史已经没有意义
+                    self.queue_history.clear();
+                    self.emit_queue_changed().await?;
+                }
+                AudioThreadMessage::PlayNext { songs } => {
+                    self.queue_history
+                        .record(&self.playlist, self.current_play_index);
+                    insert_play_next(
+                        &mut self.playlist,
+                        self.current_play_index,
+                        self.current_song.is_some(),
+                        songs,
+                    );
+                    self.playlist_inited = true;
+                    self.emit_queue_changed().await?;
+                }
+                AudioThreadMessage::AddToQueue { songs } => {
+                    self.queue_history
+                        .record(&self.playlist, self.current_play_index);
+                    self.playlist.extend(songs.iter().cloned());
+                    self.playlist_inited = true;
+                    self.emit_queue_changed().await?;
+                }
+                AudioThreadMessage::MoveSong {
+                    from_index,
+                    to_index,
+                } => {
+                    let snapshot = self.playlist.clone();
+                    let snapshot_index = self.current_play_index;
+                    if move_song(
+                        &mut self.playlist,
+                        &mut self.current_play_index,
+                        *from_index,
+                        *to_index,
+                    ) {
+                        self.queue_history.record(&snapshot, snapshot_index);
+                        self.emit_queue_changed().await?;
+                    } else {
+                        warn!("移动歌曲失败，下标越界：{from_index} -> {to_index}");
+                    }
+                }
+                AudioThreadMessage::UndoQueueChange => {
+                    if let Some((playlist, current_play_index)) =
+                        self.queue_history.undo(self.current_song.as_ref())
+                    {
+                        self.playlist = playlist;
+                        self.current_play_index = current_play_index;
+                        self.emit_queue_changed().await?;
+                    }
                 }
                 AudioThreadMessage::SetFFTRange { from_freq, to_freq } => {
                     let fft_player_clone = self.fft_player.clone();
@@ -477,6 +1007,22 @@ impl AudioPlayer {
                     })
                     .await?;
                 }
+                AudioThreadMessage::SetFFTBands { count } => {
+                    let fft_player_clone = self.fft_player.clone();
+                    let count = *count;
+                    tokio::task::spawn_blocking(move || {
+                        fft_player_clone.write().set_band_count(count);
+                    })
+                    .await?;
+                }
+                AudioThreadMessage::SetBeatDetection { enabled } => {
+                    let fft_player_clone = self.fft_player.clone();
+                    let enabled = *enabled;
+                    tokio::task::spawn_blocking(move || {
+                        fft_player_clone.write().set_onset_detection(enabled);
+                    })
+                    .await?;
+                }
                 AudioThreadMessage::SetMediaControlsEnabled { enabled } => {
                     if let Some(manager) = self.media_state_manager.as_ref()
                         && let Err(e) = manager.set_enabled(*enabled)
@@ -493,7 +1039,12 @@ impl AudioPlayer {
     }
 
     async fn start_playing_song(&mut self, clear_sink: bool) -> anyhow::Result<()> {
+        if self.loop_region.is_some() {
+            self.set_loop_region(None).await?;
+        }
+        self.current_chapter = None;
         if clear_sink {
+            self.fade_out_current().await;
             self.sink.stop();
 
             let fft_player_clone = self.fft_player.clone();
@@ -503,52 +1054,889 @@ impl AudioPlayer {
             .await?;
 
             self.sink = Arc::new(Sink::connect_new(&self.stream_handle.mixer()));
-            self.sink.set_volume(self.volume as f32);
             self.current_decoder_handle = None;
         }
 
+        if let Some(queued) = self.queued_next.take() {
+            queued.handle.stop();
+        }
+        self.next_preload_attempted = false;
+
         let song_data = self.current_song.clone().context("没有当前歌曲可播放")?;
-        let file_path = match song_data {
+        let file_path = match &song_data {
+            SongData::Local { file_path, .. } => file_path.clone(),
+            _ => return Err(anyhow!("当前实现仅支持本地文件")),
+        };
+
+        let output_format = self.desired_output_format(&file_path).await;
+        self.apply_output_format(output_format).await;
+
+        let (source, handle) = self.open_decoder(&song_data, file_path).await?;
+        self.configure_decoder(&handle);
+        self.current_decoder_handle = Some(handle);
+
+        let info = source.audio_info();
+        let quality = source.audio_quality();
+
+        *self.current_audio_info.write().await = info;
+        *self.current_audio_quality.write().await = quality.clone();
+        self.emit_track_opened(quality).await?;
+
+        self.sink.append(source);
+        self.update_media_manager_metadata().await?;
+
+        let is_playing = !self.sink.is_paused();
+        self.update_media_manager_playback_state(is_playing).await?;
+
+        let _ = self.play_pos_sx.send((is_playing, 0.0));
+        self.sync_ui().await?;
+
+        Ok(())
+    }
+
+    /// 跳转到当前歌曲的指定位置，单位为秒
+    async fn seek_to(&mut self, position: f64) -> anyhow::Result<()> {
+        if self
+            .loop_region
+            .is_some_and(|region| position < region.start_secs() || position >= region.end_secs())
+        {
+            self.set_loop_region(None).await?;
+        }
+        if self.current_audio_info.read().await.is_live {
+            warn!("当前播放的是直播流，无法跳转");
+        } else if let Some(handle) = &self.current_decoder_handle {
+            let seek_pos = Duration::from_secs_f64(position);
+
+            self.fade_out_current().await;
+            let seek_result = handle.seek(seek_pos);
+            if !self.sink.is_paused() {
+                handle.fade_to(1.0, self.fade.duration());
+            }
+            if seek_result.is_err() {
+                warn!("发送跳转命令失败, 解码器可能已关闭");
+            } else {
+                let fft_player_clone = self.fft_player.clone();
+                tokio::task::spawn_blocking(move || {
+                    fft_player_clone.write().clear();
+                })
+                .await?;
+                let is_playing = !self.sink.is_paused();
+                let _ = self.play_pos_sx.send((is_playing, position));
+                self.update_media_manager_playback_state(is_playing).await?;
+            }
+        } else {
+            warn!("找不到解码器句柄, 无法执行跳转");
+        }
+        Ok(())
+    }
+
+    /// 把解码器跳过或无法恢复的错误通知前端
+    async fn report_decode_errors(&self) -> anyhow::Result<()> {
+        let Some(handle) = &self.current_decoder_handle else {
+            return Ok(());
+        };
+        let music_id = self
+            .current_song
+            .as_ref()
+            .map(|s| s.get_id())
+            .unwrap_or_default();
+        for report in handle.take_decode_errors() {
+            self.emitter()
+                .emit(AudioThreadEvent::DecodeError {
+                    music_id: music_id.clone(),
+                    error: report.message,
+                    recovered: report.recovered,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 播放进度进入另一个章节时通知前端
+    async fn update_current_chapter(&mut self) -> anyhow::Result<()> {
+        let position = *self.current_position.read().await;
+        let chapter = {
+            let audio_info = self.current_audio_info.read().await;
+            audio_info
+                .chapters
+                .iter()
+                .position(|chapter| position >= chapter.start && position < chapter.end)
+                .map(|index| (index, audio_info.chapters[index].clone()))
+        };
+        let index = chapter.as_ref().map(|(index, _)| *index);
+        if index == self.current_chapter {
+            return Ok(());
+        }
+        self.current_chapter = index;
+        self.emitter()
+            .emit(AudioThreadEvent::ChapterChanged {
+                index,
+                chapter: chapter.map(|(_, chapter)| chapter),
+            })
+            .await
+    }
+
+    /// 设置或取消当前歌曲的 A-B 循环，区间无效或当前是直播流时保持原样
+    async fn set_loop_region(&mut self, region: Option<LoopRegion>) -> anyhow::Result<()> {
+        let region = match region {
+            Some(mut region) => {
+                let audio_info = self.current_audio_info.read().await;
+                if audio_info.is_live {
+                    warn!("当前播放的是直播流，无法设置循环区间");
+                    return Ok(());
+                }
+                if audio_info.duration > 0.0 {
+                    region.end_ms = region.end_ms.min((audio_info.duration * 1000.0) as u64);
+                }
+                drop(audio_info);
+                if region.end_ms < region.start_ms + MIN_LOOP_MS {
+                    warn!("循环区间过短或无效：{region:?}");
+                    return Ok(());
+                }
+                Some(region)
+            }
+            None => None,
+        };
+
+        if let Some(handle) = &self.current_decoder_handle {
+            let bounds = region.map(|region| {
+                (
+                    Duration::from_secs_f64(region.start_secs()),
+                    Duration::from_secs_f64(region.end_secs()),
+                )
+            });
+            if handle.set_loop(bounds).is_err() {
+                warn!("发送循环区间失败, 解码器可能已关闭");
+            }
+        }
+        self.loop_region = region;
+        self.emitter()
+            .emit(AudioThreadEvent::LoopRegionChanged { region })
+            .await
+    }
+
+    /// 解码器到达循环终点时会自行跳回起点，这里让播放进度跟着回到起点
+    async fn update_loop_position(&self) {
+        let Some(region) = self.loop_region else {
+            return;
+        };
+        if self.sink.is_paused() {
+            return;
+        }
+        let position = *self.current_position.read().await;
+        if position >= region.end_secs() {
+            let looped = region.start_secs() + (position - region.end_secs());
+            *self.current_position.write().await = looped;
+            let _ = self.play_pos_sx.send((true, looped));
+        }
+    }
+
+    /// 解码器跳过的静音开始播放时，把播放进度向前推进同样的时长
+    async fn update_skipped_silence(&self) {
+        let Some(handle) = &self.current_decoder_handle else {
+            return;
+        };
+        let skipped_secs = handle.take_skipped_secs();
+        if skipped_secs <= 0.0 {
+            return;
+        }
+        let duration = self.current_audio_info.read().await.duration;
+        let mut position = *self.current_position.read().await + skipped_secs;
+        if duration > 0.0 {
+            position = position.min(duration);
+        }
+        *self.current_position.write().await = position;
+        let _ = self.play_pos_sx.send((!self.sink.is_paused(), position));
+    }
+
+    /// 音量滑块位置对应的 Sink 增益
+    fn output_gain(&self) -> f32 {
+        volume_to_gain(self.volume) as f32
+    }
+
+    /// 考虑静音后最终应当使用的音量
+    fn sink_gain(&self) -> f32 {
+        if self.muted { 0.0 } else { self.output_gain() }
+    }
+
+    /// 主播放使用的音量，试听期间会被压低，让试听的声音更清楚
+    fn main_gain(&self) -> f32 {
+        if self.preview.is_some() {
+            self.sink_gain() * PREVIEW_DUCK_GAIN
+        } else {
+            self.sink_gain()
+        }
+    }
+
+    /// 在 `ramp` 时长内把正在播放和已经加入无缝播放队列的歌曲的音量变到目标值
+    ///
+    /// 主播放的 Sink 音量始终为 1，音量由解码器在抖动之前作用在采样上，
+    /// 试听不做抖动，仍然直接设置 Sink 的音量
+    fn apply_output_volume(&self, ramp: Duration) {
+        let handles = self
+            .current_decoder_handle
+            .iter()
+            .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+        for handle in handles {
+            handle.set_volume(self.main_gain(), ramp);
+        }
+        if let Some(preview) = &self.preview {
+            preview.sink.set_volume(self.sink_gain());
+        }
+    }
+
+    /// 静音或取消静音，音量在 `fade_ms` 毫秒内线性渐变到目标值，而不是直接切断
+    async fn set_muted(&mut self, muted: bool, fade_ms: u32) -> anyhow::Result<()> {
+        if muted != self.muted {
+            self.muted = muted;
+            // 解码器从当前的音量开始渐变，上一次渐变未完成时也能平滑衔接
+            self.apply_output_volume(Duration::from_millis(fade_ms.min(MAX_MUTE_FADE_MS) as u64));
+        }
+        self.emitter()
+            .emit(AudioThreadEvent::MuteChanged { muted: self.muted })
+            .await
+    }
+
+    /// 按照输出设备当前的采样格式更新解码器的抖动设置
+    fn apply_dither(&self, handle: &FFmpegDecoderHandle) {
+        let output_bits = integer_sample_bits(self.stream_handle.sample_format());
+        handle.set_dither(self.dither, output_bits);
+    }
+
+    /// 让正在播放的歌曲淡出，并等待淡出完成，暂停中或未开启淡入淡出时立即返回
+    async fn fade_out_current(&self) {
+        let duration = self.fade.duration();
+        if duration.is_zero() || self.sink.is_paused() {
+            return;
+        }
+        if let Some(handle) = &self.current_decoder_handle {
+            handle.fade_to(0.0, duration);
+            tokio::time::sleep(duration).await;
+        }
+    }
+
+    async fn pause_with_fade(&self) {
+        self.fade_out_current().await;
+        self.sink.pause();
+    }
+
+    /// 暂停时输出增益停留在零，继续播放时需要淡入回来
+    fn resume_with_fade(&self) {
+        self.sink.play();
+        if let Some(handle) = &self.current_decoder_handle {
+            handle.fade_to(1.0, self.fade.duration());
+        }
+    }
+
+    /// 开启或关闭独占输出，无法独占时退回共享输出并把原因通知前端
+    #[cfg(target_os = "macos")]
+    async fn set_exclusive_output(&mut self, enabled: bool) -> anyhow::Result<()> {
+        if enabled == self.exclusive_output.is_some() {
+            return Ok(());
+        }
+        let mut error = None;
+        self.exclusive_output = if enabled {
+            match ExclusiveOutput::acquire() {
+                Ok(exclusive) => {
+                    info!("已独占音频输出设备");
+                    Some(exclusive)
+                }
+                Err(err) => {
+                    warn!("独占音频输出设备失败，继续使用共享输出：{err:?}");
+                    error = Some(format!("{err:#}"));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        self.emitter()
+            .emit(AudioThreadEvent::ExclusiveOutputChanged {
+                active: self.exclusive_output.is_some(),
+                error,
+            })
+            .await
+    }
+
+    /// 开启或关闭 WASAPI 独占输出，从下一首歌曲开始生效，无法独占的原因在打开设备时通知前端
+    #[cfg(target_os = "windows")]
+    async fn set_exclusive_output(&mut self, enabled: bool) -> anyhow::Result<()> {
+        if enabled == self.exclusive_output {
+            return Ok(());
+        }
+        self.exclusive_output = enabled;
+        self.emitter()
+            .emit(AudioThreadEvent::ExclusiveOutputChanged {
+                active: enabled,
+                error: None,
+            })
+            .await
+    }
+
+    /// 是否正在独占输出设备
+    #[cfg(target_os = "macos")]
+    fn is_exclusive(&self) -> bool {
+        self.exclusive_output.is_some()
+    }
+
+    #[cfg(target_os = "windows")]
+    fn is_exclusive(&self) -> bool {
+        self.exclusive_output
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn is_exclusive(&self) -> bool {
+        false
+    }
+
+    /// 取得播放指定歌曲时输出设备应当使用的格式，未开启按源格式输出时为空
+    ///
+    /// 独占输出时总是按源格式打开，避免设备端再做重采样
+    async fn desired_output_format(&self, file_path: &str) -> Option<SourceOutputFormat> {
+        if !self.follow_source_format && !self.is_exclusive() {
+            return None;
+        }
+        let file_path = file_path.to_string();
+        let result = tokio::task::spawn_blocking(move || probe_audio_format(&file_path))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+        match result {
+            Ok((sample_rate, channels, source_format)) => Some(SourceOutputFormat::new(
+                sample_rate,
+                channels,
+                source_format,
+            )),
+            Err(err) => {
+                warn!("读取歌曲的音频格式失败：{err:?}");
+                None
+            }
+        }
+    }
+
+    /// 按照给定的格式重新打开输出设备，格式与当前一致时什么都不做
+    ///
+    /// Windows 上开启独占输出时以 WASAPI 独占模式打开，无法独占时退回共享输出并通知前端。
+    /// 重新打开会替换掉当前的 Sink，打开失败时继续使用当前的输出
+    async fn apply_output_format(&mut self, output_format: Option<SourceOutputFormat>) {
+        let wasapi_exclusive =
+            cfg!(target_os = "windows") && self.is_exclusive() && output_format.is_some();
+        if output_format == self.output_format
+            && wasapi_exclusive == self.stream_handle.is_wasapi_exclusive()
+        {
+            return;
+        }
+
+        let Some(stream) = self.open_output(output_format, wasapi_exclusive).await else {
+            return;
+        };
+
+        self.stop_preview().await;
+        self.sink.stop();
+        self.stream_handle = stream;
+        self.output_format = output_format;
+
+        self.target_channels = self.stream_handle.channels();
+        self.target_sample_rate = self.stream_handle.sample_rate();
+        info!(
+            "重新打开音频输出 声道数:{}, 采样率:{}",
+            self.target_channels, self.target_sample_rate
+        );
+
+        self.sink = Arc::new(Sink::connect_new(&self.stream_handle.mixer()));
+        self.current_decoder_handle = None;
+    }
+
+    /// 按照给定的格式打开输出设备，打开失败时返回空
+    #[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+    async fn open_output(
+        &mut self,
+        output_format: Option<SourceOutputFormat>,
+        wasapi_exclusive: bool,
+    ) -> Option<OutputHandle> {
+        #[cfg(target_os = "windows")]
+        if let Some(format) = output_format
+            && wasapi_exclusive
+        {
+            // 设备同一时间只能被独占一次，重新独占之前先关闭当前的独占输出
+            if self.stream_handle.is_wasapi_exclusive() {
+                self.stop_preview().await;
+                self.sink.stop();
+                match OutputStreamBuilder::open_default_stream() {
+                    Ok(stream) => {
+                        self.stream_handle = OutputHandle::Shared(stream);
+                        self.output_format = None;
+                        self.sink = Arc::new(Sink::connect_new(self.stream_handle.mixer()));
+                        self.current_decoder_handle = None;
+                    }
+                    Err(err) => {
+                        warn!("关闭独占输出时打开共享输出失败：{err:?}");
+                        return None;
+                    }
+                }
+            }
+            match WasapiExclusiveStream::open(
+                format.sample_rate,
+                format.channels,
+                format.sample_format,
+            ) {
+                Ok(stream) => {
+                    info!("已以 WASAPI 独占模式打开音频输出");
+                    return Some(OutputHandle::Exclusive(stream));
+                }
+                Err(err) => {
+                    warn!("以 WASAPI 独占模式打开音频输出失败，改用共享输出：{err:?}");
+                    self.exclusive_output = false;
+                    let event = AudioThreadEvent::ExclusiveOutputChanged {
+                        active: false,
+                        error: Some(format!("{err:#}")),
+                    };
+                    if let Err(err) = self.emitter().emit(event).await {
+                        warn!("发送独占输出状态失败：{err:?}");
+                    }
+                }
+            }
+        }
+
+        let stream = match output_format {
+            Some(format) => OutputStreamBuilder::from_default_device().and_then(|builder| {
+                builder
+                    .with_sample_rate(format.sample_rate)
+                    .with_channels(format.channels)
+                    .with_sample_format(format.sample_format)
+                    .open_stream()
+            }),
+            None => OutputStreamBuilder::open_default_stream(),
+        };
+        match stream {
+            Ok(stream) => Some(OutputHandle::Shared(stream)),
+            Err(err) => {
+                warn!("以 {output_format:?} 打开音频输出失败，继续使用当前的输出：{err:?}");
+                None
+            }
+        }
+    }
+
+    fn next_play_index(&self) -> Option<usize> {
+        (!self.playlist.is_empty()).then(|| (self.current_play_index + 1) % self.playlist.len())
+    }
+
+    /// 维护无缝播放的预加载：临近结尾时把下一首歌曲的解码器提前加入 Sink，
+    /// 当前歌曲播放完毕、Sink 开始播放它时再切换当前歌曲的状态
+    async fn update_gapless_queue(&mut self) -> anyhow::Result<()> {
+        if let Some(queued) = &self.queued_next {
+            let still_next = self.next_play_index() == Some(queued.play_index)
+                && self
+                    .playlist
+                    .get(queued.play_index)
+                    .is_some_and(|song| song.get_id() == queued.song.get_id());
+            if !still_next {
+                // 队列已被修改，放弃预加载，之后按普通方式切换到新的下一首
+                queued.handle.stop();
+                self.queued_next = None;
+            } else if self.sink.len() <= 1 {
+                self.promote_queued_track().await?;
+            }
+            return Ok(());
+        }
+
+        // 循环播放时不会播到结尾
+        if self.next_preload_attempted
+            || self.loop_region.is_some()
+            || self.current_song.is_none()
+            || self.sink.len() != 1
+        {
+            return Ok(());
+        }
+        let duration = self.current_audio_info.read().await.duration;
+        let position = *self.current_position.read().await;
+        if duration <= 0.0 || duration - position > GAPLESS_PRELOAD_SECS {
+            return Ok(());
+        }
+
+        self.next_preload_attempted = true;
+        let Some(play_index) = self.next_play_index() else {
+            return Ok(());
+        };
+        let song = self.playlist[play_index].clone();
+        let SongData::Local { file_path, .. } = &song else {
+            return Ok(());
+        };
+        // 下一首需要以不同的格式重新打开输出设备时无法无缝衔接，留到切歌时处理
+        if self.desired_output_format(file_path).await != self.output_format {
+            return Ok(());
+        }
+
+        let (source, handle) = self.open_decoder(&song, file_path.clone()).await?;
+
+        self.configure_decoder(&handle);
+        let audio_info = source.audio_info();
+        let audio_quality = source.audio_quality();
+        self.sink.append(source);
+        self.queued_next = Some(QueuedTrack {
+            play_index,
+            song,
+            handle,
+            audio_info,
+            audio_quality,
+        });
+        Ok(())
+    }
+
+    /// 按当前的响度均衡设置、前级增益和扫描记录更新解码器的增益
+    fn apply_loudness(&self, handle: &FFmpegDecoderHandle) {
+        let scanned = self.scanned_loudness.get(handle.path()).copied();
+        handle.set_loudness(&self.loudness, self.pre_amp_db, scanned);
+    }
+
+    /// 更新正在播放和已经加入无缝播放队列的歌曲的增益
+    fn apply_loudness_to_all(&self) {
+        let handles = self
+            .current_decoder_handle
+            .iter()
+            .chain(self.queued_next.as_ref().map(|queued| &queued.handle));
+        for handle in handles {
+            self.apply_loudness(handle);
+        }
+    }
+
+    /// 把当前的音效和播放设置应用到新打开的解码器上
+    fn configure_decoder(&self, handle: &FFmpegDecoderHandle) {
+        handle.set_volume(self.main_gain(), Duration::ZERO);
+        self.apply_loudness(handle);
+        handle.set_equalizer(&self.equalizer);
+        handle.set_balance(&self.balance);
+        handle.set_limiter(&self.limiter);
+        self.apply_dither(handle);
+        if self.silence_skip.enabled {
+            let _ = handle.set_silence_skip(self.silence_skip);
+        }
+        if self.playback_rate != 1.0 {
+            let _ = handle.set_tempo(self.playback_rate);
+        }
+        if self.pitch_semitones != 0.0 {
+            let _ = handle.set_pitch(self.pitch_semitones);
+        }
+        if self.build_seek_index {
+            handle.build_seek_index();
+        }
+    }
+
+    /// 在后台打开一首歌曲的解码器并开始缓冲，替换掉之前预加载的歌曲
+    ///
+    /// 之后切换到这首歌曲（包括无缝播放）时直接使用已经打开的解码器，
+    /// 网络音源也不需要再等待连接和缓冲
+    fn preload(&mut self, song: SongData) -> anyhow::Result<()> {
+        let music_id = song.get_id();
+        if self
+            .preloaded
+            .as_ref()
+            .is_some_and(|preloaded| preloaded.music_id == music_id)
+        {
+            return Ok(());
+        }
+        // 丢弃旧的预加载时，解码器会在打开完成后随任务结果一起释放
+        self.preloaded = None;
+
+        let file_path = match song {
             SongData::Local { file_path, .. } => file_path,
             _ => return Err(anyhow!("当前实现仅支持本地文件")),
         };
+        let task = self.spawn_open_decoder(file_path);
+        self.preloaded = Some(PreloadedTrack {
+            music_id,
+            target_channels: self.target_channels,
+            target_sample_rate: self.target_sample_rate,
+            downmix: self.downmix,
+            task,
+        });
+        Ok(())
+    }
 
+    /// 按当前的输出配置在后台线程打开解码器
+    fn spawn_open_decoder(
+        &self,
+        file_path: String,
+    ) -> JoinHandle<anyhow::Result<(FFmpegDecoder, FFmpegDecoderHandle)>> {
+        let fft_player_clone = self.fft_player.clone();
         let target_channels = self.target_channels;
         let target_sample_rate = self.target_sample_rate;
-
-        let fft_player_clone = self.fft_player.clone();
-        let file_path_clone = file_path.clone();
-
-        let source_result = tokio::task::spawn_blocking(move || {
+        let downmix = self.downmix;
+        tokio::task::spawn_blocking(move || {
             FFmpegDecoder::new(
-                file_path_clone,
+                file_path,
                 fft_player_clone,
                 target_channels,
                 target_sample_rate,
+                downmix,
+                None,
             )
         })
-        .await?;
+    }
 
-        let (source, handle) = source_result?;
-        self.current_decoder_handle = Some(handle);
+    /// 打开歌曲的解码器，切歌和无缝播放的预加载共用
+    ///
+    /// 之前通过 `Preload` 预加载过这首歌曲时直接使用预加载的解码器，否则重新打开
+    async fn open_decoder(
+        &mut self,
+        song: &SongData,
+        file_path: String,
+    ) -> anyhow::Result<(FFmpegDecoder, FFmpegDecoderHandle)> {
+        match self.take_preloaded(song).await {
+            Some(decoder) => Ok(decoder),
+            None => self.spawn_open_decoder(file_path).await?,
+        }
+    }
 
-        let info = source.audio_info();
-        let quality = source.audio_quality();
+    /// 取出预加载的解码器，歌曲或输出配置不一致、打开失败时返回空，由调用方重新打开
+    async fn take_preloaded(
+        &mut self,
+        song: &SongData,
+    ) -> Option<(FFmpegDecoder, FFmpegDecoderHandle)> {
+        let preloaded = self.preloaded.take()?;
+        if preloaded.music_id != song.get_id() {
+            self.preloaded = Some(preloaded);
+            return None;
+        }
+        if preloaded.target_channels != self.target_channels
+            || preloaded.target_sample_rate != self.target_sample_rate
+            || preloaded.downmix != self.downmix
+        {
+            return None;
+        }
+        match preloaded.task.await {
+            Ok(Ok(decoder)) => Some(decoder),
+            Ok(Err(err)) => {
+                warn!("预加载的歌曲打开失败，重新打开：{err:?}");
+                None
+            }
+            Err(err) => {
+                warn!("预加载任务异常结束，重新打开：{err:?}");
+                None
+            }
+        }
+    }
 
-        *self.current_audio_info.write().await = info;
-        *self.current_audio_quality.write().await = quality;
+    async fn emit_track_opened(&self, quality: AudioQuality) -> anyhow::Result<()> {
+        self.emitter()
+            .emit(AudioThreadEvent::TrackOpened {
+                music_id: self
+                    .current_song
+                    .as_ref()
+                    .map(|s| s.get_id())
+                    .unwrap_or_default(),
+                quality,
+            })
+            .await
+    }
 
-        self.sink.append(source);
+    /// Sink 已经开始播放预加载的歌曲，把它设为当前歌曲
+    async fn promote_queued_track(&mut self) -> anyhow::Result<()> {
+        let Some(queued) = self.queued_next.take() else {
+            return Ok(());
+        };
+        self.current_play_index = queued.play_index;
+        self.current_song = Some(queued.song);
+        self.current_decoder_handle = Some(queued.handle);
+        self.next_preload_attempted = false;
+
+        *self.current_audio_info.write().await = queued.audio_info;
+        *self.current_audio_quality.write().await = queued.audio_quality.clone();
+        self.emit_track_opened(queued.audio_quality).await?;
         self.update_media_manager_metadata().await?;
 
         let is_playing = !self.sink.is_paused();
-        self.update_media_manager_playback_state(is_playing).await?;
-
         let _ = self.play_pos_sx.send((is_playing, 0.0));
-        self.sync_ui().await?;
+        self.sync_ui().await
+    }
+
+    async fn start_preview(
+        &mut self,
+        song: SongData,
+        start_position: f64,
+        duration: f64,
+    ) -> anyhow::Result<()> {
+        self.stop_preview().await;
+
+        let music_id = song.get_id();
+        let file_path = match song {
+            SongData::Local { file_path, .. } => file_path,
+            _ => return Err(anyhow!("当前实现仅支持本地文件")),
+        };
+
+        let target_channels = self.target_channels;
+        let target_sample_rate = self.target_sample_rate;
+        let downmix = self.downmix;
+        let start_position = Duration::from_secs_f64(start_position.max(0.0));
+        let duration = Duration::from_secs_f64(duration.max(0.0));
+
+        // 试听的频谱数据不需要展示，交给一个单独的 FFTPlayer 以免干扰主播放的频谱
+        let fft_player = Arc::new(ParkingLotRwLock::new(FFTPlayer::new()));
+        let (source, _handle) = tokio::task::spawn_blocking(move || {
+            FFmpegDecoder::new(
+                file_path,
+                fft_player,
+                target_channels,
+                target_sample_rate,
+                downmix,
+                Some(start_position),
+            )
+        })
+        .await??;
+
+        let sink = Sink::connect_new(&self.stream_handle.mixer());
+        sink.set_volume(self.sink_gain());
+        sink.append(source.take_duration(duration));
+
+        self.preview = Some(PreviewSession {
+            music_id: music_id.clone(),
+            sink,
+        });
+        self.apply_output_volume(PREVIEW_DUCK_RAMP);
+        self.emitter()
+            .emit(AudioThreadEvent::PreviewStatus {
+                music_id,
+                is_previewing: true,
+            })
+            .await
+    }
+
+    /// 通知前端播放队列及其撤销历史已经变化
+    async fn emit_queue_changed(&self) -> anyhow::Result<()> {
+        let emitter = self.emitter();
+        emitter
+            .emit(AudioThreadEvent::PlayListChanged {
+                playlist: self.playlist.clone(),
+                current_play_index: self.current_play_index,
+            })
+            .await?;
+        emitter
+            .emit(AudioThreadEvent::QueueHistoryChanged {
+                can_undo: self.queue_history.can_undo(),
+            })
+            .await
+    }
+
+    /// 取得要导出的本地歌曲路径，未指定歌曲时使用当前歌曲
+    fn local_file_path(&self, song: Option<SongData>) -> anyhow::Result<String> {
+        let song = song
+            .or_else(|| self.current_song.clone())
+            .context("没有可导出的歌曲")?;
+        match song {
+            SongData::Local { file_path, .. } => Ok(file_path),
+            _ => Err(anyhow!("当前实现仅支持本地文件")),
+        }
+    }
+
+    fn start_waveform_export(&self, options: WaveformOptions) {
+        let evt_sender = self.evt_sender.clone();
+        tokio::spawn(async move {
+            let output_path = options.output_path.clone();
+            let result = tokio::task::spawn_blocking(move || export_waveform(options))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+
+            if let Err(err) = &result {
+                warn!("导出波形失败：{err:?}");
+            } else {
+                info!("波形已导出到 {output_path}");
+            }
+            let _ = evt_sender.send(AudioThreadEventMessage::new(
+                "".into(),
+                Some(AudioThreadEvent::WaveformExported {
+                    output_path,
+                    error: result.err().map(|err| format!("{err:?}")),
+                }),
+            ));
+        });
+    }
+
+    fn start_export(
+        &mut self,
+        song: Option<SongData>,
+        output_path: String,
+        format: AudioExportFormat,
+        start_position: f64,
+        duration: Option<f64>,
+    ) -> anyhow::Result<()> {
+        if self.export.as_ref().is_some_and(|e| !e.task.is_finished()) {
+            return Err(anyhow!("已有正在进行的导出任务"));
+        }
+
+        let file_path = self.local_file_path(song)?;
+        let options = ExportOptions {
+            scanned_loudness: self.scanned_loudness.get(&file_path).copied(),
+            file_path,
+            output_path: output_path.clone(),
+            format,
+            start_position: Duration::from_secs_f64(start_position.max(0.0)),
+            duration: duration.map(|d| Duration::from_secs_f64(d.max(0.0))),
+            volume: self.output_gain(),
+            target_channels: self.target_channels,
+            target_sample_rate: self.target_sample_rate,
+            downmix: self.downmix,
+            loudness: self.loudness,
+            pre_amp_db: self.pre_amp_db,
+            equalizer: self.equalizer,
+            balance: self.balance,
+            limiter: self.limiter,
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let evt_sender = self.evt_sender.clone();
+
+        let task = tokio::spawn({
+            let cancelled = cancelled.clone();
+            async move {
+                let progress_sender = evt_sender.clone();
+                let progress_path = output_path.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    export_audio(options, cancelled, |progress| {
+                        let _ = progress_sender.send(AudioThreadEventMessage::new(
+                            "".into(),
+                            Some(AudioThreadEvent::ExportProgress {
+                                output_path: progress_path.clone(),
+                                progress,
+                            }),
+                        ));
+                    })
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+
+                if let Err(err) = &result {
+                    warn!("导出音频失败：{err:?}");
+                } else {
+                    info!("音频已导出到 {output_path}");
+                }
+                let _ = evt_sender.send(AudioThreadEventMessage::new(
+                    "".into(),
+                    Some(AudioThreadEvent::ExportFinished {
+                        output_path,
+                        error: result.err().map(|err| format!("{err:?}")),
+                    }),
+                ));
+            }
+        });
 
+        self.export = Some(ExportTask { cancelled, task });
         Ok(())
     }
+
+    async fn stop_preview(&mut self) {
+        if let Some(preview) = self.preview.take() {
+            preview.sink.stop();
+            self.apply_output_volume(PREVIEW_DUCK_RAMP);
+            let _ = self
+                .emitter()
+                .emit(AudioThreadEvent::PreviewStatus {
+                    music_id: preview.music_id,
+                    is_previewing: false,
+                })
+                .await;
+        }
+    }
 }
 
 impl Drop for AudioPlayer {
@@ -559,6 +1947,9 @@ impl Drop for AudioPlayer {
         if let Some(handle) = self.fft_broadcast_task.take() {
             handle.abort();
         }
+        if let Some(export) = self.export.take() {
+            export.cancelled.store(true, Ordering::Relaxed);
+        }
     }
 }
 
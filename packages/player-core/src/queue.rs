@@ -0,0 +1,205 @@
+//! 播放队列的编辑操作（插播、添加到队列、拖拽重排），以及用于撤销这些操作的历史记录
+//!
+//! 这些操作都不会改变当前正在播放的歌曲，只会调整它在队列中的下标
+
+use std::collections::VecDeque;
+
+use crate::SongData;
+
+/// 最多保留的撤销步数
+const MAX_HISTORY_LEN: usize = 50;
+
+struct QueueSnapshot {
+    playlist: Vec<SongData>,
+    current_play_index: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct QueueHistory {
+    snapshots: VecDeque<QueueSnapshot>,
+}
+
+impl QueueHistory {
+    /// 在修改播放队列前记录一次快照
+    pub fn record(&mut self, playlist: &[SongData], current_play_index: usize) {
+        self.snapshots.push_back(QueueSnapshot {
+            playlist: playlist.to_vec(),
+            current_play_index,
+        });
+        if self.snapshots.len() > MAX_HISTORY_LEN {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// 恢复到上一次记录的播放队列，返回恢复后的队列和当前歌曲的下标
+    ///
+    /// 记录之后可能已经切换过歌曲，所以会在恢复的队列中重新查找当前歌曲的位置，
+    /// 找不到时才使用记录时的下标
+    pub fn undo(&mut self, current_song: Option<&SongData>) -> Option<(Vec<SongData>, usize)> {
+        let snapshot = self.snapshots.pop_back()?;
+        let current_play_index = current_song
+            .and_then(|song| find_nearest(&snapshot.playlist, song, snapshot.current_play_index))
+            .unwrap_or(snapshot.current_play_index)
+            .min(snapshot.playlist.len().saturating_sub(1));
+        Some((snapshot.playlist, current_play_index))
+    }
+}
+
+/// 在队列中查找距离 `hint` 最近的同一首歌曲，队列中可能有重复的歌曲
+fn find_nearest(playlist: &[SongData], song: &SongData, hint: usize) -> Option<usize> {
+    playlist
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| *s == song)
+        .min_by_key(|(i, _)| i.abs_diff(hint))
+        .map(|(i, _)| i)
+}
+
+/// 将歌曲插入到当前歌曲之后，没有正在播放的歌曲时插入到当前下标处
+pub(crate) fn insert_play_next(
+    playlist: &mut Vec<SongData>,
+    current_play_index: usize,
+    has_current_song: bool,
+    songs: &[SongData],
+) {
+    let position = if has_current_song {
+        current_play_index + 1
+    } else {
+        current_play_index
+    }
+    .min(playlist.len());
+    playlist.splice(position..position, songs.iter().cloned());
+}
+
+/// 将 `from` 处的歌曲移动到 `to` 处，并让 `current_play_index` 继续指向当前歌曲
+///
+/// 下标越界时不做任何修改并返回 `false`
+pub(crate) fn move_song(
+    playlist: &mut Vec<SongData>,
+    current_play_index: &mut usize,
+    from: usize,
+    to: usize,
+) -> bool {
+    if from >= playlist.len() || to >= playlist.len() {
+        return false;
+    }
+    let song = playlist.remove(from);
+    playlist.insert(to, song);
+
+    let current = *current_play_index;
+    *current_play_index = if from == current {
+        to
+    } else if from < current && to >= current {
+        current - 1
+    } else if from > current && to <= current {
+        current + 1
+    } else {
+        current
+    };
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(name: &str) -> SongData {
+        SongData::Local {
+            file_path: name.to_string(),
+            orig_order: 0,
+        }
+    }
+
+    fn playlist(names: &[&str]) -> Vec<SongData> {
+        names.iter().map(|name| song(name)).collect()
+    }
+
+    /// 移动后的队列和当前下标，当前下标应当仍然指向 `c`
+    fn moved(from: usize, to: usize) -> (Vec<SongData>, usize) {
+        let mut list = playlist(&["a", "b", "c", "d", "e"]);
+        let mut current = 2;
+        assert!(move_song(&mut list, &mut current, from, to));
+        assert_eq!(list[current], song("c"));
+        (list, current)
+    }
+
+    #[test]
+    fn move_song_keeps_current_song() {
+        // 从前面移到后面，跨过当前歌曲
+        assert_eq!(moved(0, 3), (playlist(&["b", "c", "d", "a", "e"]), 1));
+        // 从后面移到前面，跨过当前歌曲
+        assert_eq!(moved(4, 1), (playlist(&["a", "e", "b", "c", "d"]), 3));
+        // 移到当前歌曲的位置上
+        assert_eq!(moved(0, 2), (playlist(&["b", "c", "a", "d", "e"]), 1));
+        assert_eq!(moved(3, 2), (playlist(&["a", "b", "d", "c", "e"]), 3));
+        // 不跨过当前歌曲
+        assert_eq!(moved(3, 4), (playlist(&["a", "b", "c", "e", "d"]), 2));
+        // 移动当前歌曲本身
+        assert_eq!(moved(2, 0), (playlist(&["c", "a", "b", "d", "e"]), 0));
+    }
+
+    #[test]
+    fn move_song_rejects_out_of_range() {
+        let mut list = playlist(&["a", "b"]);
+        let mut current = 1;
+        assert!(!move_song(&mut list, &mut current, 2, 0));
+        assert!(!move_song(&mut list, &mut current, 0, 2));
+        assert_eq!((list, current), (playlist(&["a", "b"]), 1));
+    }
+
+    #[test]
+    fn undo_after_insert_restores_queue() {
+        let mut list = playlist(&["a", "b", "c"]);
+        let mut history = QueueHistory::default();
+
+        history.record(&list, 1);
+        insert_play_next(&mut list, 1, true, &playlist(&["x", "y"]));
+        assert_eq!(list, playlist(&["a", "b", "x", "y", "c"]));
+
+        assert!(history.can_undo());
+        assert_eq!(
+            history.undo(Some(&song("b"))),
+            Some((playlist(&["a", "b", "c"]), 1))
+        );
+        assert!(!history.can_undo());
+        assert_eq!(history.undo(Some(&song("b"))), None);
+    }
+
+    #[test]
+    fn undo_follows_current_song() {
+        let mut list = playlist(&["a", "b", "c"]);
+        let mut history = QueueHistory::default();
+
+        history.record(&list, 0);
+        insert_play_next(&mut list, 0, true, &playlist(&["x"]));
+        // 插播之后切到了下一首 `x`，之后又切到了 `b`
+        assert_eq!(
+            history.undo(Some(&song("b"))),
+            Some((playlist(&["a", "b", "c"]), 1))
+        );
+
+        // 当前歌曲在恢复的队列中不存在时使用记录时的下标
+        history.record(&list, 1);
+        assert_eq!(
+            history.undo(Some(&song("x"))),
+            Some((playlist(&["a", "x", "b", "c"]), 1))
+        );
+    }
+
+    #[test]
+    fn insert_without_current_song_uses_current_index() {
+        let mut list = playlist(&["a", "b"]);
+        insert_play_next(&mut list, 1, false, &playlist(&["x"]));
+        assert_eq!(list, playlist(&["a", "x", "b"]));
+        insert_play_next(&mut list, 10, true, &playlist(&["y"]));
+        assert_eq!(list, playlist(&["a", "x", "b", "y"]));
+    }
+}
@@ -0,0 +1,248 @@
+//! 使用 FFmpeg 的 ebur128 滤镜离线测量歌曲的 EBU R128 响度，换算为 ReplayGain 2.0 的增益
+//!
+//! 扫描结果不会写回音频文件，而是由宿主程序保存后通过
+//! [`AudioThreadMessage::SetScannedLoudness`](crate::AudioThreadMessage::SetScannedLoudness)
+//! 交给播放器，文件本身缺少的增益标签会用这里的记录补上
+
+use std::sync::atomic::AtomicBool;
+
+use anyhow::{Context, anyhow};
+use ffmpeg_next as ffmpeg;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    http_source::open_input,
+    loudness::REPLAYGAIN_REFERENCE_LUFS,
+    offline_decode::{DecodeTarget, decode_to_f32},
+    utils::{ensure_ffmpeg_initialized, read_audio_tags},
+};
+
+/// ebur128 滤镜对静音输出的响度，低于等于该值时无法得到有意义的增益
+const SILENCE_LUFS: f64 = -70.0;
+
+/// 一首歌曲的测量结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackLoudness {
+    /// 整体响度，单位为 LUFS
+    pub integrated_lufs: f64,
+    /// 真峰值，线性幅度
+    pub peak: f64,
+    pub duration_secs: f64,
+    /// 文件标签中的专辑名，用于把歌曲归入同一张专辑计算专辑增益
+    pub album: String,
+}
+
+impl TrackLoudness {
+    /// 达到 ReplayGain 参考响度所需的增益，单位为 dB
+    pub fn gain_db(&self) -> f64 {
+        loudness_to_gain_db(self.integrated_lufs)
+    }
+}
+
+/// 把响度换算为 ReplayGain 2.0 的增益，单位为 dB
+pub fn loudness_to_gain_db(lufs: f64) -> f64 {
+    REPLAYGAIN_REFERENCE_LUFS - lufs
+}
+
+/// 按时长加权合并同一张专辑中各歌曲的响度，返回专辑响度（LUFS）与专辑峰值
+///
+/// 各歌曲的门限是分别计算的，所以结果与把整张专辑连起来测量会有细微差别
+pub fn album_loudness(tracks: &[TrackLoudness]) -> Option<(f64, f64)> {
+    let total_secs: f64 = tracks.iter().map(|track| track.duration_secs).sum();
+    if total_secs <= 0.0 {
+        return None;
+    }
+    let energy = tracks
+        .iter()
+        .map(|track| track.duration_secs * 10f64.powf(track.integrated_lufs / 10.0))
+        .sum::<f64>()
+        / total_secs;
+    let peak = tracks
+        .iter()
+        .fold(0.0f64, |peak, track| peak.max(track.peak));
+    Some((10.0 * energy.log10(), peak))
+}
+
+/// 完整解码一首歌曲并测量响度，会阻塞当前线程直到解码完成
+///
+/// `cancelled` 被置为真时会尽快停止并返回错误，单个损坏的数据包会被跳过
+pub fn scan_track_loudness(path: &str, cancelled: &AtomicBool) -> anyhow::Result<TrackLoudness> {
+    ensure_ffmpeg_initialized()?;
+    let mut input_ctx = open_input(path)?;
+    let album = read_audio_tags(&input_ctx).album;
+
+    let mut meter: Option<LoudnessMeter> = None;
+    decode_to_f32(
+        &mut input_ctx,
+        path,
+        DecodeTarget::default(),
+        cancelled,
+        |frame| LoudnessMeter::feed(&mut meter, frame),
+    )?;
+
+    let meter = meter.ok_or_else(|| anyhow!("没有解码出任何音频"))?;
+    meter.finish(album)
+}
+
+struct LoudnessMeter {
+    graph: ffmpeg::filter::Graph,
+    sample_rate: u32,
+    next_pts: i64,
+    integrated_lufs: Option<f64>,
+    peak: f64,
+}
+
+impl LoudnessMeter {
+    /// 滤镜按第一帧的格式创建
+    fn feed(meter: &mut Option<Self>, frame: &mut ffmpeg::frame::Audio) -> anyhow::Result<()> {
+        if meter.is_none() {
+            *meter = Some(Self::new(frame)?);
+        }
+        match meter {
+            Some(meter) => meter.add(frame),
+            None => Ok(()),
+        }
+    }
+
+    fn new(frame: &ffmpeg::frame::Audio) -> anyhow::Result<Self> {
+        let sample_rate = frame.rate();
+        let channels = frame.channels();
+        // 滤镜输入的声道布局必须与送入的帧完全一致，否则会被当作中途改变格式而拒绝
+        let layout = frame.channel_layout();
+        let channel_layout = if layout.is_empty() {
+            format!("{channels}c")
+        } else {
+            format!("0x{:x}", layout.bits())
+        };
+        let mut graph = ffmpeg::filter::Graph::new();
+        let args = format!(
+            "time_base=1/{sample_rate}:sample_rate={sample_rate}:sample_fmt={}:channel_layout={channel_layout}",
+            frame.format().name()
+        );
+        graph.add(
+            &ffmpeg::filter::find("abuffer").context("找不到 abuffer 滤镜")?,
+            "in",
+            &args,
+        )?;
+        graph.add(
+            &ffmpeg::filter::find("abuffersink").context("找不到 abuffersink 滤镜")?,
+            "out",
+            "",
+        )?;
+        // R128 建议把单声道当作两个相同的声道测量
+        let spec = if channels == 1 {
+            "ebur128=metadata=1:peak=true:dualmono=true"
+        } else {
+            "ebur128=metadata=1:peak=true"
+        };
+        graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+        graph.validate()?;
+
+        Ok(Self {
+            graph,
+            sample_rate,
+            next_pts: 0,
+            integrated_lufs: None,
+            peak: 0.0,
+        })
+    }
+
+    fn add(&mut self, frame: &mut ffmpeg::frame::Audio) -> anyhow::Result<()> {
+        frame.set_pts(Some(self.next_pts));
+        self.next_pts += frame.samples() as i64;
+        self.graph
+            .get("in")
+            .ok_or_else(|| anyhow!("找不到滤镜输入"))?
+            .source()
+            .add(frame)?;
+        self.drain()
+    }
+
+    /// 每一块输出都带有截至目前的整体响度和峰值
+    fn drain(&mut self) -> anyhow::Result<()> {
+        let mut sink = self
+            .graph
+            .get("out")
+            .ok_or_else(|| anyhow!("找不到滤镜输出"))?;
+        let mut measured = ffmpeg::frame::Audio::empty();
+        while sink.sink().frame(&mut measured).is_ok() {
+            let metadata = measured.metadata();
+            if let Some(lufs) = metadata
+                .get("lavfi.r128.I")
+                .and_then(|value| value.parse::<f64>().ok())
+            {
+                self.integrated_lufs = Some(lufs);
+            }
+            if let Some(peak) = metadata
+                .get("lavfi.r128.true_peak")
+                .and_then(|value| value.parse::<f64>().ok())
+            {
+                self.peak = self.peak.max(peak);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, album: String) -> anyhow::Result<TrackLoudness> {
+        self.graph
+            .get("in")
+            .ok_or_else(|| anyhow!("找不到滤镜输入"))?
+            .source()
+            .flush()?;
+        self.drain()?;
+
+        let integrated_lufs = self
+            .integrated_lufs
+            .filter(|lufs| lufs.is_finite() && *lufs > SILENCE_LUFS)
+            .ok_or_else(|| anyhow!("音频过短或全为静音，无法测量响度"))?;
+        Ok(TrackLoudness {
+            integrated_lufs,
+            peak: self.peak,
+            duration_secs: self.next_pts as f64 / self.sample_rate.max(1) as f64,
+            album,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(integrated_lufs: f64, peak: f64, duration_secs: f64) -> TrackLoudness {
+        TrackLoudness {
+            integrated_lufs,
+            peak,
+            duration_secs,
+            album: String::new(),
+        }
+    }
+
+    #[test]
+    fn gain_is_relative_to_replaygain_reference() {
+        assert_eq!(loudness_to_gain_db(-18.0), 0.0);
+        assert_eq!(track(-8.0, 1.0, 1.0).gain_db(), -10.0);
+        assert_eq!(loudness_to_gain_db(-23.0), 5.0);
+    }
+
+    #[test]
+    fn album_loudness_weights_by_duration() {
+        let (lufs, peak) =
+            album_loudness(&[track(-10.0, 0.5, 60.0), track(-10.0, 0.9, 180.0)]).unwrap();
+        assert!((lufs - -10.0).abs() < 1e-9);
+        assert_eq!(peak, 0.9);
+
+        // 能量按时长加权，较长的安静歌曲把专辑响度拉低
+        let (lufs, _) =
+            album_loudness(&[track(-10.0, 1.0, 60.0), track(-20.0, 1.0, 180.0)]).unwrap();
+        let expected =
+            10.0 * ((10f64.powf(-1.0) * 60.0 + 10f64.powf(-2.0) * 180.0) / 240.0).log10();
+        assert!((lufs - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn album_loudness_without_duration_is_none() {
+        assert_eq!(album_loudness(&[]), None);
+        assert_eq!(album_loudness(&[track(-10.0, 1.0, 0.0)]), None);
+    }
+}
@@ -0,0 +1,65 @@
+//! 播放器线程向音频输出线程传递设置
+//!
+//! 音频回调中不能阻塞等待锁。输出线程只在有新设置时尝试加锁，
+//! 锁正被播放器线程占用时留到下一次再取，有状态的处理器（如滤波器）始终由输出线程独占
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::Mutex;
+
+pub(crate) struct SettingsSlot<T> {
+    pending: AtomicBool,
+    value: Mutex<Option<T>>,
+}
+
+impl<T> Default for SettingsSlot<T> {
+    fn default() -> Self {
+        Self {
+            pending: AtomicBool::new(false),
+            value: Mutex::new(None),
+        }
+    }
+}
+
+impl<T> SettingsSlot<T> {
+    /// 发布新的设置，覆盖尚未被取走的旧设置
+    pub fn publish(&self, value: T) {
+        *self.value.lock() = Some(value);
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// 取出最新的设置，没有新设置或锁正被占用时返回空，不会阻塞
+    pub fn take(&self) -> Option<T> {
+        if !self.pending.load(Ordering::Acquire) {
+            return None;
+        }
+        let mut value = self.value.try_lock()?;
+        self.pending.store(false, Ordering::Relaxed);
+        value.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_returns_latest_value_once() {
+        let slot = SettingsSlot::default();
+        assert_eq!(slot.take(), None);
+        slot.publish(1);
+        slot.publish(2);
+        assert_eq!(slot.take(), Some(2));
+        assert_eq!(slot.take(), None);
+    }
+
+    #[test]
+    fn take_does_not_block_while_locked() {
+        let slot = SettingsSlot::default();
+        slot.publish(1);
+        let guard = slot.value.lock();
+        assert_eq!(slot.take(), None);
+        drop(guard);
+        assert_eq!(slot.take(), Some(1));
+    }
+}
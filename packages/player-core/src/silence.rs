@@ -0,0 +1,110 @@
+//! 跳过歌曲开头、结尾和中间（例如隐藏曲目之前）的长段静音
+//!
+//! 解码出的采样块峰值低于阈值时视为静音，连续静音达到设定时长后开始丢弃，
+//! 短于该时长的静音会原样保留，避免切掉正常的停顿
+
+use serde::{Deserialize, Serialize};
+
+/// 静音跳过设置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SilenceSkipOptions {
+    pub enabled: bool,
+    /// 低于该峰值电平的采样视为静音，单位为 dBFS
+    pub threshold_db: f64,
+    /// 连续静音达到该时长才会被跳过，单位为毫秒
+    pub min_duration_ms: u32,
+}
+
+impl Default for SilenceSkipOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -60.0,
+            min_duration_ms: 2000,
+        }
+    }
+}
+
+/// 等待判断是否属于长段静音的采样块
+pub(crate) struct PendingChunk {
+    pub player_samples: Vec<f32>,
+    pub fft_samples: Vec<f32>,
+    pub start_secs: f64,
+}
+
+pub(crate) struct SilenceSkipper {
+    threshold: f32,
+    min_frames: u64,
+    sample_rate: u32,
+    channels: usize,
+    pending: Vec<PendingChunk>,
+    pending_frames: u64,
+    skipping: bool,
+    skipped_frames: u64,
+}
+
+impl SilenceSkipper {
+    /// 未开启时返回空
+    pub fn new(options: &SilenceSkipOptions, sample_rate: u32, channels: u16) -> Option<Self> {
+        if !options.enabled {
+            return None;
+        }
+        Some(Self {
+            threshold: 10f64.powf(options.threshold_db.min(0.0) / 20.0) as f32,
+            min_frames: options.min_duration_ms as u64 * sample_rate as u64 / 1000,
+            sample_rate,
+            channels: channels.max(1) as usize,
+            pending: Vec::new(),
+            pending_frames: 0,
+            skipping: false,
+            skipped_frames: 0,
+        })
+    }
+
+    /// 送入一个采样块，返回可以输出的采样块，以及在它们之前被跳过的时长（秒）
+    pub fn push(&mut self, chunk: PendingChunk) -> (Vec<PendingChunk>, f64) {
+        let frames = (chunk.player_samples.len() / self.channels) as u64;
+        let is_silent = chunk
+            .player_samples
+            .iter()
+            .all(|sample| sample.abs() < self.threshold);
+
+        if is_silent {
+            if self.skipping {
+                self.skipped_frames += frames;
+            } else {
+                self.pending.push(chunk);
+                self.pending_frames += frames;
+                if self.pending_frames >= self.min_frames {
+                    self.skipping = true;
+                    self.skipped_frames = self.pending_frames;
+                    self.pending.clear();
+                    self.pending_frames = 0;
+                }
+            }
+            return (Vec::new(), 0.0);
+        }
+
+        let skipped_secs = self.skipped_frames as f64 / self.sample_rate.max(1) as f64;
+        let mut ready = std::mem::take(&mut self.pending);
+        ready.push(chunk);
+        self.pending_frames = 0;
+        self.skipping = false;
+        self.skipped_frames = 0;
+        (ready, skipped_secs)
+    }
+
+    /// 音频结束时取出还在等待判断的短静音，结尾的长段静音则直接丢弃
+    pub fn finish(&mut self) -> Vec<PendingChunk> {
+        self.pending_frames = 0;
+        self.skipping = false;
+        self.skipped_frames = 0;
+        std::mem::take(&mut self.pending)
+    }
+
+    /// 跳转后之前的状态不再有意义
+    pub fn reset(&mut self) {
+        self.finish();
+    }
+}
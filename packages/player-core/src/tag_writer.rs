@@ -0,0 +1,217 @@
+//! 修改歌曲文件的标签和内嵌封面
+//!
+//! 使用 lofty 直接在原文件中改写标签，音频数据保持不动，不需要重新封装整个文件。
+//! 只支持 MP3、FLAC 和 M4A
+
+use std::{io::Cursor, path::Path};
+
+use anyhow::{Context, bail};
+use lofty::{
+    config::WriteOptions,
+    file::TaggedFileExt,
+    picture::{Picture, PictureType},
+    tag::{Accessor, ItemKey, Tag, TagExt},
+};
+use serde::{Deserialize, Serialize};
+
+const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a"];
+
+/// 要修改的标签，为空的字段保持原样，文本字段为空字符串时会删除该标签
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TagUpdate {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub genre: Option<String>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub year: Option<i32>,
+    /// 新的封面图片，支持 JPEG 和 PNG，会替换文件中原有的所有图片
+    pub cover: Option<Vec<u8>>,
+}
+
+impl TagUpdate {
+    /// 把修改应用到文件的主标签上，没有修改的字段保持原样
+    fn apply(&self, tag: &mut Tag) -> anyhow::Result<()> {
+        let texts = [
+            (ItemKey::TrackTitle, &self.title),
+            (ItemKey::TrackArtist, &self.artist),
+            (ItemKey::AlbumTitle, &self.album),
+            (ItemKey::AlbumArtist, &self.album_artist),
+            (ItemKey::Composer, &self.composer),
+            (ItemKey::Genre, &self.genre),
+        ];
+        for (key, value) in texts {
+            let Some(value) = value else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                tag.remove_key(&key);
+            } else {
+                tag.insert_text(key, value.to_string());
+            }
+        }
+
+        // 编号和总数分开保存，只修改其中一个时另一个自然沿用文件中原有的值
+        if let Some(number) = self.track_number {
+            tag.set_track(number);
+        }
+        if let Some(total) = self.track_total {
+            tag.set_track_total(total);
+        }
+        if let Some(number) = self.disc_number {
+            tag.set_disk(number);
+        }
+        if let Some(total) = self.disc_total {
+            tag.set_disk_total(total);
+        }
+        if let Some(year) = self.year {
+            tag.set_year(u32::try_from(year).context("年份不能为负数")?);
+        }
+
+        if let Some(cover) = &self.cover {
+            ensure_cover_format(cover)?;
+            let mut picture =
+                Picture::from_reader(&mut Cursor::new(cover)).context("无法读取封面")?;
+            picture.set_pic_type(PictureType::CoverFront);
+            while !tag.pictures().is_empty() {
+                tag.remove_picture(0);
+            }
+            tag.push_picture(picture);
+        }
+        Ok(())
+    }
+}
+
+fn ensure_cover_format(data: &[u8]) -> anyhow::Result<()> {
+    match data {
+        [0xff, 0xd8, 0xff, ..] | [0x89, b'P', b'N', b'G', ..] => Ok(()),
+        _ => bail!("封面只支持 JPEG 和 PNG 图片"),
+    }
+}
+
+/// 修改本地歌曲文件的标签，文件中还没有标签时按格式创建一个
+///
+/// 正在播放的歌曲在部分系统上无法写入，此时会返回错误
+pub fn write_tags(path: &str, update: &TagUpdate) -> anyhow::Result<()> {
+    let source = Path::new(path);
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    if !SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
+        bail!("不支持修改 {extension} 文件的标签");
+    }
+
+    let mut tagged_file =
+        lofty::read_from_path(source).with_context(|| format!("无法打开文件: {path}"))?;
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+    let tag = tagged_file
+        .primary_tag_mut()
+        .context("无法创建文件的标签")?;
+    update.apply(tag)?;
+    tag.save_to_path(source, WriteOptions::default())
+        .context("无法写入标签，文件可能正在被使用")
+}
+
+#[cfg(test)]
+mod tests {
+    use lofty::tag::TagType;
+
+    use super::*;
+
+    const PNG: &[u8] = &[
+        0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d, b'I', b'H', b'D', b'R', 0,
+        0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0, 0x1f, 0x15, 0xc4, 0x89,
+    ];
+
+    fn tag_with_title() -> Tag {
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.set_title("旧标题".to_string());
+        tag.set_artist("歌手".to_string());
+        tag.set_track(3);
+        tag.set_track_total(12);
+        tag
+    }
+
+    #[test]
+    fn untouched_fields_are_kept() {
+        let mut tag = tag_with_title();
+        let update = TagUpdate {
+            title: Some("  新标题 ".to_string()),
+            ..Default::default()
+        };
+        update.apply(&mut tag).unwrap();
+        assert_eq!(tag.title().as_deref(), Some("新标题"));
+        assert_eq!(tag.artist().as_deref(), Some("歌手"));
+        assert_eq!(tag.track(), Some(3));
+        assert_eq!(tag.track_total(), Some(12));
+    }
+
+    #[test]
+    fn empty_text_removes_the_field() {
+        let mut tag = tag_with_title();
+        let update = TagUpdate {
+            artist: Some(String::new()),
+            ..Default::default()
+        };
+        update.apply(&mut tag).unwrap();
+        assert_eq!(tag.artist(), None);
+        assert_eq!(tag.title().as_deref(), Some("旧标题"));
+    }
+
+    #[test]
+    fn changing_number_keeps_total() {
+        let mut tag = tag_with_title();
+        let update = TagUpdate {
+            track_number: Some(5),
+            disc_total: Some(2),
+            ..Default::default()
+        };
+        update.apply(&mut tag).unwrap();
+        assert_eq!(tag.track(), Some(5));
+        assert_eq!(tag.track_total(), Some(12));
+        assert_eq!(tag.disk(), None);
+        assert_eq!(tag.disk_total(), Some(2));
+    }
+
+    #[test]
+    fn negative_year_is_rejected() {
+        let update = TagUpdate {
+            year: Some(-1),
+            ..Default::default()
+        };
+        assert!(update.apply(&mut tag_with_title()).is_err());
+    }
+
+    #[test]
+    fn cover_replaces_all_pictures() {
+        let mut tag = tag_with_title();
+        let update = TagUpdate {
+            cover: Some(PNG.to_vec()),
+            ..Default::default()
+        };
+        update.apply(&mut tag).unwrap();
+        update.apply(&mut tag).unwrap();
+        assert_eq!(tag.pictures().len(), 1);
+        assert_eq!(tag.pictures()[0].pic_type(), PictureType::CoverFront);
+    }
+
+    #[test]
+    fn unsupported_cover_is_rejected() {
+        let update = TagUpdate {
+            cover: Some(b"GIF89a".to_vec()),
+            ..Default::default()
+        };
+        assert!(update.apply(&mut tag_with_title()).is_err());
+    }
+}
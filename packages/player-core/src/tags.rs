@@ -0,0 +1,119 @@
+//! 读取并规范化歌曲文件的标签，供曲库扫描和正在播放界面使用
+//!
+//! 不同容器的标签键名不同（ID3 的 `TRCK` 为 `3/12`，Vorbis 注释则分为 `TRACKNUMBER` 和 `TRACKTOTAL`），
+//! FFmpeg 已经把常见的键名统一为小写的通用名称，这里再把编号和日期解析为数字
+
+use ffmpeg_next as ffmpeg;
+use serde::{Deserialize, Serialize};
+
+use crate::{http_source::open_input, utils::ensure_ffmpeg_initialized};
+
+/// 规范化后的歌曲标签，缺少的文本字段为空字符串，缺少的数字字段为空
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioTags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub composer: String,
+    pub genre: String,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    /// 发行年份，取自日期标签的开头
+    pub year: Option<i32>,
+    /// 时长，单位为秒，无法确定时为 0
+    pub duration: f64,
+}
+
+/// 读取歌曲文件的标签，只读取容器头部，不会解码音频
+pub fn read_tags(path: &str) -> anyhow::Result<AudioTags> {
+    ensure_ffmpeg_initialized()?;
+    let input_ctx = open_input(path)?;
+
+    let mut tags = AudioTags::default();
+    let stream = input_ctx.streams().best(ffmpeg::media::Type::Audio);
+    // Ogg 等容器把标签放在音频流上，流上的标签优先
+    for metadata in
+        std::iter::once(input_ctx.metadata()).chain(stream.as_ref().map(|stream| stream.metadata()))
+    {
+        for (key, value) in metadata.iter() {
+            tags.apply_tag(key, value.trim());
+        }
+    }
+
+    if input_ctx.duration() > 0 {
+        tags.duration = input_ctx.duration() as f64 / ffmpeg::ffi::AV_TIME_BASE as f64;
+    } else if let Some(stream) = &stream
+        && stream.duration() > 0
+    {
+        tags.duration = stream.duration() as f64 * f64::from(stream.time_base());
+    }
+    Ok(tags)
+}
+
+impl AudioTags {
+    fn apply_tag(&mut self, key: &str, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        match key.to_ascii_lowercase().as_str() {
+            "title" => self.title = value.to_string(),
+            "artist" => self.artist = value.to_string(),
+            "album" => self.album = value.to_string(),
+            "album_artist" | "albumartist" | "album artist" => {
+                self.album_artist = value.to_string()
+            }
+            "composer" => self.composer = value.to_string(),
+            "genre" => self.genre = value.to_string(),
+            "track" | "tracknumber" => {
+                let (number, total) = parse_position(value);
+                self.track_number = number.or(self.track_number);
+                self.track_total = total.or(self.track_total);
+            }
+            "tracktotal" | "totaltracks" => self.track_total = parse_number(value),
+            "disc" | "discnumber" => {
+                let (number, total) = parse_position(value);
+                self.disc_number = number.or(self.disc_number);
+                self.disc_total = total.or(self.disc_total);
+            }
+            "disctotal" | "totaldiscs" => self.disc_total = parse_number(value),
+            "date" | "year" | "tdrc" | "tyer" => {
+                if let Some(year) = parse_year(value) {
+                    self.year = Some(year);
+                }
+            }
+            // 原始发行日期只在没有其他日期时使用
+            "originaldate" => {
+                if self.year.is_none() {
+                    self.year = parse_year(value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 解析 `3` 或 `3/12` 形式的编号
+fn parse_position(value: &str) -> (Option<u32>, Option<u32>) {
+    match value.split_once('/') {
+        Some((number, total)) => (parse_number(number), parse_number(total)),
+        None => (parse_number(value), None),
+    }
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    value.trim().parse().ok().filter(|&number| number > 0)
+}
+
+/// 日期标签可能是 `2020`、`2020-05-01` 或 `2020-05-01T00:00:00` 等形式，只取开头的四位年份
+fn parse_year(value: &str) -> Option<i32> {
+    let digits: String = value.trim().chars().take(4).collect();
+    if digits.len() == 4 && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
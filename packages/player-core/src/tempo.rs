@@ -0,0 +1,140 @@
+//! 变速不变调与变调不变速处理，使用 FFmpeg 的 atempo、asetrate 滤镜
+//!
+//! 处理的是重采样之后的交错 f32 采样，因此与音源格式无关。
+//! 变调时先用 asetrate 改变采样率（同时改变音高和速度），重采样回原采样率后再用 atempo 把速度补偿回来
+
+use anyhow::{Context, anyhow};
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::ChannelLayout;
+
+pub const MIN_TEMPO: f64 = 0.5;
+pub const MAX_TEMPO: f64 = 2.0;
+pub const MIN_PITCH_SEMITONES: f64 = -12.0;
+pub const MAX_PITCH_SEMITONES: f64 = 12.0;
+
+const SAMPLE_BYTES: usize = std::mem::size_of::<f32>();
+
+pub(crate) struct TempoStage {
+    graph: ffmpeg::filter::Graph,
+    sample_rate: u32,
+    channels: usize,
+    next_pts: i64,
+}
+
+impl TempoStage {
+    pub fn new(
+        tempo: f64,
+        pitch_semitones: f64,
+        sample_rate: u32,
+        channels: u16,
+    ) -> anyhow::Result<Self> {
+        let mut graph = ffmpeg::filter::Graph::new();
+        let args = format!(
+            "time_base=1/{sample_rate}:sample_rate={sample_rate}:sample_fmt=flt:channel_layout={channels}c"
+        );
+        graph.add(
+            &ffmpeg::filter::find("abuffer").context("找不到 abuffer 滤镜")?,
+            "in",
+            &args,
+        )?;
+        graph.add(
+            &ffmpeg::filter::find("abuffersink").context("找不到 abuffersink 滤镜")?,
+            "out",
+            "",
+        )?;
+        graph.output("in", 0)?.input("out", 0)?.parse(&filter_spec(
+            tempo,
+            pitch_semitones,
+            sample_rate,
+        ))?;
+        graph.validate()?;
+
+        Ok(Self {
+            graph,
+            sample_rate,
+            channels: channels.max(1) as usize,
+            next_pts: 0,
+        })
+    }
+
+    /// 把一段交错采样送入滤镜，并用滤镜目前能输出的采样替换它
+    pub fn process(&mut self, samples: &mut Vec<f32>) -> anyhow::Result<()> {
+        let frames = samples.len() / self.channels;
+        if frames > 0 {
+            let mut frame = ffmpeg::frame::Audio::new(
+                ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed),
+                frames,
+                ChannelLayout::default(self.channels as i32),
+            );
+            frame.set_rate(self.sample_rate);
+            frame.set_pts(Some(self.next_pts));
+            self.next_pts += frames as i64;
+
+            let bytes = &mut frame.data_mut(0)[..frames * self.channels * SAMPLE_BYTES];
+            for (dst, sample) in bytes.chunks_exact_mut(SAMPLE_BYTES).zip(samples.iter()) {
+                dst.copy_from_slice(&sample.to_ne_bytes());
+            }
+            self.input()?.source().add(&frame)?;
+        }
+
+        samples.clear();
+        self.drain(samples)
+    }
+
+    /// 取出滤镜中剩余的全部采样，用于音频结束时
+    pub fn flush(&mut self, out: &mut Vec<f32>) -> anyhow::Result<()> {
+        self.input()?.source().flush()?;
+        self.drain(out)
+    }
+
+    fn input(&mut self) -> anyhow::Result<ffmpeg::filter::Context<'_>> {
+        self.graph
+            .get("in")
+            .ok_or_else(|| anyhow!("找不到滤镜输入"))
+    }
+
+    fn drain(&mut self, out: &mut Vec<f32>) -> anyhow::Result<()> {
+        let channels = self.channels;
+        let mut sink = self
+            .graph
+            .get("out")
+            .ok_or_else(|| anyhow!("找不到滤镜输出"))?;
+        let mut filtered = ffmpeg::frame::Audio::empty();
+        while sink.sink().frame(&mut filtered).is_ok() {
+            let len = filtered.samples() * channels * SAMPLE_BYTES;
+            out.extend(
+                filtered.data(0)[..len]
+                    .chunks_exact(SAMPLE_BYTES)
+                    .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// 生成滤镜链描述，atempo 单个实例只接受 0.5 到 2 倍，超出时拆成多个串联
+fn filter_spec(tempo: f64, pitch_semitones: f64, sample_rate: u32) -> String {
+    let tempo = tempo.clamp(MIN_TEMPO, MAX_TEMPO);
+    let semitones = pitch_semitones.clamp(MIN_PITCH_SEMITONES, MAX_PITCH_SEMITONES);
+
+    let mut filters = Vec::new();
+    let mut remaining_tempo = tempo;
+    if semitones != 0.0 {
+        let shifted_rate = (sample_rate as f64 * 2f64.powf(semitones / 12.0)).round();
+        filters.push(format!("asetrate={shifted_rate}"));
+        filters.push(format!("aresample={sample_rate}"));
+        remaining_tempo /= shifted_rate / sample_rate as f64;
+    }
+
+    while remaining_tempo > MAX_TEMPO {
+        filters.push(format!("atempo={MAX_TEMPO}"));
+        remaining_tempo /= MAX_TEMPO;
+    }
+    while remaining_tempo < MIN_TEMPO {
+        filters.push(format!("atempo={MIN_TEMPO}"));
+        remaining_tempo /= MIN_TEMPO;
+    }
+    filters.push(format!("atempo={remaining_tempo}"));
+
+    filters.join(",")
+}
@@ -1,7 +1,61 @@
-use crate::AudioInfo;
+use std::{
+    sync::{Once, OnceLock},
+    time::Instant,
+};
+
+use crate::{
+    AudioInfo, Chapter,
+    cover::read_embedded_cover,
+    dsd::{DSD_MAX_PCM_OUTPUT_RATE, is_dsd_codec},
+    embedded_lyrics::read_tag_lyrics,
+    http_source::open_input,
+};
+use anyhow::anyhow;
 use ffmpeg_next as ffmpeg;
+use tracing::info;
+
+static FFMPEG_INIT: OnceLock<Result<(), ffmpeg::Error>> = OnceLock::new();
+static FFMPEG_NETWORK_INIT: Once = Once::new();
+
+/// 初始化 ffmpeg，只有第一次调用时会真正执行初始化，之后直接返回第一次的结果
+///
+/// 为了加快冷启动，ffmpeg 不在程序启动时初始化，而是在第一次打开本地文件前调用此函数
+pub fn ensure_ffmpeg_initialized() -> anyhow::Result<()> {
+    let result = FFMPEG_INIT.get_or_init(|| {
+        let start = Instant::now();
+        let result = ffmpeg::init();
+        info!("ffmpeg 初始化完成，耗时 {:?}", start.elapsed());
+        result
+    });
+    result.map_err(|err| anyhow!("初始化 ffmpeg 失败: {err}"))
+}
+
+/// 初始化 ffmpeg 的网络模块，只在第一次直接交给 ffmpeg 打开网络地址前调用
+pub fn ensure_ffmpeg_network_initialized() {
+    FFMPEG_NETWORK_INIT.call_once(ffmpeg::format::network::init);
+}
+
+/// 读取音频文件中音频流的采样率、声道数和解码后的采样格式，不会解码任何音频数据
+pub fn probe_audio_format(path: &str) -> anyhow::Result<(u32, u16, ffmpeg::format::Sample)> {
+    ensure_ffmpeg_initialized()?;
+    let input_ctx = open_input(path)?;
+    let stream = input_ctx
+        .streams()
+        .best(ffmpeg::media::Type::Audio)
+        .ok_or_else(|| anyhow!("找不到音频流"))?;
+    let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .audio()?;
+    let rate = if is_dsd_codec(decoder.id()) {
+        decoder.rate().min(DSD_MAX_PCM_OUTPUT_RATE)
+    } else {
+        decoder.rate()
+    };
+    Ok((rate, decoder.channels() as u16, decoder.format()))
+}
 
-pub fn read_audio_info(input_ctx: &mut ffmpeg::format::context::Input) -> AudioInfo {
+/// 只读取容器中的文本标签，不包括封面
+pub fn read_audio_tags(input_ctx: &ffmpeg::format::context::Input) -> AudioInfo {
     let mut new_audio_info = AudioInfo::default();
 
     let metadata = input_ctx.metadata();
@@ -14,30 +68,35 @@ pub fn read_audio_info(input_ctx: &mut ffmpeg::format::context::Input) -> AudioI
     if let Some(album) = metadata.get("album") {
         new_audio_info.album = album.to_string();
     }
-    if let Some(lyrics) = metadata.get("lyrics") {
-        new_audio_info.lyric = lyrics.to_string();
-    }
+    new_audio_info.lyric = read_tag_lyrics(input_ctx);
     if let Some(comment) = metadata.get("comment") {
         new_audio_info.comment = comment.to_string();
     }
-
-    'outer: for (stream, packet) in input_ctx.packets() {
-        if stream
-            .disposition()
-            .contains(ffmpeg::format::stream::Disposition::ATTACHED_PIC)
-        {
-            if let Some(data) = packet.data() {
-                new_audio_info.cover = Some(data.to_vec());
-                let codec_name = ffmpeg::codec::decoder::find(stream.parameters().id())
-                    .map(|d| d.name().to_string())
-                    .unwrap_or("unknown".to_string());
-                new_audio_info.cover_media_type = format!("image/{}", codec_name.to_lowercase());
-                break 'outer;
+    new_audio_info.chapters = input_ctx
+        .chapters()
+        .map(|chapter| {
+            let time_base = f64::from(chapter.time_base());
+            Chapter {
+                title: chapter
+                    .metadata()
+                    .get("title")
+                    .unwrap_or_default()
+                    .to_string(),
+                start: chapter.start() as f64 * time_base,
+                end: chapter.end() as f64 * time_base,
             }
-        }
-    }
+        })
+        .collect();
 
-    input_ctx.seek(0, ..).ok();
+    new_audio_info
+}
 
+/// 读取文本标签和内嵌的封面，封面在打开文件时已经读入，不会扫描数据包
+pub fn read_audio_info(input_ctx: &ffmpeg::format::context::Input) -> AudioInfo {
+    let mut new_audio_info = read_audio_tags(input_ctx);
+    if let Some(cover) = read_embedded_cover(input_ctx) {
+        new_audio_info.cover = Some(cover.data);
+        new_audio_info.cover_media_type = cover.media_type;
+    }
     new_audio_info
 }
@@ -0,0 +1,31 @@
+//! 把音量滑块的位置换算为实际的增益
+//!
+//! 人耳对响度的感知近似对数，直接把滑块位置当作线性增益时，滑块的下半段几乎都挤在最响的那一侧。
+//! 这里按分贝均匀分布：滑块从 0 到 1 对应 [`MIN_VOLUME_DB`] 到 0 dB，
+//! 并在最底部一小段线性收拢到静音，使滑块拉到 0 时完全无声
+
+/// 滑块位置接近 0 时对应的衰减，再往下会线性收拢到静音
+pub const MIN_VOLUME_DB: f64 = -50.0;
+/// 前级增益的可调范围，单位为 dB
+pub const MAX_PRE_AMP_DB: f64 = 12.0;
+/// 滑块最底部的这一段线性收拢到静音
+const MUTE_RAMP: f64 = 0.05;
+
+/// 把 0 到 1 的滑块位置换算为线性增益
+pub fn volume_to_gain(volume: f64) -> f64 {
+    let volume = volume.clamp(0.0, 1.0);
+    if volume <= 0.0 {
+        return 0.0;
+    }
+    let gain = 10f64.powf((1.0 - volume) * MIN_VOLUME_DB / 20.0);
+    if volume < MUTE_RAMP {
+        gain * volume / MUTE_RAMP
+    } else {
+        gain
+    }
+}
+
+/// 把分贝换算为线性增益
+pub fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
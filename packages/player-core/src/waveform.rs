@@ -0,0 +1,385 @@
+//! 导出一段音频的波形以及歌词音节的边界，用于排查歌词时间轴与音频对不上的问题
+//!
+//! 可以导出为 PNG 图片，音节的开始和结束位置会以不同颜色的竖线画在波形上，
+//! 也可以导出为 JSON 数据，方便附在 issue 中或用其它工具进一步分析
+//!
+//! [`read_waveform_peaks`] 则只返回降采样后的峰值数据，供前端绘制波形进度条
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use ffmpeg_next::{self as ffmpeg, ChannelLayout};
+use parking_lot::RwLock;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ffmpeg_decoder::{DownmixOptions, FFmpegDecoder},
+    fft_player::FFTPlayer,
+    http_source::open_input,
+    offline_decode::{DecodeTarget, decode_to_f32},
+    utils::ensure_ffmpeg_initialized,
+};
+
+/// 波形只需要大致的振幅，使用单声道和较低的采样率解码以加快速度
+const WAVEFORM_CHANNELS: u16 = 1;
+const WAVEFORM_SAMPLE_RATE: u32 = 16000;
+const DEFAULT_WIDTH: u32 = 1600;
+const DEFAULT_HEIGHT: u32 = 320;
+/// 图片尺寸的上限，避免传入过大的尺寸时分配数 GB 的像素缓冲
+const MAX_WIDTH: u32 = 16384;
+const MAX_HEIGHT: u32 = 4096;
+/// 进度条上的波形只有几百个像素宽，更低的采样率足以保留峰值的大致轮廓
+const PEAKS_SAMPLE_RATE: u32 = 8000;
+const MAX_PEAK_BUCKETS: usize = 16384;
+
+const BACKGROUND_COLOR: [u8; 3] = [0x1e, 0x1e, 0x1e];
+const WAVEFORM_COLOR: [u8; 3] = [0x9e, 0xc5, 0xfe];
+const SYLLABLE_START_COLOR: [u8; 3] = [0x4c, 0xd9, 0x64];
+const SYLLABLE_END_COLOR: [u8; 3] = [0xff, 0x5f, 0x57];
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum WaveformExportFormat {
+    Png,
+    Json,
+}
+
+/// 一个歌词音节，时间单位为毫秒，与歌词数据中的时间一致
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WaveformSyllable {
+    pub start_time: u64,
+    pub end_time: u64,
+    #[serde(default)]
+    pub text: String,
+}
+
+pub(crate) struct WaveformOptions {
+    pub file_path: String,
+    pub output_path: String,
+    pub format: WaveformExportFormat,
+    pub start_position: Duration,
+    /// 为空时导出到歌曲结尾
+    pub duration: Option<Duration>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub syllables: Vec<WaveformSyllable>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WaveformData<'a> {
+    /// 波形覆盖的时间范围，单位为毫秒
+    start_time: u64,
+    end_time: u64,
+    /// 每一列波形的最小值和最大值，范围为 -1 到 1，各列在时间上均匀分布
+    peaks: Vec<[f32; 2]>,
+    syllables: &'a [WaveformSyllable],
+}
+
+/// 用于绘制波形进度条的峰值数据
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WaveformPeaks {
+    /// 解码出的音频时长，单位为毫秒
+    pub duration: u64,
+    /// 每个像素区间的最小值和最大值，范围为 -1 到 1，各区间在时间上均匀分布
+    pub peaks: Vec<[f32; 2]>,
+}
+
+/// 执行一次波形导出，会阻塞当前线程直到导出完成
+pub(crate) fn export_waveform(options: WaveformOptions) -> anyhow::Result<()> {
+    let width = options.width.unwrap_or(DEFAULT_WIDTH).clamp(1, MAX_WIDTH);
+    let height = options
+        .height
+        .unwrap_or(DEFAULT_HEIGHT)
+        .clamp(2, MAX_HEIGHT);
+
+    let fft_player = Arc::new(RwLock::new(FFTPlayer::new()));
+    let (decoder, _handle) = FFmpegDecoder::new(
+        options.file_path,
+        fft_player,
+        WAVEFORM_CHANNELS,
+        WAVEFORM_SAMPLE_RATE,
+        DownmixOptions::default(),
+        Some(options.start_position),
+    )?;
+    let decoder = decoder.into_offline();
+    let duration = options
+        .duration
+        .or_else(|| {
+            decoder
+                .total_duration()
+                .map(|d| d.saturating_sub(options.start_position))
+        })
+        .filter(|d| !d.is_zero())
+        .context("无法确定要导出的波形时长")?;
+
+    let samples: Vec<f32> = decoder.take_duration(duration).collect();
+    let peaks = compute_peaks(&samples, width as usize);
+
+    let start_time = options.start_position.as_millis() as u64;
+    let end_time = start_time + duration.as_millis() as u64;
+    let output_path = Path::new(&options.output_path);
+    match options.format {
+        WaveformExportFormat::Json => {
+            let data = WaveformData {
+                start_time,
+                end_time,
+                peaks,
+                syllables: &options.syllables,
+            };
+            let file = File::create(output_path)
+                .with_context(|| format!("无法创建导出文件: {}", output_path.display()))?;
+            serde_json::to_writer(BufWriter::new(file), &data)?;
+        }
+        WaveformExportFormat::Png => {
+            let pixels = render_waveform(
+                &peaks,
+                &options.syllables,
+                start_time,
+                end_time,
+                width,
+                height,
+            );
+            write_png(output_path, &pixels, width, height)?;
+        }
+    }
+    Ok(())
+}
+
+/// 完整解码一首歌曲，按 `buckets` 个区间计算波形峰值，会阻塞当前线程直到解码完成
+///
+/// 边解码边计算峰值，不会缓存整首歌曲的采样。
+/// `cancelled` 被置为真时会尽快停止并返回错误，单个损坏的数据包会被跳过
+pub fn read_waveform_peaks(
+    path: &str,
+    buckets: usize,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<WaveformPeaks> {
+    ensure_ffmpeg_initialized()?;
+    let buckets = buckets.clamp(1, MAX_PEAK_BUCKETS);
+    let mut input_ctx = open_input(path)?;
+
+    let estimated_samples = (input_ctx.duration().max(0) as f64 / ffmpeg::ffi::AV_TIME_BASE as f64
+        * PEAKS_SAMPLE_RATE as f64) as u64;
+    let mut peaks = PeakAccumulator::new(buckets, estimated_samples);
+    decode_to_f32(
+        &mut input_ctx,
+        path,
+        DecodeTarget {
+            channel_layout: Some(ChannelLayout::MONO),
+            rate: Some(PEAKS_SAMPLE_RATE),
+        },
+        cancelled,
+        |frame| {
+            peaks.push(&frame.plane::<f32>(0)[..frame.samples()]);
+            Ok(())
+        },
+    )?;
+
+    if peaks.total == 0 {
+        return Err(anyhow!("没有解码出任何音频"));
+    }
+    Ok(WaveformPeaks {
+        duration: peaks.total * 1000 / PEAKS_SAMPLE_RATE as u64,
+        peaks: peaks.finish(),
+    })
+}
+
+/// 边解码边把采样归入各列，只保存每列的最小值和最大值
+///
+/// 每列的采样数按估计的时长算出。实际比估计的更长、列数达到 `buckets` 的两倍时，
+/// 相邻两列合并为一列，每列的采样数随之加倍，结束时再合并为恰好 `buckets` 列
+struct PeakAccumulator {
+    buckets: usize,
+    samples_per_column: u64,
+    columns: Vec<[f32; 2]>,
+    /// 最后一列已经归入的采样数
+    filled: u64,
+    total: u64,
+}
+
+impl PeakAccumulator {
+    fn new(buckets: usize, estimated_samples: u64) -> Self {
+        Self {
+            buckets,
+            samples_per_column: (estimated_samples / buckets as u64).max(1),
+            columns: Vec::with_capacity(buckets * 2),
+            filled: 0,
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            if self.filled == 0 {
+                if self.columns.len() == self.buckets * 2 {
+                    self.merge_pairs();
+                }
+                self.columns.push([0.0, 0.0]);
+            }
+            if let Some([min, max]) = self.columns.last_mut() {
+                *min = min.min(sample);
+                *max = max.max(sample);
+            }
+            self.filled = (self.filled + 1) % self.samples_per_column;
+            self.total += 1;
+        }
+    }
+
+    /// 只在最后一列已满时调用，合并后各列的采样数仍然相同
+    fn merge_pairs(&mut self) {
+        self.columns = self.columns.chunks(2).map(merge_columns).collect();
+        self.samples_per_column *= 2;
+    }
+
+    fn finish(self) -> Vec<[f32; 2]> {
+        let len = self.columns.len();
+        (0..self.buckets)
+            .map(|bucket| {
+                let from = bucket * len / self.buckets;
+                let to = ((bucket + 1) * len / self.buckets).max(from + 1);
+                merge_columns(self.columns.get(from..to.min(len)).unwrap_or_default())
+            })
+            .collect()
+    }
+}
+
+fn merge_columns(columns: &[[f32; 2]]) -> [f32; 2] {
+    columns
+        .iter()
+        .fold([0.0f32, 0.0f32], |[min, max], &[column_min, column_max]| {
+            [min.min(column_min), max.max(column_max)]
+        })
+}
+
+/// 将采样均匀分为 `columns` 列，计算每列的最小值和最大值
+fn compute_peaks(samples: &[f32], columns: usize) -> Vec<[f32; 2]> {
+    (0..columns)
+        .map(|column| {
+            let from = column * samples.len() / columns;
+            let to = ((column + 1) * samples.len() / columns).max(from + 1);
+            samples
+                .get(from..to.min(samples.len()))
+                .unwrap_or_default()
+                .iter()
+                .fold([0.0f32, 0.0f32], |[min, max], &sample| {
+                    [min.min(sample), max.max(sample)]
+                })
+        })
+        .collect()
+}
+
+fn render_waveform(
+    peaks: &[[f32; 2]],
+    syllables: &[WaveformSyllable],
+    start_time: u64,
+    end_time: u64,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut pixels = BACKGROUND_COLOR.repeat(width * height);
+    let mut fill_column = |x: usize, from: usize, to: usize, color: [u8; 3]| {
+        for y in from..=to.min(height - 1) {
+            let offset = (y * width + x) * 3;
+            pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    };
+
+    let half = (height - 1) as f32 / 2.0;
+    let to_y = |value: f32| ((1.0 - value.clamp(-1.0, 1.0)) * half).round() as usize;
+    for (x, [min, max]) in peaks.iter().enumerate().take(width) {
+        fill_column(x, to_y(*max), to_y(*min), WAVEFORM_COLOR);
+    }
+
+    // 先画结束线再画开始线，两个音节首尾相接时开始线会覆盖在上面
+    let span = (end_time - start_time).max(1) as f64;
+    let to_x = |time: u64| {
+        (time >= start_time && time <= end_time)
+            .then(|| (((time - start_time) as f64 / span) * (width - 1) as f64).round() as usize)
+    };
+    for syllable in syllables {
+        if let Some(x) = to_x(syllable.end_time) {
+            fill_column(x, 0, height - 1, SYLLABLE_END_COLOR);
+        }
+    }
+    for syllable in syllables {
+        if let Some(x) = to_x(syllable.start_time) {
+            fill_column(x, 0, height - 1, SYLLABLE_START_COLOR);
+        }
+    }
+    pixels
+}
+
+fn write_png(path: &Path, pixels: &[u8], width: u32, height: u32) -> anyhow::Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("无法创建导出文件: {}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|err| anyhow!("PNG 编码失败: {err}"))?;
+    writer
+        .write_image_data(pixels)
+        .map_err(|err| anyhow!("PNG 编码失败: {err}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accumulate(buckets: usize, estimated_samples: u64, samples: &[f32]) -> Vec<[f32; 2]> {
+        let mut peaks = PeakAccumulator::new(buckets, estimated_samples);
+        for chunk in samples.chunks(7) {
+            peaks.push(chunk);
+        }
+        assert_eq!(peaks.total, samples.len() as u64);
+        peaks.finish()
+    }
+
+    /// 幅度逐渐增大、正负交替的采样
+    fn ramp(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                sign * i as f32 / len as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accurate_estimate_matches_buffered_peaks() {
+        let samples = ramp(1000);
+        assert_eq!(accumulate(10, 1000, &samples), compute_peaks(&samples, 10));
+    }
+
+    #[test]
+    fn unknown_duration_merges_columns() {
+        let samples = ramp(1000);
+        let peaks = accumulate(10, 0, &samples);
+        assert_eq!(peaks.len(), 10);
+        // 最后一列包含最响的采样
+        let [min, max] = peaks[9];
+        assert!(min < -0.99 && max > 0.99);
+        let [min, max] = peaks[0];
+        assert!(min > -0.2 && max < 0.2);
+    }
+
+    #[test]
+    fn short_audio_fills_every_bucket() {
+        let peaks = accumulate(8, 1000, &[0.5, -0.5]);
+        assert_eq!(peaks.len(), 8);
+        assert!(peaks.iter().all(|&peak| peak == [-0.5, 0.5]));
+    }
+}
@@ -0,0 +1,64 @@
+package net.stevexmh.amllplayer
+
+import android.Manifest
+import android.app.Activity
+import android.content.pm.PackageManager
+import android.os.Build
+import androidx.core.app.ActivityCompat
+import androidx.core.content.ContextCompat
+import app.tauri.annotation.Command
+import app.tauri.annotation.InvokeArg
+import app.tauri.annotation.TauriPlugin
+import app.tauri.plugin.Invoke
+import app.tauri.plugin.Plugin
+
+@InvokeArg
+class NowPlayingArgs {
+    var title: String? = null
+    var artist: String? = null
+    var lyric: String? = null
+}
+
+/**
+ * 供 Rust 层调用的后台服务接口，对应 `src/background_service.rs`
+ */
+@TauriPlugin
+class BackgroundServicePlugin(private val activity: Activity) : Plugin(activity) {
+    @Command
+    fun start(invoke: Invoke) {
+        requestNotificationPermission()
+        val args = invoke.parseArgs(NowPlayingArgs::class.java)
+        PlaybackService.update(activity, args.title, args.artist, args.lyric)
+        invoke.resolve()
+    }
+
+    @Command
+    fun update(invoke: Invoke) {
+        val args = invoke.parseArgs(NowPlayingArgs::class.java)
+        PlaybackService.update(activity, args.title, args.artist, args.lyric)
+        invoke.resolve()
+    }
+
+    @Command
+    fun stop(invoke: Invoke) {
+        PlaybackService.stop(activity)
+        invoke.resolve()
+    }
+
+    /** Android 13 起需要动态申请通知权限，未授权时服务仍可运行，只是不显示通知 */
+    private fun requestNotificationPermission() {
+        if (Build.VERSION.SDK_INT < Build.VERSION_CODES.TIRAMISU) return
+        if (ContextCompat.checkSelfPermission(activity, Manifest.permission.POST_NOTIFICATIONS)
+            == PackageManager.PERMISSION_GRANTED
+        ) return
+        ActivityCompat.requestPermissions(
+            activity,
+            arrayOf(Manifest.permission.POST_NOTIFICATIONS),
+            REQUEST_NOTIFICATION_PERMISSION
+        )
+    }
+
+    companion object {
+        private const val REQUEST_NOTIFICATION_PERMISSION = 1001
+    }
+}
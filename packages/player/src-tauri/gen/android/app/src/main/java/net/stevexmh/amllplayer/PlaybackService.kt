@@ -0,0 +1,161 @@
+package net.stevexmh.amllplayer
+
+import android.app.NotificationChannel
+import android.app.NotificationManager
+import android.app.PendingIntent
+import android.app.Service
+import android.content.Context
+import android.content.Intent
+import android.content.pm.ServiceInfo
+import android.net.wifi.WifiManager
+import android.os.Build
+import android.os.IBinder
+import android.os.PowerManager
+import androidx.core.app.NotificationCompat
+import androidx.core.content.ContextCompat
+
+/**
+ * 前台服务，在锁屏或切到后台时保持进程运行，使 WS 连接和音频播放不会被系统暂停，
+ * 并在通知栏中显示当前的歌曲和歌词行
+ */
+class PlaybackService : Service() {
+    private var wakeLock: PowerManager.WakeLock? = null
+    private var wifiLock: WifiManager.WifiLock? = null
+
+    private var title: String? = null
+    private var artist: String? = null
+    private var lyric: String? = null
+
+    override fun onBind(intent: Intent?): IBinder? = null
+
+    override fun onCreate() {
+        super.onCreate()
+        createNotificationChannel()
+        acquireLocks()
+    }
+
+    override fun onStartCommand(intent: Intent?, flags: Int, startId: Int): Int {
+        intent?.let {
+            if (it.hasExtra(EXTRA_TITLE)) title = it.getStringExtra(EXTRA_TITLE)
+            if (it.hasExtra(EXTRA_ARTIST)) artist = it.getStringExtra(EXTRA_ARTIST)
+            if (it.hasExtra(EXTRA_LYRIC)) lyric = it.getStringExtra(EXTRA_LYRIC)
+        }
+
+        // 重复调用 startForeground 会直接替换通知内容
+        val notification = buildNotification()
+        if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
+            startForeground(
+                NOTIFICATION_ID,
+                notification,
+                ServiceInfo.FOREGROUND_SERVICE_TYPE_MEDIA_PLAYBACK
+            )
+        } else {
+            startForeground(NOTIFICATION_ID, notification)
+        }
+        return START_NOT_STICKY
+    }
+
+    override fun onDestroy() {
+        releaseLocks()
+        super.onDestroy()
+    }
+
+    private fun createNotificationChannel() {
+        val channel = NotificationChannel(
+            CHANNEL_ID,
+            getString(R.string.background_service_channel_name),
+            NotificationManager.IMPORTANCE_LOW
+        ).apply {
+            description = getString(R.string.background_service_channel_description)
+            setShowBadge(false)
+            lockscreenVisibility = android.app.Notification.VISIBILITY_PUBLIC
+        }
+        getSystemService(NotificationManager::class.java).createNotificationChannel(channel)
+    }
+
+    private fun buildNotification(): android.app.Notification {
+        val contentIntent = PendingIntent.getActivity(
+            this,
+            0,
+            Intent(this, MainActivity::class.java).addFlags(Intent.FLAG_ACTIVITY_SINGLE_TOP),
+            PendingIntent.FLAG_IMMUTABLE or PendingIntent.FLAG_UPDATE_CURRENT
+        )
+        val songTitle = title?.takeIf { it.isNotBlank() }
+            ?: getString(R.string.background_service_default_title)
+        return NotificationCompat.Builder(this, CHANNEL_ID)
+            .setSmallIcon(R.mipmap.ic_launcher)
+            .setContentTitle(songTitle)
+            .setContentText(lyric?.takeIf { it.isNotBlank() } ?: artist)
+            .setSubText(artist?.takeIf { it.isNotBlank() && !lyric.isNullOrBlank() })
+            .setContentIntent(contentIntent)
+            .setCategory(NotificationCompat.CATEGORY_TRANSPORT)
+            .setVisibility(NotificationCompat.VISIBILITY_PUBLIC)
+            .setOngoing(true)
+            .setOnlyAlertOnce(true)
+            .setSilent(true)
+            .build()
+    }
+
+    private fun acquireLocks() {
+        val powerManager = getSystemService(Context.POWER_SERVICE) as PowerManager
+        wakeLock = powerManager
+            .newWakeLock(PowerManager.PARTIAL_WAKE_LOCK, "AMLLPlayer::PlaybackService")
+            .apply {
+                setReferenceCounted(false)
+                acquire()
+            }
+
+        // 锁屏后保持 Wi-Fi 连接，避免 WS 连接被断开
+        val wifiManager = applicationContext.getSystemService(Context.WIFI_SERVICE) as WifiManager
+        val wifiMode = if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
+            WifiManager.WIFI_MODE_FULL_LOW_LATENCY
+        } else {
+            @Suppress("DEPRECATION")
+            WifiManager.WIFI_MODE_FULL_HIGH_PERF
+        }
+        wifiLock = wifiManager.createWifiLock(wifiMode, "AMLLPlayer::PlaybackService").apply {
+            setReferenceCounted(false)
+            acquire()
+        }
+    }
+
+    private fun releaseLocks() {
+        wakeLock?.takeIf { it.isHeld }?.release()
+        wakeLock = null
+        wifiLock?.takeIf { it.isHeld }?.release()
+        wifiLock = null
+    }
+
+    companion object {
+        private const val CHANNEL_ID = "playback"
+        private const val NOTIFICATION_ID = 1
+
+        private const val ACTION_UPDATE = "net.stevexmh.amllplayer.action.UPDATE"
+        private const val EXTRA_TITLE = "title"
+        private const val EXTRA_ARTIST = "artist"
+        private const val EXTRA_LYRIC = "lyric"
+
+        /**
+         * 启动前台服务，或在服务已经运行时更新通知内容
+         *
+         * 参数为 null 时保留通知中原有的内容
+         */
+        fun update(context: Context, title: String?, artist: String?, lyric: String?) {
+            val intent = Intent(context, PlaybackService::class.java).setAction(ACTION_UPDATE)
+            title?.let { intent.putExtra(EXTRA_TITLE, it) }
+            artist?.let { intent.putExtra(EXTRA_ARTIST, it) }
+            lyric?.let { intent.putExtra(EXTRA_LYRIC, it) }
+            ContextCompat.startForegroundService(context, intent)
+        }
+
+        /**
+         * 停止前台服务并移除通知
+         *
+         * 直接调用 stopService，服务没有运行时什么都不做，
+         * 不会像通过 startService 发送停止命令那样在后台被拒绝或反而先启动服务
+         */
+        fun stop(context: Context) {
+            context.stopService(Intent(context, PlaybackService::class.java))
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Android 的后台服务模式
+//!
+//! 通过 Kotlin 层的 `BackgroundServicePlugin` 启动一个前台服务，
+//! 使锁屏后仍能继续接收 WS 数据和播放音频，并在通知栏显示当前的歌词行
+
+use serde::Serialize;
+use tauri::{
+    Manager, Runtime, State,
+    plugin::{Builder, PluginHandle, TauriPlugin},
+};
+
+const PLUGIN_IDENTIFIER: &str = "net.stevexmh.amllplayer";
+
+pub struct BackgroundService<R: Runtime>(PluginHandle<R>);
+
+/// 通知栏中显示的内容，为空的字段会保留通知中原有的内容
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NowPlaying {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lyric: Option<String>,
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("background-service")
+        .setup(|app, api| {
+            let handle =
+                api.register_android_plugin(PLUGIN_IDENTIFIER, "BackgroundServicePlugin")?;
+            app.manage(BackgroundService(handle));
+            Ok(())
+        })
+        .build()
+}
+
+impl<R: Runtime> BackgroundService<R> {
+    fn run(&self, command: &str, payload: NowPlaying) -> Result<(), String> {
+        self.0
+            .run_mobile_plugin::<()>(command, payload)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// 启动或停止后台服务
+#[tauri::command]
+pub fn set_background_service_enabled<R: Runtime>(
+    service: State<'_, BackgroundService<R>>,
+    enabled: bool,
+) -> Result<(), String> {
+    if enabled {
+        service.run("start", NowPlaying::default())
+    } else {
+        service.run("stop", NowPlaying::default())
+    }
+}
+
+/// 更新通知栏中显示的歌曲信息和当前歌词行，后台服务未启动时会同时启动它
+#[tauri::command]
+pub fn update_background_service<R: Runtime>(
+    service: State<'_, BackgroundService<R>>,
+    title: Option<String>,
+    artist: Option<String>,
+    lyric: Option<String>,
+) -> Result<(), String> {
+    service.run(
+        "update",
+        NowPlaying {
+            title,
+            artist,
+            lyric,
+        },
+    )
+}
@@ -0,0 +1,246 @@
+//! 批量转换一个文件夹中的歌词文件
+//!
+//! 与查找歌曲同名歌词文件时一样按扩展名识别格式，支持 TTML、LRC、ESLRC、YRC、QRC、
+//! Lyricify Syllable 以及规范 JSON（`.json`）格式的歌词。
+//! 转换结果会写入与源文件同名、扩展名为目标格式的文件中，同名文件已经存在时会在文件名后加上编号，
+//! 不会覆盖已有的文件。输出文件夹不能与源文件夹相同，以免再次转换时把之前的结果当作源文件。
+//! 转换进度通过前端传入的通道逐个文件报告，单个文件失败不会中断整个任务
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use amll_lyric::{
+    LyricLine,
+    eslrc::parse_eslrc,
+    lrc::parse_lrc,
+    lys::parse_lys,
+    qrc::parse_qrc,
+    ttml::{TTMLLyric, stringify_ttml},
+    yrc::parse_yrc,
+};
+use anyhow::{Context, bail};
+use serde::Serialize;
+use tauri::{AppHandle, ipc::Channel};
+use tauri_plugin_fs::FsExt;
+use tracing::{info, warn};
+use ttml_processor::{
+    canonical::from_canonical_json,
+    export::{LyricExportFormat, export_lyrics, export_ttml},
+};
+
+/// 批量转换过程中发送给前端的事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum BatchConvertEvent {
+    /// 扫描完成，`total` 为将要转换的文件数量
+    #[serde(rename_all = "camelCase")]
+    Started { total: usize },
+    /// 一个文件转换成功
+    #[serde(rename_all = "camelCase")]
+    Converted {
+        index: usize,
+        total: usize,
+        source: PathBuf,
+        output: PathBuf,
+    },
+    /// 一个文件转换失败
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        index: usize,
+        total: usize,
+        source: PathBuf,
+        error: String,
+    },
+    /// 所有文件均已处理
+    #[serde(rename_all = "camelCase")]
+    Finished { succeeded: usize, failed: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceFormat {
+    Ttml,
+    Lrc,
+    Eslrc,
+    Yrc,
+    Qrc,
+    Lys,
+    CanonicalJson,
+}
+
+impl SourceFormat {
+    /// 与加载同名歌词文件时一样按扩展名识别
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "ttml" => Some(Self::Ttml),
+            "lrc" => Some(Self::Lrc),
+            "eslrc" => Some(Self::Eslrc),
+            "yrc" => Some(Self::Yrc),
+            "qrc" => Some(Self::Qrc),
+            "lys" => Some(Self::Lys),
+            "json" => Some(Self::CanonicalJson),
+            _ => None,
+        }
+    }
+}
+
+/// 没有指定输出文件夹时，结果写入源文件夹下的这个子文件夹
+const DEFAULT_OUTPUT_SUBDIR: &str = "converted";
+
+/// 在 `dir` 中为 `stem.extension` 找一个还不存在的文件名，已存在时依次尝试 `stem (1).extension` 等
+fn unused_output_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let path = dir.join(format!("{stem}.{extension}"));
+    if !path.exists() {
+        return path;
+    }
+    (1..)
+        .map(|n| dir.join(format!("{stem} ({n}).{extension}")))
+        .find(|path| !path.exists())
+        .expect("编号不会用尽")
+}
+
+/// 返回文件夹中所有支持且允许读取的歌词文件，按文件名排序，不会进入子文件夹
+fn collect_sources(
+    dir: &Path,
+    is_allowed: &impl Fn(&Path) -> bool,
+) -> anyhow::Result<Vec<(PathBuf, SourceFormat)>> {
+    let mut sources = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("无法读取文件夹 {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file()
+            && is_allowed(&path)
+            && let Some(format) = SourceFormat::from_path(&path)
+        {
+            sources.push((path, format));
+        }
+    }
+    sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(sources)
+}
+
+/// 逐行格式的歌词先生成 TTML，再与 TTML 源文件一样导出
+fn export_lines(lines: Vec<LyricLine<'_>>, format: LyricExportFormat) -> anyhow::Result<String> {
+    let ttml = stringify_ttml(&TTMLLyric {
+        lines,
+        ..Default::default()
+    })?;
+    Ok(export_ttml(&ttml, format)?)
+}
+
+fn convert_file(
+    source: &Path,
+    source_format: SourceFormat,
+    output_dir: &Path,
+    format: LyricExportFormat,
+    is_allowed: &impl Fn(&Path) -> bool,
+) -> anyhow::Result<PathBuf> {
+    let stem = source
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context("文件名无效")?;
+
+    let content = fs::read_to_string(source).context("无法读取文件")?;
+    let converted = match source_format {
+        SourceFormat::Ttml => export_ttml(&content, format)?,
+        SourceFormat::Lrc => export_lines(parse_lrc(&content), format)?,
+        SourceFormat::Eslrc => export_lines(parse_eslrc(&content), format)?,
+        SourceFormat::Yrc => export_lines(parse_yrc(&content), format)?,
+        SourceFormat::Qrc => export_lines(parse_qrc(&content), format)?,
+        SourceFormat::Lys => export_lines(parse_lys(&content), format)?,
+        SourceFormat::CanonicalJson => export_lyrics(&from_canonical_json(&content)?, format),
+    };
+    let output = unused_output_path(output_dir, stem, format.file_extension());
+    if !is_allowed(&output) {
+        bail!("没有写入 {} 的权限", output.display());
+    }
+    fs::write(&output, converted).with_context(|| format!("无法写入文件 {}", output.display()))?;
+    Ok(output)
+}
+
+fn convert_folder(
+    dir: &Path,
+    output_dir: &Path,
+    format: LyricExportFormat,
+    on_event: &Channel<BatchConvertEvent>,
+    is_allowed: impl Fn(&Path) -> bool,
+) -> anyhow::Result<()> {
+    if !is_allowed(dir) {
+        bail!("没有读取文件夹 {} 的权限", dir.display());
+    }
+    if !is_allowed(output_dir) {
+        bail!("没有写入文件夹 {} 的权限", output_dir.display());
+    }
+    let sources = collect_sources(dir, &is_allowed)?;
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("无法创建文件夹 {}", output_dir.display()))?;
+    if fs::canonicalize(dir)? == fs::canonicalize(output_dir)? {
+        bail!("输出文件夹不能与源文件夹相同");
+    }
+
+    let total = sources.len();
+    let _ = on_event.send(BatchConvertEvent::Started { total });
+
+    let mut succeeded = 0;
+    for (index, (source, source_format)) in sources.into_iter().enumerate() {
+        let event = match convert_file(&source, source_format, output_dir, format, &is_allowed) {
+            Ok(output) => {
+                succeeded += 1;
+                BatchConvertEvent::Converted {
+                    index,
+                    total,
+                    source,
+                    output,
+                }
+            }
+            Err(err) => {
+                warn!("转换歌词文件 {} 失败: {err:?}", source.display());
+                BatchConvertEvent::Failed {
+                    index,
+                    total,
+                    source,
+                    error: format!("{err:#}"),
+                }
+            }
+        };
+        let _ = on_event.send(event);
+    }
+
+    info!(
+        "已批量转换 {} 中的歌词文件，成功 {succeeded} 个，失败 {} 个",
+        dir.display(),
+        total - succeeded
+    );
+    let _ = on_event.send(BatchConvertEvent::Finished {
+        succeeded,
+        failed: total - succeeded,
+    });
+    Ok(())
+}
+
+/// 将文件夹中所有支持的歌词文件转换为指定格式
+///
+/// `output_dir` 为空时写入源文件夹下的 `converted` 子文件夹。两个文件夹都需要在文件系统插件
+/// 允许访问的范围内，只有文件夹本身无法访问、输出文件夹无法创建或与源文件夹相同时才会返回错误，
+/// 单个文件的错误通过 `on_event` 报告
+#[tauri::command]
+pub async fn convert_lyrics_batch(
+    app: AppHandle,
+    dir: PathBuf,
+    format: LyricExportFormat,
+    output_dir: Option<PathBuf>,
+    on_event: Channel<BatchConvertEvent>,
+) -> Result<(), String> {
+    let output_dir = output_dir.unwrap_or_else(|| dir.join(DEFAULT_OUTPUT_SUBDIR));
+    tauri::async_runtime::spawn_blocking(move || {
+        let scope = app.fs_scope();
+        convert_folder(&dir, &output_dir, format, &on_event, |path| {
+            scope.is_allowed(path)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{e:#}"))
+}
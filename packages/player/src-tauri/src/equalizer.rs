@@ -0,0 +1,179 @@
+//! 十段均衡器的设置与持久化
+//!
+//! 设置保存在状态目录的 `equalizer.json` 中，播放器启动后会读取并发送给音频线程，
+//! 每次通过命令修改后都会立即应用并重新保存
+
+use std::sync::LazyLock;
+
+use amll_player_core::{
+    AudioThreadMessage, EQ_BAND_FREQUENCIES, EqualizerPreset, EqualizerSettings,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{persistence, player::PLAYER_HANDLER};
+
+const EQUALIZER_STATE_KEY: &str = "equalizer";
+
+static EQUALIZER_SETTINGS: LazyLock<RwLock<EqualizerSettings>> =
+    LazyLock::new(|| RwLock::new(EqualizerSettings::default()));
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EqualizerPresetInfo {
+    preset: EqualizerPreset,
+    band_gains_db: Vec<f64>,
+}
+
+async fn load_settings<R: Runtime>(
+    app: &AppHandle<R>,
+) -> anyhow::Result<Option<EqualizerSettings>> {
+    let path = persistence::state_file_path(app, EQUALIZER_STATE_KEY)?;
+    let content = tokio::task::spawn_blocking(move || {
+        persistence::load_with_fallback(&path, persistence::is_valid_json)
+    })
+    .await??;
+    Ok(match content {
+        Some(content) => Some(serde_json::from_slice::<EqualizerSettings>(&content)?.sanitized()),
+        None => None,
+    })
+}
+
+async fn save_settings<R: Runtime>(
+    app: &AppHandle<R>,
+    settings: &EqualizerSettings,
+) -> anyhow::Result<()> {
+    let path = persistence::state_file_path(app, EQUALIZER_STATE_KEY)?;
+    let content = serde_json::to_vec(settings)?;
+    tokio::task::spawn_blocking(move || {
+        persistence::save_with_backup(&path, &content, persistence::is_valid_json)
+    })
+    .await??;
+    Ok(())
+}
+
+async fn send_to_player(settings: EqualizerSettings) {
+    if let Some(handler) = &*PLAYER_HANDLER.read().await
+        && let Err(err) = handler
+            .send_anonymous(AudioThreadMessage::SetEqualizer { settings })
+            .await
+    {
+        warn!("failed to send SetEqualizer msg to local player: {:?}", err);
+    }
+}
+
+/// 读取保存的均衡器设置并发送给播放器，在播放器初始化完成后调用
+pub async fn restore_equalizer<R: Runtime>(app: &AppHandle<R>) {
+    match load_settings(app).await {
+        Ok(Some(settings)) => {
+            *EQUALIZER_SETTINGS.write().await = settings;
+            send_to_player(settings).await;
+        }
+        Ok(None) => {}
+        Err(err) => warn!("读取均衡器设置失败: {err:?}"),
+    }
+}
+
+/// 修改设置后应用到播放器并保存，返回修改后的设置
+async fn update_settings<R: Runtime>(
+    app: &AppHandle<R>,
+    update: impl FnOnce(&mut EqualizerSettings) -> Result<(), String>,
+) -> Result<EqualizerSettings, String> {
+    let settings = {
+        let mut settings = EQUALIZER_SETTINGS.write().await;
+        update(&mut settings)?;
+        *settings
+    };
+    send_to_player(settings).await;
+    save_settings(app, &settings)
+        .await
+        .map_err(|e| format!("保存均衡器设置失败: {e}"))?;
+    Ok(settings)
+}
+
+#[tauri::command]
+pub async fn get_equalizer_settings() -> EqualizerSettings {
+    *EQUALIZER_SETTINGS.read().await
+}
+
+#[tauri::command]
+pub fn get_equalizer_bands() -> Vec<f64> {
+    EQ_BAND_FREQUENCIES.to_vec()
+}
+
+#[tauri::command]
+pub fn list_equalizer_presets() -> Vec<EqualizerPresetInfo> {
+    EqualizerPreset::ALL
+        .into_iter()
+        .map(|preset| EqualizerPresetInfo {
+            preset,
+            band_gains_db: preset.band_gains_db().to_vec(),
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub async fn set_equalizer_settings<R: Runtime>(
+    app: AppHandle<R>,
+    settings: EqualizerSettings,
+) -> Result<EqualizerSettings, String> {
+    update_settings(&app, |current| {
+        *current = settings.sanitized();
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_equalizer_enabled<R: Runtime>(
+    app: AppHandle<R>,
+    enabled: bool,
+) -> Result<EqualizerSettings, String> {
+    update_settings(&app, |settings| {
+        settings.enabled = enabled;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_equalizer_band_gain<R: Runtime>(
+    app: AppHandle<R>,
+    band: usize,
+    gain_db: f64,
+) -> Result<EqualizerSettings, String> {
+    update_settings(&app, |settings| {
+        if settings.set_band_gain(band, gain_db) {
+            Ok(())
+        } else {
+            Err(format!("无效的均衡器频段: {band}"))
+        }
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_equalizer_preamp<R: Runtime>(
+    app: AppHandle<R>,
+    preamp_db: f64,
+) -> Result<EqualizerSettings, String> {
+    update_settings(&app, |settings| {
+        settings.set_preamp(preamp_db);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn apply_equalizer_preset<R: Runtime>(
+    app: AppHandle<R>,
+    preset: EqualizerPreset,
+) -> Result<EqualizerSettings, String> {
+    update_settings(&app, |settings| {
+        settings.apply_preset(preset);
+        Ok(())
+    })
+    .await
+}
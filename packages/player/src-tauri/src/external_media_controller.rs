@@ -1,3 +1,8 @@
+use std::sync::{
+    Arc, Mutex, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
 use anyhow::{Context, Result};
 use base64::{Engine, engine::general_purpose::STANDARD};
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
@@ -7,6 +12,14 @@ use smtc_suite::{
 };
 use tauri::{AppHandle, Emitter, Runtime};
 use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{info, warn};
+
+use crate::{
+    loudness_monitor::{
+        AutoVolumeAdjuster, LoudnessMonitor, LoudnessNormalizationConfig, LoudnessReport, PcmFormat,
+    },
+    startup_metrics,
+};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -24,11 +37,13 @@ pub enum TextConversionMode {
 #[serde(tag = "type", content = "data", rename_all = "camelCase")]
 pub enum SmtcEvent {
     TrackChanged(FrontendNowPlayingInfo),
+    TrackMetadataChanged(FrontendNowPlayingInfo),
     SessionsChanged(Vec<SmtcSessionInfo>),
     SelectedSessionVanished(String),
     AudioData(Vec<u8>),
     Error(String),
     VolumeChanged { volume: f32, is_muted: bool },
+    LoudnessEstimate(LoudnessReport),
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
@@ -64,6 +79,8 @@ pub enum MediaCommand {
     StopAudioVisualization,
     SetHighFrequencyProgressUpdates { enabled: bool },
     SetProgressOffset { offset_ms: i64 },
+    SetLoudnessNormalization { config: LoudnessNormalizationConfig },
+    SetLyricLock { locked: bool },
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -164,11 +181,30 @@ impl From<SmtcNowPlayingInfo> for FrontendNowPlayingInfo {
     }
 }
 
+#[derive(Default)]
+pub struct LoudnessState {
+    monitor: LoudnessMonitor,
+    adjuster: AutoVolumeAdjuster,
+}
+
 pub struct ExternalMediaControllerState {
     pub smtc_command_tx: Sender<SmtcMediaCommand>,
+    pub loudness: Arc<Mutex<LoudnessState>>,
+    pub lyric_locked: Arc<AtomicBool>,
 }
 
 impl ExternalMediaControllerState {
+    /// 开始回环捕获前按默认输出设备的格式设置响度估计
+    fn update_capture_format(&self) {
+        let format = PcmFormat::of_default_output().unwrap_or_else(|| {
+            warn!("无法获取默认输出设备的音频格式，按 48kHz 双声道估计响度");
+            PcmFormat::default()
+        });
+        if let Ok(mut loudness) = self.loudness.lock() {
+            loudness.monitor.set_format(format);
+        }
+    }
+
     pub async fn send_smtc_command(&self, command: SmtcMediaCommand) -> anyhow::Result<()> {
         self.smtc_command_tx
             .send(command)
@@ -180,8 +216,9 @@ impl ExternalMediaControllerState {
 #[tauri::command]
 pub async fn control_external_media(
     payload: MediaCommand,
-    state: tauri::State<'_, ExternalMediaControllerState>,
+    controller: tauri::State<'_, ExternalMediaController>,
 ) -> Result<(), String> {
+    let state = controller.state();
     let command = match payload {
         MediaCommand::SelectSession { session_id } => {
             let target_id = if session_id == "null" {
@@ -241,7 +278,10 @@ pub async fn control_external_media(
             let clamped_volume = volume.clamp(0.0, 1.0);
             SmtcMediaCommand::Control(smtc_suite::SmtcControlCommand::SetVolume(clamped_volume))
         }
-        MediaCommand::StartAudioVisualization => SmtcMediaCommand::StartAudioCapture,
+        MediaCommand::StartAudioVisualization => {
+            state.update_capture_format();
+            SmtcMediaCommand::StartAudioCapture
+        }
         MediaCommand::StopAudioVisualization => SmtcMediaCommand::StopAudioCapture,
         MediaCommand::SetHighFrequencyProgressUpdates { enabled } => {
             SmtcMediaCommand::SetHighFrequencyProgressUpdates(enabled)
@@ -249,6 +289,26 @@ pub async fn control_external_media(
         MediaCommand::SetProgressOffset { offset_ms } => {
             SmtcMediaCommand::SetProgressOffset(offset_ms)
         }
+        MediaCommand::SetLoudnessNormalization { config } => {
+            if let Ok(mut loudness) = state.loudness.lock() {
+                loudness.monitor.set_config(config);
+            }
+            if !config.enabled {
+                return Ok(());
+            }
+            state.update_capture_format();
+            // 响度估计依赖回环捕获的音频数据
+            SmtcMediaCommand::StartAudioCapture
+        }
+        MediaCommand::SetLyricLock { locked } => {
+            // 锁定期间曲目变化只会以 `TrackMetadataChanged` 通知前端更新元数据显示，不会替换歌词
+            let was_locked = state.lyric_locked.swap(locked, Ordering::SeqCst);
+            if locked || !was_locked {
+                return Ok(());
+            }
+            // 解锁后重新获取一次当前曲目信息，让前端按当前曲目同步歌词
+            SmtcMediaCommand::RequestUpdate
+        }
     };
 
     state
@@ -259,21 +319,48 @@ pub async fn control_external_media(
 
 #[tauri::command]
 pub async fn request_smtc_update(
-    state: tauri::State<'_, ExternalMediaControllerState>,
+    controller: tauri::State<'_, ExternalMediaController>,
 ) -> Result<(), String> {
-    state
+    controller
+        .state()
         .send_smtc_command(SmtcMediaCommand::RequestUpdate)
         .await
         .map_err(|e| e.to_string())
 }
 
-pub fn start_listener<R: Runtime>(app_handle: AppHandle<R>) -> ExternalMediaControllerState {
+/// 按需启动的外部媒体控制器，第一次被使用时才会启动 SMTC 监听
+pub struct ExternalMediaController {
+    app_handle: AppHandle,
+    state: OnceLock<ExternalMediaControllerState>,
+}
+
+impl ExternalMediaController {
+    pub fn new(app_handle: AppHandle) -> Self {
+        Self {
+            app_handle,
+            state: OnceLock::new(),
+        }
+    }
+
+    pub fn state(&self) -> &ExternalMediaControllerState {
+        self.state.get_or_init(|| {
+            info!("正在初始化外部媒体控制器...");
+            startup_metrics::measure("smtc", || start_listener(self.app_handle.clone()))
+        })
+    }
+}
+
+fn start_listener<R: Runtime>(app_handle: AppHandle<R>) -> ExternalMediaControllerState {
+    let loudness = Arc::new(Mutex::new(LoudnessState::default()));
+    let lyric_locked = Arc::new(AtomicBool::new(false));
     let (controller, update_rx) = match smtc_suite::MediaManager::start() {
         Ok(c) => c,
         Err(_e) => {
             let (smtc_tx, _) = tokio::sync::mpsc::channel(1);
             return ExternalMediaControllerState {
                 smtc_command_tx: smtc_tx,
+                loudness,
+                lyric_locked,
             };
         }
     };
@@ -281,8 +368,18 @@ pub fn start_listener<R: Runtime>(app_handle: AppHandle<R>) -> ExternalMediaCont
     let smtc_command_tx = controller.command_tx;
 
     let app_handle_receiver = app_handle.clone();
+    let loudness_receiver = loudness.clone();
+    let lyric_locked_receiver = lyric_locked.clone();
+    let command_tx_receiver = smtc_command_tx.clone();
     tauri::async_runtime::spawn(async move {
-        event_receiver_loop(app_handle_receiver, update_rx).await;
+        event_receiver_loop(
+            app_handle_receiver,
+            update_rx,
+            loudness_receiver,
+            lyric_locked_receiver,
+            command_tx_receiver,
+        )
+        .await;
     });
 
     let initial_command_tx = smtc_command_tx.clone();
@@ -292,27 +389,72 @@ pub fn start_listener<R: Runtime>(app_handle: AppHandle<R>) -> ExternalMediaCont
             .await;
     });
 
-    ExternalMediaControllerState { smtc_command_tx }
+    ExternalMediaControllerState {
+        smtc_command_tx,
+        loudness,
+        lyric_locked,
+    }
+}
+
+/// 将回环捕获的音频送入响度估计，返回响度报告以及需要自动设置的会话音量
+fn process_loudness(
+    loudness: &Mutex<LoudnessState>,
+    data: &[u8],
+) -> Option<(LoudnessReport, Option<f32>)> {
+    let mut loudness = loudness.lock().ok()?;
+    let LoudnessState { monitor, adjuster } = &mut *loudness;
+    let report = monitor.push_pcm_bytes(data)?;
+    let config = monitor.config();
+    let volume = if config.auto_adjust {
+        adjuster.next_volume(&report, &config)
+    } else {
+        None
+    };
+    Some((report, volume))
 }
 
 async fn event_receiver_loop<R: Runtime>(
     app_handle: AppHandle<R>,
     mut update_rx: Receiver<MediaUpdate>,
+    loudness: Arc<Mutex<LoudnessState>>,
+    lyric_locked: Arc<AtomicBool>,
+    smtc_command_tx: Sender<SmtcMediaCommand>,
 ) {
     while let Some(update) = update_rx.recv().await {
         let event_to_emit = match update {
             MediaUpdate::TrackChanged(info) => {
                 let dto: FrontendNowPlayingInfo = (*info).into();
-                Some(SmtcEvent::TrackChanged(dto))
+                if lyric_locked.load(Ordering::SeqCst) {
+                    Some(SmtcEvent::TrackMetadataChanged(dto))
+                } else {
+                    Some(SmtcEvent::TrackChanged(dto))
+                }
             }
             MediaUpdate::SessionsChanged(sessions) => Some(SmtcEvent::SessionsChanged(
                 sessions.into_iter().map(SmtcSessionInfo::from).collect(),
             )),
-            MediaUpdate::AudioData(bytes) => Some(SmtcEvent::AudioData(bytes)),
+            MediaUpdate::AudioData(bytes) => {
+                if let Some((report, volume)) = process_loudness(&loudness, &bytes) {
+                    let _ = app_handle.emit("smtc_update", SmtcEvent::LoudnessEstimate(report));
+                    if let Some(volume) = volume {
+                        let _ = smtc_command_tx
+                            .send(SmtcMediaCommand::Control(
+                                smtc_suite::SmtcControlCommand::SetVolume(volume),
+                            ))
+                            .await;
+                    }
+                }
+                Some(SmtcEvent::AudioData(bytes))
+            }
             MediaUpdate::Error(e) => Some(SmtcEvent::Error(e)),
             MediaUpdate::VolumeChanged {
                 volume, is_muted, ..
-            } => Some(SmtcEvent::VolumeChanged { volume, is_muted }),
+            } => {
+                if let Ok(mut loudness) = loudness.lock() {
+                    loudness.adjuster.on_volume_changed(volume);
+                }
+                Some(SmtcEvent::VolumeChanged { volume, is_muted })
+            }
             MediaUpdate::SelectedSessionVanished(id) => {
                 Some(SmtcEvent::SelectedSessionVanished(id))
             }
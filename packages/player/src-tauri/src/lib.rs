@@ -1,24 +1,47 @@
-use crate::server::AMLLWebSocketServer;
-use amll_player_core::AudioInfo;
+use crate::server::{AMLLWebSocketServer, EndpointConfig, EndpointInfo};
+use amll_player_core::{AudioInfo, AudioTags, TagUpdate, WaveformPeaks};
 use anyhow::Context;
 use ffmpeg_next as ffmpeg;
 use serde::*;
 use serde_json::Value;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use tauri::ipc::Channel;
 use tauri::{
     AppHandle, Manager, PhysicalSize, Runtime, Size, State, WebviewWindowBuilder,
     utils::config::WindowEffectsConfig, window::Effect,
 };
+use tauri_plugin_fs::FsExt;
 use tokio::sync::RwLock;
 use tracing::*;
+use ttml_processor::{
+    agent_recognizer::{AgentAssignment, AgentRecognizerOptions},
+    dry_run::{OptimizerPass, ProposedEdit},
+    export::LyricExportFormat,
+    pipeline::ProcessingStep,
+    rescale::TimeAnchor,
+};
 
+mod batch_convert;
+mod equalizer;
+mod lyric_backup;
+mod metadata_prefetch;
+mod persistence;
 mod player;
+mod plugin_host;
+mod replaygain;
 mod screen_capture;
 mod server;
+mod startup_metrics;
 
+#[cfg(target_os = "android")]
+mod background_service;
 #[cfg(target_os = "windows")]
 mod external_media_controller;
+#[cfg(target_os = "windows")]
+mod loudness_monitor;
 
 pub type AMLLWebSocketServerWrapper = RwLock<AMLLWebSocketServer>;
 pub type AMLLWebSocketServerState<'r> = State<'r, AMLLWebSocketServerWrapper>;
@@ -30,8 +53,11 @@ async fn ws_reopen_connection(
     ws: AMLLWebSocketServerState<'_>,
     channel: Channel<ws_protocol::v2::Payload>,
 ) -> Result<(), String> {
-    ws.write().await.reopen(addr.to_string(), channel);
-    Ok(())
+    ws.write()
+        .await
+        .reopen(addr.to_string(), channel)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -56,6 +82,43 @@ async fn ws_broadcast_payload(
     Ok(())
 }
 
+/// 打开一个 WebSocket 端点，已经存在相同 ID 的端点时会以新的配置重新打开
+#[tauri::command]
+async fn ws_open_endpoint(
+    config: EndpointConfig,
+    ws: AMLLWebSocketServerState<'_>,
+    channel: Channel<ws_protocol::v2::Payload>,
+) -> Result<(), String> {
+    ws.write()
+        .await
+        .open_endpoint(config, channel)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn ws_close_endpoint(id: String, ws: AMLLWebSocketServerState<'_>) -> Result<bool, String> {
+    Ok(ws.write().await.close_endpoint(&id).await)
+}
+
+#[tauri::command]
+async fn ws_list_endpoints(ws: AMLLWebSocketServerState<'_>) -> Result<Vec<EndpointInfo>, String> {
+    Ok(ws.read().await.list_endpoints().await)
+}
+
+#[tauri::command]
+async fn ws_broadcast_endpoint_payload(
+    id: String,
+    ws: AMLLWebSocketServerState<'_>,
+    payload: ws_protocol::v2::Payload,
+) -> Result<(), String> {
+    ws.write()
+        .await
+        .broadcast_endpoint_payload(&id, payload)
+        .await;
+    Ok(())
+}
+
 #[tauri::command]
 fn restart_app<R: Runtime>(app: AppHandle<R>) {
     tauri::process::restart(&app.env())
@@ -106,55 +169,171 @@ impl From<AudioInfo> for MusicInfo {
     }
 }
 
+const LYRIC_FILE_EXTENSIONS: &[&str] = &["ttml", "lys", "yrc", "qrc", "eslrc", "lrc"];
+
+fn read_audio_info(path: &Path) -> anyhow::Result<AudioInfo> {
+    amll_player_core::utils::ensure_ffmpeg_initialized()?;
+    let input_ctx =
+        ffmpeg::format::input(path).with_context(|| format!("无法打开文件: {}", path.display()))?;
+    let mut info = amll_player_core::utils::read_audio_info(&input_ctx);
+    if let Some(stream) = input_ctx.streams().best(ffmpeg::media::Type::Audio) {
+        let time_base = stream.time_base();
+        let duration = stream.duration();
+        info.duration = duration as f64 * time_base.0 as f64 / time_base.1 as f64;
+    }
+    amll_player_core::attach_synced_lyrics(&mut info, path);
+    Ok(info)
+}
+
+/// 歌曲没有内嵌歌词时，查找与歌曲文件同名的歌词文件
+fn attach_sidecar_lyric(
+    music_info: &mut MusicInfo,
+    file_path: &Path,
+    read_to_string: impl Fn(&Path) -> std::io::Result<String>,
+) {
+    if !music_info.lyric.is_empty() {
+        return;
+    }
+    for ext in LYRIC_FILE_EXTENSIONS {
+        let lyric_file_path = file_path.with_extension(ext);
+        if lyric_file_path.exists() {
+            if let Ok(lyric) = read_to_string(&lyric_file_path) {
+                music_info.lyric_format = ext.to_string();
+                music_info.lyric = lyric;
+                break;
+            } else {
+                warn!("歌词文件存在但读取失败: {}", lyric_file_path.display());
+            }
+        }
+    }
+}
+
+/// 读取本地歌曲的元数据，会阻塞当前线程，同名的歌词文件通过 `read_to_string` 读取
+pub(crate) fn load_music_info(
+    path: &Path,
+    read_to_string: impl Fn(&Path) -> std::io::Result<String>,
+) -> anyhow::Result<MusicInfo> {
+    let mut music_info: MusicInfo = read_audio_info(path)?.into();
+    attach_sidecar_lyric(&mut music_info, path, read_to_string);
+    Ok(music_info)
+}
+
 #[tauri::command]
 async fn read_local_music_metadata(
     file_path: tauri_plugin_fs::FilePath,
     fs: State<'_, tauri_plugin_fs::Fs<tauri::Wry>>,
+    prefetch: State<'_, metadata_prefetch::MetadataPrefetchState>,
 ) -> Result<MusicInfo, String> {
+    if let Some(path) = file_path.as_path()
+        && let Some(music_info) = prefetch.take(path)
+    {
+        return Ok(music_info);
+    }
+
     let path_clone = file_path
         .as_path()
         .context("Invalid file path")
         .map_err(|e| e.to_string())?
         .to_path_buf();
 
-    let audio_info = tokio::task::spawn_blocking(move || -> anyhow::Result<AudioInfo> {
-        let mut input_ctx = ffmpeg::format::input(&path_clone)
-            .with_context(|| format!("无法打开文件: {}", path_clone.display()))?;
-        let mut info = amll_player_core::utils::read_audio_info(&mut input_ctx);
-        if let Some(stream) = input_ctx.streams().best(ffmpeg::media::Type::Audio) {
-            let time_base = stream.time_base();
-            let duration = stream.duration();
-            info.duration = duration as f64 * time_base.0 as f64 / time_base.1 as f64;
-        }
-        Ok(info)
-    })
-    .await
-    .map_err(|e| e.to_string())?
-    .map_err(|e| e.to_string())?;
+    let audio_info = tokio::task::spawn_blocking(move || read_audio_info(&path_clone))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
 
     let mut music_info: MusicInfo = audio_info.into();
 
-    if let Some(file_path_ref) = file_path.as_path()
-        && music_info.lyric.is_empty()
-    {
-        const LYRIC_FILE_EXTENSIONS: &[&str] = &["ttml", "lys", "yrc", "qrc", "eslrc", "lrc"];
-        for ext in LYRIC_FILE_EXTENSIONS {
-            let lyric_file_path = file_path_ref.with_extension(ext);
-            if lyric_file_path.exists() {
-                if let Ok(lyric) = fs.read_to_string(&lyric_file_path) {
-                    music_info.lyric_format = ext.to_string();
-                    music_info.lyric = lyric;
-                    break;
-                } else {
-                    warn!("歌词文件存在但读取失败: {}", lyric_file_path.display());
-                }
-            }
-        }
+    if let Some(file_path_ref) = file_path.as_path() {
+        attach_sidecar_lyric(&mut music_info, file_path_ref, |p| {
+            fs.read_to_string(p.to_path_buf())
+        });
     }
 
     Ok(music_info)
 }
 
+/// 把前端传入的路径转换为本地路径，路径不在文件系统插件允许访问的范围内时返回错误
+pub(crate) fn scoped_path<R: Runtime>(
+    app: &AppHandle<R>,
+    path: tauri_plugin_fs::FilePath,
+) -> Result<String, String> {
+    let path = path.into_path().map_err(|e| e.to_string())?;
+    if !app.fs_scope().is_allowed(&path) {
+        return Err(format!("没有访问 {} 的权限", path.display()));
+    }
+    path.into_os_string()
+        .into_string()
+        .map_err(|path| format!("路径不是有效的 UTF-8: {}", path.to_string_lossy()))
+}
+
+/// 读取歌曲文件的规范化标签（标题、艺术家、专辑、音轨号、碟号、年份等），供曲库扫描使用
+#[tauri::command]
+async fn read_tags(path: tauri_plugin_fs::FilePath, app: AppHandle) -> Result<AudioTags, String> {
+    let path = scoped_path(&app, path)?;
+    tokio::task::spawn_blocking(move || amll_player_core::read_tags(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))
+}
+
+/// 修改 MP3、FLAC 或 M4A 文件的标签和封面，写入临时文件后替换原文件
+#[tauri::command]
+async fn write_tags(
+    path: tauri_plugin_fs::FilePath,
+    update: TagUpdate,
+    app: AppHandle,
+) -> Result<(), String> {
+    let path = scoped_path(&app, path)?;
+    tokio::task::spawn_blocking(move || amll_player_core::write_tags(&path, &update))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))
+}
+
+/// 正在读取的波形峰值的取消标记，同一时间只为一首歌曲读取
+#[derive(Default)]
+struct WaveformPeaksState(Mutex<Option<Arc<AtomicBool>>>);
+
+impl WaveformPeaksState {
+    /// 换上新的取消标记并取消之前的读取，`next` 为空时只取消
+    fn replace(&self, next: Option<Arc<AtomicBool>>) {
+        let previous = std::mem::replace(
+            &mut *self.0.lock().unwrap_or_else(PoisonError::into_inner),
+            next,
+        );
+        if let Some(previous) = previous {
+            previous.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 解码歌曲并返回 `buckets` 个区间的波形峰值，供前端绘制波形进度条
+///
+/// 开始读取新的歌曲时，之前尚未完成的读取会被取消
+#[tauri::command]
+async fn read_waveform_peaks(
+    path: tauri_plugin_fs::FilePath,
+    buckets: usize,
+    state: State<'_, WaveformPeaksState>,
+    app: AppHandle,
+) -> Result<WaveformPeaks, String> {
+    let path = scoped_path(&app, path)?;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    state.replace(Some(cancelled.clone()));
+    tokio::task::spawn_blocking(move || {
+        amll_player_core::read_waveform_peaks(&path, buckets, &cancelled)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{e:#}"))
+}
+
+/// 取消正在进行的波形峰值读取
+#[tauri::command]
+fn cancel_waveform_peaks(state: State<'_, WaveformPeaksState>) {
+    state.replace(None);
+}
+
 async fn create_common_win<'a>(
     app: &'a AppHandle,
     url: tauri::WebviewUrl,
@@ -252,6 +431,47 @@ async fn recreate_window(app: &AppHandle, label: &str, path: Option<&str>) {
     info!("Created window: {}", label);
 }
 
+/// 将 TTML 歌词导出为 LRC 等其它歌词格式
+#[tauri::command]
+fn export_lyrics(ttml_content: String, format: LyricExportFormat) -> Result<String, String> {
+    ttml_processor::export::export_ttml(&ttml_content, format).map_err(|e| e.to_string())
+}
+
+/// 按两个锚点线性缩放 TTML 歌词的时间轴，用于将歌词适配到前奏长度或速度不同的音频版本
+#[tauri::command]
+fn rescale_lyrics_timeline(
+    ttml_content: String,
+    first: TimeAnchor,
+    second: TimeAnchor,
+) -> Result<String, String> {
+    ttml_processor::rescale::rescale_ttml(&ttml_content, first, second).map_err(|e| e.to_string())
+}
+
+/// 按给定的顺序对 TTML 歌词执行一组处理步骤，最后一步可以将结果转换为其它格式
+#[tauri::command]
+fn process_lyrics(ttml_content: String, steps: Vec<ProcessingStep>) -> Result<String, String> {
+    ttml_processor::pipeline::process_ttml(&ttml_content, &steps).map_err(|e| e.to_string())
+}
+
+/// 预览优化操作对 TTML 歌词将要做出的修改，不会修改歌词本身
+#[tauri::command]
+fn preview_lyrics_optimization(
+    ttml_content: String,
+    pass: OptimizerPass,
+) -> Result<Vec<ProposedEdit>, String> {
+    ttml_processor::dry_run::preview_ttml(&ttml_content, &pass).map_err(|e| e.to_string())
+}
+
+/// 预览演唱者识别对每一行歌词的处理结果，不会修改歌词本身
+#[tauri::command]
+fn preview_agent_recognition(
+    ttml_content: String,
+    options: AgentRecognizerOptions,
+) -> Result<Vec<AgentAssignment>, String> {
+    ttml_processor::agent_recognizer::preview_agents_ttml(&ttml_content, &options)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn open_screenshot_window(app: AppHandle) {
     recreate_window(&app, "screenshot", Some("screenshot.html")).await;
@@ -292,6 +512,7 @@ fn init_logging() {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    startup_metrics::mark_process_start();
     init_logging();
     info!("AMLL Player is starting!");
     #[allow(unused_mut)]
@@ -311,6 +532,9 @@ pub fn run() {
             "".into()
         }
     };
+    #[cfg(target_os = "android")]
+    let builder = builder.plugin(background_service::init());
+
     #[cfg(not(mobile))]
     let builder = builder.plugin(tauri_plugin_updater::Builder::new().pubkey(pubkey).build());
 
@@ -325,8 +549,6 @@ pub fn run() {
             })
     }
 
-    ffmpeg::init().expect("初始化 ffmpeg 失败");
-
     builder
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_os::init())
@@ -338,40 +560,91 @@ pub fn run() {
             ws_get_connections,
             ws_broadcast_payload,
             ws_close_connection,
+            ws_open_endpoint,
+            ws_close_endpoint,
+            ws_list_endpoints,
+            ws_broadcast_endpoint_payload,
             open_screenshot_window,
             screen_capture::take_screenshot,
             player::local_player_send_msg,
             player::set_media_controls_enabled,
+            player::get_stream_info,
+            equalizer::get_equalizer_settings,
+            equalizer::get_equalizer_bands,
+            equalizer::list_equalizer_presets,
+            equalizer::set_equalizer_settings,
+            equalizer::set_equalizer_enabled,
+            equalizer::set_equalizer_band_gain,
+            equalizer::set_equalizer_preamp,
+            equalizer::apply_equalizer_preset,
+            replaygain::scan_replaygain,
+            replaygain::cancel_replaygain_scan,
+            replaygain::get_replaygain_records,
             read_local_music_metadata,
+            read_tags,
+            write_tags,
+            read_waveform_peaks,
+            cancel_waveform_peaks,
+            export_lyrics,
+            rescale_lyrics_timeline,
+            process_lyrics,
+            preview_lyrics_optimization,
+            preview_agent_recognition,
+            batch_convert::convert_lyrics_batch,
+            metadata_prefetch::set_metadata_prefetch_count,
+            persistence::save_persisted_state,
+            persistence::load_persisted_state,
+            persistence::remove_persisted_state,
+            lyric_backup::backup_user_lyrics,
+            lyric_backup::list_lyric_snapshots,
+            lyric_backup::restore_lyric_snapshot,
+            plugin_host::list_plugins,
+            plugin_host::install_plugin,
+            plugin_host::uninstall_plugin,
+            plugin_host::reload_plugins,
+            plugin_host::plugin_fetch_lyrics,
+            plugin_host::plugin_process_lyrics,
+            plugin_host::plugin_export_lyrics,
             restart_app,
+            startup_metrics::get_startup_metrics,
             #[cfg(target_os = "windows")]
             external_media_controller::control_external_media,
             #[cfg(target_os = "windows")]
             external_media_controller::request_smtc_update,
+            #[cfg(target_os = "android")]
+            background_service::set_background_service_enabled,
+            #[cfg(target_os = "android")]
+            background_service::update_background_service,
             reset_window_theme,
         ])
         .setup(|app| {
-            player::init_local_player(app.handle().clone());
+            // 播放器一启动就会发出事件，预取状态需要在此之前托管
+            app.manage(metadata_prefetch::MetadataPrefetchState::default());
+            startup_metrics::measure("local_player", || {
+                player::init_local_player(app.handle().clone())
+            });
 
+            // SMTC 监听会在前端第一次发送外部媒体命令时才启动
             #[cfg(target_os = "windows")]
-            {
-                info!("正在初始化外部媒体控制器...");
-                let controller_state =
-                    external_media_controller::start_listener(app.handle().clone());
-                app.manage(controller_state);
-            }
+            app.manage(external_media_controller::ExternalMediaController::new(
+                app.handle().clone(),
+            ));
 
             #[cfg(desktop)]
             let _ = app
                 .handle()
                 .plugin(tauri_plugin_global_shortcut::Builder::new().build());
+            // 插件在后台线程中加载，不会阻塞窗口的创建
+            plugin_host::init_plugins(app.handle());
+            app.manage(WaveformPeaksState::default());
+            // WebSocket 服务器在前端调用 `ws_reopen_connection` 时才会开始监听
             app.manage::<AMLLWebSocketServerWrapper>(RwLock::new(AMLLWebSocketServer::new(
                 app.handle().clone(),
             )));
             #[cfg(not(mobile))]
-            {
-                tauri::async_runtime::block_on(recreate_window(app.handle(), "main", None));
-            }
+            startup_metrics::measure("main_window", || {
+                tauri::async_runtime::block_on(recreate_window(app.handle(), "main", None))
+            });
             Ok(())
         })
         .run(context)
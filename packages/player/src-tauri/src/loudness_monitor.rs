@@ -0,0 +1,432 @@
+//! 对 SMTC 回环捕获到的外部音频进行实时响度估计
+//!
+//! 外部 App 的音频无法离线扫描，所以这里参考 ITU-R BS.1770 / EBU R128 的短期响度
+//! （K 计权，3 秒滑动窗口）持续估计当前响度，并据此给出建议的音量调整量。
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// 短期响度使用 30 个块，即 3 秒的窗口
+const SHORT_TERM_BLOCKS: usize = 30;
+/// 低于该响度的块视为静音，不参与计算
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// 对建议增益做指数平滑，避免音量随歌曲段落频繁跳动
+const GAIN_SMOOTHING: f64 = 0.2;
+
+/// 回环捕获的音频格式，采样均为交错排列的 f32 小端 PCM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+impl Default for PcmFormat {
+    /// 取不到输出设备的格式时按 48kHz 双声道处理
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+}
+
+impl PcmFormat {
+    /// 回环捕获的是默认输出设备的混音，格式与该设备共享模式下的格式一致
+    pub fn of_default_output() -> Option<Self> {
+        use rodio::cpal::traits::{DeviceTrait, HostTrait};
+
+        let config = rodio::cpal::default_host()
+            .default_output_device()?
+            .default_output_config()
+            .ok()?;
+        Some(Self {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels() as usize,
+        })
+    }
+
+    /// 每个响度块（100ms）包含的帧数
+    fn block_frames(self) -> usize {
+        (self.sample_rate as usize / 10).max(1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessNormalizationConfig {
+    pub enabled: bool,
+    /// 目标响度，单位 LUFS
+    pub target_lufs: f64,
+    /// 相对用户音量允许调整的最大幅度，单位 dB
+    pub max_adjust_db: f64,
+    /// 是否直接通过会话音量接口自动微调，关闭时只发送建议事件
+    pub auto_adjust: bool,
+}
+
+impl Default for LoudnessNormalizationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_lufs: -14.0,
+            max_adjust_db: 12.0,
+            auto_adjust: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessReport {
+    /// 当前的短期响度，单位 LUFS
+    pub loudness_lufs: f64,
+    /// 为达到目标响度建议调整的增益，单位 dB
+    pub suggested_gain_db: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// 按 BS.1770 构造 K 计权滤波器（高架滤波 + 高通滤波）
+fn k_weighting_filters(rate: f64) -> [Biquad; 2] {
+    let f0 = 1681.974_450_955_533;
+    let gain_db = 3.999_843_853_973_347;
+    let q = 0.707_175_236_955_419_6;
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+        ..Default::default()
+    };
+
+    let f0 = 38.135_470_876_024_44;
+    let q = 0.500_327_037_323_877_3;
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+        ..Default::default()
+    };
+
+    [shelf, high_pass]
+}
+
+fn mean_square_to_lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.max(f64::MIN_POSITIVE).log10()
+}
+
+pub struct LoudnessMonitor {
+    config: LoudnessNormalizationConfig,
+    format: PcmFormat,
+    filters: Vec<[Biquad; 2]>,
+    block_sum: f64,
+    block_len: usize,
+    blocks: VecDeque<f64>,
+    smoothed_gain_db: Option<f64>,
+    last_report: Instant,
+}
+
+impl Default for LoudnessMonitor {
+    fn default() -> Self {
+        Self::new(LoudnessNormalizationConfig::default())
+    }
+}
+
+impl LoudnessMonitor {
+    pub fn new(config: LoudnessNormalizationConfig) -> Self {
+        let format = PcmFormat::default();
+        Self {
+            config,
+            format,
+            filters: vec![k_weighting_filters(format.sample_rate as f64); format.channels],
+            block_sum: 0.0,
+            block_len: 0,
+            blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            smoothed_gain_db: None,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn config(&self) -> LoudnessNormalizationConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: LoudnessNormalizationConfig) {
+        if !config.enabled {
+            self.reset();
+        }
+        self.config = config;
+    }
+
+    /// 设置之后送入的音频数据的格式，格式变化时之前的数据会被丢弃
+    pub fn set_format(&mut self, format: PcmFormat) {
+        if format != self.format {
+            self.format = format;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filters =
+            vec![k_weighting_filters(self.format.sample_rate as f64); self.format.channels];
+        self.block_sum = 0.0;
+        self.block_len = 0;
+        self.blocks.clear();
+        self.smoothed_gain_db = None;
+    }
+
+    /// 压入一段回环捕获的原始 PCM 数据，每隔一段时间返回一次响度估计
+    pub fn push_pcm_bytes(&mut self, data: &[u8]) -> Option<LoudnessReport> {
+        if !self.config.enabled || self.format.channels == 0 {
+            return None;
+        }
+
+        let block_frames = self.format.block_frames();
+        for frame in data.chunks_exact(4 * self.format.channels) {
+            let mut frame_power = 0.0;
+            for (channel, sample) in frame.chunks_exact(4).enumerate() {
+                let sample = f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]);
+                let filtered = self.filters[channel]
+                    .iter_mut()
+                    .fold(sample as f64, |x, filter| filter.process(x));
+                frame_power += filtered * filtered;
+            }
+            self.block_sum += frame_power;
+            self.block_len += 1;
+
+            if self.block_len >= block_frames {
+                if self.blocks.len() >= SHORT_TERM_BLOCKS {
+                    self.blocks.pop_front();
+                }
+                self.blocks
+                    .push_back(self.block_sum / self.block_len as f64);
+                self.block_sum = 0.0;
+                self.block_len = 0;
+            }
+        }
+
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return None;
+        }
+        self.last_report = Instant::now();
+        self.report()
+    }
+
+    fn report(&mut self) -> Option<LoudnessReport> {
+        let gated: Vec<f64> = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|&power| mean_square_to_lufs(power) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+
+        let loudness_lufs = mean_square_to_lufs(gated.iter().sum::<f64>() / gated.len() as f64);
+        let target_gain_db = (self.config.target_lufs - loudness_lufs)
+            .clamp(-self.config.max_adjust_db, self.config.max_adjust_db);
+        let suggested_gain_db = match self.smoothed_gain_db {
+            Some(prev) => prev + (target_gain_db - prev) * GAIN_SMOOTHING,
+            None => target_gain_db,
+        };
+        self.smoothed_gain_db = Some(suggested_gain_db);
+
+        Some(LoudnessReport {
+            loudness_lufs,
+            suggested_gain_db,
+        })
+    }
+}
+
+/// 跟踪用户设置的会话音量，并把建议增益换算成新的会话音量
+///
+/// 回环捕获的是混音后的音频，调整会话音量会反过来影响测得的响度，
+/// 所以每次只按建议增益做增量调整，并以用户音量为基准限制总的调整范围。
+#[derive(Debug, Default)]
+pub struct AutoVolumeAdjuster {
+    user_volume: Option<f32>,
+    applied_volume: Option<f32>,
+}
+
+impl AutoVolumeAdjuster {
+    /// 会话音量发生变化时调用，用于区分用户手动调节与自动调节
+    pub fn on_volume_changed(&mut self, volume: f32) {
+        let is_own_change = self
+            .applied_volume
+            .is_some_and(|applied| (applied - volume).abs() < 0.01);
+        if !is_own_change {
+            self.user_volume = Some(volume);
+            self.applied_volume = None;
+        }
+    }
+
+    /// 根据响度报告计算新的会话音量，音量变化过小时返回 `None`
+    pub fn next_volume(
+        &mut self,
+        report: &LoudnessReport,
+        config: &LoudnessNormalizationConfig,
+    ) -> Option<f32> {
+        let user_volume = self.user_volume?;
+        let current = self.applied_volume.unwrap_or(user_volume);
+        let max_ratio = 10f32.powf(config.max_adjust_db as f32 / 20.0);
+        let next = (current * 10f32.powf(report.suggested_gain_db as f32 / 20.0))
+            .clamp(user_volume / max_ratio, user_volume * max_ratio)
+            .clamp(0.0, 1.0);
+        if (next - current).abs() < 0.01 {
+            return None;
+        }
+        self.applied_volume = Some(next);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_monitor(format: PcmFormat) -> LoudnessMonitor {
+        let mut monitor = LoudnessMonitor::new(LoudnessNormalizationConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        monitor.set_format(format);
+        monitor
+    }
+
+    /// 生成所有声道相同的 1kHz 正弦波
+    fn sine_bytes(format: PcmFormat, amplitude: f32, seconds: f32) -> Vec<u8> {
+        let frames = (format.sample_rate as f32 * seconds) as usize;
+        let mut bytes = Vec::with_capacity(frames * format.channels * 4);
+        for i in 0..frames {
+            let t = i as f32 / format.sample_rate as f32;
+            let sample = amplitude * (2.0 * std::f32::consts::PI * 1000.0 * t).sin();
+            for _ in 0..format.channels {
+                bytes.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn measure(format: PcmFormat, amplitude: f32) -> f64 {
+        let mut monitor = enabled_monitor(format);
+        monitor.push_pcm_bytes(&sine_bytes(format, amplitude, 3.0));
+        monitor.report().unwrap().loudness_lufs
+    }
+
+    #[test]
+    fn loudness_follows_stream_format() {
+        // 1kHz 处 K 计权的增益约为 +0.7dB，与 -0.691 的偏移基本抵消，
+        // 各声道的均方值（振幅平方的一半）之和即为响度
+        let stereo = PcmFormat {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        assert!((measure(stereo, 0.1) + 20.0).abs() < 0.2);
+
+        let mono = PcmFormat {
+            sample_rate: 44100,
+            channels: 1,
+        };
+        assert!((measure(mono, 0.1) + 23.0).abs() < 0.2);
+
+        let surround = PcmFormat {
+            sample_rate: 96000,
+            channels: 6,
+        };
+        assert!((measure(surround, 0.1) + 15.2).abs() < 0.2);
+    }
+
+    #[test]
+    fn format_change_discards_previous_blocks() {
+        let format = PcmFormat::default();
+        let mut monitor = enabled_monitor(format);
+        monitor.push_pcm_bytes(&sine_bytes(format, 0.1, 1.0));
+        assert_eq!(monitor.blocks.len(), 10);
+
+        monitor.set_format(format);
+        assert_eq!(monitor.blocks.len(), 10);
+        monitor.set_format(PcmFormat {
+            sample_rate: 44100,
+            channels: 2,
+        });
+        assert!(monitor.blocks.is_empty());
+        assert!(monitor.report().is_none());
+    }
+
+    #[test]
+    fn silence_is_gated() {
+        let mut monitor = enabled_monitor(PcmFormat::default());
+        monitor.push_pcm_bytes(&sine_bytes(PcmFormat::default(), 0.0, 1.0));
+        assert!(monitor.report().is_none());
+    }
+
+    #[test]
+    fn suggested_gain_is_clamped_and_smoothed() {
+        let format = PcmFormat::default();
+        let mut monitor = enabled_monitor(format);
+        monitor.push_pcm_bytes(&sine_bytes(format, 0.001, 3.0));
+        // 约 -60 LUFS，距离 -14 LUFS 的目标超过了 12dB 的调整上限
+        assert_eq!(monitor.report().unwrap().suggested_gain_db, 12.0);
+
+        monitor.push_pcm_bytes(&sine_bytes(format, 0.1, 3.0));
+        let report = monitor.report().unwrap();
+        // 目标增益约为 +6dB，只向其靠近 20%
+        assert!((report.suggested_gain_db - (12.0 + (6.0 - 12.0) * GAIN_SMOOTHING)).abs() < 0.2);
+    }
+
+    #[test]
+    fn auto_volume_stays_within_range_of_user_volume() {
+        let config = LoudnessNormalizationConfig {
+            max_adjust_db: 6.0,
+            ..Default::default()
+        };
+        let report = |suggested_gain_db| LoudnessReport {
+            loudness_lufs: -20.0,
+            suggested_gain_db,
+        };
+        let mut adjuster = AutoVolumeAdjuster::default();
+        assert_eq!(adjuster.next_volume(&report(3.0), &config), None);
+
+        adjuster.on_volume_changed(0.25);
+        let volume = adjuster.next_volume(&report(20.0), &config).unwrap();
+        assert!((volume - 0.25 * 10f32.powf(6.0 / 20.0)).abs() < 1e-4);
+
+        // 自动设置的音量回报回来时不应被当作用户调节
+        adjuster.on_volume_changed(volume);
+        assert_eq!(adjuster.next_volume(&report(20.0), &config), None);
+
+        adjuster.on_volume_changed(0.8);
+        assert_eq!(adjuster.next_volume(&report(0.01), &config), None);
+        assert!(adjuster.next_volume(&report(-3.0), &config).unwrap() < 0.8);
+    }
+}
@@ -0,0 +1,264 @@
+//! 用户修改过的歌词的自动备份
+//!
+//! 歌词本身存储在前端的数据库中，前端每次保存歌词后调用 `backup_user_lyrics`
+//! 把所有修改过的歌词交给后端。后端每天只保存一份快照，以当天的本地日期为名，
+//! 同一天内的多次保存会覆盖当天的快照，超过保留天数的快照会被删除。
+//! 恢复时返回快照中的歌词，由前端写回数据库
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+use tracing::{info, warn};
+
+use crate::persistence::write_file_atomic;
+
+const BACKUP_DIR_NAME: &str = "lyric_backups";
+const SNAPSHOT_EXTENSION: &str = "json";
+const DEFAULT_KEEP_DAYS: u32 = 30;
+/// 快照 ID 的格式，即保存快照的日期，按字符串排序即为按时间排序
+const SNAPSHOT_ID_FORMAT: &str = "%Y-%m-%d";
+
+/// 一首歌被用户修改过的歌词，字段与前端数据库中的歌曲记录一致
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserLyricEntry {
+    pub song_id: String,
+    pub lyric_format: String,
+    pub lyric: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translated_lrc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roman_lrc: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LyricSnapshot {
+    /// 快照最后一次写入的时间，为 Unix 毫秒时间戳
+    updated_at: i64,
+    lyrics: Vec<UserLyricEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricSnapshotInfo {
+    /// 快照的 ID，即保存快照的本地日期，格式为 `YYYY-MM-DD`
+    pub id: String,
+    pub updated_at: i64,
+    pub lyric_count: usize,
+}
+
+/// 解析快照 ID 对应的日期
+fn snapshot_date(id: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(id, SNAPSHOT_ID_FORMAT)
+        .ok()
+        .filter(|date| date.format(SNAPSHOT_ID_FORMAT).to_string() == id)
+}
+
+/// 快照 ID 必须是合法的日期，避免被用来访问备份目录以外的文件
+fn is_valid_snapshot_id(id: &str) -> bool {
+    snapshot_date(id).is_some()
+}
+
+fn backup_dir<R: Runtime>(app: &AppHandle<R>) -> anyhow::Result<PathBuf> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .context("无法获取应用数据目录")?
+        .join(BACKUP_DIR_NAME))
+}
+
+fn snapshot_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.{SNAPSHOT_EXTENSION}"))
+}
+
+fn read_snapshot(path: &Path) -> anyhow::Result<LyricSnapshot> {
+    let content = fs::read(path).with_context(|| format!("无法读取快照 {}", path.display()))?;
+    serde_json::from_slice(&content).with_context(|| format!("快照 {} 已损坏", path.display()))
+}
+
+/// 返回备份目录中所有快照的 ID，从新到旧排列
+fn snapshot_ids(dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == SNAPSHOT_EXTENSION)
+            && let Some(id) = path.file_stem().and_then(|stem| stem.to_str())
+            && is_valid_snapshot_id(id)
+        {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(ids)
+}
+
+/// 写入 `now` 当天的快照，再删除早于最近 `keep_days` 天的快照
+fn save_snapshot(
+    dir: &Path,
+    lyrics: Vec<UserLyricEntry>,
+    now: DateTime<Local>,
+    keep_days: u32,
+) -> anyhow::Result<String> {
+    fs::create_dir_all(dir)?;
+    let today = now.date_naive();
+    let id = today.format(SNAPSHOT_ID_FORMAT).to_string();
+    let snapshot = LyricSnapshot {
+        updated_at: now.timestamp_millis(),
+        lyrics,
+    };
+    write_file_atomic(&snapshot_path(dir, &id), &serde_json::to_vec(&snapshot)?)?;
+
+    let expired = |old_id: &String| {
+        snapshot_date(old_id)
+            .is_some_and(|date| (today - date).num_days() >= keep_days.max(1) as i64)
+    };
+    for old_id in snapshot_ids(dir)?.into_iter().filter(expired) {
+        let path = snapshot_path(dir, &old_id);
+        match fs::remove_file(&path) {
+            Ok(()) => info!("已删除过期的歌词快照 {old_id}"),
+            Err(err) => warn!("删除过期的歌词快照 {} 失败: {err}", path.display()),
+        }
+    }
+    Ok(id)
+}
+
+/// 保存当天的歌词快照，当天已有快照时覆盖它，并删除早于最近 `keep_days` 天（默认为 30）的快照
+///
+/// 返回保存的快照 ID
+#[tauri::command]
+pub async fn backup_user_lyrics<R: Runtime>(
+    app: AppHandle<R>,
+    lyrics: Vec<UserLyricEntry>,
+    keep_days: Option<u32>,
+) -> Result<String, String> {
+    let dir = backup_dir(&app).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || {
+        save_snapshot(
+            &dir,
+            lyrics,
+            Local::now(),
+            keep_days.unwrap_or(DEFAULT_KEEP_DAYS),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("备份歌词失败: {e:#}"))
+}
+
+/// 列出所有歌词快照，从新到旧排列，已损坏的快照会被跳过
+#[tauri::command]
+pub async fn list_lyric_snapshots<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<Vec<LyricSnapshotInfo>, String> {
+    let dir = backup_dir(&app).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let mut infos = Vec::new();
+        for id in snapshot_ids(&dir)? {
+            match read_snapshot(&snapshot_path(&dir, &id)) {
+                Ok(snapshot) => infos.push(LyricSnapshotInfo {
+                    id,
+                    updated_at: snapshot.updated_at,
+                    lyric_count: snapshot.lyrics.len(),
+                }),
+                Err(err) => warn!("{err:#}"),
+            }
+        }
+        Ok(infos)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("读取歌词快照列表失败: {e:#}"))
+}
+
+/// 读取指定快照中的歌词，由前端写回数据库以完成恢复
+#[tauri::command]
+pub async fn restore_lyric_snapshot<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+) -> Result<Vec<UserLyricEntry>, String> {
+    if !is_valid_snapshot_id(&id) {
+        return Err(format!("无效的快照 ID: {id}"));
+    }
+    let dir = backup_dir(&app).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || read_snapshot(&snapshot_path(&dir, &id)))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|snapshot| snapshot.lyrics)
+        .map_err(|e| format!("恢复歌词快照失败: {e:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(song_id: &str) -> UserLyricEntry {
+        UserLyricEntry {
+            song_id: song_id.to_string(),
+            lyric_format: "lrc".to_string(),
+            lyric: "[00:01.00]a".to_string(),
+            translated_lrc: None,
+            roman_lrc: None,
+        }
+    }
+
+    fn at(date: &str) -> DateTime<Local> {
+        NaiveDate::parse_from_str(date, SNAPSHOT_ID_FORMAT)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+    }
+
+    #[test]
+    fn snapshot_ids_are_dates() {
+        assert!(is_valid_snapshot_id("2026-10-15"));
+        assert!(!is_valid_snapshot_id("2026-10-15_21-30-05-123"));
+        assert!(!is_valid_snapshot_id("2026-13-01"));
+        assert!(!is_valid_snapshot_id("2026-1-5"));
+        assert!(!is_valid_snapshot_id("../2026-10-15"));
+    }
+
+    #[test]
+    fn saves_one_snapshot_per_day_and_prunes_by_age() {
+        let dir = std::env::temp_dir().join(format!("amll-lyric-backup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        save_snapshot(&dir, vec![entry("a")], at("2026-10-01"), 3).unwrap();
+        save_snapshot(&dir, vec![entry("b")], at("2026-10-02"), 3).unwrap();
+        // 同一天的多次保存覆盖当天的快照，不会挤掉之前几天的快照
+        for song_id in ["c", "d", "e", "f"] {
+            save_snapshot(&dir, vec![entry(song_id)], at("2026-10-03"), 3).unwrap();
+        }
+        assert_eq!(
+            snapshot_ids(&dir).unwrap(),
+            vec!["2026-10-03", "2026-10-02", "2026-10-01"]
+        );
+        let snapshot = read_snapshot(&snapshot_path(&dir, "2026-10-03")).unwrap();
+        assert_eq!(snapshot.lyrics[0].song_id, "f");
+
+        // 只保留最近三天的快照
+        save_snapshot(&dir, vec![entry("g")], at("2026-10-05"), 3).unwrap();
+        assert_eq!(
+            snapshot_ids(&dir).unwrap(),
+            vec!["2026-10-05", "2026-10-03"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
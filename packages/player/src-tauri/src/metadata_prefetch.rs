@@ -0,0 +1,168 @@
+//! 播放列表中接下来若干首歌曲的元数据（歌词、封面等）的后台预取
+//!
+//! 根据播放器发出的事件跟踪当前的播放列表和播放位置，在后台按顺序读取接下来的歌曲并缓存，
+//! 切歌时 `read_local_music_metadata` 就可以直接从缓存中取得结果。
+//! 缓存的结果只会被取用一次，之后再次读取同一首歌曲时会重新从文件中读取，避免歌词文件修改后读到旧内容
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use amll_player_core::{AudioThreadEvent, SongData};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tauri_plugin_fs::FsExt;
+use tracing::{debug, warn};
+
+use crate::MusicInfo;
+
+const DEFAULT_PREFETCH_COUNT: usize = 3;
+const CACHE_CAPACITY: usize = 32;
+
+#[derive(Default)]
+struct MetadataCache {
+    entries: HashMap<PathBuf, MusicInfo>,
+    order: VecDeque<PathBuf>,
+}
+
+impl MetadataCache {
+    fn contains(&self, path: &Path) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    fn take(&mut self, path: &Path) -> Option<MusicInfo> {
+        let info = self.entries.remove(path)?;
+        self.order.retain(|p| p != path);
+        Some(info)
+    }
+
+    fn insert(&mut self, path: PathBuf, info: MusicInfo) {
+        if self.entries.insert(path.clone(), info).is_none() {
+            self.order.push_back(path);
+        }
+        while self.order.len() > CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// 预取的缓存和当前的播放列表，由 Tauri 托管
+pub struct MetadataPrefetchState {
+    cache: Mutex<MetadataCache>,
+    playlist: Mutex<Vec<SongData>>,
+    prefetch_count: AtomicUsize,
+    // 每次播放位置或播放列表变化时递增，用于让过时的预取任务提前退出
+    generation: AtomicU64,
+}
+
+impl Default for MetadataPrefetchState {
+    fn default() -> Self {
+        Self {
+            cache: Mutex::default(),
+            playlist: Mutex::default(),
+            prefetch_count: AtomicUsize::new(DEFAULT_PREFETCH_COUNT),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl MetadataPrefetchState {
+    fn cache(&self) -> MutexGuard<'_, MetadataCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 取出已经预取好的歌曲元数据，取出后缓存中将不再保留
+    pub fn take(&self, path: &Path) -> Option<MusicInfo> {
+        self.cache().take(path)
+    }
+}
+
+/// 根据播放器事件更新播放列表，并预取当前歌曲之后的若干首歌曲
+pub fn handle_player_event<R: Runtime>(app: &AppHandle<R>, evt: &AudioThreadEvent) {
+    let Some(state) = app.try_state::<MetadataPrefetchState>() else {
+        return;
+    };
+    match evt {
+        AudioThreadEvent::PlayListChanged {
+            playlist,
+            current_play_index,
+        }
+        | AudioThreadEvent::SyncStatus {
+            playlist,
+            current_play_index,
+            ..
+        } => {
+            *state
+                .playlist
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = playlist.clone();
+            schedule_prefetch(app, &state, *current_play_index);
+        }
+        AudioThreadEvent::LoadingAudio {
+            current_play_index, ..
+        } => {
+            schedule_prefetch(app, &state, *current_play_index);
+        }
+        _ => {}
+    }
+}
+
+fn schedule_prefetch<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &MetadataPrefetchState,
+    current_play_index: usize,
+) {
+    let count = state.prefetch_count.load(Ordering::Relaxed);
+    let scope = app.fs_scope();
+    let paths: Vec<PathBuf> = state
+        .playlist
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .skip(current_play_index + 1)
+        .take(count)
+        .filter_map(|song| match song {
+            SongData::Local { file_path, .. } => Some(PathBuf::from(file_path)),
+            _ => None,
+        })
+        // 只预取前端有权访问的文件
+        .filter(|path| scope.is_allowed(path))
+        .collect();
+    let generation = state.generation.fetch_add(1, Ordering::SeqCst) + 1;
+    if paths.is_empty() {
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<MetadataPrefetchState>();
+        for path in paths {
+            if state.generation.load(Ordering::SeqCst) != generation {
+                debug!("播放位置已变化，停止过时的元数据预取");
+                return;
+            }
+            if state.cache().contains(&path) {
+                continue;
+            }
+            match crate::load_music_info(&path, |p| app.fs().read_to_string(p.to_path_buf())) {
+                Ok(info) => {
+                    debug!("已预取歌曲元数据: {}", path.display());
+                    state.cache().insert(path, info);
+                }
+                Err(err) => warn!("预取歌曲元数据失败 {}: {err:?}", path.display()),
+            }
+        }
+    });
+}
+
+/// 设置每次预取的歌曲数量，设置为 0 时关闭预取
+#[tauri::command]
+pub fn set_metadata_prefetch_count(count: usize, state: State<'_, MetadataPrefetchState>) {
+    state.prefetch_count.store(count, Ordering::Relaxed);
+}
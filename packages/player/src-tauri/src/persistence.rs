@@ -0,0 +1,306 @@
+//! 播放器状态（设置、播放队列等）的持久化
+//!
+//! 所有写入都先写到同一目录下名字唯一的临时文件并 fsync，再原子地重命名到目标位置，
+//! 这样即使在写入过程中崩溃，目标文件也只会是旧内容或新内容之一，而不会被截断清零，
+//! 同时进行的多次写入也不会互相截断对方的临时文件。
+//! 每次覆盖前会把旧内容保存为备份，读取失败时回退到最近一次的备份。
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tauri::{AppHandle, Manager, Runtime};
+use tracing::warn;
+
+const STATE_DIR_NAME: &str = "state";
+const BACKUP_EXTENSION: &str = "bak";
+
+fn with_extra_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+/// 将数据写入临时文件，fsync 后原子地替换目标文件
+pub fn write_file_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    file.write_all(content)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|err| err.error)?;
+
+    // 重命名本身也需要落盘，Windows 上无法对目录调用 fsync
+    #[cfg(unix)]
+    File::open(parent)?.sync_all()?;
+
+    Ok(())
+}
+
+/// 持久化一份状态数据，覆盖前会把当前内容保存为备份
+///
+/// 当前内容本身已经损坏（无法通过 `is_valid` 校验）时不会用它覆盖备份
+pub fn save_with_backup(
+    path: &Path,
+    content: &[u8],
+    is_valid: impl Fn(&[u8]) -> bool,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::read(path) {
+        Ok(current) if is_valid(&current) => {
+            write_file_atomic(&with_extra_extension(path, BACKUP_EXTENSION), &current)?;
+        }
+        Ok(_) => warn!("状态文件 {} 已损坏，跳过备份", path.display()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    write_file_atomic(path, content)
+}
+
+/// 读取一份状态数据，读取失败或内容无效时回退到最近一次的备份
+///
+/// 两者都不存在时返回 `None`
+pub fn load_with_fallback(
+    path: &Path,
+    is_valid: impl Fn(&[u8]) -> bool,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut last_error = None;
+    for candidate in [
+        path.to_path_buf(),
+        with_extra_extension(path, BACKUP_EXTENSION),
+    ] {
+        match fs::read(&candidate) {
+            Ok(content) if is_valid(&content) => return Ok(Some(content)),
+            Ok(_) => warn!("状态文件 {} 已损坏，尝试读取备份", candidate.display()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                warn!("读取状态文件 {} 失败: {err}", candidate.display());
+                last_error = Some(err);
+            }
+        }
+    }
+    last_error.map_or(Ok(None), Err)
+}
+
+/// 删除一份状态数据及其备份，两者都不存在时视为成功
+pub fn remove_with_backup(path: &Path) -> io::Result<()> {
+    for candidate in [
+        path.to_path_buf(),
+        with_extra_extension(path, BACKUP_EXTENSION),
+    ] {
+        match fs::remove_file(&candidate) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_valid_json(content: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(content).is_ok()
+}
+
+/// 状态键名直接用作文件名，只允许不以点开头的字母、数字和 `.-_`
+fn is_valid_state_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+pub(crate) fn state_file_path<R: Runtime>(
+    app: &AppHandle<R>,
+    key: &str,
+) -> anyhow::Result<PathBuf> {
+    if !is_valid_state_key(key) {
+        anyhow::bail!("无效的状态键名: {key}");
+    }
+    let dir = app
+        .path()
+        .app_data_dir()
+        .context("无法获取应用数据目录")?
+        .join(STATE_DIR_NAME);
+    Ok(dir.join(format!("{key}.json")))
+}
+
+#[tauri::command]
+pub async fn save_persisted_state<R: Runtime>(
+    app: AppHandle<R>,
+    key: String,
+    value: String,
+) -> Result<(), String> {
+    if !is_valid_json(value.as_bytes()) {
+        return Err(format!("状态 {key} 不是有效的 JSON"));
+    }
+    let path = state_file_path(&app, &key).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || save_with_backup(&path, value.as_bytes(), is_valid_json))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("保存状态 {key} 失败: {e}"))
+}
+
+#[tauri::command]
+pub async fn load_persisted_state<R: Runtime>(
+    app: AppHandle<R>,
+    key: String,
+) -> Result<Option<String>, String> {
+    let path = state_file_path(&app, &key).map_err(|e| e.to_string())?;
+    let content = tokio::task::spawn_blocking(move || load_with_fallback(&path, is_valid_json))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("读取状态 {key} 失败: {e}"))?;
+    // 已经校验过是有效的 JSON，必然是合法的 UTF-8
+    Ok(content.map(|content| String::from_utf8_lossy(&content).into_owned()))
+}
+
+#[tauri::command]
+pub async fn remove_persisted_state<R: Runtime>(
+    app: AppHandle<R>,
+    key: String,
+) -> Result<(), String> {
+    let path = state_file_path(&app, &key).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || remove_with_backup(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("删除状态 {key} 失败: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每个测试使用独立的临时目录，结束时删除
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("amll-persistence-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn backup_of(path: &Path) -> Vec<u8> {
+        fs::read(with_extra_extension(path, BACKUP_EXTENSION)).unwrap()
+    }
+
+    #[test]
+    fn atomic_write_replaces_content_without_leaving_temp_file() {
+        let dir = TempDir::new("atomic");
+        let path = dir.0.join("state.json");
+        write_file_atomic(&path, b"1").unwrap();
+        write_file_atomic(&path, b"2").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"2");
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn concurrent_writes_never_tear_the_file() {
+        let dir = TempDir::new("concurrent");
+        let path = dir.0.join("state.json");
+        std::thread::scope(|scope| {
+            for n in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    let content = format!("[\"{}\"]", n.to_string().repeat(4096));
+                    for _ in 0..20 {
+                        write_file_atomic(path, content.as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        assert!(is_valid_json(&fs::read(&path).unwrap()));
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn save_keeps_previous_content_as_backup() {
+        let dir = TempDir::new("backup");
+        let path = dir.0.join("nested").join("state.json");
+        save_with_backup(&path, b"[1]", is_valid_json).unwrap();
+        assert!(!with_extra_extension(&path, BACKUP_EXTENSION).exists());
+        save_with_backup(&path, b"[2]", is_valid_json).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"[2]");
+        assert_eq!(backup_of(&path), b"[1]");
+    }
+
+    #[test]
+    fn corrupted_content_does_not_overwrite_backup() {
+        let dir = TempDir::new("corrupted");
+        let path = dir.0.join("state.json");
+        save_with_backup(&path, b"[1]", is_valid_json).unwrap();
+        save_with_backup(&path, b"[2]", is_valid_json).unwrap();
+        fs::write(&path, b"").unwrap();
+        save_with_backup(&path, b"[3]", is_valid_json).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"[3]");
+        assert_eq!(backup_of(&path), b"[1]");
+    }
+
+    #[test]
+    fn load_falls_back_to_backup() {
+        let dir = TempDir::new("fallback");
+        let path = dir.0.join("state.json");
+        assert_eq!(load_with_fallback(&path, is_valid_json).unwrap(), None);
+
+        save_with_backup(&path, b"[1]", is_valid_json).unwrap();
+        save_with_backup(&path, b"[2]", is_valid_json).unwrap();
+        assert_eq!(
+            load_with_fallback(&path, is_valid_json).unwrap().as_deref(),
+            Some(&b"[2]"[..])
+        );
+
+        // 崩溃时被截断的文件
+        fs::write(&path, b"[2").unwrap();
+        assert_eq!(
+            load_with_fallback(&path, is_valid_json).unwrap().as_deref(),
+            Some(&b"[1]"[..])
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            load_with_fallback(&path, is_valid_json).unwrap().as_deref(),
+            Some(&b"[1]"[..])
+        );
+    }
+
+    #[test]
+    fn remove_deletes_state_and_backup() {
+        let dir = TempDir::new("remove");
+        let path = dir.0.join("state.json");
+        remove_with_backup(&path).unwrap();
+        save_with_backup(&path, b"[1]", is_valid_json).unwrap();
+        save_with_backup(&path, b"[2]", is_valid_json).unwrap();
+        remove_with_backup(&path).unwrap();
+        assert_eq!(load_with_fallback(&path, is_valid_json).unwrap(), None);
+    }
+
+    #[test]
+    fn state_keys_cannot_escape_state_dir() {
+        assert!(is_valid_state_key("amll-player.queue"));
+        assert!(is_valid_state_key("equalizer_v2"));
+        assert!(!is_valid_state_key(""));
+        assert!(!is_valid_state_key(".hidden"));
+        assert!(!is_valid_state_key("../settings"));
+        assert!(!is_valid_state_key("a/b"));
+        assert!(!is_valid_state_key(r"a\b"));
+    }
+}
@@ -1,25 +1,51 @@
-use std::sync::LazyLock;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
 
 use amll_player_core::AudioThreadEventMessage;
 use amll_player_core::AudioThreadMessage;
 use amll_player_core::{AudioPlayer, AudioPlayerConfig, AudioPlayerHandle};
+use amll_player_core::{AudioQuality, AudioThreadEvent};
 use rodio::OutputStream;
 use rodio::OutputStreamBuilder;
 use tauri::{AppHandle, Emitter, Runtime};
+use tauri_plugin_fs::FsExt;
 use tokio::sync::RwLock;
 use tracing::error;
 use tracing::warn;
 
+use crate::{equalizer, metadata_prefetch, replaygain};
+
 pub static PLAYER_HANDLER: LazyLock<RwLock<Option<AudioPlayerHandle>>> =
     LazyLock::new(|| RwLock::new(None));
+/// 根据播放器事件记录的当前歌曲的音源信息
+static STREAM_INFO: Mutex<Option<AudioQuality>> = Mutex::new(None);
+
+/// 消息中会被播放器创建或覆盖的文件路径
+fn written_path(msg: &AudioThreadMessage) -> Option<&str> {
+    match msg {
+        AudioThreadMessage::ExportAudio { output_path, .. }
+        | AudioThreadMessage::ExportWaveform { output_path, .. } => Some(output_path),
+        _ => None,
+    }
+}
 
+/// 把消息转发给播放器，会写入文件的消息只有在路径位于文件系统插件允许访问的范围内时才会转发
 #[tauri::command]
-pub async fn local_player_send_msg(msg: AudioThreadEventMessage<AudioThreadMessage>) {
+pub async fn local_player_send_msg(
+    app: AppHandle,
+    msg: AudioThreadEventMessage<AudioThreadMessage>,
+) -> Result<(), String> {
+    if let Some(path) = msg.data().and_then(written_path)
+        && !app.fs_scope().is_allowed(Path::new(path))
+    {
+        return Err(format!("没有写入 {path} 的权限"));
+    }
     if let Some(handler) = &*PLAYER_HANDLER.read().await
         && let Err(err) = handler.send(msg).await
     {
         warn!("failed to send msg to local player: {:?}", err);
     }
+    Ok(())
 }
 
 #[tauri::command]
@@ -35,6 +61,26 @@ pub async fn set_media_controls_enabled(enabled: bool) {
     }
 }
 
+/// 取得当前歌曲的编码、容器、码率、采样率、位深和声道布局，没有正在播放的歌曲时为空
+#[tauri::command]
+pub fn get_stream_info() -> Option<AudioQuality> {
+    STREAM_INFO.lock().unwrap().clone()
+}
+
+fn record_stream_info(evt: &AudioThreadEvent) {
+    match evt {
+        AudioThreadEvent::TrackOpened { quality, .. } => {
+            *STREAM_INFO.lock().unwrap() = Some(quality.clone());
+        }
+        AudioThreadEvent::SyncStatus {
+            music_id, quality, ..
+        } => {
+            *STREAM_INFO.lock().unwrap() = (!music_id.is_empty()).then(|| quality.clone());
+        }
+        _ => {}
+    }
+}
+
 pub fn init_local_player<R: Runtime>(app: AppHandle<R>) {
     std::thread::spawn(move || {
         let stream = OutputStreamBuilder::open_default_stream().expect("无法创建默认的音频输出流");
@@ -51,9 +97,15 @@ async fn local_player_main<R: Runtime>(app: AppHandle<R>, stream: OutputStream)
     let player = AudioPlayer::new(AudioPlayerConfig {}, stream);
     let handler = player.handler();
     PLAYER_HANDLER.write().await.replace(handler);
+    equalizer::restore_equalizer(&app).await;
+    replaygain::restore_replaygain_records(&app).await;
     let app_clone = app.clone();
     player
         .run(move |evt| {
+            if let Some(data) = evt.data() {
+                metadata_prefetch::handle_player_event(&app_clone, data);
+                record_stream_info(data);
+            }
             if let Err(err) = app_clone.emit("plugin:player-core-event", &evt) {
                 error!("发送事件时出错: {err:?}");
             }
@@ -0,0 +1,724 @@
+//! 基于 WASM 的运行时插件宿主
+//!
+//! 插件是放在应用数据目录 `plugins` 文件夹中的 `.wasm` 模块，可以提供歌词源、
+//! 歌词处理器或导出格式，安装和卸载后立即生效，不需要重新启动播放器。
+//!
+//! 每次调用都会使用一个新的实例，插件不能在调用之间保存状态。
+//! 每次调用能执行的指令数量、运行时间和可用内存都有限制，避免有问题的插件卡住播放器。
+//!
+//! # 插件 ABI（版本 1）
+//!
+//! 宿主与插件之间只传递字节串：宿主通过插件导出的 `amll_alloc` 申请内存并写入输入，
+//! 插件返回一个 `u64`，高 32 位为输出的指针，低 32 位为输出的长度，返回 `0` 表示没有输出，
+//! 宿主读取输出后会调用 `amll_free` 释放它，输入同样由宿主在调用结束后释放。
+//!
+//! 插件需要导出:
+//! * `memory`
+//! * `amll_abi_version() -> u32`，返回 [`PLUGIN_ABI_VERSION`]
+//! * `amll_alloc(len: u32) -> u32` 和 `amll_free(ptr: u32, len: u32)`
+//! * `amll_manifest() -> u64`，输出 JSON 格式的 [`PluginManifest`]
+//!
+//! 以及按 `capabilities` 声明的能力导出:
+//! * `amll_fetch_lyrics(ptr, len) -> u64`：输入 JSON 格式的 [`SongQuery`]，
+//!   输出 JSON 格式的 [`FetchedLyric`]，找不到歌词时返回 `0`
+//! * `amll_process_lyrics(ptr, len) -> u64`：输入并输出 TTML 歌词
+//! * `amll_export_lyrics(ptr, len) -> u64`：输入 JSON 格式的 [`ExportRequest`]，输出导出结果
+//!
+//! 宿主在 `amll` 模块中提供以下函数供插件导入:
+//! * `log(ptr: u32, len: u32)`：输出一条 UTF-8 日志
+//! * `http_get(ptr: u32, len: u32) -> u64`：以 GET 请求输入的 URL 并返回响应体，失败时返回 `0`。
+//!   只能请求插件在 `allowedHosts` 中声明的主机，响应体超过 [`MAX_HTTP_RESPONSE_BYTES`] 时视为失败
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, bail, ensure};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tauri_plugin_http::reqwest::{self, Url};
+use tracing::{error, info, warn};
+use wasmtime::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TypedFunc,
+};
+
+/// 当前宿主支持的插件 ABI 版本
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+const PLUGIN_DIR_NAME: &str = "plugins";
+const PLUGIN_EXTENSION: &str = "wasm";
+/// 每次调用插件时可以消耗的燃料，大致对应执行的指令数量
+const FUEL_PER_CALL: u64 = 5_000_000_000;
+/// 插件的线性内存上限
+const MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
+/// 引擎计时的间隔，每隔这么久推进一次 epoch
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+/// 每次调用插件最多可以运行的 epoch 数，约 30 秒，超时后调用会被中断
+const EPOCH_TICKS_PER_CALL: u64 = 300;
+/// 插件发起的 HTTP 请求的超时时间
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+/// 插件发起的 HTTP 请求的响应体大小上限
+pub const MAX_HTTP_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+pub type PluginHostState = RwLock<PluginHost>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PluginCapability {
+    /// 根据歌曲信息提供歌词
+    LyricSource,
+    /// 在歌词加载后对其进行处理，例如繁简转换或修正标点
+    LyricProcessor,
+    /// 提供额外的歌词导出格式
+    ExportFormat,
+}
+
+/// 插件提供的一种导出格式
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginExportFormat {
+    pub id: String,
+    pub name: String,
+    /// 导出文件的扩展名，不包含 `.`
+    pub extension: String,
+}
+
+/// 插件的描述信息，由插件的 `amll_manifest` 返回
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginManifest {
+    /// 插件的唯一 ID，只能包含字母、数字、`.`、`_` 和 `-`
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub capabilities: Vec<PluginCapability>,
+    #[serde(default)]
+    pub export_formats: Vec<PluginExportFormat>,
+    /// 插件可以通过 `http_get` 请求的主机，`*.example.com` 表示 `example.com` 及其所有子域名
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// 传给歌词源插件的歌曲信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongQuery {
+    pub music_name: String,
+    #[serde(default)]
+    pub artists: Vec<String>,
+    #[serde(default)]
+    pub album_name: String,
+    #[serde(default)]
+    pub duration_ms: u64,
+}
+
+/// 歌词源插件返回的歌词
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchedLyric {
+    /// 歌词的格式，与歌曲记录中的 `lyricFormat` 相同，例如 `ttml`、`lrc`
+    pub format: String,
+    pub content: String,
+    /// 提供歌词的插件 ID，由宿主填写
+    #[serde(default)]
+    pub plugin_id: String,
+}
+
+/// 传给导出格式插件的请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    /// 插件在 `exportFormats` 中声明的格式 ID
+    pub format: String,
+    pub ttml: String,
+}
+
+#[derive(Clone)]
+struct LoadedPlugin {
+    manifest: PluginManifest,
+    path: PathBuf,
+    module: Module,
+}
+
+impl LoadedPlugin {
+    fn has_capability(&self, capability: PluginCapability) -> bool {
+        self.manifest.capabilities.contains(&capability)
+    }
+
+    fn instantiate(&self, engine: &Engine) -> anyhow::Result<PluginInstance> {
+        PluginInstance::new(engine, &self.module, self.manifest.allowed_hosts.clone())
+    }
+}
+
+/// 每个插件实例的宿主状态
+struct PluginState {
+    limits: StoreLimits,
+    allowed_hosts: Vec<String>,
+}
+
+const fn pack(ptr: u32, len: u32) -> u64 {
+    ((ptr as u64) << 32) | len as u64
+}
+
+const fn unpack(packed: u64) -> (u32, u32) {
+    ((packed >> 32) as u32, packed as u32)
+}
+
+fn guest_memory(caller: &mut Caller<'_, PluginState>) -> anyhow::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .context("插件没有导出 memory")
+}
+
+/// 检查插件给出的一段内存是否在线性内存范围之内，在按长度分配缓冲区之前调用
+fn check_guest_range(memory_size: usize, ptr: u32, len: u32) -> anyhow::Result<()> {
+    ensure!(
+        ptr as u64 + len as u64 <= memory_size as u64,
+        "插件给出的内存范围 {ptr}+{len} 超出了内存大小 {memory_size}"
+    );
+    Ok(())
+}
+
+fn read_guest_bytes(
+    caller: &mut Caller<'_, PluginState>,
+    ptr: u32,
+    len: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let memory = guest_memory(caller)?;
+    check_guest_range(memory.data_size(&*caller), ptr, len)?;
+    let mut buf = vec![0; len as usize];
+    memory.read(&*caller, ptr as usize, &mut buf)?;
+    Ok(buf)
+}
+
+fn write_guest_bytes(caller: &mut Caller<'_, PluginState>, data: &[u8]) -> anyhow::Result<u64> {
+    let memory = guest_memory(caller)?;
+    let alloc = caller
+        .get_export("amll_alloc")
+        .and_then(Extern::into_func)
+        .context("插件没有导出 amll_alloc")?
+        .typed::<u32, u32>(&*caller)?;
+    let len = u32::try_from(data.len())?;
+    let ptr = alloc.call(&mut *caller, len)?;
+    memory.write(&mut *caller, ptr as usize, data)?;
+    Ok(pack(ptr, len))
+}
+
+fn is_host_allowed(allowed_hosts: &[String], host: &str) -> bool {
+    allowed_hosts.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        match allowed.strip_prefix("*.") {
+            Some(domain) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain)
+                        .is_some_and(|sub| sub.ends_with('.'))
+            }
+            None => host == allowed,
+        }
+    })
+}
+
+fn http_get(url: &str, allowed_hosts: &[String]) -> anyhow::Result<Vec<u8>> {
+    let url = Url::parse(url)?;
+    ensure!(
+        matches!(url.scheme(), "https" | "http"),
+        "只允许请求 HTTP(S) 地址"
+    );
+    let host = url.host_str().context("URL 中没有主机名")?;
+    ensure!(
+        is_host_allowed(allowed_hosts, &host.to_ascii_lowercase()),
+        "插件没有声明可以请求主机 {host}"
+    );
+    tauri::async_runtime::block_on(async {
+        let client = reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?;
+        let mut resp = client.get(url).send().await?.error_for_status()?;
+        if let Some(len) = resp.content_length() {
+            ensure!(
+                len <= MAX_HTTP_RESPONSE_BYTES as u64,
+                "响应体大小 {len} 超出了上限"
+            );
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            ensure!(
+                body.len() + chunk.len() <= MAX_HTTP_RESPONSE_BYTES,
+                "响应体超出了大小上限"
+            );
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    })
+}
+
+fn host_linker(engine: &Engine) -> anyhow::Result<Linker<PluginState>> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        "amll",
+        "log",
+        |mut caller: Caller<'_, PluginState>, ptr: u32, len: u32| -> anyhow::Result<()> {
+            let message = read_guest_bytes(&mut caller, ptr, len)?;
+            info!("[插件] {}", String::from_utf8_lossy(&message));
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "amll",
+        "http_get",
+        |mut caller: Caller<'_, PluginState>, ptr: u32, len: u32| -> anyhow::Result<u64> {
+            let url = String::from_utf8(read_guest_bytes(&mut caller, ptr, len)?)?;
+            match http_get(&url, &caller.data().allowed_hosts) {
+                Ok(body) if !body.is_empty() => write_guest_bytes(&mut caller, &body),
+                Ok(_) => Ok(0),
+                Err(err) => {
+                    warn!("插件请求 {url} 失败: {err:?}");
+                    Ok(0)
+                }
+            }
+        },
+    )?;
+    Ok(linker)
+}
+
+/// 插件的一个实例，只用于一次调用
+struct PluginInstance {
+    store: Store<PluginState>,
+    instance: wasmtime::Instance,
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+    free: TypedFunc<(u32, u32), ()>,
+}
+
+impl PluginInstance {
+    /// `allowed_hosts` 为插件可以请求的主机，读取描述信息时插件还没有声明，应当为空
+    fn new(engine: &Engine, module: &Module, allowed_hosts: Vec<String>) -> anyhow::Result<Self> {
+        let linker = host_linker(engine)?;
+        let state = PluginState {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY_BYTES)
+                .instances(1)
+                .build(),
+            allowed_hosts,
+        };
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+        store.set_epoch_deadline(EPOCH_TICKS_PER_CALL);
+        let instance = linker.instantiate(&mut store, module)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("插件没有导出 memory")?;
+        let alloc = instance.get_typed_func(&mut store, "amll_alloc")?;
+        let free = instance.get_typed_func(&mut store, "amll_free")?;
+        Ok(Self {
+            store,
+            instance,
+            memory,
+            alloc,
+            free,
+        })
+    }
+
+    fn abi_version(&mut self) -> anyhow::Result<u32> {
+        let func = self
+            .instance
+            .get_typed_func::<(), u32>(&mut self.store, "amll_abi_version")?;
+        Ok(func.call(&mut self.store, ())?)
+    }
+
+    fn take_output(&mut self, packed: u64) -> anyhow::Result<Option<Vec<u8>>> {
+        if packed == 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = unpack(packed);
+        check_guest_range(self.memory.data_size(&self.store), ptr, len)?;
+        let mut buf = vec![0; len as usize];
+        self.memory.read(&self.store, ptr as usize, &mut buf)?;
+        self.free.call(&mut self.store, (ptr, len))?;
+        Ok(Some(buf))
+    }
+
+    fn call_without_input(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let func = self
+            .instance
+            .get_typed_func::<(), u64>(&mut self.store, name)?;
+        let packed = func.call(&mut self.store, ())?;
+        self.take_output(packed)
+    }
+
+    fn call(&mut self, name: &str, input: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let func = self
+            .instance
+            .get_typed_func::<(u32, u32), u64>(&mut self.store, name)?;
+        let len = u32::try_from(input.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as usize, input)?;
+        let packed = func.call(&mut self.store, (ptr, len))?;
+        self.free.call(&mut self.store, (ptr, len))?;
+        self.take_output(packed)
+    }
+}
+
+fn is_valid_plugin_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        && !id.starts_with('.')
+}
+
+fn load_plugin(engine: &Engine, path: &Path) -> anyhow::Result<LoadedPlugin> {
+    let module = Module::from_file(engine, path)?;
+    let mut instance = PluginInstance::new(engine, &module, Vec::new())?;
+    let abi_version = instance.abi_version()?;
+    ensure!(
+        abi_version == PLUGIN_ABI_VERSION,
+        "插件的 ABI 版本为 {abi_version}，当前只支持版本 {PLUGIN_ABI_VERSION}"
+    );
+    let manifest = instance
+        .call_without_input("amll_manifest")?
+        .context("插件没有返回描述信息")?;
+    let manifest: PluginManifest =
+        serde_json::from_slice(&manifest).context("插件的描述信息无效")?;
+    ensure!(
+        is_valid_plugin_id(&manifest.id),
+        "插件 ID {:?} 无效",
+        manifest.id
+    );
+    Ok(LoadedPlugin {
+        manifest,
+        path: path.to_path_buf(),
+        module,
+    })
+}
+
+pub struct PluginHost {
+    engine: Engine,
+    dir: Option<PathBuf>,
+    plugins: Vec<LoadedPlugin>,
+}
+
+impl PluginHost {
+    pub fn new() -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true).epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+        // 插件宿主与应用的生命周期相同，计时线程不需要退出
+        let ticker = engine.clone();
+        thread::Builder::new()
+            .name("plugin-epoch".into())
+            .spawn(move || {
+                loop {
+                    thread::sleep(EPOCH_TICK);
+                    ticker.increment_epoch();
+                }
+            })?;
+        Ok(Self {
+            engine,
+            dir: None,
+            plugins: Vec::new(),
+        })
+    }
+
+    fn dir(&self) -> anyhow::Result<&Path> {
+        self.dir.as_deref().context("插件目录尚未初始化")
+    }
+
+    /// 重新加载插件目录中的所有插件，无法加载的插件会被跳过
+    pub fn load_dir(&mut self, dir: PathBuf) -> anyhow::Result<()> {
+        fs::create_dir_all(&dir)?;
+        let mut plugins: Vec<LoadedPlugin> = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(PLUGIN_EXTENSION) {
+                continue;
+            }
+            match load_plugin(&self.engine, &path) {
+                Ok(plugin) if plugins.iter().any(|p| p.manifest.id == plugin.manifest.id) => {
+                    warn!("插件 {} 的 ID 重复，已跳过", path.display());
+                }
+                Ok(plugin) => {
+                    info!(
+                        "已加载插件 {} {}",
+                        plugin.manifest.id, plugin.manifest.version
+                    );
+                    plugins.push(plugin);
+                }
+                Err(err) => warn!("加载插件 {} 失败: {err:?}", path.display()),
+            }
+        }
+        plugins.sort_by(|a, b| a.manifest.id.cmp(&b.manifest.id));
+        self.plugins = plugins;
+        self.dir = Some(dir);
+        Ok(())
+    }
+
+    /// 安装一个插件，已经安装了相同 ID 的插件时会替换它
+    pub fn install(&mut self, source: &Path) -> anyhow::Result<PluginManifest> {
+        let dir = self.dir()?.to_path_buf();
+        let plugin = load_plugin(&self.engine, source)?;
+        let target = dir.join(format!("{}.{PLUGIN_EXTENSION}", plugin.manifest.id));
+        if source != target {
+            // 先复制到临时文件再替换，复制失败时已经安装的旧版本保持不变
+            let temp = dir.join(format!("{}.{PLUGIN_EXTENSION}.tmp", plugin.manifest.id));
+            let replaced = fs::copy(source, &temp).and_then(|_| fs::rename(&temp, &target));
+            if let Err(err) = replaced {
+                let _ = fs::remove_file(&temp);
+                return Err(err).with_context(|| format!("无法复制插件到 {}", target.display()));
+            }
+        }
+        if let Some(old) = self
+            .plugins
+            .iter()
+            .position(|p| p.manifest.id == plugin.manifest.id)
+        {
+            let old = self.plugins.remove(old);
+            if old.path != target && old.path != source {
+                let _ = fs::remove_file(&old.path);
+            }
+        }
+        let manifest = plugin.manifest.clone();
+        self.plugins.push(LoadedPlugin {
+            path: target,
+            ..plugin
+        });
+        self.plugins
+            .sort_by(|a, b| a.manifest.id.cmp(&b.manifest.id));
+        info!("已安装插件 {} {}", manifest.id, manifest.version);
+        Ok(manifest)
+    }
+
+    /// 卸载并删除一个插件，插件不存在时返回 `false`
+    pub fn uninstall(&mut self, id: &str) -> anyhow::Result<bool> {
+        let Some(index) = self.plugins.iter().position(|p| p.manifest.id == id) else {
+            return Ok(false);
+        };
+        let plugin = self.plugins.remove(index);
+        fs::remove_file(&plugin.path)
+            .with_context(|| format!("无法删除插件 {}", plugin.path.display()))?;
+        info!("已卸载插件 {id}");
+        Ok(true)
+    }
+
+    pub fn list(&self) -> Vec<PluginManifest> {
+        self.plugins.iter().map(|p| p.manifest.clone()).collect()
+    }
+
+    /// 取出具有某种能力的插件，以便在释放锁之后调用它们
+    fn plugins_with(&self, capability: PluginCapability) -> (Engine, Vec<LoadedPlugin>) {
+        let plugins = self
+            .plugins
+            .iter()
+            .filter(|p| p.has_capability(capability))
+            .cloned()
+            .collect();
+        (self.engine.clone(), plugins)
+    }
+}
+
+fn fetch_lyrics(
+    engine: &Engine,
+    plugins: &[LoadedPlugin],
+    query: &SongQuery,
+) -> anyhow::Result<Option<FetchedLyric>> {
+    let input = serde_json::to_vec(query)?;
+    for plugin in plugins {
+        let result = plugin
+            .instantiate(engine)
+            .and_then(|mut instance| instance.call("amll_fetch_lyrics", &input))
+            .and_then(|output| {
+                output
+                    .map(|output| serde_json::from_slice::<FetchedLyric>(&output))
+                    .transpose()
+                    .map_err(Into::into)
+            });
+        match result {
+            Ok(Some(lyric)) => {
+                return Ok(Some(FetchedLyric {
+                    plugin_id: plugin.manifest.id.clone(),
+                    ..lyric
+                }));
+            }
+            Ok(None) => {}
+            Err(err) => warn!("歌词源插件 {} 出错: {err:?}", plugin.manifest.id),
+        }
+    }
+    Ok(None)
+}
+
+fn process_lyrics(engine: &Engine, plugins: &[LoadedPlugin], ttml: String) -> String {
+    plugins.iter().fold(ttml, |ttml, plugin| {
+        let result = plugin
+            .instantiate(engine)
+            .and_then(|mut instance| instance.call("amll_process_lyrics", ttml.as_bytes()))
+            .and_then(|output| {
+                output
+                    .map(String::from_utf8)
+                    .transpose()
+                    .map_err(Into::into)
+            });
+        match result {
+            Ok(Some(processed)) => processed,
+            Ok(None) => ttml,
+            Err(err) => {
+                warn!("歌词处理插件 {} 出错: {err:?}", plugin.manifest.id);
+                ttml
+            }
+        }
+    })
+}
+
+fn export_lyrics(
+    engine: &Engine,
+    plugins: &[LoadedPlugin],
+    plugin_id: &str,
+    request: &ExportRequest,
+) -> anyhow::Result<String> {
+    let Some(plugin) = plugins.iter().find(|p| p.manifest.id == plugin_id) else {
+        bail!("没有提供导出格式的插件 {plugin_id}");
+    };
+    if !plugin
+        .manifest
+        .export_formats
+        .iter()
+        .any(|f| f.id == request.format)
+    {
+        bail!("插件 {plugin_id} 不支持导出格式 {}", request.format);
+    }
+    let output = plugin
+        .instantiate(engine)?
+        .call("amll_export_lyrics", &serde_json::to_vec(request)?)?
+        .context("插件没有返回导出结果")?;
+    Ok(String::from_utf8(output)?)
+}
+
+fn plugin_dir<R: Runtime>(app: &AppHandle<R>) -> anyhow::Result<PathBuf> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .context("无法获取应用数据目录")?
+        .join(PLUGIN_DIR_NAME))
+}
+
+/// 创建插件宿主并在后台加载插件目录中的插件，宿主无法创建时记录错误，播放器不加载插件继续运行
+pub fn init_plugins<R: Runtime>(app: &AppHandle<R>) {
+    match PluginHost::new() {
+        Ok(host) => {
+            app.manage::<PluginHostState>(RwLock::new(host));
+        }
+        Err(err) => {
+            error!("创建插件宿主失败，将不加载插件: {err:?}");
+            return;
+        }
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = plugin_dir(&app).and_then(|dir| host(&app)?.write().unwrap().load_dir(dir));
+        if let Err(err) = result {
+            warn!("加载插件失败: {err:?}");
+        }
+    });
+}
+
+fn host<R: Runtime>(app: &AppHandle<R>) -> anyhow::Result<State<'_, PluginHostState>> {
+    app.try_state::<PluginHostState>()
+        .context("插件宿主未能初始化")
+}
+
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub fn list_plugins<R: Runtime>(app: AppHandle<R>) -> Vec<PluginManifest> {
+    host(&app)
+        .map(|host| host.read().unwrap().list())
+        .unwrap_or_default()
+}
+
+/// 安装一个插件文件，返回插件的描述信息
+#[tauri::command]
+pub async fn install_plugin<R: Runtime>(
+    app: AppHandle<R>,
+    path: PathBuf,
+) -> Result<PluginManifest, String> {
+    run_blocking(move || host(&app)?.write().unwrap().install(&path)).await
+}
+
+#[tauri::command]
+pub async fn uninstall_plugin<R: Runtime>(app: AppHandle<R>, id: String) -> Result<bool, String> {
+    run_blocking(move || host(&app)?.write().unwrap().uninstall(&id)).await
+}
+
+/// 重新扫描并加载插件目录，用于用户手动放入插件文件之后
+#[tauri::command]
+pub async fn reload_plugins<R: Runtime>(app: AppHandle<R>) -> Result<Vec<PluginManifest>, String> {
+    run_blocking(move || {
+        let dir = plugin_dir(&app)?;
+        let host = host(&app)?;
+        let mut host = host.write().unwrap();
+        host.load_dir(dir)?;
+        Ok(host.list())
+    })
+    .await
+}
+
+/// 依次询问歌词源插件，返回第一个找到的歌词
+#[tauri::command]
+pub async fn plugin_fetch_lyrics<R: Runtime>(
+    app: AppHandle<R>,
+    query: SongQuery,
+) -> Result<Option<FetchedLyric>, String> {
+    let Ok(host) = host(&app) else {
+        return Ok(None);
+    };
+    let (engine, plugins) = host
+        .read()
+        .unwrap()
+        .plugins_with(PluginCapability::LyricSource);
+    run_blocking(move || fetch_lyrics(&engine, &plugins, &query)).await
+}
+
+/// 依次交给所有歌词处理插件处理 TTML 歌词，出错的插件会被跳过
+#[tauri::command]
+pub async fn plugin_process_lyrics<R: Runtime>(
+    app: AppHandle<R>,
+    ttml: String,
+) -> Result<String, String> {
+    let Ok(host) = host(&app) else {
+        return Ok(ttml);
+    };
+    let (engine, plugins) = host
+        .read()
+        .unwrap()
+        .plugins_with(PluginCapability::LyricProcessor);
+    run_blocking(move || Ok(process_lyrics(&engine, &plugins, ttml))).await
+}
+
+#[tauri::command]
+pub async fn plugin_export_lyrics<R: Runtime>(
+    app: AppHandle<R>,
+    plugin_id: String,
+    request: ExportRequest,
+) -> Result<String, String> {
+    let (engine, plugins) = host(&app)
+        .map_err(|e| format!("{e:#}"))?
+        .read()
+        .unwrap()
+        .plugins_with(PluginCapability::ExportFormat);
+    run_blocking(move || export_lyrics(&engine, &plugins, &plugin_id, &request)).await
+}
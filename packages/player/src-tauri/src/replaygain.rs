@@ -0,0 +1,276 @@
+//! 扫描曲库中歌曲的响度，计算 ReplayGain 的单曲增益与专辑增益
+//!
+//! 扫描结果保存在状态目录的 `replaygain.json` 中，播放器启动后会读取并交给播放核心，
+//! 歌曲文件本身没有增益标签时会使用这里的记录。
+//! 扫描进度通过前端传入的通道逐个文件报告，单个文件失败不会中断整个任务
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use amll_player_core::{
+    AudioThreadMessage, LoudnessTags, TrackLoudness, album_loudness, loudness_to_gain_db,
+    scan_track_loudness,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Runtime, ipc::Channel};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::{persistence, player::PLAYER_HANDLER};
+
+const REPLAYGAIN_STATE_KEY: &str = "replaygain";
+
+/// 以文件路径为键的扫描记录
+static REPLAYGAIN_RECORDS: LazyLock<RwLock<HashMap<String, LoudnessTags>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+static SCAN_RUNNING: AtomicBool = AtomicBool::new(false);
+static SCAN_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// 扫描过程中发送给前端的事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum ReplayGainScanEvent {
+    /// 开始扫描，`total` 为将要扫描的文件数量
+    #[serde(rename_all = "camelCase")]
+    Started { total: usize },
+    /// 一个文件扫描成功
+    #[serde(rename_all = "camelCase")]
+    Scanned {
+        index: usize,
+        total: usize,
+        path: String,
+        loudness_lufs: f64,
+        track_gain_db: f64,
+        track_peak: f64,
+    },
+    /// 一个文件扫描失败
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        index: usize,
+        total: usize,
+        path: String,
+        error: String,
+    },
+    /// 一张专辑的歌曲均已扫描，算出了专辑增益
+    #[serde(rename_all = "camelCase")]
+    AlbumScanned {
+        album: String,
+        tracks: usize,
+        album_gain_db: f64,
+        album_peak: f64,
+    },
+    /// 扫描结束，`cancelled` 为真时表示被中途取消，此时不会计算专辑增益
+    #[serde(rename_all = "camelCase")]
+    Finished {
+        scanned: usize,
+        failed: usize,
+        cancelled: bool,
+    },
+}
+
+async fn save_records<R: Runtime>(
+    app: &AppHandle<R>,
+    records: &HashMap<String, LoudnessTags>,
+) -> anyhow::Result<()> {
+    let path = persistence::state_file_path(app, REPLAYGAIN_STATE_KEY)?;
+    let content = serde_json::to_vec(records)?;
+    tokio::task::spawn_blocking(move || {
+        persistence::save_with_backup(&path, &content, persistence::is_valid_json)
+    })
+    .await??;
+    Ok(())
+}
+
+async fn send_to_player(msg: AudioThreadMessage) {
+    if let Some(handler) = &*PLAYER_HANDLER.read().await
+        && let Err(err) = handler.send_anonymous(msg).await
+    {
+        warn!("failed to send scanned loudness to local player: {:?}", err);
+    }
+}
+
+/// 读取保存的扫描记录并交给播放核心，在播放器初始化完成后调用
+pub async fn restore_replaygain_records<R: Runtime>(app: &AppHandle<R>) {
+    let result = async {
+        let path = persistence::state_file_path(app, REPLAYGAIN_STATE_KEY)?;
+        let content = tokio::task::spawn_blocking(move || {
+            persistence::load_with_fallback(&path, persistence::is_valid_json)
+        })
+        .await??;
+        anyhow::Ok(match content {
+            Some(content) => Some(serde_json::from_slice::<HashMap<String, LoudnessTags>>(
+                &content,
+            )?),
+            None => None,
+        })
+    }
+    .await;
+
+    match result {
+        Ok(Some(records)) => {
+            *REPLAYGAIN_RECORDS.write().await = records.clone();
+            send_to_player(AudioThreadMessage::SetScannedLoudness { records }).await;
+        }
+        Ok(None) => {}
+        Err(err) => warn!("读取 ReplayGain 扫描记录失败: {err:?}"),
+    }
+}
+
+/// 同一文件夹中专辑名相同的歌曲视为同一张专辑，没有专辑名的歌曲只计算单曲增益
+fn album_key(path: &str, track: &TrackLoudness) -> Option<(String, String)> {
+    if track.album.trim().is_empty() {
+        return None;
+    }
+    let dir = Path::new(path)
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Some((dir, track.album.clone()))
+}
+
+fn scan_files(
+    files: Vec<String>,
+    on_event: &Channel<ReplayGainScanEvent>,
+) -> HashMap<String, LoudnessTags> {
+    let total = files.len();
+    let _ = on_event.send(ReplayGainScanEvent::Started { total });
+
+    let mut records = HashMap::new();
+    let mut albums: HashMap<(String, String), Vec<(String, TrackLoudness)>> = HashMap::new();
+    let mut failed = 0;
+    let mut cancelled = false;
+    for (index, path) in files.into_iter().enumerate() {
+        if SCAN_CANCELLED.load(Ordering::Relaxed) {
+            cancelled = true;
+            break;
+        }
+        let event = match scan_track_loudness(&path, &SCAN_CANCELLED) {
+            Ok(track) => {
+                records.insert(
+                    path.clone(),
+                    LoudnessTags {
+                        track_gain_db: Some(track.gain_db()),
+                        track_peak: Some(track.peak),
+                        ..LoudnessTags::default()
+                    },
+                );
+                let event = ReplayGainScanEvent::Scanned {
+                    index,
+                    total,
+                    path: path.clone(),
+                    loudness_lufs: track.integrated_lufs,
+                    track_gain_db: track.gain_db(),
+                    track_peak: track.peak,
+                };
+                if let Some(key) = album_key(&path, &track) {
+                    albums.entry(key).or_default().push((path, track));
+                }
+                event
+            }
+            Err(_) if SCAN_CANCELLED.load(Ordering::Relaxed) => {
+                cancelled = true;
+                break;
+            }
+            Err(err) => {
+                warn!("扫描 {path} 的响度失败: {err:?}");
+                failed += 1;
+                ReplayGainScanEvent::Failed {
+                    index,
+                    total,
+                    path,
+                    error: format!("{err:#}"),
+                }
+            }
+        };
+        let _ = on_event.send(event);
+    }
+
+    if !cancelled {
+        for ((_, album), tracks) in albums {
+            let loudness: Vec<TrackLoudness> =
+                tracks.iter().map(|(_, track)| track.clone()).collect();
+            let Some((album_lufs, album_peak)) = album_loudness(&loudness) else {
+                continue;
+            };
+            let album_gain_db = loudness_to_gain_db(album_lufs);
+            for (path, _) in &tracks {
+                if let Some(tags) = records.get_mut(path) {
+                    tags.album_gain_db = Some(album_gain_db);
+                    tags.album_peak = Some(album_peak);
+                }
+            }
+            let _ = on_event.send(ReplayGainScanEvent::AlbumScanned {
+                album,
+                tracks: tracks.len(),
+                album_gain_db,
+                album_peak,
+            });
+        }
+    }
+
+    info!(
+        "ReplayGain 扫描结束，成功 {} 个，失败 {failed} 个{}",
+        records.len(),
+        if cancelled { "（已取消）" } else { "" }
+    );
+    let _ = on_event.send(ReplayGainScanEvent::Finished {
+        scanned: records.len(),
+        failed,
+        cancelled,
+    });
+    records
+}
+
+/// 扫描给定歌曲文件的响度，计算并记录单曲增益与专辑增益
+///
+/// 同一时间只能进行一个扫描任务。已经扫描的结果在取消后也会保留，
+/// 只有结果无法保存时才会返回错误，单个文件的错误通过 `on_event` 报告
+#[tauri::command]
+pub async fn scan_replaygain<R: Runtime>(
+    app: AppHandle<R>,
+    files: Vec<String>,
+    on_event: Channel<ReplayGainScanEvent>,
+) -> Result<(), String> {
+    if SCAN_RUNNING.swap(true, Ordering::AcqRel) {
+        return Err("已有 ReplayGain 扫描任务正在进行".to_string());
+    }
+    SCAN_CANCELLED.store(false, Ordering::Relaxed);
+
+    let scanned = tauri::async_runtime::spawn_blocking(move || scan_files(files, &on_event)).await;
+    let result = match scanned {
+        Ok(scanned) => {
+            send_to_player(AudioThreadMessage::RecordScannedLoudness {
+                records: scanned.clone(),
+            })
+            .await;
+            let records = {
+                let mut records = REPLAYGAIN_RECORDS.write().await;
+                records.extend(scanned);
+                records.clone()
+            };
+            save_records(&app, &records)
+                .await
+                .map_err(|e| format!("保存 ReplayGain 扫描记录失败: {e}"))
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    SCAN_RUNNING.store(false, Ordering::Release);
+    result
+}
+
+/// 让正在进行的扫描尽快停止
+#[tauri::command]
+pub fn cancel_replaygain_scan() {
+    SCAN_CANCELLED.store(true, Ordering::Relaxed);
+}
+
+#[tauri::command]
+pub async fn get_replaygain_records() -> HashMap<String, LoudnessTags> {
+    REPLAYGAIN_RECORDS.read().await.clone()
+}
@@ -1,121 +1,335 @@
 use std::collections::HashMap;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use futures::prelude::*;
 use futures::stream::SplitSink;
+use serde::{Deserialize, Serialize};
 use tauri::ipc::Channel;
 use tauri::{AppHandle, Emitter};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::RwLock as TokioRwLock;
 use tokio::task::JoinHandle;
 use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{WebSocketStream, accept_async};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::{WebSocketStream, accept_hdr_async};
 use tracing::*;
 use ws_protocol::{v1, v2};
 
 type Connections = Arc<TokioRwLock<HashMap<SocketAddr, ConnectionInfo>>>;
+type Endpoints = Arc<RwLock<HashMap<String, Arc<Endpoint>>>>;
+
+/// 默认端点的 ID，旧的 `ws_*` 命令都作用于这个端点
+pub const DEFAULT_ENDPOINT_ID: &str = "default";
+/// 客户端连接后需要在该时长内发送能识别出协议的消息，否则断开连接
+const IDENTIFY_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ProtocolType {
     Unknown,
     BinaryV1,
+    /// 旧版 AMLL Player 桌面端使用的 JSON 文本形式的 v1 协议
+    ///
+    /// 这类客户端不会发送 `Initialize`，发送给它们的消息会降级为 v1 协议，
+    /// v1 协议无法表示的消息（如循环和随机播放模式）会被直接丢弃
+    JsonV1,
     HybridV2,
 }
 
+impl ProtocolType {
+    /// 根据客户端发来的消息判断其使用的协议，无法判断时返回 `Unknown`
+    ///
+    /// 旧版客户端不会发送 `Initialize`，而是直接发送 v1 或 v2 消息，
+    /// 而心跳消息在两种协议中的 JSON 格式相同，因此不能用于判断
+    fn detect(message: &Message) -> Self {
+        match message {
+            Message::Text(text) => {
+                if let Ok(v2_message) = serde_json::from_str::<v2::MessageV2>(text) {
+                    match v2_message.payload {
+                        v2::Payload::Ping | v2::Payload::Pong => Self::Unknown,
+                        _ => Self::HybridV2,
+                    }
+                } else if serde_json::from_str::<v1::Body>(text).is_ok() {
+                    Self::JsonV1
+                } else {
+                    Self::Unknown
+                }
+            }
+            Message::Binary(_) => Self::BinaryV1,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 struct ConnectionInfo {
     sink: SplitSink<WebSocketStream<TcpStream>, Message>,
     protocol: ProtocolType,
 }
 
+/// 一个 WebSocket 端点的配置
+///
+/// 多个端点可以监听不同的端口，也可以共用同一个端口并以不同的路径区分，
+/// 每个端点收到的数据只会发送到打开该端点时传入的通道中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointConfig {
+    pub id: String,
+    /// 监听地址，例如 `0.0.0.0:11444`
+    pub addr: String,
+    /// 端点的路径，例如 `/smtc`，为空时接受该地址上所有未被其它端点占用的路径
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointInfo {
+    #[serde(flatten)]
+    pub config: EndpointConfig,
+    pub connections: Vec<SocketAddr>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct EndpointClientEvent<'a> {
+    endpoint_id: &'a str,
+    addr: &'a str,
+}
+
+struct Endpoint {
+    config: EndpointConfig,
+    channel: Channel<v2::Payload>,
+    connections: Connections,
+}
+
+impl Endpoint {
+    fn normalized_path(&self) -> Option<String> {
+        self.config.path.as_deref().map(normalize_path)
+    }
+
+    async fn close_connections(&self) {
+        let mut conns = self.connections.write().await;
+        for (addr, conn_sink) in conns.iter_mut() {
+            if let Err(e) = conn_sink.sink.close().await {
+                warn!("断开和 {} 的 WebSocket 连接失败:{:?}", addr, e);
+            }
+        }
+        conns.clear();
+    }
+
+    fn emit_client_event(&self, app: &AppHandle, event: &str, addr: &str) {
+        // 默认端点继续发送旧的事件，兼容只使用单个端点的前端
+        if self.config.id == DEFAULT_ENDPOINT_ID {
+            let _ = app.emit(&format!("on-ws-protocol-client-{event}"), addr);
+        }
+        let _ = app.emit(
+            &format!("on-ws-endpoint-client-{event}"),
+            EndpointClientEvent {
+                endpoint_id: &self.config.id,
+                addr,
+            },
+        );
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    let trimmed = path.trim().trim_end_matches('/');
+    if trimmed.starts_with('/') {
+        trimmed.to_string()
+    } else {
+        format!("/{trimmed}")
+    }
+}
+
+/// 选出监听地址 `addr` 上处理路径 `path` 的端点，路径完全匹配的端点优先于未指定路径的端点
+fn route(endpoints: &Endpoints, addr: &str, path: &str) -> Option<Arc<Endpoint>> {
+    let path = normalize_path(path);
+    let mut fallback = None;
+    for endpoint in endpoints.read().unwrap().values() {
+        if endpoint.config.addr != addr {
+            continue;
+        }
+        match endpoint.normalized_path() {
+            Some(endpoint_path) if endpoint_path == path => return Some(endpoint.clone()),
+            None => fallback = Some(endpoint.clone()),
+            Some(_) => {}
+        }
+    }
+    fallback
+}
+
 pub struct AMLLWebSocketServer {
     app: AppHandle,
-    server_handle: Option<JoinHandle<()>>,
-    connections: Connections,
+    endpoints: Endpoints,
+    /// 每个监听地址对应一个监听任务，由该地址上的所有端点共用
+    listeners: HashMap<String, JoinHandle<()>>,
 }
 
 impl AMLLWebSocketServer {
     pub fn new(app: AppHandle) -> Self {
         Self {
             app,
-            server_handle: None,
-            connections: Arc::new(TokioRwLock::new(HashMap::with_capacity(8))),
+            endpoints: Arc::new(RwLock::new(HashMap::with_capacity(4))),
+            listeners: HashMap::with_capacity(4),
         }
     }
 
+    /// 关闭默认端点
     pub async fn close(&mut self) {
-        if let Some(task) = self.server_handle.take() {
-            task.abort();
+        self.close_endpoint(DEFAULT_ENDPOINT_ID).await;
+    }
+
+    /// 以新的地址重新打开默认端点，地址为空时只关闭默认端点
+    pub async fn reopen(
+        &mut self,
+        addr: String,
+        channel: Channel<v2::Payload>,
+    ) -> anyhow::Result<()> {
+        if addr.is_empty() {
+            self.close().await;
+            return Ok(());
         }
-        let mut conns = self.connections.write().await;
-        for (addr, conn_sink) in conns.iter_mut() {
-            if let Err(e) = conn_sink.sink.close().await {
-                warn!("断开和 {} 的 WebSocket 连接失败:{:?}", addr, e);
-            }
+        self.open_endpoint(
+            EndpointConfig {
+                id: DEFAULT_ENDPOINT_ID.to_string(),
+                addr,
+                path: None,
+            },
+            channel,
+        )
+        .await
+    }
+
+    /// 打开一个端点，已经存在相同 ID 的端点时会先关闭它
+    pub async fn open_endpoint(
+        &mut self,
+        config: EndpointConfig,
+        channel: Channel<v2::Payload>,
+    ) -> anyhow::Result<()> {
+        if config.id.is_empty() {
+            anyhow::bail!("端点 ID 不能为空");
         }
-        conns.clear();
-        info!("WebSocket 服务器已关闭");
+        if config.addr.is_empty() {
+            anyhow::bail!("端点 {} 的监听地址不能为空", config.id);
+        }
+        let path = config.path.as_deref().map(normalize_path);
+        if let Some(other) = self.endpoints.read().unwrap().values().find(|e| {
+            e.config.id != config.id && e.config.addr == config.addr && e.normalized_path() == path
+        }) {
+            anyhow::bail!(
+                "端点 {} 与端点 {} 的地址和路径相同",
+                config.id,
+                other.config.id
+            );
+        }
+
+        self.close_endpoint(&config.id).await;
+
+        let addr = config.addr.clone();
+        info!(
+            "已打开 WebSocket 端点 {}: {addr}{}",
+            config.id,
+            path.as_deref().unwrap_or_default()
+        );
+        self.endpoints.write().unwrap().insert(
+            config.id.clone(),
+            Arc::new(Endpoint {
+                config,
+                channel,
+                connections: Arc::new(TokioRwLock::new(HashMap::with_capacity(8))),
+            }),
+        );
+        if !self.listeners.contains_key(&addr) {
+            let listener = self.spawn_listener(addr.clone());
+            self.listeners.insert(addr, listener);
+        }
+        Ok(())
     }
 
-    pub fn reopen(&mut self, addr: String, channel: Channel<v2::Payload>) {
-        if let Some(task) = self.server_handle.take() {
+    /// 关闭一个端点并断开它的所有连接，该地址上没有其它端点时会停止监听
+    ///
+    /// 端点不存在时返回 `false`
+    pub async fn close_endpoint(&mut self, id: &str) -> bool {
+        let Some(endpoint) = self.endpoints.write().unwrap().remove(id) else {
+            return false;
+        };
+        endpoint.close_connections().await;
+
+        let addr = &endpoint.config.addr;
+        let addr_in_use = self
+            .endpoints
+            .read()
+            .unwrap()
+            .values()
+            .any(|e| &e.config.addr == addr);
+        if !addr_in_use && let Some(task) = self.listeners.remove(addr) {
             task.abort();
+            info!("WebSocket 服务器 {addr} 已关闭");
         }
-        if addr.is_empty() {
-            info!("WebSocket 服务器已关闭");
-            return;
-        }
-        let app = self.app.clone();
-        let connections = self.connections.clone();
+        info!("WebSocket 端点 {id} 已关闭");
+        true
+    }
 
-        self.server_handle = Some(tokio::spawn(async move {
-            loop {
-                info!("正在开启 WebSocket 服务器到 {addr}");
-                match TcpListener::bind(&addr).await {
-                    Ok(listener) => {
-                        info!("已开启 WebSocket 服务器到 {addr}");
-                        while let Ok((stream, _)) = listener.accept().await {
-                            tokio::spawn(Self::accept_conn(
-                                stream,
-                                app.clone(),
-                                connections.clone(),
-                                channel.clone(),
-                            ));
-                        }
-                        warn!("WebSocket 监听器失效，正在尝试重启...");
-                    }
-                    Err(err) => {
-                        error!("WebSocket 服务器 {addr} 开启失败: {err:?}");
-                    }
-                }
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-        }));
+    pub async fn list_endpoints(&self) -> Vec<EndpointInfo> {
+        let endpoints: Vec<_> = self.endpoints.read().unwrap().values().cloned().collect();
+        let mut infos = Vec::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            infos.push(EndpointInfo {
+                config: endpoint.config.clone(),
+                connections: endpoint.connections.read().await.keys().copied().collect(),
+            });
+        }
+        infos
     }
 
     pub async fn get_connections(&self) -> Vec<SocketAddr> {
-        self.connections.read().await.keys().copied().collect()
+        self.get_endpoint_connections(DEFAULT_ENDPOINT_ID).await
+    }
+
+    pub async fn get_endpoint_connections(&self, id: &str) -> Vec<SocketAddr> {
+        let endpoint = self.endpoints.read().unwrap().get(id).cloned();
+        match endpoint {
+            Some(endpoint) => endpoint.connections.read().await.keys().copied().collect(),
+            None => Vec::new(),
+        }
     }
 
     pub async fn broadcast_payload(&mut self, payload: v2::Payload) {
-        let mut conns = self.connections.write().await;
+        self.broadcast_endpoint_payload(DEFAULT_ENDPOINT_ID, payload)
+            .await;
+    }
+
+    /// 向一个端点上的所有客户端广播数据，端点不存在时忽略
+    pub async fn broadcast_endpoint_payload(&mut self, id: &str, payload: v2::Payload) {
+        let Some(endpoint) = self.endpoints.read().unwrap().get(id).cloned() else {
+            return;
+        };
+        let mut conns = endpoint.connections.write().await;
 
         let v2_msg = serde_json::to_string(&payload)
             .ok()
             .map(|s| Message::Text(s.into()));
 
-        let v1_msg = if let Ok(v1_body) = v1::Body::try_from(payload.clone()) {
-            v1::to_body(&v1_body)
-                .ok()
-                .map(|d| Message::Binary(d.into()))
-        } else {
-            None
-        };
+        let v1_body = v1::Body::try_from(payload.clone()).ok();
+        let v1_msg = v1_body
+            .as_ref()
+            .and_then(|body| v1::to_body(body).ok())
+            .map(|d| Message::Binary(d.into()));
+        let v1_json_msg = v1_body
+            .as_ref()
+            .and_then(|body| serde_json::to_string(body).ok())
+            .map(|s| Message::Text(s.into()));
 
         let mut disconnected_addrs = Vec::new();
 
         for (addr, conn_info) in conns.iter_mut() {
             let msg_to_send = match conn_info.protocol {
                 ProtocolType::BinaryV1 => v1_msg.as_ref(),
+                ProtocolType::JsonV1 => v1_json_msg.as_ref(),
                 ProtocolType::HybridV2 => v2_msg.as_ref(),
                 _ => None,
             };
@@ -136,106 +350,204 @@ impl AMLLWebSocketServer {
         }
     }
 
+    fn spawn_listener(&self, addr: String) -> JoinHandle<()> {
+        let app = self.app.clone();
+        let endpoints = self.endpoints.clone();
+
+        tokio::spawn(async move {
+            loop {
+                info!("正在开启 WebSocket 服务器到 {addr}");
+                match TcpListener::bind(&addr).await {
+                    Ok(listener) => {
+                        info!("已开启 WebSocket 服务器到 {addr}");
+                        while let Ok((stream, _)) = listener.accept().await {
+                            tokio::spawn(Self::accept_conn(
+                                stream,
+                                addr.clone(),
+                                app.clone(),
+                                endpoints.clone(),
+                            ));
+                        }
+                        warn!("WebSocket 监听器失效，正在尝试重启...");
+                    }
+                    Err(err) => {
+                        error!("WebSocket 服务器 {addr} 开启失败: {err:?}");
+                    }
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+    }
+
     async fn accept_conn(
         stream: TcpStream,
+        listen_addr: String,
         app: AppHandle,
-        conns: Connections,
-        channel: Channel<v2::Payload>,
+        endpoints: Endpoints,
     ) -> anyhow::Result<()> {
         let addr = stream.peer_addr()?;
         let addr_str = addr.to_string();
         info!("已接受套接字连接: {addr}");
 
-        let wss = accept_async(stream).await?;
-        info!("已连接 WebSocket 客户端: {addr}");
-        app.emit("on-ws-protocol-client-connected", &addr_str)?;
+        let mut routed = None;
+        let wss = accept_hdr_async(stream, |req: &Request, resp: Response| {
+            routed = route(&endpoints, &listen_addr, req.uri().path());
+            if routed.is_some() {
+                Ok(resp)
+            } else {
+                warn!("{addr} 请求的路径 {} 没有对应的端点", req.uri().path());
+                let mut resp = ErrorResponse::new(Some("No such endpoint".to_string()));
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+                Err(resp)
+            }
+        })
+        .await?;
+        let Some(endpoint) = routed else {
+            return Ok(());
+        };
+        let conns = &endpoint.connections;
+        let channel = &endpoint.channel;
+        info!(
+            "已连接 WebSocket 客户端: {addr}，端点: {}",
+            endpoint.config.id
+        );
+        endpoint.emit_client_event(&app, "connected", &addr_str);
 
         let (write_sink, mut read_stream) = wss.split();
 
         let mut temp_sink = Some(write_sink);
+        let mut protocol_type = ProtocolType::Unknown;
+        let identify_deadline = tokio::time::Instant::now() + IDENTIFY_TIMEOUT;
 
-        if let Some(Ok(first_message)) = read_stream.next().await {
-            let protocol_type = match first_message {
-                Message::Text(ref text) => {
-                    if let Ok(v2_message) = serde_json::from_str::<v2::MessageV2>(text) {
-                        if v2_message.payload == v2::Payload::Initialize {
-                            info!("已识别为 HybridV2 协议");
-                            ProtocolType::HybridV2
-                        } else {
-                            warn!("收到了一个非 Initialize 的 V2 消息，断开。");
-                            return Ok(());
-                        }
-                    } else {
-                        warn!("发送了无法识别的文本消息，断开。");
-                        return Ok(());
+        loop {
+            let next = if protocol_type == ProtocolType::Unknown {
+                match tokio::time::timeout_at(identify_deadline, read_stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        warn!(
+                            "{addr} 在 {IDENTIFY_TIMEOUT:?} 内没有发送可识别协议的消息，断开连接"
+                        );
+                        break;
                     }
                 }
-                Message::Binary(_) => {
-                    info!("已识别为 BinaryV1 协议");
-                    if let Err(e) = Self::process_v1_message(first_message, &channel).await {
-                        error!("处理 V1 协议的消息时失败: {e:?}");
-                        return Ok(());
-                    }
-                    ProtocolType::BinaryV1
-                }
-                _ => ProtocolType::Unknown,
+            } else {
+                read_stream.next().await
+            };
+            let Some(Ok(message)) = next else {
+                break;
             };
 
-            if protocol_type != ProtocolType::Unknown
-                && let Some(sink) = temp_sink.take()
-            {
-                conns.write().await.insert(
-                    addr,
-                    ConnectionInfo {
-                        sink,
-                        protocol: protocol_type,
-                    },
-                );
+            if protocol_type == ProtocolType::Unknown {
+                protocol_type = ProtocolType::detect(&message);
+                if protocol_type != ProtocolType::Unknown
+                    && let Some(sink) = temp_sink.take()
+                {
+                    info!("已将 {addr} 识别为 {protocol_type:?} 协议");
+                    conns.write().await.insert(
+                        addr,
+                        ConnectionInfo {
+                            sink,
+                            protocol: protocol_type,
+                        },
+                    );
+                }
             }
-        }
 
-        while let Some(Ok(message)) = read_stream.next().await {
-            let conns_read = conns.read().await;
-            if let Some(conn_info) = conns_read.get(&addr) {
-                let process_result = match conn_info.protocol {
-                    ProtocolType::BinaryV1 => Self::process_v1_message(message, &channel).await,
-                    ProtocolType::HybridV2 => Self::process_v2_message(message, &channel).await,
-                    _ => Ok(()),
-                };
-                if let Err(e) = process_result {
-                    error!("处理消息失败: {e:?}");
-                    break;
+            let parse_result = match protocol_type {
+                ProtocolType::BinaryV1 => Self::parse_v1_message(message),
+                ProtocolType::JsonV1 => Self::parse_v1_json_message(message),
+                // 协议尚未确定时只可能收到心跳消息，按 v2 协议处理即可
+                ProtocolType::HybridV2 | ProtocolType::Unknown => Self::parse_v2_message(message),
+            };
+            match parse_result {
+                Ok(Some(payload)) => {
+                    // 通道失效说明前端已经不再接收这个端点的数据，继续保持连接没有意义
+                    if let Err(err) = channel.send(payload) {
+                        warn!("无法将 {addr} 的消息转发给前端，断开连接: {err:?}");
+                        break;
+                    }
                 }
+                Ok(None) => {}
+                // 无法识别的消息可能来自更新或更旧的客户端，忽略它们而不是断开连接
+                Err(e) => warn!("忽略 {addr} 发送的无法处理的消息: {e:?}"),
             }
         }
 
         info!("已断开 WebSocket 客户端: {addr}");
-        app.emit("on-ws-protocol-client-disconnected", &addr_str)?;
+        endpoint.emit_client_event(&app, "disconnected", &addr_str);
         conns.write().await.remove(&addr);
         Ok(())
     }
 
-    async fn process_v1_message(
-        message: Message,
-        channel: &Channel<v2::Payload>,
-    ) -> anyhow::Result<()> {
-        if let Message::Binary(data) = message {
-            let v1_body = v1::parse_body(&data)?;
-            channel.send(v1_body.into())?;
+    fn parse_v1_message(message: Message) -> anyhow::Result<Option<v2::Payload>> {
+        match message {
+            Message::Binary(data) => Ok(Some(v1::parse_body(&data)?.into())),
+            _ => Ok(None),
         }
-        Ok(())
     }
 
-    async fn process_v2_message(
-        message: Message,
-        channel: &Channel<v2::Payload>,
-    ) -> anyhow::Result<()> {
+    /// 解析 v2 协议的消息，`Initialize` 只用于识别协议，不会转发给前端
+    fn parse_v2_message(message: Message) -> anyhow::Result<Option<v2::Payload>> {
         let payload = match message {
             Message::Text(text) => serde_json::from_str::<v2::MessageV2>(&text)?.payload,
             Message::Binary(data) => v2::parse_binary_v2(&data)?.into(),
-            _ => return Ok(()),
+            _ => return Ok(None),
         };
-        channel.send(payload)?;
-        Ok(())
+        Ok((payload != v2::Payload::Initialize).then_some(payload))
+    }
+
+    fn parse_v1_json_message(message: Message) -> anyhow::Result<Option<v2::Payload>> {
+        match message {
+            Message::Text(text) => Ok(Some(serde_json::from_str::<v1::Body>(&text)?.into())),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initialize_only_identifies_the_protocol() {
+        let initialize = Message::Text(r#"{"type":"initialize"}"#.into());
+        assert_eq!(ProtocolType::detect(&initialize), ProtocolType::HybridV2);
+        assert_eq!(
+            AMLLWebSocketServer::parse_v2_message(initialize).unwrap(),
+            None
+        );
+
+        let ping = Message::Text(r#"{"type":"ping"}"#.into());
+        assert_eq!(ProtocolType::detect(&ping), ProtocolType::Unknown);
+        assert_eq!(
+            AMLLWebSocketServer::parse_v2_message(ping).unwrap(),
+            Some(v2::Payload::Ping)
+        );
+    }
+
+    #[test]
+    fn v1_messages_are_upgraded_to_v2_payloads() {
+        let binary = Message::Binary(v1::to_body(&v1::Body::Ping).unwrap().into());
+        assert_eq!(ProtocolType::detect(&binary), ProtocolType::BinaryV1);
+        assert_eq!(
+            AMLLWebSocketServer::parse_v1_message(binary).unwrap(),
+            Some(v2::Payload::Ping)
+        );
+
+        let json = Message::Text(serde_json::to_string(&v1::Body::Ping).unwrap().into());
+        assert_eq!(
+            AMLLWebSocketServer::parse_v1_json_message(json).unwrap(),
+            Some(v2::Payload::Ping)
+        );
+    }
+
+    #[test]
+    fn malformed_messages_are_errors_and_control_frames_are_ignored() {
+        assert!(AMLLWebSocketServer::parse_v2_message(Message::Text("{".into())).is_err());
+        assert!(AMLLWebSocketServer::parse_v1_json_message(Message::Text("{".into())).is_err());
+        assert_eq!(
+            AMLLWebSocketServer::parse_v1_message(Message::Ping(Vec::new().into())).unwrap(),
+            None
+        );
     }
 }
@@ -0,0 +1,53 @@
+//! 启动各阶段的耗时统计
+//!
+//! 每个阶段完成后都会输出一条日志，前端也可以通过 `get_startup_metrics` 取得全部记录。
+//! 按需初始化的子系统（如 SMTC）会在第一次使用时记录，因此可能晚于首屏出现
+
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tracing::info;
+
+static PROCESS_START: LazyLock<Instant> = LazyLock::new(Instant::now);
+static PHASES: Mutex<Vec<StartupPhase>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupPhase {
+    pub name: String,
+    /// 该阶段本身的耗时
+    pub duration_ms: f64,
+    /// 该阶段完成时距离程序启动经过的时间
+    pub finished_at_ms: f64,
+}
+
+/// 记录程序启动的时间点，应当在 `run` 的最开始调用
+pub fn mark_process_start() {
+    LazyLock::force(&PROCESS_START);
+}
+
+fn record(name: &str, duration: Duration) {
+    let finished_at = PROCESS_START.elapsed();
+    info!("启动阶段 {name} 耗时 {duration:?}，距启动 {finished_at:?}");
+    PHASES.lock().unwrap().push(StartupPhase {
+        name: name.to_string(),
+        duration_ms: duration.as_secs_f64() * 1000.0,
+        finished_at_ms: finished_at.as_secs_f64() * 1000.0,
+    });
+}
+
+/// 执行一个启动阶段并记录它的耗时
+pub fn measure<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(name, start.elapsed());
+    result
+}
+
+#[tauri::command]
+pub fn get_startup_metrics() -> Vec<StartupPhase> {
+    PHASES.lock().unwrap().clone()
+}
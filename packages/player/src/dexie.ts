@@ -29,6 +29,10 @@ export interface Song {
 	addTime: number;
 	accessTime: number;
 	lyricOffset?: number;
+	/**
+	 * 用户最后一次在歌词页面保存歌词的时间，只有编辑过的歌词才会被自动备份
+	 */
+	lyricEditTime?: number;
 }
 
 export interface TTMLDBLyricEntry {
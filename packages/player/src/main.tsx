@@ -4,6 +4,7 @@ import { ErrorBoundary, type FallbackProps } from "react-error-boundary";
 import "react-toastify/dist/ReactToastify.css";
 import App from "./App.tsx";
 import "./i18n";
+import { preloadPersistedState } from "./states/persistedStorage.ts";
 import "./styles.css";
 
 const ErrorRender = (props: FallbackProps) => {
@@ -21,10 +22,13 @@ const ErrorRender = (props: FallbackProps) => {
 	);
 };
 
-createRoot(document.getElementById("root") as HTMLElement).render(
-	<ErrorBoundary fallbackRender={ErrorRender}>
-		<Provider>
-			<App />
-		</Provider>
-	</ErrorBoundary>,
-);
+// 持久化的状态需要在首次读取 atom 之前加载完成
+preloadPersistedState().then(() => {
+	createRoot(document.getElementById("root") as HTMLElement).render(
+		<ErrorBoundary fallbackRender={ErrorRender}>
+			<Provider>
+				<App />
+			</Provider>
+		</ErrorBoundary>,
+	);
+});
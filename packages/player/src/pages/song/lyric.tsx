@@ -17,6 +17,7 @@ import { Trans, useTranslation } from "react-i18next";
 import { toast } from "react-toastify";
 import { TTMLImportDialog } from "../../components/TTMLImportDialog/index.tsx";
 import { db } from "../../dexie.ts";
+import { scheduleLyricBackup } from "../../utils/lyric-backup.ts";
 import { Option } from "./common.tsx";
 import { SongContext } from "./song-ctx.ts";
 
@@ -60,6 +61,7 @@ export const LyricTabContent: FC = () => {
 			db.songs.update(song, (song) => {
 				song.lyric = saveLyricFormat;
 				song.lyricOffset = offset;
+				song.lyricEditTime = Date.now();
 				if (saveLyricFormat === "none") {
 					song.lyricFormat = "none";
 					song.lyric = "";
@@ -90,7 +92,7 @@ export const LyricTabContent: FC = () => {
 				setLyricContent(saveLyricContent);
 				setTranslatedLyricContent(saveTranslatedLyricContent);
 				setRomanLyricContent(saveRomanLyricContent);
-			});
+			}).then(scheduleLyricBackup);
 		},
 		[song],
 	);
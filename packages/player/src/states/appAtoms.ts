@@ -1,5 +1,6 @@
 import { atom } from "jotai";
 import { atomWithStorage } from "jotai/utils";
+import { atomWithPersistedState } from "./persistedStorage";
 
 // ==================================================================
 //                            类型定义
@@ -22,7 +23,7 @@ export enum DarkMode {
  * 应用的显示语言。
  * @default "zh-CN"
  */
-export const displayLanguageAtom = atomWithStorage(
+export const displayLanguageAtom = atomWithPersistedState(
 	"amll-player.displayLanguage",
 	"zh-CN",
 );
@@ -31,7 +32,7 @@ export const displayLanguageAtom = atomWithStorage(
  * 应用的主题（暗黑/明亮）模式设置。
  * @default DarkMode.Auto
  */
-export const darkModeAtom = atomWithStorage(
+export const darkModeAtom = atomWithPersistedState(
 	"amll-player.darkMode",
 	DarkMode.Auto,
 );
@@ -49,7 +50,7 @@ export const lyricDBVersionAtom = atomWithStorage<string | null>(
 /**
  * 是否在应用中显示性能统计（Stat.js）面板。
  */
-export const showStatJSFrameAtom = atomWithStorage(
+export const showStatJSFrameAtom = atomWithPersistedState(
 	"amll-player.showStatJSFrame",
 	false,
 );
@@ -117,24 +118,20 @@ export const hideNowPlayingBarAtom = atom(false);
 /**
  * @description 存储 Song ID 列表
  */
-export const currentMusicQueueAtom = atomWithStorage<string[]>(
+export const currentMusicQueueAtom = atomWithPersistedState<string[]>(
 	"amll-player.queue",
 	[],
-	undefined,
-	{ getOnInit: true },
 );
 
-export const originalQueueAtom = atomWithStorage<string[] | null>(
+export const originalQueueAtom = atomWithPersistedState<string[] | null>(
 	"amll-player.original_queue",
 	null,
-	undefined,
-	{ getOnInit: true },
 );
 
 /**
  * @description 当前播放索引
  */
-export const currentMusicIndexAtom = atomWithStorage<number>(
+export const currentMusicIndexAtom = atomWithPersistedState<number>(
 	"amll-player.index",
 	0,
 );
@@ -172,7 +169,7 @@ export const wsConnectionStatusAtom = atom<WebSocketConnectionStatus>(
 	WebSocketConnectionStatus.Disconnected,
 );
 
-export const wsServerUrlAtom = atomWithStorage<string>(
+export const wsServerUrlAtom = atomWithPersistedState<string>(
 	"amll-player.wsServerUrl",
 	"ws://localhost:11455",
 );
@@ -0,0 +1,88 @@
+/**
+ * 播放器设置与播放队列的持久化
+ *
+ * 在桌面端通过 `save_persisted_state` 等命令交给后端，
+ * 后端以“临时文件 + fsync + 原子重命名”的方式写入，读取失败时回退到备份。
+ * 命令是异步的，因此启动时先由 {@link preloadPersistedState} 把所有状态读入内存，
+ * 之后的读取都是同步的；网页端没有后端，仍然使用 localStorage。
+ */
+
+import { invoke, isTauri } from "@tauri-apps/api/core";
+import { atomWithStorage, createJSONStorage } from "jotai/utils";
+
+const persistedKeys = new Set<string>();
+const cache = new Map<string, string>();
+const pendingWrites = new Map<string, Promise<void>>();
+
+/**
+ * 把对同一个状态的写入排成队列依次执行，
+ * 避免两次写入同时进行时较早的写入在后完成，覆盖掉较新的值
+ */
+function enqueueWrite(key: string, write: () => Promise<unknown>) {
+	const next = (pendingWrites.get(key) ?? Promise.resolve())
+		.then(write)
+		.then(
+			() => {},
+			(err) => console.error(`写入状态 ${key} 失败`, err),
+		);
+	pendingWrites.set(key, next);
+	next.then(() => {
+		if (pendingWrites.get(key) === next) pendingWrites.delete(key);
+	});
+}
+
+const backendStorage = {
+	getItem: (key: string) => cache.get(key) ?? null,
+	setItem: (key: string, value: string) => {
+		cache.set(key, value);
+		enqueueWrite(key, () => invoke("save_persisted_state", { key, value }));
+	},
+	removeItem: (key: string) => {
+		cache.delete(key);
+		enqueueWrite(key, () => invoke("remove_persisted_state", { key }));
+	},
+};
+
+/**
+ * 创建一个持久化的 atom，用法与 `atomWithStorage` 相同
+ * @param key 状态键名，同时用作后端的文件名，只能包含字母、数字和 `.-_`
+ */
+export function atomWithPersistedState<T>(key: string, initialValue: T) {
+	persistedKeys.add(key);
+	return atomWithStorage<T>(
+		key,
+		initialValue,
+		createJSONStorage<T>(() => (isTauri() ? backendStorage : localStorage)),
+		{ getOnInit: true },
+	);
+}
+
+/**
+ * 从后端读取所有持久化状态，需要在渲染之前调用
+ *
+ * 后端还没有某个状态时，会把旧版本保存在 localStorage 中的值迁移过去
+ */
+export async function preloadPersistedState() {
+	if (!isTauri()) return;
+	await Promise.all(
+		[...persistedKeys].map(async (key) => {
+			try {
+				const value = await invoke<string | null>("load_persisted_state", {
+					key,
+				});
+				if (value !== null) {
+					cache.set(key, value);
+					localStorage.removeItem(key);
+					return;
+				}
+				const legacy = localStorage.getItem(key);
+				if (legacy === null) return;
+				cache.set(key, legacy);
+				await invoke("save_persisted_state", { key, value: legacy });
+				localStorage.removeItem(key);
+			} catch (err) {
+				console.error(`读取状态 ${key} 失败`, err);
+			}
+		}),
+	);
+}
@@ -0,0 +1,43 @@
+/**
+ * 用户保存歌词后自动备份到后端
+ */
+
+import { invoke, isTauri } from "@tauri-apps/api/core";
+import { db } from "../dexie.ts";
+
+/**
+ * 保存后等待的时长，连续保存多次时只备份一次
+ */
+const BACKUP_DELAY_MS = 2000;
+
+let backupTimer: ReturnType<typeof setTimeout> | undefined;
+
+/**
+ * 安排一次歌词备份，把所有用户编辑过的歌词交给后端保存为当天的快照
+ *
+ * 从网络获取后缓存下来的歌词没有 `lyricEditTime`，不会被备份
+ */
+export function scheduleLyricBackup() {
+	if (!isTauri()) return;
+	clearTimeout(backupTimer);
+	backupTimer = setTimeout(async () => {
+		try {
+			const songs = await db.songs
+				.filter(
+					(song) => song.lyricEditTime !== undefined && song.lyric !== "",
+				)
+				.toArray();
+			await invoke("backup_user_lyrics", {
+				lyrics: songs.map((song) => ({
+					songId: song.id,
+					lyricFormat: song.lyricFormat,
+					lyric: song.lyric,
+					translatedLrc: song.translatedLrc || undefined,
+					romanLrc: song.romanLrc || undefined,
+				})),
+			});
+		} catch (err) {
+			console.error("备份歌词失败", err);
+		}
+	}, BACKUP_DELAY_MS);
+}
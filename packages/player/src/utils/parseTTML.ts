@@ -31,5 +31,5 @@ export function parseTTML(ttmlContent: string): TTMLLyric {
 		throw new Error("WASM not loaded");
 	}
 
-	return parse_ttml(ttmlContent);
+	return parse_ttml(ttmlContent, undefined);
 }
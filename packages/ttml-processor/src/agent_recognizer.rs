@@ -0,0 +1,1023 @@
+//! 从歌词行开头的“演唱者：”标记识别对唱的演唱者
+//!
+//! 不少对唱歌词以 `周杰伦：歌词` 或单独一行 `阿信:` 的形式标注演唱者。
+//! 这里把这些标记从歌词中移除，并为每个出现过的名字分配一个演唱者 ID（`v1`、`v2`……），
+//! 写入歌词行的 `agent` 和演唱者列表，让 AMLL 能按左右两侧显示对唱。
+//! 有名字的演唱者还会以 `id=名字` 的形式写入元数据的 `agent` 项，导出时一并保留。
+//!
+//! 只包含标记的行会被删除，其后的行归属于该演唱者。`男：…… 女：……` 这样一行中有多个标记时，
+//! 会在后面的标记处拆成多行，逐行歌词按字数比例分配时间。
+//! 可以通过别名把名字映射到固定的 ID，让同一位歌手在不同歌曲中始终显示在同一侧。
+//! `合：`、`All:` 等合唱标记会被归入合唱演唱者 `v1000`。
+//!
+//! 整份歌词都没有标记时，可以选择根据括号、缩进或翻译前缀推断对唱，
+//! 每种线索的置信度会写入解析结果的警告中
+
+use std::collections::HashMap;
+
+use lyrics_helper_core::{
+    Agent, AgentType, ContentType, ConvertError, LyricLine, LyricSyllable, LyricTrack,
+    ParsedSourceData, TtmlParsingOptions,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chorus::{CHORUS_AGENT_ID, ChorusOptions, ensure_chorus_agent, merge_simultaneous},
+    line_split::split_line_with,
+};
+
+/// 移除的标记以 `行下标=标记` 的形式写入 `raw_metadata` 时使用的键
+///
+/// 行下标在其它处理增删行之后就会失效，因此生成歌词时不会输出该项，见 [`output_metadata`]
+pub const AGENT_MARKER_METADATA_KEY: &str = "agentMarker";
+
+/// 控制演唱者识别的选项
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AgentRecognizerOptions {
+    /// 演唱者名字的最大字数，冒号前的内容更长时不视为演唱者标记
+    pub max_name_chars: usize,
+    /// 没有标记的行是否沿用上一个标记的演唱者
+    pub inherit_agent: bool,
+    /// 演唱者名字到固定演唱者 ID 的映射，如 `{"周杰伦": "v1", "合": "v1000"}`
+    ///
+    /// 不在其中的名字按出现顺序分配未被占用的 ID
+    pub aliases: HashMap<String, String>,
+    /// 合唱的识别选项，名字为其中之一的标记（如 `合：`、`All:`）会被归入 `v1000`
+    pub chorus: ChorusOptions,
+    /// 是否把不同演唱者同时演唱的相同歌词合并为一行合唱
+    pub merge_chorus_duplicates: bool,
+    /// 整份歌词都没有标记时，是否根据括号、缩进或翻译前缀推断对唱
+    pub infer_duet: bool,
+    /// 推断对唱时采用某种线索所需的最低置信度（0.0 ~ 1.0）
+    pub min_inference_confidence: f64,
+    /// 自定义的标记正则表达式，按顺序尝试，都不匹配时再按“名字：”识别
+    ///
+    /// 只有从行首开始的匹配有效，整个匹配连同其后的空白会被移除。名字取自名为 `name` 的捕获组，
+    /// 没有该组时取第一个捕获组，都没有时取整个匹配，如 `^【(?<name>[^】]+)】`
+    pub marker_patterns: Vec<String>,
+    /// 是否在行中间的标记处拆分以标记开头的行，如 `男：…… 女：……`
+    pub split_mid_line_markers: bool,
+    /// 是否把移除的标记保存到元数据的 `agentMarker` 项，以便用 [`restore_agent_markers`] 还原
+    ///
+    /// 该项只在处理过程中有效，不会被写入生成的歌词
+    pub keep_markers: bool,
+}
+
+impl Default for AgentRecognizerOptions {
+    fn default() -> Self {
+        Self {
+            max_name_chars: 10,
+            inherit_agent: true,
+            aliases: HashMap::new(),
+            chorus: ChorusOptions::default(),
+            merge_chorus_duplicates: false,
+            infer_duet: false,
+            min_inference_confidence: 0.5,
+            marker_patterns: Vec::new(),
+            split_mid_line_markers: true,
+            keep_markers: false,
+        }
+    }
+}
+
+/// 行首的演唱者标记
+struct Marker {
+    name: String,
+    /// 标记连同冒号和其后空白的字数
+    prefix_chars: usize,
+    /// 标记之后是否还有歌词
+    has_lyric: bool,
+}
+
+fn find_colon_marker(text: &str, max_name_chars: usize) -> Option<Marker> {
+    let (colon, _) = text
+        .char_indices()
+        .take(max_name_chars + 1)
+        .find(|&(_, c)| c == ':' || c == '：')?;
+    let name = text[..colon].trim();
+    // 纯数字多半是时间或编号，不是名字
+    if name.is_empty() || name.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let colon_len = text[colon..].chars().next().map_or(1, char::len_utf8);
+    marker_at(text, name, colon + colon_len)
+}
+
+/// 构造一个占据 `text[..end]` 的标记，其后的空白也计入标记
+fn marker_at(text: &str, name: &str, end: usize) -> Option<Marker> {
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let lyric = text[end..].trim_start();
+    Some(Marker {
+        name: name.to_string(),
+        prefix_chars: text[..text.len() - lyric.len()].chars().count(),
+        has_lyric: !lyric.trim().is_empty(),
+    })
+}
+
+/// 按选项中的自定义正则表达式和“名字：”规则查找行首的标记
+struct MarkerFinder {
+    patterns: Vec<Regex>,
+    max_name_chars: usize,
+}
+
+impl MarkerFinder {
+    fn new(options: &AgentRecognizerOptions) -> Result<Self, ConvertError> {
+        let patterns = options
+            .marker_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| {
+                    ConvertError::Internal(format!("无效的正则表达式 `{pattern}`: {e}"))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            patterns,
+            max_name_chars: options.max_name_chars,
+        })
+    }
+
+    fn find(&self, text: &str) -> Option<Marker> {
+        self.patterns
+            .iter()
+            .find_map(|pattern| {
+                let captures = pattern.captures(text)?;
+                let whole = captures.get(0)?;
+                if whole.start() != 0 || whole.is_empty() {
+                    return None;
+                }
+                let name = captures
+                    .name("name")
+                    .or_else(|| captures.get(1))
+                    .unwrap_or(whole);
+                marker_at(text, name.as_str(), whole.end())
+            })
+            .or_else(|| find_colon_marker(text, self.max_name_chars))
+    }
+}
+
+/// 从轨道开头移除指定字数的文本，音节之后的空格也计入字数
+fn strip_prefix_chars(track: &mut LyricTrack, mut count: usize) {
+    for word in &mut track.words {
+        word.syllables.retain_mut(|syllable| {
+            if count == 0 {
+                return true;
+            }
+            let len = syllable.text.chars().count();
+            if count < len {
+                syllable.text = syllable.text.chars().skip(count).collect();
+                count = 0;
+                return true;
+            }
+            count -= len;
+            if syllable.ends_with_space && count > 0 {
+                count -= 1;
+            }
+            false
+        });
+    }
+    track.words.retain(|word| !word.syllables.is_empty());
+    if let Some(first) = track
+        .words
+        .first_mut()
+        .and_then(|word| word.syllables.first_mut())
+    {
+        first.text = first.text.trim_start().to_string();
+    }
+}
+
+/// 标记中的名字对应的演唱者 ID，合唱标记对应 `v1000`
+fn marker_agent_id(
+    name: &str,
+    data: &mut ParsedSourceData,
+    options: &AgentRecognizerOptions,
+    ids: &mut HashMap<String, String>,
+) -> String {
+    if options.chorus.is_chorus_name(name) {
+        ensure_chorus_agent(data);
+        CHORUS_AGENT_ID.to_string()
+    } else {
+        agent_id_for(name, data, ids)
+    }
+}
+
+fn agent_id_for(
+    name: &str,
+    data: &mut ParsedSourceData,
+    ids: &mut HashMap<String, String>,
+) -> String {
+    let id = ids.get(name).cloned().unwrap_or_else(|| {
+        // 跳过已有的演唱者和映射中用到的 ID
+        let id = (1..=data.agents.agents_by_id.len() + ids.len() + 1)
+            .map(|n| format!("v{n}"))
+            .find(|id| {
+                !data.agents.agents_by_id.contains_key(id) && !ids.values().any(|used| used == id)
+            })
+            .unwrap_or_default();
+        ids.insert(name.to_string(), id.clone());
+        id
+    });
+    data.agents
+        .agents_by_id
+        .entry(id.clone())
+        .or_insert_with(|| {
+            if id == CHORUS_AGENT_ID {
+                Agent {
+                    id: id.clone(),
+                    name: None,
+                    agent_type: AgentType::Group,
+                }
+            } else {
+                Agent {
+                    id: id.clone(),
+                    name: Some(name.to_string()),
+                    agent_type: AgentType::Person,
+                }
+            }
+        });
+    id
+}
+
+/// 找出以标记开头的行中间的其它标记，返回它们开始的音节下标
+///
+/// 行中间的标记只能从音节的边界开始，名字中不能有空白，之后需要有歌词。
+/// 以同一个冒号结尾的候选中只保留最靠后的，避免 `男：一女：二` 被识别为名字 `一女`
+fn mid_line_marker_starts(syllables: &[LyricSyllable], finder: &MarkerFinder) -> Vec<usize> {
+    let text_from = |start: usize| {
+        let mut text = String::new();
+        for syllable in &syllables[start..] {
+            text.push_str(&syllable.text);
+            if syllable.ends_with_space {
+                text.push(' ');
+            }
+        }
+        text
+    };
+    if finder.find(&text_from(0)).is_none() {
+        return Vec::new();
+    }
+    // 每个候选的下标及其标记之后剩余的字数
+    let mut candidates: Vec<(usize, usize)> = Vec::new();
+    for i in 1..syllables.len() {
+        let text = text_from(i);
+        let text = text.trim_start();
+        if let Some(marker) = finder.find(text)
+            && marker.has_lyric
+            && !marker.name.contains(char::is_whitespace)
+        {
+            let rest = text.chars().count() - marker.prefix_chars;
+            candidates.retain(|&(_, other)| other != rest);
+            candidates.push((i, rest));
+        }
+    }
+    candidates.into_iter().map(|(i, _)| i).collect()
+}
+
+/// 识别并移除所有行首的演唱者标记，返回被分配了演唱者的行数
+///
+/// # Errors
+///
+/// 选项中的正则表达式无效时返回 `ConvertError::Internal`
+pub fn recognize_agents(
+    data: &mut ParsedSourceData,
+    options: &AgentRecognizerOptions,
+) -> Result<usize, ConvertError> {
+    let finder = MarkerFinder::new(options)?;
+    let mut ids = options.aliases.clone();
+    let mut current: Option<String> = None;
+    let mut recognized = 0;
+    let mut found_marker = false;
+    let mut source = std::mem::take(&mut data.lines);
+    if options.split_mid_line_markers {
+        source = source
+            .into_iter()
+            .flat_map(|line| {
+                split_line_with(&line, |syllables| {
+                    mid_line_marker_starts(syllables, &finder)
+                })
+                .unwrap_or_else(|| vec![line])
+            })
+            .collect();
+    }
+    let mut lines: Vec<LyricLine> = Vec::with_capacity(source.len());
+    // 每个保留下来的行被移除的标记原文
+    let mut stripped: Vec<Option<String>> = Vec::with_capacity(source.len());
+    for mut line in source {
+        let text = line.main_text().unwrap_or_default();
+        let marker = finder.find(&text);
+        let mut marker_text = None;
+        if let Some(marker) = marker {
+            found_marker = true;
+            current = Some(marker_agent_id(&marker.name, data, options, &mut ids));
+            if !marker.has_lyric {
+                continue;
+            }
+            if let Some(track) = line
+                .tracks
+                .iter_mut()
+                .find(|track| track.content_type == ContentType::Main)
+            {
+                strip_prefix_chars(&mut track.content, marker.prefix_chars);
+                marker_text = Some(text.chars().take(marker.prefix_chars).collect());
+                // 标记单独占有音节时，行从剩下的第一个音节开始
+                if let Some(first) = track.content.syllables().next() {
+                    line.start_ms = line.start_ms.max(first.start_ms);
+                }
+            }
+        } else if !options.inherit_agent {
+            current = None;
+        }
+        if let Some(id) = &current {
+            line.agent = Some(id.clone());
+            recognized += 1;
+        }
+        lines.push(line);
+        stripped.push(marker_text);
+    }
+    if options.merge_chorus_duplicates {
+        let (merged, _) = merge_simultaneous(&mut lines, options.chorus.time_tolerance_ms);
+        let mut index = 0;
+        stripped.retain(|_| {
+            let keep = merged.binary_search(&index).is_err();
+            index += 1;
+            keep
+        });
+    }
+    data.lines = lines;
+    if options.keep_markers {
+        write_marker_metadata(data, &stripped);
+    }
+    if !found_marker && options.infer_duet {
+        recognized = infer_duet(data, options, &finder, &mut ids);
+    }
+    Ok(recognized)
+}
+
+fn write_marker_metadata(data: &mut ParsedSourceData, stripped: &[Option<String>]) {
+    let entries: Vec<String> = stripped
+        .iter()
+        .enumerate()
+        .filter_map(|(index, marker)| Some(format!("{index}={}", marker.as_deref()?)))
+        .collect();
+    if !entries.is_empty() {
+        data.raw_metadata
+            .insert(AGENT_MARKER_METADATA_KEY.to_string(), entries);
+    }
+}
+
+/// 生成歌词时使用的元数据，去掉了行下标可能已经失效的 `agentMarker` 项
+pub(crate) fn output_metadata(data: &ParsedSourceData) -> HashMap<String, Vec<String>> {
+    data.raw_metadata
+        .iter()
+        .filter(|(key, _)| key.as_str() != AGENT_MARKER_METADATA_KEY)
+        .map(|(key, values)| (key.clone(), values.clone()))
+        .collect()
+}
+
+/// 把 `keep_markers` 保存的标记加回各行开头并移除元数据中的 `agentMarker` 项，返回还原的行数
+///
+/// 行下标对应识别之后的歌词，需要在增删行的其它处理之前调用。只包含标记而被删除的行无法还原
+pub fn restore_agent_markers(data: &mut ParsedSourceData) -> usize {
+    let Some(entries) = data.raw_metadata.remove(AGENT_MARKER_METADATA_KEY) else {
+        return 0;
+    };
+    let mut restored = 0;
+    for entry in entries {
+        let Some((index, marker)) = entry.split_once('=') else {
+            continue;
+        };
+        let Some(first) = index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| data.lines.get_mut(index))
+            .and_then(|line| {
+                line.tracks
+                    .iter_mut()
+                    .find(|track| track.content_type == ContentType::Main)
+            })
+            .and_then(|track| track.content.words.first_mut())
+            .and_then(|word| word.syllables.first_mut())
+        else {
+            continue;
+        };
+        first.text.insert_str(0, marker);
+        restored += 1;
+    }
+    restored
+}
+
+/// 演唱者识别对一行歌词的处理结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentAssignment {
+    pub line_index: usize,
+    /// 行首匹配到的标记，如 `周深：`
+    pub marker: Option<String>,
+    /// 标记中的名字
+    pub name: Option<String>,
+    /// 这一行将被分配的演唱者 ID
+    pub agent_id: Option<String>,
+    /// 只包含标记的行会被删除
+    pub removed: bool,
+}
+
+/// 返回每一行将被分配的演唱者，不会修改 `data`
+///
+/// 行的下标对应识别前的歌词，只报告行首的标记，不包含拆分行中间的标记和合并合唱重复行的结果
+///
+/// # Errors
+///
+/// 选项中的正则表达式无效时返回 `ConvertError::Internal`
+pub fn preview_agent_recognition(
+    data: &ParsedSourceData,
+    options: &AgentRecognizerOptions,
+) -> Result<Vec<AgentAssignment>, ConvertError> {
+    let finder = MarkerFinder::new(options)?;
+    let mut scratch = data.clone();
+    let mut ids = options.aliases.clone();
+    let mut current: Option<String> = None;
+    let mut found_marker = false;
+    let mut assignments = Vec::with_capacity(data.lines.len());
+    for (line_index, line) in data.lines.iter().enumerate() {
+        let mut assignment = AgentAssignment {
+            line_index,
+            marker: None,
+            name: None,
+            agent_id: None,
+            removed: false,
+        };
+        let text = line.main_text().unwrap_or_default();
+        if let Some(marker) = finder.find(&text) {
+            found_marker = true;
+            current = Some(marker_agent_id(
+                &marker.name,
+                &mut scratch,
+                options,
+                &mut ids,
+            ));
+            let prefix: String = text.chars().take(marker.prefix_chars).collect();
+            assignment.marker = Some(prefix.trim_end().to_string());
+            assignment.name = Some(marker.name);
+            assignment.removed = !marker.has_lyric;
+        } else if !options.inherit_agent {
+            current = None;
+        }
+        if !assignment.removed {
+            assignment.agent_id.clone_from(&current);
+        }
+        assignments.push(assignment);
+    }
+
+    if !found_marker && options.infer_duet {
+        infer_duet(&mut scratch, options, &finder, &mut ids);
+        for (assignment, line) in assignments.iter_mut().zip(&scratch.lines) {
+            assignment.agent_id.clone_from(&line.agent);
+        }
+    }
+    Ok(assignments)
+}
+
+/// 解析一份 TTML 歌词并预览演唱者识别的结果
+///
+/// # Errors
+///
+/// TTML 解析失败或选项中的正则表达式无效时返回 `ConvertError`
+pub fn preview_agents_ttml(
+    ttml_content: &str,
+    options: &AgentRecognizerOptions,
+) -> Result<Vec<AgentAssignment>, ConvertError> {
+    let data = ttml_processor::parse_ttml(ttml_content, &TtmlParsingOptions::default())?;
+    preview_agent_recognition(&data, options)
+}
+
+/// 推断对唱时使用的线索
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DuetHint {
+    /// 整行被括号括起的行由另一位演唱者演唱
+    Brackets,
+    /// 行首有缩进的行由另一位演唱者演唱
+    Indentation,
+    /// 翻译以“演唱者：”开头
+    TranslationPrefix,
+}
+
+impl DuetHint {
+    const fn description(self) -> &'static str {
+        match self {
+            Self::Brackets => "括号",
+            Self::Indentation => "缩进",
+            Self::TranslationPrefix => "翻译前缀",
+        }
+    }
+}
+
+const BRACKETS: [(char, char); 4] = [('(', ')'), ('（', '）'), ('[', ']'), ('【', '】')];
+
+fn is_bracketed(line: &LyricLine) -> bool {
+    line.main_text().is_some_and(|text| {
+        let text = text.trim();
+        text.chars().count() > 2
+            && BRACKETS
+                .iter()
+                .any(|&(open, close)| text.starts_with(open) && text.ends_with(close))
+    })
+}
+
+fn is_indented(line: &LyricLine) -> bool {
+    line.main_text()
+        .is_some_and(|text| text.starts_with(char::is_whitespace) && !text.trim().is_empty())
+}
+
+fn translation_marker(line: &LyricLine, finder: &MarkerFinder) -> Option<Marker> {
+    let translation = line.main_track()?.translations.first()?;
+    finder
+        .find(&translation.text())
+        .filter(|marker| marker.has_lyric)
+}
+
+/// 被标记的行约占一半时置信度最高，没有或全部被标记时为 0
+#[allow(clippy::cast_precision_loss)]
+fn balance(marked: usize, total: usize) -> f64 {
+    if marked == 0 || marked >= total {
+        return 0.0;
+    }
+    let ratio = marked as f64 / total as f64;
+    (ratio - 0.5).abs().mul_add(-2.0, 1.0)
+}
+
+/// 移除整行外层的括号或行首的缩进
+fn strip_hint(track: &mut LyricTrack, hint: DuetHint) {
+    let brackets = matches!(hint, DuetHint::Brackets);
+    if let Some(first) = track
+        .words
+        .iter_mut()
+        .flat_map(|word| &mut word.syllables)
+        .next()
+    {
+        first.text = first.text.trim_start().to_string();
+        if brackets {
+            first.text.remove(0);
+        }
+    }
+    if brackets
+        && let Some(last) = track
+            .words
+            .iter_mut()
+            .flat_map(|word| &mut word.syllables)
+            .next_back()
+    {
+        last.text = last.text.trim_end().to_string();
+        last.text.pop();
+    }
+    for word in &mut track.words {
+        word.syllables.retain(|syllable| !syllable.text.is_empty());
+    }
+    track.words.retain(|word| !word.syllables.is_empty());
+}
+
+fn ensure_agent(data: &mut ParsedSourceData, id: &str) {
+    data.agents
+        .agents_by_id
+        .entry(id.to_string())
+        .or_insert_with(|| Agent {
+            id: id.to_string(),
+            name: None,
+            agent_type: AgentType::Person,
+        });
+}
+
+/// 把每种线索的置信度写入 `warnings`
+fn report_candidates(
+    data: &mut ParsedSourceData,
+    candidates: &[(DuetHint, f64)],
+    best: Option<DuetHint>,
+) {
+    for &(hint, confidence) in candidates {
+        if confidence > 0.0 {
+            let adopted = if best == Some(hint) {
+                "，已采用"
+            } else {
+                ""
+            };
+            data.warnings.push(format!(
+                "对唱推断：依据{}，置信度 {confidence:.2}{adopted}",
+                hint.description()
+            ));
+        }
+    }
+}
+
+/// 在没有任何标记时根据括号、缩进或翻译前缀推断对唱，返回被分配了演唱者的行数
+///
+/// 每种线索的置信度都会写入 `warnings`，只有置信度最高且不低于阈值的线索会被采用
+#[allow(clippy::cast_precision_loss)]
+fn infer_duet(
+    data: &mut ParsedSourceData,
+    options: &AgentRecognizerOptions,
+    finder: &MarkerFinder,
+    ids: &mut HashMap<String, String>,
+) -> usize {
+    let total = data
+        .lines
+        .iter()
+        .filter(|line| line.main_text().is_some_and(|text| !text.trim().is_empty()))
+        .count();
+    let markers: Vec<Option<Marker>> = data
+        .lines
+        .iter()
+        .map(|line| translation_marker(line, finder))
+        .collect();
+    let prefixed = markers.iter().flatten().count();
+    let mut names: Vec<&str> = markers.iter().flatten().map(|m| m.name.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+    let prefix_confidence = if names.len() >= 2 && total > 0 {
+        prefixed as f64 / total as f64
+    } else {
+        0.0
+    };
+
+    let candidates = [
+        (
+            DuetHint::Brackets,
+            balance(data.lines.iter().filter(|l| is_bracketed(l)).count(), total),
+        ),
+        (
+            DuetHint::Indentation,
+            balance(data.lines.iter().filter(|l| is_indented(l)).count(), total),
+        ),
+        (DuetHint::TranslationPrefix, prefix_confidence),
+    ];
+    let best = candidates
+        .iter()
+        .filter(|(_, confidence)| *confidence >= options.min_inference_confidence)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|&(hint, _)| hint);
+    report_candidates(data, &candidates, best);
+
+    let Some(hint) = best else {
+        return 0;
+    };
+    let mut assigned = 0;
+    let mut current: Option<String> = None;
+    for (index, marker) in markers.into_iter().enumerate() {
+        let line = &data.lines[index];
+        let id = match hint {
+            DuetHint::Brackets | DuetHint::Indentation => {
+                let marked = if matches!(hint, DuetHint::Brackets) {
+                    is_bracketed(line)
+                } else {
+                    is_indented(line)
+                };
+                if line.main_text().is_none_or(|text| text.trim().is_empty()) {
+                    None
+                } else if marked {
+                    ensure_agent(data, "v2");
+                    if let Some(track) = data.lines[index]
+                        .tracks
+                        .iter_mut()
+                        .find(|track| track.content_type == ContentType::Main)
+                    {
+                        strip_hint(&mut track.content, hint);
+                    }
+                    Some("v2".to_string())
+                } else {
+                    ensure_agent(data, "v1");
+                    Some("v1".to_string())
+                }
+            }
+            DuetHint::TranslationPrefix => {
+                if let Some(marker) = marker {
+                    current = Some(marker_agent_id(&marker.name, data, options, ids));
+                    if let Some(translation) = data.lines[index]
+                        .tracks
+                        .iter_mut()
+                        .find(|track| track.content_type == ContentType::Main)
+                        .and_then(|track| track.translations.first_mut())
+                    {
+                        strip_prefix_chars(translation, marker.prefix_chars);
+                    }
+                } else if !options.inherit_agent {
+                    current = None;
+                }
+                current.clone()
+            }
+        };
+        if let Some(id) = id {
+            data.lines[index].agent = Some(id);
+            assigned += 1;
+        }
+    }
+    assigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:01.300">周杰伦：</span><span begin="00:01.300" end="00:02.000">你好</span></p><p begin="00:02.000" end="00:03.000">还是我</p><p begin="00:03.000" end="00:03.500">阿信:</p><p begin="00:04.000" end="00:05.000">Hello there</p><p begin="00:05.000" end="00:06.000">周杰伦： 再见</p><p begin="00:06.000" end="00:07.000">12:30 不是名字</p></div></body></tt>"#;
+
+    #[test]
+    fn test_recognize_agents() {
+        let mut data = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        data.agents.agents_by_id.clear();
+        for line in &mut data.lines {
+            line.agent = None;
+        }
+        assert_eq!(
+            recognize_agents(&mut data, &AgentRecognizerOptions::default()).unwrap(),
+            5
+        );
+
+        let lines: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| (line.main_text().unwrap_or_default(), line.agent.as_deref()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("你好".to_string(), Some("v1")),
+                ("还是我".to_string(), Some("v1")),
+                ("Hello there".to_string(), Some("v2")),
+                ("再见".to_string(), Some("v1")),
+                ("12:30 不是名字".to_string(), Some("v1")),
+            ]
+        );
+        assert_eq!(
+            data.agents.agents_by_id["v1"].name.as_deref(),
+            Some("周杰伦")
+        );
+        assert_eq!(data.agents.agents_by_id["v2"].name.as_deref(), Some("阿信"));
+        assert!(!data.raw_metadata.contains_key("agent"));
+    }
+
+    #[test]
+    fn test_preview_agent_recognition() {
+        let data = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let assignments =
+            preview_agent_recognition(&data, &AgentRecognizerOptions::default()).unwrap();
+        assert_eq!(
+            data,
+            ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap()
+        );
+        assert_eq!(assignments.len(), 6);
+        assert_eq!(
+            assignments[2],
+            AgentAssignment {
+                line_index: 2,
+                marker: Some("阿信:".to_string()),
+                name: Some("阿信".to_string()),
+                agent_id: None,
+                removed: true,
+            }
+        );
+        let agents: Vec<_> = assignments
+            .iter()
+            .map(|assignment| assignment.agent_id.as_deref())
+            .collect();
+        assert_eq!(
+            agents,
+            vec![
+                Some("v1"),
+                Some("v1"),
+                None,
+                Some("v2"),
+                Some("v1"),
+                Some("v1")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut data = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        data.agents.agents_by_id.clear();
+        let options = AgentRecognizerOptions {
+            aliases: HashMap::from([("阿信".to_string(), "v1".to_string())]),
+            ..Default::default()
+        };
+        recognize_agents(&mut data, &options).unwrap();
+
+        let agents: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| line.agent.as_deref().unwrap_or_default())
+            .collect();
+        assert_eq!(agents, vec!["v2", "v2", "v1", "v2", "v2"]);
+        assert_eq!(data.agents.agents_by_id["v1"].name.as_deref(), Some("阿信"));
+        assert_eq!(
+            data.agents.agents_by_id["v2"].name.as_deref(),
+            Some("周杰伦")
+        );
+    }
+
+    const CHORUS_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000">A：一起唱</p><p begin="00:01.000" end="00:02.500">all: 一起唱</p><p begin="00:03.000" end="00:04.000">还是合唱</p><p begin="00:05.000" end="00:06.000">B: 独唱</p><p begin="00:07.000" end="00:08.000">Chorus: 再一起唱</p></div></body></tt>"#;
+
+    #[test]
+    fn test_chorus_keywords() {
+        let mut data =
+            ttml_processor::parse_ttml(CHORUS_TTML, &TtmlParsingOptions::default()).unwrap();
+        data.agents.agents_by_id.clear();
+        let options = AgentRecognizerOptions {
+            merge_chorus_duplicates: true,
+            ..Default::default()
+        };
+        recognize_agents(&mut data, &options).unwrap();
+
+        let lines: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.main_text().unwrap_or_default(),
+                    line.agent.clone().unwrap_or_default(),
+                    line.start_ms,
+                    line.end_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("一起唱".to_string(), "v1000".to_string(), 1000, 2500),
+                ("还是合唱".to_string(), "v1000".to_string(), 3000, 4000),
+                ("独唱".to_string(), "v2".to_string(), 5000, 6000),
+                ("再一起唱".to_string(), "v1000".to_string(), 7000, 8000),
+            ]
+        );
+        assert_eq!(
+            data.agents.agents_by_id[CHORUS_AGENT_ID].agent_type,
+            AgentType::Group
+        );
+    }
+
+    const DUET_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000">第一句</p><p begin="00:02.000" end="00:03.000"><span begin="00:02.000" end="00:02.500">（第二</span><span begin="00:02.500" end="00:03.000">句）</span></p><p begin="00:03.000" end="00:04.000">第三句</p><p begin="00:04.000" end="00:05.000">(第四句)</p></div></body></tt>"#;
+
+    fn parse_duet() -> ParsedSourceData {
+        let mut data =
+            ttml_processor::parse_ttml(DUET_TTML, &TtmlParsingOptions::default()).unwrap();
+        data.agents.agents_by_id.clear();
+        for line in &mut data.lines {
+            line.agent = None;
+        }
+        data.warnings.clear();
+        data
+    }
+
+    #[test]
+    fn test_infer_duet_from_brackets() {
+        let mut data = parse_duet();
+        let options = AgentRecognizerOptions {
+            infer_duet: true,
+            ..Default::default()
+        };
+        assert_eq!(recognize_agents(&mut data, &options).unwrap(), 4);
+
+        let lines: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| (line.main_text().unwrap_or_default(), line.agent.as_deref()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("第一句".to_string(), Some("v1")),
+                ("第二句".to_string(), Some("v2")),
+                ("第三句".to_string(), Some("v1")),
+                ("第四句".to_string(), Some("v2")),
+            ]
+        );
+        assert_eq!(
+            data.warnings,
+            vec!["对唱推断：依据括号，置信度 1.00，已采用"]
+        );
+    }
+
+    #[test]
+    fn test_infer_duet_below_threshold() {
+        let mut data = parse_duet();
+        let options = AgentRecognizerOptions {
+            infer_duet: true,
+            min_inference_confidence: 1.5,
+            ..Default::default()
+        };
+        assert_eq!(recognize_agents(&mut data, &options).unwrap(), 0);
+        assert!(data.lines.iter().all(|line| line.agent.is_none()));
+        assert_eq!(data.warnings, vec!["对唱推断：依据括号，置信度 1.00"]);
+
+        // 默认不推断
+        let mut data = parse_duet();
+        recognize_agents(&mut data, &AgentRecognizerOptions::default()).unwrap();
+        assert!(data.warnings.is_empty());
+    }
+
+    const PATTERN_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000">【男】第一句</p><p begin="00:02.000" end="00:03.000">(女) 第二句</p><p begin="00:03.000" end="00:04.000">男：第三句</p><p begin="00:04.000" end="00:05.000">(笑) 不是标记【男】</p></div></body></tt>"#;
+
+    #[test]
+    fn test_marker_patterns() {
+        let mut data =
+            ttml_processor::parse_ttml(PATTERN_TTML, &TtmlParsingOptions::default()).unwrap();
+        data.agents.agents_by_id.clear();
+        let options = AgentRecognizerOptions {
+            marker_patterns: vec![
+                "【(?<name>[^】]+)】".to_string(),
+                r"^\((男|女)\)".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(recognize_agents(&mut data, &options).unwrap(), 4);
+
+        let lines: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| (line.main_text().unwrap_or_default(), line.agent.as_deref()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("第一句".to_string(), Some("v1")),
+                ("第二句".to_string(), Some("v2")),
+                ("第三句".to_string(), Some("v1")),
+                ("(笑) 不是标记【男】".to_string(), Some("v1")),
+            ]
+        );
+
+        let invalid = AgentRecognizerOptions {
+            marker_patterns: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(recognize_agents(&mut data, &invalid).is_err());
+    }
+
+    const MID_LINE_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:03.000">男：你好 女：再见</p><p begin="00:03.000" end="00:05.000"><span begin="00:03.000" end="00:03.500">男：</span><span begin="00:03.500" end="00:04.000">一</span><span begin="00:04.000" end="00:04.500">女：</span><span begin="00:04.500" end="00:05.000">二</span></p></div></body></tt>"#;
+
+    #[test]
+    fn test_split_mid_line_markers() {
+        let mut data =
+            ttml_processor::parse_ttml(MID_LINE_TTML, &TtmlParsingOptions::default()).unwrap();
+        data.agents.agents_by_id.clear();
+        recognize_agents(&mut data, &AgentRecognizerOptions::default()).unwrap();
+
+        let lines: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.main_text().unwrap_or_default(),
+                    line.agent.clone().unwrap_or_default(),
+                    line.start_ms,
+                    line.end_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("你好".to_string(), "v1".to_string(), 1000, 2111),
+                ("再见".to_string(), "v2".to_string(), 2111, 3000),
+                ("一".to_string(), "v1".to_string(), 3500, 4000),
+                ("二".to_string(), "v2".to_string(), 4500, 5000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_and_restore_markers() {
+        let mut data = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let options = AgentRecognizerOptions {
+            keep_markers: true,
+            ..Default::default()
+        };
+        recognize_agents(&mut data, &options).unwrap();
+        assert_eq!(
+            data.raw_metadata[AGENT_MARKER_METADATA_KEY],
+            vec!["0=周杰伦：", "3=周杰伦： "]
+        );
+
+        assert_eq!(restore_agent_markers(&mut data), 2);
+        assert!(!data.raw_metadata.contains_key(AGENT_MARKER_METADATA_KEY));
+        let texts: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| line.main_text().unwrap_or_default())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "周杰伦：你好",
+                "还是我",
+                "Hello there",
+                "周杰伦： 再见",
+                "12:30 不是名字"
+            ]
+        );
+    }
+}
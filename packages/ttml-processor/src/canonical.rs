@@ -0,0 +1,421 @@
+//! 版本化的规范 JSON 格式，供不链接本 crate 的外部工具读写解析后的歌词
+//!
+//! `lyrics_helper_core` 的 `ParsedSourceData` 虽然可以直接序列化，但字段名随上游变化。
+//! 这里定义一套独立的结构，字段名使用 camelCase 且在同一个 `version` 内保持不变，
+//! 时间单位均为毫秒。版本 1 的结构如下：
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "metadata": { "<原始键>": ["<值>", ...] },
+//!   "agents": [{ "id": "v1", "name"?: "...", "type": "person" | "group" | "other" }],
+//!   "lines": [{
+//!     "startMs": 0, "endMs": 0,
+//!     "agent"?: "v1", "songPart"?: "...", "itunesKey"?: "L1",
+//!     "tracks": [{
+//!       "kind": "main" | "background",
+//!       "content": <track>,
+//!       "translations": [<track>],
+//!       "romanizations": [<track>]
+//!     }]
+//!   }]
+//! }
+//!
+//! <track> = {
+//!   "language"?: "zh-Hans", "scheme"?: "hepburn", "custom"?: { "<键>": "<值>" },
+//!   "words": [{
+//!     "syllables": [{ "text": "...", "startMs": 0, "endMs": 0, "endsWithSpace"?: true }],
+//!     "furigana"?: [{ "text": "...", "startMs"?: 0, "endMs"?: 0 }]
+//!   }]
+//! }
+//! ```
+//!
+//! 带 `?` 的字段为空时不会写出，读取时可以省略。
+//! 新增可选字段不会提升版本号，删除或修改已有字段的含义时才会提升
+
+use lyrics_helper_core::{
+    Agent, AgentStore, AgentType, AnnotatedTrack, ContentType, ConvertError, FuriganaSyllable,
+    LyricLine, LyricSyllable, LyricTrack, ParsedSourceData, TrackMetadataKey, Word,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::agent_recognizer::output_metadata;
+
+/// 当前写出的格式版本
+pub const CANONICAL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalLyrics {
+    pub version: u32,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub agents: Vec<CanonicalAgent>,
+    #[serde(default)]
+    pub lines: Vec<CanonicalLine>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CanonicalAgentType {
+    Person,
+    Group,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalAgent {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub agent_type: CanonicalAgentType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalLine {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub song_part: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub itunes_key: Option<String>,
+    #[serde(default)]
+    pub tracks: Vec<CanonicalAnnotatedTrack>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CanonicalTrackKind {
+    Main,
+    Background,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalAnnotatedTrack {
+    pub kind: CanonicalTrackKind,
+    pub content: CanonicalTrack,
+    #[serde(default)]
+    pub translations: Vec<CanonicalTrack>,
+    #[serde(default)]
+    pub romanizations: Vec<CanonicalTrack>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalTrack {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,
+    #[serde(default)]
+    pub words: Vec<CanonicalWord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalWord {
+    pub syllables: Vec<CanonicalSyllable>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub furigana: Option<Vec<CanonicalFurigana>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalSyllable {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ends_with_space: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalFurigana {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<u64>,
+}
+
+fn track_to_canonical(track: &LyricTrack) -> CanonicalTrack {
+    let mut canonical = CanonicalTrack::default();
+    for (key, value) in &track.metadata {
+        match key {
+            TrackMetadataKey::Language => canonical.language = Some(value.clone()),
+            TrackMetadataKey::Scheme => canonical.scheme = Some(value.clone()),
+            TrackMetadataKey::Custom(key) => {
+                canonical.custom.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    canonical.words = track
+        .words
+        .iter()
+        .map(|word| CanonicalWord {
+            syllables: word
+                .syllables
+                .iter()
+                .map(|syl| CanonicalSyllable {
+                    text: syl.text.clone(),
+                    start_ms: syl.start_ms,
+                    end_ms: syl.end_ms,
+                    ends_with_space: syl.ends_with_space,
+                })
+                .collect(),
+            furigana: word.furigana.as_ref().map(|furigana| {
+                furigana
+                    .iter()
+                    .map(|f| CanonicalFurigana {
+                        text: f.text.clone(),
+                        start_ms: f.timing.map(|(start, _)| start),
+                        end_ms: f.timing.map(|(_, end)| end),
+                    })
+                    .collect()
+            }),
+        })
+        .collect();
+    canonical
+}
+
+fn track_from_canonical(track: CanonicalTrack) -> LyricTrack {
+    let mut metadata = HashMap::new();
+    if let Some(language) = track.language {
+        metadata.insert(TrackMetadataKey::Language, language);
+    }
+    if let Some(scheme) = track.scheme {
+        metadata.insert(TrackMetadataKey::Scheme, scheme);
+    }
+    for (key, value) in track.custom {
+        metadata.insert(TrackMetadataKey::Custom(key), value);
+    }
+    LyricTrack {
+        words: track
+            .words
+            .into_iter()
+            .map(|word| Word {
+                syllables: word
+                    .syllables
+                    .into_iter()
+                    .map(|syl| LyricSyllable {
+                        text: syl.text,
+                        start_ms: syl.start_ms,
+                        end_ms: syl.end_ms,
+                        // 未计时的音节（如行级翻译）开始和结束时间都为 0，没有时长
+                        duration_ms: (syl.end_ms > syl.start_ms).then(|| syl.end_ms - syl.start_ms),
+                        ends_with_space: syl.ends_with_space,
+                    })
+                    .collect(),
+                furigana: word.furigana.map(|furigana| {
+                    furigana
+                        .into_iter()
+                        .map(|f| FuriganaSyllable {
+                            text: f.text,
+                            timing: f.start_ms.zip(f.end_ms),
+                        })
+                        .collect()
+                }),
+            })
+            .collect(),
+        metadata,
+    }
+}
+
+/// 将解析得到的歌词转换为规范结构
+#[must_use]
+pub fn to_canonical(data: &ParsedSourceData) -> CanonicalLyrics {
+    let mut agents: Vec<_> = data
+        .agents
+        .agents_by_id
+        .values()
+        .map(|agent| CanonicalAgent {
+            id: agent.id.clone(),
+            name: agent.name.clone(),
+            agent_type: match agent.agent_type {
+                AgentType::Person => CanonicalAgentType::Person,
+                AgentType::Group => CanonicalAgentType::Group,
+                AgentType::Other => CanonicalAgentType::Other,
+            },
+        })
+        .collect();
+    agents.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let lines = data
+        .lines
+        .iter()
+        .map(|line| CanonicalLine {
+            start_ms: line.start_ms,
+            end_ms: line.end_ms,
+            agent: line.agent.clone(),
+            song_part: line.song_part.clone(),
+            itunes_key: line.itunes_key.clone(),
+            tracks: line
+                .tracks
+                .iter()
+                .map(|track| CanonicalAnnotatedTrack {
+                    kind: match track.content_type {
+                        ContentType::Main => CanonicalTrackKind::Main,
+                        ContentType::Background => CanonicalTrackKind::Background,
+                    },
+                    content: track_to_canonical(&track.content),
+                    translations: track.translations.iter().map(track_to_canonical).collect(),
+                    romanizations: track.romanizations.iter().map(track_to_canonical).collect(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    CanonicalLyrics {
+        version: CANONICAL_VERSION,
+        metadata: output_metadata(data).into_iter().collect(),
+        agents,
+        lines,
+    }
+}
+
+/// 将规范结构还原为 `ParsedSourceData`，来源格式会标记为 TTML
+///
+/// # Errors
+///
+/// 版本号高于当前支持的版本时返回 `ConvertError::InvalidJsonStructure`
+pub fn from_canonical(canonical: CanonicalLyrics) -> Result<ParsedSourceData, ConvertError> {
+    if canonical.version == 0 || canonical.version > CANONICAL_VERSION {
+        return Err(ConvertError::InvalidJsonStructure(format!(
+            "不支持的规范 JSON 版本: {}，当前支持的最高版本为 {CANONICAL_VERSION}",
+            canonical.version
+        )));
+    }
+
+    let mut agents = AgentStore::new();
+    for agent in canonical.agents {
+        agents.agents_by_id.insert(
+            agent.id.clone(),
+            Agent {
+                id: agent.id,
+                name: agent.name,
+                agent_type: match agent.agent_type {
+                    CanonicalAgentType::Person => AgentType::Person,
+                    CanonicalAgentType::Group => AgentType::Group,
+                    CanonicalAgentType::Other => AgentType::Other,
+                },
+            },
+        );
+    }
+
+    let lines = canonical
+        .lines
+        .into_iter()
+        .map(|line| LyricLine {
+            tracks: line
+                .tracks
+                .into_iter()
+                .map(|track| AnnotatedTrack {
+                    content_type: match track.kind {
+                        CanonicalTrackKind::Main => ContentType::Main,
+                        CanonicalTrackKind::Background => ContentType::Background,
+                    },
+                    content: track_from_canonical(track.content),
+                    translations: track
+                        .translations
+                        .into_iter()
+                        .map(track_from_canonical)
+                        .collect(),
+                    romanizations: track
+                        .romanizations
+                        .into_iter()
+                        .map(track_from_canonical)
+                        .collect(),
+                })
+                .collect(),
+            start_ms: line.start_ms,
+            end_ms: line.end_ms,
+            agent: line.agent,
+            song_part: line.song_part,
+            itunes_key: line.itunes_key,
+        })
+        .collect();
+
+    Ok(ParsedSourceData {
+        lines,
+        raw_metadata: canonical.metadata.into_iter().collect(),
+        agents,
+        ..Default::default()
+    })
+}
+
+/// 将解析得到的歌词序列化为规范 JSON
+#[must_use]
+pub fn to_canonical_json(data: &ParsedSourceData) -> String {
+    // 规范结构中只有字符串键的映射，序列化不会失败
+    serde_json::to_string(&to_canonical(data)).unwrap_or_default()
+}
+
+/// 从规范 JSON 读取歌词
+///
+/// # Errors
+///
+/// JSON 无效或版本不受支持时返回 `ConvertError`
+pub fn from_canonical_json(json: &str) -> Result<ParsedSourceData, ConvertError> {
+    let canonical: CanonicalLyrics =
+        serde_json::from_str(json).map_err(|source| ConvertError::JsonParse {
+            source,
+            context: "规范 JSON".to_string(),
+        })?;
+    from_canonical(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:amll="http://www.example.com/ns/amll"><head><metadata><ttm:agent type="person" xml:id="v1"/><ttm:agent type="group" xml:id="v1000"/><amll:meta key="musicName" value="Song"/></metadata></head><body><div><p begin="00:01.000" end="00:03.000" ttm:agent="v1" itunes:key="L1" xmlns:itunes="http://music.apple.com/lyric-ttml-internal"><span begin="00:01.000" end="00:01.500">Hello</span> <span begin="00:01.500" end="00:02.000">world</span><span ttm:role="x-translation" xml:lang="zh-Hans">你好世界</span><span ttm:role="x-bg"><span begin="00:02.000" end="00:03.000">(echo)</span></span></p><p begin="00:03.000" end="00:04.000" ttm:agent="v1000"><span begin="00:03.000" end="00:04.000">all</span></p></div></body></tt>"#;
+
+    #[test]
+    fn test_canonical_roundtrip() {
+        let parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let json = to_canonical_json(&parsed);
+        let imported = from_canonical_json(&json).unwrap();
+
+        assert_eq!(imported.lines, parsed.lines);
+        assert_eq!(imported.raw_metadata, parsed.raw_metadata);
+        assert_eq!(imported.agents, parsed.agents);
+        assert_eq!(to_canonical_json(&imported), json);
+    }
+
+    #[test]
+    fn test_canonical_schema() {
+        let parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&to_canonical_json(&parsed)).unwrap();
+
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["metadata"]["musicName"][0], "Song");
+        assert_eq!(value["agents"][1]["type"], "group");
+        let line = &value["lines"][0];
+        assert_eq!(line["startMs"], 1000);
+        assert_eq!(line["tracks"][0]["kind"], "main");
+        let syllable = &line["tracks"][0]["content"]["words"][0]["syllables"][0];
+        assert_eq!(syllable["text"], "Hello");
+        assert_eq!(syllable["endsWithSpace"], true);
+        assert_eq!(line["tracks"][0]["translations"][0]["language"], "zh-Hans");
+        assert_eq!(line["tracks"][1]["kind"], "background");
+    }
+
+    #[test]
+    fn test_canonical_version() {
+        assert!(from_canonical_json(r#"{"version":2}"#).is_err());
+        let empty = from_canonical_json(r#"{"version":1}"#).unwrap();
+        assert!(empty.lines.is_empty());
+    }
+}
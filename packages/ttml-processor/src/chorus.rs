@@ -0,0 +1,187 @@
+//! 把合唱的歌词行归入合唱演唱者 `v1000`
+//!
+//! AMLL 会把演唱者为 `v1000` 的行居中显示，表示所有人一起演唱。对唱歌词中的合唱部分
+//! 常被标为名为“合”“All”的演唱者，或者被写成两位演唱者同时演唱的两行相同歌词。
+//! 这里把前者的演唱者改为 `v1000`，把后者合并为一行 `v1000` 的歌词
+
+use lyrics_helper_core::{Agent, AgentType, LyricLine, ParsedSourceData};
+use serde::{Deserialize, Serialize};
+
+/// 合唱演唱者的 ID
+pub const CHORUS_AGENT_ID: &str = "v1000";
+
+/// 控制合唱识别的选项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChorusOptions {
+    /// 视为合唱的演唱者名字，不区分大小写
+    pub names: Vec<String>,
+    /// 两行开始时间相差不超过该值（毫秒）时视为同时演唱
+    pub time_tolerance_ms: u64,
+}
+
+impl Default for ChorusOptions {
+    fn default() -> Self {
+        Self {
+            names: ["合", "合唱", "All", "Both", "Chorus"]
+                .map(ToString::to_string)
+                .to_vec(),
+            time_tolerance_ms: 100,
+        }
+    }
+}
+
+impl ChorusOptions {
+    /// 名字是否表示合唱
+    #[must_use]
+    pub fn is_chorus_name(&self, name: &str) -> bool {
+        let name = name.trim();
+        self.names
+            .iter()
+            .any(|chorus| chorus.eq_ignore_ascii_case(name))
+    }
+}
+
+/// 确保演唱者列表中有合唱演唱者
+pub(crate) fn ensure_chorus_agent(data: &mut ParsedSourceData) {
+    data.agents
+        .agents_by_id
+        .entry(CHORUS_AGENT_ID.to_string())
+        .or_insert_with(|| Agent {
+            id: CHORUS_AGENT_ID.to_string(),
+            name: None,
+            agent_type: AgentType::Group,
+        });
+}
+
+/// 找出不同演唱者同时演唱的相同歌词，返回每一行要被合并到的行的下标
+fn find_simultaneous(lines: &[LyricLine], tolerance_ms: u64) -> Vec<Option<usize>> {
+    let mut merged_into = vec![None; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        if merged_into[i].is_some() || line.agent.is_none() {
+            continue;
+        }
+        let Some(text) = line.main_text() else {
+            continue;
+        };
+        for (j, other) in lines.iter().enumerate().skip(i + 1) {
+            if merged_into[j].is_none()
+                && other.agent.is_some()
+                && other.agent != line.agent
+                && other.start_ms.abs_diff(line.start_ms) <= tolerance_ms
+                && other
+                    .main_text()
+                    .is_some_and(|other| other.trim() == text.trim())
+            {
+                merged_into[j] = Some(i);
+            }
+        }
+    }
+    merged_into
+}
+
+/// 把不同演唱者同时演唱的相同歌词合并为一行 `v1000` 的歌词
+///
+/// 返回被合并掉的行在合并前的下标（升序），以及演唱者被改为 `v1000` 的行数
+pub(crate) fn merge_simultaneous(
+    lines: &mut Vec<LyricLine>,
+    tolerance_ms: u64,
+) -> (Vec<usize>, usize) {
+    let merged_into = find_simultaneous(lines, tolerance_ms);
+    let mut reassigned = 0;
+    for (j, target) in merged_into.iter().enumerate() {
+        let Some(i) = *target else {
+            continue;
+        };
+        let (start_ms, end_ms) = (lines[j].start_ms, lines[j].end_ms);
+        let line = &mut lines[i];
+        line.start_ms = line.start_ms.min(start_ms);
+        line.end_ms = line.end_ms.max(end_ms);
+        if line.agent.as_deref() != Some(CHORUS_AGENT_ID) {
+            line.agent = Some(CHORUS_AGENT_ID.to_string());
+            reassigned += 1;
+        }
+    }
+    let mut index = 0;
+    lines.retain(|_| {
+        let keep = merged_into[index].is_none();
+        index += 1;
+        keep
+    });
+    let merged = (0..merged_into.len())
+        .filter(|&j| merged_into[j].is_some())
+        .collect();
+    (merged, reassigned)
+}
+
+/// 把合唱的行归入 `v1000`，返回被修改的行数与被合并掉的行数之和
+pub fn normalize_chorus(data: &mut ParsedSourceData, options: &ChorusOptions) -> usize {
+    let chorus_ids: Vec<String> = data
+        .agents
+        .all_agents()
+        .filter(|agent| agent.id != CHORUS_AGENT_ID)
+        .filter(|agent| {
+            agent
+                .name
+                .as_deref()
+                .is_some_and(|name| options.is_chorus_name(name))
+        })
+        .map(|agent| agent.id.clone())
+        .collect();
+
+    let mut changed = 0;
+    for line in &mut data.lines {
+        if line
+            .agent
+            .as_ref()
+            .is_some_and(|agent| chorus_ids.contains(agent))
+        {
+            line.agent = Some(CHORUS_AGENT_ID.to_string());
+            changed += 1;
+        }
+    }
+
+    let (merged, reassigned) = merge_simultaneous(&mut data.lines, options.time_tolerance_ms);
+    changed += merged.len() + reassigned;
+
+    if changed > 0 {
+        ensure_chorus_agent(data);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><head><metadata><ttm:agent type="person" xml:id="v1"><ttm:name type="full">A</ttm:name></ttm:agent><ttm:agent type="person" xml:id="v2"><ttm:name type="full">B</ttm:name></ttm:agent><ttm:agent type="group" xml:id="v3"><ttm:name type="full">All</ttm:name></ttm:agent></metadata></head><body><div><p begin="00:01.000" end="00:02.000" ttm:agent="v1">a</p><p begin="00:03.000" end="00:04.000" ttm:agent="v1">together</p><p begin="00:03.050" end="00:04.200" ttm:agent="v2">together</p><p begin="00:05.000" end="00:06.000" ttm:agent="v3">everyone</p><p begin="00:07.000" end="00:08.000" ttm:agent="v2">a</p></div></body></tt>"#;
+
+    #[test]
+    fn test_normalize_chorus() {
+        let mut data = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        assert_eq!(normalize_chorus(&mut data, &ChorusOptions::default()), 3);
+
+        let lines: Vec<_> = data
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.main_text().unwrap_or_default(),
+                    line.agent.clone().unwrap_or_default(),
+                    line.end_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("a".to_string(), "v1".to_string(), 2000),
+                ("together".to_string(), "v1000".to_string(), 4200),
+                ("everyone".to_string(), "v1000".to_string(), 6000),
+                ("a".to_string(), "v2".to_string(), 8000),
+            ]
+        );
+        assert!(data.agents.agents_by_id.contains_key(CHORUS_AGENT_ID));
+    }
+}
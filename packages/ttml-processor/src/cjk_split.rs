@@ -0,0 +1,213 @@
+//! 将逐行歌词中的中日韩文本拆分为逐字音节
+//!
+//! 从 LRC 等逐行格式转换来的歌词每行只有一个音节，无法逐字高亮。
+//! 这里把只有一个音节且包含中日韩文字的轨道按字拆分，并把该音节的时间平均分配给每个字，
+//! 让这类歌词也能有逐字动画。
+//!
+//! 中日韩文字每个字单独成为一个音节，其余的文字（如夹杂的英文单词）以空格为界整体成为一个音节。
+//! 结尾标点并入前一个音节，开括号、开引号等并入后一个音节
+
+use lyrics_helper_core::{LyricLine, LyricSyllable, LyricTrack, Word};
+
+use crate::punctuation::is_trailing_punctuation;
+
+/// 是否为需要逐字拆分的中日韩文字
+const fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Unit {
+    text: String,
+    ends_with_space: bool,
+}
+
+impl Unit {
+    fn new(prefix: &mut String, c: char) -> Self {
+        let mut text = std::mem::take(prefix);
+        text.push(c);
+        Self {
+            text,
+            ends_with_space: false,
+        }
+    }
+}
+
+fn segment(text: &str) -> Vec<Unit> {
+    let mut units: Vec<Unit> = Vec::new();
+    // 等待并入下一个音节的开括号等标点
+    let mut prefix = String::new();
+    // 最后一个音节是否为尚未结束的非中日韩单词
+    let mut in_word = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if let Some(last) = units.last_mut() {
+                last.ends_with_space = true;
+            }
+            in_word = false;
+        } else if is_cjk(c) {
+            units.push(Unit::new(&mut prefix, c));
+            in_word = false;
+        } else if let Some(last) = units.last_mut()
+            && (in_word || (prefix.is_empty() && is_trailing_punctuation(c)))
+            && !last.ends_with_space
+        {
+            last.text.push(c);
+        } else if c.is_alphanumeric() {
+            units.push(Unit::new(&mut prefix, c));
+            in_word = true;
+        } else {
+            prefix.push(c);
+        }
+    }
+    if !prefix.is_empty() {
+        match units.last_mut() {
+            Some(last) => last.text.push_str(&prefix),
+            None => units.push(Unit {
+                text: prefix,
+                ends_with_space: false,
+            }),
+        }
+    }
+    units
+}
+
+fn split_track(track: &mut LyricTrack, line_start_ms: u64, line_end_ms: u64) -> bool {
+    let syllable = {
+        let mut syllables = track.syllables();
+        let (Some(syllable), None) = (syllables.next(), syllables.next()) else {
+            return false;
+        };
+        syllable.clone()
+    };
+    if !syllable.text.chars().any(is_cjk) {
+        return false;
+    }
+    let units = segment(&syllable.text);
+    if units.len() < 2 {
+        return false;
+    }
+    // 没有音节时间时使用行的时间
+    let (start_ms, end_ms) = if syllable.end_ms > syllable.start_ms {
+        (syllable.start_ms, syllable.end_ms)
+    } else {
+        (line_start_ms, line_end_ms)
+    };
+    let ends_with_space = syllable.ends_with_space;
+
+    let count = units.len() as u64;
+    let span = end_ms.saturating_sub(start_ms);
+    let boundary = |i: u64| start_ms + span * i / count;
+    let mut new_syllables: Vec<LyricSyllable> = (0..count)
+        .zip(units)
+        .map(|(i, unit)| LyricSyllable {
+            text: unit.text,
+            start_ms: boundary(i),
+            end_ms: boundary(i + 1),
+            duration_ms: Some(boundary(i + 1) - boundary(i)),
+            ends_with_space: unit.ends_with_space,
+        })
+        .collect();
+    if let Some(last) = new_syllables.last_mut() {
+        last.ends_with_space = ends_with_space;
+    }
+    track.words = vec![Word {
+        syllables: new_syllables,
+        furigana: None,
+    }];
+    true
+}
+
+/// 将逐行歌词中包含中日韩文字的主歌词和背景人声轨道拆分为逐字音节，返回被拆分的轨道数量
+///
+/// 已经有多个音节的轨道不会被修改
+pub fn split_cjk_syllables(lines: &mut [LyricLine]) -> usize {
+    let mut split = 0;
+    for line in lines {
+        let (line_start_ms, line_end_ms) = (line.start_ms, line.end_ms);
+        for track in &mut line.tracks {
+            if split_track(&mut track.content, line_start_ms, line_end_ms) {
+                split += 1;
+            }
+        }
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:03.000">「你好，世界 don't cry</p><p begin="00:03.000" end="00:04.000">Hello world</p></div></body></tt>"#;
+
+    fn texts(units: &[Unit]) -> Vec<(&str, bool)> {
+        units
+            .iter()
+            .map(|unit| (unit.text.as_str(), unit.ends_with_space))
+            .collect()
+    }
+
+    #[test]
+    fn test_segment() {
+        assert_eq!(
+            texts(&segment("「你好，世界」 don't cry！")),
+            vec![
+                ("「你", false),
+                ("好，", false),
+                ("世", false),
+                ("界」", true),
+                ("don't", true),
+                ("cry！", false),
+            ]
+        );
+        assert_eq!(
+            texts(&segment("こんにちは")),
+            vec![
+                ("こ", false),
+                ("ん", false),
+                ("に", false),
+                ("ち", false),
+                ("は", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_cjk_syllables() {
+        let mut parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let english = parsed.lines[1].clone();
+        assert_eq!(split_cjk_syllables(&mut parsed.lines), 1);
+
+        let main = parsed.lines[0].main_track().unwrap();
+        let syllables: Vec<_> = main
+            .content
+            .syllables()
+            .map(|syl| (syl.text.as_str(), syl.start_ms, syl.end_ms))
+            .collect();
+        assert_eq!(
+            syllables,
+            vec![
+                ("「你", 1000, 1333),
+                ("好，", 1333, 1666),
+                ("世", 1666, 2000),
+                ("界", 2000, 2333),
+                ("don't", 2333, 2666),
+                ("cry", 2666, 3000),
+            ]
+        );
+        assert_eq!(
+            parsed.lines[0].main_text().as_deref(),
+            Some("「你好，世界 don't cry")
+        );
+        assert_eq!(parsed.lines[1], english);
+    }
+}
@@ -0,0 +1,54 @@
+//! 删除完全重复的歌词行
+//!
+//! 合并多个来源或多次导入同一份歌词时，常会出现时间、演唱者和内容都完全相同的行。
+//! 这里只删除与前面某一行完全一致的行，内容相同但时间不同的行（如重复的副歌）会被保留
+
+use lyrics_helper_core::LyricLine;
+
+/// 找出与前面某一行完全相同的行，返回它们的下标
+#[must_use]
+pub fn find_duplicate_lines(lines: &[LyricLine]) -> Vec<usize> {
+    (0..lines.len())
+        .filter(|&i| lines[..i].contains(&lines[i]))
+        .collect()
+}
+
+/// 删除完全重复的行，返回删除的行数
+pub fn remove_duplicate_lines(lines: &mut Vec<LyricLine>) -> usize {
+    let duplicates = find_duplicate_lines(lines);
+    let mut index = 0;
+    lines.retain(|_| {
+        let keep = duplicates.binary_search(&index).is_err();
+        index += 1;
+        keep
+    });
+    duplicates.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse_lines;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000">a</p><p begin="00:01.000" end="00:02.000">a</p><p begin="00:03.000" end="00:04.000">b</p><p begin="00:05.000" end="00:06.000">a</p><p begin="00:03.000" end="00:04.000">b</p></div></body></tt>"#;
+
+    #[test]
+    fn test_remove_duplicate_lines() {
+        let mut lines = parse_lines(TTML);
+        assert_eq!(find_duplicate_lines(&lines).len(), 2);
+        assert_eq!(remove_duplicate_lines(&mut lines), 2);
+
+        let remaining: Vec<_> = lines
+            .iter()
+            .map(|line| (line.main_text().unwrap_or_default(), line.start_ms))
+            .collect();
+        assert_eq!(
+            remaining,
+            vec![
+                ("a".to_string(), 1000),
+                ("b".to_string(), 3000),
+                ("a".to_string(), 5000)
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! 预览优化操作将要做出的修改
+//!
+//! 平滑、去重和修正重叠都会直接改写歌词，用户往往希望先看到会改动哪些地方。
+//! 这里在歌词的副本上执行操作，把结果与原歌词比较，返回所有将要做出的修改，原歌词保持不变
+
+use lyrics_helper_core::{ConvertError, LyricLine, SyllableSmoothingOptions, TtmlParsingOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dedupe::find_duplicate_lines,
+    overlap::{OverlapStrategy, TimeRange, resolve_overlaps},
+    smoothing::apply_smoothing,
+};
+
+/// 可以预览的优化操作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "pass", rename_all = "kebab-case")]
+pub enum OptimizerPass {
+    /// 音节时长平滑，见 [`crate::smoothing`]
+    Smoothing {
+        #[serde(default)]
+        options: SyllableSmoothingOptions,
+    },
+    /// 删除完全重复的行，见 [`crate::dedupe`]
+    Dedupe,
+    /// 修正时间重叠，见 [`crate::overlap`]
+    Overlap {
+        #[serde(default)]
+        strategy: OverlapStrategy,
+    },
+}
+
+/// 一处将要做出的修改
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum ProposedEdit {
+    /// `syllable_index` 为音节在轨道内所有单词中的下标
+    #[serde(rename_all = "camelCase")]
+    SyllableTiming {
+        line_index: usize,
+        track_index: usize,
+        syllable_index: usize,
+        before: TimeRange,
+        after: TimeRange,
+    },
+    #[serde(rename_all = "camelCase")]
+    LineTiming {
+        line_index: usize,
+        before: TimeRange,
+        after: TimeRange,
+    },
+    #[serde(rename_all = "camelCase")]
+    RemoveLine { line_index: usize },
+}
+
+const fn range(start_ms: u64, end_ms: u64) -> TimeRange {
+    TimeRange { start_ms, end_ms }
+}
+
+/// 比较只修改了时间的两份歌词，两者的行、轨道和音节必须一一对应
+fn diff_timings(before: &[LyricLine], after: &[LyricLine]) -> Vec<ProposedEdit> {
+    let mut edits = Vec::new();
+    for (line_index, (old_line, new_line)) in before.iter().zip(after).enumerate() {
+        for (track_index, (old_track, new_track)) in
+            old_line.tracks.iter().zip(&new_line.tracks).enumerate()
+        {
+            let syllables = old_track
+                .content
+                .syllables()
+                .zip(new_track.content.syllables());
+            for (syllable_index, (old, new)) in syllables.enumerate() {
+                let (old, new) = (
+                    range(old.start_ms, old.end_ms),
+                    range(new.start_ms, new.end_ms),
+                );
+                if old != new {
+                    edits.push(ProposedEdit::SyllableTiming {
+                        line_index,
+                        track_index,
+                        syllable_index,
+                        before: old,
+                        after: new,
+                    });
+                }
+            }
+        }
+        let old = range(old_line.start_ms, old_line.end_ms);
+        let new = range(new_line.start_ms, new_line.end_ms);
+        if old != new {
+            edits.push(ProposedEdit::LineTiming {
+                line_index,
+                before: old,
+                after: new,
+            });
+        }
+    }
+    edits
+}
+
+/// 返回优化操作将要做出的所有修改，不会修改 `lines`
+#[must_use]
+pub fn preview_pass(lines: &[LyricLine], pass: &OptimizerPass) -> Vec<ProposedEdit> {
+    match pass {
+        OptimizerPass::Smoothing { options } => {
+            let mut after = lines.to_vec();
+            apply_smoothing(&mut after, options);
+            diff_timings(lines, &after)
+        }
+        OptimizerPass::Dedupe => find_duplicate_lines(lines)
+            .into_iter()
+            .map(|line_index| ProposedEdit::RemoveLine { line_index })
+            .collect(),
+        OptimizerPass::Overlap { strategy } => {
+            let mut after = lines.to_vec();
+            resolve_overlaps(&mut after, *strategy);
+            diff_timings(lines, &after)
+        }
+    }
+}
+
+/// 解析一份 TTML 歌词并预览优化操作将要做出的修改
+///
+/// # Errors
+///
+/// TTML 解析失败时返回 `ConvertError`
+pub fn preview_ttml(
+    ttml_content: &str,
+    pass: &OptimizerPass,
+) -> Result<Vec<ProposedEdit>, ConvertError> {
+    let data = ttml_processor::parse_ttml(ttml_content, &TtmlParsingOptions::default())?;
+    Ok(preview_pass(&data.lines, pass))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse_lines;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:01.600">a</span><span begin="00:01.400" end="00:02.000">b</span></p><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:01.600">a</span><span begin="00:01.400" end="00:02.000">b</span></p></div></body></tt>"#;
+
+    #[test]
+    fn test_preview_does_not_modify() {
+        let lines = parse_lines(TTML);
+        let edits = preview_pass(
+            &lines,
+            &OptimizerPass::Overlap {
+                strategy: OverlapStrategy::Clamp,
+            },
+        );
+        assert_eq!(lines, parse_lines(TTML));
+        assert_eq!(
+            edits[0],
+            ProposedEdit::SyllableTiming {
+                line_index: 0,
+                track_index: 0,
+                syllable_index: 0,
+                before: range(1000, 1600),
+                after: range(1000, 1400),
+            }
+        );
+        assert_eq!(
+            preview_pass(&lines, &OptimizerPass::Dedupe),
+            vec![ProposedEdit::RemoveLine { line_index: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_pass_from_json() {
+        let pass: OptimizerPass =
+            serde_json::from_str(r#"{"pass": "overlap", "strategy": "split"}"#).unwrap();
+        assert!(matches!(
+            pass,
+            OptimizerPass::Overlap {
+                strategy: OverlapStrategy::Split
+            }
+        ));
+    }
+}
@@ -0,0 +1,424 @@
+//! 将 `ttml_processor` 解析得到的歌词导出为其它歌词格式
+//!
+//! 能由 `amll_lyric` 生成的格式会先转换为它的歌词行，再交给其中对应的函数生成
+
+use amll_lyric::{
+    LyricLine as AmllLine, LyricWord as AmllWord,
+    ass::{AegisubTemplate, stringify_aegisub_project},
+};
+use lyrics_helper_core::converter::types as helper_types;
+use lyrics_helper_core::{ConvertError, MetadataStore, TtmlParsingOptions};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::{
+    agent_recognizer::output_metadata,
+    canonical::to_canonical_json,
+    translation::{ConvertOptions, convert_to_amll_lyrics},
+    ttml_generator::to_ms,
+};
+
+/// 可导出的歌词格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LyricExportFormat {
+    /// 逐行的 `[mm:ss.xxx]` LRC
+    Lrc,
+    /// 在 LRC 的基础上用 `<mm:ss.xx>` 标注逐字时间的增强 LRC
+    EnhancedLrc,
+    /// QQ 音乐的逐字 QRC，每个音节写为 `文本(开始,时长)`
+    Qrc,
+    /// 与 QRC 配套的翻译，为逐行 LRC，没有翻译的行写为 `//`
+    QrcTranslation,
+    /// Lyricify Syllable，每行以 `[属性]` 开头标注背景和对唱，音节写为 `文本(开始,时长)`
+    Lys,
+    /// `ESLyric` 的逐字 LRC，每个音节之后写上它的结束时间 `[mm:ss.xxx]`
+    Eslrc,
+    /// 逐行的 SRT 字幕，有翻译时会作为字幕的第二行
+    Srt,
+    /// 逐行的 ASS 字幕，背景、对唱、翻译和音译会使用各自的样式
+    Ass,
+    /// 在 ASS 的基础上用 `\k` 标签标注逐字时间的卡拉 OK 字幕
+    AssKaraoke,
+    /// 版本化的规范 JSON，见 [`crate::canonical`]
+    CanonicalJson,
+}
+
+impl LyricExportFormat {
+    /// 根据格式名称取得导出格式，名称不区分大小写
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "lrc" => Some(Self::Lrc),
+            "enhancedlrc" | "enhanced-lrc" | "elrc" => Some(Self::EnhancedLrc),
+            "qrc" => Some(Self::Qrc),
+            "qrctranslation" | "qrc-translation" => Some(Self::QrcTranslation),
+            "lys" | "lyricifysyllable" | "lyricify-syllable" => Some(Self::Lys),
+            "eslrc" | "eslyric" => Some(Self::Eslrc),
+            "srt" => Some(Self::Srt),
+            "ass" => Some(Self::Ass),
+            "asskaraoke" | "ass-karaoke" => Some(Self::AssKaraoke),
+            "json" | "canonicaljson" | "canonical-json" => Some(Self::CanonicalJson),
+            _ => None,
+        }
+    }
+
+    /// 导出文件时通常使用的扩展名，不包含 `.`
+    #[must_use]
+    pub const fn file_extension(self) -> &'static str {
+        match self {
+            Self::Lrc | Self::EnhancedLrc | Self::QrcTranslation | Self::Eslrc => "lrc",
+            Self::Qrc => "qrc",
+            Self::Lys => "lys",
+            Self::Srt => "srt",
+            Self::Ass | Self::AssKaraoke => "ass",
+            Self::CanonicalJson => "json",
+        }
+    }
+}
+
+/// 转换为 `amll_lyric` 的歌词行，背景和对唱的判断与转换为 AMLL 歌词行时一致
+fn amll_lines(data: &helper_types::ParsedSourceData) -> Vec<AmllLine<'static>> {
+    convert_to_amll_lyrics(data, &ConvertOptions::default())
+        .into_iter()
+        .map(|line| AmllLine {
+            words: line
+                .words
+                .into_iter()
+                .map(|word| AmllWord {
+                    start_time: to_ms(word.start_time),
+                    end_time: to_ms(word.end_time),
+                    word: word.word.into(),
+                    roman_word: word.roman_word.into(),
+                })
+                .collect(),
+            translated_lyric: line.translated_lyric.into(),
+            roman_lyric: line.roman_lyric.into(),
+            is_bg: line.is_bg,
+            is_duet: line.is_duet,
+            start_time: to_ms(line.start_time),
+            end_time: to_ms(line.end_time),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// 导出 LRC，某一行结束后到下一行开始之间有间隔时，会在该行的结束时间插入一个空行用于清除显示
+fn export_lrc(data: &helper_types::ParsedSourceData, output: &mut String) {
+    let lines: Vec<_> = amll_lines(data)
+        .into_iter()
+        .filter(|line| !line.is_bg && !line.words.is_empty())
+        .collect();
+    let mut with_gaps = Vec::with_capacity(lines.len() * 2);
+    for (index, line) in lines.iter().enumerate() {
+        with_gaps.push(line.clone());
+        let next_start = lines.get(index + 1).map(|next| next.start_time);
+        if line.end_time > line.start_time && next_start.is_none_or(|start| start > line.end_time) {
+            with_gaps.push(AmllLine {
+                words: vec![AmllWord {
+                    start_time: line.end_time,
+                    end_time: line.end_time,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            });
+        }
+    }
+    output.push_str(&amll_lyric::lrc::stringify_lrc(&with_gaps));
+}
+
+fn write_lrc_time(output: &mut String, open: char, time_ms: u64, close: char) {
+    let centis = time_ms / 10;
+    let _ = write!(
+        output,
+        "{open}{:02}:{:02}.{:02}{close}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    );
+}
+
+fn write_enhanced_lrc_line(
+    output: &mut String,
+    line: &helper_types::LyricLine,
+    track: &helper_types::LyricTrack,
+) {
+    write_lrc_time(output, '[', line.start_ms, ']');
+    let mut syllables = track.syllables().peekable();
+    while let Some(syl) = syllables.next() {
+        write_lrc_time(output, '<', syl.start_ms, '>');
+        output.push_str(&syl.text);
+        if syl.ends_with_space && syllables.peek().is_some() {
+            output.push(' ');
+        }
+        if syllables.peek().is_none() {
+            write_lrc_time(output, '<', syl.end_ms, '>');
+        }
+    }
+    output.push('\n');
+}
+
+/// 导出增强 LRC，与 LRC 一样只包含主歌词轨道，并在行与行之间的间隔处插入空行
+fn export_enhanced_lrc(data: &helper_types::ParsedSourceData, output: &mut String) {
+    let lines: Vec<_> = data
+        .lines
+        .iter()
+        .filter_map(|line| {
+            line.main_track()
+                .map(|track| (line, &track.content))
+                .filter(|(_, track)| !track.is_empty())
+        })
+        .collect();
+
+    for (index, (line, track)) in lines.iter().enumerate() {
+        write_enhanced_lrc_line(output, line, track);
+
+        let next_start = lines.get(index + 1).map(|(next, _)| next.start_ms);
+        if line.end_ms > line.start_ms && next_start.is_none_or(|start| start > line.end_ms) {
+            write_lrc_time(output, '[', line.end_ms, ']');
+            output.push('\n');
+        }
+    }
+}
+
+/// 导出 QRC，背景人声会作为单独的一行写在对应的主歌词行之后
+fn export_qrc(data: &helper_types::ParsedSourceData, output: &mut String) {
+    output.push_str(&amll_lyric::qrc::stringify_qrc(&amll_lines(data)));
+}
+
+/// 导出 QRC 的翻译，每一个主歌词行对应一行
+fn export_qrc_translation(data: &helper_types::ParsedSourceData, output: &mut String) {
+    for line in &data.lines {
+        let Some(annotated) = line.main_track() else {
+            continue;
+        };
+        if annotated.content.is_empty() {
+            continue;
+        }
+        write_lrc_time(output, '[', line.start_ms, ']');
+        match annotated
+            .translations
+            .iter()
+            .map(helper_types::LyricTrack::text)
+            .find(|text| !text.trim().is_empty())
+        {
+            Some(text) => output.push_str(&text),
+            None => output.push_str("//"),
+        }
+        output.push('\n');
+    }
+}
+
+/// 导出 Lyricify Syllable，背景和对唱以每行开头的属性标注
+fn export_lys(data: &helper_types::ParsedSourceData, output: &mut String) {
+    output.push_str(&amll_lyric::lys::stringify_lys(&amll_lines(data)));
+}
+
+/// 导出 `ESLyric` 的逐字 LRC，只包含主歌词轨道，每行的最后一个时间即为该行的结束时间
+fn export_eslrc(data: &helper_types::ParsedSourceData, output: &mut String) {
+    let lines: Vec<_> = amll_lines(data)
+        .into_iter()
+        .filter(|line| !line.is_bg)
+        .collect();
+    output.push_str(&amll_lyric::eslrc::stringify_eslrc(&lines));
+}
+
+/// 字幕格式使用的歌词行：主歌词轨道及其第一条非空的翻译
+fn subtitle_lines(
+    data: &helper_types::ParsedSourceData,
+) -> impl Iterator<
+    Item = (
+        &helper_types::LyricLine,
+        &helper_types::LyricTrack,
+        Option<String>,
+    ),
+> {
+    data.lines.iter().filter_map(|line| {
+        let annotated = line.main_track()?;
+        if annotated.content.is_empty() {
+            return None;
+        }
+        let translation = annotated
+            .translations
+            .iter()
+            .map(helper_types::LyricTrack::text)
+            .find(|text| !text.trim().is_empty());
+        Some((line, &annotated.content, translation))
+    })
+}
+
+fn write_srt_time(output: &mut String, time_ms: u64) {
+    let _ = write!(
+        output,
+        "{:02}:{:02}:{:02},{:03}",
+        time_ms / 3_600_000,
+        time_ms / 60_000 % 60,
+        time_ms / 1000 % 60,
+        time_ms % 1000
+    );
+}
+
+fn export_srt(data: &helper_types::ParsedSourceData) -> String {
+    let mut output = String::new();
+    for (index, (line, track, translation)) in subtitle_lines(data).enumerate() {
+        let _ = writeln!(output, "{}", index + 1);
+        write_srt_time(&mut output, line.start_ms);
+        output.push_str(" --> ");
+        write_srt_time(&mut output, line.end_ms.max(line.start_ms));
+        output.push('\n');
+        output.push_str(&track.text());
+        output.push('\n');
+        if let Some(translation) = translation {
+            output.push_str(&translation);
+            output.push('\n');
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// 导出 ASS 字幕，背景、对唱、翻译和音译会使用各自的样式
+fn export_ass(data: &helper_types::ParsedSourceData, karaoke: bool) -> String {
+    let template = AegisubTemplate {
+        karaoke,
+        ..Default::default()
+    };
+    stringify_aegisub_project(&amll_lines(data), &template)
+}
+
+/// 在导出结果之前写入由元数据生成的 LRC 头部标签
+fn with_lrc_header(
+    data: &helper_types::ParsedSourceData,
+    export: fn(&helper_types::ParsedSourceData, &mut String),
+) -> String {
+    let mut metadata_store = MetadataStore::new();
+    metadata_store.load_from_raw(&output_metadata(data));
+    let mut output = metadata_store.generate_lrc_header();
+    export(data, &mut output);
+    output
+}
+
+/// 将解析得到的歌词导出为指定格式的字符串
+///
+/// SRT、ASS 和规范 JSON 以外的格式会将元数据写为 LRC 的头部标签
+#[must_use]
+pub fn export_lyrics(data: &helper_types::ParsedSourceData, format: LyricExportFormat) -> String {
+    match format {
+        LyricExportFormat::Lrc => with_lrc_header(data, export_lrc),
+        LyricExportFormat::EnhancedLrc => with_lrc_header(data, export_enhanced_lrc),
+        LyricExportFormat::Qrc => with_lrc_header(data, export_qrc),
+        LyricExportFormat::QrcTranslation => with_lrc_header(data, export_qrc_translation),
+        LyricExportFormat::Lys => with_lrc_header(data, export_lys),
+        LyricExportFormat::Eslrc => with_lrc_header(data, export_eslrc),
+        LyricExportFormat::Srt => export_srt(data),
+        LyricExportFormat::Ass => export_ass(data, false),
+        LyricExportFormat::AssKaraoke => export_ass(data, true),
+        LyricExportFormat::CanonicalJson => to_canonical_json(data),
+    }
+}
+
+/// 解析一份 TTML 歌词并导出为指定格式的字符串
+///
+/// # Errors
+///
+/// TTML 解析失败时返回 `ConvertError`
+pub fn export_ttml(ttml_content: &str, format: LyricExportFormat) -> Result<String, ConvertError> {
+    let data = ttml_processor::parse_ttml(ttml_content, &TtmlParsingOptions::default())?;
+    Ok(export_lyrics(&data, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:amll="http://www.example.com/ns/amll"><head><metadata><amll:meta key="musicName" value="Song"/></metadata></head><body><div><p begin="00:01.000" end="00:02.500"><span begin="00:01.000" end="00:01.500">Hello</span> <span begin="00:01.500" end="00:02.500">world</span></p><p begin="00:02.500" end="00:04.000"><span begin="00:02.500" end="00:04.000">again</span></p><p begin="01:05.120" end="01:06.000"><span begin="01:05.120" end="01:06.000">last</span></p></div></body></tt>"#;
+
+    #[test]
+    fn test_export_lrc() {
+        let lrc = export_ttml(TTML, LyricExportFormat::Lrc).unwrap();
+        assert_eq!(
+            lrc,
+            "[ti:Song]\n[00:01.000]Hello world\n[00:02.500]again\n[00:04.000]\n[01:05.120]last\n[01:06.000]\n"
+        );
+    }
+
+    #[test]
+    fn test_file_extension() {
+        assert_eq!(LyricExportFormat::EnhancedLrc.file_extension(), "lrc");
+        assert_eq!(LyricExportFormat::AssKaraoke.file_extension(), "ass");
+        assert_eq!(LyricExportFormat::CanonicalJson.file_extension(), "json");
+    }
+
+    #[test]
+    fn test_export_enhanced_lrc() {
+        let lrc = export_ttml(TTML, LyricExportFormat::EnhancedLrc).unwrap();
+        assert_eq!(
+            lrc.lines().nth(1),
+            Some("[00:01.00]<00:01.00>Hello <00:01.50>world<00:02.50>")
+        );
+        assert_eq!(
+            LyricExportFormat::from_name("Enhanced-LRC"),
+            Some(LyricExportFormat::EnhancedLrc)
+        );
+    }
+
+    #[test]
+    fn test_export_qrc() {
+        let qrc = export_ttml(TTML, LyricExportFormat::Qrc).unwrap();
+        assert_eq!(
+            qrc,
+            "[ti:Song]\n[1000,1500]Hello (1000,500)world(1500,1000)\n[2500,1500]again(2500,1500)\n[65120,880]last(65120,880)\n"
+        );
+    }
+
+    #[test]
+    fn test_export_qrc_translation() {
+        const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:02.000">Hello</span><span ttm:role="x-translation">你好</span></p><p begin="00:02.000" end="00:03.000"><span begin="00:02.000" end="00:03.000">again</span></p></div></body></tt>"#;
+        let translation = export_ttml(TTML, LyricExportFormat::QrcTranslation).unwrap();
+        assert_eq!(translation, "[00:01.00]你好\n[00:02.00]//\n");
+    }
+
+    #[test]
+    fn test_export_lys() {
+        const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><head><metadata><ttm:agent type="person" xml:id="v1"/><ttm:agent type="person" xml:id="v2"/></metadata></head><body><div><p begin="00:01.000" end="00:03.000" ttm:agent="v1"><span begin="00:01.000" end="00:02.000">Hello</span><span ttm:role="x-bg"><span begin="00:02.000" end="00:03.000">(echo)</span></span></p><p begin="00:03.000" end="00:04.000" ttm:agent="v2"><span begin="00:03.000" end="00:04.000">duet</span></p></div></body></tt>"#;
+        let lys = export_ttml(TTML, LyricExportFormat::Lys).unwrap();
+        assert_eq!(
+            lys,
+            "[0]Hello(1000,1000)\n[6]echo(2000,1000)\n[2]duet(3000,1000)\n"
+        );
+    }
+
+    #[test]
+    fn test_export_eslrc() {
+        let eslrc = export_ttml(TTML, LyricExportFormat::Eslrc).unwrap();
+        assert_eq!(
+            eslrc.lines().nth(1),
+            Some("[00:01.000]Hello [00:01.500]world[00:02.500]")
+        );
+    }
+
+    #[test]
+    fn test_export_srt() {
+        const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.500"><span begin="00:01.000" end="00:02.500">Hello</span><span ttm:role="x-translation">你好</span></p><p begin="01:02:03.450" end="01:02:04.000"><span begin="01:02:03.450" end="01:02:04.000">again</span></p></div></body></tt>"#;
+        let srt = export_ttml(TTML, LyricExportFormat::Srt).unwrap();
+        assert_eq!(
+            srt,
+            "1\n00:00:01,000 --> 00:00:02,500\nHello\n你好\n\n2\n01:02:03,450 --> 01:02:04,000\nagain\n\n"
+        );
+    }
+
+    #[test]
+    fn test_export_ass_karaoke() {
+        let ass = export_ttml(TTML, LyricExportFormat::Ass).unwrap();
+        assert!(ass.starts_with("[Script Info]\n"));
+        assert!(ass.contains("Dialogue: 0,0:00:01.00,0:00:02.50,Lyric,v1,0,0,0,,Hello world\n"));
+
+        let karaoke = export_ttml(TTML, LyricExportFormat::AssKaraoke).unwrap();
+        assert!(karaoke.contains(
+            "Dialogue: 0,0:00:01.00,0:00:02.50,Lyric,v1,0,0,0,,{\\k50}Hello {\\k100}world\n"
+        ));
+        assert!(
+            karaoke.contains("Dialogue: 0,0:01:05.12,0:01:06.00,Lyric,v1,0,0,0,,{\\k88}last\n")
+        );
+    }
+}
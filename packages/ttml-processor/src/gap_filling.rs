@@ -0,0 +1,81 @@
+//! 填补音节之间的短暂间隔
+//!
+//! 逐字歌词中相邻音节之间常有几十毫秒的空隙，播放时会出现短暂的“没有字被高亮”的闪烁。
+//! 间隔小于阈值时，把前一个音节的结束时间延长到下一个音节的开始时间。
+//! 主歌词轨道和背景人声轨道分别处理，不会跨轨道或跨行填补
+
+use lyrics_helper_core::{LyricLine, LyricSyllable};
+use serde::{Deserialize, Serialize};
+
+/// 控制间隔填补的选项
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GapFillingOptions {
+    /// 小于该值（毫秒）的间隔会被填补
+    pub max_gap_ms: u64,
+}
+
+impl Default for GapFillingOptions {
+    fn default() -> Self {
+        Self { max_gap_ms: 100 }
+    }
+}
+
+/// 填补所有歌词行中主歌词和背景人声音节之间的短暂间隔，返回被延长的音节数量
+pub fn fill_gaps(lines: &mut [LyricLine], options: &GapFillingOptions) -> usize {
+    let mut filled = 0;
+    for line in lines {
+        for track in &mut line.tracks {
+            let mut syllables = track
+                .content
+                .words
+                .iter_mut()
+                .flat_map(|word| &mut word.syllables)
+                .filter(|syl| syl.end_ms > syl.start_ms)
+                .peekable();
+            while let Some(syllable) = syllables.next() {
+                if let Some(next) = syllables.peek()
+                    && fill_gap(syllable, next, options.max_gap_ms)
+                {
+                    filled += 1;
+                }
+            }
+        }
+    }
+    filled
+}
+
+const fn fill_gap(syllable: &mut LyricSyllable, next: &LyricSyllable, max_gap_ms: u64) -> bool {
+    let gap = next.start_ms.saturating_sub(syllable.end_ms);
+    if gap == 0 || gap >= max_gap_ms {
+        return false;
+    }
+    syllable.end_ms = next.start_ms;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::track_times;
+    use lyrics_helper_core::{ContentType, TtmlParsingOptions};
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:03.000"><span begin="00:01.000" end="00:01.200">a</span><span begin="00:01.250" end="00:01.500">b</span> <span begin="00:01.800" end="00:02.000">c</span><span ttm:role="x-bg"><span begin="00:02.000" end="00:02.400">(d</span><span begin="00:02.420" end="00:03.000">e)</span></span></p></div></body></tt>"#;
+
+    #[test]
+    fn test_fill_gaps() {
+        let mut parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let filled = fill_gaps(&mut parsed.lines, &GapFillingOptions::default());
+        assert_eq!(filled, 2);
+
+        let line = &parsed.lines[0];
+        assert_eq!(
+            track_times(line, ContentType::Main),
+            vec![(1000, 1250), (1250, 1500), (1800, 2000)]
+        );
+        assert_eq!(
+            track_times(line, ContentType::Background),
+            vec![(2000, 2420), (2420, 3000)]
+        );
+    }
+}
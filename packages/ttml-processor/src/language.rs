@@ -0,0 +1,217 @@
+//! BCP-47 语言标签的解析与匹配
+//!
+//! 歌词中的 `xml:lang` 写法并不统一，例如同样是简体中文，可能写为 `zh-CN`、
+//! `zh-Hans`、`zh-Hans-CN` 或 `cmn-Hans`。这里将标签拆分为语言、文字和地区，
+//! 并在匹配时按文字、地区逐级降级，而不是要求字符串完全相同
+
+/// 解析后的 BCP-47 语言标签，各子标签均已规范化大小写
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageTag {
+    /// 主语言子标签，已将 `cmn`、`zho` 等别名替换为对应的两字母代码
+    pub language: String,
+    /// 文字子标签，例如 `Hans`，没有写出时会根据语言和地区推断
+    pub script: Option<String>,
+    /// 地区子标签，例如 `CN`
+    pub region: Option<String>,
+    /// 变体子标签，例如 `ja-Latn-hepburn` 中的 `hepburn`
+    pub variants: Vec<String>,
+}
+
+/// 将三字母代码和已弃用的代码替换为首选的语言代码
+fn canonical_language(language: &str) -> &str {
+    match language {
+        "cmn" | "zho" | "chi" => "zh",
+        "jpn" => "ja",
+        "kor" => "ko",
+        "eng" => "en",
+        "nor" => "no",
+        "nob" => "nb",
+        "nno" => "nn",
+        "iw" => "he",
+        "in" => "id",
+        "ji" => "yi",
+        other => other,
+    }
+}
+
+/// 返回语言所属的宏语言，用于把挪威语的书面变体等视为同一种语言
+fn macrolanguage(language: &str) -> &str {
+    match language {
+        "nb" | "nn" => "no",
+        other => other,
+    }
+}
+
+/// 未写出文字子标签时，根据语言和地区推断的文字
+fn likely_script(language: &str, region: Option<&str>) -> Option<&'static str> {
+    match (language, region) {
+        ("zh", Some("TW" | "HK" | "MO")) => Some("Hant"),
+        ("zh", _) => Some("Hans"),
+        _ => None,
+    }
+}
+
+impl LanguageTag {
+    /// 解析一个语言标签，子标签之间可以使用 `-` 或 `_` 分隔，不区分大小写
+    ///
+    /// 私有使用（`x-`）和扩展子标签会被忽略，主语言子标签无效时返回 `None`
+    #[must_use]
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut subtags = tag.trim().split(['-', '_']).peekable();
+
+        let language = subtags.next()?.to_ascii_lowercase();
+        if !(2..=8).contains(&language.len()) || !language.bytes().all(|b| b.is_ascii_alphabetic())
+        {
+            return None;
+        }
+        // 扩展语言子标签，例如 `zh-cmn-Hans` 中的 `cmn`
+        let language = subtags
+            .next_if(|s| s.len() == 3 && is_alpha(s))
+            .map_or(language, str::to_ascii_lowercase);
+        let language = canonical_language(&language).to_string();
+
+        let script = subtags
+            .next_if(|s| s.len() == 4 && is_alpha(s))
+            .map(|s| s[..1].to_ascii_uppercase() + &s[1..].to_ascii_lowercase());
+
+        let region = subtags
+            .next_if(|s| {
+                (s.len() == 2 && is_alpha(s))
+                    || (s.len() == 3 && s.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .map(str::to_ascii_uppercase);
+
+        let mut variants = Vec::new();
+        while let Some(variant) = subtags.next_if(|s| {
+            s.bytes().all(|b| b.is_ascii_alphanumeric())
+                && ((5..=8).contains(&s.len())
+                    || (s.len() == 4 && s.as_bytes()[0].is_ascii_digit()))
+        }) {
+            variants.push(variant.to_ascii_lowercase());
+        }
+
+        let script =
+            script.or_else(|| likely_script(&language, region.as_deref()).map(ToString::to_string));
+
+        Some(Self {
+            language,
+            script,
+            region,
+            variants,
+        })
+    }
+
+    /// 计算 `self` 作为期望的语言时与 `other` 的匹配程度，语言不同时返回 `None`
+    ///
+    /// 分数越高越匹配，优先比较文字，其次比较地区，最后比较语言代码本身是否相同
+    /// （例如 `nb` 与 `no` 属于同一宏语言，但匹配程度低于两者完全相同）。
+    /// 文字不同（如简体与繁体）时仍视为匹配，只是分数最低
+    #[must_use]
+    pub fn match_score(&self, other: &Self) -> Option<u8> {
+        if macrolanguage(&self.language) != macrolanguage(&other.language) {
+            return None;
+        }
+        let score = |a: Option<&String>, b: Option<&String>| match (a, b) {
+            (Some(a), Some(b)) if a == b => 2,
+            (Some(_), Some(_)) => 0,
+            _ => 1,
+        };
+        let script = score(self.script.as_ref(), other.script.as_ref());
+        let region = score(self.region.as_ref(), other.region.as_ref());
+        let language = u8::from(self.language == other.language);
+        Some(script * 9 + region * 3 + language)
+    }
+}
+
+fn is_alpha(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// 按期望的语言顺序从候选中选出最匹配的一项
+///
+/// 依次尝试 `preferred` 中的每个语言，返回第一个有匹配的语言下分数最高的候选，
+/// 分数相同时选择靠前的候选。无法解析的标签不会参与匹配
+pub fn select_by_language<'a, T>(
+    candidates: &'a [T],
+    preferred: &[String],
+    lang_of: impl Fn(&T) -> Option<&str>,
+) -> Option<&'a T> {
+    let candidate_tags: Vec<_> = candidates
+        .iter()
+        .map(|c| lang_of(c).and_then(LanguageTag::parse))
+        .collect();
+
+    preferred
+        .iter()
+        .filter_map(|p| LanguageTag::parse(p))
+        .find_map(|preferred| {
+            candidates
+                .iter()
+                .zip(&candidate_tags)
+                .filter_map(|(candidate, tag)| {
+                    Some((candidate, preferred.match_score(tag.as_ref()?)?))
+                })
+                .fold(
+                    None,
+                    |best: Option<(&T, u8)>, (candidate, score)| match best {
+                        Some((_, best_score)) if best_score >= score => best,
+                        _ => Some((candidate, score)),
+                    },
+                )
+                .map(|(candidate, _)| candidate)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(s: &str) -> LanguageTag {
+        LanguageTag::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_normalizes_subtags() {
+        let parsed = tag("zh_hans_cn");
+        assert_eq!(parsed.language, "zh");
+        assert_eq!(parsed.script.as_deref(), Some("Hans"));
+        assert_eq!(parsed.region.as_deref(), Some("CN"));
+
+        assert_eq!(tag("cmn-Hans"), tag("zh-Hans"));
+        assert_eq!(tag("zh-cmn-Hant").script.as_deref(), Some("Hant"));
+        assert_eq!(tag("zh-TW").script.as_deref(), Some("Hant"));
+        assert_eq!(tag("ja-Latn-hepburn").variants, vec!["hepburn"]);
+        assert_eq!(tag("es-419").region.as_deref(), Some("419"));
+        assert!(LanguageTag::parse("").is_none());
+        assert!(LanguageTag::parse("x1").is_none());
+    }
+
+    #[test]
+    fn test_match_score() {
+        let zh_cn = tag("zh-CN");
+        let exact = zh_cn.match_score(&tag("zh-Hans-CN")).unwrap();
+        let script_only = zh_cn.match_score(&tag("cmn-Hans")).unwrap();
+        let traditional = zh_cn.match_score(&tag("zh-Hant")).unwrap();
+        assert!(exact > script_only);
+        assert!(script_only > traditional);
+        assert_eq!(zh_cn.match_score(&tag("ja")), None);
+
+        let no = tag("no");
+        assert!(no.match_score(&tag("no")) > no.match_score(&tag("nb")));
+        assert!(no.match_score(&tag("nn")).is_some());
+    }
+
+    #[test]
+    fn test_select_by_language() {
+        let candidates = ["en", "zh-Hant", "cmn-Hans", "zh-Hans-CN"];
+        let select = |preferred: &[&str]| {
+            let preferred: Vec<_> = preferred.iter().map(ToString::to_string).collect();
+            select_by_language(&candidates, &preferred, |c| Some(*c)).copied()
+        };
+        assert_eq!(select(&["zh-CN"]), Some("zh-Hans-CN"));
+        assert_eq!(select(&["zh-SG"]), Some("cmn-Hans"));
+        assert_eq!(select(&["zh-HK"]), Some("zh-Hant"));
+        assert_eq!(select(&["fr", "EN-us"]), Some("en"));
+        assert_eq!(select(&["nb"]), None);
+    }
+}
@@ -1,10 +1,37 @@
-use lyrics_helper_core::TtmlParsingOptions;
-use serde::{Deserialize, Serialize};
+use lyrics_helper_core::ParsedSourceData;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
-use crate::translation::convert_to_amll_lyrics;
+use crate::{
+    canonical::from_canonical_json,
+    export::{LyricExportFormat, export_ttml},
+    strict::{ParseTtmlError, parse_ttml_data},
+    translation::{ConvertOptions, build_line_key_map, build_sections, convert_to_amll_lyrics},
+    ttml_generator::{GenerateOptions, generate_ttml_from_lines},
+};
 
+pub mod agent_recognizer;
+pub mod canonical;
+pub mod chorus;
+pub mod cjk_split;
+pub mod dedupe;
+pub mod dry_run;
+pub mod export;
+pub mod gap_filling;
+mod language;
+pub mod line_split;
+pub mod metadata_stripper;
+pub mod overlap;
+pub mod pipeline;
+pub mod punctuation;
+pub mod rescale;
+pub mod smoothing;
+mod strict;
+#[cfg(test)]
+mod test_utils;
 mod translation;
+mod ttml_generator;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -13,6 +40,18 @@ pub struct JsLyricWord {
     pub end_time: f64,
     pub word: String,
     pub roman_word: String,
+    /// 逐字罗马音所使用的方案，即 `xml:scheme` 属性
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roman_scheme: Option<String>,
+}
+
+/// 演唱者的类型，对应 `ttm:agent` 元素的 `type` 属性
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JsAgentType {
+    Person,
+    Group,
+    Other,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -21,17 +60,52 @@ pub struct JsLyricLine {
     pub words: Vec<JsLyricWord>,
     pub translated_lyric: String,
     pub roman_lyric: String,
+    /// 罗马音所使用的方案，即 `xml:scheme` 属性
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roman_scheme: Option<String>,
     pub start_time: f64,
     pub end_time: f64,
     #[serde(rename = "isBG")]
     pub is_bg: bool,
     pub is_duet: bool,
+    /// 来源中的 `itunes:key` 属性，背景行与其所属的主行相同
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub itunes_key: Option<String>,
+    /// 该行演唱者的类型，可用于区分合唱行与独唱行，没有指定或声明演唱者时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_type: Option<JsAgentType>,
+    /// 该行演唱者按首次出现顺序的编号（从 0 开始），可用于为三位及以上的演唱者分配不同的颜色，
+    /// 背景行与其所属的主行相同，没有演唱者或为合唱时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice_index: Option<usize>,
+    /// 背景行所属主行在 `lines` 中的下标，主行和没有对应主行的背景行为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_index: Option<usize>,
+}
+
+/// 歌曲中的一个段落，由连续的具有相同 `itunes:songPart` 的行组成
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JsSection {
+    pub name: String,
+    pub start_time: f64,
+    pub end_time: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct JsTTMLLyric {
     pub lines: Vec<JsLyricLine>,
     pub metadata: Vec<(String, Vec<String>)>,
+    /// `itunes:key` 到 `lines` 中对应行下标的映射，指向该 key 的第一行（通常为主行），
+    /// 用于将之后获取到的翻译等数据合并到正确的行上
+    #[serde(rename = "lineKeyMap", default)]
+    pub line_key_map: HashMap<String, usize>,
+    /// 解析的 TTML 是否为带缩进的格式化 TTML，重新生成时默认沿用同样的格式
+    #[serde(default)]
+    pub formatted: bool,
+    /// 由 `itunes:songPart` 得到的段落（如主歌、副歌），按时间顺序排列
+    #[serde(default)]
+    pub sections: Vec<JsSection>,
 }
 
 #[wasm_bindgen]
@@ -40,8 +114,34 @@ pub fn init() {
     // console_error_panic_hook::set_once();
 }
 
+/// `parse_ttml` 的选项，所有字段都可以省略
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ParseOptions {
+    strict: bool,
+    #[serde(flatten)]
+    convert: ConvertOptions,
+}
+
 /// 使用 `ttml_processor` 解析一份 TTML 文件，并返回 AMLL 的数据结构
 ///
+/// `options` 为可选的选项对象，未传入或省略的字段使用默认值:
+///
+/// * `strict` - 为 `true` 时启用严格模式，原本只会产生警告的问题（如音节时间无效、
+///   重复的 `xml:id`、逐字模式下缺少时间的 span）也会返回错误，默认关闭
+/// * `preferredRomanSchemes` - 按优先级排列的罗马音方案名（如 `["hepburn", "kunrei"]`），
+///   同一行存在多个罗马音方案时按此选择，未指定或都不匹配时使用第一个方案
+/// * `preferredTranslationLanguages` - 按优先级排列的翻译语言（BCP-47 标签，如 `["zh-CN", "en"]`），
+///   会按文字和地区降级匹配，例如 `zh-CN` 也能匹配 `zh-Hans` 或 `cmn-Hans-CN`，
+///   未指定时优先选用简体中文，都不匹配时使用第一个翻译
+/// * `duet` - 对唱标识的分配方式，形如 `{ strategy: "explicitMap", overrides: { v3: true } }`，
+///   `strategy` 可以是 `alternate`（默认，按出现顺序交替分配）、`explicitMap`（只按 `overrides`）
+///   或 `allFalse`（全部不是对唱），`overrides` 为演唱者 ID 到对唱标识的映射
+/// * `instrumental` - 只有一个音节的纯音乐提示文本的选项，形如
+///   `{ keywords: ["纯音乐"], extensionMs: 3600000, trackDurationMs: 180000 }`，
+///   `keywords` 为空时不检查文本，`extensionMs` 为结束时间的延长量（默认 1 小时），
+///   给出 `trackDurationMs` 时结束时间不会超过歌曲时长
+///
 /// # Returns
 ///
 /// * `Result<JsValue, JsValue>` -
@@ -53,21 +153,55 @@ pub fn init() {
 /// * `ConvertError::Xml` - 当输入的 TTML 内容不是有效的 XML 格式时
 /// * `ConvertError::InvalidTime` - 当 TTML 中的时间戳格式无效或无法解析时
 /// * `ConvertError::Internal` - 当内部处理过程中出现意外错误时（如上下文丢失）
+/// * `TTML Strict Error` - 严格模式下解析产生了警告，或存在重复的 `xml:id`
+/// * `Options Error` - `options` 不是有效的选项对象
 /// * `Serialization Error` - 序列化数据失败，通常不应该发生
 #[wasm_bindgen]
-pub fn parse_ttml(ttml_content: &str) -> Result<JsValue, JsValue> {
-    let parsing_options = TtmlParsingOptions::default();
+pub fn parse_ttml(ttml_content: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let options: ParseOptions = options_from_js(options, "Options Error")?;
+    let parsed_data = parse_ttml_data(ttml_content, options.strict).map_err(parse_error_to_js)?;
+    parsed_data_to_js(parsed_data, &options.convert)
+}
 
-    let parsed_data = ttml_processor::parse_ttml(ttml_content, &parsing_options)
-        .map_err(|e| JsValue::from_str(&format!("TTML Parse Error: {e:?}")))?;
+fn parse_error_to_js(err: ParseTtmlError) -> JsValue {
+    match err {
+        ParseTtmlError::Convert(e) => JsValue::from_str(&format!("TTML Parse Error: {e:?}")),
+        ParseTtmlError::Warning(warning) => {
+            JsValue::from_str(&format!("TTML Strict Error: {warning}"))
+        }
+    }
+}
+
+/// 读取可选的 JS 选项对象，未传入时使用默认值
+fn options_from_js<T: DeserializeOwned + Default>(
+    value: JsValue,
+    error_label: &str,
+) -> Result<T, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(T::default());
+    }
+    serde_wasm_bindgen::from_value(value)
+        .map_err(|e| JsValue::from_str(&format!("{error_label}: {e:?}")))
+}
 
-    let simple_lines = convert_to_amll_lyrics(&parsed_data);
+fn parsed_data_to_js(
+    parsed_data: ParsedSourceData,
+    options: &ConvertOptions,
+) -> Result<JsValue, JsValue> {
+    let simple_lines = convert_to_amll_lyrics(&parsed_data, options);
+    let formatted = parsed_data.detected_formatted_ttml_input.unwrap_or(false);
 
     let metadata: Vec<(String, Vec<String>)> = parsed_data.raw_metadata.into_iter().collect();
 
+    let line_key_map = build_line_key_map(&simple_lines);
+    let sections = build_sections(&parsed_data.lines);
+
     let result = JsTTMLLyric {
         lines: simple_lines,
         metadata,
+        line_key_map,
+        formatted,
+        sections,
     };
 
     let serializer = serde_wasm_bindgen::Serializer::json_compatible();
@@ -77,3 +211,95 @@ pub fn parse_ttml(ttml_content: &str) -> Result<JsValue, JsValue> {
 
     Ok(js_result)
 }
+
+/// 读取一份规范 JSON（见 [`canonical`] 模块），并返回与 `parse_ttml` 相同的 AMLL 数据结构
+///
+/// `options` 与 `parse_ttml` 的选项相同，但不支持 `strict`
+///
+/// # Errors
+/// 会在以下情况下返回错误:
+/// * `Canonical JSON Error` - JSON 无效或版本不受支持
+/// * `Options Error` - `options` 不是有效的选项对象
+/// * `Serialization Error` - 序列化数据失败，通常不应该发生
+#[wasm_bindgen]
+pub fn parse_canonical_json(json: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let parsed_data = from_canonical_json(json)
+        .map_err(|e| JsValue::from_str(&format!("Canonical JSON Error: {e}")))?;
+    let options: ConvertOptions = options_from_js(options, "Options Error")?;
+    parsed_data_to_js(parsed_data, &options)
+}
+
+/// 将 AMLL 的 `TTMLLyric` 对象重新生成为 Apple Music 兼容的 TTML 字符串
+///
+/// 会写出逐字的 `<span>`、背景人声（`x-bg`）、翻译（`x-translation`）、罗马音、
+/// 演唱者（`ttm:agent`）以及元数据中的词曲作者等信息
+///
+/// `format` 为 `true` 时输出带缩进的 TTML，未指定时沿用解析时检测到的格式，
+/// 即输入为格式化的 TTML 时重新生成的 TTML 也会带有缩进
+///
+/// `use_apple_format_rules` 为 `true` 时遵循 Apple Music 的格式规则，
+/// 例如将翻译写入 `<head>` 而不是内联，默认关闭
+///
+/// `translation_language` 为翻译的语言代码，未指定时不标注语言
+///
+/// # Errors
+/// 会在以下情况下返回错误:
+/// * `Deserialization Error` - 传入的对象不是有效的 `TTMLLyric`
+/// * `TTML Generate Error` - 生成 XML 时发生错误
+#[wasm_bindgen]
+pub fn generate_ttml(
+    lyric: JsValue,
+    format: Option<bool>,
+    use_apple_format_rules: Option<bool>,
+    translation_language: Option<String>,
+) -> Result<String, JsValue> {
+    let lyric: JsTTMLLyric = serde_wasm_bindgen::from_value(lyric)
+        .map_err(|e| JsValue::from_str(&format!("Deserialization Error: {e:?}")))?;
+
+    let options = GenerateOptions {
+        format: format.unwrap_or(lyric.formatted),
+        use_apple_format_rules: use_apple_format_rules.unwrap_or(false),
+        translation_language,
+    };
+
+    generate_ttml_from_lines(&lyric.lines, &lyric.metadata, &options)
+        .map_err(|e| JsValue::from_str(&format!("TTML Generate Error: {e:?}")))
+}
+
+/// 将一份 TTML 歌词导出为其它歌词格式
+///
+/// `format` 为导出格式的名称，不区分大小写，目前支持 `lrc`、`enhancedLrc`、`qrc`、
+/// `qrcTranslation`、`lys`、`eslrc`、`srt`、`ass`、`assKaraoke` 和 `canonicalJson`
+///
+/// # Errors
+/// 会在以下情况下返回错误:
+/// * `Unsupported Format` - 不支持指定的导出格式
+/// * `TTML Parse Error` - 解析 TTML 失败
+#[wasm_bindgen]
+pub fn export_lyrics(ttml_content: &str, format: &str) -> Result<String, JsValue> {
+    let format = LyricExportFormat::from_name(format)
+        .ok_or_else(|| JsValue::from_str(&format!("Unsupported Format: {format}")))?;
+    export_ttml(ttml_content, format)
+        .map_err(|e| JsValue::from_str(&format!("TTML Parse Error: {e:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options_from_one_object() {
+        let options: ParseOptions = serde_json::from_str(
+            r#"{"strict": true, "preferredRomanSchemes": ["kunrei"], "instrumental": {"extensionMs": 5000}}"#,
+        )
+        .unwrap();
+        assert!(options.strict);
+        assert_eq!(options.convert.preferred_roman_schemes, ["kunrei"]);
+        assert!(options.convert.preferred_translation_languages.is_empty());
+        assert_eq!(options.convert.instrumental.extension_ms, 5000);
+
+        let defaults: ParseOptions = serde_json::from_str("{}").unwrap();
+        assert!(!defaults.strict);
+        assert!(defaults.convert.preferred_roman_schemes.is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+//! 自动拆分过长的歌词行
+//!
+//! 一些歌词把很长的一段唱词放在同一行中，在窄窗口里会折成好几行，很难阅读。
+//! 这里把字数或音节数超过上限的行在空格或标点处拆开，优先选择靠近中间的标点，
+//! 拆出的部分仍然超过上限时继续拆分。
+//!
+//! 逐字歌词按音节时间确定新行的边界；逐行歌词没有音节时间，按字数比例插值。
+//! 主歌词之外的背景人声轨道放到其开始时间所在的新行中，翻译和罗马音无法对应拆分，
+//! 保留在第一个新行上
+
+use std::ops::Range;
+
+use lyrics_helper_core::{AnnotatedTrack, ContentType, LyricLine, LyricSyllable, Word};
+
+use crate::punctuation::is_trailing_punctuation;
+
+/// 控制长行拆分的选项
+#[derive(Debug, Clone, Copy)]
+pub struct LineSplitOptions {
+    /// 主歌词的字数（包括空格）超过该值的行会被拆分
+    pub max_chars: usize,
+    /// 主歌词的音节数超过该值的行会被拆分
+    pub max_syllables: usize,
+}
+
+impl Default for LineSplitOptions {
+    fn default() -> Self {
+        Self {
+            max_chars: 40,
+            max_syllables: 24,
+        }
+    }
+}
+
+fn char_count(syllable: &LyricSyllable) -> usize {
+    syllable.text.chars().count() + usize::from(syllable.ends_with_space)
+}
+
+fn exceeds(part: &[LyricSyllable], options: &LineSplitOptions) -> bool {
+    part.len() > options.max_syllables
+        || part.iter().map(char_count).sum::<usize>() > options.max_chars
+}
+
+/// 选出一个拆分点，返回新的部分从哪个音节开始
+///
+/// 落在中间一半范围内的标点优先，否则选择最靠近中间的空格或标点
+fn best_break(part: &[LyricSyllable]) -> Option<usize> {
+    let total: usize = part.iter().map(char_count).sum();
+    let mut before = 0;
+    // （是否不是优先的标点，到中间距离的两倍，下标）
+    let mut best: Option<(bool, usize, usize)> = None;
+    for i in 1..part.len() {
+        let prev = &part[i - 1];
+        before += char_count(prev);
+        let punctuation = prev
+            .text
+            .chars()
+            .last()
+            .is_some_and(is_trailing_punctuation);
+        if !punctuation && !prev.ends_with_space {
+            continue;
+        }
+        let distance = (before * 2).abs_diff(total);
+        let key = (!(punctuation && distance <= total / 2), distance, i);
+        if best.is_none_or(|best| key < best) {
+            best = Some(key);
+        }
+    }
+    best.map(|(_, _, i)| i)
+}
+
+fn split_range(
+    syllables: &[LyricSyllable],
+    range: Range<usize>,
+    options: &LineSplitOptions,
+    starts: &mut Vec<usize>,
+) {
+    let part = &syllables[range.clone()];
+    if !exceeds(part, options) {
+        return;
+    }
+    let Some(at) = best_break(part).map(|i| range.start + i) else {
+        return;
+    };
+    split_range(syllables, range.start..at, options, starts);
+    starts.push(at);
+    split_range(syllables, at..range.end, options, starts);
+}
+
+/// 把逐行歌词的唯一音节在空格和标点处切成若干块，按字数比例分配时间
+fn chunk_line_timed(syllable: &LyricSyllable, start_ms: u64, end_ms: u64) -> Vec<LyricSyllable> {
+    let mut chunks: Vec<LyricSyllable> = Vec::new();
+    let mut current = String::new();
+    // 当前块已经以标点结尾，遇到下一个非标点字符时结束
+    let mut after_punctuation = false;
+    for c in syllable.text.chars() {
+        if c.is_whitespace() {
+            if !current.is_empty() {
+                chunks.push(LyricSyllable {
+                    text: std::mem::take(&mut current),
+                    ends_with_space: true,
+                    ..Default::default()
+                });
+            }
+            after_punctuation = false;
+        } else if is_trailing_punctuation(c) {
+            current.push(c);
+            after_punctuation = true;
+        } else {
+            if after_punctuation {
+                chunks.push(LyricSyllable {
+                    text: std::mem::take(&mut current),
+                    ..Default::default()
+                });
+                after_punctuation = false;
+            }
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(LyricSyllable {
+            text: current,
+            ..Default::default()
+        });
+    }
+    if let Some(last) = chunks.last_mut() {
+        last.ends_with_space = syllable.ends_with_space;
+    }
+
+    let total = chunks.iter().map(char_count).sum::<usize>().max(1) as u64;
+    let span = end_ms.saturating_sub(start_ms);
+    let mut before = 0;
+    for chunk in &mut chunks {
+        chunk.start_ms = start_ms + span * before / total;
+        before += char_count(chunk) as u64;
+        chunk.end_ms = start_ms + span * before / total;
+    }
+    chunks
+}
+
+/// 把逐行歌词的若干块重新合并为一个音节
+fn join_chunks(chunks: &[LyricSyllable]) -> LyricSyllable {
+    let mut text = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        text.push_str(&chunk.text);
+        if chunk.ends_with_space && i + 1 < chunks.len() {
+            text.push(' ');
+        }
+    }
+    let start_ms = chunks.first().map_or(0, |chunk| chunk.start_ms);
+    let end_ms = chunks.last().map_or(0, |chunk| chunk.end_ms);
+    LyricSyllable {
+        text,
+        start_ms,
+        end_ms,
+        duration_ms: Some(end_ms - start_ms),
+        ends_with_space: chunks.last().is_some_and(|chunk| chunk.ends_with_space),
+    }
+}
+
+/// 按原来的单词重新分组，完整保留下来的单词保留其振假名
+fn regroup_words(original: &[Word], word_of: &[usize], syllables: &[LyricSyllable]) -> Vec<Word> {
+    let mut words: Vec<Word> = Vec::new();
+    let mut current_word = None;
+    for (&word_index, syllable) in word_of.iter().zip(syllables) {
+        if current_word != Some(word_index) {
+            words.push(Word::default());
+            current_word = Some(word_index);
+        }
+        if let Some(word) = words.last_mut() {
+            word.syllables.push(syllable.clone());
+        }
+    }
+    let mut offset = 0;
+    for word in &mut words {
+        let word_index = word_of[offset];
+        offset += word.syllables.len();
+        if word.syllables.len() == original[word_index].syllables.len() {
+            word.furigana.clone_from(&original[word_index].furigana);
+        }
+    }
+    words
+}
+
+/// 展开主歌词的音节，返回每个音节所属的单词下标、音节列表以及是否为逐行歌词
+///
+/// 逐行歌词的唯一音节会先被切成若干块
+fn flatten_main(line: &LyricLine, main: &AnnotatedTrack) -> (Vec<usize>, Vec<LyricSyllable>, bool) {
+    let mut word_of = Vec::new();
+    let mut syllables = Vec::new();
+    for (word_index, word) in main.content.words.iter().enumerate() {
+        for syllable in &word.syllables {
+            word_of.push(word_index);
+            syllables.push(syllable.clone());
+        }
+    }
+    let [syllable] = syllables.as_slice() else {
+        return (word_of, syllables, false);
+    };
+    let (start_ms, end_ms) = if syllable.end_ms > syllable.start_ms {
+        (syllable.start_ms, syllable.end_ms)
+    } else {
+        (line.start_ms, line.end_ms)
+    };
+    let chunks = chunk_line_timed(syllable, start_ms, end_ms);
+    (vec![0; chunks.len()], chunks, true)
+}
+
+/// 在选出的音节处把一行拆成若干行
+///
+/// `pick_starts` 收到主歌词展开后的音节（逐行歌词已被切成若干块），按顺序返回除第一部分外
+/// 每一部分开始的音节下标，没有返回任何下标时不拆分
+pub(crate) fn split_line_with(
+    line: &LyricLine,
+    pick_starts: impl FnOnce(&[LyricSyllable]) -> Vec<usize>,
+) -> Option<Vec<LyricLine>> {
+    let main_index = line
+        .tracks
+        .iter()
+        .position(|track| track.content_type == ContentType::Main)?;
+    let main = &line.tracks[main_index];
+
+    let (word_of, syllables, line_timed) = flatten_main(line, main);
+
+    let mut starts = vec![0];
+    starts.extend(pick_starts(&syllables));
+    if starts.len() < 2 {
+        return None;
+    }
+    let ranges: Vec<Range<usize>> = starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&syllables.len()]))
+        .map(|(&start, &end)| start..end)
+        .collect();
+    let last_part = ranges.len() - 1;
+    let part_starts: Vec<u64> = ranges
+        .iter()
+        .enumerate()
+        .map(|(k, range)| {
+            if k == 0 {
+                line.start_ms
+            } else {
+                syllables[range.start].start_ms
+            }
+        })
+        .collect();
+    // 其它内容轨道放到其开始时间所在的新行中
+    let owner_of = |track: &AnnotatedTrack| {
+        track.content.syllables().next().map_or(0, |first| {
+            part_starts
+                .iter()
+                .rposition(|&start| start <= first.start_ms)
+                .unwrap_or(0)
+        })
+    };
+
+    let parts = ranges
+        .into_iter()
+        .enumerate()
+        .map(|(k, range)| {
+            let mut part = syllables[range.clone()].to_vec();
+            if k < last_part
+                && let Some(last) = part.last_mut()
+            {
+                last.ends_with_space = false;
+            }
+            let end_ms = if k == last_part {
+                line.end_ms
+            } else {
+                part.last().map_or(line.end_ms, |syl| syl.end_ms)
+            };
+            let words = if line_timed {
+                vec![Word {
+                    syllables: vec![join_chunks(&part)],
+                    furigana: None,
+                }]
+            } else {
+                regroup_words(&main.content.words, &word_of[range], &part)
+            };
+
+            let tracks = line
+                .tracks
+                .iter()
+                .enumerate()
+                .filter_map(|(track_index, track)| {
+                    if track_index == main_index {
+                        let mut main_part = track.clone();
+                        main_part.content.words.clone_from(&words);
+                        if k > 0 {
+                            main_part.translations.clear();
+                            main_part.romanizations.clear();
+                        }
+                        Some(main_part)
+                    } else {
+                        (owner_of(track) == k).then(|| track.clone())
+                    }
+                })
+                .collect();
+
+            LyricLine {
+                tracks,
+                start_ms: part_starts[k],
+                end_ms,
+                agent: line.agent.clone(),
+                song_part: line.song_part.clone(),
+                itunes_key: if k == 0 {
+                    line.itunes_key.clone()
+                } else {
+                    None
+                },
+            }
+        })
+        .collect();
+    Some(parts)
+}
+
+/// 拆分所有过长的歌词行，返回被拆分的原始行数
+pub fn split_long_lines(lines: &mut Vec<LyricLine>, options: &LineSplitOptions) -> usize {
+    let mut split = 0;
+    let mut result = Vec::with_capacity(lines.len());
+    for line in lines.drain(..) {
+        let parts = split_line_with(&line, |syllables| {
+            let mut starts = Vec::new();
+            split_range(syllables, 0..syllables.len(), options, &mut starts);
+            starts
+        });
+        match parts {
+            Some(parts) => {
+                split += 1;
+                result.extend(parts);
+            }
+            None => result.push(line),
+        }
+    }
+    *lines = result;
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse_lines;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:05.000"><span begin="00:01.000" end="00:01.500">one</span> <span begin="00:01.500" end="00:02.000">two,</span> <span begin="00:02.000" end="00:02.500">three</span> <span begin="00:02.500" end="00:03.000">four</span> <span begin="00:03.000" end="00:04.000">five</span> <span begin="00:04.000" end="00:05.000">six</span><span ttm:role="x-translation">一二三四五六</span></p><p begin="00:06.000" end="00:08.000">我们一起走吧，去看看远方的海 好吗</p><p begin="00:09.000" end="00:10.000">short</p></div></body></tt>"#;
+
+    fn parts(lines: &[LyricLine]) -> Vec<(String, u64, u64)> {
+        lines
+            .iter()
+            .map(|line| {
+                (
+                    line.main_text().unwrap_or_default(),
+                    line.start_ms,
+                    line.end_ms,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_long_lines() {
+        let mut lines = parse_lines(TTML);
+        let options = LineSplitOptions {
+            max_chars: 16,
+            max_syllables: 24,
+        };
+        assert_eq!(split_long_lines(&mut lines, &options), 2);
+
+        assert_eq!(
+            parts(&lines),
+            vec![
+                ("one two,".to_string(), 1000, 2000),
+                ("three four".to_string(), 2000, 3000),
+                ("five six".to_string(), 3000, 5000),
+                ("我们一起走吧，".to_string(), 6000, 6823),
+                ("去看看远方的海 好吗".to_string(), 6823, 8000),
+                ("short".to_string(), 9000, 10000),
+            ]
+        );
+        let translations: Vec<_> = lines[..3]
+            .iter()
+            .map(|line| line.main_track().unwrap().translations.len())
+            .collect();
+        assert_eq!(translations, vec![1, 0, 0]);
+    }
+}
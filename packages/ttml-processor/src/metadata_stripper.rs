@@ -0,0 +1,228 @@
+//! 移除歌词开头和结尾的制作信息行
+//!
+//! 来自各平台的歌词常在开头写上“作词：”“作曲：”，在结尾写上“Produced by”等制作信息，
+//! 这些行显示在歌词中会很突兀。这里按 [`MetadataStripperOptions`] 在开头和结尾的扫描范围内
+//! 查找匹配关键词或正则表达式的连续多行并移除。开头的匹配行之前可以有不匹配的行（如标题），
+//! 这些行会一同被移除，结尾同理。开头和结尾各自最多扫描一半的行。
+//!
+//! 关键词匹配行首，正则表达式匹配行内任意位置，是否区分大小写由选项中的标志控制
+
+use lyrics_helper_core::{ConvertError, LyricLine, MetadataStripperFlags, MetadataStripperOptions};
+use regex::{Regex, RegexBuilder};
+
+/// 选项中没有给出关键词时使用的默认关键词
+pub const DEFAULT_KEYWORDS: &[&str] = &[
+    "作词",
+    "作詞",
+    "作曲",
+    "编曲",
+    "編曲",
+    "词",
+    "詞",
+    "曲",
+    "演唱",
+    "歌手",
+    "原唱",
+    "翻唱",
+    "制作人",
+    "製作人",
+    "监制",
+    "監製",
+    "混音",
+    "母带",
+    "录音",
+    "和声",
+    "吉他",
+    "贝斯",
+    "鼓",
+    "出品",
+    "发行",
+    "OP",
+    "SP",
+    "Lyrics by",
+    "Composed by",
+    "Arranged by",
+    "Produced by",
+    "Mixed by",
+    "Mastered by",
+    "Recorded by",
+];
+
+struct Matcher {
+    keywords: Vec<String>,
+    keyword_case_sensitive: bool,
+    regexes: Vec<Regex>,
+}
+
+impl Matcher {
+    fn new(options: &MetadataStripperOptions) -> Result<Self, ConvertError> {
+        let keyword_case_sensitive = options
+            .flags
+            .contains(MetadataStripperFlags::KEYWORD_CASE_SENSITIVE);
+        let keywords: Vec<String> = if options.keywords.is_empty() {
+            DEFAULT_KEYWORDS.iter().map(ToString::to_string).collect()
+        } else {
+            options.keywords.clone()
+        };
+        let keywords = keywords
+            .into_iter()
+            .map(|keyword| {
+                let keyword = keyword.trim().to_string();
+                if keyword_case_sensitive {
+                    keyword
+                } else {
+                    keyword.to_lowercase()
+                }
+            })
+            .filter(|keyword| !keyword.is_empty())
+            .collect();
+
+        let regexes = if options
+            .flags
+            .contains(MetadataStripperFlags::ENABLE_REGEX_STRIPPING)
+        {
+            let case_sensitive = options
+                .flags
+                .contains(MetadataStripperFlags::REGEX_CASE_SENSITIVE);
+            options
+                .regex_patterns
+                .iter()
+                .map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(!case_sensitive)
+                        .build()
+                        .map_err(|e| {
+                            ConvertError::Internal(format!("无效的正则表达式 `{pattern}`: {e}"))
+                        })
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            keywords,
+            keyword_case_sensitive,
+            regexes,
+        })
+    }
+
+    fn matches(&self, line: &LyricLine) -> bool {
+        let Some(text) = line.main_text() else {
+            return false;
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return false;
+        }
+        let folded = if self.keyword_case_sensitive {
+            text.to_string()
+        } else {
+            text.to_lowercase()
+        };
+        self.keywords.iter().any(|keyword| {
+            folded.strip_prefix(keyword.as_str()).is_some_and(|rest| {
+                // 关键词之后需要是分隔符，避免“曲”匹配到以“曲”开头的歌词
+                let rest = rest.trim_start();
+                keyword.contains(' ') || rest.starts_with([':', '：', '/', '／', '-', '='])
+            })
+        }) || self.regexes.iter().any(|regex| regex.is_match(text))
+    }
+}
+
+/// 移除开头和结尾的制作信息行，返回移除的行数
+///
+/// # Errors
+///
+/// 选项中的正则表达式无效时返回 `ConvertError::Internal`
+pub fn strip_metadata_lines(
+    lines: &mut Vec<LyricLine>,
+    options: &MetadataStripperOptions,
+) -> Result<usize, ConvertError> {
+    if !options.flags.contains(MetadataStripperFlags::ENABLED) || lines.is_empty() {
+        return Ok(0);
+    }
+    let matcher = Matcher::new(options)?;
+    let total = lines.len();
+
+    // 开头和结尾各自最多扫描一半的行，避免短歌词被整体移除
+    let header_limit = options
+        .header_scan_limit
+        .calculate(total)
+        .min(total.div_ceil(2));
+    let mut header_end = 0;
+    for (index, line) in lines[..header_limit].iter().enumerate() {
+        if matcher.matches(line) {
+            header_end = index + 1;
+        } else if header_end > 0 {
+            break;
+        }
+    }
+
+    let footer_limit = options.footer_scan_limit.calculate(total).min(total / 2);
+    let footer_floor = (total - footer_limit).max(header_end);
+    let mut footer_start = total;
+    for (offset, line) in lines[footer_floor..].iter().enumerate().rev() {
+        if matcher.matches(line) {
+            footer_start = footer_floor + offset;
+        } else if footer_start < total {
+            break;
+        }
+    }
+
+    lines.truncate(footer_start);
+    lines.drain(..header_end);
+    Ok(total - lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse_lines;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:00.000" end="00:01.000">作词 : 某人</p><p begin="00:01.000" end="00:02.000">編曲：某人</p><p begin="00:02.000" end="00:03.000">曲终人散</p><p begin="00:03.000" end="00:04.000">second line</p><p begin="00:04.000" end="00:05.000">PRODUCED BY someone</p><p begin="00:05.000" end="00:06.000">Mixed by someone</p></div></body></tt>"#;
+
+    fn texts(lines: &[LyricLine]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| line.main_text().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn test_strip_default_keywords() {
+        let mut lines = parse_lines(TTML);
+        let removed =
+            strip_metadata_lines(&mut lines, &MetadataStripperOptions::default()).unwrap();
+        assert_eq!(removed, 4);
+        assert_eq!(texts(&lines), vec!["曲终人散", "second line"]);
+    }
+
+    #[test]
+    fn test_keyword_case_sensitive() {
+        let mut lines = parse_lines(TTML);
+        let options = MetadataStripperOptions {
+            flags: MetadataStripperFlags::ENABLED | MetadataStripperFlags::KEYWORD_CASE_SENSITIVE,
+            keywords: vec!["Produced by".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(strip_metadata_lines(&mut lines, &options).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_regex() {
+        let mut lines = parse_lines(TTML);
+        let options = MetadataStripperOptions {
+            keywords: vec!["none".to_string()],
+            regex_patterns: vec![r"^mixed\s+by".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(strip_metadata_lines(&mut lines, &options).unwrap(), 1);
+
+        let invalid = MetadataStripperOptions {
+            regex_patterns: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(strip_metadata_lines(&mut lines, &invalid).is_err());
+    }
+}
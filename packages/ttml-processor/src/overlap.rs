@@ -0,0 +1,266 @@
+//! 修正时间重叠的音节和歌词行
+//!
+//! 手工制作的 TTML 中常有前一个音节还没结束、后一个音节就已开始的情况。
+//! 这里按轨道检查相邻的音节，并按所选策略消除重叠，每一处修改都会记录下来，
+//! 方便在界面中展示或让用户确认。
+//!
+//! 歌词行只在同一演唱者的相邻行之间修正，不同演唱者的行本就可能同时演唱。
+//! 修正歌词行时不会越过行内音节的时间，音节本身跨行重叠时会保持原样
+
+use lyrics_helper_core::{LyricLine, LyricSyllable};
+use serde::{Deserialize, Serialize};
+
+/// 消除重叠的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlapStrategy {
+    /// 将前一项的结束时间截断到后一项的开始时间
+    #[default]
+    Clamp,
+    /// 按两项各自的时长比例划分重叠的部分
+    Split,
+}
+
+/// 一段时间范围，单位为毫秒
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeRange {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// 一处被修改的时间
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum OverlapAdjustment {
+    /// `syllable_index` 为音节在轨道内所有单词中的下标
+    #[serde(rename_all = "camelCase")]
+    Syllable {
+        line_index: usize,
+        track_index: usize,
+        syllable_index: usize,
+        before: TimeRange,
+        after: TimeRange,
+    },
+    #[serde(rename_all = "camelCase")]
+    Line {
+        line_index: usize,
+        before: TimeRange,
+        after: TimeRange,
+    },
+}
+
+const fn range(start_ms: u64, end_ms: u64) -> TimeRange {
+    TimeRange { start_ms, end_ms }
+}
+
+/// 计算两段重叠的时间之间新的分界点，两段时间的顺序颠倒时返回 `None`
+///
+/// `prev` 和 `next` 为两项的原始时间，`min` 和 `max` 为分界点允许的范围
+fn boundary(
+    prev: TimeRange,
+    next: TimeRange,
+    strategy: OverlapStrategy,
+    min: u64,
+    max: u64,
+) -> Option<u64> {
+    if next.start_ms <= prev.start_ms || min > max {
+        return None;
+    }
+    let point = match strategy {
+        OverlapStrategy::Clamp => next.start_ms,
+        OverlapStrategy::Split => {
+            let prev_duration = u128::from(prev.end_ms - prev.start_ms);
+            let next_duration = u128::from(next.end_ms.saturating_sub(next.start_ms));
+            let overlap = u128::from(prev.end_ms - next.start_ms);
+            let share = overlap * prev_duration / (prev_duration + next_duration).max(1);
+            // `share` 不会超过 `overlap`，而 `overlap` 来自 `u64` 的差值
+            next.start_ms + u64::try_from(share).unwrap_or(0)
+        }
+    };
+    Some(point.clamp(min, max))
+}
+
+fn resolve_syllables(
+    line_index: usize,
+    track_index: usize,
+    syllables: &mut [&mut LyricSyllable],
+    strategy: OverlapStrategy,
+    report: &mut Vec<OverlapAdjustment>,
+) {
+    for i in 1..syllables.len() {
+        let prev = range(syllables[i - 1].start_ms, syllables[i - 1].end_ms);
+        let next = range(syllables[i].start_ms, syllables[i].end_ms);
+        if prev.end_ms <= next.start_ms {
+            continue;
+        }
+        let Some(point) = boundary(prev, next, strategy, next.start_ms, prev.end_ms) else {
+            continue;
+        };
+
+        syllables[i - 1].end_ms = point;
+        syllables[i].start_ms = point;
+        syllables[i].end_ms = syllables[i].end_ms.max(point);
+
+        for (syllable_index, before) in [(i - 1, prev), (i, next)] {
+            let syllable = &syllables[syllable_index];
+            let after = range(syllable.start_ms, syllable.end_ms);
+            if after != before {
+                report.push(OverlapAdjustment::Syllable {
+                    line_index,
+                    track_index,
+                    syllable_index,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+}
+
+/// 行内所有音节的时间范围
+fn syllable_span(line: &LyricLine) -> Option<TimeRange> {
+    let mut syllables = line
+        .tracks
+        .iter()
+        .flat_map(|track| track.content.syllables())
+        .filter(|syl| syl.end_ms > syl.start_ms);
+    let first = syllables.next()?;
+    Some(
+        syllables.fold(range(first.start_ms, first.end_ms), |span, syl| {
+            range(span.start_ms.min(syl.start_ms), span.end_ms.max(syl.end_ms))
+        }),
+    )
+}
+
+fn resolve_lines(
+    lines: &mut [LyricLine],
+    strategy: OverlapStrategy,
+    report: &mut Vec<OverlapAdjustment>,
+) {
+    for i in 0..lines.len() {
+        let Some(j) = (i + 1..lines.len()).find(|&j| lines[j].agent == lines[i].agent) else {
+            continue;
+        };
+        let prev = range(lines[i].start_ms, lines[i].end_ms);
+        let next = range(lines[j].start_ms, lines[j].end_ms);
+        if prev.end_ms <= next.start_ms {
+            continue;
+        }
+        let min = syllable_span(&lines[i]).map_or(next.start_ms, |span| span.end_ms);
+        let max = syllable_span(&lines[j]).map_or(prev.end_ms, |span| span.start_ms);
+        let Some(point) = boundary(
+            prev,
+            next,
+            strategy,
+            min.max(next.start_ms),
+            max.min(prev.end_ms),
+        ) else {
+            continue;
+        };
+
+        lines[i].end_ms = point;
+        lines[j].start_ms = point;
+        for (line_index, before) in [(i, prev), (j, next)] {
+            let after = range(lines[line_index].start_ms, lines[line_index].end_ms);
+            if after != before {
+                report.push(OverlapAdjustment::Line {
+                    line_index,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+}
+
+/// 消除音节和歌词行之间的时间重叠，返回所有修改过的时间
+pub fn resolve_overlaps(
+    lines: &mut [LyricLine],
+    strategy: OverlapStrategy,
+) -> Vec<OverlapAdjustment> {
+    let mut report = Vec::new();
+    for (line_index, line) in lines.iter_mut().enumerate() {
+        for (track_index, track) in line.tracks.iter_mut().enumerate() {
+            let mut syllables: Vec<&mut LyricSyllable> = track
+                .content
+                .words
+                .iter_mut()
+                .flat_map(|word| &mut word.syllables)
+                .collect();
+            resolve_syllables(
+                line_index,
+                track_index,
+                &mut syllables,
+                strategy,
+                &mut report,
+            );
+        }
+    }
+    resolve_lines(lines, strategy, &mut report);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse_lines;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.500" ttm:agent="v1"><span begin="00:01.000" end="00:01.600">a</span><span begin="00:01.400" end="00:01.800">b</span><span begin="00:01.800" end="00:02.000">c</span></p><p begin="00:02.200" end="00:03.000" ttm:agent="v1"><span begin="00:02.200" end="00:03.000">d</span></p><p begin="00:02.100" end="00:03.000" ttm:agent="v2"><span begin="00:02.100" end="00:03.000">e</span></p></div></body></tt>"#;
+
+    fn find_line(lines: &[LyricLine], text: &str) -> usize {
+        lines
+            .iter()
+            .position(|line| line.main_text().as_deref() == Some(text))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_clamp() {
+        let mut lines = parse_lines(TTML);
+        let report = resolve_overlaps(&mut lines, OverlapStrategy::Clamp);
+        let abc = find_line(&lines, "abc");
+        let d = find_line(&lines, "d");
+
+        let times: Vec<_> = lines[abc].tracks[0]
+            .content
+            .syllables()
+            .map(|syl| (syl.start_ms, syl.end_ms))
+            .collect();
+        assert_eq!(times, vec![(1000, 1400), (1400, 1800), (1800, 2000)]);
+        assert_eq!((lines[abc].end_ms, lines[d].start_ms), (2200, 2200));
+        assert_eq!(
+            report,
+            vec![
+                OverlapAdjustment::Syllable {
+                    line_index: abc,
+                    track_index: 0,
+                    syllable_index: 0,
+                    before: range(1000, 1600),
+                    after: range(1000, 1400),
+                },
+                OverlapAdjustment::Line {
+                    line_index: abc,
+                    before: range(1000, 2500),
+                    after: range(1000, 2200),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let mut lines = parse_lines(TTML);
+        let report = resolve_overlaps(&mut lines, OverlapStrategy::Split);
+        let abc = find_line(&lines, "abc");
+
+        // 重叠的 200ms 按 600:400 划分
+        let times: Vec<_> = lines[abc].tracks[0]
+            .content
+            .syllables()
+            .map(|syl| (syl.start_ms, syl.end_ms))
+            .collect();
+        assert_eq!(times, vec![(1000, 1520), (1520, 1800), (1800, 2000)]);
+        assert_eq!(report.len(), 3);
+    }
+}
@@ -0,0 +1,179 @@
+//! 按顺序执行的歌词处理流水线
+//!
+//! 前端的设置面板给出一组有序的处理步骤及各自的选项，这里按给定的顺序依次执行，
+//! 同样的输入和步骤总会得到同样的结果。
+//!
+//! 没有转换步骤时输出重新生成的 TTML，转换步骤会把结果导出为指定的格式，因此只能是最后一步
+
+use lyrics_helper_core::{
+    ConvertError, MetadataStore, MetadataStripperOptions, ParsedSourceData,
+    SyllableSmoothingOptions, TtmlGenerationOptions, TtmlParsingOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent_recognizer::{AgentRecognizerOptions, output_metadata, recognize_agents},
+    chorus::{ChorusOptions, normalize_chorus},
+    export::{LyricExportFormat, export_lyrics},
+    gap_filling::{GapFillingOptions, fill_gaps},
+    metadata_stripper::strip_metadata_lines,
+    rescale::{TimelineScale, rescale_lines},
+    smoothing::apply_smoothing,
+};
+
+/// 流水线中的一个处理步骤
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+pub enum ProcessingStep {
+    /// 移除开头和结尾的制作信息行，见 [`crate::metadata_stripper`]
+    MetadataStrip {
+        #[serde(default)]
+        options: MetadataStripperOptions,
+    },
+    /// 识别行首的演唱者标记，见 [`crate::agent_recognizer`]
+    AgentRecognize {
+        #[serde(default)]
+        options: AgentRecognizerOptions,
+    },
+    /// 把合唱的行归入合唱演唱者，见 [`crate::chorus`]
+    ChorusNormalize {
+        #[serde(default)]
+        options: ChorusOptions,
+    },
+    /// 音节时长平滑，见 [`crate::smoothing`]
+    Smoothing {
+        #[serde(default)]
+        options: SyllableSmoothingOptions,
+    },
+    /// 填补音节之间的短暂间隔，见 [`crate::gap_filling`]
+    GapFill {
+        #[serde(default)]
+        options: GapFillingOptions,
+    },
+    /// 整体平移所有时间，负数表示提前
+    #[serde(rename_all = "camelCase")]
+    Offset { offset_ms: i64 },
+    /// 导出为其它歌词格式，只能作为最后一步
+    Conversion { format: LyricExportFormat },
+}
+
+/// 根据解析得到的歌词重新生成 TTML，输入为格式化的 TTML 时输出也会带有缩进
+pub(crate) fn regenerate_ttml(data: &ParsedSourceData) -> Result<String, ConvertError> {
+    let mut metadata_store = MetadataStore::new();
+    metadata_store.load_from_raw(&output_metadata(data));
+    let options = TtmlGenerationOptions {
+        format: data.detected_formatted_ttml_input.unwrap_or(false),
+        ..Default::default()
+    };
+    ttml_processor::generate_ttml(&data.lines, &metadata_store, &data.agents, &options)
+}
+
+/// 按顺序对一份 TTML 歌词执行所有步骤
+///
+/// # Errors
+///
+/// TTML 解析或生成失败，元数据清理或演唱者识别的正则表达式无效，或转换步骤不是最后一步时返回 `ConvertError`
+pub fn process_ttml(ttml_content: &str, steps: &[ProcessingStep]) -> Result<String, ConvertError> {
+    let mut data = ttml_processor::parse_ttml(ttml_content, &TtmlParsingOptions::default())?;
+    for (index, step) in steps.iter().enumerate() {
+        match step {
+            ProcessingStep::MetadataStrip { options } => {
+                strip_metadata_lines(&mut data.lines, options)?;
+            }
+            ProcessingStep::AgentRecognize { options } => {
+                recognize_agents(&mut data, options)?;
+            }
+            ProcessingStep::ChorusNormalize { options } => {
+                normalize_chorus(&mut data, options);
+            }
+            ProcessingStep::Smoothing { options } => apply_smoothing(&mut data.lines, options),
+            ProcessingStep::GapFill { options } => {
+                fill_gaps(&mut data.lines, options);
+            }
+            ProcessingStep::Offset { offset_ms } => {
+                rescale_lines(&mut data.lines, &TimelineScale::offset(*offset_ms));
+            }
+            ProcessingStep::Conversion { format } => {
+                if index + 1 != steps.len() {
+                    return Err(ConvertError::Internal("转换步骤只能是最后一步".to_string()));
+                }
+                return Ok(export_lyrics(&data, *format));
+            }
+        }
+    }
+    regenerate_ttml(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:01.450">A：</span><span begin="00:01.450" end="00:01.500">a</span><span begin="00:01.550" end="00:02.000">b</span></p><p begin="00:03.000" end="00:04.000"><span begin="00:03.000" end="00:04.000">c</span></p></div></body></tt>"#;
+
+    fn steps(json: &str) -> Vec<ProcessingStep> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_process_ttml() {
+        let steps = steps(
+            r#"[
+                {"step": "agent-recognize"},
+                {"step": "gap-fill", "options": {"maxGapMs": 80}},
+                {"step": "offset", "offsetMs": -500},
+                {"step": "conversion", "format": "enhancedLrc"}
+            ]"#,
+        );
+        assert_eq!(
+            process_ttml(TTML, &steps).unwrap(),
+            "[00:00.95]<00:00.95>a<00:01.05>b<00:01.50>\n[00:01.50]\n[00:02.50]<00:02.50>c<00:03.50>\n[00:03.50]\n"
+        );
+    }
+
+    #[test]
+    fn test_conversion_must_be_last() {
+        let steps = steps(
+            r#"[{"step": "conversion", "format": "lrc"}, {"step": "offset", "offsetMs": 10}]"#,
+        );
+        assert!(process_ttml(TTML, &steps).is_err());
+    }
+
+    #[test]
+    fn test_without_conversion() {
+        let ttml = process_ttml(TTML, &steps(r#"[{"step": "offset", "offsetMs": 1000}]"#)).unwrap();
+        let parsed = ttml_processor::parse_ttml(&ttml, &TtmlParsingOptions::default()).unwrap();
+        assert_eq!(parsed.lines[1].start_ms, 4000);
+    }
+
+    #[test]
+    fn test_recognized_agents_are_exported() {
+        let ttml = process_ttml(TTML, &steps(r#"[{"step": "agent-recognize"}]"#)).unwrap();
+        assert!(!ttml.contains(r#"key="agent""#));
+        let parsed = ttml_processor::parse_ttml(&ttml, &TtmlParsingOptions::default()).unwrap();
+        assert_eq!(parsed.agents.agents_by_id["v1"].name.as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn test_kept_markers_are_not_exported() {
+        let recognize = r#"{"step": "agent-recognize", "options": {"keepMarkers": true}}"#;
+        let ttml = process_ttml(TTML, &steps(&format!("[{recognize}]"))).unwrap();
+        assert!(!ttml.contains("agentMarker"));
+
+        let lrc = process_ttml(
+            TTML,
+            &steps(&format!(
+                r#"[{recognize}, {{"step": "conversion", "format": "lrc"}}]"#
+            )),
+        )
+        .unwrap();
+        assert!(!lrc.contains("agentMarker"));
+        let json = process_ttml(
+            TTML,
+            &steps(&format!(
+                r#"[{recognize}, {{"step": "conversion", "format": "canonicalJson"}}]"#
+            )),
+        )
+        .unwrap();
+        assert!(!json.contains("agentMarker"));
+    }
+}
@@ -0,0 +1,150 @@
+//! 将单独成为音节的标点并入前一个音节
+//!
+//! 部分来源会把 `,`、`!`、`…` 等标点单独放在一个（通常时长为零的）音节中，
+//! 逐字动画时标点会单独跳出来，看起来很奇怪。这里把只包含结尾标点的音节
+//! 合并到同一轨道中的前一个音节里。开括号、开引号等属于后一个音节的标点不会被合并
+
+use lyrics_helper_core::{LyricLine, LyricSyllable, LyricTrack};
+
+/// 出现在词语之后的标点
+pub(crate) const fn is_trailing_punctuation(c: char) -> bool {
+    matches!(
+        c,
+        ',' | '.'
+            | '!'
+            | '?'
+            | ';'
+            | ':'
+            | ')'
+            | ']'
+            | '}'
+            | '"'
+            | '\''
+            | '…'
+            | '—'
+            | '~'
+            | '，'
+            | '。'
+            | '！'
+            | '？'
+            | '、'
+            | '；'
+            | '：'
+            | '）'
+            | '」'
+            | '』'
+            | '》'
+            | '〉'
+            | '】'
+            | '”'
+            | '’'
+            | '～'
+            | '・'
+            | '·'
+    )
+}
+
+fn is_punctuation_only(text: &str) -> bool {
+    let trimmed = text.trim();
+    !trimmed.is_empty() && trimmed.chars().all(is_trailing_punctuation)
+}
+
+fn merge_into(target: &mut LyricSyllable, punctuation: &LyricSyllable) {
+    target.text.push_str(punctuation.text.trim());
+    target.end_ms = target.end_ms.max(punctuation.end_ms);
+    target.duration_ms = target
+        .duration_ms
+        .map(|_| target.end_ms.saturating_sub(target.start_ms));
+    target.ends_with_space = punctuation.ends_with_space;
+}
+
+fn merge_track(track: &mut LyricTrack) -> usize {
+    let mut merged = 0;
+    // 最近一个被保留的音节所在的单词和音节下标
+    let mut prev: Option<(usize, usize)> = None;
+    for word_index in 0..track.words.len() {
+        let mut syllable_index = 0;
+        while syllable_index < track.words[word_index].syllables.len() {
+            if let Some((prev_word, prev_syllable)) = prev
+                && is_punctuation_only(&track.words[word_index].syllables[syllable_index].text)
+            {
+                let punctuation = track.words[word_index].syllables.remove(syllable_index);
+                merge_into(
+                    &mut track.words[prev_word].syllables[prev_syllable],
+                    &punctuation,
+                );
+                merged += 1;
+                continue;
+            }
+            prev = Some((word_index, syllable_index));
+            syllable_index += 1;
+        }
+    }
+    track.words.retain(|word| !word.syllables.is_empty());
+    merged
+}
+
+/// 合并所有歌词行中只包含标点的音节，返回被合并的音节数量
+///
+/// 翻译和罗马音轨道同样会被处理
+pub fn merge_punctuation(lines: &mut [LyricLine]) -> usize {
+    let mut merged = 0;
+    for line in lines {
+        for track in &mut line.tracks {
+            merged += merge_track(&mut track.content);
+            for aux in track
+                .translations
+                .iter_mut()
+                .chain(&mut track.romanizations)
+            {
+                merged += merge_track(aux);
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:03.000"><span begin="00:01.000" end="00:01.500">Hello</span><span begin="00:01.500" end="00:01.500">,</span> <span begin="00:01.600" end="00:02.000">world</span><span begin="00:02.000" end="00:02.200">!…</span></p><p begin="00:03.000" end="00:04.000"><span begin="00:03.000" end="00:03.000">「</span><span begin="00:03.000" end="00:04.000">你好</span><span begin="00:04.000" end="00:04.000">」</span></p></div></body></tt>"#;
+
+    fn syllables(line: &LyricLine) -> Vec<(String, u64, u64, bool)> {
+        line.main_track()
+            .unwrap()
+            .content
+            .syllables()
+            .map(|syl| {
+                (
+                    syl.text.clone(),
+                    syl.start_ms,
+                    syl.end_ms,
+                    syl.ends_with_space,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_punctuation() {
+        let mut parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        assert_eq!(merge_punctuation(&mut parsed.lines), 3);
+
+        assert_eq!(
+            syllables(&parsed.lines[0]),
+            vec![
+                ("Hello,".to_string(), 1000, 1500, true),
+                ("world!…".to_string(), 1600, 2200, false),
+            ]
+        );
+        assert_eq!(
+            syllables(&parsed.lines[1]),
+            vec![
+                ("「".to_string(), 3000, 3000, false),
+                ("你好」".to_string(), 3000, 4000, false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! 按两个锚点线性缩放歌词的时间轴
+//!
+//! 同一首歌的重制版、加长版等版本常常只是前奏长度或整体速度不同。
+//! 给出两个时间点在原版和新版中的位置，就可以把所有时间戳按线性关系换算到新版上，
+//! 让一份歌词适配多个音频版本
+
+use lyrics_helper_core::{ConvertError, LyricLine, LyricTrack, TtmlParsingOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::regenerate_ttml;
+
+/// 同一个时间点在原音频和目标音频中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeAnchor {
+    pub source_ms: u64,
+    pub target_ms: u64,
+}
+
+/// 由两个锚点确定的线性时间映射
+#[derive(Debug, Clone, Copy)]
+pub struct TimelineScale {
+    scale: f64,
+    offset: f64,
+}
+
+impl TimelineScale {
+    /// 根据两个锚点构造时间映射，两个锚点在原音频中的位置相同时返回错误
+    ///
+    /// # Errors
+    ///
+    /// 无法由锚点确定映射时返回 `ConvertError::Internal`
+    // 歌词时间远小于 f64 的精度上限
+    #[allow(clippy::cast_precision_loss)]
+    pub fn from_anchors(first: TimeAnchor, second: TimeAnchor) -> Result<Self, ConvertError> {
+        if first.source_ms == second.source_ms {
+            return Err(ConvertError::Internal(
+                "两个锚点在原音频中的时间不能相同".to_string(),
+            ));
+        }
+        let scale = (second.target_ms as f64 - first.target_ms as f64)
+            / (second.source_ms as f64 - first.source_ms as f64);
+        if scale <= 0.0 {
+            return Err(ConvertError::Internal(
+                "锚点的先后顺序在两个音频中必须一致".to_string(),
+            ));
+        }
+        Ok(Self {
+            scale,
+            offset: (first.source_ms as f64).mul_add(-scale, first.target_ms as f64),
+        })
+    }
+
+    /// 把所有时间整体平移 `offset_ms` 毫秒，负数表示提前
+    #[must_use]
+    pub const fn offset(offset_ms: i64) -> Self {
+        Self {
+            scale: 1.0,
+            offset: offset_ms as f64,
+        }
+    }
+
+    /// 换算一个时间点，换算后早于 0 的时间会被限制为 0
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub const fn apply(&self, time_ms: u64) -> u64 {
+        (time_ms as f64)
+            .mul_add(self.scale, self.offset)
+            .round()
+            .max(0.0) as u64
+    }
+}
+
+fn rescale_track(track: &mut LyricTrack, scale: &TimelineScale) {
+    for syllable in track.words.iter_mut().flat_map(|word| &mut word.syllables) {
+        // 行级翻译和罗马音的音节没有时间，保持原样
+        if syllable.start_ms == 0 && syllable.end_ms == 0 {
+            continue;
+        }
+        syllable.start_ms = scale.apply(syllable.start_ms);
+        syllable.end_ms = scale.apply(syllable.end_ms);
+        syllable.duration_ms = syllable
+            .duration_ms
+            .map(|_| syllable.end_ms.saturating_sub(syllable.start_ms));
+    }
+}
+
+/// 换算所有歌词行、音节以及翻译和罗马音中的时间
+pub fn rescale_lines(lines: &mut [LyricLine], scale: &TimelineScale) {
+    for line in lines {
+        line.start_ms = scale.apply(line.start_ms);
+        line.end_ms = scale.apply(line.end_ms);
+        for track in &mut line.tracks {
+            rescale_track(&mut track.content, scale);
+            for aux in track
+                .translations
+                .iter_mut()
+                .chain(&mut track.romanizations)
+            {
+                rescale_track(aux, scale);
+            }
+        }
+    }
+}
+
+/// 按两个锚点缩放一份 TTML 歌词的时间轴，并重新生成 TTML
+///
+/// 输入为格式化的 TTML 时，输出也会带有缩进
+///
+/// # Errors
+///
+/// 解析或生成 TTML 失败，或无法由锚点确定映射时返回 `ConvertError`
+pub fn rescale_ttml(
+    ttml_content: &str,
+    first: TimeAnchor,
+    second: TimeAnchor,
+) -> Result<String, ConvertError> {
+    let scale = TimelineScale::from_anchors(first, second)?;
+    let mut data = ttml_processor::parse_ttml(ttml_content, &TtmlParsingOptions::default())?;
+    rescale_lines(&mut data.lines, &scale);
+    regenerate_ttml(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:10.000" end="00:12.000"><span begin="00:10.000" end="00:11.000">a</span><span begin="00:11.000" end="00:12.000">b</span><span ttm:role="x-translation">甲乙</span></p></div></body></tt>"#;
+
+    fn anchor(source_ms: u64, target_ms: u64) -> TimeAnchor {
+        TimeAnchor {
+            source_ms,
+            target_ms,
+        }
+    }
+
+    #[test]
+    fn test_timeline_scale() {
+        // 新版的前奏长了 5 秒，整体速度慢了 10%
+        let scale =
+            TimelineScale::from_anchors(anchor(0, 5_000), anchor(100_000, 115_000)).unwrap();
+        assert_eq!(scale.apply(0), 5_000);
+        assert_eq!(scale.apply(10_000), 16_000);
+        assert_eq!(scale.apply(100_000), 115_000);
+
+        let shift = TimelineScale::from_anchors(anchor(10_000, 0), anchor(20_000, 10_000)).unwrap();
+        assert_eq!(shift.apply(5_000), 0);
+
+        assert!(TimelineScale::from_anchors(anchor(1, 2), anchor(1, 3)).is_err());
+        assert!(TimelineScale::from_anchors(anchor(0, 10), anchor(10, 0)).is_err());
+    }
+
+    #[test]
+    fn test_rescale_ttml() {
+        let rescaled = rescale_ttml(TTML, anchor(0, 2_000), anchor(10_000, 12_000)).unwrap();
+        let parsed = ttml_processor::parse_ttml(&rescaled, &TtmlParsingOptions::default()).unwrap();
+        let line = &parsed.lines[0];
+        assert_eq!((line.start_ms, line.end_ms), (12_000, 14_000));
+
+        let main = line.main_track().unwrap();
+        let times: Vec<_> = main
+            .content
+            .syllables()
+            .map(|syl| (syl.start_ms, syl.end_ms))
+            .collect();
+        assert_eq!(times, vec![(12_000, 13_000), (13_000, 14_000)]);
+        assert_eq!(main.translations[0].text(), "甲乙");
+    }
+}
@@ -0,0 +1,128 @@
+//! 音节时长的平滑优化
+//!
+//! 逐字歌词的音节时长常常因为手动打轴而忽长忽短。这里把时长和间隔都相近的
+//! 连续音节分为一组，在组内反复将每个音节的时长向相邻音节靠拢，
+//! 同时保持组的开始和结束时间以及音节之间的间隔不变。
+//!
+//! 主歌词轨道和背景人声轨道使用相同的选项分别处理，翻译和罗马音轨道不会被修改
+
+use lyrics_helper_core::{LyricLine, LyricSyllable, SyllableSmoothingOptions};
+
+/// 对所有歌词行的主歌词和背景人声音节进行平滑
+pub fn apply_smoothing(lines: &mut [LyricLine], options: &SyllableSmoothingOptions) {
+    for line in lines {
+        for track in &mut line.tracks {
+            let mut syllables: Vec<&mut LyricSyllable> = track
+                .content
+                .words
+                .iter_mut()
+                .flat_map(|word| &mut word.syllables)
+                .collect();
+            smooth_syllables(&mut syllables, options);
+        }
+    }
+}
+
+const fn duration(syllable: &LyricSyllable) -> u64 {
+    syllable.end_ms.saturating_sub(syllable.start_ms)
+}
+
+fn smooth_syllables(syllables: &mut [&mut LyricSyllable], options: &SyllableSmoothingOptions) {
+    let mut group_start = 0;
+    for i in 1..=syllables.len() {
+        let continues_group = syllables.get(i).is_some_and(|current| {
+            let prev = &syllables[i - 1];
+            duration(current) > 0
+                && duration(prev) > 0
+                && duration(current).abs_diff(duration(prev)) <= options.duration_threshold_ms
+                && current.start_ms >= prev.end_ms
+                && current.start_ms - prev.end_ms <= options.gap_threshold_ms
+        });
+        if !continues_group {
+            smooth_group(&mut syllables[group_start..i], options);
+            group_start = i;
+        }
+    }
+}
+
+// 歌词时间远小于 f64 的精度上限，且平滑后的时长不会为负数
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn smooth_group(group: &mut [&mut LyricSyllable], options: &SyllableSmoothingOptions) {
+    if group.len() < 2 {
+        return;
+    }
+    let factor = options.factor.clamp(0.0, 0.5);
+    let original: Vec<f64> = group.iter().map(|syl| duration(syl) as f64).collect();
+    let total: f64 = original.iter().sum();
+
+    let mut durations = original;
+    for _ in 0..options.smoothing_iterations {
+        durations = (0..durations.len())
+            .map(|i| {
+                let prev = durations[i.saturating_sub(1)];
+                let next = durations[(i + 1).min(durations.len() - 1)];
+                durations[i].mul_add(2.0f64.mul_add(-factor, 1.0), (prev + next) * factor)
+            })
+            .collect();
+    }
+    // 边界上的音节只与一侧相邻，平滑后总时长会有偏差，按比例修正回原来的总时长
+    let scale = total / durations.iter().sum::<f64>();
+
+    let gaps: Vec<u64> = group
+        .windows(2)
+        .map(|pair| pair[1].start_ms - pair[0].end_ms)
+        .collect();
+    let group_start = group[0].start_ms;
+    let group_end = group[group.len() - 1].end_ms;
+    let mut start = group_start;
+    // 已经经过的时长和间隔，用浮点数累计以免舍入误差累积
+    let mut elapsed = 0.0;
+    for (i, syllable) in group.iter_mut().enumerate() {
+        elapsed += durations[i] * scale;
+        syllable.start_ms = start;
+        syllable.end_ms = match gaps.get(i) {
+            Some(&gap) => {
+                let end = (group_start as f64 + elapsed).round() as u64;
+                elapsed += gap as f64;
+                start = end + gap;
+                end
+            }
+            None => group_end,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::track_times;
+    use lyrics_helper_core::{ContentType, TtmlParsingOptions};
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.150"><span begin="00:01.000" end="00:01.200">a</span><span begin="00:01.200" end="00:01.440">b</span><span begin="00:01.440" end="00:01.640">c</span><span begin="00:01.640" end="00:01.880">d</span><span ttm:role="x-bg"><span begin="00:01.900" end="00:02.000">(e</span><span begin="00:02.000" end="00:02.050">f</span><span begin="00:02.050" end="00:02.150">g)</span></span></p></div></body></tt>"#;
+
+    fn spread(times: &[(u64, u64)]) -> u64 {
+        let durations: Vec<_> = times.iter().map(|(start, end)| end - start).collect();
+        durations.iter().max().unwrap() - durations.iter().min().unwrap()
+    }
+
+    #[test]
+    fn test_smoothing_main_and_background() {
+        let mut parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let before = parsed.lines[0].clone();
+        apply_smoothing(&mut parsed.lines, &SyllableSmoothingOptions::default());
+        let after = &parsed.lines[0];
+
+        for content_type in [ContentType::Main, ContentType::Background] {
+            let old = track_times(&before, content_type);
+            let new = track_times(after, content_type);
+            assert!(spread(&new) < spread(&old), "{content_type:?}: {new:?}");
+            assert_eq!(new.first().unwrap().0, old.first().unwrap().0);
+            assert_eq!(new.last().unwrap().1, old.last().unwrap().1);
+            assert!(new.windows(2).all(|pair| pair[0].1 <= pair[1].0));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! 严格解析模式
+//!
+//! 普通模式下，音节时间无效、逐字模式下的 span 缺少时间等问题只会产生警告，
+//! 歌词投稿校验则需要把这些问题当作错误拒绝掉。
+
+use std::collections::HashSet;
+
+use lyrics_helper_core::{ConvertError, ParsedSourceData, TtmlParsingOptions};
+use quick_xml::{Reader, events::Event};
+
+/// 解析 TTML 失败的原因
+#[derive(Debug)]
+pub enum ParseTtmlError {
+    /// 底层解析器返回的错误
+    Convert(ConvertError),
+    /// 严格模式下出现的问题
+    Warning(String),
+}
+
+/// 解析 TTML 文件
+///
+/// `strict` 为 `true` 时，返回结果的解析器产生的第一条警告（如音节时间无效、缺少时间的 span）
+/// 会作为错误返回。解析器本身不检查 `xml:id` 是否重复，严格模式下会额外扫描一遍元素的属性。
+///
+/// # Errors
+/// * `ParseTtmlError::Convert` - 底层解析器返回的所有错误
+/// * `ParseTtmlError::Warning` - 严格模式下出现警告或重复的 `xml:id`
+pub fn parse_ttml_data(
+    ttml_content: &str,
+    strict: bool,
+) -> Result<ParsedSourceData, ParseTtmlError> {
+    if strict {
+        check_duplicate_ids(ttml_content)?;
+    }
+
+    let parsed_data = ttml_processor::parse_ttml(ttml_content, &TtmlParsingOptions::default())
+        .map_err(ParseTtmlError::Convert)?;
+
+    if strict && let Some(warning) = parsed_data.warnings.first() {
+        return Err(ParseTtmlError::Warning(warning.clone()));
+    }
+
+    Ok(parsed_data)
+}
+
+/// 只读取元素的 `xml:id` 属性，不构建歌词数据
+fn check_duplicate_ids(ttml_content: &str) -> Result<(), ParseTtmlError> {
+    let mut reader = Reader::from_str(ttml_content);
+    let mut seen_ids = HashSet::new();
+
+    loop {
+        let element_offset = reader.buffer_position();
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) => {
+                let id = e
+                    .attributes()
+                    .flatten()
+                    .find(|attr| attr.key.as_ref() == b"xml:id");
+                if let Some(id) = id {
+                    let id = String::from_utf8_lossy(&id.value).into_owned();
+                    if !seen_ids.insert(id.clone()) {
+                        return Err(ParseTtmlError::Warning(format!(
+                            "重复的 xml:id '{id}'，位于字节偏移 {element_offset}"
+                        )));
+                    }
+                }
+            }
+            // XML 本身的错误交给解析器报告
+            Ok(Event::Eof) | Err(_) => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><head><metadata><ttm:agent type="person" xml:id="v1"/></metadata></head><body><div><p begin="00:01.000" end="00:02.000" ttm:agent="v1"><span begin="00:01.000" end="00:02.000">Hello</span></p></div></body></tt>"#;
+
+    #[test]
+    fn test_strict_accepts_valid_ttml() {
+        assert!(parse_ttml_data(VALID_TTML, true).is_ok());
+    }
+
+    #[test]
+    fn test_strict_rejects_invalid_syllable_timing() {
+        let ttml = VALID_TTML.replace(
+            r#"<span begin="00:01.000" end="00:02.000">"#,
+            r#"<span begin="00:02.000" end="00:01.000">"#,
+        );
+        let parsed = parse_ttml_data(&ttml, false).unwrap();
+        let Err(ParseTtmlError::Warning(warning)) = parse_ttml_data(&ttml, true) else {
+            panic!("无效的音节时间应当被拒绝");
+        };
+        // 返回的错误就是普通模式下同一次解析产生的警告
+        assert_eq!(parsed.warnings.first(), Some(&warning));
+    }
+
+    #[test]
+    fn test_strict_rejects_duplicate_ids() {
+        let ttml = VALID_TTML.replace(
+            r#"<ttm:agent type="person" xml:id="v1"/>"#,
+            r#"<ttm:agent type="person" xml:id="v1"/><ttm:agent type="person" xml:id="v1"/>"#,
+        );
+        assert!(parse_ttml_data(&ttml, false).is_ok());
+        let Err(ParseTtmlError::Warning(warning)) = parse_ttml_data(&ttml, true) else {
+            panic!("重复的 xml:id 应当被拒绝");
+        };
+        let offset = ttml.rfind("<ttm:agent").unwrap();
+        assert!(
+            warning.ends_with(&format!("字节偏移 {offset}")),
+            "{warning}"
+        );
+    }
+
+    #[test]
+    fn test_strict_rejects_untimed_span() {
+        let ttml = VALID_TTML.replace(
+            r#"<span begin="00:01.000" end="00:02.000">Hello</span>"#,
+            r#"<span begin="00:01.000" end="00:02.000">Hello</span><span>World</span>"#,
+        );
+        let parsed = parse_ttml_data(&ttml, false).unwrap();
+        let Err(ParseTtmlError::Warning(warning)) = parse_ttml_data(&ttml, true) else {
+            panic!("缺少时间的 span 应当被拒绝");
+        };
+        assert_eq!(parsed.warnings.first(), Some(&warning));
+    }
+}
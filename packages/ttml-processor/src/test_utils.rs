@@ -0,0 +1,22 @@
+//! 各模块测试共用的辅助函数
+
+use lyrics_helper_core::{ContentType, LyricLine, TtmlParsingOptions};
+
+/// 以默认选项解析 TTML，只取出其中的歌词行
+pub fn parse_lines(ttml: &str) -> Vec<LyricLine> {
+    ttml_processor::parse_ttml(ttml, &TtmlParsingOptions::default())
+        .unwrap()
+        .lines
+}
+
+/// 某一行中指定类型的轨道里每个音节的开始和结束时间
+pub fn track_times(line: &LyricLine, content_type: ContentType) -> Vec<(u64, u64)> {
+    line.tracks
+        .iter()
+        .find(|t| t.content_type == content_type)
+        .unwrap()
+        .content
+        .syllables()
+        .map(|syl| (syl.start_ms, syl.end_ms))
+        .collect()
+}
@@ -1,7 +1,11 @@
 use lyrics_helper_core::converter::types as helper_types;
+use serde::Deserialize;
 use std::collections::HashMap;
 
-use crate::{JsLyricLine, JsLyricWord};
+use crate::{
+    JsAgentType, JsLyricLine, JsLyricWord, JsSection,
+    language::{LanguageTag, select_by_language},
+};
 
 const CHORUS_AGENT_ID: &str = "v1000";
 const PREFERRED_TRANSLATION_LANG: &str = "zh-CN";
@@ -23,21 +27,260 @@ fn get_track_text(track: &helper_types::LyricTrack) -> String {
         .to_string()
 }
 
+fn resolve_agent_type(
+    agents: &helper_types::AgentStore,
+    agent_id: Option<&str>,
+) -> Option<JsAgentType> {
+    let agent = agents.agents_by_id.get(agent_id?)?;
+    Some(match agent.agent_type {
+        helper_types::AgentType::Person => JsAgentType::Person,
+        helper_types::AgentType::Group => JsAgentType::Group,
+        helper_types::AgentType::Other => JsAgentType::Other,
+    })
+}
+
+/// 为各个演唱者分配对唱标识（`isDuet`）的方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuetStrategy {
+    /// 按出现顺序为演唱者交替分配 `false` 和 `true`，`v2` 始终为对唱
+    #[default]
+    Alternate,
+    /// 只有 `overrides` 中标为 `true` 的演唱者为对唱
+    ExplicitMap,
+    /// 所有行都不是对唱
+    AllFalse,
+}
+
+/// 对唱标识的分配选项
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DuetOptions {
+    pub strategy: DuetStrategy,
+    /// 演唱者 ID 到对唱标识的映射，优先于 `Alternate` 的自动分配
+    pub overrides: HashMap<String, bool>,
+}
+
+/// 按选项为各行的演唱者分配对唱标识，记住已经分配过的演唱者
+struct DuetAssigner<'a> {
+    options: &'a DuetOptions,
+    assigned: HashMap<String, bool>,
+}
+
+impl<'a> DuetAssigner<'a> {
+    fn new(options: &'a DuetOptions) -> Self {
+        Self {
+            options,
+            assigned: HashMap::new(),
+        }
+    }
+
+    fn is_duet(&mut self, agent_id: Option<&str>) -> bool {
+        let Some(agent_id) = agent_id.filter(|&id| id != CHORUS_AGENT_ID) else {
+            return false;
+        };
+        let overridden = self.options.overrides.get(agent_id).copied();
+        match (self.options.strategy, overridden) {
+            // `AllFalse` 不受 `overrides` 影响
+            (DuetStrategy::AllFalse, _) | (DuetStrategy::ExplicitMap, None) => false,
+            (_, Some(is_duet)) => is_duet,
+            // AMLL 的对唱标识
+            (DuetStrategy::Alternate, None) if agent_id == "v2" => true,
+            (DuetStrategy::Alternate, None) => {
+                if let Some(&is_duet) = self.assigned.get(agent_id) {
+                    return is_duet;
+                }
+                // 为新出现的 agent 交替分配 `false` 和 `true`。
+                // 上层已对歌词行进行排序，所以这里不需要排序。
+                let is_duet = !self.assigned.len().is_multiple_of(2);
+                self.assigned.insert(agent_id.to_string(), is_duet);
+                is_duet
+            }
+        }
+    }
+}
+
+/// 纯音乐提示文本（如“纯音乐，请欣赏”）的识别与显示选项
+///
+/// 只有一行且只有一个音节的歌词会被视为纯音乐提示，其结束时间会被延长，让提示一直显示
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InstrumentalOptions {
+    /// 提示文本需要包含其中之一（不区分大小写），为空时不检查文本
+    pub keywords: Vec<String>,
+    /// 结束时间相对开始时间延长到多少毫秒
+    pub extension_ms: u64,
+    /// 歌曲的时长（毫秒），已知时结束时间不会超过它
+    pub track_duration_ms: Option<u64>,
+}
+
+impl Default for InstrumentalOptions {
+    fn default() -> Self {
+        Self {
+            keywords: Vec::new(),
+            extension_ms: 3_600_000, // 1 h
+            track_duration_ms: None,
+        }
+    }
+}
+
+impl InstrumentalOptions {
+    fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        self.keywords.is_empty()
+            || self
+                .keywords
+                .iter()
+                .any(|keyword| text.contains(&keyword.trim().to_lowercase()))
+    }
+
+    fn end_ms(&self, start_ms: u64) -> u64 {
+        let end_ms = start_ms.saturating_add(self.extension_ms);
+        self.track_duration_ms
+            .map_or(end_ms, |duration| end_ms.min(duration.max(start_ms)))
+    }
+}
+
+/// 转换为 AMLL 数据结构时的可选项
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ConvertOptions {
+    /// 同一行存在多个罗马音方案（如 hepburn/kunrei）时，按顺序优先选用的方案名，
+    /// 都不匹配时使用第一个罗马音轨道
+    pub preferred_roman_schemes: Vec<String>,
+    /// 按顺序优先选用的翻译语言（BCP-47 标签），为空时使用 `zh-CN`，
+    /// 都不匹配时使用第一个翻译轨道
+    pub preferred_translation_languages: Vec<String>,
+    /// 对唱标识的分配方式
+    pub duet: DuetOptions,
+    /// 纯音乐提示文本的识别与显示选项
+    pub instrumental: InstrumentalOptions,
+}
+
+/// 行级罗马音，及其对应的罗马音方案
+struct LineRomanization {
+    text: String,
+    scheme: Option<String>,
+}
+
+fn track_scheme(track: &helper_types::LyricTrack) -> Option<&String> {
+    track.metadata.get(&helper_types::TrackMetadataKey::Scheme)
+}
+
+fn track_language(track: &helper_types::LyricTrack) -> Option<&str> {
+    track
+        .metadata
+        .get(&helper_types::TrackMetadataKey::Language)
+        .map(String::as_str)
+}
+
+/// 判断罗马音轨道是否使用了指定的方案，方案既可以写在 `xml:scheme` 中，
+/// 也可以写为语言标签的变体子标签，例如 `ja-Latn-hepburn`
+fn uses_scheme(track: &helper_types::LyricTrack, scheme: &str) -> bool {
+    track_scheme(track).is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+        || track_language(track)
+            .and_then(LanguageTag::parse)
+            .is_some_and(|tag| tag.variants.iter().any(|v| v.eq_ignore_ascii_case(scheme)))
+}
+
+fn select_romanization<'a>(
+    romanizations: &'a [helper_types::LyricTrack],
+    preferred_schemes: &[String],
+) -> Option<&'a helper_types::LyricTrack> {
+    preferred_schemes
+        .iter()
+        .find_map(|preferred| {
+            romanizations
+                .iter()
+                .find(|track| uses_scheme(track, preferred))
+        })
+        .or_else(|| romanizations.first())
+}
+
+fn select_translation<'a>(
+    translations: &'a [helper_types::LyricTrack],
+    preferred_languages: &[String],
+) -> Option<&'a helper_types::LyricTrack> {
+    let selected = if preferred_languages.is_empty() {
+        select_by_language(
+            translations,
+            &[PREFERRED_TRANSLATION_LANG.to_string()],
+            track_language,
+        )
+    } else {
+        select_by_language(translations, preferred_languages, track_language)
+    };
+    selected.or_else(|| translations.first())
+}
+
+/// 把逐字罗马音的音节分配到主歌词的音节上，返回每个主歌词音节对应的罗马音
+///
+/// 优先按时间重叠最多的音节分配。罗马音音节与主歌词完全没有时间重叠时（如没有时间的罗马音轨道），
+/// 数量相同则一一对应，否则按下标比例分配
+fn align_romanization(
+    syllables: &[helper_types::LyricSyllable],
+    roman_syllables: &[helper_types::LyricSyllable],
+) -> Vec<Vec<String>> {
+    let mut roman_groups: Vec<Vec<String>> = vec![Vec::new(); syllables.len()];
+    if roman_syllables.is_empty() || syllables.is_empty() {
+        return roman_groups;
+    }
+
+    let best_matches: Vec<Option<usize>> = roman_syllables
+        .iter()
+        .map(|roman_syl| {
+            let mut best_match_index = None;
+            let mut max_overlap: i64 = 0;
+
+            for (i, main_syl) in syllables.iter().enumerate() {
+                let overlap = std::cmp::min(main_syl.end_ms, roman_syl.end_ms) as i64
+                    - std::cmp::max(main_syl.start_ms, roman_syl.start_ms) as i64;
+
+                if overlap > max_overlap {
+                    max_overlap = overlap;
+                    best_match_index = Some(i);
+                }
+            }
+            best_match_index
+        })
+        .collect();
+
+    if best_matches.iter().all(Option::is_none) {
+        // 没有可用的时间，按下标对齐
+        for (k, roman_syl) in roman_syllables.iter().enumerate() {
+            let index = k * syllables.len() / roman_syllables.len();
+            roman_groups[index].push(roman_syl.text.clone());
+        }
+        return roman_groups;
+    }
+
+    for (roman_syl, best_match_index) in roman_syllables.iter().zip(best_matches) {
+        if let Some(index) = best_match_index {
+            roman_groups[index].push(roman_syl.text.clone());
+        } else {
+            // warn!(
+            //     "未匹配的罗马音音节 '{}', {}ms - {}ms",
+            //     roman_syl.text, roman_syl.start_ms, roman_syl.end_ms
+            // );
+        }
+    }
+    roman_groups
+}
+
 fn extract_line_components(
     syllables: &[helper_types::LyricSyllable],
     translations: &[helper_types::LyricTrack],
     romanizations: &[helper_types::LyricTrack],
     is_instrumental: bool,
-) -> (Vec<JsLyricWord>, String, String) {
+    options: &ConvertOptions,
+) -> (Vec<JsLyricWord>, String, LineRomanization) {
+    let roman_track = select_romanization(romanizations, &options.preferred_roman_schemes);
+    let roman_scheme = roman_track.and_then(track_scheme).cloned();
     let mut line_romanization = String::new();
-    let mut syllables_romanizations = romanizations;
+    let mut syllables_roman_track = roman_track;
 
-    if let Some(first_track) = romanizations.first() {
-        let all_roma_syllables: Vec<_> = first_track
-            .words
-            .iter()
-            .flat_map(|w| &w.syllables)
-            .collect();
+    if let Some(track) = roman_track {
+        let all_roma_syllables: Vec<_> = track.words.iter().flat_map(|w| &w.syllables).collect();
 
         if all_roma_syllables.len() == 1
             && let Some(syl) = all_roma_syllables.first()
@@ -45,12 +288,11 @@ fn extract_line_components(
             && syl.end_ms == 0
         {
             line_romanization.clone_from(&syl.text);
-            syllables_romanizations = &[];
+            syllables_roman_track = None;
         }
     }
 
-    let roman_syllables: Vec<_> = syllables_romanizations
-        .first()
+    let roman_syllables: Vec<_> = syllables_roman_track
         .map(|track| {
             track
                 .words
@@ -61,33 +303,7 @@ fn extract_line_components(
         })
         .unwrap_or_default();
 
-    let mut roman_groups: Vec<Vec<String>> = vec![Vec::new(); syllables.len()];
-
-    if !roman_syllables.is_empty() && !syllables.is_empty() {
-        for roman_syl in &roman_syllables {
-            let mut best_match_index = None;
-            let mut max_overlap: i64 = 0;
-
-            for (i, main_syl) in syllables.iter().enumerate() {
-                let overlap = std::cmp::min(main_syl.end_ms, roman_syl.end_ms) as i64
-                    - std::cmp::max(main_syl.start_ms, roman_syl.start_ms) as i64;
-
-                if overlap > max_overlap {
-                    max_overlap = overlap;
-                    best_match_index = Some(i);
-                }
-            }
-
-            if let Some(index) = best_match_index {
-                roman_groups[index].push(roman_syl.text.clone());
-            } else {
-                // warn!(
-                //     "未匹配的罗马音音节 '{}', {}ms - {}ms",
-                //     roman_syl.text, roman_syl.start_ms, roman_syl.end_ms
-                // );
-            }
-        }
-    }
+    let roman_groups = align_romanization(syllables, &roman_syllables);
 
     let words = syllables
         .iter()
@@ -101,83 +317,81 @@ fn extract_line_components(
 
             let end_time = if is_instrumental {
                 // 应对纯音乐提示文本
-                syllable.start_ms + 3_600_000 // 1 h
+                options.instrumental.end_ms(syllable.start_ms)
             } else {
                 syllable.end_ms
             };
 
             let roman_word_text = roman_groups[i].join("");
+            let roman_scheme = if roman_word_text.is_empty() {
+                None
+            } else {
+                roman_scheme.clone()
+            };
 
             JsLyricWord {
                 start_time: syllable.start_ms as f64,
                 end_time: end_time as f64,
                 word: word_text,
                 roman_word: roman_word_text,
+                roman_scheme,
             }
         })
         .collect();
 
-    let mut translation = translations
-        .iter()
-        .find(|t| {
-            t.metadata
-                .get(&helper_types::TrackMetadataKey::Language)
-                .is_some_and(|lang| lang.eq_ignore_ascii_case(PREFERRED_TRANSLATION_LANG))
-        })
-        .or_else(|| translations.first())
-        .map_or(String::new(), get_track_text);
+    let mut translation =
+        select_translation(translations, &options.preferred_translation_languages)
+            .map_or(String::new(), get_track_text);
 
     if translation == "//" {
         translation = String::new();
     }
 
-    let romanization = line_romanization;
+    let romanization = LineRomanization {
+        text: line_romanization,
+        scheme: roman_scheme,
+    };
 
     (words, translation, romanization)
 }
 
 #[allow(clippy::too_many_lines)]
-pub fn convert_to_amll_lyrics(source_data: &helper_types::ParsedSourceData) -> Vec<JsLyricLine> {
-    let is_instrumental = if source_data.lines.len() == 1 {
-        source_data
-            .lines
-            .first()
-            .and_then(|line| {
-                line.tracks
-                    .iter()
-                    .find(|t| t.content_type == helper_types::ContentType::Main)
-            })
+pub fn convert_to_amll_lyrics(
+    source_data: &helper_types::ParsedSourceData,
+    options: &ConvertOptions,
+) -> Vec<JsLyricLine> {
+    let is_instrumental = if let [line] = source_data.lines.as_slice() {
+        line.tracks
+            .iter()
+            .find(|t| t.content_type == helper_types::ContentType::Main)
             .is_some_and(|main_track| {
-                main_track
-                    .content
-                    .words
-                    .iter()
-                    .flat_map(|w| &w.syllables)
-                    .count()
-                    == 1
+                let mut syllables = main_track.content.words.iter().flat_map(|w| &w.syllables);
+                matches!((syllables.next(), syllables.next()), (Some(syllable), None)
+                    if options.instrumental.matches(&syllable.text))
             })
     } else {
         false
     };
 
-    let mut agent_duet_map: HashMap<String, bool> = HashMap::new();
+    let mut duet_assigner = DuetAssigner::new(&options.duet);
+    let mut voice_indices: HashMap<String, usize> = HashMap::new();
+    // 已经输出的行数，用于得到背景行所属主行的下标
+    let mut emitted = 0;
 
     source_data
         .lines
         .iter()
         .flat_map(|helper_line| {
-            let current_line_is_duet = match helper_line.agent.as_deref() {
-                None | Some(CHORUS_AGENT_ID) => false,
-                // AMLL 的对唱标识
-                Some("v2") => true,
-                Some(agent_id) => agent_duet_map.get(agent_id).copied().unwrap_or_else(|| {
-                    // 为新出现的 agent 交替分配 `false` 和 `true`。
-                    // 上层已对歌词行进行排序，所以这里不需要排序。
-                    let new_duet_status = !agent_duet_map.len().is_multiple_of(2);
-                    agent_duet_map.insert(agent_id.to_string(), new_duet_status);
-                    new_duet_status
-                }),
-            };
+            let current_line_is_duet = duet_assigner.is_duet(helper_line.agent.as_deref());
+            let agent_type = resolve_agent_type(&source_data.agents, helper_line.agent.as_deref());
+            let voice_index = helper_line
+                .agent
+                .as_deref()
+                .filter(|&agent_id| agent_id != CHORUS_AGENT_ID)
+                .map(|agent_id| {
+                    let next = voice_indices.len();
+                    *voice_indices.entry(agent_id.to_string()).or_insert(next)
+                });
 
             let main_annotated_track = helper_line
                 .tracks
@@ -197,11 +411,12 @@ pub fn convert_to_amll_lyrics(source_data: &helper_types::ParsedSourceData) -> V
                     return None;
                 }
 
-                let (words, translated_lyric, roman_lyric) = extract_line_components(
+                let (words, translated_lyric, romanization) = extract_line_components(
                     &main_syllables,
                     &main_track.translations,
                     &main_track.romanizations,
                     is_instrumental,
+                    options,
                 );
 
                 let start_time = words
@@ -230,9 +445,14 @@ pub fn convert_to_amll_lyrics(source_data: &helper_types::ParsedSourceData) -> V
                     end_time: final_end,
                     words,
                     translated_lyric,
-                    roman_lyric,
+                    roman_lyric: romanization.text,
+                    roman_scheme: romanization.scheme,
                     is_bg: false,
                     is_duet: current_line_is_duet,
+                    itunes_key: helper_line.itunes_key.clone(),
+                    agent_type,
+                    voice_index,
+                    parent_index: None,
                 })
             });
 
@@ -241,7 +461,7 @@ pub fn convert_to_amll_lyrics(source_data: &helper_types::ParsedSourceData) -> V
                 .iter()
                 .find(|t| t.content_type == helper_types::ContentType::Background);
 
-            let bg_line = background_annotated_track.and_then(|bg_track| {
+            let mut bg_line = background_annotated_track.and_then(|bg_track| {
                 let bg_syllables: Vec<_> = bg_track
                     .content
                     .words
@@ -258,6 +478,7 @@ pub fn convert_to_amll_lyrics(source_data: &helper_types::ParsedSourceData) -> V
                     &bg_track.translations,
                     &bg_track.romanizations,
                     false,
+                    options,
                 );
 
                 let start_time = bg_words
@@ -285,19 +506,322 @@ pub fn convert_to_amll_lyrics(source_data: &helper_types::ParsedSourceData) -> V
                     end_time: final_end,
                     words: bg_words,
                     translated_lyric: bg_translation,
-                    roman_lyric: bg_romanization,
+                    roman_lyric: bg_romanization.text,
+                    roman_scheme: bg_romanization.scheme,
                     is_bg: true,
                     is_duet: current_line_is_duet,
+                    itunes_key: helper_line.itunes_key.clone(),
+                    agent_type,
+                    voice_index,
+                    parent_index: None,
                 })
             });
 
-            if let (Some(main), Some(bg)) = (&mut main_line, &bg_line)
-                && bg.end_time > main.end_time
-            {
-                main.end_time = bg.end_time;
+            if let (Some(main), Some(bg)) = (&mut main_line, &mut bg_line) {
+                if bg.end_time > main.end_time {
+                    main.end_time = bg.end_time;
+                }
+                bg.parent_index = Some(emitted);
             }
+            emitted += usize::from(main_line.is_some()) + usize::from(bg_line.is_some());
 
             main_line.into_iter().chain(bg_line)
         })
         .collect()
 }
+
+/// 建立 `itunes:key` 到行下标的映射，同一个 key 对应多行时取第一行
+pub fn build_line_key_map(lines: &[JsLyricLine]) -> HashMap<String, usize> {
+    let mut map = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(key) = &line.itunes_key {
+            map.entry(key.clone()).or_insert(index);
+        }
+    }
+    map
+}
+
+/// 把连续的具有相同 `song_part` 的行合并为段落，没有 `song_part` 的行不属于任何段落
+#[allow(clippy::cast_precision_loss)]
+pub fn build_sections(lines: &[helper_types::LyricLine]) -> Vec<JsSection> {
+    let mut sections: Vec<JsSection> = Vec::new();
+    let mut previous: Option<&str> = None;
+    for line in lines {
+        let part = line
+            .song_part
+            .as_deref()
+            .filter(|part| !part.trim().is_empty());
+        if let Some(name) = part {
+            let (start_time, end_time) = (line.start_ms as f64, line.end_ms as f64);
+            match sections.last_mut() {
+                Some(section) if previous == Some(name) => {
+                    section.start_time = section.start_time.min(start_time);
+                    section.end_time = section.end_time.max(end_time);
+                }
+                _ => sections.push(JsSection {
+                    name: name.to_string(),
+                    start_time,
+                    end_time,
+                }),
+            }
+        }
+        previous = part;
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:02.000">東京</span><span ttm:role="x-roman" xml:scheme="hepburn">toukyou</span><span ttm:role="x-roman" xml:scheme="kunrei">tôkyô</span></p></div></body></tt>"#;
+
+    fn convert(preferred_roman_schemes: &[&str]) -> Vec<JsLyricLine> {
+        let parsed = ttml_processor::parse_ttml(TTML, &TtmlParsingOptions::default()).unwrap();
+        let options = ConvertOptions {
+            preferred_roman_schemes: preferred_roman_schemes
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            ..Default::default()
+        };
+        convert_to_amll_lyrics(&parsed, &options)
+    }
+
+    #[test]
+    fn test_translation_language_matching() {
+        const TRANSLATED_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:02.000">Hello</span><span ttm:role="x-translation" xml:lang="zh-Hant">哈囉</span><span ttm:role="x-translation" xml:lang="cmn-Hans-CN">你好</span><span ttm:role="x-translation" xml:lang="ja">こんにちは</span></p></div></body></tt>"#;
+
+        let parsed =
+            ttml_processor::parse_ttml(TRANSLATED_TTML, &TtmlParsingOptions::default()).unwrap();
+        let translated = |preferred: &[&str]| {
+            let options = ConvertOptions {
+                preferred_translation_languages: preferred
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                ..Default::default()
+            };
+            convert_to_amll_lyrics(&parsed, &options)[0]
+                .translated_lyric
+                .clone()
+        };
+        assert_eq!(translated(&[]), "你好");
+        assert_eq!(translated(&["zh-TW"]), "哈囉");
+        assert_eq!(translated(&["jpn", "zh"]), "こんにちは");
+        assert_eq!(translated(&["fr"]), "哈囉");
+    }
+
+    #[test]
+    fn test_romanization_scheme_selection() {
+        let lines = convert(&[]);
+        assert_eq!(lines[0].roman_lyric, "toukyou");
+        assert_eq!(lines[0].roman_scheme.as_deref(), Some("hepburn"));
+
+        let lines = convert(&["wapuro", "Kunrei"]);
+        assert_eq!(lines[0].roman_lyric, "tôkyô");
+        assert_eq!(lines[0].roman_scheme.as_deref(), Some("kunrei"));
+    }
+
+    #[test]
+    fn test_line_key_map() {
+        const KEYED_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:itunes="http://music.apple.com/lyric-ttml-internal"><body><div><p begin="00:01.000" end="00:02.000" itunes:key="L1"><span begin="00:01.000" end="00:02.000">one</span><span ttm:role="x-bg"><span begin="00:01.500" end="00:02.000">(bg)</span></span></p><p begin="00:02.000" end="00:03.000" itunes:key="L2"><span begin="00:02.000" end="00:03.000">two</span></p></div></body></tt>"#;
+
+        let parsed =
+            ttml_processor::parse_ttml(KEYED_TTML, &TtmlParsingOptions::default()).unwrap();
+        let lines = convert_to_amll_lyrics(&parsed, &ConvertOptions::default());
+        let keys: Vec<_> = lines
+            .iter()
+            .map(|line| (line.is_bg, line.itunes_key.as_deref()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![(false, Some("L1")), (true, Some("L1")), (false, Some("L2"))]
+        );
+
+        // 前端按 `itunesKey` 将行与 Apple Music 的同步数据和外部翻译对应起来
+        let json = serde_json::to_value(&lines[1]).unwrap();
+        assert_eq!(json["itunesKey"], "L1");
+
+        let parents: Vec<_> = lines.iter().map(|line| line.parent_index).collect();
+        assert_eq!(parents, vec![None, Some(0), None]);
+
+        let map = build_line_key_map(&lines);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["L1"], 0);
+        assert_eq!(map["L2"], 2);
+    }
+
+    #[test]
+    fn test_agent_type() {
+        const AGENT_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><head><metadata><ttm:agent type="person" xml:id="v1"/><ttm:agent type="group" xml:id="v2"/><ttm:agent type="other" xml:id="v3"/></metadata></head><body><div><p begin="00:01.000" end="00:02.000" ttm:agent="v1"><span begin="00:01.000" end="00:02.000">solo</span></p><p begin="00:02.000" end="00:03.000" ttm:agent="v2"><span begin="00:02.000" end="00:03.000">chorus</span><span ttm:role="x-bg"><span begin="00:02.500" end="00:03.000">(bg)</span></span></p><p begin="00:03.000" end="00:04.000" ttm:agent="v3"><span begin="00:03.000" end="00:04.000">other</span></p></div></body></tt>"#;
+
+        let parsed =
+            ttml_processor::parse_ttml(AGENT_TTML, &TtmlParsingOptions::default()).unwrap();
+        let lines = convert_to_amll_lyrics(&parsed, &ConvertOptions::default());
+        let agent_types: Vec<_> = lines.iter().map(|line| line.agent_type).collect();
+        assert_eq!(
+            agent_types,
+            vec![
+                Some(JsAgentType::Person),
+                Some(JsAgentType::Group),
+                Some(JsAgentType::Group),
+                Some(JsAgentType::Other),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duet_strategy() {
+        const TRIO_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000" ttm:agent="v1"><span begin="00:01.000" end="00:02.000">a</span></p><p begin="00:02.000" end="00:03.000" ttm:agent="v2"><span begin="00:02.000" end="00:03.000">b</span></p><p begin="00:03.000" end="00:04.000" ttm:agent="v3"><span begin="00:03.000" end="00:04.000">c</span></p></div></body></tt>"#;
+
+        let parsed = ttml_processor::parse_ttml(TRIO_TTML, &TtmlParsingOptions::default()).unwrap();
+        let duets = |strategy: DuetStrategy, overrides: &[(&str, bool)]| {
+            let options = ConvertOptions {
+                duet: DuetOptions {
+                    strategy,
+                    overrides: overrides
+                        .iter()
+                        .map(|&(id, is_duet)| (id.to_string(), is_duet))
+                        .collect(),
+                },
+                ..Default::default()
+            };
+            convert_to_amll_lyrics(&parsed, &options)
+                .iter()
+                .map(|line| line.is_duet)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(duets(DuetStrategy::Alternate, &[]), vec![false, true, true]);
+        assert_eq!(
+            duets(DuetStrategy::Alternate, &[("v3", false)]),
+            vec![false, true, false]
+        );
+        assert_eq!(
+            duets(DuetStrategy::ExplicitMap, &[("v3", true)]),
+            vec![false, false, true]
+        );
+        assert_eq!(
+            duets(DuetStrategy::AllFalse, &[("v3", true)]),
+            vec![false; 3]
+        );
+
+        let options: DuetOptions =
+            serde_json::from_str(r#"{"strategy": "explicitMap", "overrides": {"v3": true}}"#)
+                .unwrap();
+        assert_eq!(options.strategy, DuetStrategy::ExplicitMap);
+    }
+
+    #[test]
+    fn test_voice_index() {
+        const VOICES_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><head><metadata><ttm:agent type="person" xml:id="v1"/><ttm:agent type="person" xml:id="v2"/><ttm:agent type="person" xml:id="v3"/></metadata></head><body><div><p begin="00:01.000" end="00:02.000" ttm:agent="v2"><span begin="00:01.000" end="00:02.000">a</span><span ttm:role="x-bg"><span begin="00:01.500" end="00:02.000">(bg)</span></span></p><p begin="00:02.000" end="00:03.000" ttm:agent="v1"><span begin="00:02.000" end="00:03.000">b</span></p><p begin="00:03.000" end="00:04.000" ttm:agent="v3"><span begin="00:03.000" end="00:04.000">c</span></p><p begin="00:04.000" end="00:05.000" ttm:agent="v2"><span begin="00:04.000" end="00:05.000">d</span></p><p begin="00:05.000" end="00:06.000"><span begin="00:05.000" end="00:06.000">e</span></p></div></body></tt>"#;
+
+        let parsed =
+            ttml_processor::parse_ttml(VOICES_TTML, &TtmlParsingOptions::default()).unwrap();
+        let voices: Vec<_> = convert_to_amll_lyrics(&parsed, &ConvertOptions::default())
+            .iter()
+            .map(|line| line.voice_index)
+            .collect();
+        assert_eq!(
+            voices,
+            vec![Some(0), Some(0), Some(1), Some(2), Some(0), None]
+        );
+    }
+
+    #[test]
+    fn test_untimed_romanization_alignment() {
+        let syllable = |text: &str, start_ms: u64, end_ms: u64| helper_types::LyricSyllable {
+            text: text.to_string(),
+            start_ms,
+            end_ms,
+            ..Default::default()
+        };
+        let main = [
+            syllable("東", 1000, 1500),
+            syllable("京", 1500, 2000),
+            syllable("へ", 2000, 2500),
+        ];
+        let untimed = |texts: &[&str]| {
+            texts
+                .iter()
+                .map(|text| syllable(text, 0, 0))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            align_romanization(&main, &untimed(&["tou", "kyou", "e"])),
+            vec![vec!["tou"], vec!["kyou"], vec!["e"]]
+        );
+        assert_eq!(
+            align_romanization(&main, &untimed(&["to", "u", "kyo", "u", "e", "!"])),
+            vec![vec!["to", "u"], vec!["kyo", "u"], vec!["e", "!"]]
+        );
+        assert_eq!(
+            align_romanization(&main, &untimed(&["toukyou"])),
+            vec![vec!["toukyou"], vec![], vec![]]
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        const SECTIONS_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:itunes="http://music.apple.com/lyric-ttml-internal"><body><div itunes:song-part="Verse"><p begin="00:01.000" end="00:02.000">a</p><p begin="00:02.000" end="00:03.000">b</p></div><div itunes:song-part="Chorus"><p begin="00:03.000" end="00:04.000">c</p></div><div><p begin="00:04.000" end="00:05.000">d</p></div><div itunes:song-part="Verse"><p begin="00:05.000" end="00:06.000">e</p></div></body></tt>"#;
+
+        let parsed =
+            ttml_processor::parse_ttml(SECTIONS_TTML, &TtmlParsingOptions::default()).unwrap();
+        let sections: Vec<_> = build_sections(&parsed.lines)
+            .into_iter()
+            .map(|section| (section.name, section.start_time, section.end_time))
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                ("Verse".to_string(), 1000.0, 3000.0),
+                ("Chorus".to_string(), 3000.0, 4000.0),
+                ("Verse".to_string(), 5000.0, 6000.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instrumental_options() {
+        const INSTRUMENTAL_TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata"><body><div><p begin="00:01.000" end="00:02.000"><span begin="00:01.000" end="00:02.000">纯音乐，请欣赏</span></p></div></body></tt>"#;
+
+        let parsed =
+            ttml_processor::parse_ttml(INSTRUMENTAL_TTML, &TtmlParsingOptions::default()).unwrap();
+        let end_times = |instrumental: InstrumentalOptions| {
+            let options = ConvertOptions {
+                instrumental,
+                ..Default::default()
+            };
+            convert_to_amll_lyrics(&parsed, &options)[0]
+                .words
+                .iter()
+                .map(|word| word.end_time)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(end_times(InstrumentalOptions::default()), vec![3_601_000.0]);
+        assert_eq!(
+            end_times(InstrumentalOptions {
+                track_duration_ms: Some(180_000),
+                ..Default::default()
+            }),
+            vec![180_000.0]
+        );
+        assert_eq!(
+            end_times(InstrumentalOptions {
+                keywords: vec!["Instrumental".to_string()],
+                ..Default::default()
+            }),
+            vec![2000.0]
+        );
+        assert_eq!(
+            end_times(InstrumentalOptions {
+                keywords: vec!["纯音乐".to_string()],
+                extension_ms: 60_000,
+                track_duration_ms: None,
+            }),
+            vec![61_000.0]
+        );
+    }
+}
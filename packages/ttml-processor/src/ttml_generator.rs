@@ -0,0 +1,361 @@
+//! 将 AMLL 的歌词数据结构重新生成为 Apple Music 兼容的 TTML
+//!
+//! 这是 [`crate::translation`] 的逆过程：先把歌词行还原为 `lyrics_helper_core` 的数据结构，
+//! 再交给 `ttml_processor` 的生成器输出，用于在应用内编辑歌词后重新导出
+
+use lyrics_helper_core::{
+    Agent, AgentStore, AgentType, AnnotatedTrack, ContentType, ConvertError, LyricLine,
+    LyricSyllable, LyricTrack, MetadataStore, TrackMetadataKey, TtmlGenerationOptions, Word,
+};
+use std::collections::HashMap;
+
+use crate::{JsAgentType, JsLyricLine, JsLyricWord};
+
+const MAIN_AGENT_ID: &str = "v1";
+const DUET_AGENT_ID: &str = "v2";
+const CHORUS_AGENT_ID: &str = "v1000";
+
+/// 生成 TTML 时的可选项
+#[derive(Debug, Default, Clone)]
+pub struct GenerateOptions {
+    /// 是否输出带缩进的 TTML
+    pub format: bool,
+    /// 是否遵循 Apple Music 的格式规则，例如将翻译写入 `<head>` 而不是内联
+    pub use_apple_format_rules: bool,
+    /// 翻译的语言代码，为空时不标注语言
+    pub translation_language: Option<String>,
+}
+
+// 时间已经限制为非负数，歌词时间也不会超出 u64 的范围
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub const fn to_ms(time: f64) -> u64 {
+    time.max(0.0).round() as u64
+}
+
+/// 将单词转换为音节，单词首尾的空格会转换为前一个音节的 `ends_with_space`
+fn words_to_syllables<'a>(
+    words: impl IntoIterator<Item = (&'a JsLyricWord, &'a str)>,
+) -> Vec<LyricSyllable> {
+    let mut syllables: Vec<LyricSyllable> = Vec::new();
+    for (word, text) in words {
+        let trimmed = text.trim();
+        if text.starts_with(char::is_whitespace)
+            && let Some(prev) = syllables.last_mut()
+        {
+            prev.ends_with_space = true;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        syllables.push(LyricSyllable {
+            text: trimmed.to_string(),
+            start_ms: to_ms(word.start_time),
+            end_ms: to_ms(word.end_time),
+            duration_ms: None,
+            ends_with_space: text.ends_with(char::is_whitespace),
+        });
+    }
+    if let Some(last) = syllables.last_mut() {
+        last.ends_with_space = false;
+    }
+    syllables
+}
+
+fn single_word_track(
+    syllables: Vec<LyricSyllable>,
+    metadata: HashMap<TrackMetadataKey, String>,
+) -> LyricTrack {
+    LyricTrack {
+        words: vec![Word {
+            syllables,
+            furigana: None,
+        }],
+        metadata,
+    }
+}
+
+/// 未计时的整行文本轨道，用于行级翻译和罗马音
+fn untimed_text_track(text: &str, key: TrackMetadataKey, value: Option<&str>) -> LyricTrack {
+    let metadata = value
+        .filter(|v| !v.is_empty())
+        .map(|v| HashMap::from([(key, v.to_string())]))
+        .unwrap_or_default();
+    single_word_track(
+        vec![LyricSyllable {
+            text: text.to_string(),
+            ..Default::default()
+        }],
+        metadata,
+    )
+}
+
+fn build_annotated_track(
+    line: &JsLyricLine,
+    content_type: ContentType,
+    options: &GenerateOptions,
+) -> AnnotatedTrack {
+    let content = single_word_track(
+        words_to_syllables(line.words.iter().map(|w| (w, w.word.as_str()))),
+        HashMap::new(),
+    );
+
+    let mut translations = Vec::new();
+    if !line.translated_lyric.is_empty() {
+        translations.push(untimed_text_track(
+            &line.translated_lyric,
+            TrackMetadataKey::Language,
+            options.translation_language.as_deref(),
+        ));
+    }
+
+    let mut romanizations = Vec::new();
+    if !line.roman_lyric.is_empty() {
+        romanizations.push(untimed_text_track(
+            &line.roman_lyric,
+            TrackMetadataKey::Scheme,
+            line.roman_scheme.as_deref(),
+        ));
+    } else if line.words.iter().any(|w| !w.roman_word.is_empty()) {
+        // 逐字罗马音，使用与对应单词相同的时间
+        let scheme = line
+            .words
+            .iter()
+            .find_map(|w| w.roman_scheme.as_deref())
+            .or(line.roman_scheme.as_deref());
+        let metadata = scheme
+            .map(|s| HashMap::from([(TrackMetadataKey::Scheme, s.to_string())]))
+            .unwrap_or_default();
+        romanizations.push(single_word_track(
+            words_to_syllables(line.words.iter().map(|w| (w, w.roman_word.as_str()))),
+            metadata,
+        ));
+    }
+
+    AnnotatedTrack {
+        content_type,
+        content,
+        translations,
+        romanizations,
+    }
+}
+
+/// 根据行的对唱状态和演唱者类型选择演唱者 ID，并在首次出现时登记到 `agents` 中
+fn assign_agent(line: &JsLyricLine, agents: &mut AgentStore) -> String {
+    let id = match (line.agent_type, line.is_duet) {
+        (Some(JsAgentType::Group), false) => CHORUS_AGENT_ID,
+        (_, true) => DUET_AGENT_ID,
+        (_, false) => MAIN_AGENT_ID,
+    };
+    agents
+        .agents_by_id
+        .entry(id.to_string())
+        .or_insert_with(|| Agent {
+            id: id.to_string(),
+            name: None,
+            agent_type: match line.agent_type {
+                Some(JsAgentType::Group) => AgentType::Group,
+                Some(JsAgentType::Other) => AgentType::Other,
+                Some(JsAgentType::Person) | None => AgentType::Person,
+            },
+        });
+    id.to_string()
+}
+
+/// 将 AMLL 歌词行还原为 `lyrics_helper_core` 的歌词行
+///
+/// 背景行会合并到它之前的主行中，没有对应主行的背景行会单独成行
+fn restore_lines(lines: &[JsLyricLine], options: &GenerateOptions) -> (Vec<LyricLine>, AgentStore) {
+    let mut agents = AgentStore::new();
+    let mut result: Vec<LyricLine> = Vec::new();
+
+    for line in lines {
+        if line.is_bg
+            && let Some(main_line) = result.last_mut()
+            && !main_line
+                .tracks
+                .iter()
+                .any(|t| t.content_type == ContentType::Background)
+        {
+            main_line.tracks.push(build_annotated_track(
+                line,
+                ContentType::Background,
+                options,
+            ));
+            main_line.end_ms = main_line.end_ms.max(to_ms(line.end_time));
+            continue;
+        }
+
+        let content_type = if line.is_bg {
+            ContentType::Background
+        } else {
+            ContentType::Main
+        };
+        result.push(LyricLine {
+            tracks: vec![build_annotated_track(line, content_type, options)],
+            start_ms: to_ms(line.start_time),
+            end_ms: to_ms(line.end_time),
+            agent: Some(assign_agent(line, &mut agents)),
+            song_part: None,
+            itunes_key: line.itunes_key.clone(),
+        });
+    }
+
+    (result, agents)
+}
+
+/// 将 AMLL 歌词行和元数据生成为 TTML 字符串
+///
+/// 元数据的键与解析时得到的原始键相同，例如 `songwriters`、`musicName`、`artists`
+///
+/// # Errors
+///
+/// 生成 XML 失败时返回 `ConvertError`
+pub fn generate_ttml_from_lines(
+    lines: &[JsLyricLine],
+    metadata: &[(String, Vec<String>)],
+    options: &GenerateOptions,
+) -> Result<String, ConvertError> {
+    let (helper_lines, agents) = restore_lines(lines, options);
+
+    let raw_metadata: HashMap<String, Vec<String>> = metadata.iter().cloned().collect();
+    let mut metadata_store = MetadataStore::new();
+    metadata_store.load_from_raw(&raw_metadata);
+
+    let generation_options = TtmlGenerationOptions {
+        translation_language: options.translation_language.clone(),
+        use_apple_format_rules: options.use_apple_format_rules,
+        format: options.format,
+        ..Default::default()
+    };
+
+    ttml_processor::generate_ttml(&helper_lines, &metadata_store, &agents, &generation_options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation::{ConvertOptions, convert_to_amll_lyrics};
+    use lyrics_helper_core::TtmlParsingOptions;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:amll="http://www.example.com/ns/amll"><head><metadata><ttm:agent type="person" xml:id="v1"/><ttm:agent type="person" xml:id="v2"/><ttm:agent type="group" xml:id="v1000"/><amll:meta key="songwriters" value="Someone"/></metadata></head><body><div><p begin="00:01.000" end="00:03.000" ttm:agent="v1"><span begin="00:01.000" end="00:01.500">Hello</span> <span begin="00:01.500" end="00:02.000">world</span><span ttm:role="x-translation">你好世界</span><span ttm:role="x-bg"><span begin="00:02.000" end="00:03.000">(echo)</span></span></p><p begin="00:03.000" end="00:04.000" ttm:agent="v2"><span begin="00:03.000" end="00:04.000">duet</span></p><p begin="00:04.000" end="00:05.000" ttm:agent="v1000"><span begin="00:04.000" end="00:05.000">all</span></p></div></body></tt>"#;
+
+    fn parse(ttml: &str) -> (Vec<JsLyricLine>, Vec<(String, Vec<String>)>) {
+        let parsed = ttml_processor::parse_ttml(ttml, &TtmlParsingOptions::default()).unwrap();
+        let lines = convert_to_amll_lyrics(&parsed, &ConvertOptions::default());
+        (lines, parsed.raw_metadata.into_iter().collect())
+    }
+
+    fn summarize(lines: &[JsLyricLine]) -> Vec<(String, String, bool, bool, Option<JsAgentType>)> {
+        lines
+            .iter()
+            .map(|line| {
+                let text: String = line.words.iter().map(|w| w.word.as_str()).collect();
+                (
+                    text,
+                    line.translated_lyric.clone(),
+                    line.is_bg,
+                    line.is_duet,
+                    line.agent_type,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_generate_roundtrip() {
+        let (lines, metadata) = parse(TTML);
+        let ttml =
+            generate_ttml_from_lines(&lines, &metadata, &GenerateOptions::default()).unwrap();
+        assert!(!ttml.contains('\n'));
+        let parsed = ttml_processor::parse_ttml(&ttml, &TtmlParsingOptions::default()).unwrap();
+        assert_ne!(parsed.detected_formatted_ttml_input, Some(true));
+        let (regenerated, regenerated_metadata) = parse(&ttml);
+
+        assert_eq!(summarize(&regenerated), summarize(&lines));
+        let times = |lines: &[JsLyricLine]| -> Vec<_> {
+            lines
+                .iter()
+                .flat_map(|l| &l.words)
+                .map(|w| (w.start_time, w.end_time))
+                .collect()
+        };
+        assert_eq!(times(&regenerated), times(&lines));
+        assert!(
+            regenerated_metadata
+                .iter()
+                .any(|(key, values)| key == "songwriters" && values == &["Someone"])
+        );
+    }
+
+    #[test]
+    fn test_formatted_roundtrip() {
+        const EXPECTED: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml"
+    xmlns:itunes="http://music.apple.com/lyric-ttml-internal"
+    xmlns:ttm="http://www.w3.org/ns/ttml#metadata"
+    itunes:timing="Word">
+  <head>
+    <metadata>
+      <ttm:agent type="person" xml:id="v1"/>
+      <ttm:agent type="group" xml:id="v1000"/>
+      <ttm:agent type="person" xml:id="v2"/>
+      <iTunesMetadata xmlns="http://music.apple.com/lyric-ttml-internal">
+        <songwriters>
+          <songwriter>Someone</songwriter>
+        </songwriters>
+      </iTunesMetadata>
+    </metadata>
+  </head>
+  <body dur="5.000">
+    <div begin="1.000" end="5.000">
+      <p begin="1.000" end="3.000" itunes:key="L1" ttm:agent="v1">
+        <span begin="1.000" end="1.500">Hello </span>
+        <span begin="1.500" end="2.000">world</span>
+        <span ttm:role="x-translation">你好世界</span>
+        <span ttm:role="x-bg" begin="2.000" end="3.000">
+          <span begin="2.000" end="3.000">(echo)</span>
+        </span>
+      </p>
+      <p begin="3.000" end="4.000" itunes:key="L2" ttm:agent="v2">
+        <span begin="3.000" end="4.000">duet</span>
+      </p>
+      <p begin="4.000" end="5.000" itunes:key="L3" ttm:agent="v1000">
+        <span begin="4.000" end="5.000">all</span>
+      </p>
+    </div>
+  </body>
+</tt>"#;
+
+        let (lines, metadata) = parse(TTML);
+        let options = GenerateOptions {
+            format: true,
+            ..Default::default()
+        };
+        let formatted = generate_ttml_from_lines(&lines, &metadata, &options).unwrap();
+        assert_eq!(formatted, EXPECTED);
+
+        let parsed =
+            ttml_processor::parse_ttml(&formatted, &TtmlParsingOptions::default()).unwrap();
+        assert_eq!(parsed.detected_formatted_ttml_input, Some(true));
+
+        // 缩进和换行不会混入歌词文本，解析回来的数据与原始数据一致
+        let (reparsed, reparsed_metadata) = parse(&formatted);
+        assert_eq!(summarize(&reparsed), summarize(&lines));
+        let words = |lines: &[JsLyricLine]| -> Vec<_> {
+            lines
+                .iter()
+                .flat_map(|l| &l.words)
+                .map(|w| (w.word.clone(), w.start_time, w.end_time))
+                .collect()
+        };
+        assert_eq!(words(&reparsed), words(&lines));
+        assert!(
+            reparsed_metadata
+                .iter()
+                .any(|(key, values)| key == "songwriters" && values == &["Someone"])
+        );
+
+        let regenerated =
+            generate_ttml_from_lines(&reparsed, &reparsed_metadata, &options).unwrap();
+        assert_eq!(regenerated, formatted);
+    }
+}